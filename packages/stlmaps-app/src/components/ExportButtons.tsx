@@ -12,15 +12,17 @@ import {
   Chip,
   useTheme,
   IconButton,
+  TextField,
+  InputAdornment,
+  MenuItem,
   useMediaQuery
 } from "@mui/material";
 import * as THREE from "three";
+import type { Feature, Polygon } from "geojson";
 import { useAppStore } from "../stores/useAppStore";
-import { STLExporter } from 'three/examples/jsm/exporters/STLExporter.js';
-import { OBJExporter } from 'three/examples/jsm/exporters/OBJExporter.js';
-import { GLTFExporter } from 'three/examples/jsm/exporters/GLTFExporter.js';
 import * as BufferGeometryUtils from 'three/examples/jsm/utils/BufferGeometryUtils.js';
 import { getWasmModule } from "@threegis/core";
+import { buildWasmExportScene } from "../utils/wasmExportScene";
 import FileDownloadIcon from '@mui/icons-material/FileDownload';
 import ModelTrainingIcon from '@mui/icons-material/ModelTraining';
 import ScatterPlotIcon from '@mui/icons-material/ScatterPlot';
@@ -34,15 +36,49 @@ interface ExportFormat {
   fileExtension: string;
 }
 
+// Longest model side in millimeters; matches the terrain size in mesh units
+const DEFAULT_MODEL_SIZE_MM = 200;
+// Same default DEFLATE level as the WASM 3MF writer
+const DEFAULT_COMPRESSION_LEVEL = 6;
+
+const COMPRESSION_LEVELS = [
+  { value: 0, label: "None (fastest)" },
+  { value: 1, label: "Fast" },
+  { value: 6, label: "Balanced" },
+  { value: 9, label: "Smallest file" },
+];
+
+// [minLng, minLat, maxLng, maxLat] of the selected bbox polygon, or null without one
+const bboxBounds = (bbox: Feature | null): [number, number, number, number] | null => {
+  if (!bbox?.geometry || bbox.geometry.type !== "Polygon") {
+    return null;
+  }
+
+  let minLng = Infinity, minLat = Infinity, maxLng = -Infinity, maxLat = -Infinity;
+  (bbox.geometry as Polygon).coordinates[0].forEach(([lng, lat]) => {
+    minLng = Math.min(minLng, lng);
+    minLat = Math.min(minLat, lat);
+    maxLng = Math.max(maxLng, lng);
+    maxLat = Math.max(maxLat, lat);
+  });
+
+  return [minLng, minLat, maxLng, maxLat];
+};
+
 const ExportButtons: React.FC = () => {
   // Get geometry data and scene directly from the Zustand store
-  const { geometryDataSets, vtLayers, terrainSettings, sceneGetter: getCurrentScene } = useAppStore();
+  const { geometryDataSets, vtLayers, terrainSettings, bbox, sceneGetter: getCurrentScene } = useAppStore();
   const theme = useTheme();
   const isMobile = useMediaQuery(theme.breakpoints.down("sm"));
 
   // State for dialog
   const [dialogOpen, setDialogOpen] = useState<boolean>(false);
 
+  // Physical size of the longest model side; scales the GLB, OBJ and STL and sets the 3MF transform
+  const [modelSizeMm, setModelSizeMm] = useState<number>(DEFAULT_MODEL_SIZE_MM);
+  // ZIP DEFLATE level of the 3MF archive, 0 (store) to 9 (smallest)
+  const [compressionLevel, setCompressionLevel] = useState<number>(DEFAULT_COMPRESSION_LEVEL);
+
   // Downloads are now handled immediately in export functions
 
   // State for loading indicators
@@ -140,45 +176,55 @@ const ExportButtons: React.FC = () => {
     return validatedGeometry;
   };
 
-  // Create a scene using the same positioning logic as ModelPreview
-  const createExportScene = (validateGeometries = false): THREE.Scene => {
-    const exportScene = new THREE.Scene();
-
+  // Visible, non-empty meshes of the preview scene with up-to-date world matrices;
+  // null when no preview scene is available
+  const collectPreviewMeshes = (): THREE.Mesh[] | null => {
     const currentScene = typeof getCurrentScene === 'function' ? getCurrentScene() : null;
-    if (currentScene) {
-      currentScene.updateMatrixWorld(true);
+    if (!currentScene) {
+      return null;
+    }
+    currentScene.updateMatrixWorld(true);
 
-      const meshesToExport: THREE.Mesh[] = [];
+    const meshes: THREE.Mesh[] = [];
 
-      // Use iterative traversal to avoid stack overflow from circular references
-      const objectsToCheck: THREE.Object3D[] = [currentScene];
-      const visitedObjects = new Set<THREE.Object3D>();
+    // Use iterative traversal to avoid stack overflow from circular references
+    const objectsToCheck: THREE.Object3D[] = [currentScene];
+    const visitedObjects = new Set<THREE.Object3D>();
 
-      while (objectsToCheck.length > 0) {
-        const object = objectsToCheck.pop()!;
+    while (objectsToCheck.length > 0) {
+      const object = objectsToCheck.pop()!;
 
-        // Skip if already visited
-        if (visitedObjects.has(object)) continue;
-        visitedObjects.add(object);
+      // Skip if already visited
+      if (visitedObjects.has(object)) continue;
+      visitedObjects.add(object);
 
-        // Add children to check queue
-        for (const child of object.children) {
-          objectsToCheck.push(child);
-        }
+      // Add children to check queue
+      for (const child of object.children) {
+        objectsToCheck.push(child);
+      }
 
-        if (object instanceof THREE.Mesh) {
-          const geometry = object.geometry;
-          const positionAttribute = geometry?.attributes?.position as THREE.BufferAttribute | undefined;
-          if (!object.visible || !geometry || !positionAttribute || positionAttribute.count === 0) {
-            continue;
-          }
-          if (object.name === 'terrain' && !terrainSettings.enabled) {
-            continue;
-          }
-          meshesToExport.push(object);
+      if (object instanceof THREE.Mesh) {
+        const geometry = object.geometry;
+        const positionAttribute = geometry?.attributes?.position as THREE.BufferAttribute | undefined;
+        if (!object.visible || !geometry || !positionAttribute || positionAttribute.count === 0) {
+          continue;
+        }
+        if (object.name === 'terrain' && !terrainSettings.enabled) {
+          continue;
         }
+        meshes.push(object);
       }
+    }
 
+    return meshes;
+  };
+
+  // Create a scene using the same positioning logic as ModelPreview
+  const createExportScene = (validateGeometries = false): THREE.Scene => {
+    const exportScene = new THREE.Scene();
+
+    const meshesToExport = collectPreviewMeshes();
+    if (meshesToExport) {
       if (meshesToExport.length > 0) {
         const extractMaterialColor = (material: THREE.Material | THREE.Material[] | undefined): THREE.Color => {
           if (!material) {
@@ -499,35 +545,58 @@ const ExportButtons: React.FC = () => {
   };
 
 
-  const generateOBJFile = (): void => {
-    if (!geometryDataSets.terrainGeometry) return;
+  // Meshes to export with up-to-date world matrices: the preview meshes, or the flat
+  // export scene when there is no preview to take them from
+  const collectExportMeshes = (): THREE.Mesh[] => {
+    const previewMeshes = collectPreviewMeshes();
+    if (previewMeshes && previewMeshes.length > 0) {
+      return previewMeshes;
+    }
+    const scene = createExportScene(false);
+    scene.updateMatrixWorld(true);
+    return scene.children.filter((child): child is THREE.Mesh => child instanceof THREE.Mesh);
+  };
 
-    try {
-      // Create scene without validation to preserve manifold geometry
-      const scene = createExportScene(false);
+  // Scene input shared by the WASM GLB, OBJ and STL exporters
+  const createWasmExportScene = () =>
+    buildWasmExportScene(collectExportMeshes(), vtLayers, geometryDataSets.polygonGeometries ?? []);
 
-      // Create OBJ exporter and export the scene
-      const exporter = new OBJExporter();
-      const objString = exporter.parse(scene);
+  const downloadBlob = (blob: Blob, filename: string) => {
+    const url = URL.createObjectURL(blob);
 
-      // Create downloadable Blob and URL
-      const blob = new Blob([objString], { type: 'text/plain' });
-      const url = URL.createObjectURL(blob);
+    // Trigger immediate download
+    const a = document.createElement('a');
+    a.href = url;
+    a.download = filename;
+    document.body.appendChild(a);
+    a.click();
+    document.body.removeChild(a);
 
-      // Trigger immediate download
-      const a = document.createElement('a');
-      a.href = url;
-      a.download = 'model.obj';
-      document.body.appendChild(a);
-      a.click();
-      document.body.removeChild(a);
+    // Clean up URL
+    URL.revokeObjectURL(url);
+  };
 
-      // Clean up URL
-      URL.revokeObjectURL(url);
+  const generateOBJFile = (): void => {
+    if (!geometryDataSets.terrainGeometry) return;
 
+    try {
+      const wasmModule = getWasmModule();
+      if (!wasmModule?.export_obj) {
+        throw new Error("OBJ export not available in WASM module");
+      }
 
+      // One group and material per layer; the MTL has to be saved next to the OBJ
+      const { obj, mtl, mtlFileName } = wasmModule.export_obj(
+        JSON.stringify(createWasmExportScene()),
+        JSON.stringify({
+          modelSizeMm: modelSizeMm > 0 ? modelSizeMm : null,
+          processId: geometryDataSets.processId ?? null
+        })
+      );
+      downloadBlob(new Blob([obj], { type: 'text/plain' }), 'model.obj');
+      downloadBlob(new Blob([mtl], { type: 'text/plain' }), mtlFileName);
     } catch (error) {
-
+      console.error('❌ OBJ Export Error:', error);
     } finally {
       setLoading(prev => ({ ...prev, obj: false }));
     }
@@ -537,31 +606,23 @@ const ExportButtons: React.FC = () => {
     if (!geometryDataSets.terrainGeometry) return;
 
     try {
-      // Create scene without validation to preserve manifold geometry
-      const scene = createExportScene(false);
-
-      // Create STL exporter and export the scene (binary format for smaller file size)
-      const exporter = new STLExporter();
-      const stlString = exporter.parse(scene, { binary: true });
-
-      // Create downloadable Blob and URL
-      const blob = new Blob([stlString], { type: 'application/octet-stream' });
-      const url = URL.createObjectURL(blob);
-
-      // Trigger immediate download
-      const a = document.createElement('a');
-      a.href = url;
-      a.download = 'model.stl';
-      document.body.appendChild(a);
-      a.click();
-      document.body.removeChild(a);
-
-      // Clean up URL
-      URL.revokeObjectURL(url);
-
+      const wasmModule = getWasmModule();
+      if (!wasmModule?.export_stl) {
+        throw new Error("STL export not available in WASM module");
+      }
 
+      // Binary STL with transforms and the model size baked into the vertices
+      const stl = wasmModule.export_stl(
+        JSON.stringify({
+          ...createWasmExportScene(),
+          modelSizeMm: modelSizeMm > 0 ? modelSizeMm : null,
+          processId: geometryDataSets.processId ?? null
+        }),
+        false
+      );
+      downloadBlob(new Blob([stl], { type: 'application/octet-stream' }), 'model.stl');
     } catch (error) {
-
+      console.error('❌ STL Export Error:', error);
     } finally {
       setLoading(prev => ({ ...prev, stl: false }));
     }
@@ -571,55 +632,24 @@ const ExportButtons: React.FC = () => {
     if (!geometryDataSets.terrainGeometry) return;
 
     try {
-      // Create scene without validation to preserve manifold geometry
-      const scene = createExportScene(false);
-
-      // Create GLTF exporter with binary option for better compatibility
-      const exporter = new GLTFExporter();
-      exporter.parse(
-        scene,
-        (gltf) => {
-          // Create downloadable Blob with appropriate type
-          let blob;
-
-          // Check if the export is binary (GLB) or JSON (GLTF)
-          if (gltf instanceof ArrayBuffer) {
-            blob = new Blob([gltf], { type: 'application/octet-stream' });
-          } else {
-            blob = new Blob([JSON.stringify(gltf)], { type: 'model/gltf+json' });
-          }
-
-          const url = URL.createObjectURL(blob);
-
-          // Trigger immediate download
-          const a = document.createElement('a');
-          a.href = url;
-          a.download = 'model.glb';
-          document.body.appendChild(a);
-          a.click();
-          document.body.removeChild(a);
-
-          // Clean up URL
-          URL.revokeObjectURL(url);
-
-
-
-          // Update loading state when complete
-          setLoading(prev => ({ ...prev, gltf: false }));
-        },
-        (error) => {
+      const wasmModule = getWasmModule();
+      if (!wasmModule?.export_gltf) {
+        throw new Error("GLB export not available in WASM module");
+      }
 
-          setLoading(prev => ({ ...prev, gltf: false }));
-        },
-        {
-          binary: true, // Use binary GLB format for better compatibility
-          onlyVisible: true,
-          truncateDrawRange: true, // Ensure proper buffer lengths
-          animations: []
-        }
+      // One named node per layer with provenance extras; meshes sharing a geometry share
+      // its buffer views
+      const glb = wasmModule.export_gltf(
+        JSON.stringify(createWasmExportScene()),
+        JSON.stringify({
+          modelSizeMm: modelSizeMm > 0 ? modelSizeMm : null,
+          processId: geometryDataSets.processId ?? null
+        })
       );
+      downloadBlob(new Blob([glb], { type: 'model/gltf-binary' }), 'model.glb');
     } catch (error) {
-
+      console.error('❌ GLB Export Error:', error);
+    } finally {
       setLoading(prev => ({ ...prev, gltf: false }));
     }
   };
@@ -768,50 +798,28 @@ const ExportButtons: React.FC = () => {
       const modelData = {
         meshes: meshes,
         title: "STLMaps 3D Model",
-        description: "3D terrain model generated by STLMaps"
+        description: "3D terrain model generated by STLMaps",
+        // Unit transform and real-world scale metadata so slicers import at the chosen size
+        modelSizeMm: modelSizeMm > 0 ? modelSizeMm : null,
+        // Generation process whose terrain scale the model size is relative to
+        processId: geometryDataSets.processId ?? null,
+        bbox: bboxBounds(bbox),
+        compressionLevel
       };
 
-      // Generate 3MF files using WASM
+      // Stream the 3MF archive out of WASM; chunks go straight into the Blob so the
+      // archive is never assembled as one buffer
       const wasmModule = getWasmModule();
-      if (!wasmModule?.generate_3mf_model_xml) {
+      if (!wasmModule?.stream_3mf_archive) {
         throw new Error("3MF export not available in WASM module");
       }
 
-      // Generate XML files using WASM
-      const modelXml = wasmModule.generate_3mf_model_xml(JSON.stringify(modelData));
-      const contentTypesXml = wasmModule.generate_3mf_content_types_xml();
-      const relsXml = wasmModule.generate_3mf_rels_xml();
-
-      // Create ZIP file using JSZip (we need to add this dependency)
-      // For now, we'll create a simple 3MF file with just the model XML
-      // In production, you'd want to use JSZip to create proper 3MF structure
-
-      // Import JSZip dynamically
-      const JSZip = (await import('jszip')).default;
-
-      // Create proper 3MF ZIP structure
-      const zip = new JSZip();
-
-      // Add required folders and files
-      zip.file("[Content_Types].xml", contentTypesXml);
-
-      const relsFolder = zip.folder("_rels");
-      relsFolder!.file(".rels", relsXml);
-
-      const threeDFolder = zip.folder("3D");
-      threeDFolder!.file("3dmodel.model", modelXml);
-
-      // Generate ZIP as blob
-      const zipBlob = await zip.generateAsync({
-        type: "blob",
-        compression: "DEFLATE",
-        compressionOptions: {
-          level: 6
-        },
-        mimeType: "model/3mf"
+      const chunks: Uint8Array[] = [];
+      wasmModule.stream_3mf_archive(JSON.stringify(modelData), (chunk: Uint8Array) => {
+        chunks.push(chunk);
       });
 
-      const blob = zipBlob;
+      const blob = new Blob(chunks, { type: 'model/3mf' });
       const url = URL.createObjectURL(blob);
 
       // Trigger immediate download
@@ -1008,6 +1016,36 @@ const ExportButtons: React.FC = () => {
               );
             })}
           </Grid>
+
+          <Box sx={{ mt: isMobile ? 2 : 3, display: 'flex', flexDirection: isMobile ? 'column' : 'row', gap: 2 }}>
+            <TextField
+              label="Model size (longest side)"
+              type="number"
+              value={modelSizeMm}
+              onChange={(e) => setModelSizeMm(Number(e.target.value))}
+              InputProps={{
+                endAdornment: <InputAdornment position="end">mm</InputAdornment>,
+                inputProps: { min: 1, step: 1 }
+              }}
+              size="small"
+              fullWidth={isMobile}
+            />
+            <TextField
+              select
+              label="3MF compression"
+              value={compressionLevel}
+              onChange={(e) => setCompressionLevel(Number(e.target.value))}
+              size="small"
+              fullWidth={isMobile}
+              sx={{ minWidth: 180 }}
+            >
+              {COMPRESSION_LEVELS.map(({ value, label }) => (
+                <MenuItem key={value} value={value}>
+                  {label}
+                </MenuItem>
+              ))}
+            </TextField>
+          </Box>
         </DialogContent>
 
         <DialogActions sx={{ p: isMobile ? "12px 16px" : "16px 24px", bgcolor: theme.palette.grey[50] }}>
@@ -62,6 +62,7 @@ export interface MeshGenerationResult {
   totalProcessingTimeMs: number;
   parallelizationEfficiency: number;
   success: boolean;
+  processId?: string;
   error?: Error;
 }
 
@@ -197,6 +198,11 @@ class ParallelLayerProcessor {
           fixedBufferSize: layer.fixedBufferSize ?? null,
           extrusionDepth: layer.extrusionDepth ?? null,
           minExtrusionDepth: layer.minExtrusionDepth ?? null,
+          minHeight: layer.minHeight ?? null,
+          maxHeight: layer.maxHeight ?? null,
+          minClearance: layer.minClearance ?? null,
+          submergeOffset: layer.submergeOffset ?? null,
+          stackOrder: layer.stackOrder ?? null,
           zOffset: layer.zOffset ?? null,
           alignVerticesToTerrain: layer.alignVerticesToTerrain ?? null,
           applyMedianHeight: layer.applyMedianHeight ?? null,
@@ -369,6 +375,11 @@ class ParallelLayerProcessor {
           fixedBufferSize: layer.fixedBufferSize ?? null,
           extrusionDepth: layer.extrusionDepth ?? null,
           minExtrusionDepth: layer.minExtrusionDepth ?? null,
+          minHeight: layer.minHeight ?? null,
+          maxHeight: layer.maxHeight ?? null,
+          minClearance: layer.minClearance ?? null,
+          submergeOffset: layer.submergeOffset ?? null,
+          stackOrder: layer.stackOrder ?? null,
           zOffset: layer.zOffset ?? null,
           alignVerticesToTerrain: layer.alignVerticesToTerrain ?? null,
           applyMedianHeight: layer.applyMedianHeight ?? null,
@@ -875,7 +886,8 @@ export function useGenerateMesh(options: UseGenerateMeshOptions = {}) {
         layerResults,
         totalProcessingTimeMs: totalTime,
         parallelizationEfficiency: efficiency,
-        success: true
+        success: true,
+        processId
       };
 
     } catch (error) {
@@ -960,7 +972,8 @@ export function useGenerateMesh(options: UseGenerateMeshOptions = {}) {
 
         setGeometryDataSets({
           terrainGeometry: result.terrainResult.terrainGeometry,
-          polygonGeometries
+          polygonGeometries,
+          processId: result.processId
         });
 
         // Update configuration hashes
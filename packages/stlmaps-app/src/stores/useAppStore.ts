@@ -13,6 +13,8 @@ export interface TerrainSettings {
   baseHeight: number;
   color: string;
   simpleMesh: boolean;
+  minClearance?: number; // Gap between terrain-aligned layers and terrain (WASM default 0.1)
+  submergeOffset?: number; // Depth buildings/roads are embedded into terrain (WASM default 0.05)
 }
 
 // Building settings interface  
@@ -36,6 +38,11 @@ export interface VtDataSet {
   filter?: any; // MapLibre filter expression
   extrusionDepth?: number;
   minExtrusionDepth?: number;
+  minHeight?: number; // Lower height clamp in terrain units (WASM default 0.01)
+  maxHeight?: number; // Upper height clamp in terrain units (WASM default 500)
+  minClearance?: number; // Per-layer override of terrainSettings.minClearance
+  submergeOffset?: number; // Per-layer override of terrainSettings.submergeOffset
+  stackOrder?: number; // Higher values sit on top where terrain-aligned layers overlap
   zOffset: number;
   alignVerticesToTerrain: boolean;
   /** When true, the WASM layer has already baked per-polygon terrain Z into the geometry.
@@ -62,6 +69,8 @@ export interface GridSize {
 export interface GeometryDataSets {
   terrainGeometry?: THREE.BufferGeometry;
   polygonGeometries?: VtDataSet[];
+  // Generation process the geometries came from; exports resolve its terrain scale
+  processId?: string;
 }
 
 // Config hashes interface
@@ -211,6 +220,7 @@ const defaultLayers: VtDataSet[] = [
     alignVerticesToTerrain: true,
     useCsgClipping: false,
     order: 1,
+    stackOrder: 0,
     filter: ["in", "class", "commercial", "residential"]
   },
   {
@@ -223,7 +233,8 @@ const defaultLayers: VtDataSet[] = [
     zOffset: -0.2,
     alignVerticesToTerrain: true,
     useCsgClipping: false,
-    order: 2
+    order: 2,
+    stackOrder: 0
   },
   {
     sourceLayer: "park",
@@ -235,7 +246,8 @@ const defaultLayers: VtDataSet[] = [
     zOffset: -0.01,
     alignVerticesToTerrain: true,
     useCsgClipping: false,
-    order: 3
+    order: 3,
+    stackOrder: 1
   },
   {
     sourceLayer: "water",
@@ -247,7 +259,8 @@ const defaultLayers: VtDataSet[] = [
     zOffset: -0.02,
     alignVerticesToTerrain: true,
     useCsgClipping: false,
-    order: 4
+    order: 4,
+    stackOrder: 1
   },
   {
     sourceLayer: "transportation",
@@ -260,6 +273,7 @@ const defaultLayers: VtDataSet[] = [
     alignVerticesToTerrain: true,
     useCsgClipping: false,
     order: 5,
+    stackOrder: 3,
     filter: [
       "in",
       "subclass",
@@ -277,6 +291,7 @@ const defaultLayers: VtDataSet[] = [
     alignVerticesToTerrain: true,
     useCsgClipping: false,
     order: 5,
+    stackOrder: 2,
     filter: [
       "in",
       "class",
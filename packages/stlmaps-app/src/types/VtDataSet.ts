@@ -3,6 +3,7 @@ import type { FilterExpression } from "./MapLibre";
 
 // VtDataSet interface for vector tile layer configuration
 export interface VtDataSet {
+  source?: string; // Named vector tile source; default source when omitted
   sourceLayer: string;
   subClass?: string;
   geometry?: THREE.BufferGeometry;
@@ -14,6 +15,11 @@ export interface VtDataSet {
   filter?: FilterExpression; // MapLibre filter expression with proper types
   extrusionDepth?: number;
   minExtrusionDepth?: number;
+  minHeight?: number; // Lower height clamp in terrain units (WASM default 0.01)
+  maxHeight?: number; // Upper height clamp in terrain units (WASM default 500)
+  minClearance?: number; // Per-layer override of terrainSettings.minClearance
+  submergeOffset?: number; // Per-layer override of terrainSettings.submergeOffset
+  stackOrder?: number; // Higher values sit on top where terrain-aligned layers overlap
   zOffset: number;
   alignVerticesToTerrain: boolean;
   /** When true, the WASM layer has already baked per-polygon terrain Z into the geometry.
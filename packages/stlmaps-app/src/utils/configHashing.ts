@@ -12,6 +12,9 @@ export function hashTerrainConfig(config: TerrainSettings): string {
     // baseHeight excluded - can be updated in real-time
     // Color is excluded to prevent geometry regeneration on color changes
     simpleMesh: config.simpleMesh,
+    // Clearance and submerge offsets are baked into layer geometry
+    minClearance: config.minClearance,
+    submergeOffset: config.submergeOffset,
   });
 }
 
@@ -36,6 +39,11 @@ export function hashVtLayerConfig(vtLayer: VtDataSet): string {
     label: vtLayer.label, // Include label to differentiate layers with same sourceLayer
     subClass: vtLayer.subClass,
     extrusionDepth: vtLayer.extrusionDepth,
+    minHeight: vtLayer.minHeight,
+    maxHeight: vtLayer.maxHeight,
+    minClearance: vtLayer.minClearance,
+    submergeOffset: vtLayer.submergeOffset,
+    stackOrder: vtLayer.stackOrder,
     // zOffset excluded - can be updated in real-time
     bufferSize: vtLayer.bufferSize,
     fixedBufferSize: vtLayer.fixedBufferSize,
@@ -43,6 +51,7 @@ export function hashVtLayerConfig(vtLayer: VtDataSet): string {
     useAdaptiveScaleFactor: vtLayer.useAdaptiveScaleFactor,
     // heightScaleFactor excluded - can be updated in real-time
     alignVerticesToTerrain: vtLayer.alignVerticesToTerrain,
+    useCsgClipping: vtLayer.useCsgClipping, // 3D clipping to the terrain solid changes the mesh
     // enabled excluded - visibility doesn't affect geometry, only affects 3D preview display
     // Color is excluded to prevent geometry regeneration on color changes
   });
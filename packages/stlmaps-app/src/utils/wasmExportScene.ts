@@ -0,0 +1,131 @@
+import * as THREE from "three";
+import type { VtDataSet } from "../stores/useAppStore";
+
+const TERRAIN_LABEL = 'terrain';
+
+/**
+ * One mesh of the scene passed to the WASM exporters (`export_gltf`, `export_obj`,
+ * `export_stl`). Vertices stay in geometry space; `transform` is the mesh's world matrix.
+ */
+export interface WasmExportMesh {
+  vertices: number[];
+  indices: number[];
+  colors?: number[];
+  normals?: number[];
+  name: string;
+  color?: string;
+  transform: number[];
+  geometryId: string;
+}
+
+/**
+ * Provenance written to the `extras` of a layer node
+ */
+export interface WasmExportLayerInfo {
+  sourceLayer?: string;
+  featureCount?: number;
+}
+
+export interface WasmExportScene {
+  meshes: WasmExportMesh[];
+  title: string;
+  layerOrder: string[];
+  layers: Record<string, WasmExportLayerInfo>;
+}
+
+/**
+ * Layer label of a preview mesh: its userData label, or "terrain" for terrain meshes
+ */
+function layerLabelOf(mesh: THREE.Mesh): string {
+  if (typeof mesh.userData?.label === 'string') {
+    return mesh.userData.label;
+  }
+  if (mesh.name === TERRAIN_LABEL || mesh.name.startsWith(`${TERRAIN_LABEL}_`)) {
+    return TERRAIN_LABEL;
+  }
+  return mesh.name || 'mesh';
+}
+
+/**
+ * Number of features a layer was built from, summed over its datasets
+ */
+function featureCountOf(label: string, datasets: VtDataSet[]): number | undefined {
+  const matching = datasets.filter(dataset => (dataset.label || dataset.sourceLayer) === label);
+  if (matching.length === 0) {
+    return undefined;
+  }
+  return matching.reduce((sum, dataset) => {
+    const count = dataset.geometry?.userData?.geometryCount ?? dataset.geometries?.length ?? 0;
+    return sum + count;
+  }, 0);
+}
+
+function materialColorOf(material: THREE.Material | THREE.Material[]): THREE.Color | undefined {
+  const materials = Array.isArray(material) ? material : [material];
+  for (const mat of materials) {
+    const color = (mat as THREE.Material & { color?: THREE.Color }).color;
+    if (color instanceof THREE.Color) {
+      return color;
+    }
+  }
+  return undefined;
+}
+
+function attributeValues(geometry: THREE.BufferGeometry, name: string): number[] | undefined {
+  const attribute = geometry.getAttribute(name);
+  return attribute ? Array.from(attribute.array as ArrayLike<number>) : undefined;
+}
+
+/**
+ * Build the scene input of the WASM exporters from meshes with up-to-date world matrices.
+ * Meshes are grouped into layers by label (terrain first, then configuration order) and
+ * meshes that share a geometry share its `geometryId`, so the GLB writes it once.
+ */
+export function buildWasmExportScene(
+  meshes: THREE.Mesh[],
+  layers: VtDataSet[],
+  datasets: VtDataSet[] = []
+): WasmExportScene {
+  const exportMeshes: WasmExportMesh[] = [];
+  const layerInfo: Record<string, WasmExportLayerInfo> = {};
+
+  for (const mesh of meshes) {
+    const label = layerLabelOf(mesh);
+    const config = layers.find(layer => (layer.label || layer.sourceLayer) === label);
+    const geometry = mesh.geometry;
+    const vertices = attributeValues(geometry, 'position');
+    if (!vertices || vertices.length === 0) {
+      continue;
+    }
+
+    const indices = geometry.index
+      ? Array.from(geometry.index.array as ArrayLike<number>)
+      : Array.from({ length: vertices.length / 3 }, (_, i) => i);
+    const baseColor = materialColorOf(mesh.material);
+
+    exportMeshes.push({
+      vertices,
+      indices,
+      colors: attributeValues(geometry, 'color'),
+      normals: attributeValues(geometry, 'normal'),
+      name: label,
+      color: config?.color ?? (baseColor ? `#${baseColor.getHexString()}` : undefined),
+      transform: mesh.matrixWorld.toArray(),
+      geometryId: geometry.uuid
+    });
+
+    if (!layerInfo[label]) {
+      layerInfo[label] = {
+        sourceLayer: config?.sourceLayer ?? mesh.userData?.sourceLayer,
+        featureCount: label === TERRAIN_LABEL ? undefined : featureCountOf(label, datasets)
+      };
+    }
+  }
+
+  return {
+    meshes: exportMeshes,
+    title: 'STLMaps Model',
+    layerOrder: layers.map(layer => layer.label || layer.sourceLayer),
+    layers: layerInfo
+  };
+}
@@ -20,6 +20,9 @@ interface TileFetchResponse {
   pixelData?: Uint8Array;
   rawData?: Uint8Array;
   mimeType: string;
+  etag?: string;
+  lastModified?: string;
+  notModified?: boolean;
 }
 
 interface FetchConfig {
@@ -27,6 +30,7 @@ interface FetchConfig {
   timeoutMs: number;
   backoffMs: number;
   validateContent: boolean;
+  headers?: Record<string, string>;
 }
 
 const extractTileCoordinatesFromUrl = (url: string): { x: number; y: number; z: number } => {
@@ -55,12 +59,16 @@ const extractTileCoordinatesFromUrl = (url: string): { x: number; y: number; z:
   return { x, y, z };
 };
 
-const fetchWithTimeout = async (url: string, timeoutMs: number): Promise<Response> => {
+const fetchWithTimeout = async (
+  url: string,
+  timeoutMs: number,
+  headers?: Record<string, string>
+): Promise<Response> => {
   const controller = new AbortController();
   const timeoutId = setTimeout(() => controller.abort(), timeoutMs);
 
   try {
-    const response = await fetch(url, { signal: controller.signal }); // Use global fetch in worker
+    const response = await fetch(url, { signal: controller.signal, headers }); // Use global fetch in worker
     clearTimeout(timeoutId);
     return response;
   } catch (error) {
@@ -77,7 +85,23 @@ const robustFetch = async (url: string, config: FetchConfig): Promise<TileFetchR
 
   for (let attempt = 0; attempt <= config.maxRetries; attempt++) {
     try {
-      const response = await fetchWithTimeout(url, config.timeoutMs);
+      const response = await fetchWithTimeout(url, config.timeoutMs, config.headers);
+      const validators = {
+        etag: response.headers.get('etag') || undefined,
+        lastModified: response.headers.get('last-modified') || undefined
+      };
+
+      if (response.status === 304) {
+        const tileCoords = extractTileCoordinatesFromUrl(url);
+        return {
+          width: 256,
+          height: 256,
+          ...tileCoords,
+          mimeType: '',
+          ...validators,
+          notModified: true
+        };
+      }
 
       if (!response.ok) {
         throw new Error(`HTTP ${response.status}: ${response.statusText}`);
@@ -100,7 +124,8 @@ const robustFetch = async (url: string, config: FetchConfig): Promise<TileFetchR
         y: tileCoords.y,
         z: tileCoords.z,
         rawData,
-        mimeType: contentType
+        mimeType: contentType,
+        ...validators
       };
     } catch (error) {
       lastError = error instanceof Error ? error : new Error(String(error));
@@ -218,6 +243,8 @@ let sharedVectorTiles: Map<string, SharedVectorTileData> = new Map();
 let sharedElevationData: any = null;
 let currentProcessId: string | null = null;
 let fetchingProcessId: string | null = null;  // Track which process is currently being fetched
+// Terrain mesh last stored in WASM; layers of a process sample it without resending it
+let storedTerrainMesh: { processId: string; vertices: ArrayLike<number> } | null = null;
 
 // ================================================================================
 // WASM Initialization
@@ -645,6 +672,18 @@ async function processLayerInWorker(input: LayerProcessingInput): Promise<any> {
       terrainIsGpuLayout = Math.abs(x0 - x1) < 1e-4 && Math.abs(y0 - y1) < 1e-4;
     }
 
+    // Store the terrain mesh once per process; every layer then samples the stored copy
+    if (terrainVerts && (storedTerrainMesh?.processId !== activeProcessId
+      || storedTerrainMesh.vertices !== terrainVerts)) {
+      const indices = terrainData.terrainIndices;
+      wasmModule.store_terrain_mesh(
+        activeProcessId,
+        terrainVerts instanceof Float32Array ? terrainVerts : Float32Array.from(terrainVerts),
+        indices instanceof Uint32Array ? indices : Uint32Array.from(indices ?? [])
+      );
+      storedTerrainMesh = { processId: activeProcessId, vertices: terrainVerts };
+    }
+
     const polygonGeometryInput = {
       terrainBaseHeight: terrainSettings.baseHeight,
       verticalExaggeration: terrainSettings.verticalExaggeration,
@@ -653,9 +692,6 @@ async function processLayerInWorker(input: LayerProcessingInput): Promise<any> {
       gridSize: terrainData.gridSize,
       minElevation: terrainData.originalMinElevation,
       maxElevation: terrainData.originalMaxElevation,
-      // Add terrain mesh data as CSV strings for easy serialization
-      terrainVerticesBase64: terrainVerts ? Array.from(terrainVerts).join(',') : '',
-      terrainIndicesBase64: terrainData.terrainIndices ? Array.from(terrainData.terrainIndices).join(',') : '',
       // Explicit grid dimensions so the Rust sampler never has to guess
       terrainGridWidth: terrainGridW,
       terrainGridHeight: terrainGridH,
@@ -668,6 +704,8 @@ async function processLayerInWorker(input: LayerProcessingInput): Promise<any> {
       // Other layers like roads/parks can share Z offset for consistency
       useSameZOffset: layerConfig.sourceLayer !== 'building',
       processId: activeProcessId,
+      minClearance: terrainSettings.minClearance ?? null,
+      submergeOffset: terrainSettings.submergeOffset ?? null,
     };
 
     if (cancelFlag) {
@@ -681,9 +719,9 @@ async function processLayerInWorker(input: LayerProcessingInput): Promise<any> {
       data: { message: `Processing ${layerConfig.sourceLayer} geometry...` }
     } as WorkerResponse);
 
-    // Process geometry in WASM — returns a JsValue object directly (no JSON string)
-    const serializedInput = JSON.stringify(polygonGeometryInput);
-    const geometryResult = await wasmModule.process_polygon_geometry(serializedInput);
+    // Process geometry in WASM — the input object is read directly (no JSON string) and
+    // a JsValue object is returned
+    const geometryResult = await wasmModule.process_polygon_geometry(polygonGeometryInput);
 
     if (cancelFlag) {
       throw new Error('Task was cancelled');
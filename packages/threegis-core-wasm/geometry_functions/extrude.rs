@@ -125,9 +125,11 @@ fn default_depth() -> f64 {
 }
 
 // UV Generator similar to WorldUVGenerator in JS
+#[allow(dead_code)]
 struct UVGenerator;
 
 impl UVGenerator {
+    #[allow(dead_code)]
     fn generate_top_uv(
         vertices: &[f32],
         index_a: usize,
@@ -252,6 +254,48 @@ pub fn extrude_geometry_native_with_options(
     opts: ExtrudeOptions,
     skip_bottom_face: bool,
 ) -> Result<JsValue, JsValue> {
+    let ExtrudedBuffers {
+        vertices: final_vertices,
+        normals: final_normals,
+        uvs: final_uvs,
+        indices: final_indices,
+    } = extrude_buffers(raw_shapes, opts, skip_bottom_face);
+
+    // Prepare return object
+    let result = Object::new();
+    let pos_arr = Float32Array::from(final_vertices.as_slice());
+    let normal_arr = Float32Array::from(final_normals.as_slice());
+    let uv_arr = Float32Array::from(final_uvs.as_slice());
+
+    // Create a JS array of indices
+    let indices_js_array = Array::new_with_length(final_indices.len() as u32);
+    for (i, &index) in final_indices.iter().enumerate() {
+        indices_js_array.set(i as u32, JsValue::from_f64(index as f64));
+    }
+
+    // Set properties on result
+    js_sys::Reflect::set(&result, &JsValue::from_str("position"), &pos_arr)?;
+    js_sys::Reflect::set(&result, &JsValue::from_str("normal"), &normal_arr)?;
+    js_sys::Reflect::set(&result, &JsValue::from_str("uv"), &uv_arr)?;
+    js_sys::Reflect::set(&result, &JsValue::from_str("index"), &indices_js_array)?;
+
+    Ok(result.into())
+}
+
+/// Vertex attributes and triangle indices of an extruded geometry
+pub struct ExtrudedBuffers {
+    pub vertices: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub uvs: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+/// The extrusion itself, without creating JS objects, so it also runs outside the browser
+pub fn extrude_buffers(
+    raw_shapes: Vec<RawShape>,
+    opts: ExtrudeOptions,
+    skip_bottom_face: bool,
+) -> ExtrudedBuffers {
     let mut final_vertices: Vec<f32> = Vec::new();
     let mut final_uvs: Vec<f32> = Vec::new();
     let mut final_indices: Vec<u32> = Vec::new();
@@ -326,7 +370,7 @@ pub fn extrude_geometry_native_with_options(
         }
 
         // Triangulate the shape (with holes)
-        let faces: Vec<Vec<usize>>;
+        
 
         // Triangulate contour and holes directly (no bevel)
         let mut data: Vec<f64> = Vec::new();
@@ -347,7 +391,7 @@ pub fn extrude_geometry_native_with_options(
         let indices = earcut(&data, &hole_indices, 2).unwrap();
 
         // Convert to the faces format (triplets of indices)
-        faces = indices.chunks(3).map(|chunk| chunk.to_vec()).collect();
+        let faces: Vec<Vec<usize>> = indices.chunks(3).map(|chunk| chunk.to_vec()).collect();
 
         // Function to add a vertex to the placeholder
         let mut v = |x: f64, y: f64, z: f64| {
@@ -359,8 +403,7 @@ pub fn extrude_geometry_native_with_options(
         let vlen = vertices.len();
 
         // Add back facing vertices
-        for i in 0..vlen {
-            let vert = vertices[i];
+        for vert in vertices.iter() {
 
             if !extrude_by_path {
                 v(vert.x, vert.y, 0.0);
@@ -376,8 +419,7 @@ pub fn extrude_geometry_native_with_options(
 
         // Add stepped vertices (front facing for simple extrusion)
         for s in 1..=opts.steps {
-            for i in 0..vlen {
-                let vert = vertices[i];
+            for vert in vertices.iter() {
 
                 if !extrude_by_path {
                     v(vert.x, vert.y, opts.depth / opts.steps as f64 * s as f64);
@@ -488,8 +530,7 @@ pub fn extrude_geometry_native_with_options(
         layer_offset += contour.len();
 
         // Sidewalls for holes
-        for h in 0..holes.len() {
-            let ahole = &holes[h];
+        for ahole in holes.iter() {
 
             for i in (0..ahole.len()).rev() {
                 let j = i;
@@ -539,25 +580,12 @@ pub fn extrude_geometry_native_with_options(
         vertex_offset += vertex_count as u32;
     }
 
-    // Prepare return object
-    let result = Object::new();
-    let pos_arr = Float32Array::from(final_vertices.as_slice());
-    let normal_arr = Float32Array::from(final_normals.as_slice());
-    let uv_arr = Float32Array::from(final_uvs.as_slice());
-
-    // Create a JS array of indices
-    let indices_js_array = Array::new_with_length(final_indices.len() as u32);
-    for (i, &index) in final_indices.iter().enumerate() {
-        indices_js_array.set(i as u32, JsValue::from_f64(index as f64));
+    ExtrudedBuffers {
+        vertices: final_vertices,
+        normals: final_normals,
+        uvs: final_uvs,
+        indices: final_indices,
     }
-
-    // Set properties on result
-    js_sys::Reflect::set(&result, &JsValue::from_str("position"), &pos_arr)?;
-    js_sys::Reflect::set(&result, &JsValue::from_str("normal"), &normal_arr)?;
-    js_sys::Reflect::set(&result, &JsValue::from_str("uv"), &uv_arr)?;
-    js_sys::Reflect::set(&result, &JsValue::from_str("index"), &indices_js_array)?;
-
-    Ok(result.into())
 }
 
 /// A convenience function to directly extrude a shape with Rust native types.
@@ -586,7 +614,7 @@ pub fn extrude_shape_with_options(
     skip_bottom_face: bool,
 ) -> Result<JsValue, JsValue> {
     // Convert the shapes to RawShapes
-    let raw_shapes: Vec<RawShape> = shapes.into_iter().map(|shape| RawShape(shape)).collect();
+    let raw_shapes: Vec<RawShape> = shapes.into_iter().map(RawShape).collect();
 
     // Create the extrusion options
     let opts = ExtrudeOptions {
@@ -599,3 +627,20 @@ pub fn extrude_shape_with_options(
     // Call the native implementation
     extrude_geometry_native_with_options(raw_shapes, opts, skip_bottom_face)
 }
+
+/// `extrude_shape_with_options` returning the buffers instead of a JS object
+pub fn extrude_shape_buffers(
+    shapes: Vec<Vec<Vec<[f64; 2]>>>,
+    depth: f64,
+    steps: u32,
+    skip_bottom_face: bool,
+) -> ExtrudedBuffers {
+    let raw_shapes: Vec<RawShape> = shapes.into_iter().map(RawShape).collect();
+    let opts = ExtrudeOptions {
+        depth,
+        steps,
+        curve_segments: 12, // Default
+        extrude_path: None,
+    };
+    extrude_buffers(raw_shapes, opts, skip_bottom_face)
+}
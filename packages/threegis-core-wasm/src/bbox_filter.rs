@@ -1,4 +1,6 @@
 // filepath: /home/tobi/project/stlmaps/packages/threegis-core-wasm/src/bbox_filter.rs
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
 
 // Function to check if a point is inside a bounding box
 pub fn point_in_bbox(point: &[f64], bbox: &[f64]) -> bool {
@@ -12,8 +14,27 @@ pub fn point_in_bbox(point: &[f64], bbox: &[f64]) -> bool {
     lng >= min_lng && lng <= max_lng && lat >= min_lat && lat <= max_lat
 }
 
-// Function to check if a polygon intersects with a bounding box
-pub fn polygon_intersects_bbox(polygon: &Vec<Vec<f64>>, bbox: &[f64]) -> bool {
+// Function to check if a polygon with optional holes intersects with a bounding box.
+// A bbox that lies completely inside a hole does not intersect the polygon.
+pub fn polygon_intersects_bbox(
+    exterior: &[Vec<f64>],
+    holes: &[Vec<Vec<f64>>],
+    bbox: &[f64],
+) -> bool {
+    ring_intersects_bbox(exterior, bbox) && !holes.iter().any(|hole| ring_contains_bbox(hole, bbox))
+}
+
+// Function to check if any polygon of a MultiPolygon intersects with a bounding box.
+// Each polygon is a list of rings: the exterior ring followed by its holes.
+pub fn multipolygon_intersects_bbox(polygons: &[Vec<Vec<Vec<f64>>>], bbox: &[f64]) -> bool {
+    polygons.iter().any(|rings| match rings.split_first() {
+        Some((exterior, holes)) => polygon_intersects_bbox(exterior, holes, bbox),
+        None => false,
+    })
+}
+
+// Function to check if the area enclosed by a ring intersects with a bounding box
+fn ring_intersects_bbox(polygon: &[Vec<f64>], bbox: &[f64]) -> bool {
     // 1. Quick rejection tests first - if the polygon's bounding box doesn't overlap the target bbox, reject it
     let min_lng = bbox[0];
     let min_lat = bbox[1];
@@ -72,14 +93,7 @@ pub fn polygon_intersects_bbox(polygon: &Vec<Vec<f64>>, bbox: &[f64]) -> bool {
 
     // 4. Check if the bbox is completely inside the polygon
     // Test if any corner of the bbox is inside the polygon
-    let bbox_corners = [
-        [min_lng, min_lat], // bottom-left
-        [max_lng, min_lat], // bottom-right
-        [max_lng, max_lat], // top-right
-        [min_lng, max_lat], // top-left
-    ];
-
-    for corner in &bbox_corners {
+    for corner in &bbox_corners(bbox) {
         if is_point_in_polygon(corner, polygon) {
             return true;
         }
@@ -95,6 +109,42 @@ pub fn polygon_intersects_bbox(polygon: &Vec<Vec<f64>>, bbox: &[f64]) -> bool {
     false
 }
 
+// Helper function to check if a ring encloses a bounding box without touching it:
+// every corner is inside and no ring vertex or edge reaches into the bbox
+fn ring_contains_bbox(ring: &[Vec<f64>], bbox: &[f64]) -> bool {
+    if ring.len() < 3 || ring.iter().any(|point| point_in_bbox(point, bbox)) {
+        return false;
+    }
+    let corners = bbox_corners(bbox);
+    if !corners
+        .iter()
+        .all(|corner| is_point_in_polygon(corner, ring))
+    {
+        return false;
+    }
+    let n = ring.len();
+    !(0..n).any(|i| {
+        (0..4).any(|edge| {
+            line_segments_intersect(
+                &ring[i],
+                &ring[(i + 1) % n],
+                &corners[edge],
+                &corners[(edge + 1) % 4],
+            )
+        })
+    })
+}
+
+// Helper function to list the corners of a bbox counter-clockwise from bottom-left
+fn bbox_corners(bbox: &[f64]) -> [[f64; 2]; 4] {
+    [
+        [bbox[0], bbox[1]], // bottom-left
+        [bbox[2], bbox[1]], // bottom-right
+        [bbox[2], bbox[3]], // top-right
+        [bbox[0], bbox[3]], // top-left
+    ]
+}
+
 // Helper function to check if two line segments intersect
 fn line_segments_intersect(p1: &[f64], p2: &[f64], p3: &[f64], p4: &[f64]) -> bool {
     let d1 = direction(p3, p4, p1);
@@ -140,7 +190,7 @@ fn is_point_on_segment(p1: &[f64], p2: &[f64], p: &[f64]) -> bool {
 }
 
 // Helper function to check if a point is inside a polygon using the ray casting algorithm
-fn is_point_in_polygon(point: &[f64], polygon: &Vec<Vec<f64>>) -> bool {
+pub(crate) fn is_point_in_polygon(point: &[f64], polygon: &[Vec<f64>]) -> bool {
     let mut inside = false;
     let x = point[0];
     let y = point[1];
@@ -162,3 +212,121 @@ fn is_point_in_polygon(point: &[f64], polygon: &Vec<Vec<f64>>) -> bool {
 
     inside
 }
+
+// GeoJSON geometries accepted by the JS-side bbox pre-filter
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "coordinates")]
+pub enum BboxFilterGeometry {
+    Polygon(Vec<Vec<Vec<f64>>>),
+    MultiPolygon(Vec<Vec<Vec<Vec<f64>>>>),
+}
+
+impl BboxFilterGeometry {
+    fn rings(&self) -> Box<dyn Iterator<Item = &Vec<Vec<f64>>> + '_> {
+        match self {
+            BboxFilterGeometry::Polygon(rings) => Box::new(rings.iter()),
+            BboxFilterGeometry::MultiPolygon(polygons) => Box::new(polygons.iter().flatten()),
+        }
+    }
+
+    fn intersects_bbox(&self, bbox: &[f64]) -> bool {
+        match self {
+            BboxFilterGeometry::Polygon(rings) => {
+                multipolygon_intersects_bbox(std::slice::from_ref(rings), bbox)
+            }
+            BboxFilterGeometry::MultiPolygon(polygons) => {
+                multipolygon_intersects_bbox(polygons, bbox)
+            }
+        }
+    }
+}
+
+// Indices of the GeoJSON Polygon/MultiPolygon geometries in `geometries_json` (a JSON
+// array) that intersect `bbox` ([minLng, minLat, maxLng, maxLat]), honoring holes
+#[wasm_bindgen]
+pub fn filter_geometries_by_bbox(geometries_json: &str, bbox: &[f64]) -> Result<Vec<u32>, JsValue> {
+    if bbox.len() != 4 {
+        return Err(JsValue::from_str(
+            "Invalid bbox: must contain [minLng, minLat, maxLng, maxLat]",
+        ));
+    }
+    let geometries: Vec<BboxFilterGeometry> = serde_json::from_str(geometries_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse geometries: {}", e)))?;
+    if let Some(index) = geometries
+        .iter()
+        .position(|geometry| geometry.rings().flatten().any(|point| point.len() < 2))
+    {
+        return Err(JsValue::from_str(&format!(
+            "Geometry {} has a position with fewer than 2 coordinates",
+            index
+        )));
+    }
+    Ok(geometries
+        .iter()
+        .enumerate()
+        .filter(|(_, geometry)| geometry.intersects_bbox(bbox))
+        .map(|(index, _)| index as u32)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64) -> Vec<Vec<f64>> {
+        vec![
+            vec![min, min],
+            vec![max, min],
+            vec![max, max],
+            vec![min, max],
+        ]
+    }
+
+    #[test]
+    fn test_holes_and_multipolygons() {
+        let exterior = square(0.0, 10.0);
+        let holes = vec![square(2.0, 8.0)];
+
+        // Inside the hole, straddling the hole edge, and inside the filled part
+        assert!(!polygon_intersects_bbox(
+            &exterior,
+            &holes,
+            &[4.0, 4.0, 5.0, 5.0]
+        ));
+        assert!(polygon_intersects_bbox(
+            &exterior,
+            &holes,
+            &[1.0, 4.0, 3.0, 5.0]
+        ));
+        assert!(polygon_intersects_bbox(
+            &exterior,
+            &holes,
+            &[0.5, 0.5, 1.5, 1.5]
+        ));
+        assert!(polygon_intersects_bbox(
+            &exterior,
+            &[],
+            &[4.0, 4.0, 5.0, 5.0]
+        ));
+
+        // Only the second polygon reaches the bbox
+        let multi = vec![
+            vec![square(0.0, 1.0)],
+            vec![exterior.clone(), holes[0].clone()],
+        ];
+        assert!(multipolygon_intersects_bbox(
+            &multi,
+            &[9.0, 9.0, 12.0, 12.0]
+        ));
+        assert!(!multipolygon_intersects_bbox(&multi, &[4.0, 4.0, 5.0, 5.0]));
+
+        let geometries = r#"[
+            {"type": "Polygon", "coordinates": [[[0,0],[10,0],[10,10],[0,10]], [[2,2],[8,2],[8,8],[2,8]]]},
+            {"type": "MultiPolygon", "coordinates": [[[[20,20],[21,20],[21,21]]], [[[4,4],[6,4],[6,6],[4,6]]]]}
+        ]"#;
+        assert_eq!(
+            filter_geometries_by_bbox(geometries, &[4.5, 4.5, 5.0, 5.0]).unwrap(),
+            vec![1]
+        );
+    }
+}
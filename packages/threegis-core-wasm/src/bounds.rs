@@ -0,0 +1,175 @@
+// Bounding volumes of generated output, kept per process so the host can frame the
+// camera and cull layers without scanning vertex arrays in JS.
+use serde::Serialize;
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::BufferGeometry;
+
+// Key under which the terrain volume is stored next to the layer labels
+pub(crate) const TERRAIN_BOUNDS_KEY: &str = "terrain";
+
+/// Axis-aligned box plus enclosing sphere in mesh space
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct BoundingVolume {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+    pub center: [f64; 3],
+    pub radius: f64,
+}
+
+impl BoundingVolume {
+    /// Volume of a flat [x, y, z, ...] position array; None when it has no finite vertex
+    pub fn from_positions(positions: &[f32]) -> Option<Self> {
+        Self::from_position_sets(&[positions])
+    }
+
+    /// Volume enclosing several position arrays, e.g. all features of a layer
+    pub fn from_position_sets(sets: &[&[f32]]) -> Option<Self> {
+        let points: Vec<[f64; 3]> = sets
+            .iter()
+            .flat_map(|positions| positions.chunks_exact(3))
+            .map(|p| [p[0] as f64, p[1] as f64, p[2] as f64])
+            .filter(|p| p.iter().all(|c| c.is_finite()))
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for point in &points {
+            for (axis, value) in point.iter().enumerate() {
+                min[axis] = min[axis].min(*value);
+                max[axis] = max[axis].max(*value);
+            }
+        }
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        // Tighter than the half diagonal: farthest actual vertex from the box center
+        let radius = points
+            .iter()
+            .map(|p| distance(p, &center))
+            .fold(0.0, f64::max);
+
+        Some(Self {
+            min,
+            max,
+            center,
+            radius,
+        })
+    }
+
+    /// Smallest volume of this kind enclosing both
+    pub fn union(&self, other: &Self) -> Self {
+        let min = [0, 1, 2].map(|axis| self.min[axis].min(other.min[axis]));
+        let max = [0, 1, 2].map(|axis| self.max[axis].max(other.max[axis]));
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        let radius = f64::max(
+            distance(&center, &self.center) + self.radius,
+            distance(&center, &other.center) + other.radius,
+        );
+        Self {
+            min,
+            max,
+            center,
+            radius,
+        }
+    }
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Recompute the volume of one generated layer of a process
+pub(crate) fn register_layer_bounds(
+    process_id: &str,
+    layer_label: &str,
+    geometries: &[BufferGeometry],
+) {
+    let sets: Vec<&[f32]> = geometries
+        .iter()
+        .filter(|g| g.has_data)
+        .map(|g| g.vertices.as_slice())
+        .collect();
+    store_bounds(
+        process_id,
+        layer_label,
+        BoundingVolume::from_position_sets(&sets),
+    );
+}
+
+/// Store (or with None, drop) the volume of one layer of a process
+pub(crate) fn store_bounds(process_id: &str, key: &str, volume: Option<BoundingVolume>) {
+    ModuleState::with_mut(|state| {
+        let layers = state
+            .model_bounds
+            .entry(process_id.to_string())
+            .or_default();
+        match volume {
+            Some(volume) => {
+                layers.insert(key.to_string(), volume);
+            }
+            None => {
+                layers.remove(key);
+            }
+        }
+    });
+}
+
+#[derive(Serialize, Debug)]
+pub struct ModelBounds {
+    /// Volumes keyed by layer label, plus "terrain" when terrain was generated
+    pub layers: BTreeMap<String, BoundingVolume>,
+    /// Volume enclosing all layers; null when nothing was generated
+    pub model: Option<BoundingVolume>,
+}
+
+pub(crate) fn model_bounds(process_id: &str) -> ModelBounds {
+    let layers: BTreeMap<String, BoundingVolume> = ModuleState::with(|state| {
+        state
+            .model_bounds
+            .get(process_id)
+            .map(|layers| layers.iter().map(|(k, v)| (k.clone(), *v)).collect())
+            .unwrap_or_default()
+    });
+    let model = layers
+        .values()
+        .copied()
+        .reduce(|acc, volume| acc.union(&volume));
+    ModelBounds { layers, model }
+}
+
+/// Bounding volumes of every generated layer and of the whole model for a process, as JSON
+#[wasm_bindgen]
+pub fn get_model_bounds(process_id: &str) -> Result<String, JsValue> {
+    serde_json::to_string(&model_bounds(process_id))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize bounds: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volumes_enclose_vertices_and_union() {
+        let a = BoundingVolume::from_positions(&[0.0, 0.0, 0.0, 2.0, 0.0, 0.0]).unwrap();
+        assert_eq!(a.center, [1.0, 0.0, 0.0]);
+        assert_eq!(a.radius, 1.0);
+        assert!(BoundingVolume::from_positions(&[f32::NAN, 0.0, 0.0]).is_none());
+
+        store_bounds("bounds-test", "roads", Some(a));
+        let b = BoundingVolume::from_positions(&[10.0, 0.0, 5.0]).unwrap();
+        store_bounds("bounds-test", TERRAIN_BOUNDS_KEY, Some(b));
+
+        let bounds = model_bounds("bounds-test");
+        assert_eq!(bounds.layers.len(), 2);
+        let model = bounds.model.unwrap();
+        assert_eq!(model.min, [0.0, 0.0, 0.0]);
+        assert_eq!(model.max, [10.0, 0.0, 5.0]);
+        for corner in [[0.0, 0.0, 0.0], [10.0, 0.0, 5.0]] {
+            assert!(distance(&corner, &model.center) <= model.radius + 1e-9);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// Bridges and tunnels of transportation layers, from the OpenMapTiles `brunnel` property
+// or the OSM bridge / tunnel tags. Bridges are lifted off the terrain as a straight deck
+// rising from both ends and standing on piers; tunnels are left out, sunk below the
+// surface or draped like other roads.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::polygon_geometry::GeometryData;
+use crate::solid_mesh::SolidMesh;
+
+/// Deck height above the terrain between the bridge ramps, in meters
+const DEFAULT_BRIDGE_CLEARANCE: f64 = 5.0;
+const DEFAULT_DECK_THICKNESS: f64 = 1.5;
+const DEFAULT_PIER_SPACING: f64 = 40.0;
+const DEFAULT_TUNNEL_DEPTH: f64 = 2.0;
+/// Share of the bridge length at each end over which the deck rises to its clearance
+const RAMP_SHARE: f64 = 0.25;
+/// Pier width as a share of the deck width
+const PIER_WIDTH_SHARE: f64 = 0.5;
+/// Longest deck section between profile stations, in mesh units
+const STATION_STEP: f64 = 1.0;
+/// Longest miter at a bend of the deck, as a multiple of its half width
+const MITER_LIMIT: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Brunnel {
+    Bridge,
+    Tunnel,
+}
+
+/// Whether a feature is a bridge or a tunnel: `brunnel` as in OpenMapTiles, else the OSM
+/// `bridge` and `tunnel` tags (building passages are not tunnels)
+pub(crate) fn brunnel_of(feature: &GeometryData) -> Option<Brunnel> {
+    let tag = |key: &str| {
+        [feature.properties.as_ref(), feature.tags.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|values| values.get(key))
+            .and_then(|value| match value {
+                Value::String(s) => Some(s.as_str()),
+                Value::Bool(true) => Some("yes"),
+                _ => None,
+            })
+            .filter(|value| !matches!(*value, "no" | "building_passage"))
+    };
+    match tag("brunnel") {
+        Some("bridge") => return Some(Brunnel::Bridge),
+        Some("tunnel") => return Some(Brunnel::Tunnel),
+        _ => {}
+    }
+    if tag("bridge").is_some() {
+        Some(Brunnel::Bridge)
+    } else if tag("tunnel").is_some() {
+        Some(Brunnel::Tunnel)
+    } else {
+        None
+    }
+}
+
+/// How tunnels of a layer with a brunnel config are drawn
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TunnelMode {
+    /// Leave tunnels out
+    #[default]
+    Skip,
+    /// Sink tunnels below the terrain surface by tunnelDepth
+    Recessed,
+    /// Drape tunnels on the terrain like other roads
+    Ground,
+}
+
+/// Bridge and tunnel handling of a transportation layer, lengths in meters. Heights are
+/// scaled like other line extrusions, pier spacing to the map scale.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BrunnelConfig {
+    #[serde(default)]
+    pub tunnels: TunnelMode,
+    /// Deck height above the terrain between the bridge ramps (default 5)
+    #[serde(default, rename = "bridgeClearance")]
+    pub bridge_clearance: Option<f64>,
+    /// Bridge deck thickness (default 1.5)
+    #[serde(default, rename = "deckThickness")]
+    pub deck_thickness: Option<f64>,
+    /// Distance between bridge piers (default 40); 0 builds no piers
+    #[serde(default, rename = "pierSpacing")]
+    pub pier_spacing: Option<f64>,
+    /// Depth of recessed tunnels below the surface (default 2)
+    #[serde(default, rename = "tunnelDepth")]
+    pub tunnel_depth: Option<f64>,
+}
+
+fn length_or(value: Option<f64>, default: f64) -> f64 {
+    value
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .unwrap_or(default)
+}
+
+impl BrunnelConfig {
+    pub fn bridge_clearance(&self) -> f64 {
+        length_or(self.bridge_clearance, DEFAULT_BRIDGE_CLEARANCE)
+    }
+
+    pub fn deck_thickness(&self) -> f64 {
+        length_or(self.deck_thickness, DEFAULT_DECK_THICKNESS)
+    }
+
+    pub fn pier_spacing(&self) -> f64 {
+        length_or(self.pier_spacing, DEFAULT_PIER_SPACING)
+    }
+
+    pub fn tunnel_depth(&self) -> f64 {
+        length_or(self.tunnel_depth, DEFAULT_TUNNEL_DEPTH)
+    }
+}
+
+/// Bridge dimensions in mesh units
+pub(crate) struct BridgeParams {
+    pub half_width: f64,
+    /// Deck top above the terrain at the bridge ends, where it meets the road
+    pub end_clearance: f64,
+    /// Deck top above the terrain between the ramps
+    pub clearance: f64,
+    pub deck_thickness: f64,
+    /// Distance between piers; 0 builds no piers
+    pub pier_spacing: f64,
+    /// Depth piers reach into the terrain
+    pub submerge: f64,
+}
+
+/// A point of the deck profile: position, distance along the bridge and the direction
+/// of the deck there
+struct Station {
+    point: [f64; 2],
+    distance: f64,
+    /// Left-hand offset to the deck edge
+    offset: [f64; 2],
+}
+
+// Points every STATION_STEP along the line, with mitered offsets at its bends
+fn stations(line: &[[f64; 2]], half_width: f64) -> Vec<Station> {
+    let normal = |a: [f64; 2], b: [f64; 2]| {
+        let length = (b[0] - a[0]).hypot(b[1] - a[1]);
+        [-(b[1] - a[1]) / length, (b[0] - a[0]) / length]
+    };
+    let mut stations = Vec::new();
+    let mut distance = 0.0;
+    for i in 0..line.len() - 1 {
+        let (a, b) = (line[i], line[i + 1]);
+        let n = normal(a, b);
+        let length = (b[0] - a[0]).hypot(b[1] - a[1]);
+        // The start of a segment is the bend with the previous one
+        let start_offset = match i.checked_sub(1).map(|j| normal(line[j], a)) {
+            Some(previous) => {
+                let mid = [previous[0] + n[0], previous[1] + n[1]];
+                let mid_length = mid[0].hypot(mid[1]).max(1e-12);
+                let cos_half = (mid_length / 2.0).max(1.0 / MITER_LIMIT);
+                let scale = half_width / cos_half / mid_length;
+                [mid[0] * scale, mid[1] * scale]
+            }
+            None => [n[0] * half_width, n[1] * half_width],
+        };
+        let steps = (length / STATION_STEP).ceil().max(1.0) as usize;
+        for k in 0..steps {
+            let t = k as f64 / steps as f64;
+            stations.push(Station {
+                point: [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t],
+                distance: distance + length * t,
+                offset: if k == 0 {
+                    start_offset
+                } else {
+                    [n[0] * half_width, n[1] * half_width]
+                },
+            });
+        }
+        distance += length;
+        if i == line.len() - 2 {
+            stations.push(Station {
+                point: b,
+                distance,
+                offset: [n[0] * half_width, n[1] * half_width],
+            });
+        }
+    }
+    stations
+}
+
+/// Closed box of `half_side` around `center`, turned along `direction`, from `bottom` to
+/// `top`
+fn push_pier(
+    mesh: &mut SolidMesh,
+    center: [f64; 2],
+    direction: [f64; 2],
+    half_side: f64,
+    bottom: f64,
+    top: f64,
+) {
+    let (d, n) = (direction, [-direction[1], direction[0]]);
+    let corner = |sd: f64, sn: f64| {
+        [
+            center[0] + (d[0] * sd + n[0] * sn) * half_side,
+            center[1] + (d[1] * sd + n[1] * sn) * half_side,
+        ]
+    };
+    // Counter-clockwise seen from above
+    let ring = [
+        corner(-1.0, -1.0),
+        corner(1.0, -1.0),
+        corner(1.0, 1.0),
+        corner(-1.0, 1.0),
+    ];
+    let at = |p: [f64; 2], z: f64| [p[0], p[1], z];
+    mesh.push_quad(ring.map(|p| at(p, top)));
+    mesh.push_quad([ring[3], ring[2], ring[1], ring[0]].map(|p| at(p, bottom)));
+    for i in 0..4 {
+        let (a, b) = (ring[i], ring[(i + 1) % 4]);
+        mesh.push_quad([at(a, bottom), at(b, bottom), at(b, top), at(a, top)]);
+    }
+}
+
+/// Deck and piers of a bridge along `line` (mesh coordinates) over the terrain heights
+/// given by `surface`. The deck runs straight from one end to the other and is lifted
+/// between its ramps until it clears the terrain everywhere by `clearance`.
+pub(crate) fn bridge_mesh(
+    line: &[[f64; 2]],
+    params: &BridgeParams,
+    surface: impl Fn(f64, f64) -> f64,
+) -> Option<SolidMesh> {
+    let mut line: Vec<[f64; 2]> = line
+        .iter()
+        .copied()
+        .filter(|p| p[0].is_finite() && p[1].is_finite())
+        .collect();
+    line.dedup();
+    if line.len() < 2 || params.half_width <= 0.0 || params.deck_thickness <= 0.0 {
+        return None;
+    }
+    let stations = stations(&line, params.half_width);
+    let length = stations.last()?.distance;
+
+    // Straight deck between the ends, lifted where the ramps are complete
+    let first = stations[0].point;
+    let last = stations[stations.len() - 1].point;
+    let start_z = surface(first[0], first[1]) + params.end_clearance;
+    let end_z = surface(last[0], last[1]) + params.end_clearance;
+    let straight = |distance: f64| start_z + (end_z - start_z) * distance / length;
+    let ramp =
+        |distance: f64| (distance.min(length - distance) / (RAMP_SHARE * length)).clamp(0.0, 1.0);
+    let lift = stations
+        .iter()
+        .filter(|s| ramp(s.distance) >= 1.0)
+        .map(|s| surface(s.point[0], s.point[1]) + params.clearance - straight(s.distance))
+        .fold(0.0, f64::max);
+    let deck_top = |distance: f64| straight(distance) + lift * ramp(distance);
+
+    let mut mesh = SolidMesh::default();
+    let corners = |s: &Station| {
+        let top = deck_top(s.distance);
+        let bottom = top - params.deck_thickness;
+        let left = [s.point[0] + s.offset[0], s.point[1] + s.offset[1]];
+        let right = [s.point[0] - s.offset[0], s.point[1] - s.offset[1]];
+        // Left bottom, left top, right bottom, right top
+        [
+            [left[0], left[1], bottom],
+            [left[0], left[1], top],
+            [right[0], right[1], bottom],
+            [right[0], right[1], top],
+        ]
+    };
+    let sections: Vec<[[f64; 3]; 4]> = stations.iter().map(corners).collect();
+    for pair in sections.windows(2) {
+        let ([lb, lt, rb, rt], [lb2, lt2, rb2, rt2]) = (pair[0], pair[1]);
+        mesh.push_quad([rt, rt2, lt2, lt]);
+        mesh.push_quad([rb, lb, lb2, rb2]);
+        mesh.push_quad([rb, rb2, rt2, rt]);
+        mesh.push_quad([lb2, lb, lt, lt2]);
+    }
+    let [lb, lt, rb, rt] = sections[0];
+    mesh.push_quad([lb, rb, rt, lt]);
+    let [lb, lt, rb, rt] = sections[sections.len() - 1];
+    mesh.push_quad([rb, lb, lt, rt]);
+
+    // Piers every pier_spacing from the middle of the bridge, where the deck is lifted
+    if params.pier_spacing > 0.0 {
+        let half_side = params.half_width * PIER_WIDTH_SHARE;
+        let count = (length / 2.0 / params.pier_spacing).floor() as i64;
+        for k in -count..=count {
+            let distance = length / 2.0 + k as f64 * params.pier_spacing;
+            if ramp(distance) < 1.0 {
+                continue;
+            }
+            let Some(i) = stations.windows(2).position(|w| w[1].distance >= distance) else {
+                continue;
+            };
+            let (a, b) = (&stations[i], &stations[i + 1]);
+            let span = b.distance - a.distance;
+            let t = if span > 0.0 {
+                (distance - a.distance) / span
+            } else {
+                0.0
+            };
+            let center = [
+                a.point[0] + (b.point[0] - a.point[0]) * t,
+                a.point[1] + (b.point[1] - a.point[1]) * t,
+            ];
+            let direction = [
+                (b.point[0] - a.point[0]) / span.max(1e-12),
+                (b.point[1] - a.point[1]) / span.max(1e-12),
+            ];
+            let ground = surface(center[0], center[1]);
+            // Reaching halfway into the deck, so pier and deck overlap
+            let top = deck_top(distance) - params.deck_thickness / 2.0;
+            if top > ground {
+                push_pier(
+                    &mut mesh,
+                    center,
+                    direction,
+                    half_side,
+                    ground - params.submerge,
+                    top,
+                );
+            }
+        }
+    }
+    (!mesh.is_empty()).then_some(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn road(properties: Value) -> GeometryData {
+        GeometryData {
+            geometry: Vec::new(),
+            holes: None,
+            r#type: Some("LineString".to_string()),
+            height: None,
+            layer: Some("transportation".to_string()),
+            label: None,
+            tags: None,
+            properties: Some(properties),
+        }
+    }
+
+    #[test]
+    fn test_brunnel_from_properties_and_osm_tags() {
+        let brunnel = |properties| brunnel_of(&road(properties));
+        assert_eq!(
+            brunnel(json!({ "brunnel": "bridge" })),
+            Some(Brunnel::Bridge)
+        );
+        assert_eq!(
+            brunnel(json!({ "brunnel": "tunnel" })),
+            Some(Brunnel::Tunnel)
+        );
+        assert_eq!(
+            brunnel(json!({ "bridge": "viaduct" })),
+            Some(Brunnel::Bridge)
+        );
+        assert_eq!(brunnel(json!({ "tunnel": "building_passage" })), None);
+        assert_eq!(brunnel(json!({ "brunnel": "ford", "bridge": "no" })), None);
+    }
+
+    #[test]
+    fn test_bridge_deck_is_lifted_onto_piers() {
+        let params = BridgeParams {
+            half_width: 1.0,
+            end_clearance: 0.1,
+            clearance: 2.0,
+            deck_thickness: 0.5,
+            pier_spacing: 5.0,
+            submerge: 0.1,
+        };
+        let mesh = bridge_mesh(&[[0.0, 0.0], [20.0, 0.0]], &params, |_, _| 0.0).unwrap();
+
+        // Deck 20 x 2 x 0.5 plus piers at 5, 10 and 15: 1 x 1 from -0.1 up to 1.75
+        let expected = 20.0 * 2.0 * 0.5 + 3.0 * 1.85;
+        assert!((mesh.volume() - expected).abs() < 1e-3);
+        let top = mesh
+            .into_geometry(None)
+            .vertices
+            .chunks(3)
+            .map(|v| v[2])
+            .fold(f32::MIN, f32::max);
+        assert!((top - 2.0).abs() < 1e-5);
+    }
+}
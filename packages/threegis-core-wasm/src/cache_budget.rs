@@ -0,0 +1,339 @@
+// Byte accounting and least-recently-used eviction for the large ModuleState caches:
+// raster tiles, fetched vector tiles, parsed MVT tiles and elevation grids. Every entry
+// is registered with an estimate of its heap size when stored and touched when read;
+// once the total exceeds the memory budget the least recently used entries are dropped
+// until it fits again, so long sessions do not exhaust WASM memory.
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::chunking::now_ms;
+use crate::module_state::{ModuleState, TileData};
+use crate::vectortile::ParsedMvtTile;
+
+// Budget until `set_cache_memory_limit` is called
+const DEFAULT_MEMORY_LIMIT_BYTES: usize = 512 * 1024 * 1024;
+
+/// Cache an accounted entry belongs to
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum CacheKind {
+    /// `raster_tiles`, keyed "z/x/y"
+    RasterTile,
+    /// `process_vector_tiles`, keyed by process id
+    VectorTiles,
+    /// `mvt_parsed_tiles`, keyed like the map
+    ParsedMvt,
+    /// `elevation_grids`, keyed like the map
+    ElevationGrid,
+}
+
+impl CacheKind {
+    pub fn parse(kind: &str) -> Result<Self, String> {
+        match kind {
+            "rasterTile" => Ok(CacheKind::RasterTile),
+            "vectorTiles" => Ok(CacheKind::VectorTiles),
+            "parsedMvt" => Ok(CacheKind::ParsedMvt),
+            "elevationGrid" => Ok(CacheKind::ElevationGrid),
+            other => Err(format!(
+                "Unknown cache kind '{}', expected rasterTile, vectorTiles, parsedMvt or \
+                 elevationGrid",
+                other
+            )),
+        }
+    }
+}
+
+struct CacheEntry {
+    bytes: usize,
+    last_used: Cell<u64>,
+    stored_ms: f64,
+    last_used_ms: Cell<f64>,
+    hits: Cell<u64>,
+}
+
+/// An accounted cache entry as listed by `list_cache_entries`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntryInfo {
+    pub kind: CacheKind,
+    pub key: String,
+    pub bytes: usize,
+    pub stored_ms: f64,
+    pub last_used_ms: f64,
+    /// Reads since the entry was stored
+    pub hits: u64,
+}
+
+/// Sizes and recency of the accounted cache entries. Reads go through `&ModuleState`, so
+/// recency lives in cells.
+pub struct CacheUsage {
+    entries: HashMap<(CacheKind, String), CacheEntry>,
+    clock: Cell<u64>,
+    total_bytes: usize,
+    /// None for no limit
+    pub limit_bytes: Option<usize>,
+    /// Entries evicted to stay within the budget
+    pub evictions: usize,
+}
+
+impl Default for CacheUsage {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            clock: Cell::new(0),
+            total_bytes: 0,
+            limit_bytes: Some(DEFAULT_MEMORY_LIMIT_BYTES),
+            evictions: 0,
+        }
+    }
+}
+
+impl CacheUsage {
+    fn tick(&self) -> u64 {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+        now
+    }
+
+    /// Account a stored entry, replacing the size of an earlier one under the same key
+    pub fn record(&mut self, kind: CacheKind, key: &str, bytes: usize) {
+        let now = now_ms();
+        let entry = CacheEntry {
+            bytes,
+            last_used: Cell::new(self.tick()),
+            stored_ms: now,
+            last_used_ms: Cell::new(now),
+            hits: Cell::new(0),
+        };
+        if let Some(old) = self.entries.insert((kind, key.to_string()), entry) {
+            self.total_bytes -= old.bytes;
+        }
+        self.total_bytes += bytes;
+    }
+
+    /// Mark an entry as just used
+    pub fn touch(&self, kind: CacheKind, key: &str) {
+        if let Some(entry) = self.entries.get(&(kind, key.to_string())) {
+            entry.last_used.set(self.tick());
+            entry.last_used_ms.set(now_ms());
+            entry.hits.set(entry.hits.get() + 1);
+        }
+    }
+
+    /// Stop accounting an entry that was removed from its cache
+    pub fn forget(&mut self, kind: CacheKind, key: &str) {
+        if let Some(old) = self.entries.remove(&(kind, key.to_string())) {
+            self.total_bytes -= old.bytes;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.total_bytes = 0;
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// Entries of `kind` (all when None), most recently used first
+    pub fn entries(&self, kind: Option<CacheKind>) -> Vec<CacheEntryInfo> {
+        let mut entries: Vec<(u64, CacheEntryInfo)> = self
+            .entries
+            .iter()
+            .filter(|((entry_kind, _), _)| kind.is_none_or(|kind| kind == *entry_kind))
+            .map(|((kind, key), entry)| {
+                (
+                    entry.last_used.get(),
+                    CacheEntryInfo {
+                        kind: *kind,
+                        key: key.clone(),
+                        bytes: entry.bytes,
+                        stored_ms: entry.stored_ms,
+                        last_used_ms: entry.last_used_ms.get(),
+                        hits: entry.hits.get(),
+                    },
+                )
+            })
+            .collect();
+        entries.sort_by_key(|(last_used, _)| std::cmp::Reverse(*last_used));
+        entries.into_iter().map(|(_, info)| info).collect()
+    }
+
+    /// Least recently used entry other than `keep`, while the total is over the budget
+    pub fn next_eviction(&self, keep: Option<(CacheKind, &str)>) -> Option<(CacheKind, String)> {
+        let limit = self.limit_bytes?;
+        if self.total_bytes <= limit {
+            return None;
+        }
+        self.entries
+            .iter()
+            .filter(|((kind, key), _)| keep != Some((*kind, key.as_str())))
+            .min_by_key(|(_, entry)| entry.last_used.get())
+            .map(|(id, _)| id.clone())
+    }
+}
+
+/// Approximate heap size of a cached tile
+pub fn tile_bytes(tile: &TileData) -> usize {
+    let parsed_layers: usize = tile.parsed_layers.as_ref().map_or(0, |layers| {
+        layers
+            .values()
+            .flatten()
+            .map(|feature| {
+                feature.geometry.coordinates.to_string().len()
+                    + feature.properties.to_string().len()
+            })
+            .sum()
+    });
+    std::mem::size_of::<TileData>()
+        + tile.data.len()
+        + tile.buffer.len()
+        + tile.key.len()
+        + tile.rust_parsed_mvt.as_ref().map_or(0, Vec::len)
+        + parsed_layers
+}
+
+/// Approximate heap size of a parsed MVT tile: the raw bytes plus the decoded features
+pub fn parsed_mvt_bytes(tile: &ParsedMvtTile) -> usize {
+    let features: usize = tile
+        .layers
+        .values()
+        .flat_map(|layer| &layer.features)
+        .map(|feature| {
+            let points: usize = feature.geometry.iter().flatten().map(|p| p.len()).sum();
+            // Property maps cost roughly a key, a value and the table slot per entry
+            64 + points * std::mem::size_of::<f64>() + feature.properties.len() * 64
+        })
+        .sum();
+    std::mem::size_of::<ParsedMvtTile>() + tile.raw_data.len() + features
+}
+
+pub fn elevation_grid_bytes(grid: &[Vec<f64>]) -> usize {
+    grid.iter()
+        .map(|row| std::mem::size_of::<Vec<f64>>() + row.len() * std::mem::size_of::<f64>())
+        .sum()
+}
+
+/// Limit the memory used by tile and elevation caches to `mb` megabytes, evicting least
+/// recently used entries right away when they exceed it. 0 removes the limit. Returns
+/// the number of entries evicted.
+#[wasm_bindgen]
+pub fn set_cache_memory_limit(mb: f64) -> Result<usize, JsValue> {
+    if !mb.is_finite() || mb < 0.0 {
+        return Err(JsValue::from_str(&format!(
+            "Cache memory limit must be a non-negative number of MB, got {}",
+            mb
+        )));
+    }
+    ModuleState::with_mut(|state| {
+        state.cache_usage.limit_bytes = (mb > 0.0).then_some((mb * 1024.0 * 1024.0) as usize);
+        Ok(state.enforce_memory_budget(None))
+    })
+}
+
+/// Entries of the tile and elevation caches as a JSON array of `CacheEntryInfo`, most
+/// recently used first. `kind` is "rasterTile", "vectorTiles", "parsedMvt" or
+/// "elevationGrid"; omitted or "all" lists every kind.
+#[wasm_bindgen]
+pub fn list_cache_entries(kind: Option<String>) -> Result<String, JsValue> {
+    let kind = match kind.as_deref() {
+        None | Some("all") => None,
+        Some(kind) => Some(CacheKind::parse(kind).map_err(|e| JsValue::from_str(&e))?),
+    };
+    let entries = ModuleState::with(|state| state.cache_usage.entries(kind));
+    serde_json::to_string(&entries)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize cache entries: {}", e)))
+}
+
+/// Drop one entry, as listed by `list_cache_entries`, from its cache. Returns whether it
+/// was cached.
+#[wasm_bindgen]
+pub fn evict_cache_entry(kind: &str, key: &str) -> Result<bool, JsValue> {
+    let kind = CacheKind::parse(kind).map_err(|e| JsValue::from_str(&e))?;
+    Ok(ModuleState::with_mut(|state| {
+        state.remove_cache_entry(kind, key)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_entry_is_evicted_first() {
+        let mut usage = CacheUsage {
+            limit_bytes: Some(250),
+            ..CacheUsage::default()
+        };
+        usage.record(CacheKind::RasterTile, "1/0/0", 100);
+        usage.record(CacheKind::ElevationGrid, "p", 100);
+        assert_eq!(usage.next_eviction(None), None);
+
+        usage.touch(CacheKind::RasterTile, "1/0/0");
+        usage.record(CacheKind::ParsedMvt, "1/0/1", 100);
+        assert_eq!(usage.total_bytes(), 300);
+        assert_eq!(
+            usage.next_eviction(None),
+            Some((CacheKind::ElevationGrid, "p".to_string()))
+        );
+        // The entry just stored is never the one evicted
+        usage.forget(CacheKind::ElevationGrid, "p");
+        usage.record(CacheKind::ElevationGrid, "p", 100);
+        assert_eq!(
+            usage.next_eviction(Some((CacheKind::ElevationGrid, "p"))),
+            Some((CacheKind::RasterTile, "1/0/0".to_string()))
+        );
+
+        // Re-storing a key replaces its size
+        usage.record(CacheKind::ElevationGrid, "p", 10);
+        assert_eq!(usage.total_bytes(), 210);
+        assert_eq!(usage.next_eviction(None), None);
+        usage.forget(CacheKind::RasterTile, "1/0/0");
+        assert_eq!(usage.total_bytes(), 110);
+    }
+
+    #[test]
+    fn entries_report_hits_most_recent_first() {
+        let mut usage = CacheUsage::default();
+        usage.record(CacheKind::RasterTile, "1/0/0", 100);
+        usage.record(CacheKind::ParsedMvt, "1/0/0", 50);
+        usage.touch(CacheKind::RasterTile, "1/0/0");
+        usage.touch(CacheKind::RasterTile, "1/0/0");
+
+        let all = usage.entries(None);
+        assert_eq!(all.len(), 2);
+        assert_eq!((all[0].kind, all[0].hits), (CacheKind::RasterTile, 2));
+        assert_eq!((all[1].kind, all[1].hits), (CacheKind::ParsedMvt, 0));
+        assert!(all[0].last_used_ms >= all[0].stored_ms);
+
+        let parsed = usage.entries(Some(CacheKind::ParsedMvt));
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].bytes, 50);
+        assert!(CacheKind::parse("geometry").is_err());
+    }
+
+    #[test]
+    fn storing_past_the_budget_evicts_old_elevation_grids() {
+        let mut state = ModuleState::new();
+        let grid = vec![vec![0.0; 100]; 100];
+        let grid_bytes = elevation_grid_bytes(&grid);
+        state.cache_usage.limit_bytes = Some(grid_bytes * 2);
+        state.store_elevation_grid("a".to_string(), grid.clone());
+        state.store_elevation_grid("b".to_string(), grid.clone());
+        assert!(state.get_elevation_grid("a").is_some());
+        state.store_elevation_grid("c".to_string(), grid);
+
+        // "a" was read after "b" was stored, so "b" goes
+        assert!(state.elevation_grids.contains_key("a"));
+        assert!(!state.elevation_grids.contains_key("b"));
+        assert_eq!(state.cache_usage.total_bytes(), grid_bytes * 2);
+        assert_eq!(state.cache_usage.evictions, 1);
+
+        assert!(state.remove_cache_entry(CacheKind::ElevationGrid, "a"));
+        assert!(!state.remove_cache_entry(CacheKind::ElevationGrid, "a"));
+        assert_eq!(state.cache_usage.total_bytes(), grid_bytes);
+    }
+}
@@ -1,15 +1,76 @@
 // Utility functions to generate consistent cache keys across the application.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU32, Ordering};
+use wasm_bindgen::prelude::*;
+
+// Decimal places bbox coordinates are rounded to before keying; 6 places is ~0.1 m
+const DEFAULT_KEY_PRECISION: u32 = 6;
+const MAX_KEY_PRECISION: u32 = 12;
+
+static KEY_PRECISION: AtomicU32 = AtomicU32::new(DEFAULT_KEY_PRECISION);
+
+/// Set the decimal places bbox coordinates are rounded to in cache keys. Bboxes that
+/// agree to this precision share cached data.
+#[wasm_bindgen]
+pub fn set_cache_key_precision(decimals: u32) -> Result<(), JsValue> {
+    if decimals > MAX_KEY_PRECISION {
+        return Err(JsValue::from_str(&format!(
+            "Cache key precision must be at most {} decimals, got {}",
+            MAX_KEY_PRECISION, decimals
+        )));
+    }
+    KEY_PRECISION.store(decimals, Ordering::Relaxed);
+    Ok(())
+}
+
+pub(crate) fn reset_cache_key_precision() {
+    KEY_PRECISION.store(DEFAULT_KEY_PRECISION, Ordering::Relaxed);
+}
+
+pub fn cache_key_precision() -> u32 {
+    KEY_PRECISION.load(Ordering::Relaxed)
+}
+
+/// Coordinate rounded to `precision` decimals, as an integer so float formatting and
+/// -0.0 cannot produce different keys
+pub fn normalize_coordinate(value: f64, precision: u32) -> i64 {
+    (value * 10f64.powi(precision as i32)).round() as i64
+}
 
 /// Generate a cache key for process-specific data storage.
 pub fn make_process_cache_key(process_id: &str, data_type: &str) -> String {
     format!("{}_{}", process_id, data_type)
 }
 
-/// Generate a legacy bbox key for backward compatibility (deprecated).
-#[deprecated(note = "Use process-based keys instead")]
-#[allow(dead_code)]
+/// Generate a hashed bbox key from coordinates normalized to the cache key precision.
 pub fn make_bbox_key(min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64) -> String {
-    format!("{}_{}_{}_{}", min_lng, min_lat, max_lng, max_lat)
+    bbox_key_with_precision([min_lng, min_lat, max_lng, max_lat], cache_key_precision())
+}
+
+fn bbox_key_with_precision(bbox: [f64; 4], precision: u32) -> String {
+    let mut hasher = DefaultHasher::new();
+    precision.hash(&mut hasher);
+    for value in bbox {
+        normalize_coordinate(value, precision).hash(&mut hasher);
+    }
+    format!("bbox_{:016x}", hasher.finish())
+}
+
+/// Compatibility shim for keys stored before bbox keys were hashed: a legacy
+/// "minLng_minLat_maxLng_maxLat" key maps to the hashed key of the same bbox, any
+/// other key (such as a process id) is returned unchanged.
+pub fn normalize_bbox_key(key: &str) -> String {
+    let coords: Vec<f64> = key
+        .split('_')
+        .filter_map(|part| part.parse().ok())
+        .collect();
+    match coords[..] {
+        [min_lng, min_lat, max_lng, max_lat] if key.split('_').count() == 4 => {
+            make_bbox_key(min_lng, min_lat, max_lng, max_lat)
+        }
+        _ => key.to_string(),
+    }
 }
 
 /// Generate an inner cache key from a source layer and optional filter string.
@@ -32,28 +93,36 @@ pub fn make_inner_key_with_label(label: &str, filter_str: &str) -> String {
     }
 }
 
-/// Generate an inner cache key from a source layer and optional filter JSON value.
-/// Handles null values and empty filters consistently by treating them as empty strings.
+/// Filter part of an inner cache key. Handles null values and empty filters consistently
+/// by treating them as empty strings; a named vector tile source is appended as "@name"
+/// so layers with the same filter from different sources do not share data.
+fn make_filter_key(filter: Option<&serde_json::Value>, source: Option<&str>) -> String {
+    let filter_str = filter
+        .filter(|f| !f.is_null())
+        .map(|f| f.to_string())
+        .unwrap_or_default();
+    match source {
+        Some(source) if source != crate::tilejson::DEFAULT_VECTOR_SOURCE => {
+            format!("{}@{}", filter_str, source)
+        }
+        _ => filter_str,
+    }
+}
+
+/// Generate an inner cache key from a source layer, optional filter JSON value and
+/// vector tile source.
 pub fn make_inner_key_from_filter(
     source_layer: &str,
     filter: Option<&serde_json::Value>,
+    source: Option<&str>,
 ) -> String {
-    let filter_str = filter
-        .filter(|f| !f.is_null())
-        .map(|f| f.to_string())
-        .unwrap_or_else(|| "".to_string());
-    make_inner_key(source_layer, &filter_str)
+    make_inner_key(source_layer, &make_filter_key(filter, source))
 }
 
 /// Generate an inner cache key from a VtDataSet using its label.
 #[allow(dead_code)]
 pub fn make_inner_key_from_vtdataset(vt_dataset: &crate::polygon_geometry::VtDataSet) -> String {
-    let filter_str = vt_dataset
-        .filter
-        .as_ref()
-        .filter(|f| !f.is_null())
-        .map(|f| f.to_string())
-        .unwrap_or_else(|| "".to_string());
+    let filter_str = make_filter_key(vt_dataset.filter.as_ref(), vt_dataset.source.as_deref());
     make_inner_key_with_label(vt_dataset.get_label(), &filter_str)
 }
 
@@ -62,12 +131,58 @@ pub fn make_process_vtdataset_key(
     process_id: &str,
     vt_dataset: &crate::polygon_geometry::VtDataSet,
 ) -> String {
-    let filter_str = vt_dataset
-        .filter
-        .as_ref()
-        .filter(|f| !f.is_null())
-        .map(|f| f.to_string())
-        .unwrap_or_else(|| "".to_string());
+    let filter_str = make_filter_key(vt_dataset.filter.as_ref(), vt_dataset.source.as_deref());
     let inner_key = make_inner_key_with_label(vt_dataset.get_label(), &filter_str);
     make_process_cache_key(process_id, &inner_key)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bbox_keys_ignore_float_noise() {
+        let bbox = [13.4, 52.5, 13.41, 52.51];
+        let noisy = [13.400000000001, 52.49999999999, 13.41, 52.510000000002];
+        assert_eq!(
+            bbox_key_with_precision(bbox, 6),
+            bbox_key_with_precision(noisy, 6)
+        );
+        assert_ne!(
+            bbox_key_with_precision(bbox, 6),
+            bbox_key_with_precision([13.4001, 52.5, 13.41, 52.51], 6)
+        );
+        assert_eq!(normalize_coordinate(-0.0, 6), normalize_coordinate(0.0, 6));
+
+        // Legacy string keys resolve to the hashed key, process ids are left alone
+        let legacy = format!("{}_{}_{}_{}", bbox[0], bbox[1], bbox[2], bbox[3]);
+        assert_eq!(
+            normalize_bbox_key(&legacy),
+            make_bbox_key(bbox[0], bbox[1], bbox[2], bbox[3])
+        );
+        assert_eq!(normalize_bbox_key("process_1_2_3"), "process_1_2_3");
+    }
+
+    #[test]
+    fn test_inner_keys_include_named_sources() {
+        let filter = serde_json::json!(["==", "class", "park"]);
+        let default = make_inner_key_from_filter("landuse", Some(&filter), None);
+        assert_eq!(default, r#"landuse_["==","class","park"]"#);
+        assert_eq!(
+            make_inner_key_from_filter("landuse", Some(&filter), Some("default")),
+            default
+        );
+        assert_eq!(
+            make_inner_key_from_filter("landuse", Some(&filter), Some("overlay")),
+            r#"landuse_["==","class","park"]@overlay"#
+        );
+        assert_eq!(
+            make_inner_key_from_filter("landuse", None, Some("overlay")),
+            "landuse_@overlay"
+        );
+        assert_eq!(
+            make_inner_key_from_filter("landuse", Some(&serde_json::Value::Null), None),
+            "landuse"
+        );
+    }
+}
@@ -174,3 +174,10 @@ pub fn free_cache_by_id(group_id: &str) -> Result<(), JsValue> {
     mgr.free_group(group_id);
     Ok(())
 }
+
+/// Drop all cache groups
+pub(crate) fn free_all_groups() {
+    if let Ok(mut mgr) = GLOBAL_CACHE_MANAGER.lock() {
+        mgr.groups.clear();
+    }
+}
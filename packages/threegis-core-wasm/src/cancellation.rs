@@ -69,9 +69,39 @@ impl CancellationManager {
         self.tokens.get(id)
     }
 
+    /// Token of a running job with `id`, shared with other jobs of the same id
+    pub fn job_token(&mut self, id: &str) -> CancellationToken {
+        self.tokens
+            .entry(id.to_string())
+            .or_insert_with(|| CancellationToken::new(id.to_string()))
+            .clone()
+    }
+
+    /// Cancel the jobs of `id` and forget their token, so later jobs start fresh;
+    /// returns whether any were running
+    pub fn cancel_jobs(&mut self, id: &str) -> bool {
+        match self.tokens.remove(id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn cleanup_token(&mut self, id: &str) {
         self.tokens.remove(id);
     }
+
+    /// Cancel every token and forget them; returns how many there were
+    pub fn cancel_all(&mut self) -> usize {
+        for token in self.tokens.values() {
+            token.cancel();
+        }
+        let count = self.tokens.len();
+        self.tokens.clear();
+        count
+    }
 }
 
 lazy_static! {
@@ -126,3 +156,91 @@ pub fn get_cancellation_token(id: &str) -> Option<CancellationToken> {
         None
     }
 }
+
+/// Stop the geometry jobs of a process (layer generation, feature extraction, tile
+/// fetching) at their next chunk or tile. They fail with a JS Error named "Cancelled".
+/// Returns whether a job of the process was running.
+#[wasm_bindgen]
+pub fn cancel_process(process_id: &str) -> bool {
+    GLOBAL_CANCELLATION_MANAGER
+        .lock()
+        .map(|mut manager| manager.cancel_jobs(process_id))
+        .unwrap_or(false)
+}
+
+// Prefix of the error message of a cancelled job
+const CANCELLED: &str = "Cancelled";
+
+/// Cancellation state of the jobs of a process, checked between chunks and tiles
+pub(crate) struct ProcessCancellation {
+    token: Option<CancellationToken>,
+}
+
+impl ProcessCancellation {
+    pub fn for_process(process_id: &str) -> Self {
+        ProcessCancellation {
+            token: GLOBAL_CANCELLATION_MANAGER
+                .lock()
+                .ok()
+                .map(|mut manager| manager.job_token(process_id)),
+        }
+    }
+
+    /// Error out once the process has been cancelled
+    pub fn check(&self) -> Result<(), String> {
+        match &self.token {
+            Some(token) if token.is_cancelled() => {
+                Err(format!("{}: process {} was cancelled", CANCELLED, token.id))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// JS error of a job error message: an Error named "Cancelled" for cancelled jobs, the
+/// message string otherwise
+pub(crate) fn job_error(message: &str) -> JsValue {
+    if message.starts_with(CANCELLED) {
+        let error = js_sys::Error::new(message);
+        error.set_name(CANCELLED);
+        error.into()
+    } else {
+        JsValue::from_str(message)
+    }
+}
+
+/// Cancel all running operations, e.g. before tearing down the module
+pub(crate) fn cancel_all_operations() -> usize {
+    GLOBAL_CANCELLATION_MANAGER
+        .lock()
+        .map(|mut manager| manager.cancel_all())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_all_cancels_handed_out_tokens() {
+        let mut manager = CancellationManager::new();
+        let first = manager.create_token("a".to_string());
+        let second = manager.create_token("b".to_string());
+        assert_eq!(manager.cancel_all(), 2);
+        assert!(first.is_cancelled() && second.is_cancelled());
+        assert!(manager.get_token("a").is_none());
+
+        // Jobs of a process share a token until the process is cancelled
+        let job = manager.job_token("p");
+        assert!(!manager.job_token("p").is_cancelled());
+        assert!(manager.cancel_jobs("p"));
+        assert!(job.is_cancelled());
+        assert!(!manager.job_token("p").is_cancelled());
+        assert!(!manager.cancel_jobs("q"));
+
+        let cancellation = ProcessCancellation::for_process("cancellation-test");
+        assert!(cancellation.check().is_ok());
+        assert!(cancel_process("cancellation-test"));
+        assert!(cancellation.check().unwrap_err().starts_with(CANCELLED));
+    }
+}
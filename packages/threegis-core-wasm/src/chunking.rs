@@ -0,0 +1,123 @@
+// Adaptive chunking for long-running generation loops. Chunk sizes follow the measured
+// time per feature so every chunk takes about TARGET_CHUNK_MS, and the loop yields to
+// the event loop between chunks to keep the UI (or worker message handling) responsive.
+
+const TARGET_CHUNK_MS: f64 = 16.0;
+const INITIAL_CHUNK_SIZE: usize = 100;
+const MIN_CHUNK_SIZE: usize = 10;
+const MAX_CHUNK_SIZE: usize = 2000;
+
+pub(crate) struct AdaptiveChunker {
+    size: usize,
+}
+
+impl AdaptiveChunker {
+    pub fn new() -> Self {
+        Self {
+            size: INITIAL_CHUNK_SIZE,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Resize the next chunk from the time the last one took. Growth is limited to
+    /// doubling per chunk so one unusually cheap chunk cannot cause a long freeze.
+    pub fn record(&mut self, processed: usize, elapsed_ms: f64) {
+        if processed == 0 {
+            return;
+        }
+        let per_item_ms = elapsed_ms.max(0.0) / processed as f64;
+        let ideal = if per_item_ms > 0.0 {
+            (TARGET_CHUNK_MS / per_item_ms) as usize
+        } else {
+            MAX_CHUNK_SIZE
+        };
+        self.size = ideal.clamp(MIN_CHUNK_SIZE, (self.size * 2).min(MAX_CHUNK_SIZE));
+    }
+}
+
+/// Milliseconds from a monotonic-enough clock, for measuring chunk durations
+pub(crate) fn now_ms() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Yields to the event loop once a chunk's worth of time has passed, for loops over
+/// items of very different cost such as tiles
+pub(crate) struct YieldTimer {
+    last_yield_ms: f64,
+}
+
+impl YieldTimer {
+    pub fn new() -> Self {
+        Self {
+            last_yield_ms: now_ms(),
+        }
+    }
+
+    pub async fn tick(&mut self) {
+        if now_ms() - self.last_yield_ms >= TARGET_CHUNK_MS {
+            yield_now().await;
+            self.last_yield_ms = now_ms();
+        }
+    }
+}
+
+/// Let pending event loop work run; a no-op outside the browser
+pub(crate) async fn yield_now() {
+    #[cfg(target_arch = "wasm32")]
+    crate::rate_limit::sleep_ms(0.0).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_size_follows_measured_time() {
+        let mut chunker = AdaptiveChunker::new();
+
+        // 100 features in 4ms: grows, but at most doubles per chunk
+        chunker.record(100, 4.0);
+        assert_eq!(chunker.size(), 200);
+        chunker.record(200, 8.0);
+        assert_eq!(chunker.size(), 400);
+
+        // 400 features in 64ms: shrinks straight to the 16ms target
+        chunker.record(400, 64.0);
+        assert_eq!(chunker.size(), 100);
+
+        // Very slow features never go below the minimum
+        chunker.record(100, 10_000.0);
+        assert_eq!(chunker.size(), MIN_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_yield_timer_and_chunk_size_limits() {
+        // Once a chunk's worth of time has passed the timer yields and starts over
+        let mut timer = YieldTimer::new();
+        let started = timer.last_yield_ms;
+        timer.last_yield_ms -= TARGET_CHUNK_MS;
+        futures::executor::block_on(timer.tick());
+        assert!(timer.last_yield_ms >= started);
+
+        // Chunk sizes never grow past the maximum, and empty chunks change nothing
+        let mut chunker = AdaptiveChunker::new();
+        for _ in 0..10 {
+            chunker.record(chunker.size(), 0.0);
+        }
+        assert_eq!(chunker.size(), MAX_CHUNK_SIZE);
+        chunker.record(0, 1000.0);
+        assert_eq!(chunker.size(), MAX_CHUNK_SIZE);
+    }
+}
@@ -0,0 +1,227 @@
+// Horizontal clipping-plane previews: the model split at a given height into the capped
+// part below and the part above, e.g. for flood-level visualization or inspecting a
+// print layer by layer. Works on copies; the cached layer geometry is left untouched.
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::cross_section::{mesh_bounds, model_meshes, terrain_mesh};
+use crate::csg_union::split_by_prism;
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::BufferGeometry;
+use crate::projection;
+use crate::vertical_datum::VerticalDatum;
+
+#[derive(Deserialize)]
+pub struct ClipPreviewInput {
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    /// Clipping height in mesh units
+    #[serde(default)]
+    pub z: Option<f64>,
+    /// Clipping height as a real-world elevation in meters, placed on the terrain's
+    /// vertical datum; used when `z` is omitted
+    #[serde(default)]
+    pub elevation: Option<f64>,
+    /// Layer labels to clip; every cached layer when omitted
+    #[serde(default)]
+    pub layers: Option<Vec<String>>,
+}
+
+pub struct ClipPreview {
+    /// Clipping height in mesh units
+    pub z: f64,
+    pub below: Vec<BufferGeometry>,
+    pub above: Vec<BufferGeometry>,
+}
+
+impl ClipPreviewInput {
+    /// Clipping height in mesh units
+    fn clip_z(&self) -> Result<f64, String> {
+        if let Some(z) = self.z {
+            return Ok(z);
+        }
+        let elevation = self
+            .elevation
+            .ok_or_else(|| "Either z or elevation is required".to_string())?;
+        let model_scale = projection::process_scale(Some(&self.process_id)).model_scale;
+        ModuleState::with(|state| {
+            let scale = state
+                .model_manifests
+                .get(&self.process_id)
+                .and_then(|manifest| manifest.scale.clone());
+            let extent = state
+                .get_elevation_grid_with_extent(Some(&self.process_id))
+                .map(|(_, extent)| *extent);
+            match (scale, extent) {
+                (Some(scale), Some(extent)) => Ok(VerticalDatum::new(
+                    scale.terrain_base_height,
+                    scale.vertical_exaggeration,
+                    extent.min_elevation,
+                    extent.max_elevation,
+                    model_scale,
+                )
+                .elevation_to_z(elevation)),
+                _ => Err(format!(
+                    "No model scale or elevation grid for process '{}'; pass z instead",
+                    self.process_id
+                )),
+            }
+        })
+    }
+}
+
+pub(crate) fn clip_preview(
+    terrain: Option<BufferGeometry>,
+    input: &ClipPreviewInput,
+) -> Result<ClipPreview, String> {
+    let z = input.clip_z()?;
+    if !z.is_finite() {
+        return Err("Clipping height must be finite".to_string());
+    }
+    let meshes = model_meshes(terrain, &input.process_id, input.layers.as_deref())?;
+    let (min, max) = mesh_bounds(&meshes);
+
+    let mut preview = ClipPreview {
+        z,
+        below: Vec::new(),
+        above: Vec::new(),
+    };
+    // Everything on one side: nothing to cut
+    if z <= min[2] {
+        preview.above = meshes.into_iter().map(|(_, mesh)| mesh).collect();
+        return Ok(preview);
+    }
+    if z >= max[2] {
+        preview.below = meshes.into_iter().map(|(_, mesh)| mesh).collect();
+        return Ok(preview);
+    }
+
+    let margin = 1.0;
+    let footprint = [
+        [min[0] - margin, min[1] - margin],
+        [max[0] + margin, min[1] - margin],
+        [max[0] + margin, max[1] + margin],
+        [min[0] - margin, max[1] + margin],
+    ];
+    for (_, mesh) in &meshes {
+        let (below, above) = split_by_prism(mesh, &footprint, min[2] - margin, z);
+        preview.below.extend(below);
+        preview.above.extend(above);
+    }
+    Ok(preview)
+}
+
+/// Split the model of a process at a horizontal clipping height. `positions`/`indices`
+/// are the terrain mesh as returned by `create_terrain_geometry` (may be empty);
+/// `input_json` is `{ processId, z?, elevation?, layers? }` with `z` in mesh units or
+/// `elevation` in meters. Returns `{ z, below, above }` with capped parts shaped like
+/// `process_polygon_geometry` output; cached geometry is not modified.
+#[wasm_bindgen]
+pub fn preview_clipping_plane(
+    positions: &[f32],
+    indices: &[u32],
+    input_json: &str,
+) -> Result<JsValue, JsValue> {
+    let input: ClipPreviewInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let preview = clip_preview(Some(terrain_mesh(positions, indices)), &input)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("z"),
+        &JsValue::from_f64(preview.z),
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("below"),
+        &crate::geometries_to_js(&preview.below),
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("above"),
+        &crate::geometries_to_js(&preview.above),
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_cache::store_layer_geometry;
+
+    fn z_range(geometries: &[BufferGeometry]) -> (f32, f32) {
+        geometries
+            .iter()
+            .flat_map(|g| g.vertices.chunks_exact(3))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                (lo.min(p[2]), hi.max(p[2]))
+            })
+    }
+
+    #[test]
+    fn test_clip_splits_at_height_and_keeps_cache() {
+        // Box of 0..2 in x/y and 0..4 in z
+        let mut vertices = Vec::new();
+        for i in 0..8 {
+            vertices.push(if i & 1 == 0 { 0.0 } else { 2.0 });
+            vertices.push(if i & 2 == 0 { 0.0 } else { 2.0 });
+            vertices.push(if i & 4 == 0 { 0.0 } else { 4.0 });
+        }
+        let block = BufferGeometry {
+            vertices,
+            normals: None,
+            colors: None,
+            indices: Some(vec![
+                0, 2, 1, 1, 2, 3, 4, 5, 6, 5, 7, 6, 0, 1, 4, 1, 5, 4, 2, 6, 3, 3, 6, 7, 0, 4, 2, 2,
+                4, 6, 1, 3, 5, 3, 7, 5,
+            ]),
+            uvs: None,
+            has_data: true,
+            properties: None,
+        };
+        store_layer_geometry(
+            "clip-preview-test",
+            "buildings",
+            "hash".to_string(),
+            false,
+            vec![block.clone()],
+        );
+
+        let input = ClipPreviewInput {
+            process_id: "clip-preview-test".to_string(),
+            z: Some(1.5),
+            elevation: None,
+            layers: None,
+        };
+        let preview = clip_preview(None, &input).unwrap();
+        let (lo, hi) = z_range(&preview.below);
+        assert!((lo - 0.0).abs() < 1e-4 && (hi - 1.5).abs() < 1e-4);
+        let (lo, hi) = z_range(&preview.above);
+        assert!((lo - 1.5).abs() < 1e-4 && (hi - 4.0).abs() < 1e-4);
+
+        let above_all = ClipPreviewInput {
+            z: Some(10.0),
+            ..input
+        };
+        let preview = clip_preview(None, &above_all).unwrap();
+        assert_eq!(preview.below.len(), 1);
+        assert!(preview.above.is_empty());
+
+        let cached = ModuleState::with(|state| {
+            state.layer_geometries["clip-preview-test"]["buildings"].geometries[0]
+                .vertices
+                .clone()
+        });
+        assert_eq!(cached, block.vertices);
+
+        let missing = ClipPreviewInput {
+            process_id: "clip-preview-unknown".to_string(),
+            z: None,
+            elevation: Some(100.0),
+            layers: None,
+        };
+        assert!(clip_preview(None, &missing).is_err());
+    }
+}
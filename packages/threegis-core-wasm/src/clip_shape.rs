@@ -0,0 +1,535 @@
+// Custom clip boundaries: instead of the full square terrain, a process can be clipped to a
+// rectangle, circle, hexagon or any polygon in geographic coordinates. The terrain solid
+// is cut along the shape and closed with a new bottom and walls, and layer solids are cut
+// by the prism over it, so round or region-shaped models stay watertight.
+use geo::{Area, BooleanOps, Polygon};
+use serde::Deserialize;
+use std::f64::consts::TAU;
+use wasm_bindgen::prelude::*;
+
+use crate::drape::{self, Plane, Triangle};
+use crate::module_state::ModuleState;
+use crate::projection::{self, terrain_size};
+use crate::solid_mesh::SolidMesh;
+use crate::terrain::TerrainGeometryParams;
+use crate::terrain_index::TerrainIndex;
+use crate::vertical_datum::meters_to_terrain_units;
+
+/// Outline points of a circle unless given
+const DEFAULT_CIRCLE_SEGMENTS: usize = 64;
+const MAX_CIRCLE_SEGMENTS: usize = 4096;
+/// Terrain vertices this close to z = 0 belong to the bottom of the solid
+const BOTTOM_EPSILON: f32 = 1e-6;
+/// Largest outline-edge grid side
+const MAX_GRID_DIMENSION: usize = 256;
+
+/// Shape the terrain and layers of a process are clipped to. Circles and hexagons are
+/// regular in mesh units, so they print round: `center` is [lng, lat] and `radius` in
+/// meters (to the corners of a hexagon), by default the largest shape inside the terrain
+/// around its center.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClipShape {
+    /// [minLng, minLat, maxLng, maxLat]
+    Rectangle { bounds: [f64; 4] },
+    Circle {
+        center: Option<[f64; 2]>,
+        radius: Option<f64>,
+        segments: Option<usize>,
+    },
+    Hexagon {
+        center: Option<[f64; 2]>,
+        radius: Option<f64>,
+    },
+    /// Outer ring of [lng, lat] points
+    Polygon { coordinates: Vec<[f64; 2]> },
+}
+
+impl ClipShape {
+    fn validate(&self) -> Result<(), String> {
+        let finite = |values: &[f64]| values.iter().all(|v| v.is_finite());
+        match self {
+            ClipShape::Rectangle { bounds }
+                if !(finite(bounds) && bounds[0] < bounds[2] && bounds[1] < bounds[3]) =>
+            {
+                Err("Clip rectangle bounds must be [minLng, minLat, maxLng, maxLat]".to_string())
+            }
+            ClipShape::Circle { center, radius, .. } | ClipShape::Hexagon { center, radius }
+                if !center.is_none_or(|c| finite(&c))
+                    || !radius.is_none_or(|r| r.is_finite() && r > 0.0) =>
+            {
+                Err("Clip center must be [lng, lat] and its radius positive meters".to_string())
+            }
+            ClipShape::Circle {
+                segments: Some(segments),
+                ..
+            } if !(8..=MAX_CIRCLE_SEGMENTS).contains(segments) => Err(format!(
+                "Clip circle segments must be 8..{}, got {}",
+                MAX_CIRCLE_SEGMENTS, segments
+            )),
+            ClipShape::Polygon { coordinates }
+                if coordinates.len() < 3 || !coordinates.iter().all(|p| finite(p)) =>
+            {
+                Err("Clip polygon needs at least 3 [lng, lat] points".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counter-clockwise outline in mesh coordinates on the terrain over `bbox`, cut to
+    /// the terrain square; where that leaves several pieces, the largest one
+    pub(crate) fn footprint(&self, bbox: &[f64]) -> Result<Vec<[f64; 2]>, String> {
+        if bbox.len() != 4 || !(bbox[0] < bbox[2] && bbox[1] < bbox[3]) {
+            return Err("Invalid bbox: must contain [minLng, minLat, maxLng, maxLat]".to_string());
+        }
+        let size = terrain_size();
+        let to_mesh = |p: [f64; 2]| projection::lng_lat_to_mesh(p[0], p[1], bbox, size);
+        let regular = |center: &Option<[f64; 2]>, radius: &Option<f64>, sides: usize| {
+            let center = center.map_or([0.0, 0.0], to_mesh);
+            let radius = radius.map_or(size / 2.0, |r| r * meters_to_terrain_units(bbox));
+            (0..sides)
+                .map(|i| {
+                    let angle = TAU * i as f64 / sides as f64;
+                    [
+                        center[0] + radius * angle.cos(),
+                        center[1] + radius * angle.sin(),
+                    ]
+                })
+                .collect::<Vec<_>>()
+        };
+        let outline: Vec<[f64; 2]> = match self {
+            ClipShape::Rectangle { bounds: b } => {
+                [[b[0], b[1]], [b[2], b[1]], [b[2], b[3]], [b[0], b[3]]]
+                    .map(to_mesh)
+                    .to_vec()
+            }
+            ClipShape::Circle {
+                center,
+                radius,
+                segments,
+            } => regular(center, radius, segments.unwrap_or(DEFAULT_CIRCLE_SEGMENTS)),
+            ClipShape::Hexagon { center, radius } => regular(center, radius, 6),
+            ClipShape::Polygon { coordinates } => {
+                coordinates.iter().copied().map(to_mesh).collect()
+            }
+        };
+
+        let half = size / 2.0;
+        let square = [[-half, -half], [half, -half], [half, half], [-half, half]];
+        let mut ring = Polygon::new(drape::to_line_string(&outline), Vec::new())
+            .intersection(&Polygon::new(drape::to_line_string(&square), Vec::new()))
+            .into_iter()
+            .max_by(|a, b| a.unsigned_area().total_cmp(&b.unsigned_area()))
+            .map(|piece| drape::open_ring(piece.exterior()))
+            .filter(|ring| ring.len() >= 3)
+            .ok_or_else(|| "Clip shape does not overlap the terrain".to_string())?;
+        if signed_area(&ring) < 0.0 {
+            ring.reverse();
+        }
+        Ok(ring)
+    }
+}
+
+fn signed_area(ring: &[[f64; 2]]) -> f64 {
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+fn bounds(points: &[[f64; 2]]) -> ([f64; 2], [f64; 2]) {
+    points.iter().fold(
+        ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+        |(lo, hi), p| {
+            (
+                [lo[0].min(p[0]), lo[1].min(p[1])],
+                [hi[0].max(p[0]), hi[1].max(p[1])],
+            )
+        },
+    )
+}
+
+/// Whether `p` lies inside `footprint` or within `slack` of its outline
+pub(crate) fn contains(footprint: &[[f64; 2]], p: [f64; 2], slack: f64) -> bool {
+    let mut inside = false;
+    for i in 0..footprint.len() {
+        let (a, b) = (footprint[i], footprint[(i + 1) % footprint.len()]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+        // Distance to the edge
+        let d = [b[0] - a[0], b[1] - a[1]];
+        let t = (((p[0] - a[0]) * d[0] + (p[1] - a[1]) * d[1]) / (d[0] * d[0] + d[1] * d[1]))
+            .clamp(0.0, 1.0);
+        if (p[0] - a[0] - d[0] * t).hypot(p[1] - a[1] - d[1] * t) <= slack {
+            return true;
+        }
+    }
+    inside
+}
+
+/// Mesh outline of the clip shape of `process_id` on the terrain over `bbox`; None when
+/// the process is not clipped
+pub(crate) fn process_clip_footprint(
+    process_id: &str,
+    bbox: &[f64],
+) -> Result<Option<Vec<[f64; 2]>>, String> {
+    ModuleState::with(|state| state.clip_shapes.get(process_id).cloned())
+        .map(|shape| shape.footprint(bbox))
+        .transpose()
+}
+
+/// Outline edges bucketed in a uniform grid, to find the terrain triangles the outline
+/// passes through without testing every edge
+struct EdgeGrid {
+    edges: Vec<[[f64; 2]; 2]>,
+    min: [f64; 2],
+    cell_size: f64,
+    dimension: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl EdgeGrid {
+    fn new(outline: &[[f64; 2]]) -> EdgeGrid {
+        let edges: Vec<[[f64; 2]; 2]> = (0..outline.len())
+            .map(|i| [outline[i], outline[(i + 1) % outline.len()]])
+            .collect();
+        let (min, max) = bounds(outline);
+        let dimension = ((edges.len() as f64).sqrt().ceil() as usize).clamp(1, MAX_GRID_DIMENSION);
+        let cell_size =
+            ((max[0] - min[0]).max(max[1] - min[1]) / dimension as f64).max(f64::EPSILON);
+        let mut grid = EdgeGrid {
+            edges: Vec::new(),
+            min,
+            cell_size,
+            dimension,
+            cells: vec![Vec::new(); dimension * dimension],
+        };
+        for (id, edge) in edges.iter().enumerate() {
+            let (lo, hi) = bounds(edge);
+            for y in grid.cell(lo[1], 1)..=grid.cell(hi[1], 1) {
+                for x in grid.cell(lo[0], 0)..=grid.cell(hi[0], 0) {
+                    grid.cells[y * dimension + x].push(id);
+                }
+            }
+        }
+        grid.edges = edges;
+        grid
+    }
+
+    fn cell(&self, value: f64, axis: usize) -> usize {
+        (((value - self.min[axis]) / self.cell_size).floor().max(0.0) as usize)
+            .min(self.dimension - 1)
+    }
+
+    /// Whether an outline edge overlaps the box between `lo` and `hi`
+    fn overlaps(&self, lo: [f64; 2], hi: [f64; 2]) -> bool {
+        (self.cell(lo[1], 1)..=self.cell(hi[1], 1)).any(|y| {
+            (self.cell(lo[0], 0)..=self.cell(hi[0], 0)).any(|x| {
+                self.cells[y * self.dimension + x].iter().any(|&id| {
+                    let (edge_lo, edge_hi) = bounds(&self.edges[id]);
+                    edge_lo[0] <= hi[0]
+                        && edge_hi[0] >= lo[0]
+                        && edge_lo[1] <= hi[1]
+                        && edge_hi[1] >= lo[1]
+                })
+            })
+        })
+    }
+}
+
+/// Cut a terrain solid (top surface above z = 0, bottom at z = 0) to the prism over the
+/// counter-clockwise `footprint`. Top triangles inside keep their shared vertices,
+/// triangles along the outline are cut to it, and a new bottom and walls following the
+/// surface close the solid.
+pub(crate) fn clip_terrain_mesh(
+    positions: &[f32],
+    indices: &[u32],
+    footprint: &[[f64; 2]],
+) -> (Vec<f32>, Vec<u32>) {
+    let vertex_count = positions.len() / 3;
+    let point = |i: u32| {
+        let i = i as usize * 3;
+        [
+            positions[i] as f64,
+            positions[i + 1] as f64,
+            positions[i + 2] as f64,
+        ]
+    };
+    let grid = EdgeGrid::new(footprint);
+    let outline = Polygon::new(drape::to_line_string(footprint), Vec::new());
+    let (min, max) = bounds(footprint);
+
+    let mut kept: Vec<u32> = Vec::new();
+    let mut boundary: Vec<Triangle> = Vec::new();
+    let mut cut = SolidMesh::default();
+    for t in indices.chunks_exact(3) {
+        // The old bottom and walls are rebuilt along the outline
+        if t.iter()
+            .any(|&i| i as usize >= vertex_count || positions[i as usize * 3 + 2] <= BOTTOM_EPSILON)
+        {
+            continue;
+        }
+        let triangle: Triangle = [point(t[0]), point(t[1]), point(t[2])];
+        let (lo, hi) = bounds(&triangle.map(|p| [p[0], p[1]]));
+        if hi[0] < min[0] || lo[0] > max[0] || hi[1] < min[1] || lo[1] > max[1] {
+            continue;
+        }
+        if grid.overlaps(lo, hi) {
+            let Some(plane) = Plane::through(&triangle) else {
+                continue;
+            };
+            let cell = Polygon::new(
+                drape::to_line_string(&triangle.map(|p| [p[0], p[1]])),
+                Vec::new(),
+            );
+            for piece in outline.intersection(&cell) {
+                let ring = drape::open_ring(piece.exterior());
+                if ring.len() >= 3 {
+                    cut.push_surface(&ring, true, |p| plane.z(p));
+                }
+            }
+            boundary.push(triangle);
+        } else {
+            let centroid = [(lo[0] + hi[0]) / 2.0, (lo[1] + hi[1]) / 2.0];
+            if contains(footprint, centroid, 0.0) {
+                kept.extend_from_slice(t);
+            }
+        }
+    }
+
+    cut.push_surface(footprint, false, |_| 0.0);
+    // Walls from the bottom up to the surface, split where the outline crosses a triangle
+    let boundary_vertices: Vec<f32> = boundary
+        .iter()
+        .flatten()
+        .flat_map(|p| p.map(|v| v as f32))
+        .collect();
+    let boundary_indices: Vec<u32> = (0..boundary.len() as u32 * 3).collect();
+    if let Some(surface) = TerrainIndex::build(&boundary_vertices, &boundary_indices) {
+        for i in 0..footprint.len() {
+            let (a, b) = (footprint[i], footprint[(i + 1) % footprint.len()]);
+            let (lo, hi) = bounds(&[a, b]);
+            let mut stops = vec![0.0];
+            stops.extend(drape::edge_crossings(
+                a,
+                b,
+                &surface.top_triangles_in(lo, hi),
+            ));
+            stops.push(1.0);
+            let at = |t: f64| [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+            for pair in stops.windows(2) {
+                let (p, q) = (at(pair[0]), at(pair[1]));
+                let zp = surface.height_at(p[0], p[1]).unwrap_or(0.0);
+                let zq = surface.height_at(q[0], q[1]).unwrap_or(0.0);
+                cut.push_quad([
+                    [p[0], p[1], 0.0],
+                    [q[0], q[1], 0.0],
+                    [q[0], q[1], zq],
+                    [p[0], p[1], zp],
+                ]);
+            }
+        }
+    }
+
+    let mut remap = vec![u32::MAX; vertex_count];
+    let mut clipped_positions = Vec::new();
+    let mut clipped_indices = Vec::with_capacity(kept.len());
+    for i in kept {
+        let i = i as usize;
+        if remap[i] == u32::MAX {
+            remap[i] = (clipped_positions.len() / 3) as u32;
+            clipped_positions.extend_from_slice(&positions[i * 3..i * 3 + 3]);
+        }
+        clipped_indices.push(remap[i]);
+    }
+    let cut = cut.into_geometry(None);
+    let offset = (clipped_positions.len() / 3) as u32;
+    clipped_positions.extend(cut.vertices);
+    clipped_indices.extend(
+        cut.indices
+            .unwrap_or_default()
+            .into_iter()
+            .map(|i| i + offset),
+    );
+    (clipped_positions, clipped_indices)
+}
+
+/// Cut the terrain solid of `params` to the clip shape of its process; false when the
+/// process is not clipped. Colors and normals are left for the caller to rebuild.
+pub(crate) fn clip_process_terrain(
+    params: &TerrainGeometryParams,
+    positions: &mut Vec<f32>,
+    indices: &mut Vec<u32>,
+) -> Result<bool, String> {
+    let bbox = [
+        params.min_lng,
+        params.min_lat,
+        params.max_lng,
+        params.max_lat,
+    ];
+    let Some(footprint) = process_clip_footprint(&params.process_id, &bbox)? else {
+        return Ok(false);
+    };
+    (*positions, *indices) = clip_terrain_mesh(positions, indices, &footprint);
+    Ok(true)
+}
+
+/// Clip the terrain, its base and the layers of `process_id` to `shape` instead of the
+/// square terrain; `null` removes the clip. Shapes are `{ type: "rectangle", bounds }`
+/// with [minLng, minLat, maxLng, maxLat] bounds, `{ type: "circle", center, radius,
+/// segments }` and `{ type: "hexagon", center, radius }` with an optional [lng, lat]
+/// center and radius in meters, or `{ type: "polygon", coordinates }` with the outer ring
+/// as [lng, lat] points. Applies to terrain and layers generated afterwards.
+#[wasm_bindgen]
+pub fn set_clip_shape(process_id: &str, shape: JsValue) -> Result<(), JsValue> {
+    let shape: Option<ClipShape> = if shape.is_null() || shape.is_undefined() {
+        None
+    } else {
+        let shape: ClipShape = serde_wasm_bindgen::from_value(shape)?;
+        shape.validate().map_err(|e| JsValue::from_str(&e))?;
+        Some(shape)
+    };
+    store_clip_shape(process_id, shape);
+    Ok(())
+}
+
+/// Set or remove the clip shape of a process. A different shape drops the cached layer
+/// geometry of the process, which was clipped to the old one.
+fn store_clip_shape(process_id: &str, shape: Option<ClipShape>) {
+    ModuleState::with_mut(|state| {
+        let previous = match shape {
+            Some(shape) => state
+                .clip_shapes
+                .insert(process_id.to_string(), shape.clone()),
+            None => state.clip_shapes.remove(process_id),
+        };
+        if previous.as_ref() != state.clip_shapes.get(process_id) {
+            state.layer_geometries.remove(process_id);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BBOX: [f64; 4] = [8.0, 47.0, 9.0, 48.0];
+
+    #[test]
+    fn test_footprints_on_the_terrain() {
+        let circle = ClipShape::Circle {
+            center: None,
+            radius: None,
+            segments: None,
+        };
+        let outline = circle.footprint(&BBOX).unwrap();
+        assert!(outline.len() >= DEFAULT_CIRCLE_SEGMENTS);
+        assert!(signed_area(&outline) > 0.0);
+        // The boolean ops with the terrain square move points by rounding only
+        assert!(outline
+            .iter()
+            .all(|p| (p[0].hypot(p[1]) - 100.0).abs() < 1e-6));
+
+        // A clockwise polygon reaching past the terrain is cut to it and turned around
+        let polygon = ClipShape::Polygon {
+            coordinates: vec![[8.5, 47.5], [8.5, 49.0], [10.0, 49.0], [10.0, 47.5]],
+        };
+        let outline = polygon.footprint(&BBOX).unwrap();
+        assert!((signed_area(&outline) - 100.0 * 100.0).abs() < 1e-6);
+        assert!(contains(&outline, [50.0, 50.0], 0.0));
+        assert!(!contains(&outline, [-50.0, 50.0], 0.0));
+
+        let hexagon = ClipShape::Hexagon {
+            center: None,
+            radius: Some(-1.0),
+        };
+        assert!(hexagon.validate().is_err());
+        let outside = ClipShape::Rectangle {
+            bounds: [10.0, 10.0, 11.0, 11.0],
+        };
+        assert!(outside.footprint(&BBOX).is_err());
+    }
+
+    #[test]
+    fn test_terrain_cut_to_a_closed_prism() {
+        // 5x5 top grid over [-2, 2]² sloping in x, on a bottom at z = 0
+        let height = |x: f64| 2.0 + 0.25 * x;
+        let mut positions = Vec::new();
+        for y in -2..=2 {
+            for x in -2..=2 {
+                positions.extend([x as f32, y as f32, height(x as f64) as f32]);
+            }
+        }
+        positions.extend([
+            -2.0, -2.0, 0.0, 2.0, -2.0, 0.0, 2.0, 2.0, 0.0, -2.0, 2.0, 0.0,
+        ]);
+        let mut indices = Vec::new();
+        for y in 0..4 {
+            for x in 0..4 {
+                let i = y * 5 + x;
+                indices.extend([i, i + 1, i + 6, i, i + 6, i + 5]);
+            }
+        }
+        indices.extend([25, 27, 26, 25, 28, 27]);
+
+        let diamond = [[1.5, 0.0], [0.0, 1.5], [-1.5, 0.0], [0.0, -1.5]];
+        let (positions, indices) = clip_terrain_mesh(&positions, &indices, &diamond);
+
+        let p = |i: u32| {
+            let i = i as usize * 3;
+            [
+                positions[i] as f64,
+                positions[i + 1] as f64,
+                positions[i + 2] as f64,
+            ]
+        };
+        assert!((0..positions.len() as u32 / 3).map(p).all(|v| contains(
+            &diamond,
+            [v[0], v[1]],
+            1e-6
+        )));
+        // Closed and facing out: 4.5 units of area at a mean height of 2
+        let volume: f64 = indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [p(t[0]), p(t[1]), p(t[2])];
+                (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0]))
+                    / 6.0
+            })
+            .sum();
+        assert!((volume - 9.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_new_clip_shape_drops_cached_layers() {
+        let cache = |shape: Option<ClipShape>| {
+            crate::layer_cache::store_layer_geometry(
+                "clip-shape-test",
+                "water",
+                String::new(),
+                true,
+                Vec::new(),
+            );
+            store_clip_shape("clip-shape-test", shape);
+            crate::layer_cache::get_cached_layers("clip-shape-test")
+        };
+        let circle = |radius: f64| ClipShape::Circle {
+            center: None,
+            radius: Some(radius),
+            segments: None,
+        };
+        assert!(cache(Some(circle(500.0))).is_empty());
+        assert_eq!(cache(Some(circle(500.0))).len(), 1);
+        assert!(cache(Some(circle(800.0))).is_empty());
+        assert!(cache(None).is_empty());
+        assert_eq!(cache(None).len(), 1);
+        ModuleState::with_mut(|state| state.clear_process_data("clip-shape-test"));
+    }
+}
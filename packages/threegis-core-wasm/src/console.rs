@@ -1,5 +1,13 @@
+// Structured logging. Every record carries a level, a category, the process it belongs
+// to and optional timing; the latest LOG_CAPACITY records are kept in a ring buffer that
+// JS reads with `get_log_records`, and each record is echoed to the browser console.
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
 use wasm_bindgen::prelude::*;
 
+use crate::chunking::now_ms;
+
 // This allows us to access console.log from JS
 #[wasm_bindgen]
 extern "C" {
@@ -8,4 +16,198 @@ extern "C" {
     pub fn log(s: &str);
 }
 
-// Note: The console_log macro is defined in lib.rs to avoid duplication
+// Records kept for `get_log_records`; older ones are dropped
+const LOG_CAPACITY: usize = 500;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    /// Subsystem the record comes from, e.g. "terrain" or "layer"
+    pub category: String,
+    #[serde(rename = "processId")]
+    pub process_id: Option<String>,
+    pub message: String,
+    /// Milliseconds since the Unix epoch
+    #[serde(rename = "timestampMs")]
+    pub timestamp_ms: f64,
+    /// Duration of the logged operation, for records finished by a `LogTimer`
+    #[serde(rename = "durationMs")]
+    pub duration_ms: Option<f64>,
+}
+
+// Shared by all threads, so records from rayon workers reach `get_log_records`
+static LOG_RECORDS: Mutex<VecDeque<LogRecord>> = Mutex::new(VecDeque::new());
+
+impl LogRecord {
+    // Only echoed in the browser
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    fn console_line(&self) -> String {
+        let process = self
+            .process_id
+            .as_deref()
+            .map(|id| format!(" [{}]", id))
+            .unwrap_or_default();
+        let duration = self
+            .duration_ms
+            .map(|ms| format!(" ({:.1} ms)", ms))
+            .unwrap_or_default();
+        format!(
+            "[{:?}] [{}]{} {}{}",
+            self.level, self.category, process, self.message, duration
+        )
+    }
+}
+
+fn push_record(record: LogRecord) {
+    #[cfg(target_arch = "wasm32")]
+    log(&record.console_line());
+    let mut records = LOG_RECORDS.lock();
+    if records.len() == LOG_CAPACITY {
+        records.pop_front();
+    }
+    records.push_back(record);
+}
+
+/// Record a log message
+pub fn record(
+    level: LogLevel,
+    category: &str,
+    process_id: Option<&str>,
+    message: impl Into<String>,
+) {
+    push_record(LogRecord {
+        level,
+        category: category.to_string(),
+        process_id: process_id.map(str::to_string),
+        message: message.into(),
+        timestamp_ms: now_ms(),
+        duration_ms: None,
+    });
+}
+
+/// Measures an operation and records its duration along with the closing message
+pub struct LogTimer {
+    category: &'static str,
+    process_id: Option<String>,
+    started_ms: f64,
+}
+
+impl LogTimer {
+    pub fn start(category: &'static str, process_id: Option<&str>) -> Self {
+        LogTimer {
+            category,
+            process_id: process_id.map(str::to_string),
+            started_ms: now_ms(),
+        }
+    }
+
+    pub fn finish(self, level: LogLevel, message: impl Into<String>) {
+        let finished_ms = now_ms();
+        push_record(LogRecord {
+            level,
+            category: self.category.to_string(),
+            process_id: self.process_id,
+            message: message.into(),
+            timestamp_ms: finished_ms,
+            duration_ms: Some(finished_ms - self.started_ms),
+        });
+    }
+}
+
+/// Buffered log records as a JSON array, oldest first. Records below `min_level`
+/// ("debug", "info", "warn", "error") and of other categories or processes are left out.
+#[wasm_bindgen]
+pub fn get_log_records(
+    min_level: Option<String>,
+    category: Option<String>,
+    process_id: Option<String>,
+) -> Result<String, JsValue> {
+    let min_level = match min_level.as_deref() {
+        None | Some("debug") => LogLevel::Debug,
+        Some("info") => LogLevel::Info,
+        Some("warn") => LogLevel::Warn,
+        Some("error") => LogLevel::Error,
+        Some(other) => {
+            return Err(JsValue::from_str(&format!(
+                "Unknown log level '{}', expected debug, info, warn or error",
+                other
+            )))
+        }
+    };
+    let records: Vec<LogRecord> = LOG_RECORDS
+        .lock()
+        .iter()
+        .filter(|r| r.level >= min_level)
+        .filter(|r| category.as_ref().is_none_or(|c| &r.category == c))
+        .filter(|r| process_id.is_none() || r.process_id == process_id)
+        .cloned()
+        .collect();
+    serde_json::to_string(&records)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize log records: {}", e)))
+}
+
+/// Drop all buffered log records
+#[wasm_bindgen]
+pub fn clear_log_records() {
+    LOG_RECORDS.lock().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_keeps_latest_records() {
+        // Other tests log concurrently into the shared buffer, so only this test's
+        // records are checked
+        clear_log_records();
+        for i in 0..LOG_CAPACITY + 5 {
+            record(
+                LogLevel::Debug,
+                "console-test",
+                None,
+                format!("record {}", i),
+            );
+        }
+        // Records from worker threads land in the same buffer
+        std::thread::spawn(|| {
+            LogTimer::start("console-test", Some("p2")).finish(LogLevel::Warn, "slow layer")
+        })
+        .join()
+        .unwrap();
+
+        {
+            let records = LOG_RECORDS.lock();
+            assert_eq!(records.len(), LOG_CAPACITY);
+            let ours: Vec<&LogRecord> = records
+                .iter()
+                .filter(|r| r.category == "console-test")
+                .collect();
+            let first: usize = ours[0].message["record ".len()..].parse().unwrap();
+            assert!(first >= 6, "{}", ours[0].message);
+            let last = ours.last().unwrap();
+            assert!(last.duration_ms.is_some_and(|ms| ms >= 0.0));
+            assert_eq!(
+                last.console_line().split(" (").next(),
+                Some("[Warn] [console-test] [p2] slow layer")
+            );
+        }
+
+        let warnings: Vec<serde_json::Value> = serde_json::from_str(
+            &get_log_records(Some("warn".into()), Some("console-test".into()), None).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0]["level"], "warn");
+        assert_eq!(warnings[0]["processId"], "p2");
+    }
+}
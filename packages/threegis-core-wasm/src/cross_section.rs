@@ -0,0 +1,462 @@
+// Cross sections of the finished model along a vertical cut. The cut follows a line in
+// mesh XY coordinates: two points give a vertical plane, more points a polyline whose
+// first and last segments extend past the model. The model is split into the part left
+// and the part right of the cut, each capped, and the section outlines are traced per
+// layer for split prints and profile illustrations.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::csg_union::split_by_prism;
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::BufferGeometry;
+
+// Label of the terrain mesh in outlines and split parts
+const TERRAIN_LABEL: &str = "terrain";
+
+// Outline endpoints closer than this (mesh units) are joined
+const JOIN_EPSILON: f64 = 1e-4;
+
+#[derive(Deserialize)]
+pub struct CrossSectionInput {
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    /// Cut line in mesh XY coordinates, at least two points
+    pub line: Vec<[f64; 2]>,
+    /// Layer labels to cut; every cached layer when omitted
+    #[serde(default)]
+    pub layers: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SectionOutline {
+    pub layer: String,
+    /// Outline points in mesh coordinates
+    pub points: Vec<[f32; 3]>,
+    pub closed: bool,
+}
+
+pub struct CrossSection {
+    pub outlines: Vec<SectionOutline>,
+    /// Parts left of the cut, looking along the line
+    pub left: Vec<BufferGeometry>,
+    pub right: Vec<BufferGeometry>,
+}
+
+/// One segment of the cut line; the first and last extend to infinity
+struct CutSegment {
+    origin: [f64; 2],
+    direction: [f64; 2],
+    range: (f64, f64),
+}
+
+fn cut_segments(line: &[[f64; 2]]) -> Result<Vec<CutSegment>, String> {
+    let points: Vec<[f64; 2]> = line
+        .iter()
+        .enumerate()
+        .filter(|(i, p)| *i == 0 || distance(line[i - 1], **p) > JOIN_EPSILON)
+        .map(|(_, p)| *p)
+        .collect();
+    if points.len() < 2 || points.iter().flatten().any(|v| !v.is_finite()) {
+        return Err("Cut line needs at least two distinct finite points".to_string());
+    }
+
+    let last = points.len() - 2;
+    Ok(points
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let length = distance(pair[0], pair[1]);
+            CutSegment {
+                origin: pair[0],
+                direction: [
+                    (pair[1][0] - pair[0][0]) / length,
+                    (pair[1][1] - pair[0][1]) / length,
+                ],
+                range: (
+                    if i == 0 { f64::NEG_INFINITY } else { 0.0 },
+                    if i == last { f64::INFINITY } else { length },
+                ),
+            }
+        })
+        .collect())
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
+
+/// Region left of the cut line, reaching `reach` beyond it in every direction
+fn left_region(segments: &[CutSegment], reach: f64) -> Vec<[f64; 2]> {
+    let first = &segments[0];
+    let last = &segments[segments.len() - 1];
+    let left_of = |d: [f64; 2]| [-d[1] * reach, d[0] * reach];
+    let start = [
+        first.origin[0] - first.direction[0] * reach,
+        first.origin[1] - first.direction[1] * reach,
+    ];
+    let end_origin = [
+        last.origin[0] + last.direction[0] * reach,
+        last.origin[1] + last.direction[1] * reach,
+    ];
+
+    let mut region = vec![start];
+    region.extend(segments.iter().skip(1).map(|s| s.origin));
+    region.push(end_origin);
+    let (end_left, start_left) = (left_of(last.direction), left_of(first.direction));
+    region.push([end_origin[0] + end_left[0], end_origin[1] + end_left[1]]);
+    region.push([start[0] + start_left[0], start[1] + start_left[1]]);
+    region
+}
+
+/// Pieces of the triangles of `geometry` that lie on the cut surface
+fn section_segments(geometry: &BufferGeometry, segments: &[CutSegment]) -> Vec<[[f64; 3]; 2]> {
+    let vertices = &geometry.vertices;
+    let vertex_count = vertices.len() / 3;
+    let position = |i: u32| {
+        let b = i as usize * 3;
+        [
+            vertices[b] as f64,
+            vertices[b + 1] as f64,
+            vertices[b + 2] as f64,
+        ]
+    };
+    let triangles: Vec<[u32; 3]> = match &geometry.indices {
+        Some(indices) => indices
+            .chunks_exact(3)
+            .filter(|t| t.iter().all(|&i| (i as usize) < vertex_count))
+            .map(|t| [t[0], t[1], t[2]])
+            .collect(),
+        None => (0..vertex_count as u32 / 3)
+            .map(|t| [t * 3, t * 3 + 1, t * 3 + 2])
+            .collect(),
+    };
+
+    let mut pieces = Vec::new();
+    for triangle in triangles {
+        let corners = triangle.map(position);
+        for segment in segments {
+            let normal = [-segment.direction[1], segment.direction[0]];
+            let side = |p: &[f64; 3]| {
+                normal[0] * (p[0] - segment.origin[0]) + normal[1] * (p[1] - segment.origin[1])
+            };
+            let sides = corners.each_ref().map(side);
+            let mut crossings = Vec::with_capacity(2);
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                if (sides[a] > 0.0) != (sides[b] > 0.0) {
+                    let t = sides[a] / (sides[a] - sides[b]);
+                    crossings.push([
+                        corners[a][0] + (corners[b][0] - corners[a][0]) * t,
+                        corners[a][1] + (corners[b][1] - corners[a][1]) * t,
+                        corners[a][2] + (corners[b][2] - corners[a][2]) * t,
+                    ]);
+                }
+            }
+            let [p, q] = match crossings[..] {
+                [p, q] => [p, q],
+                _ => continue,
+            };
+
+            // Keep the part within the segment's extent along the line
+            let along = |p: &[f64; 3]| {
+                segment.direction[0] * (p[0] - segment.origin[0])
+                    + segment.direction[1] * (p[1] - segment.origin[1])
+            };
+            let (tp, tq) = (along(&p), along(&q));
+            let (lo, hi) = segment.range;
+            if tp.max(tq) < lo || tp.min(tq) > hi {
+                continue;
+            }
+            if (tq - tp).abs() < f64::EPSILON {
+                // Vertical piece, nothing to clamp
+                pieces.push([p, q]);
+                continue;
+            }
+            let at = |t: f64| {
+                let f = (t - tp) / (tq - tp);
+                [
+                    p[0] + (q[0] - p[0]) * f,
+                    p[1] + (q[1] - p[1]) * f,
+                    p[2] + (q[2] - p[2]) * f,
+                ]
+            };
+            pieces.push([at(tp.clamp(lo, hi)), at(tq.clamp(lo, hi))]);
+        }
+    }
+    pieces
+}
+
+/// Chain section pieces into polylines, closed where they return to their start
+fn chain_outlines(layer: &str, pieces: Vec<[[f64; 3]; 2]>) -> Vec<SectionOutline> {
+    let key = |p: &[f64; 3]| {
+        (
+            (p[0] / JOIN_EPSILON).round() as i64,
+            (p[1] / JOIN_EPSILON).round() as i64,
+            (p[2] / JOIN_EPSILON).round() as i64,
+        )
+    };
+    let mut by_point: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        for point in piece {
+            by_point.entry(key(point)).or_default().push(i);
+        }
+    }
+
+    let mut used = vec![false; pieces.len()];
+    let mut outlines = Vec::new();
+    for start in 0..pieces.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut points = vec![pieces[start][0], pieces[start][1]];
+        // Grow forward from the end, then backward from the start
+        for forward in [true, false] {
+            loop {
+                let tip = if forward {
+                    points[points.len() - 1]
+                } else {
+                    points[0]
+                };
+                let next = by_point[&key(&tip)].iter().copied().find(|&i| !used[i]);
+                let Some(next) = next else { break };
+                used[next] = true;
+                let [a, b] = pieces[next];
+                let other = if key(&a) == key(&tip) { b } else { a };
+                if forward {
+                    points.push(other);
+                } else {
+                    points.insert(0, other);
+                }
+            }
+        }
+        let closed = points.len() > 3 && key(&points[0]) == key(&points[points.len() - 1]);
+        if closed {
+            points.pop();
+        }
+        outlines.push(SectionOutline {
+            layer: layer.to_string(),
+            points: points
+                .iter()
+                .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+                .collect(),
+            closed,
+        });
+    }
+    outlines
+}
+
+/// Cut the terrain mesh and the cached layers of a process along the input's line
+/// Terrain mesh from `create_terrain_geometry` positions/indices, labelled "terrain"
+pub(crate) fn terrain_mesh(positions: &[f32], indices: &[u32]) -> BufferGeometry {
+    BufferGeometry {
+        vertices: positions.to_vec(),
+        normals: None,
+        colors: None,
+        indices: Some(indices.to_vec()),
+        uvs: None,
+        has_data: positions.len() >= 9 && indices.len() >= 3,
+        properties: Some(HashMap::from([(
+            "__label".to_string(),
+            serde_json::Value::from(TERRAIN_LABEL),
+        )])),
+    }
+}
+
+/// The terrain and the cached layer geometries of a process (all layers when `layers` is
+/// None) with their labels, sorted by label; errors when there is nothing to cut
+pub(crate) fn model_meshes(
+    terrain: Option<BufferGeometry>,
+    process_id: &str,
+    layers: Option<&[String]>,
+) -> Result<Vec<(String, BufferGeometry)>, String> {
+    let mut meshes: Vec<(String, BufferGeometry)> = terrain
+        .filter(|t| t.has_data)
+        .map(|t| (TERRAIN_LABEL.to_string(), t))
+        .into_iter()
+        .collect();
+    ModuleState::with(|state| {
+        if let Some(cached) = state.layer_geometries.get(process_id) {
+            for (label, layer) in cached {
+                if layers.is_none_or(|wanted| wanted.contains(label)) {
+                    meshes.extend(
+                        layer
+                            .geometries
+                            .iter()
+                            .filter(|g| g.has_data)
+                            .map(|g| (label.clone(), g.clone())),
+                    );
+                }
+            }
+        }
+    });
+    if meshes.is_empty() {
+        return Err(format!("Nothing to cut for process '{}'", process_id));
+    }
+    meshes.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(meshes)
+}
+
+/// Axis-aligned bounds (min, max) of all mesh vertices
+pub(crate) fn mesh_bounds(meshes: &[(String, BufferGeometry)]) -> ([f64; 3], [f64; 3]) {
+    let (mut min, mut max) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+    for (_, mesh) in meshes {
+        for p in mesh.vertices.chunks_exact(3) {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis] as f64);
+                max[axis] = max[axis].max(p[axis] as f64);
+            }
+        }
+    }
+    (min, max)
+}
+
+pub(crate) fn cross_section(
+    terrain: Option<BufferGeometry>,
+    input: &CrossSectionInput,
+) -> Result<CrossSection, String> {
+    let segments = cut_segments(&input.line)?;
+    let meshes = model_meshes(terrain, &input.process_id, input.layers.as_deref())?;
+
+    // The cutting prism has to reach past every mesh
+    let (min, max) = mesh_bounds(&meshes);
+    let line_extent = segments
+        .iter()
+        .map(|s| s.origin[0].abs().max(s.origin[1].abs()))
+        .fold(0.0, f64::max);
+    let reach = 2.0 * (max[0] - min[0] + max[1] - min[1] + line_extent) + 1.0;
+    let region = left_region(&segments, reach);
+
+    let mut section = CrossSection {
+        outlines: Vec::new(),
+        left: Vec::new(),
+        right: Vec::new(),
+    };
+    for (label, mesh) in &meshes {
+        section
+            .outlines
+            .extend(chain_outlines(label, section_segments(mesh, &segments)));
+        let (left, right) = split_by_prism(mesh, &region, min[2] - 1.0, max[2] + 1.0);
+        section.left.extend(left);
+        section.right.extend(right);
+    }
+    Ok(section)
+}
+
+/// Cut the model of a process along a vertical plane or polyline. `positions`/`indices`
+/// are the terrain mesh as returned by `create_terrain_geometry` (may be empty);
+/// `input_json` is `{ processId, line: [[x, y], ...], layers? }` in mesh coordinates.
+/// Returns `{ outlines: [{ layer, points, closed }], left, right }` where `left`/`right`
+/// are capped parts shaped like `process_polygon_geometry` output; terrain parts carry
+/// the `__label` "terrain".
+#[wasm_bindgen]
+pub fn slice_model(
+    positions: &[f32],
+    indices: &[u32],
+    input_json: &str,
+) -> Result<JsValue, JsValue> {
+    let input: CrossSectionInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let terrain = terrain_mesh(positions, indices);
+    let section = cross_section(Some(terrain), &input).map_err(|e| JsValue::from_str(&e))?;
+
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("outlines"),
+        &serde_wasm_bindgen::to_value(&section.outlines)?,
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("left"),
+        &crate::geometries_to_js(&section.left),
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("right"),
+        &crate::geometries_to_js(&section.right),
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_cache::store_layer_geometry;
+
+    /// Closed axis-aligned box as indexed triangles, outward facing
+    fn cuboid(min: [f32; 3], max: [f32; 3]) -> BufferGeometry {
+        let mut vertices = Vec::new();
+        for i in 0..8 {
+            vertices.push(if i & 1 == 0 { min[0] } else { max[0] });
+            vertices.push(if i & 2 == 0 { min[1] } else { max[1] });
+            vertices.push(if i & 4 == 0 { min[2] } else { max[2] });
+        }
+        BufferGeometry {
+            vertices,
+            normals: None,
+            colors: None,
+            indices: Some(vec![
+                0, 2, 1, 1, 2, 3, 4, 5, 6, 5, 7, 6, 0, 1, 4, 1, 5, 4, 2, 6, 3, 3, 6, 7, 0, 4, 2, 2,
+                4, 6, 1, 3, 5, 3, 7, 5,
+            ]),
+            uvs: None,
+            has_data: true,
+            properties: None,
+        }
+    }
+
+    fn x_range(geometry: &BufferGeometry) -> (f32, f32) {
+        geometry
+            .vertices
+            .chunks_exact(3)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                (lo.min(p[0]), hi.max(p[0]))
+            })
+    }
+
+    #[test]
+    fn test_plane_cut_splits_and_outlines_layers() {
+        store_layer_geometry(
+            "cross-section-test",
+            "buildings",
+            "hash".to_string(),
+            false,
+            vec![cuboid([0.0, 0.0, 0.0], [4.0, 2.0, 3.0])],
+        );
+        // Vertical plane x = 1, looking along +y: left is x < 1
+        let input = CrossSectionInput {
+            process_id: "cross-section-test".to_string(),
+            line: vec![[1.0, -10.0], [1.0, 10.0]],
+            layers: None,
+        };
+        let section = cross_section(None, &input).unwrap();
+
+        assert_eq!(section.left.len(), 1);
+        assert_eq!(section.right.len(), 1);
+        let (lo, hi) = x_range(&section.left[0]);
+        assert!((lo - 0.0).abs() < 1e-4 && (hi - 1.0).abs() < 1e-4);
+        let (lo, hi) = x_range(&section.right[0]);
+        assert!((lo - 1.0).abs() < 1e-4 && (hi - 4.0).abs() < 1e-4);
+
+        assert_eq!(section.outlines.len(), 1);
+        let outline = &section.outlines[0];
+        assert_eq!(outline.layer, "buildings");
+        assert!(outline.closed);
+        assert!(outline.points.iter().all(|p| (p[0] - 1.0).abs() < 1e-4));
+        let max_z = outline.points.iter().map(|p| p[2]).fold(0.0, f32::max);
+        assert!((max_z - 3.0).abs() < 1e-4);
+
+        // A polyline segment that ends before the box leaves it whole
+        let bent = CrossSectionInput {
+            process_id: "cross-section-test".to_string(),
+            line: vec![[1.0, -10.0], [1.0, -5.0], [-10.0, -5.0]],
+            layers: Some(vec!["buildings".to_string()]),
+        };
+        let section = cross_section(None, &bent).unwrap();
+        assert!(section.outlines.is_empty());
+        assert!(section.left.is_empty() || section.right.is_empty());
+
+        assert!(cut_segments(&[[0.0, 0.0], [0.0, 0.0]]).is_err());
+    }
+}
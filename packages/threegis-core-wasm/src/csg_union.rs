@@ -1,16 +1,18 @@
+use crate::clip_shape;
 use crate::polygon_geometry::BufferGeometry;
+use crate::parallel;
 use csgrs::float_types::Real;
 use csgrs::mesh::polygon::Polygon as CsgPolygon;
 use csgrs::mesh::vertex::Vertex as CsgVertex;
 use csgrs::mesh::Mesh as CSG;
+use csgrs::sketch::Sketch;
 use csgrs::traits::CSG as _;
 #[cfg(target_arch = "wasm32")]
 use geo::{BooleanOps, LineString, MultiPolygon, Polygon};
 #[cfg(target_arch = "wasm32")]
 use js_sys::{Array, Float32Array, Reflect};
 use nalgebra::{Point3, Vector3};
-use rayon::prelude::*;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::JsValue;
 
@@ -19,27 +21,71 @@ use crate::extrude;
 
 const NORMAL_EPS: Real = 1e-6;
 
+/// Vertex colors of a source triangle, carried through booleans as polygon metadata so
+/// the pieces cut from it are colored by interpolating at their corners
+#[derive(Debug, Clone, PartialEq)]
+struct TriangleColors {
+    corners: [Point3<Real>; 3],
+    colors: [[f32; 3]; 3],
+}
+
+impl TriangleColors {
+    // One color everywhere, for faces without a source triangle
+    fn uniform(color: [f32; 3]) -> Self {
+        TriangleColors {
+            corners: [Point3::origin(); 3],
+            colors: [color; 3],
+        }
+    }
+
+    // Barycentric interpolation of the corner colors at `p` in the triangle plane
+    fn at(&self, p: &Point3<Real>) -> [f32; 3] {
+        let [a, b, c] = self.corners;
+        let (v0, v1, v2) = (b - a, c - a, p - a);
+        let (d00, d01, d11) = (v0.dot(&v0), v0.dot(&v1), v1.dot(&v1));
+        let denom = d00 * d11 - d01 * d01;
+        if denom.abs() <= NORMAL_EPS * NORMAL_EPS {
+            return self.colors[0];
+        }
+        let (d20, d21) = (v2.dot(&v0), v2.dot(&v1));
+        let v = (d11 * d20 - d01 * d21) / denom;
+        let w = (d00 * d21 - d01 * d20) / denom;
+        let weights = [1.0 - v - w, v, w];
+        std::array::from_fn(|channel| {
+            let value: Real = (0..3)
+                .map(|corner| weights[corner] * self.colors[corner][channel] as Real)
+                .sum();
+            value.clamp(0.0, 1.0) as f32
+        })
+    }
+}
+
+/// Solid of a feature for booleans, with the colors of its triangles
+type Solid = CSG<TriangleColors>;
+
 #[cfg(target_arch = "wasm32")]
 struct FootprintGroup {
     footprint: MultiPolygon<f64>,
     min_z: f64,
     max_z: f64,
+    color: Option<[f32; 3]>,
 }
 
+// Layers come back ordered by key, so merged output does not depend on hash order
 pub fn merge_geometries_by_layer(
     geometries: Vec<BufferGeometry>,
-) -> HashMap<String, BufferGeometry> {
+) -> BTreeMap<String, BufferGeometry> {
     if geometries.is_empty() {
-        return HashMap::new();
+        return BTreeMap::new();
     }
 
-    let mut grouped: HashMap<String, Vec<BufferGeometry>> = HashMap::new();
+    let mut grouped: BTreeMap<String, Vec<BufferGeometry>> = BTreeMap::new();
     for geometry in geometries.into_iter() {
         let key = geometry_layer_key(&geometry);
         grouped.entry(key).or_default().push(geometry);
     }
 
-    let mut results = HashMap::new();
+    let mut results = BTreeMap::new();
     for (layer_key, group) in grouped.into_iter() {
         // Optimization: For buildings, use a lighter Z-alignment strategy
         // This avoids expensive boolean ops but still levels the buildings visually
@@ -153,27 +199,8 @@ fn translate_geometry_z(geometry: &mut BufferGeometry, z_offset: f64) {
     }
 }
 
-fn pairwise_union(mut solids: Vec<CSG<()>>) -> Option<CSG<()>> {
-    if solids.is_empty() {
-        return None;
-    }
-
-    let mut queue: VecDeque<CSG<()>> = solids.drain(..).collect();
-
-    while queue.len() > 1 {
-        let mut next_queue: VecDeque<CSG<()>> = VecDeque::with_capacity((queue.len() + 1) / 2);
-        while let Some(a) = queue.pop_front() {
-            if let Some(b) = queue.pop_front() {
-                let merged = a.union(&b);
-                next_queue.push_back(merged);
-            } else {
-                next_queue.push_back(a);
-            }
-        }
-        queue = next_queue;
-    }
-
-    queue.pop_front()
+fn pairwise_union(solids: Vec<Solid>) -> Option<Solid> {
+    parallel::reduce_pairwise(solids, |a, b| a.union(&b))
 }
 
 fn geometry_layer_key(geometry: &BufferGeometry) -> String {
@@ -371,13 +398,18 @@ fn quantize_value(value: f64, precision: f64) -> i64 {
     (value / precision).round() as i64
 }
 
-fn buffer_geometry_to_csg(geometry: &BufferGeometry) -> Option<CSG<()>> {
+fn buffer_geometry_to_csg(geometry: &BufferGeometry) -> Option<Solid> {
     if !geometry.has_data || geometry.vertices.len() < 9 {
         return None;
     }
 
     let vertices = &geometry.vertices;
     let normals = geometry.normals.as_ref();
+    // Vertices without a color are black, as in `fallback_layer_union`
+    let color_at = |base: usize| -> Option<[f32; 3]> {
+        let colors = geometry.colors.as_ref()?;
+        Some(colors.get(base..base + 3).map_or([0.0; 3], |c| [c[0], c[1], c[2]]))
+    };
     let indices: Vec<u32> = if let Some(ref idx) = geometry.indices {
         if idx.len() < 3 || idx.len() % 3 != 0 {
             return None;
@@ -421,7 +453,7 @@ fn buffer_geometry_to_csg(geometry: &BufferGeometry) -> Option<CSG<()>> {
                 Vector3::zeros()
             };
 
-            tri_vertices.push((Point3::new(x, y, z), normal_vec));
+            tri_vertices.push((Point3::new(x, y, z), normal_vec, color_at(base)));
         }
 
         let mut p0 = tri_vertices[0].0;
@@ -453,9 +485,17 @@ fn buffer_geometry_to_csg(geometry: &BufferGeometry) -> Option<CSG<()>> {
             }
         }
 
+        let colors = tri_vertices
+            .iter()
+            .map(|v| v.2)
+            .collect::<Option<Vec<_>>>()
+            .map(|colors| TriangleColors {
+                corners: [p0, p1, p2],
+                colors: [colors[0], colors[1], colors[2]],
+            });
         let polygon_vertices: Vec<CsgVertex> = tri_vertices
             .into_iter()
-            .map(|(pos, normal)| {
+            .map(|(pos, normal, _)| {
                 let final_normal = if normal.norm() > NORMAL_EPS {
                     normal.normalize()
                 } else {
@@ -465,14 +505,16 @@ fn buffer_geometry_to_csg(geometry: &BufferGeometry) -> Option<CSG<()>> {
             })
             .collect();
 
-        polygons.push(CsgPolygon::new(polygon_vertices, None));
+        polygons.push(CsgPolygon::new(polygon_vertices, colors));
     }
 
     if polygons.is_empty() {
         return None;
     }
 
-    Some(CSG::from_polygons(&polygons, None))
+    // Faces cut into the solid by a boolean take the color of its first vertex
+    let fallback = color_at(0).map(TriangleColors::uniform);
+    Some(CSG::from_polygons(&polygons, fallback))
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -582,7 +624,7 @@ fn geometry_centroid(vertices: &[f32]) -> Point3<Real> {
     Point3::new(sum.x, sum.y, sum.z)
 }
 
-fn csg_to_buffer_geometry(csg: &CSG<()>) -> Option<BufferGeometry> {
+fn csg_to_buffer_geometry(csg: &Solid) -> Option<BufferGeometry> {
     if csg.polygons.is_empty() {
         return None;
     }
@@ -590,13 +632,19 @@ fn csg_to_buffer_geometry(csg: &CSG<()>) -> Option<BufferGeometry> {
     let mut vertices = Vec::new();
     let mut normals = Vec::new();
     let mut indices = Vec::new();
+    let mut colors = Vec::new();
+    let colored = csg.metadata.is_some() || csg.polygons.iter().any(|p| p.metadata.is_some());
 
     for polygon in &csg.polygons {
+        let source = polygon.metadata.as_ref().or(csg.metadata.as_ref());
         for tri in polygon.triangulate() {
             for vertex in tri.iter() {
                 vertices.push(vertex.pos.x as f32);
                 vertices.push(vertex.pos.y as f32);
                 vertices.push(vertex.pos.z as f32);
+                if colored {
+                    colors.extend(source.map_or([0.0; 3], |source| source.at(&vertex.pos)));
+                }
 
                 let mut normal = vertex.normal;
                 if normal.norm() <= NORMAL_EPS {
@@ -625,7 +673,7 @@ fn csg_to_buffer_geometry(csg: &CSG<()>) -> Option<BufferGeometry> {
         } else {
             Some(normals)
         },
-        colors: None,
+        colors: colored.then_some(colors),
         indices: if indices.is_empty() {
             None
         } else {
@@ -657,7 +705,7 @@ fn fallback_layer_union(geometries: Vec<BufferGeometry>) -> BufferGeometry {
             (0..(local_vertices.len() / 3) as u32).collect()
         };
 
-        let local_colors = geometry.colors.as_ref().map(|c| c.as_slice());
+        let local_colors = geometry.colors.as_deref();
         let mut remap_cache: HashMap<u32, u32> = HashMap::new();
 
         for face in local_indices.chunks(3) {
@@ -812,7 +860,8 @@ pub fn optimize_geometry(geometry: BufferGeometry, tolerance: f32) -> BufferGeom
     let mut merged_vertices = Vec::new();
     let mut merged_normals = Vec::new();
     let mut merged_colors = Vec::new();
-    let mut vertex_map: HashMap<usize, usize> = HashMap::new();
+    // Merged index of every vertex so far; matches are searched in vertex order
+    let mut vertex_map: Vec<usize> = Vec::with_capacity(vertex_count);
 
     for i in 0..vertex_count {
         let v1_idx = i * 3;
@@ -822,12 +871,8 @@ pub fn optimize_geometry(geometry: BufferGeometry, tolerance: f32) -> BufferGeom
             geometry.vertices[v1_idx + 2],
         ];
 
-        let mut found_match = false;
-        for (existing_idx, &merged_idx) in &vertex_map {
-            if *existing_idx >= i {
-                continue;
-            }
-
+        let mut found_match = None;
+        for (existing_idx, &merged_idx) in vertex_map.iter().enumerate() {
             let existing_v_idx = existing_idx * 3;
             let existing_v = [
                 geometry.vertices[existing_v_idx],
@@ -840,15 +885,16 @@ pub fn optimize_geometry(geometry: BufferGeometry, tolerance: f32) -> BufferGeom
                 + (v1[2] - existing_v[2]).powi(2);
 
             if distance_sq <= tolerance * tolerance {
-                vertex_map.insert(i, merged_idx);
-                found_match = true;
+                found_match = Some(merged_idx);
                 break;
             }
         }
 
-        if !found_match {
+        if let Some(merged_idx) = found_match {
+            vertex_map.push(merged_idx);
+        } else {
             let new_merged_idx = merged_vertices.len() / 3;
-            vertex_map.insert(i, new_merged_idx);
+            vertex_map.push(new_merged_idx);
 
             merged_vertices.extend_from_slice(&v1);
 
@@ -869,7 +915,7 @@ pub fn optimize_geometry(geometry: BufferGeometry, tolerance: f32) -> BufferGeom
     let new_indices: Vec<u32> = if let Some(ref indices) = geometry.indices {
         indices
             .iter()
-            .map(|&idx| vertex_map.get(&(idx as usize)).copied().unwrap_or(0) as u32)
+            .map(|&idx| vertex_map.get(idx as usize).copied().unwrap_or(0) as u32)
             .collect()
     } else {
         (0..merged_vertices.len() as u32 / 3).collect()
@@ -901,10 +947,12 @@ pub fn optimize_geometry(geometry: BufferGeometry, tolerance: f32) -> BufferGeom
 
 // RESTORED: csgrs_union was missing
 fn csgrs_union(geometries: &[BufferGeometry]) -> Option<BufferGeometry> {
-    let solids: Vec<CSG<()>> = geometries
-        .iter()
-        .filter_map(buffer_geometry_to_csg)
-        .collect();
+    let solids: Vec<Solid> = parallel::map_slice(geometries, || {}, |_, geometry| {
+        buffer_geometry_to_csg(geometry)
+    })
+    .into_iter()
+    .flatten()
+    .collect();
 
     if solids.is_empty() {
         return None;
@@ -914,10 +962,214 @@ fn csgrs_union(geometries: &[BufferGeometry]) -> Option<BufferGeometry> {
     csg_to_buffer_geometry(&reduced)
 }
 
+/// Subtract the union of `cutters` from `target`
+pub fn subtract_geometries(
+    target: &BufferGeometry,
+    cutters: &[BufferGeometry],
+) -> Option<BufferGeometry> {
+    boolean_geometries(target, cutters, BooleanOp::Subtract)
+}
+
+/// Boolean operation between a target solid and the union of tool solids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BooleanOp {
+    Union,
+    Subtract,
+    Intersect,
+}
+
+impl BooleanOp {
+    pub fn parse(op: &str) -> Result<Self, String> {
+        match op.to_ascii_lowercase().as_str() {
+            "union" => Ok(BooleanOp::Union),
+            "subtract" | "difference" => Ok(BooleanOp::Subtract),
+            "intersect" | "intersection" => Ok(BooleanOp::Intersect),
+            other => Err(format!(
+                "Unknown boolean operation '{}', expected union, subtract or intersect",
+                other
+            )),
+        }
+    }
+}
+
+/// Apply `op` to `target` and the union of `tools`; None when the result is empty or the
+/// target is not a usable solid. The result keeps the target's properties.
+pub fn boolean_geometries(
+    target: &BufferGeometry,
+    tools: &[BufferGeometry],
+    op: BooleanOp,
+) -> Option<BufferGeometry> {
+    let target_solid = buffer_geometry_to_csg(target)?;
+    let tool_solids: Vec<Solid> = tools.iter().filter_map(buffer_geometry_to_csg).collect();
+    let result = match (pairwise_union(tool_solids), op) {
+        (None, BooleanOp::Intersect) => return None,
+        (None, _) => return Some(target.clone()),
+        (Some(tool), BooleanOp::Union) => target_solid.union(&tool),
+        (Some(tool), BooleanOp::Subtract) => target_solid.difference(&tool),
+        (Some(tool), BooleanOp::Intersect) => target_solid.intersection(&tool),
+    };
+    let mut geometry = csg_to_buffer_geometry(&result)?;
+    geometry.properties = target.properties.clone();
+    Some(geometry)
+}
+
+// Vertical prism over `footprint` spanning `min_z..max_z`
+fn footprint_prism(footprint: &[[f64; 2]], min_z: f64, max_z: f64) -> Solid {
+    Sketch::polygon(footprint, None)
+        .extrude(max_z - min_z)
+        .translate(0.0, 0.0, min_z)
+}
+
+/// Clip feature solids to the terrain solid: the square tile of half-size `half_size`
+/// around the origin, from the base plate bottom (z = 0) up to the terrain surface.
+/// Parts below the base or past the tile edge are cut off with capped walls, and parts
+/// buried in the terrain are cut away along the surface, so the result stays watertight
+/// and sits on the terrain without overlapping it. `terrain_under(min, max)` gives the
+/// terrain solid over the box between `min` and `max`. Features already inside are kept
+/// untouched, features cut away entirely are dropped, and features that cannot be
+/// converted to a solid are kept as they are.
+pub fn clip_to_terrain_solid(
+    geometries: Vec<BufferGeometry>,
+    half_size: f64,
+    terrain_under: impl Fn([f64; 2], [f64; 2]) -> Option<BufferGeometry>,
+) -> Vec<BufferGeometry> {
+    let footprint = [
+        [-half_size, -half_size],
+        [half_size, -half_size],
+        [half_size, half_size],
+        [-half_size, half_size],
+    ];
+    // Slack so the terrain under a feature reaches a little past its walls
+    let margin = 1e-2;
+
+    clip_to_footprint_solid(geometries, &footprint)
+        .into_iter()
+        .filter_map(|geometry| {
+            if !geometry.has_data {
+                return Some(geometry);
+            }
+            let (min, max) = axis_bounds(&geometry.vertices);
+            let clamp = |v: f64| v.clamp(-half_size, half_size);
+            let Some(terrain) = terrain_under(
+                [clamp(min[0] - margin), clamp(min[1] - margin)],
+                [clamp(max[0] + margin), clamp(max[1] + margin)],
+            ) else {
+                return Some(geometry);
+            };
+            // Features resting on the surface are not re-cut, buried ones are dropped
+            let surface_top = axis_bounds(&terrain.vertices).1[2];
+            let surface_bottom = terrain
+                .vertices
+                .chunks_exact(3)
+                .map(|p| p[2] as f64)
+                .filter(|&z| z > 0.0)
+                .fold(f64::INFINITY, f64::min);
+            if min[2] >= surface_top {
+                return Some(geometry);
+            }
+            if max[2] <= surface_bottom {
+                return None;
+            }
+
+            let (Some(solid), Some(terrain)) = (
+                buffer_geometry_to_csg(&geometry),
+                buffer_geometry_to_csg(&terrain),
+            ) else {
+                return Some(geometry);
+            };
+            csg_to_buffer_geometry(&solid.difference(&terrain)).map(|mut clipped| {
+                clipped.properties = geometry.properties.clone();
+                clipped
+            })
+        })
+        .collect()
+}
+
+/// Clip feature solids to the prism over any counter-clockwise `footprint` (mesh
+/// coordinates) from z = 0 upward, such as the clip shape of a process. Parts outside
+/// are cut off with capped walls; features inside are kept untouched.
+pub fn clip_to_footprint_solid(
+    geometries: Vec<BufferGeometry>,
+    footprint: &[[f64; 2]],
+) -> Vec<BufferGeometry> {
+    // Rounding slack so features flush with the tile edge or the base are not re-cut
+    let slack = 1e-4;
+
+    geometries
+        .into_iter()
+        .filter_map(|geometry| {
+            let (min, max) = axis_bounds(&geometry.vertices);
+            // Vertices inside a concave footprint can still have edges across a notch of it
+            let inside = min[2] >= -slack
+                && geometry.vertices.chunks_exact(3).all(|p| {
+                    clip_shape::contains(footprint, [p[0] as f64, p[1] as f64], slack)
+                })
+                && !footprint.iter().any(|c| {
+                    c[0] > min[0] + slack
+                        && c[0] < max[0] - slack
+                        && c[1] > min[1] + slack
+                        && c[1] < max[1] - slack
+                });
+            if !geometry.has_data || inside {
+                return Some(geometry);
+            }
+            if max[2] <= 0.0 {
+                return None;
+            }
+
+            let Some(solid) = buffer_geometry_to_csg(&geometry) else {
+                return Some(geometry);
+            };
+            let clipped = solid.intersection(&footprint_prism(footprint, 0.0, max[2] + 1.0));
+            csg_to_buffer_geometry(&clipped).map(|mut clipped| {
+                clipped.properties = geometry.properties.clone();
+                clipped
+            })
+        })
+        .collect()
+}
+
+// Smallest and largest coordinates of flat xyz `vertices` on each axis
+fn axis_bounds(vertices: &[f32]) -> ([f64; 3], [f64; 3]) {
+    let (mut min, mut max) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+    for p in vertices.chunks_exact(3) {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis] as f64);
+            max[axis] = max[axis].max(p[axis] as f64);
+        }
+    }
+    (min, max)
+}
+
+/// Split `target` by the vertical prism over `footprint` spanning `min_z..max_z` into the
+/// part inside and the part outside the prism, both capped along the cut
+pub fn split_by_prism(
+    target: &BufferGeometry,
+    footprint: &[[f64; 2]],
+    min_z: f64,
+    max_z: f64,
+) -> (Option<BufferGeometry>, Option<BufferGeometry>) {
+    let Some(target_solid) = buffer_geometry_to_csg(target) else {
+        return (None, None);
+    };
+    let prism = footprint_prism(footprint, min_z, max_z);
+    let with_properties = |solid: Solid| {
+        csg_to_buffer_geometry(&solid).map(|mut geometry| {
+            geometry.properties = target.properties.clone();
+            geometry
+        })
+    };
+    (
+        with_properties(target_solid.intersection(&prism)),
+        with_properties(target_solid.difference(&prism)),
+    )
+}
+
 // RESTORED: union_via_footprints was missing
 #[cfg(target_arch = "wasm32")]
 fn union_via_footprints(geometries: &[BufferGeometry]) -> Option<BufferGeometry> {
-    let mut groups: HashMap<(i64, i64), FootprintGroup> = HashMap::new();
+    // Ordered so the extruded groups are concatenated in the same order every run
+    let mut groups: BTreeMap<(i64, i64, Option<[i64; 3]>), FootprintGroup> = BTreeMap::new();
 
     for geometry in geometries {
         if let Some((footprint, min_z, max_z)) = geometry_footprint(geometry) {
@@ -926,7 +1178,17 @@ fn union_via_footprints(geometries: &[BufferGeometry]) -> Option<BufferGeometry>
                 continue;
             }
 
-            let key = (quantize_value(min_z, 1e-3), quantize_value(depth, 1e-3));
+            // Features of different colors are not merged into one footprint
+            let color = geometry
+                .colors
+                .as_ref()
+                .and_then(|colors| colors.get(0..3))
+                .map(|c| [c[0], c[1], c[2]]);
+            let key = (
+                quantize_value(min_z, 1e-3),
+                quantize_value(depth, 1e-3),
+                color.map(|c| c.map(|v| quantize_value(v as f64, 1e-3))),
+            );
 
             groups
                 .entry(key)
@@ -939,6 +1201,7 @@ fn union_via_footprints(geometries: &[BufferGeometry]) -> Option<BufferGeometry>
                     footprint,
                     min_z,
                     max_z,
+                    color,
                 });
         }
     }
@@ -950,7 +1213,10 @@ fn union_via_footprints(geometries: &[BufferGeometry]) -> Option<BufferGeometry>
             continue;
         }
 
-        if let Some(extruded) = extrude_multipolygon(&group.footprint, group.min_z, depth) {
+        if let Some(mut extruded) = extrude_multipolygon(&group.footprint, group.min_z, depth) {
+            if let Some(rgb) = group.color {
+                extruded.colors = Some(rgb.repeat(extruded.vertices.len() / 3));
+            }
             extruded_geometries.push(extruded);
         }
     }
@@ -1001,6 +1267,7 @@ fn quantize_position(x: f32, y: f32, z: f32) -> QuantizedPosition {
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn get_or_insert_vertex(
     original_index: u32,
     local_vertices: &[f32],
@@ -1182,4 +1449,90 @@ mod tests {
         approx_tuple_eq(bounds.0, (-1.0, -1.0, -1.0), 1e-4);
         approx_tuple_eq(bounds.1, (2.0, 1.0, 1.0), 1e-4);
     }
+
+    #[test]
+    fn boolean_ops_and_terrain_solid_clipping() {
+        let cube_a = cube_buffer((0.0, 0.0, 0.0), 1.0);
+        let cube_b = cube_buffer((1.0, 0.0, 0.0), 1.0);
+
+        let intersection = boolean_geometries(&cube_a, &[cube_b.clone()], BooleanOp::Intersect)
+            .expect("intersection");
+        let bounds = vertex_bounds(&intersection.vertices).expect("bounds");
+        approx_tuple_eq(bounds.0, (0.0, -1.0, -1.0), 1e-4);
+        approx_tuple_eq(bounds.1, (1.0, 1.0, 1.0), 1e-4);
+        assert!(BooleanOp::parse("xor").is_err());
+
+        // Straddles the tile edge at x = 10 and the base at z = 0
+        let straddling = cube_buffer((10.0, 0.0, 0.5), 1.0);
+        let inside = cube_buffer((0.0, 0.0, 2.0), 1.0);
+        let below = cube_buffer((0.0, 0.0, -5.0), 1.0);
+        let clipped = clip_to_terrain_solid(vec![straddling, inside.clone(), below], 10.0, |_, _| None);
+        assert_eq!(clipped.len(), 2);
+        let bounds = vertex_bounds(&clipped[0].vertices).expect("bounds");
+        approx_tuple_eq(bounds.0, (9.0, -1.0, 0.0), 1e-4);
+        approx_tuple_eq(bounds.1, (10.0, 1.0, 1.5), 1e-4);
+        assert_eq!(clipped[1].vertices, inside.vertices);
+    }
+
+    #[test]
+    fn terrain_solid_clipping_cuts_buried_parts() {
+        // Flat terrain surface at z = 1 over the whole tile
+        let terrain = |min: [f64; 2], max: [f64; 2]| {
+            let mut block = cube_buffer((0.0, 0.0, 0.5), 0.5);
+            for p in block.vertices.chunks_exact_mut(3) {
+                p[0] = if p[0] < 0.0 { min[0] } else { max[0] } as f32;
+                p[1] = if p[1] < 0.0 { min[1] } else { max[1] } as f32;
+            }
+            Some(block)
+        };
+        let submerged = cube_buffer((0.0, 0.0, 1.5), 1.0);
+        let resting = cube_buffer((5.0, 0.0, 2.5), 1.0);
+        let buried = cube_buffer((-5.0, 0.0, 0.5), 0.25);
+        let clipped =
+            clip_to_terrain_solid(vec![submerged, resting.clone(), buried], 10.0, terrain);
+        assert_eq!(clipped.len(), 2);
+        let bounds = vertex_bounds(&clipped[0].vertices).expect("bounds");
+        approx_tuple_eq(bounds.0, (-1.0, -1.0, 1.0), 1e-4);
+        approx_tuple_eq(bounds.1, (1.0, 1.0, 2.5), 1e-4);
+        assert_eq!(clipped[1].vertices, resting.vertices);
+    }
+
+    #[test]
+    fn boolean_ops_keep_vertex_colors() {
+        let colored = |center: (f32, f32, f32), rgb: [f32; 3]| {
+            let mut cube = cube_buffer(center, 1.0);
+            cube.colors = Some(rgb.repeat(cube.vertices.len() / 3));
+            cube
+        };
+        let red = colored((0.0, 0.0, 0.0), [1.0, 0.0, 0.0]);
+        let blue = colored((1.0, 0.0, 0.0), [0.0, 0.0, 1.0]);
+
+        // Every face of the union comes from one of the cubes and keeps its color
+        let union = boolean_geometries(&red, std::slice::from_ref(&blue), BooleanOp::Union)
+            .expect("union");
+        let colors = union.colors.expect("colors");
+        assert_eq!(colors.len(), union.vertices.len());
+        for (p, c) in union.vertices.chunks(3).zip(colors.chunks(3)) {
+            if p[0] < -0.5 {
+                assert_eq!(c, [1.0, 0.0, 0.0]);
+            } else if p[0] > 1.5 {
+                assert_eq!(c, [0.0, 0.0, 1.0]);
+            }
+        }
+        // Pieces of a triangle interpolate its vertex colors
+        let gradient = TriangleColors {
+            corners: [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(2.0, 0.0, 0.0),
+                Point3::new(0.0, 2.0, 0.0),
+            ],
+            colors: [[0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        };
+        assert_eq!(gradient.at(&Point3::new(1.0, 1.0, 0.0)), [0.5, 0.5, 0.0]);
+
+        // The walls cut by the clip take the color of the cut feature
+        let clipped = clip_to_terrain_solid(vec![blue], 1.5, |_, _| None);
+        let colors = clipped[0].colors.as_ref().expect("colors");
+        assert!(colors.chunks(3).all(|c| c == [0.0, 0.0, 1.0]));
+    }
 }
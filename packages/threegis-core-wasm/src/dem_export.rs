@@ -0,0 +1,274 @@
+// DEM export: the processed elevation grid as an Esri ASCII Grid or a Float32 GeoTIFF in
+// WGS84, so the elevation a model was built from can be checked or reused in GIS tools.
+// Grid values are point samples with the outer rows and columns on the bbox edges.
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::{ElevationExtent, ModuleState};
+
+// Written for cells without a finite elevation
+const NODATA: f64 = -9999.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemFormat {
+    AsciiGrid,
+    GeoTiff,
+}
+
+impl DemFormat {
+    fn parse(format: &str) -> Result<Self, String> {
+        match format.to_ascii_lowercase().as_str() {
+            "asc" | "ascii" | "aaigrid" => Ok(DemFormat::AsciiGrid),
+            "tif" | "tiff" | "geotiff" => Ok(DemFormat::GeoTiff),
+            other => Err(format!(
+                "Unknown DEM format '{}', expected 'asc' or 'geotiff'",
+                other
+            )),
+        }
+    }
+}
+
+// Grid dimensions and sample spacing in degrees; at least two samples per axis
+fn grid_geometry(
+    grid: &[Vec<f64>],
+    extent: &ElevationExtent,
+) -> Result<(usize, usize, f64, f64), String> {
+    let height = grid.len();
+    let width = grid.first().map_or(0, Vec::len);
+    if width < 2 || height < 2 || grid.iter().any(|row| row.len() != width) {
+        return Err("Elevation grid must be rectangular with at least 2x2 samples".to_string());
+    }
+    let [min_lng, min_lat, max_lng, max_lat] = extent.bbox;
+    Ok((
+        width,
+        height,
+        (max_lng - min_lng) / (width - 1) as f64,
+        (max_lat - min_lat) / (height - 1) as f64,
+    ))
+}
+
+fn value_or_nodata(elevation: f64) -> f64 {
+    if elevation.is_finite() {
+        elevation
+    } else {
+        NODATA
+    }
+}
+
+/// Esri ASCII Grid, north row first. Non-square cells use the `dx`/`dy` header keys
+/// that GDAL and QGIS read in place of `cellsize`.
+pub(crate) fn ascii_grid(grid: &[Vec<f64>], extent: &ElevationExtent) -> Result<String, String> {
+    let (width, height, dx, dy) = grid_geometry(grid, extent)?;
+    let mut out = format!(
+        "ncols {}\nnrows {}\nxllcenter {}\nyllcenter {}\n",
+        width, height, extent.bbox[0], extent.bbox[1]
+    );
+    if (dx - dy).abs() <= f64::EPSILON * dx.abs().max(1.0) {
+        out.push_str(&format!("cellsize {}\n", dx));
+    } else {
+        out.push_str(&format!("dx {}\ndy {}\n", dx, dy));
+    }
+    out.push_str(&format!("NODATA_value {}\n", NODATA));
+
+    // Grid row 0 is the southern edge
+    for row in grid.iter().rev() {
+        let values: Vec<String> = row
+            .iter()
+            .map(|&v| value_or_nodata(v).to_string())
+            .collect();
+        out.push_str(&values.join(" "));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+// TIFF field types
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const ASCII: u16 = 2;
+const DOUBLE: u16 = 12;
+
+enum TagValue {
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Ascii(String),
+    Double(Vec<f64>),
+}
+
+impl TagValue {
+    fn encode(&self) -> (u16, u32, Vec<u8>) {
+        match self {
+            TagValue::Short(v) => (
+                SHORT,
+                v.len() as u32,
+                v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            ),
+            TagValue::Long(v) => (
+                LONG,
+                v.len() as u32,
+                v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            ),
+            TagValue::Ascii(s) => {
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.push(0);
+                (ASCII, bytes.len() as u32, bytes)
+            }
+            TagValue::Double(v) => (
+                DOUBLE,
+                v.len() as u32,
+                v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            ),
+        }
+    }
+}
+
+/// Little-endian single-strip Float32 GeoTIFF in EPSG:4326 with PixelIsPoint raster space
+pub(crate) fn geotiff(grid: &[Vec<f64>], extent: &ElevationExtent) -> Result<Vec<u8>, String> {
+    let (width, height, dx, dy) = grid_geometry(grid, extent)?;
+    let pixels: Vec<u8> = grid
+        .iter()
+        .rev()
+        .flatten()
+        .flat_map(|&v| (value_or_nodata(v) as f32).to_le_bytes())
+        .collect();
+
+    // Header, then pixel data, then the IFD and the tag values that don't fit inline
+    let pixel_offset = 8u32;
+    let pixel_len = pixels.len() as u32;
+    let tags: Vec<(u16, TagValue)> = vec![
+        (256, TagValue::Long(vec![width as u32])),    // ImageWidth
+        (257, TagValue::Long(vec![height as u32])),   // ImageLength
+        (258, TagValue::Short(vec![32])),             // BitsPerSample
+        (259, TagValue::Short(vec![1])),              // Compression: none
+        (262, TagValue::Short(vec![1])),              // Photometric: BlackIsZero
+        (273, TagValue::Long(vec![pixel_offset])),    // StripOffsets
+        (277, TagValue::Short(vec![1])),              // SamplesPerPixel
+        (278, TagValue::Long(vec![height as u32])),   // RowsPerStrip
+        (279, TagValue::Long(vec![pixel_len])),       // StripByteCounts
+        (284, TagValue::Short(vec![1])),              // PlanarConfiguration: chunky
+        (339, TagValue::Short(vec![3])),              // SampleFormat: IEEE float
+        (33550, TagValue::Double(vec![dx, dy, 0.0])), // ModelPixelScale
+        // ModelTiepoint: pixel (0, 0) is the north-west sample
+        (
+            33922,
+            TagValue::Double(vec![0.0, 0.0, 0.0, extent.bbox[0], extent.bbox[3], 0.0]),
+        ),
+        // GeoKeyDirectory: geographic model, PixelIsPoint, WGS84
+        (
+            34735,
+            TagValue::Short(vec![
+                1, 1, 0, 3, //
+                1024, 0, 1, 2, // GTModelType: geographic
+                1025, 0, 1, 2, // GTRasterType: PixelIsPoint
+                2048, 0, 1, 4326, // GeographicType: WGS84
+            ]),
+        ),
+        (42113, TagValue::Ascii(NODATA.to_string())), // GDAL_NODATA
+    ];
+
+    let ifd_offset = pixel_offset + pixel_len + (pixel_len % 2);
+    let ifd_len = 2 + tags.len() as u32 * 12 + 4;
+    let mut overflow_offset = ifd_offset + ifd_len;
+
+    let mut ifd = (tags.len() as u16).to_le_bytes().to_vec();
+    let mut overflow = Vec::new();
+    for (tag, value) in &tags {
+        let (kind, count, mut bytes) = value.encode();
+        ifd.extend_from_slice(&tag.to_le_bytes());
+        ifd.extend_from_slice(&kind.to_le_bytes());
+        ifd.extend_from_slice(&count.to_le_bytes());
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            ifd.extend_from_slice(&bytes);
+        } else {
+            ifd.extend_from_slice(&overflow_offset.to_le_bytes());
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            overflow_offset += bytes.len() as u32;
+            overflow.extend_from_slice(&bytes);
+        }
+    }
+    // No further IFDs
+    ifd.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut tiff = b"II".to_vec();
+    tiff.extend_from_slice(&42u16.to_le_bytes());
+    tiff.extend_from_slice(&ifd_offset.to_le_bytes());
+    tiff.extend_from_slice(&pixels);
+    tiff.resize(ifd_offset as usize, 0);
+    tiff.extend_from_slice(&ifd);
+    tiff.extend_from_slice(&overflow);
+    Ok(tiff)
+}
+
+/// Processed elevation grid of `process_id` (or the most recently processed one) as a
+/// DEM file in meters: `format` "asc" gives an Esri ASCII Grid, "geotiff" a Float32
+/// GeoTIFF, both georeferenced in WGS84 with -9999 as nodata.
+#[wasm_bindgen]
+pub fn export_elevation_grid(process_id: Option<String>, format: &str) -> Result<Vec<u8>, JsValue> {
+    let format = DemFormat::parse(format).map_err(|e| JsValue::from_str(&e))?;
+    let file = ModuleState::with(|state| {
+        state
+            .get_elevation_grid_with_extent(process_id.as_deref())
+            .map(|(grid, extent)| match format {
+                DemFormat::AsciiGrid => ascii_grid(grid, extent).map(String::into_bytes),
+                DemFormat::GeoTiff => geotiff(grid, extent),
+            })
+    })
+    .ok_or_else(|| JsValue::from_str("No elevation grid cached; process elevation first"))?;
+    file.map_err(|e| JsValue::from_str(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_grid_and_geotiff_georeferencing() {
+        let extent = ElevationExtent {
+            bbox: [10.0, 50.0, 11.0, 52.0],
+            min_elevation: 0.0,
+            max_elevation: 300.0,
+        };
+        // Row 0 is the southern edge
+        let grid = vec![vec![0.0, 100.0], vec![200.0, f64::NAN]];
+
+        let asc = ascii_grid(&grid, &extent).unwrap();
+        assert_eq!(
+            asc,
+            "ncols 2\nnrows 2\nxllcenter 10\nyllcenter 50\ndx 1\ndy 2\nNODATA_value -9999\n200 -9999\n0 100\n"
+        );
+
+        let tiff = geotiff(&grid, &extent).unwrap();
+        assert_eq!(&tiff[..4], b"II\x2a\x00");
+        let pixels: Vec<f32> = tiff[8..24]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(pixels, vec![200.0, -9999.0, 0.0, 100.0]);
+
+        // Walk the IFD and read the tiepoint
+        let ifd = u32::from_le_bytes(tiff[4..8].try_into().unwrap()) as usize;
+        let count = u16::from_le_bytes(tiff[ifd..ifd + 2].try_into().unwrap()) as usize;
+        let entries: Vec<&[u8]> = tiff[ifd + 2..ifd + 2 + count * 12]
+            .chunks_exact(12)
+            .collect();
+        let tags: Vec<u16> = entries
+            .iter()
+            .map(|e| u16::from_le_bytes([e[0], e[1]]))
+            .collect();
+        assert!(tags.windows(2).all(|w| w[0] < w[1]), "tags must be sorted");
+        let tiepoint = entries
+            .iter()
+            .find(|e| e[..2] == 33922u16.to_le_bytes())
+            .unwrap();
+        let offset = u32::from_le_bytes(tiepoint[8..12].try_into().unwrap()) as usize;
+        let values: Vec<f64> = tiff[offset..offset + 48]
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, vec![0.0, 0.0, 0.0, 10.0, 52.0, 0.0]);
+
+        assert!(DemFormat::parse("png").is_err());
+    }
+}
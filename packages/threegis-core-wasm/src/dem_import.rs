@@ -0,0 +1,828 @@
+// DEM import: single-band GeoTIFF elevation rasters (strips or tiles, uncompressed or
+// deflate, with or without predictor) resampled into the elevation grid cache, so terrain
+// and layers can be built from a user's own DEM instead of tile servers. Georeferencing
+// is read from the tiepoint/pixel scale (or transformation) tags in WGS84 or Web Mercator.
+use flate2::read::ZlibDecoder;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Read;
+use wasm_bindgen::prelude::*;
+
+use crate::console::{self, LogLevel};
+use crate::module_state::{ElevationExtent, ModuleState};
+
+const DEFAULT_GRID_SIZE: u32 = 256;
+const MAX_GRID_SIZE: u32 = 1000;
+// Largest raster decoded, as pixels per side and in total (64M f64 samples are 512 MiB)
+const MAX_RASTER_SIDE: usize = 65_536;
+const MAX_RASTER_PIXELS: usize = 8192 * 8192;
+// Web Mercator sphere radius
+const EARTH_RADIUS: f64 = 6378137.0;
+
+// TIFF tags
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PREDICTOR: u16 = 317;
+const TILE_WIDTH: u16 = 322;
+const TILE_LENGTH: u16 = 323;
+const TILE_OFFSETS: u16 = 324;
+const TILE_BYTE_COUNTS: u16 = 325;
+const SAMPLE_FORMAT: u16 = 339;
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const MODEL_TRANSFORMATION: u16 = 34264;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GDAL_NODATA: u16 = 42113;
+
+// GeoKeys
+const GT_MODEL_TYPE: u16 = 1024;
+const GT_RASTER_TYPE: u16 = 1025;
+const PROJECTED_CS_TYPE: u16 = 3072;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+const WEB_MERCATOR_CODES: [u16; 2] = [3857, 3785];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DemImportSummary {
+    /// Size of the source raster in pixels
+    #[serde(rename = "sourceWidth")]
+    pub source_width: usize,
+    #[serde(rename = "sourceHeight")]
+    pub source_height: usize,
+    #[serde(rename = "gridWidth")]
+    pub grid_width: usize,
+    #[serde(rename = "gridHeight")]
+    pub grid_height: usize,
+    /// [minLng, minLat, maxLng, maxLat] of the stored grid
+    pub bbox: [f64; 4],
+    #[serde(rename = "minElevation")]
+    pub min_elevation: f64,
+    #[serde(rename = "maxElevation")]
+    pub max_elevation: f64,
+    /// Fraction of grid samples inside the raster with data; the rest take the lowest
+    /// elevation
+    pub coverage: f64,
+}
+
+struct Entry {
+    kind: u16,
+    count: usize,
+    // Offset of the value, inline in the entry when it fits in four bytes
+    offset: usize,
+}
+
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little: bool,
+}
+
+impl TiffReader<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], String> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| format!("TIFF truncated: {} bytes at {} out of range", len, offset))
+    }
+
+    fn uint(&self, offset: usize, len: usize) -> Result<u64, String> {
+        let bytes = self.bytes(offset, len)?;
+        Ok(raw_bits(bytes, self.little))
+    }
+
+    fn ifd(&self) -> Result<HashMap<u16, Entry>, String> {
+        let ifd_offset = self.uint(4, 4)? as usize;
+        let count = self.uint(ifd_offset, 2)? as usize;
+        let mut entries = HashMap::new();
+        for i in 0..count {
+            let at = i
+                .checked_mul(12)
+                .and_then(|entry| entry.checked_add(2))
+                .and_then(|entry| entry.checked_add(ifd_offset))
+                .ok_or_else(|| "TIFF IFD offset out of range".to_string())?;
+            let tag = self.uint(at, 2)? as u16;
+            let kind = self.uint(at + 2, 2)? as u16;
+            let count = self.uint(at + 4, 4)? as usize;
+            let size = type_size(kind).saturating_mul(count);
+            let offset = if size <= 4 {
+                at + 8
+            } else {
+                self.uint(at + 8, 4)? as usize
+            };
+            entries.insert(
+                tag,
+                Entry {
+                    kind,
+                    count,
+                    offset,
+                },
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Numeric values of an entry of any integer, rational or float type
+    fn values(&self, entry: &Entry) -> Result<Vec<f64>, String> {
+        let size = type_size(entry.kind);
+        if size == 0 {
+            return Err(format!("Unsupported TIFF field type {}", entry.kind));
+        }
+        (0..entry.count)
+            .map(|i| {
+                let at = i
+                    .checked_mul(size)
+                    .and_then(|at| at.checked_add(entry.offset))
+                    .ok_or_else(|| "TIFF value offset out of range".to_string())?;
+                let raw = self.uint(at, size.min(8))?;
+                Ok(match entry.kind {
+                    5 => raw as u32 as f64 / self.uint(at + 4, 4)?.max(1) as f64,
+                    6 => raw as i8 as f64,
+                    8 => raw as i16 as f64,
+                    9 => raw as i32 as f64,
+                    11 => f32::from_bits(raw as u32) as f64,
+                    12 => f64::from_bits(raw),
+                    _ => raw as f64,
+                })
+            })
+            .collect()
+    }
+
+    fn ascii(&self, entry: &Entry) -> Result<String, String> {
+        let bytes = self.bytes(entry.offset, entry.count)?;
+        Ok(String::from_utf8_lossy(bytes)
+            .trim_end_matches('\0')
+            .trim()
+            .to_string())
+    }
+}
+
+fn type_size(kind: u16) -> usize {
+    match kind {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+/// Unsigned value of up to eight bytes in the given byte order
+fn raw_bits(bytes: &[u8], little: bool) -> u64 {
+    let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+    if little {
+        bytes.iter().rev().fold(0, fold)
+    } else {
+        bytes.iter().fold(0, fold)
+    }
+}
+
+/// Elevation raster in pixel space, row 0 at the top
+struct Raster {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+}
+
+/// Maps raster pixels to longitude/latitude
+struct Georeference {
+    origin: [f64; 2],
+    pixel_size: [f64; 2],
+    // Pixel centers sit half a pixel inside the tiepoint for PixelIsArea rasters
+    center_offset: f64,
+    mercator: bool,
+}
+
+impl Georeference {
+    /// Fractional pixel (column, row) of a point
+    fn pixel(&self, lng: f64, lat: f64) -> (f64, f64) {
+        let (x, y) = if self.mercator {
+            let lat = lat.clamp(-85.051_128_78, 85.051_128_78).to_radians();
+            (
+                lng.to_radians() * EARTH_RADIUS,
+                (std::f64::consts::FRAC_PI_4 + lat / 2.0).tan().ln() * EARTH_RADIUS,
+            )
+        } else {
+            (lng, lat)
+        };
+        (
+            (x - self.origin[0]) / self.pixel_size[0] - self.center_offset,
+            (self.origin[1] - y) / self.pixel_size[1] - self.center_offset,
+        )
+    }
+
+    /// Longitude/latitude of a fractional pixel
+    fn lng_lat(&self, column: f64, row: f64) -> (f64, f64) {
+        let x = self.origin[0] + (column + self.center_offset) * self.pixel_size[0];
+        let y = self.origin[1] - (row + self.center_offset) * self.pixel_size[1];
+        if self.mercator {
+            (
+                (x / EARTH_RADIUS).to_degrees(),
+                (2.0 * (y / EARTH_RADIUS).exp().atan() - std::f64::consts::FRAC_PI_2).to_degrees(),
+            )
+        } else {
+            (x, y)
+        }
+    }
+
+    /// [minLng, minLat, maxLng, maxLat] spanned by the outermost pixel centers
+    fn bbox(&self, raster: &Raster) -> [f64; 4] {
+        let (west, north) = self.lng_lat(0.0, 0.0);
+        let (east, south) = self.lng_lat((raster.width - 1) as f64, (raster.height - 1) as f64);
+        [west, south, east, north]
+    }
+}
+
+fn parse_georeference(
+    tiff: &TiffReader,
+    entries: &HashMap<u16, Entry>,
+) -> Result<Georeference, String> {
+    let mut geo_keys: HashMap<u16, u16> = HashMap::new();
+    if let Some(entry) = entries.get(&GEO_KEY_DIRECTORY) {
+        let directory = tiff.values(entry)?;
+        // Header (version, revision, minor, key count), then four values per key; only
+        // keys stored inline are needed
+        for key in directory.get(4..).unwrap_or_default().chunks_exact(4) {
+            if key[1] == 0.0 {
+                geo_keys.insert(key[0] as u16, key[3] as u16);
+            }
+        }
+    }
+    let mercator = match geo_keys.get(&GT_MODEL_TYPE) {
+        Some(&MODEL_TYPE_PROJECTED) => match geo_keys.get(&PROJECTED_CS_TYPE) {
+            Some(code) if WEB_MERCATOR_CODES.contains(code) => true,
+            code => {
+                return Err(format!(
+                    "Unsupported projection {}; reproject the DEM to EPSG:4326 or EPSG:3857",
+                    code.map_or("(none)".to_string(), |c| format!("EPSG:{}", c))
+                ))
+            }
+        },
+        _ => false,
+    };
+    let center_offset = if geo_keys.get(&GT_RASTER_TYPE) == Some(&RASTER_PIXEL_IS_POINT) {
+        0.0
+    } else {
+        0.5
+    };
+
+    let (origin, pixel_size) = if let Some(entry) = entries.get(&MODEL_TRANSFORMATION) {
+        let m = tiff.values(entry)?;
+        if m.len() < 16 || m[1] != 0.0 || m[4] != 0.0 {
+            return Err("Rotated GeoTIFF rasters are not supported".to_string());
+        }
+        ([m[3], m[7]], [m[0], -m[5]])
+    } else {
+        let (Some(scale), Some(tiepoint)) = (
+            entries.get(&MODEL_PIXEL_SCALE),
+            entries.get(&MODEL_TIEPOINT),
+        ) else {
+            return Err("GeoTIFF has no georeferencing (tiepoint and pixel scale)".to_string());
+        };
+        let scale = tiff.values(scale)?;
+        let tiepoint = tiff.values(tiepoint)?;
+        if scale.len() < 2 || tiepoint.len() < 6 {
+            return Err("Malformed GeoTIFF tiepoint or pixel scale".to_string());
+        }
+        // Tiepoint (I, J, K, X, Y, Z): raster point (I, J) lies at model point (X, Y)
+        (
+            [
+                tiepoint[3] - tiepoint[0] * scale[0],
+                tiepoint[4] + tiepoint[1] * scale[1],
+            ],
+            [scale[0], scale[1]],
+        )
+    };
+    if pixel_size
+        .iter()
+        .any(|size| !size.is_finite() || *size <= 0.0)
+    {
+        return Err(format!(
+            "Unsupported pixel size {} x {}",
+            pixel_size[0], pixel_size[1]
+        ));
+    }
+    Ok(Georeference {
+        origin,
+        pixel_size,
+        center_offset,
+        mercator,
+    })
+}
+
+fn single_value(
+    tiff: &TiffReader,
+    entries: &HashMap<u16, Entry>,
+    tag: u16,
+    default: Option<f64>,
+) -> Result<f64, String> {
+    match entries.get(&tag) {
+        Some(entry) => tiff
+            .values(entry)?
+            .first()
+            .copied()
+            .ok_or_else(|| format!("TIFF tag {} is empty", tag)),
+        None => default.ok_or_else(|| format!("TIFF tag {} is missing", tag)),
+    }
+}
+
+/// Decode the first image of a GeoTIFF into elevations; nodata becomes NaN
+fn parse_geotiff(data: &[u8]) -> Result<(Raster, Georeference), String> {
+    let little = match data.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return Err("Not a TIFF file".to_string()),
+    };
+    let tiff = TiffReader { data, little };
+    match tiff.uint(2, 2)? {
+        42 => {}
+        43 => return Err("BigTIFF is not supported".to_string()),
+        other => return Err(format!("Not a TIFF file (version {})", other)),
+    }
+    let entries = tiff.ifd()?;
+    let value = |tag, default| single_value(&tiff, &entries, tag, default);
+
+    let width = value(IMAGE_WIDTH, None)? as usize;
+    let height = value(IMAGE_LENGTH, None)? as usize;
+    let bits = value(BITS_PER_SAMPLE, Some(1.0))? as usize;
+    let sample_format = value(SAMPLE_FORMAT, Some(1.0))? as u16;
+    let compression = value(COMPRESSION, Some(1.0))? as u16;
+    let predictor = value(PREDICTOR, Some(1.0))? as u16;
+    if width < 2 || height < 2 {
+        return Err(format!(
+            "DEM must be at least 2x2 pixels, got {}x{}",
+            width, height
+        ));
+    }
+    let pixels = width
+        .checked_mul(height)
+        .filter(|&pixels| pixels <= MAX_RASTER_PIXELS && width.max(height) <= MAX_RASTER_SIDE)
+        .ok_or_else(|| {
+            format!(
+                "DEM of {}x{} pixels is too large; at most {} pixels are supported",
+                width, height, MAX_RASTER_PIXELS
+            )
+        })?;
+    if value(SAMPLES_PER_PIXEL, Some(1.0))? != 1.0 {
+        return Err("Only single-band DEMs are supported".to_string());
+    }
+    match (sample_format, bits) {
+        (1 | 2, 8 | 16 | 32) | (3, 32 | 64) => {}
+        _ => {
+            return Err(format!(
+                "Unsupported sample type: format {} with {} bits",
+                sample_format, bits
+            ))
+        }
+    }
+    if !matches!(compression, 1 | 8 | 32946) {
+        return Err(format!(
+            "Unsupported TIFF compression {}; use none or deflate",
+            compression
+        ));
+    }
+    if !matches!(predictor, 1..=3) || (predictor == 3 && sample_format != 3) {
+        return Err(format!("Unsupported TIFF predictor {}", predictor));
+    }
+    let sample_size = bits / 8;
+    let nodata = entries
+        .get(&GDAL_NODATA)
+        .map(|entry| tiff.ascii(entry))
+        .transpose()?
+        .and_then(|text| text.parse::<f64>().ok());
+    let georeference = parse_georeference(&tiff, &entries)?;
+
+    // Strips are tiles spanning the full width
+    let (chunk_width, chunk_height, offsets, byte_counts) = if entries.contains_key(&TILE_OFFSETS) {
+        (
+            value(TILE_WIDTH, None)? as usize,
+            value(TILE_LENGTH, None)? as usize,
+            TILE_OFFSETS,
+            TILE_BYTE_COUNTS,
+        )
+    } else {
+        (
+            width,
+            (value(ROWS_PER_STRIP, Some(height as f64))? as usize).min(height),
+            STRIP_OFFSETS,
+            STRIP_BYTE_COUNTS,
+        )
+    };
+    let list = |tag| {
+        entries
+            .get(&tag)
+            .ok_or_else(|| format!("TIFF tag {} is missing", tag))
+            .and_then(|entry| tiff.values(entry))
+    };
+    let (offsets, byte_counts) = (list(offsets)?, list(byte_counts)?);
+    if chunk_width == 0 || chunk_height == 0 || chunk_width.max(chunk_height) > MAX_RASTER_SIDE {
+        return Err("Invalid TIFF tile or strip size".to_string());
+    }
+    let across = width.div_ceil(chunk_width);
+    let down = height.div_ceil(chunk_height);
+    let chunks = across
+        .checked_mul(down)
+        .ok_or_else(|| "Invalid TIFF tile or strip size".to_string())?;
+    if offsets.len() < chunks || byte_counts.len() < chunks {
+        return Err("TIFF has fewer tiles or strips than its size needs".to_string());
+    }
+    // Inflated data past one full tile or strip is never read
+    let chunk_bytes = chunk_width as u64 * chunk_height as u64 * sample_size as u64;
+
+    let mut values = vec![f64::NAN; pixels];
+    for chunk in 0..chunks {
+        let compressed = tiff.bytes(offsets[chunk] as usize, byte_counts[chunk] as usize)?;
+        let raw = if compression == 1 {
+            compressed.to_vec()
+        } else {
+            let mut inflated = Vec::new();
+            ZlibDecoder::new(compressed)
+                .take(chunk_bytes)
+                .read_to_end(&mut inflated)
+                .map_err(|e| format!("Failed to inflate TIFF data: {}", e))?;
+            inflated
+        };
+
+        let (x0, y0) = (
+            (chunk % across) * chunk_width,
+            (chunk / across) * chunk_height,
+        );
+        // The last strip may stop at the image edge; tiles are always full size
+        let rows = (raw.len() / (chunk_width * sample_size)).min(chunk_height);
+        for row in 0..rows.min(height - y0) {
+            let row_bytes = &raw[row * chunk_width * sample_size..][..chunk_width * sample_size];
+            let samples = decode_row(row_bytes, sample_size, little, predictor);
+            for (column, raw_sample) in samples.into_iter().enumerate().take(width - x0) {
+                let value = sample_value(raw_sample, sample_format, bits);
+                let value = match nodata {
+                    Some(nodata) if value == nodata || (value as f32) == (nodata as f32) => {
+                        f64::NAN
+                    }
+                    _ => value,
+                };
+                values[(y0 + row) * width + x0 + column] = value;
+            }
+        }
+    }
+
+    Ok((
+        Raster {
+            width,
+            height,
+            values,
+        },
+        georeference,
+    ))
+}
+
+/// Raw sample bits of one row with the predictor undone
+fn decode_row(bytes: &[u8], sample_size: usize, little: bool, predictor: u16) -> Vec<u64> {
+    let count = bytes.len() / sample_size;
+    match predictor {
+        // Floating point predictor: byte-wise differences over a row whose sample bytes
+        // are split into planes, most significant first
+        3 => {
+            let mut undone = bytes.to_vec();
+            for i in 1..undone.len() {
+                undone[i] = undone[i].wrapping_add(undone[i - 1]);
+            }
+            (0..count)
+                .map(|i| {
+                    (0..sample_size).fold(0u64, |value, plane| {
+                        (value << 8) | undone[plane * count + i] as u64
+                    })
+                })
+                .collect()
+        }
+        _ => {
+            let mut samples: Vec<u64> = bytes
+                .chunks_exact(sample_size)
+                .map(|b| raw_bits(b, little))
+                .collect();
+            // Horizontal differencing of integers, wrapping at the sample width
+            if predictor == 2 {
+                let mask = u64::MAX >> (64 - sample_size * 8);
+                for i in 1..samples.len() {
+                    samples[i] = samples[i].wrapping_add(samples[i - 1]) & mask;
+                }
+            }
+            samples
+        }
+    }
+}
+
+fn sample_value(raw: u64, sample_format: u16, bits: usize) -> f64 {
+    match (sample_format, bits) {
+        (3, 32) => f32::from_bits(raw as u32) as f64,
+        (3, _) => f64::from_bits(raw),
+        (2, 8) => raw as u8 as i8 as f64,
+        (2, 16) => raw as u16 as i16 as f64,
+        (2, _) => raw as u32 as i32 as f64,
+        _ => raw as f64,
+    }
+}
+
+/// Bilinear sample at a fractional pixel, averaging only the neighbors with data
+fn sample_raster(raster: &Raster, column: f64, row: f64) -> f64 {
+    let max_column = (raster.width - 1) as f64;
+    let max_row = (raster.height - 1) as f64;
+    // Allow the half pixel between the outer pixel centers and the raster edge
+    if !(-0.5..=max_column + 0.5).contains(&column) || !(-0.5..=max_row + 0.5).contains(&row) {
+        return f64::NAN;
+    }
+    let (column, row) = (column.clamp(0.0, max_column), row.clamp(0.0, max_row));
+    let (x0, y0) = (
+        (column.floor() as usize).min(raster.width - 2),
+        (row.floor() as usize).min(raster.height - 2),
+    );
+    let (dx, dy) = (column - x0 as f64, row - y0 as f64);
+    let mut total = 0.0;
+    let mut weight = 0.0;
+    for (x, y, w) in [
+        (x0, y0, (1.0 - dx) * (1.0 - dy)),
+        (x0 + 1, y0, dx * (1.0 - dy)),
+        (x0, y0 + 1, (1.0 - dx) * dy),
+        (x0 + 1, y0 + 1, dx * dy),
+    ] {
+        let value = raster.values[y * raster.width + x];
+        if value.is_finite() && w > 0.0 {
+            total += value * w;
+            weight += w;
+        }
+    }
+    if weight > 0.0 {
+        total / weight
+    } else {
+        f64::NAN
+    }
+}
+
+/// Resample a GeoTIFF DEM onto a `grid_size` x `grid_size` grid over `bbox` (the raster
+/// extent when None). Grid row 0 is the southern edge, as in the elevation grid cache.
+pub(crate) fn dem_grid_from_geotiff(
+    data: &[u8],
+    bbox: Option<[f64; 4]>,
+    grid_size: usize,
+) -> Result<(Vec<Vec<f64>>, ElevationExtent, DemImportSummary), String> {
+    let (raster, georeference) = parse_geotiff(data)?;
+    let bbox = bbox.unwrap_or_else(|| georeference.bbox(&raster));
+    let [min_lng, min_lat, max_lng, max_lat] = bbox;
+    if !bbox.iter().all(|v| v.is_finite()) || min_lng >= max_lng || min_lat >= max_lat {
+        return Err("Invalid bbox: must be [minLng, minLat, maxLng, maxLat]".to_string());
+    }
+
+    let mut grid = vec![vec![f64::NAN; grid_size]; grid_size];
+    for (gy, row) in grid.iter_mut().enumerate() {
+        let lat = min_lat + (max_lat - min_lat) * gy as f64 / (grid_size - 1) as f64;
+        for (gx, sample) in row.iter_mut().enumerate() {
+            let lng = min_lng + (max_lng - min_lng) * gx as f64 / (grid_size - 1) as f64;
+            let (column, pixel_row) = georeference.pixel(lng, lat);
+            *sample = sample_raster(&raster, column, pixel_row);
+        }
+    }
+
+    let covered: Vec<f64> = grid
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|v| v.is_finite())
+        .collect();
+    if covered.is_empty() {
+        return Err("The DEM has no data inside the bbox".to_string());
+    }
+    let min_elevation = covered.iter().copied().fold(f64::INFINITY, f64::min);
+    let max_elevation = covered.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    for sample in grid.iter_mut().flatten() {
+        if !sample.is_finite() {
+            *sample = min_elevation;
+        }
+    }
+
+    let summary = DemImportSummary {
+        source_width: raster.width,
+        source_height: raster.height,
+        grid_width: grid_size,
+        grid_height: grid_size,
+        bbox,
+        min_elevation,
+        max_elevation,
+        coverage: covered.len() as f64 / (grid_size * grid_size) as f64,
+    };
+    let extent = ElevationExtent {
+        bbox,
+        min_elevation,
+        max_elevation,
+    };
+    Ok((grid, extent, summary))
+}
+
+/// Load a single-band GeoTIFF DEM (WGS84 or Web Mercator; uncompressed or deflate, strips
+/// or tiles) as the elevation grid of `process_id`, which terrain and layer generation
+/// then use instead of fetching elevation tiles. The DEM is resampled onto a `grid_size`
+/// square grid (default 256) over `bbox` [minLng, minLat, maxLng, maxLat], or over the
+/// raster extent when omitted. Returns a summary with the elevation range and coverage.
+#[wasm_bindgen]
+pub fn load_dem_geotiff(
+    bytes: &[u8],
+    bbox: Option<Vec<f64>>,
+    process_id: String,
+    grid_size: Option<u32>,
+) -> Result<JsValue, JsValue> {
+    let bbox = match bbox {
+        Some(bbox) => Some(<[f64; 4]>::try_from(bbox.as_slice()).map_err(|_| {
+            JsValue::from_str("Invalid bbox: must be [minLng, minLat, maxLng, maxLat]")
+        })?),
+        None => None,
+    };
+    let grid_size = grid_size
+        .unwrap_or(DEFAULT_GRID_SIZE)
+        .clamp(2, MAX_GRID_SIZE) as usize;
+    let (grid, extent, summary) = dem_grid_from_geotiff(bytes, bbox, grid_size)
+        .map_err(|e| JsValue::from_str(&format!("Failed to load DEM: {}", e)))?;
+    if summary.coverage < 1.0 {
+        console::record(
+            LogLevel::Warn,
+            "elevation",
+            Some(&process_id),
+            format!(
+                "DEM covers {:.1}% of the bbox; the rest uses its lowest elevation",
+                summary.coverage * 100.0
+            ),
+        );
+    }
+    ModuleState::with_mut(|state| {
+        state.store_elevation_grid_with_extent(process_id, grid, extent);
+    });
+    Ok(serde_wasm_bindgen::to_value(&summary)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// Little-endian TIFF with the given (tag, type, values) entries and image data; values
+    /// larger than four bytes go after the IFD
+    fn build_tiff(entries: &[(u16, u16, Vec<u8>)], image: &[u8]) -> Vec<u8> {
+        let ifd_offset = 8 + image.len().next_multiple_of(2);
+        let mut overflow_offset = ifd_offset + 2 + entries.len() * 12 + 4;
+        let mut tiff = b"II\x2a\x00".to_vec();
+        tiff.extend_from_slice(&(ifd_offset as u32).to_le_bytes());
+        tiff.extend_from_slice(image);
+        tiff.resize(ifd_offset, 0);
+        tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        let mut overflow = Vec::new();
+        for (tag, kind, bytes) in entries {
+            tiff.extend_from_slice(&tag.to_le_bytes());
+            tiff.extend_from_slice(&kind.to_le_bytes());
+            tiff.extend_from_slice(&((bytes.len() / type_size(*kind)) as u32).to_le_bytes());
+            if bytes.len() <= 4 {
+                let mut inline = bytes.clone();
+                inline.resize(4, 0);
+                tiff.extend_from_slice(&inline);
+            } else {
+                tiff.extend_from_slice(&(overflow_offset as u32).to_le_bytes());
+                overflow_offset += bytes.len();
+                overflow.extend_from_slice(bytes);
+            }
+        }
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(&overflow);
+        tiff
+    }
+
+    fn shorts(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn doubles(values: &[f64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_geotiff_import_round_trip_and_deflate_tiles() {
+        // The DEM export's Float32 GeoTIFF reads back onto the same grid
+        let extent = ElevationExtent {
+            bbox: [10.0, 50.0, 11.0, 51.0],
+            min_elevation: 0.0,
+            max_elevation: 300.0,
+        };
+        let grid = vec![
+            vec![0.0, 100.0, 200.0],
+            vec![50.0, 150.0, 250.0],
+            vec![100.0, 200.0, 300.0],
+        ];
+        let tiff = crate::dem_export::geotiff(&grid, &extent).unwrap();
+        let (imported, imported_extent, summary) = dem_grid_from_geotiff(&tiff, None, 3).unwrap();
+        assert_eq!(summary.bbox, extent.bbox);
+        assert_eq!(summary.coverage, 1.0);
+        for (row, expected) in imported.iter().zip(&grid) {
+            for (value, expected) in row.iter().zip(expected) {
+                assert!((value - expected).abs() < 1e-3, "{:?}", imported);
+            }
+        }
+        assert_eq!(imported_extent.max_elevation, 300.0);
+
+        // 4x4 Int16 raster in two deflate 16x2 tiles with horizontal differencing,
+        // PixelIsArea over [0, 0, 4, 4]
+        let elevation = |column: usize, row: usize| (row * 10 + column) as i16;
+        let mut tiles = Vec::new();
+        let mut offsets = Vec::new();
+        let mut counts = Vec::new();
+        for tile in 0..2 {
+            let mut raw = Vec::new();
+            for row in 0..2 {
+                let mut previous = 0i16;
+                for column in 0..16 {
+                    let value = if column < 4 {
+                        elevation(column, tile * 2 + row)
+                    } else {
+                        0
+                    };
+                    raw.extend_from_slice(&value.wrapping_sub(previous).to_le_bytes());
+                    previous = value;
+                }
+            }
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&raw).unwrap();
+            let compressed = encoder.finish().unwrap();
+            offsets.push((8 + tiles.len()) as u32);
+            counts.push(compressed.len() as u32);
+            tiles.extend_from_slice(&compressed);
+        }
+        let longs =
+            |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+        let tiff = build_tiff(
+            &[
+                (IMAGE_WIDTH, 3, shorts(&[4])),
+                (IMAGE_LENGTH, 3, shorts(&[4])),
+                (BITS_PER_SAMPLE, 3, shorts(&[16])),
+                (COMPRESSION, 3, shorts(&[8])),
+                (PREDICTOR, 3, shorts(&[2])),
+                (TILE_WIDTH, 3, shorts(&[16])),
+                (TILE_LENGTH, 3, shorts(&[2])),
+                (TILE_OFFSETS, 4, longs(&offsets)),
+                (TILE_BYTE_COUNTS, 4, longs(&counts)),
+                (SAMPLE_FORMAT, 3, shorts(&[2])),
+                (MODEL_PIXEL_SCALE, 12, doubles(&[1.0, 1.0, 0.0])),
+                (MODEL_TIEPOINT, 12, doubles(&[0.0, 0.0, 0.0, 0.0, 4.0, 0.0])),
+                (
+                    GEO_KEY_DIRECTORY,
+                    3,
+                    shorts(&[1, 1, 0, 2, 1024, 0, 1, 2, 1025, 0, 1, 1]),
+                ),
+            ],
+            &tiles,
+        );
+        // Pixel centers run from 0.5 to 3.5; the north-west center is elevation(0, 0)
+        let bbox = [0.5, 0.5, 3.5, 3.5];
+        let (imported, _, summary) = dem_grid_from_geotiff(&tiff, Some(bbox), 4).unwrap();
+        assert_eq!(summary.source_width, 4);
+        // Grid row 0 is the south edge, i.e. raster row 3
+        assert_eq!(imported[0], vec![30.0, 31.0, 32.0, 33.0]);
+        assert_eq!(imported[3], vec![0.0, 1.0, 2.0, 3.0]);
+
+        assert!(dem_grid_from_geotiff(b"not a tiff", None, 4).is_err());
+    }
+
+    #[test]
+    fn test_truncated_and_oversized_headers() {
+        let longs =
+            |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+        let header = |width: u32, height: u32| {
+            build_tiff(
+                &[
+                    (IMAGE_WIDTH, 4, longs(&[width])),
+                    (IMAGE_LENGTH, 4, longs(&[height])),
+                    (BITS_PER_SAMPLE, 3, shorts(&[16])),
+                    (STRIP_OFFSETS, 4, longs(&[8])),
+                    (STRIP_BYTE_COUNTS, 4, longs(&[8])),
+                    (MODEL_PIXEL_SCALE, 12, doubles(&[1.0, 1.0, 0.0])),
+                    (MODEL_TIEPOINT, 12, doubles(&[0.0, 0.0, 0.0, 0.0, 2.0, 0.0])),
+                ],
+                &[0; 8],
+            )
+        };
+        assert!(parse_geotiff(&header(2, 2)).is_ok());
+
+        // Cut inside the IFD entries, and an IFD offset past the end
+        let tiff = header(2, 2);
+        let error = parse_geotiff(&tiff[..30]).err().unwrap();
+        assert!(error.contains("truncated"), "{}", error);
+        let mut far = tiff.clone();
+        far[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_geotiff(&far).err().unwrap().contains("truncated"));
+
+        // Dimensions whose pixel count would overflow or exhaust memory
+        for (width, height) in [(u32::MAX, u32::MAX), (100_000, 100_000), (70_000, 2)] {
+            let error = parse_geotiff(&header(width, height)).err().unwrap();
+            assert!(error.contains("too large"), "{}", error);
+        }
+    }
+}
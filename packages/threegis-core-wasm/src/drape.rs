@@ -0,0 +1,216 @@
+// Draped layers: flat features such as parks and land use become thin shells following
+// the terrain triangulation exactly. Each footprint is cut along the terrain triangles it
+// covers, so every piece of the shell lies parallel to the triangle under it, and its
+// walls are split wherever the outline crosses a triangle edge.
+use geo::orient::{Direction, Orient};
+use geo::{BooleanOps, Coord, LineString, Polygon};
+
+use crate::projection::terrain_size;
+use crate::solid_mesh::SolidMesh;
+
+/// Terrain triangle
+pub(crate) type Triangle = [[f64; 3]; 3];
+
+/// Height plane z = a·x + b·y + c of a terrain triangle
+pub(crate) struct Plane {
+    a: f64,
+    b: f64,
+    c: f64,
+}
+
+impl Plane {
+    // None for triangles without XY area
+    pub(crate) fn through([p, q, r]: &Triangle) -> Option<Plane> {
+        let u = [q[0] - p[0], q[1] - p[1], q[2] - p[2]];
+        let v = [r[0] - p[0], r[1] - p[1], r[2] - p[2]];
+        let det = u[0] * v[1] - u[1] * v[0];
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let a = (u[2] * v[1] - v[2] * u[1]) / det;
+        let b = (u[0] * v[2] - v[0] * u[2]) / det;
+        Some(Plane {
+            a,
+            b,
+            c: p[2] - a * p[0] - b * p[1],
+        })
+    }
+
+    pub(crate) fn z(&self, p: [f64; 2]) -> f64 {
+        self.a * p[0] + self.b * p[1] + self.c
+    }
+}
+
+/// Triangles of a regular grid with `spacing`, aligned to the terrain grid, covering the
+/// box between `min` and `max` at terrain heights from `surface`. Used for terrain given
+/// without a triangulation.
+pub(crate) fn grid_triangles(
+    min: [f64; 2],
+    max: [f64; 2],
+    spacing: f64,
+    surface: impl Fn(f64, f64) -> f64,
+) -> Vec<Triangle> {
+    if !spacing.is_finite() || spacing <= 0.0 {
+        return Vec::new();
+    }
+    let half = terrain_size() / 2.0;
+    let cell = |v: f64| ((v + half) / spacing).floor() as i64;
+    let point = |i: i64, j: i64| {
+        let (x, y) = (i as f64 * spacing - half, j as f64 * spacing - half);
+        [x, y, surface(x, y)]
+    };
+    let mut triangles = Vec::new();
+    for j in cell(min[1])..=cell(max[1]) {
+        for i in cell(min[0])..=cell(max[0]) {
+            let (a, b, c, d) = (
+                point(i, j),
+                point(i + 1, j),
+                point(i + 1, j + 1),
+                point(i, j + 1),
+            );
+            triangles.push([a, b, c]);
+            triangles.push([a, c, d]);
+        }
+    }
+    triangles
+}
+
+pub(crate) fn to_line_string(ring: &[[f64; 2]]) -> LineString<f64> {
+    ring.iter().map(|p| Coord { x: p[0], y: p[1] }).collect()
+}
+
+// Ring points without the closing point repeated
+pub(crate) fn open_ring(ring: &LineString<f64>) -> Vec<[f64; 2]> {
+    let mut points: Vec<[f64; 2]> = ring.coords().map(|c| [c.x, c.y]).collect();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+// Parameters along a→b where the segment crosses an edge of one of the triangles
+pub(crate) fn edge_crossings(a: [f64; 2], b: [f64; 2], triangles: &[Triangle]) -> Vec<f64> {
+    let d = [b[0] - a[0], b[1] - a[1]];
+    let mut crossings = Vec::new();
+    for triangle in triangles {
+        let outside = (0..2).any(|axis| {
+            let lo = triangle
+                .iter()
+                .map(|p| p[axis])
+                .fold(f64::INFINITY, f64::min);
+            let hi = triangle
+                .iter()
+                .map(|p| p[axis])
+                .fold(f64::NEG_INFINITY, f64::max);
+            a[axis].max(b[axis]) < lo || a[axis].min(b[axis]) > hi
+        });
+        if outside {
+            continue;
+        }
+        for k in 0..3 {
+            let (p, q) = (triangle[k], triangle[(k + 1) % 3]);
+            let e = [q[0] - p[0], q[1] - p[1]];
+            let det = d[0] * e[1] - d[1] * e[0];
+            if det.abs() < 1e-12 {
+                continue;
+            }
+            let w = [p[0] - a[0], p[1] - a[1]];
+            let t = (w[0] * e[1] - w[1] * e[0]) / det;
+            let s = (w[0] * d[1] - w[1] * d[0]) / det;
+            if t > 1e-9 && t < 1.0 - 1e-9 && (-1e-9..=1.0 + 1e-9).contains(&s) {
+                crossings.push(t);
+            }
+        }
+    }
+    crossings.sort_by(f64::total_cmp);
+    crossings.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+    crossings
+}
+
+/// Shell over the footprint `rings` (exterior first, then holes; mesh coordinates) from
+/// `bottom` to `top` above the terrain `triangles`. `surface` gives the terrain height
+/// along the outline for the walls.
+pub(crate) fn drape_mesh(
+    rings: &[Vec<[f64; 2]>],
+    triangles: &[Triangle],
+    surface: impl Fn(f64, f64) -> f64,
+    bottom: f64,
+    top: f64,
+) -> Option<SolidMesh> {
+    let exterior = rings.first().filter(|ring| ring.len() >= 3)?;
+    let holes = rings[1..]
+        .iter()
+        .filter(|ring| ring.len() >= 3)
+        .map(|ring| to_line_string(ring))
+        .collect();
+    let footprint = Polygon::new(to_line_string(exterior), holes).orient(Direction::Default);
+
+    let mut mesh = SolidMesh::default();
+    for triangle in triangles {
+        let Some(plane) = Plane::through(triangle) else {
+            continue;
+        };
+        let cell = Polygon::new(to_line_string(&triangle.map(|p| [p[0], p[1]])), Vec::new());
+        for piece in footprint.intersection(&cell) {
+            let piece_rings: Vec<Vec<[f64; 2]>> = std::iter::once(piece.exterior())
+                .chain(piece.interiors())
+                .map(open_ring)
+                .filter(|ring| ring.len() >= 3)
+                .collect();
+            if piece_rings.is_empty() {
+                continue;
+            }
+            mesh.push_surface_with_holes(&piece_rings, true, |p| plane.z(p) + top);
+            mesh.push_surface_with_holes(&piece_rings, false, |p| plane.z(p) + bottom);
+        }
+    }
+
+    // Exterior counter-clockwise and holes clockwise, so walls face out of the shell
+    for ring in std::iter::once(footprint.exterior()).chain(footprint.interiors()) {
+        let points = open_ring(ring);
+        for i in 0..points.len() {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            let mut stops = vec![0.0];
+            stops.extend(edge_crossings(a, b, triangles));
+            stops.push(1.0);
+            let at = |t: f64| [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+            for pair in stops.windows(2) {
+                let (p, q) = (at(pair[0]), at(pair[1]));
+                let (zp, zq) = (surface(p[0], p[1]), surface(q[0], q[1]));
+                mesh.push_quad([
+                    [p[0], p[1], zp + bottom],
+                    [q[0], q[1], zq + bottom],
+                    [q[0], q[1], zq + top],
+                    [p[0], p[1], zp + top],
+                ]);
+            }
+        }
+    }
+    (!mesh.is_empty()).then_some(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64) -> Vec<[f64; 2]> {
+        vec![[min, min], [max, min], [max, max], [min, max]]
+    }
+
+    #[test]
+    fn test_shell_follows_terrain_triangles() {
+        let slope = |x: f64, y: f64| 0.5 * x + 0.25 * y;
+        let triangles = grid_triangles([0.5, 0.5], [3.5, 3.5], 1.0, slope);
+        let mut hole = square(1.5, 2.5);
+        hole.reverse();
+        let mesh = drape_mesh(&[square(0.5, 3.5), hole], &triangles, slope, 0.1, 0.6).unwrap();
+
+        // (9 - 1) units of footprint, 0.5 thick everywhere
+        assert!((mesh.volume() - 4.0).abs() < 1e-4);
+        let geometry = mesh.into_geometry(None);
+        assert!(geometry.vertices.chunks(3).all(|v| {
+            let above = v[2] as f64 - slope(v[0] as f64, v[1] as f64);
+            (above - 0.1).abs() < 1e-4 || (above - 0.6).abs() < 1e-4
+        }));
+    }
+}
@@ -2,10 +2,18 @@ use js_sys::{Date, Uint8Array};
 use serde::{Deserialize, Serialize};
 use serde_wasm_bindgen::to_value;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::JsFuture;
 
-use crate::fetch;
-use crate::module_state::{create_tile_key, ModuleState, TileData};
+use crate::console::{self, LogLevel};
+use crate::elevation_reuse::{self, ReusedSamples, SampleLattice};
+use crate::fetch_hook::{network_fetch_with_headers, record_validators};
+use crate::mbtiles;
+use crate::parallel;
+use crate::gpu_dispatch::GpuCancellation;
+use crate::module_state::{create_tile_key, ElevationExtent, ModuleState, TileData};
+use crate::prefetch::TileSource;
+use crate::provenance;
+use crate::tilejson::{configured_tile_url, source_headers, source_metadata};
+use crate::vertical_datum::sample_grid_bilinear;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TileRequest {
@@ -25,6 +33,59 @@ pub struct ElevationProcessingInput {
     pub grid_height: u32,
     // Process reference for grouping cache entries
     pub process_id: String,
+    // Pixel encoding of the raster tiles; falls back to the raster TileJSON, then Mapbox
+    #[serde(default)]
+    pub encoding: Option<ElevationEncoding>,
+}
+
+/// How raster DEM tiles pack elevation into RGB. Every supported encoding is linear in
+/// the 24-bit value R * 65536 + G * 256 + B, so decoding is a scale and an offset.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ElevationEncoding {
+    /// Mapbox Terrain-RGB: -10000 + value * 0.1
+    #[default]
+    #[serde(alias = "mapbox")]
+    MapboxRgb,
+    /// Mapzen / AWS Terrain Tiles: R * 256 + G + B / 256 - 32768
+    Terrarium,
+    /// offset + value * scale
+    Custom { scale: f64, offset: f64 },
+}
+
+impl ElevationEncoding {
+    /// (scale, offset) applied to the 24-bit RGB value
+    pub fn scale_offset(&self) -> (f64, f64) {
+        match *self {
+            ElevationEncoding::MapboxRgb => (0.1, -10000.0),
+            ElevationEncoding::Terrarium => (1.0 / 256.0, -32768.0),
+            ElevationEncoding::Custom { scale, offset } => (scale, offset),
+        }
+    }
+
+    pub fn decode(&self, r: u8, g: u8, b: u8) -> f64 {
+        let (scale, offset) = self.scale_offset();
+        let value = (r as u32) * 65536 + (g as u32) * 256 + (b as u32);
+        offset + (value as f64) * scale
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (scale, offset) = self.scale_offset();
+        if !scale.is_finite() || scale == 0.0 || !offset.is_finite() {
+            return Err(format!(
+                "Invalid elevation encoding: scale {} and offset {} must be finite and scale non-zero",
+                scale, offset
+            ));
+        }
+        Ok(())
+    }
+
+    /// Explicit encoding, else the one declared by the raster TileJSON, else Mapbox
+    pub(crate) fn resolve(explicit: Option<ElevationEncoding>) -> ElevationEncoding {
+        explicit
+            .or_else(|| source_metadata(TileSource::Raster).and_then(|tilejson| tilejson.encoding))
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -63,31 +124,39 @@ pub fn tile_y_to_lat(y: u32, z: u32) -> f64 {
     sinh_lat.atan() * 180.0 / std::f64::consts::PI
 }
 
-// Process RGBA pixels to extract elevation values
-pub fn process_pixel_to_elevation(r: u8, g: u8, b: u8) -> f64 {
-    // Standard Mapbox Terrain-RGB encoding
-    // -10000 + ((R * 256² + G * 256 + B) * 0.1)
-    let value = (r as u32) * 65536 + (g as u32) * 256 + (b as u32);
-    -10000.0 + (value as f64) * 0.1
-}
-
-// Fetch a raster tile using JavaScript fetch helper
-pub async fn fetch_raster_tile(x: u32, y: u32, z: u32) -> Result<TileData, JsValue> {
-    // Construct the appropriate URL for elevation data
+// Source URL of an elevation raster tile
+pub(crate) fn raster_tile_url(x: u32, y: u32, z: u32) -> String {
+    if let Some(url) = configured_tile_url(TileSource::Raster.name(), x, y, z) {
+        return url;
+    }
     // Using Mapbox Terrain-RGB v2 format (WebP format)
-    let url = format!(
+    format!(
         "https://wms.wheregroup.com/dem_tileserver/raster_dem/{}/{}/{}.webp",
         z, x, y
-    );
+    )
+}
 
-    // Call the JavaScript helper to fetch the tile
-    let promise_result = fetch(&url);
-    // We need to unwrap the Result to get the Promise before passing it to JsFuture
-    let promise = promise_result?;
-    let js_result = JsFuture::from(promise).await?;
+// Fetch a raster tile using JavaScript fetch helper
+pub async fn fetch_raster_tile(x: u32, y: u32, z: u32) -> Result<TileData, JsValue> {
+    if let Some(tile) = mbtiles::load_raster_tile(x, y, z)? {
+        return Ok(tile);
+    }
+    let url = raster_tile_url(x, y, z);
+    let headers = source_headers(TileSource::Raster.name());
+    let js_result = network_fetch_with_headers(&url, &headers).await?;
+    record_validators(&url, &js_result);
+    provenance::record_retrieval(&url);
+    cache_raster_tile_response(x, y, z, &js_result)
+}
 
-    // Process the results from JavaScript
-    let js_obj = js_sys::Object::from(js_result);
+// Read the decoded pixels of a fetch response and cache the tile
+pub(crate) fn cache_raster_tile_response(
+    x: u32,
+    y: u32,
+    z: u32,
+    js_result: &JsValue,
+) -> Result<TileData, JsValue> {
+    let js_obj = js_sys::Object::from(js_result.clone());
 
     // Extract fields from the JS object
     let width = js_sys::Reflect::get(&js_obj, &JsValue::from_str("width"))?
@@ -101,23 +170,6 @@ pub async fn fetch_raster_tile(x: u32, y: u32, z: u32) -> Result<TileData, JsVal
     let pixel_data_js = js_sys::Reflect::get(&js_obj, &JsValue::from_str("pixelData"))?;
     let pixel_data = Uint8Array::new(&pixel_data_js);
 
-    // Sample some bytes from the data to see what's there
-    if pixel_data.length() > 20 {
-        let mut sample_bytes = vec![0u8; 20];
-        // Use slice() to get only the first 20 bytes, then copy that smaller slice
-        let sample_slice = Uint8Array::new_with_byte_offset_and_length(
-            &pixel_data.buffer(),
-            pixel_data.byte_offset(),
-            20,
-        );
-        sample_slice.copy_to(&mut sample_bytes[..]);
-        let _bytes_str = sample_bytes
-            .iter()
-            .map(|b| format!("{:02x}", b))
-            .collect::<Vec<String>>()
-            .join(" ");
-    }
-
     // Create our TileData struct
     let tile_data = TileData {
         width,
@@ -156,6 +208,9 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
     let input: ElevationProcessingInput = serde_json::from_str(input_json)
         .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
 
+    let encoding = ElevationEncoding::resolve(input.encoding);
+    encoding.validate().map_err(|e| JsValue::from_str(&e))?;
+
     let min_lng = input.min_lng;
     let min_lat = input.min_lat;
     let max_lng = input.max_lng;
@@ -163,10 +218,32 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
 
     // Get grid dimensions
     let grid_size = GridSize {
-        width: input.grid_width.max(100).min(1000),
-        height: input.grid_height.max(100).min(1000),
+        width: input.grid_width.clamp(100, 1000),
+        height: input.grid_height.clamp(100, 1000),
     };
 
+    // Samples of a cached grid on the same lattice are copied instead of recomputed
+    let lattice = SampleLattice::new(
+        [min_lng, min_lat, max_lng, max_lat],
+        grid_size.width as usize,
+        grid_size.height as usize,
+    );
+    let reused = elevation_reuse::reuse_cached_samples(&lattice);
+    if let Some(reused) = reused.as_ref().filter(|r| r.missing.is_empty()) {
+        let (processed_min, processed_max) = processed_range(&reused.grid, (0.0, 0.0));
+        let result = ElevationProcessingResult {
+            elevation_grid: reused.grid.clone(),
+            grid_size,
+            min_elevation: processed_min,
+            max_elevation: processed_max,
+            processed_min_elevation: processed_min,
+            processed_max_elevation: processed_max,
+            cache_hit_rate: 1.0,
+        };
+        cache_elevation_result(&input, &result);
+        return Ok(to_value(&result)?);
+    }
+
     let mut tile_data_array: Vec<TileData> = Vec::new();
     let mut cache_hits = 0;
     let mut cache_misses = 0;
@@ -174,7 +251,12 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
     // First pass: Check cache and record hits and misses
     let mut missing_tiles: Vec<(u32, u32, u32)> = Vec::new();
 
-    for tile_request in &input.tiles {
+    // Only tiles under the strips not covered by reused samples are needed
+    let needed_tiles = input
+        .tiles
+        .iter()
+        .filter(|tile| reused.as_ref().is_none_or(|r| r.needs_tile(tile)));
+    for tile_request in needed_tiles {
         let key = create_tile_key(tile_request.x, tile_request.y, tile_request.z);
 
         if let Some(tile_data) = ModuleState::with_mut(|state| state.get_raster_tile(&key).cloned())
@@ -204,14 +286,29 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
     // Try GPU acceleration first, fall back to CPU if needed
     let use_gpu = std::env::var("WASM_GPU_DISABLE").is_err(); // Allow disabling GPU via env var
 
-    if use_gpu && tile_data_array.len() > 0 {
-        match crate::gpu_elevation::process_elevation_gpu(&input, &tile_data_array).await {
+    // The GPU path computes the full grid, so partial reuse stays on the CPU
+    if use_gpu && reused.is_none() && !tile_data_array.is_empty() {
+        match crate::gpu_elevation::process_elevation_gpu(&input, &tile_data_array, encoding).await {
             Ok(gpu_result) => {
                 // GPU processing succeeded
+                cache_elevation_result(&input, &gpu_result);
                 return Ok(to_value(&gpu_result)?);
             }
-            Err(_e) => {
+            // A cancelled process must not continue on the CPU
+            Err(e) if GpuCancellation::for_process(Some(&input.process_id)).is_cancelled() => {
+                return Err(e);
+            }
+            Err(e) => {
                 // GPU processing failed, fall back to CPU
+                console::record(
+                    LogLevel::Warn,
+                    "elevation",
+                    Some(&input.process_id),
+                    format!(
+                        "GPU elevation processing failed, using CPU: {}",
+                        e.as_string().unwrap_or_default()
+                    ),
+                );
             }
         }
     }
@@ -230,7 +327,7 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
                 if idx + 2 >= tile.data.len() as u32 {
                     continue;
                 }
-                let elev = process_pixel_to_elevation(
+                let elev = encoding.decode(
                     tile.data[idx as usize],
                     tile.data[(idx + 1) as usize],
                     tile.data[(idx + 2) as usize],
@@ -243,32 +340,39 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
         }
     }
 
-    // Initialize accumulation grids matching the output grid size
+    // Rows are independent, each accumulates every tile's samples on its own cells
     let grid_width = grid_size.width as usize;
     let grid_height = grid_size.height as usize;
-    let mut elevation_grid: Vec<Vec<f64>> = vec![vec![0.0; grid_width]; grid_height];
-    let mut coverage_map: Vec<Vec<f64>> = vec![vec![0.0; grid_width]; grid_height];
+    let is_reused = |gx: usize, gy: usize| reused.as_ref().is_some_and(|r| r.covers(gx, gy));
+    let fill_elevation = (min_elevation_found + max_elevation_found) / 2.0;
+    let elevation_grid: Vec<Vec<f64>> = parallel::map_range(grid_height, |gy| {
+        let mut row = match &reused {
+            Some(ReusedSamples { grid, .. }) => grid[gy].clone(),
+            None => vec![0.0; grid_width],
+        };
+        let mut coverage = vec![0.0; grid_width];
+        let lat = min_lat + (max_lat - min_lat) * (gy as f64) / ((grid_height - 1) as f64);
+
+        // For each tile, accumulate elevation values on the row
+        for tile in &tile_data_array {
+            let z = tile.z;
+            // Calculate tile geographic bounds
+            let tile_min_lng = tile_x_to_lng(tile.x, z);
+            let tile_max_lng = tile_x_to_lng(tile.x + 1, z);
+            let tile_max_lat = tile_y_to_lat(tile.y, z);
+            let tile_min_lat = tile_y_to_lat(tile.y + 1, z);
+            if lat < tile_min_lat || lat > tile_max_lat {
+                continue;
+            }
 
-    // For each tile, accumulate elevation values on the output grid
-    for tile in &tile_data_array {
-        let z = tile.z;
-        // Calculate tile geographic bounds
-        let tile_min_lng = tile_x_to_lng(tile.x, z);
-        let tile_max_lng = tile_x_to_lng(tile.x + 1, z);
-        let tile_max_lat = tile_y_to_lat(tile.y, z);
-        let tile_min_lat = tile_y_to_lat(tile.y + 1, z);
-
-        // For each grid cell, compute the geographic coordinate
-        for gy in 0..grid_height {
-            let lat = min_lat + (max_lat - min_lat) * (gy as f64) / ((grid_height - 1) as f64);
+            // For each grid cell, compute the geographic coordinate
             for gx in 0..grid_width {
+                if is_reused(gx, gy) {
+                    continue;
+                }
                 let lng = min_lng + (max_lng - min_lng) * (gx as f64) / ((grid_width - 1) as f64);
                 // Skip grid points outside the tile's bounds
-                if lng < tile_min_lng
-                    || lng > tile_max_lng
-                    || lat < tile_min_lat
-                    || lat > tile_max_lat
-                {
+                if lng < tile_min_lng || lng > tile_max_lng {
                     continue;
                 }
                 // Map geographic coordinate to fractional pixel coordinates in tile
@@ -292,22 +396,22 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
                 if idx_br + 2 >= tile.data.len() {
                     continue;
                 }
-                let elev_tl = process_pixel_to_elevation(
+                let elev_tl = encoding.decode(
                     tile.data[idx_tl],
                     tile.data[idx_tl + 1],
                     tile.data[idx_tl + 2],
                 );
-                let elev_tr = process_pixel_to_elevation(
+                let elev_tr = encoding.decode(
                     tile.data[idx_tr],
                     tile.data[idx_tr + 1],
                     tile.data[idx_tr + 2],
                 );
-                let elev_bl = process_pixel_to_elevation(
+                let elev_bl = encoding.decode(
                     tile.data[idx_bl],
                     tile.data[idx_bl + 1],
                     tile.data[idx_bl + 2],
                 );
-                let elev_br = process_pixel_to_elevation(
+                let elev_br = encoding.decode(
                     tile.data[idx_br],
                     tile.data[idx_br + 1],
                     tile.data[idx_br + 2],
@@ -327,28 +431,59 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
                 let edge_weight = 1.0 - (max_dist * max_dist * 0.7);
 
                 // Accumulate the weighted elevation and corresponding coverage
-                elevation_grid[gy][gx] += elevation * edge_weight;
-                coverage_map[gy][gx] += edge_weight;
+                row[gx] += elevation * edge_weight;
+                coverage[gx] += edge_weight;
             }
         }
-    }
 
-    // Normalize grid cells by the accumulated coverage weight;
-    // fill missing data points with the average elevation if needed.
-    for gy in 0..grid_height {
+        // Normalize cells by the accumulated coverage weight;
+        // fill missing data points with the average elevation if needed.
         for gx in 0..grid_width {
-            if coverage_map[gy][gx] > 0.0 {
-                elevation_grid[gy][gx] /= coverage_map[gy][gx];
-            } else {
-                elevation_grid[gy][gx] = (min_elevation_found + max_elevation_found) / 2.0;
+            if is_reused(gx, gy) {
+                continue;
             }
+            row[gx] = if coverage[gx] > 0.0 {
+                row[gx] / coverage[gx]
+            } else {
+                fill_elevation
+            };
         }
-    }
+        row
+    });
 
     // Compute processed min/max from the normalized grid
+    let (processed_min, processed_max) =
+        processed_range(&elevation_grid, (min_elevation_found, max_elevation_found));
+
+    // Calculate tile cache hit rate as before
+    let hit_rate = if cache_hits + cache_misses > 0 {
+        cache_hits as f64 / (cache_hits + cache_misses) as f64
+    } else {
+        0.0
+    };
+
+    let min_elevation = processed_min; // Use processed min as min_elevation
+    let max_elevation = processed_max; // Use processed max as max_elevation
+
+    let result = ElevationProcessingResult {
+        elevation_grid,
+        grid_size,
+        min_elevation,
+        max_elevation,
+        processed_min_elevation: processed_min,
+        processed_max_elevation: processed_max,
+        cache_hit_rate: hit_rate,
+    };
+    cache_elevation_result(&input, &result);
+
+    Ok(to_value(&result)?)
+}
+
+// Min/max of a processed grid, widened to at least 1000m for nearly flat areas
+fn processed_range(grid: &[Vec<f64>], fallback: (f64, f64)) -> (f64, f64) {
     let mut processed_min = f64::INFINITY;
     let mut processed_max = f64::NEG_INFINITY;
-    for row in &elevation_grid {
+    for row in grid {
         for &cell in row {
             if cell.is_finite() && !cell.is_nan() {
                 processed_min = processed_min.min(cell);
@@ -357,43 +492,127 @@ pub async fn process_elevation_data_async(input_json: &str) -> Result<JsValue, J
         }
     }
     if processed_min == f64::INFINITY {
-        processed_min = min_elevation_found;
+        processed_min = fallback.0;
     }
     if processed_max == f64::NEG_INFINITY {
-        processed_max = max_elevation_found;
+        processed_max = fallback.1;
     }
     if (processed_max - processed_min).abs() < 1.0 {
         let mid = (processed_min + processed_max) / 2.0;
         processed_min = mid - 500.0;
         processed_max = mid + 500.0;
     }
+    (processed_min, processed_max)
+}
 
-    // After computing elevation_grid and before returning the result:
+// Keep the processed grid so layer generation and elevation queries can reuse it,
+// and record the tiles it was built from
+fn cache_elevation_result(input: &ElevationProcessingInput, result: &ElevationProcessingResult) {
+    let extent = ElevationExtent {
+        bbox: [input.min_lng, input.min_lat, input.max_lng, input.max_lat],
+        min_elevation: result.processed_min_elevation,
+        max_elevation: result.processed_max_elevation,
+    };
     ModuleState::with_mut(|state| {
-        state.store_elevation_grid(input.process_id.clone(), elevation_grid.clone());
+        state.store_elevation_grid_with_extent(
+            input.process_id.clone(),
+            result.elevation_grid.clone(),
+            extent,
+        );
     });
 
-    // Calculate tile cache hit rate as before
-    let hit_rate = if cache_hits + cache_misses > 0 {
-        cache_hits as f64 / (cache_hits + cache_misses) as f64
-    } else {
-        0.0
-    };
+    let tiles: Vec<crate::vectortile::TileRequest> = input
+        .tiles
+        .iter()
+        .map(|t| crate::vectortile::TileRequest { x: t.x, y: t.y, z: t.z })
+        .collect();
+    provenance::record_process_tiles(&input.process_id, TileSource::Raster, &tiles);
+}
 
-    let min_elevation = processed_min; // Use processed min as min_elevation
-    let max_elevation = processed_max; // Use processed max as max_elevation
+// Bilinear elevation in meters at (lng, lat), NaN outside the grid's bbox
+fn sample_elevation(grid: &[Vec<f64>], extent: &ElevationExtent, lng: f64, lat: f64) -> f64 {
+    let [min_lng, min_lat, max_lng, max_lat] = extent.bbox;
+    if !(lng >= min_lng && lng <= max_lng && lat >= min_lat && lat <= max_lat) {
+        return f64::NAN;
+    }
+    let normalized_x = (lng - min_lng) / (max_lng - min_lng).max(f64::EPSILON);
+    let normalized_y = (lat - min_lat) / (max_lat - min_lat).max(f64::EPSILON);
+    sample_grid_bilinear(grid, normalized_x, normalized_y)
+}
 
-    let result = ElevationProcessingResult {
-        elevation_grid,
-        grid_size,
-        min_elevation,
-        max_elevation,
-        processed_min_elevation: processed_min,
-        processed_max_elevation: processed_max,
-        cache_hit_rate: hit_rate,
-    };
+/// Elevation in meters at a coordinate, sampled from the cached processed elevation grid.
+/// Uses the grid of `process_id`, or the most recently processed one when omitted.
+/// Returns NaN outside the grid's bbox or when no grid is cached.
+#[wasm_bindgen]
+pub fn query_elevation(lng: f64, lat: f64, process_id: Option<String>) -> f64 {
+    ModuleState::with(|state| {
+        state
+            .get_elevation_grid_with_extent(process_id.as_deref())
+            .map_or(f64::NAN, |(grid, extent)| {
+                sample_elevation(grid, extent, lng, lat)
+            })
+    })
+}
 
-    Ok(to_value(&result)?)
+/// Batch variant of `query_elevation` for interleaved [lng, lat, lng, lat, ...] coordinates
+#[wasm_bindgen]
+pub fn query_elevation_batch(coords: &[f64], process_id: Option<String>) -> Vec<f64> {
+    ModuleState::with(|state| {
+        match state.get_elevation_grid_with_extent(process_id.as_deref()) {
+            Some((grid, extent)) => coords
+                .chunks_exact(2)
+                .map(|c| sample_elevation(grid, extent, c[0], c[1]))
+                .collect(),
+            None => vec![f64::NAN; coords.len() / 2],
+        }
+    })
 }
 
 // These functions have been moved to cache_manager.rs and exposed via lib.rs
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_elevation_samples_cached_grid() {
+        let extent = ElevationExtent {
+            bbox: [10.0, 50.0, 11.0, 51.0],
+            min_elevation: 0.0,
+            max_elevation: 300.0,
+        };
+        // Row 0 is the southern edge
+        let grid = vec![vec![0.0, 100.0], vec![200.0, 300.0]];
+        ModuleState::with_mut(|state| {
+            state.store_elevation_grid_with_extent("query-test".to_string(), grid, extent)
+        });
+
+        let id = Some("query-test".to_string());
+        assert_eq!(query_elevation(10.5, 50.5, id.clone()), 150.0);
+        assert_eq!(query_elevation(11.0, 51.0, id.clone()), 300.0);
+        assert!(query_elevation(12.0, 50.5, id.clone()).is_nan());
+
+        let batch = query_elevation_batch(&[10.0, 50.0, 10.0, 51.0], id);
+        assert_eq!(batch, vec![0.0, 200.0]);
+    }
+
+    #[test]
+    fn test_elevation_encodings() {
+        // 0 m in each encoding
+        assert_eq!(ElevationEncoding::MapboxRgb.decode(1, 134, 160), 0.0);
+        assert_eq!(ElevationEncoding::Terrarium.decode(128, 0, 0), 0.0);
+        // Terrarium keeps 1/256 m in the blue channel
+        assert_eq!(ElevationEncoding::Terrarium.decode(129, 2, 128), 258.5);
+        assert_eq!(ElevationEncoding::MapboxRgb.decode(1, 134, 170), 1.0);
+
+        let parse = |json: &str| serde_json::from_str::<ElevationEncoding>(json).unwrap();
+        assert_eq!(parse(r#""terrarium""#), ElevationEncoding::Terrarium);
+        assert_eq!(parse(r#""mapbox""#), ElevationEncoding::MapboxRgb);
+        let custom = parse(r#"{"custom": {"scale": 0.01, "offset": -500}}"#);
+        assert!((custom.decode(0, 0, 100) + 499.0).abs() < 1e-9);
+        assert!(ElevationEncoding::Custom { scale: 0.0, offset: 0.0 }.validate().is_err());
+
+        // Without an explicit encoding the raster TileJSON decides
+        assert_eq!(ElevationEncoding::resolve(None), ElevationEncoding::MapboxRgb);
+    }
+}
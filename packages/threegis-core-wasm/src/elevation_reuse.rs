@@ -0,0 +1,202 @@
+// Reuse of cached elevation samples for overlapping requests (pan, re-generation).
+// Cached grids are matched by their sample lattice, i.e. spacing and phase, instead of
+// the exact bbox: a bbox shifted along the same lattice copies the overlapping samples
+// and only the uncovered strips are fetched and computed.
+use std::ops::Range;
+
+use crate::elevation::{tile_x_to_lng, tile_y_to_lat, TileRequest};
+use crate::module_state::ModuleState;
+
+// Allowed mismatch of spacing and phase, as a fraction of one sample step
+const LATTICE_TOLERANCE: f64 = 1e-6;
+
+/// Sample positions of an elevation grid: cell (gx, gy) lies at (lng(gx), lat(gy)),
+/// row 0 on the southern edge
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SampleLattice {
+    pub min_lng: f64,
+    pub min_lat: f64,
+    pub step_lng: f64,
+    pub step_lat: f64,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl SampleLattice {
+    pub fn new(bbox: [f64; 4], width: usize, height: usize) -> Self {
+        Self {
+            min_lng: bbox[0],
+            min_lat: bbox[1],
+            step_lng: (bbox[2] - bbox[0]) / (width.max(2) - 1) as f64,
+            step_lat: (bbox[3] - bbox[1]) / (height.max(2) - 1) as f64,
+            width,
+            height,
+        }
+    }
+
+    pub fn lng(&self, gx: usize) -> f64 {
+        self.min_lng + self.step_lng * gx as f64
+    }
+
+    pub fn lat(&self, gy: usize) -> f64 {
+        self.min_lat + self.step_lat * gy as f64
+    }
+
+    /// Cell shift (dx, dy) such that cell (x, y) of `self` is cell (x + dx, y + dy) of
+    /// `other`, when both grids sample the same lattice
+    fn shift_to(&self, other: &SampleLattice) -> Option<(i64, i64)> {
+        let axis = |step: f64, other_step: f64, min: f64, other_min: f64| {
+            if step <= 0.0 || ((step - other_step) / step).abs() > LATTICE_TOLERANCE {
+                return None;
+            }
+            let offset = (min - other_min) / step;
+            let cells = offset.round();
+            ((offset - cells).abs() <= LATTICE_TOLERANCE).then_some(cells as i64)
+        };
+        Some((
+            axis(self.step_lng, other.step_lng, self.min_lng, other.min_lng)?,
+            axis(self.step_lat, other.step_lat, self.min_lat, other.min_lat)?,
+        ))
+    }
+
+    /// Cells of `self` covered by `other` shifted by `shift`, per axis
+    fn overlap(&self, other: &SampleLattice, shift: (i64, i64)) -> (Range<usize>, Range<usize>) {
+        let axis = |len: usize, other_len: usize, shift: i64| {
+            let start = (-shift).clamp(0, len as i64) as usize;
+            let end = (other_len as i64 - shift).clamp(start as i64, len as i64) as usize;
+            start..end
+        };
+        (
+            axis(self.width, other.width, shift.0),
+            axis(self.height, other.height, shift.1),
+        )
+    }
+
+    /// [minLng, minLat, maxLng, maxLat] spanned by a block of cells
+    fn cell_bounds(&self, xs: &Range<usize>, ys: &Range<usize>) -> [f64; 4] {
+        [
+            self.lng(xs.start),
+            self.lat(ys.start),
+            self.lng(xs.end - 1),
+            self.lat(ys.end - 1),
+        ]
+    }
+}
+
+/// Samples copied from a cached grid on the same lattice
+pub(crate) struct ReusedSamples {
+    /// Grid of the requested size; cells outside the covered ranges are still zero
+    pub grid: Vec<Vec<f64>>,
+    pub covered_x: Range<usize>,
+    pub covered_y: Range<usize>,
+    /// Bounds of the uncovered strips that still need to be computed
+    pub missing: Vec<[f64; 4]>,
+}
+
+impl ReusedSamples {
+    pub fn covers(&self, gx: usize, gy: usize) -> bool {
+        self.covered_x.contains(&gx) && self.covered_y.contains(&gy)
+    }
+
+    /// Whether a source tile contributes to any of the missing strips
+    pub fn needs_tile(&self, tile: &TileRequest) -> bool {
+        let tile_min_lng = tile_x_to_lng(tile.x, tile.z);
+        let tile_max_lng = tile_x_to_lng(tile.x + 1, tile.z);
+        let tile_max_lat = tile_y_to_lat(tile.y, tile.z);
+        let tile_min_lat = tile_y_to_lat(tile.y + 1, tile.z);
+        self.missing
+            .iter()
+            .any(|[min_lng, min_lat, max_lng, max_lat]| {
+                tile_min_lng <= *max_lng
+                    && tile_max_lng >= *min_lng
+                    && tile_min_lat <= *max_lat
+                    && tile_max_lat >= *min_lat
+            })
+    }
+}
+
+/// Copy the samples of the cached grid that overlaps `lattice` the most
+pub(crate) fn reuse_cached_samples(lattice: &SampleLattice) -> Option<ReusedSamples> {
+    ModuleState::with(|state| {
+        let (grid, covered_x, covered_y, shift) = state
+            .elevation_extents
+            .iter()
+            .filter_map(|(key, extent)| {
+                let grid = state.elevation_grids.get(key)?;
+                let cached = SampleLattice::new(
+                    extent.bbox,
+                    grid.first().map_or(0, |row| row.len()),
+                    grid.len(),
+                );
+                let shift = lattice.shift_to(&cached)?;
+                let (xs, ys) = lattice.overlap(&cached, shift);
+                (!xs.is_empty() && !ys.is_empty()).then_some((grid, xs, ys, shift))
+            })
+            .max_by_key(|(_, xs, ys, _)| xs.len() * ys.len())?;
+
+        let mut samples = vec![vec![0.0; lattice.width]; lattice.height];
+        for gy in covered_y.clone() {
+            let source = &grid[(gy as i64 + shift.1) as usize];
+            for gx in covered_x.clone() {
+                samples[gy][gx] = source[(gx as i64 + shift.0) as usize];
+            }
+        }
+
+        // Full-width strips below and above the overlap, side strips beside it
+        let all_x = 0..lattice.width;
+        let mut strips = vec![
+            (all_x.clone(), 0..covered_y.start),
+            (all_x, covered_y.end..lattice.height),
+            (0..covered_x.start, covered_y.clone()),
+            (covered_x.end..lattice.width, covered_y.clone()),
+        ];
+        strips.retain(|(xs, ys)| !xs.is_empty() && !ys.is_empty());
+        let missing = strips
+            .iter()
+            .map(|(xs, ys)| lattice.cell_bounds(xs, ys))
+            .collect();
+
+        Some(ReusedSamples {
+            grid: samples,
+            covered_x,
+            covered_y,
+            missing,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module_state::ElevationExtent;
+
+    #[test]
+    fn test_shifted_bbox_reuses_overlap() {
+        // 5x5 grid with 0.25° spacing whose values encode their column
+        let grid: Vec<Vec<f64>> = (0..5).map(|_| (0..5).map(|x| x as f64).collect()).collect();
+        ModuleState::with_mut(|state| {
+            state.store_elevation_grid_with_extent(
+                "reuse-test".to_string(),
+                grid,
+                ElevationExtent {
+                    bbox: [40.0, 60.0, 41.0, 61.0],
+                    min_elevation: 0.0,
+                    max_elevation: 4.0,
+                },
+            );
+        });
+
+        // Pan two samples east: columns 0..3 come from cached columns 2..5
+        let lattice = SampleLattice::new([40.5, 60.0, 41.5, 61.0], 5, 5);
+        let reused = reuse_cached_samples(&lattice).unwrap();
+        assert_eq!(reused.covered_x, 0..3);
+        assert_eq!(reused.covered_y, 0..5);
+        assert_eq!(reused.grid[4][..3], [2.0, 3.0, 4.0]);
+        assert_eq!(reused.missing, vec![[41.25, 60.0, 41.5, 61.0]]);
+        assert!(!reused.covers(3, 0));
+
+        // Half a sample off the lattice: nothing can be reused
+        let off_lattice = SampleLattice::new([40.125, 60.0, 41.125, 61.0], 5, 5);
+        assert!(reuse_cached_samples(&off_lattice).is_none());
+    }
+}
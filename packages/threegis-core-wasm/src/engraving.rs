@@ -0,0 +1,354 @@
+// Engraving: layers with a negative extrusionDepth or a road groove are generated as
+// cutter solids reaching from the groove floor to above the surface, through-cut layers
+// as blocks spanning the whole model height. Here they are subtracted from the terrain
+// (or flat base plate) mesh returned by create_terrain_geometry, together with the
+// pockets of water layers' slabs.
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::bounds::{self, BoundingVolume};
+use crate::csg_union::subtract_geometries;
+use crate::mesh_repair;
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::BufferGeometry;
+use crate::water;
+
+/// How far water pocket cutters reach above the highest terrain point
+const CUT_OVERSHOOT: f32 = 1.0;
+
+#[derive(Deserialize)]
+pub struct EngraveInput {
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    /// Engraved or water layer labels to carve; every cached one when omitted
+    #[serde(default)]
+    pub layers: Option<Vec<String>>,
+}
+
+fn is_cutter(geometry: &BufferGeometry) -> bool {
+    geometry.has_data
+        && geometry
+            .properties
+            .as_ref()
+            .and_then(|props| props.get("__engrave"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+}
+
+/// Cutter solids of the cached engraved layers of a process, and pockets reaching up to
+/// `top` for the slabs of its water layers
+fn cached_cutters(input: &EngraveInput, top: f32) -> Vec<BufferGeometry> {
+    ModuleState::with(|state| {
+        let Some(layers) = state.layer_geometries.get(&input.process_id) else {
+            return Vec::new();
+        };
+        layers
+            .iter()
+            .filter(|(label, _)| {
+                input
+                    .layers
+                    .as_ref()
+                    .is_none_or(|wanted| wanted.contains(label))
+            })
+            .flat_map(|(_, layer)| layer.geometries.iter())
+            .filter_map(|g| {
+                if is_cutter(g) {
+                    Some(g.clone())
+                } else if water::is_water_slab(g) {
+                    Some(water::water_cutter(g, top))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+fn engrave(
+    positions: &[f32],
+    indices: &[u32],
+    input: &EngraveInput,
+) -> Result<BufferGeometry, String> {
+    let terrain_top = positions
+        .chunks_exact(3)
+        .map(|p| p[2])
+        .fold(f32::MIN, f32::max);
+    let cutters = cached_cutters(input, terrain_top + CUT_OVERSHOOT);
+    if cutters.is_empty() {
+        return Err(format!(
+            "No engraved or water layers cached for process '{}'",
+            input.process_id
+        ));
+    }
+    let terrain = BufferGeometry {
+        vertices: positions.to_vec(),
+        normals: None,
+        colors: None,
+        indices: Some(indices.to_vec()),
+        uvs: None,
+        has_data: !positions.is_empty(),
+        properties: None,
+    };
+    subtract_geometries(&terrain, &cutters)
+        .map(mesh_repair::close_t_junctions)
+        .ok_or_else(|| "Engraving removed the whole terrain mesh".to_string())
+}
+
+/// Carve the cached engraved layers of a process, and the pockets of its water slabs,
+/// into terrain positions/indices.
+/// Returns `{ positions, normals, indices }` like `create_terrain_geometry`.
+#[wasm_bindgen]
+pub fn engrave_terrain(
+    positions: &[f32],
+    indices: &[u32],
+    input_json: &str,
+) -> Result<JsValue, JsValue> {
+    let input: EngraveInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let engraved = engrave(positions, indices, &input).map_err(|e| JsValue::from_str(&e))?;
+
+    bounds::store_bounds(
+        &input.process_id,
+        bounds::TERRAIN_BOUNDS_KEY,
+        BoundingVolume::from_positions(&engraved.vertices),
+    );
+
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("positions"),
+        &js_sys::Float32Array::from(engraved.vertices.as_slice()),
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("normals"),
+        &js_sys::Float32Array::from(engraved.normals.unwrap_or_default().as_slice()),
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("indices"),
+        &js_sys::Uint32Array::from(engraved.indices.unwrap_or_default().as_slice()),
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_cache::store_layer_geometry;
+    use std::collections::HashMap;
+
+    /// Closed axis-aligned box as indexed triangles, outward facing
+    fn cuboid(min: [f32; 3], max: [f32; 3]) -> (Vec<f32>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        for i in 0..8 {
+            vertices.push(if i & 1 == 0 { min[0] } else { max[0] });
+            vertices.push(if i & 2 == 0 { min[1] } else { max[1] });
+            vertices.push(if i & 4 == 0 { min[2] } else { max[2] });
+        }
+        let indices = vec![
+            0, 2, 1, 1, 2, 3, // bottom
+            4, 5, 6, 5, 7, 6, // top
+            0, 1, 4, 1, 5, 4, // front
+            2, 6, 3, 3, 6, 7, // back
+            0, 4, 2, 2, 4, 6, // left
+            1, 3, 5, 3, 7, 5, // right
+        ];
+        (vertices, indices)
+    }
+
+    #[test]
+    fn test_cutters_carve_groove_into_terrain() {
+        let (cutter_vertices, cutter_indices) = cuboid([-1.0, -1.0, 1.5], [1.0, 1.0, 3.0]);
+        let mut properties = HashMap::new();
+        properties.insert("__engrave".to_string(), serde_json::Value::Bool(true));
+        let cutter = BufferGeometry {
+            vertices: cutter_vertices,
+            normals: None,
+            colors: None,
+            indices: Some(cutter_indices),
+            uvs: None,
+            has_data: true,
+            properties: Some(properties),
+        };
+        store_layer_geometry(
+            "engraving-test",
+            "boundaries",
+            "hash".to_string(),
+            true,
+            vec![cutter],
+        );
+
+        // 10x10 block with its surface at z = 2; the groove floor is at z = 1.5
+        let (positions, indices) = cuboid([-5.0, -5.0, 0.0], [5.0, 5.0, 2.0]);
+        let input = EngraveInput {
+            process_id: "engraving-test".to_string(),
+            layers: None,
+        };
+        let engraved = engrave(&positions, &indices, &input).unwrap();
+        let has_vertex = |x: f32, y: f32, z: f32| {
+            engraved.vertices.chunks_exact(3).any(|v| {
+                (v[0] - x).abs() < 1e-4 && (v[1] - y).abs() < 1e-4 && (v[2] - z).abs() < 1e-4
+            })
+        };
+        assert!(has_vertex(1.0, 1.0, 1.5));
+        assert!(has_vertex(5.0, 5.0, 2.0));
+        assert!(engraved
+            .vertices
+            .chunks_exact(3)
+            .all(|v| v[2] <= 2.0 + 1e-4));
+
+        let other_layer = EngraveInput {
+            process_id: "engraving-test".to_string(),
+            layers: Some(vec!["water".to_string()]),
+        };
+        assert!(engrave(&positions, &indices, &other_layer).is_err());
+    }
+
+    /// Cutters of a layer generated on a flat base plate 2 units thick, cached for
+    /// `process_id` like `process_polygon_geometry` does
+    fn cache_flat_plate_cutters(
+        process_id: &str,
+        vt_data_set: serde_json::Value,
+        features: serde_json::Value,
+    ) -> Vec<BufferGeometry> {
+        let input: crate::polygon_geometry::PolygonGeometryInput =
+            serde_json::from_value(serde_json::json!({
+                "bbox": [13.0, 52.0, 13.1, 52.1],
+                "processId": process_id,
+                "flatBaseThickness": 2.0,
+                "vtDataSet": vt_data_set,
+                "polygons": features
+            }))
+            .unwrap();
+        let label = input.vt_data_set.get_label().to_string();
+        let output =
+            futures::executor::block_on(crate::polygon_geometry::generate_polygon_geometry(input))
+                .unwrap();
+        assert!(!output.geometries.is_empty());
+        assert!(output.geometries.iter().all(is_cutter));
+        store_layer_geometry(
+            process_id,
+            &label,
+            "hash".to_string(),
+            true,
+            output.geometries.clone(),
+        );
+        output.geometries
+    }
+
+    fn z_range(vertices: &[f32]) -> (f32, f32) {
+        vertices
+            .chunks_exact(3)
+            .fold((f32::MAX, f32::MIN), |(min, max), p| {
+                (min.min(p[2]), max.max(p[2]))
+            })
+    }
+
+    #[test]
+    fn test_road_groove_depth_on_flat_plate() {
+        let process_id = "groove-plate-test";
+        // A 20 unit long primary road across the middle of the 200x200 plate
+        let cutters = cache_flat_plate_cutters(
+            process_id,
+            serde_json::json!({
+                "sourceLayer": "transportation",
+                "groove": {"width": 2.0, "depth": 0.5}
+            }),
+            serde_json::json!([{
+                "geometry": [[13.045, 52.05], [13.055, 52.05]],
+                "type": "LineString",
+                "properties": {"class": "primary"}
+            }]),
+        );
+        // The groove depth is given in meters at the fixed line scale
+        let depth = 0.5 * crate::vertical_datum::fixed_meters_to_units() as f32;
+        let floor = cutters
+            .iter()
+            .map(|cutter| z_range(&cutter.vertices).0)
+            .fold(f32::MAX, f32::min);
+        assert!((floor - (2.0 - depth)).abs() < 1e-4, "{}", floor);
+
+        let (positions, indices) = cuboid([-100.0, -100.0, 0.0], [100.0, 100.0, 2.0]);
+        let input = EngraveInput {
+            process_id: process_id.to_string(),
+            layers: None,
+        };
+        let engraved = engrave(&positions, &indices, &input).unwrap();
+        ModuleState::with_mut(|state| state.clear_process_data(process_id));
+
+        assert_eq!(z_range(&engraved.vertices), (0.0, 2.0));
+        assert!(engraved
+            .vertices
+            .chunks_exact(3)
+            .any(|p| (p[2] - floor).abs() < 1e-4));
+        let report = crate::mesh_manifold::analyze_manifold(
+            &engraved.vertices,
+            engraved.indices.as_deref(),
+            None,
+        )
+        .unwrap();
+        assert!(report.is_manifold && report.is_watertight, "{:?}", report);
+        // Exactly the groove is removed: length x width x depth
+        let width = cutters
+            .iter()
+            .flat_map(|cutter| cutter.vertices.chunks_exact(3).map(|p| p[1]))
+            .fold((f32::MAX, f32::MIN), |(min, max), y| {
+                (min.min(y), max.max(y))
+            });
+        let groove_volume = 20.0 * (width.1 - width.0) as f64 * depth as f64;
+        let removed = 200.0 * 200.0 * 2.0 - report.signed_volume;
+        assert!(
+            (removed - groove_volume).abs() < 1e-2,
+            "{} {}",
+            removed,
+            groove_volume
+        );
+    }
+
+    #[test]
+    fn test_through_cut_removes_the_full_base() {
+        let process_id = "through-cut-test";
+        // A 40x40 unit lake in the south-west quarter of the 200x200 plate
+        let cutters = cache_flat_plate_cutters(
+            process_id,
+            serde_json::json!({"sourceLayer": "water", "throughCut": true}),
+            serde_json::json!([{
+                "geometry": [
+                    [13.02, 52.02], [13.04, 52.02], [13.04, 52.04], [13.02, 52.04], [13.02, 52.02]
+                ],
+                "type": "Polygon"
+            }]),
+        );
+        // The cutters reach from below the base to above the plate
+        for cutter in &cutters {
+            let (bottom, top) = z_range(&cutter.vertices);
+            assert!(bottom < 0.0 && top > 2.0, "{} {}", bottom, top);
+        }
+
+        let (positions, indices) = cuboid([-100.0, -100.0, 0.0], [100.0, 100.0, 2.0]);
+        let input = EngraveInput {
+            process_id: process_id.to_string(),
+            layers: None,
+        };
+        let engraved = engrave(&positions, &indices, &input).unwrap();
+        ModuleState::with_mut(|state| state.clear_process_data(process_id));
+
+        let report = crate::mesh_manifold::analyze_manifold(
+            &engraved.vertices,
+            engraved.indices.as_deref(),
+            None,
+        )
+        .unwrap();
+        assert!(report.is_manifold && report.is_watertight, "{:?}", report);
+        // The hole goes through the whole thickness: its full prism is gone
+        let removed = 200.0 * 200.0 * 2.0 - report.signed_volume;
+        assert!((removed - 40.0 * 40.0 * 2.0).abs() < 1e-2, "{}", removed);
+        // and no face is left inside the hole, neither on top nor at the bottom
+        let inside = |p: &[f32]| (-59.9..=-20.1).contains(&p[0]) && (-59.9..=-20.1).contains(&p[1]);
+        assert!(!engraved.vertices.chunks_exact(9).any(|t| {
+            let centroid = [(t[0] + t[3] + t[6]) / 3.0, (t[1] + t[4] + t[7]) / 3.0];
+            inside(&centroid)
+        }));
+    }
+}
@@ -0,0 +1,288 @@
+// Vertical exaggeration changes without a rebuild. Terrain Z is linear in the
+// exaggeration through the vertical datum, so terrain vertices are remapped
+// analytically, and cached layer geometry is shifted by the change of the terrain
+// surface underneath it, sampled from the cached elevation grid.
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::bounds::{self, BoundingVolume};
+use crate::layer_cache::CachedLayerGeometry;
+use crate::module_state::ModuleState;
+use crate::picking;
+use crate::polygon_geometry::BufferGeometry;
+use crate::projection::{self, terrain_size};
+use crate::vertical_datum::{sample_grid_bilinear, VerticalDatum, MIN_TERRAIN_THICKNESS};
+
+// Vertices this close to a feature's lowest Z belong to its bottom face
+const BOTTOM_FACE_EPSILON: f32 = 1e-4;
+
+#[derive(Deserialize)]
+pub struct RescaleInput {
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    #[serde(rename = "terrainBaseHeight")]
+    pub terrain_base_height: f64,
+    /// Exaggeration the existing results were generated with
+    #[serde(rename = "fromExaggeration")]
+    pub from_exaggeration: f64,
+    #[serde(rename = "toExaggeration")]
+    pub to_exaggeration: f64,
+    /// Layer labels to rescale; every cached layer of the process when omitted
+    #[serde(default)]
+    pub layers: Option<Vec<String>>,
+}
+
+impl RescaleInput {
+    fn parse(input_json: &str) -> Result<Self, JsValue> {
+        let input: Self = serde_json::from_str(input_json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+        if !input.from_exaggeration.is_finite() || !input.to_exaggeration.is_finite() {
+            return Err(JsValue::from_str("Exaggeration values must be finite"));
+        }
+        Ok(input)
+    }
+
+    fn datums(&self, min_elevation: f64, max_elevation: f64) -> (VerticalDatum, VerticalDatum) {
+        let model_scale = projection::process_scale(Some(&self.process_id)).model_scale;
+        let datum = |exaggeration| {
+            VerticalDatum::new(
+                self.terrain_base_height,
+                exaggeration,
+                min_elevation,
+                max_elevation,
+                model_scale,
+            )
+        };
+        (datum(self.from_exaggeration), datum(self.to_exaggeration))
+    }
+}
+
+/// Rescale terrain positions and normals returned by `create_terrain_geometry` in place
+#[wasm_bindgen]
+pub fn rescale_terrain_exaggeration(
+    positions: &mut [f32],
+    normals: &mut [f32],
+    input_json: &str,
+) -> Result<(), JsValue> {
+    let input = RescaleInput::parse(input_json)?;
+    if input.from_exaggeration <= 0.0 {
+        return Err(JsValue::from_str(
+            "fromExaggeration must be positive to rescale flat terrain",
+        ));
+    }
+    // The surface mapping only depends on base height and relief; the relief follows the
+    // elevation range only at a real-world model scale
+    let (min_elevation, max_elevation) =
+        match projection::activate_process(&input.process_id).model_scale {
+            Some(_) => ModuleState::with(|state| {
+                state
+                    .get_elevation_grid_with_extent(Some(&input.process_id))
+                    .map(|(_, extent)| (extent.min_elevation, extent.max_elevation))
+            })
+            .ok_or_else(|| {
+                JsValue::from_str(&format!(
+                    "No elevation grid cached for process '{}'",
+                    input.process_id
+                ))
+            })?,
+            None => (0.0, 0.0),
+        };
+    let (from, to) = input.datums(min_elevation, max_elevation);
+    rescale_terrain(positions, normals, &from, &to);
+
+    bounds::store_bounds(
+        &input.process_id,
+        bounds::TERRAIN_BOUNDS_KEY,
+        BoundingVolume::from_positions(positions),
+    );
+    Ok(())
+}
+
+fn rescale_terrain(
+    positions: &mut [f32],
+    normals: &mut [f32],
+    from: &VerticalDatum,
+    to: &VerticalDatum,
+) {
+    let z_scale = (to.relief_height() / from.relief_height()) as f32;
+    let has_normals = normals.len() == positions.len();
+    for (i, vertex) in positions.chunks_exact_mut(3).enumerate() {
+        // Bottom vertices sit at z = 0, below the minimum terrain thickness
+        if (vertex[2] as f64) < MIN_TERRAIN_THICKNESS / 2.0 {
+            continue;
+        }
+        let normalized = from.z_to_normalized(vertex[2] as f64);
+        vertex[2] = (to.terrain_base_height + normalized * to.relief_height())
+            .max(MIN_TERRAIN_THICKNESS) as f32;
+
+        // Normals of a height field scaled by k along z: (k·nx, k·ny, nz), renormalized
+        if has_normals {
+            let n = &mut normals[i * 3..i * 3 + 3];
+            let scaled = [n[0] * z_scale, n[1] * z_scale, n[2]];
+            let length =
+                (scaled[0] * scaled[0] + scaled[1] * scaled[1] + scaled[2] * scaled[2]).sqrt();
+            if length > f32::EPSILON {
+                n.copy_from_slice(&scaled.map(|c| c / length));
+            }
+        }
+    }
+}
+
+/// Re-align cached layer geometry to a new exaggeration. Returns an object mapping each
+/// rescaled layer label to geometry in the shape `process_polygon_geometry` returns.
+#[wasm_bindgen]
+pub fn rescale_layers_exaggeration(input_json: &str) -> Result<JsValue, JsValue> {
+    let input = RescaleInput::parse(input_json)?;
+    projection::activate_process(&input.process_id);
+    let (grid, extent) = ModuleState::with(|state| {
+        state
+            .get_elevation_grid_with_extent(Some(&input.process_id))
+            .map(|(grid, extent)| (grid.clone(), *extent))
+    })
+    .ok_or_else(|| {
+        JsValue::from_str(&format!(
+            "No elevation grid cached for process '{}'",
+            input.process_id
+        ))
+    })?;
+    let (from, to) = input.datums(extent.min_elevation, extent.max_elevation);
+    let shift = TerrainShift {
+        grid: &grid,
+        from,
+        to,
+    };
+
+    // Take the layers out of the cache while they are modified and re-indexed
+    let mut layers: Vec<(String, CachedLayerGeometry)> = ModuleState::with_mut(|state| {
+        let Some(cached) = state.layer_geometries.get_mut(&input.process_id) else {
+            return Vec::new();
+        };
+        let labels: Vec<String> = match &input.layers {
+            Some(labels) => labels.clone(),
+            None => cached.keys().cloned().collect(),
+        };
+        labels
+            .into_iter()
+            .filter_map(|label| cached.remove(&label).map(|layer| (label, layer)))
+            .collect()
+    });
+
+    let mut outputs = Vec::with_capacity(layers.len());
+    for (label, layer) in &mut layers {
+        for geometry in layer.geometries.iter_mut().filter(|g| g.has_data) {
+            shift.apply(geometry, layer.terrain_aligned);
+        }
+        picking::register_layer_geometries(&input.process_id, label, &layer.geometries);
+        bounds::register_layer_bounds(&input.process_id, label, &layer.geometries);
+        outputs.push((label.clone(), crate::geometries_to_js(&layer.geometries)));
+    }
+    ModuleState::with_mut(|state| {
+        let cached = state
+            .layer_geometries
+            .entry(input.process_id.clone())
+            .or_default();
+        cached.extend(layers);
+    });
+
+    let result = js_sys::Object::new();
+    for (label, geometries) in outputs {
+        js_sys::Reflect::set(&result, &JsValue::from_str(&label), &geometries)?;
+    }
+    Ok(result.into())
+}
+
+/// Change of the terrain surface Z between two datums
+struct TerrainShift<'a> {
+    grid: &'a [Vec<f64>],
+    from: VerticalDatum,
+    to: VerticalDatum,
+}
+
+impl TerrainShift<'_> {
+    fn at(&self, mesh_x: f64, mesh_y: f64) -> f64 {
+        let [nx, ny] = projection::mesh_to_normalized(mesh_x, mesh_y, terrain_size());
+        let elevation = sample_grid_bilinear(self.grid, nx, ny);
+        self.to.elevation_to_z(elevation) - self.from.elevation_to_z(elevation)
+    }
+
+    /// Terrain-aligned features follow the surface per vertex. Other features (buildings)
+    /// keep their shape: the bottom face follows the lowest terrain point under them and
+    /// the rest follows the highest, matching how generation extends them over slopes.
+    fn apply(&self, geometry: &mut BufferGeometry, terrain_aligned: bool) {
+        let shifts: Vec<f32> = geometry
+            .vertices
+            .chunks_exact(3)
+            .map(|v| self.at(v[0] as f64, v[1] as f64) as f32)
+            .collect();
+        if terrain_aligned {
+            for (vertex, shift) in geometry.vertices.chunks_exact_mut(3).zip(&shifts) {
+                vertex[2] += shift;
+            }
+            return;
+        }
+
+        let lowest_shift = shifts.iter().copied().fold(f32::INFINITY, f32::min);
+        let highest_shift = shifts.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let bottom_z = geometry
+            .vertices
+            .chunks_exact(3)
+            .map(|v| v[2])
+            .fold(f32::INFINITY, f32::min);
+        for vertex in geometry.vertices.chunks_exact_mut(3) {
+            vertex[2] += if vertex[2] - bottom_z <= BOTTOM_FACE_EPSILON {
+                lowest_shift
+            } else {
+                highest_shift
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terrain_and_layers_follow_new_exaggeration() {
+        // Elevation rises from 0m on the west edge to 100m on the east edge
+        let grid = vec![vec![0.0, 100.0], vec![0.0, 100.0]];
+        let from = VerticalDatum::new(1.0, 1.0, 0.0, 100.0, None);
+        let to = VerticalDatum::new(1.0, 2.0, 0.0, 100.0, None);
+
+        // Bottom vertex stays, top vertex at full relief moves from 6 to 11
+        let mut positions = vec![100.0, 0.0, 0.0, 100.0, 0.0, 6.0];
+        let mut normals = vec![0.0, 0.0, -1.0, 0.6, 0.0, 0.8];
+        rescale_terrain(&mut positions, &mut normals, &from, &to);
+        assert_eq!(positions, vec![100.0, 0.0, 0.0, 100.0, 0.0, 11.0]);
+        assert_eq!(&normals[0..3], &[0.0, 0.0, -1.0]);
+        assert!(normals[3] > 0.6 && normals[5] < 0.8);
+
+        let shift = TerrainShift {
+            grid: &grid,
+            from,
+            to,
+        };
+        let geometry = |vertices: Vec<f32>| BufferGeometry {
+            vertices,
+            normals: None,
+            colors: None,
+            indices: None,
+            uvs: None,
+            has_data: true,
+            properties: None,
+        };
+
+        let mut road = geometry(vec![-100.0, 0.0, 1.5, 100.0, 0.0, 6.5]);
+        shift.apply(&mut road, true);
+        assert_eq!(road.vertices, vec![-100.0, 0.0, 1.5, 100.0, 0.0, 11.5]);
+
+        // Building spanning the whole slope: bottom stays at the lowest point, top
+        // follows the highest
+        let mut building = geometry(vec![-100.0, 0.0, 1.0, 100.0, 0.0, 1.0, 100.0, 0.0, 9.0]);
+        shift.apply(&mut building, false);
+        assert_eq!(
+            building.vertices,
+            vec![-100.0, 0.0, 1.0, 100.0, 0.0, 1.0, 100.0, 0.0, 14.0]
+        );
+    }
+}
@@ -1,6 +1,33 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Write};
 use wasm_bindgen::prelude::*;
 
+use crate::export_validation::require_exportable_mesh;
+use crate::mesh_repair::{repair_mesh, RepairOptions, RepairedMesh};
+use crate::projection::process_scale;
+use crate::provenance::Provenance;
+use crate::reproducible::{without_retrieval_times, ContentHasher};
+use crate::vertical_datum::bbox_size_meters;
+use crate::zip_writer::{ChunkSink, ZipStreamWriter};
+
+// Namespace for STLMaps-specific metadata entries (3MF requires custom names to be qualified)
+const STLMAPS_METADATA_NS: &str = "https://stlmaps.com/3mf";
+// Matches the DEFLATE level the app used with JSZip
+const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+// Size of the byte chunks handed to JavaScript while streaming an archive
+const ARCHIVE_CHUNK_SIZE: usize = 256 * 1024;
+// Mesh name used by the app for the terrain base; always exported as the first object
+const TERRAIN_OBJECT_NAME: &str = "terrain";
+// 3MF Materials and Properties extension, needed for color groups
+const MATERIALS_NS: &str = "http://schemas.microsoft.com/3dmanufacturing/material/2015/02";
+// Resource id of the <basematerials> group when materials are assigned
+const BASE_MATERIALS_ID: usize = 1;
+// Per-object slicer settings read by PrusaSlicer (and forks) for extruder assignment
+const SLICER_CONFIG_PATH: &str = "Metadata/Slic3r_PE_model.config";
+// Display color of materials without a valid color
+const DEFAULT_MATERIAL_COLOR: &str = "#808080";
+
 #[derive(Serialize, Deserialize)]
 pub struct Mesh3MFData {
     pub vertices: Vec<f32>,
@@ -12,9 +39,209 @@ pub struct Mesh3MFData {
 
 #[derive(Serialize, Deserialize)]
 pub struct Model3MFData {
+    #[serde(default)]
     pub meshes: Vec<Mesh3MFData>,
     pub title: Option<String>,
     pub description: Option<String>,
+    /// Physical size in millimeters of the longest model side; scales the mesh space, which
+    /// is already in millimeters at a real-world model scale
+    #[serde(default, rename = "modelSizeMm")]
+    pub model_size_mm: Option<f64>,
+    /// Process the meshes were generated in, whose terrain scale sizes the model; the
+    /// default scale when None
+    #[serde(default, rename = "processId")]
+    pub process_id: Option<String>,
+    /// Geographic bbox [minLng, minLat, maxLng, maxLat] used to derive the real-world scale
+    #[serde(default)]
+    pub bbox: Option<Vec<f64>>,
+    /// ZIP DEFLATE level 0 (store) to 9 (smallest) for archive exports
+    #[serde(default, rename = "compressionLevel")]
+    pub compression_level: Option<u32>,
+    /// Layer labels in configuration order; objects are emitted terrain first, then in this order
+    #[serde(default, rename = "layerOrder")]
+    pub layer_order: Option<Vec<String>>,
+    /// How per-vertex mesh colors are carried into the archive
+    #[serde(default, rename = "colorGrouping")]
+    pub color_grouping: ColorGrouping,
+    /// Print materials written as 3MF base materials
+    #[serde(default)]
+    pub materials: Vec<Material3MF>,
+    /// Layer label (mesh name) → index into `materials`
+    #[serde(default, rename = "layerMaterials")]
+    pub layer_materials: HashMap<String, usize>,
+    /// Attribution and tile provenance as returned by `get_provenance`
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    /// Run the mesh repair pass on every mesh before it is checked and written
+    #[serde(default)]
+    pub repair: Option<RepairOptions>,
+    /// Leave out tile download times and stamp the archive with an `stlmaps:ContentHash`
+    /// of `meshes`, so identical models give byte-identical archives
+    #[serde(default)]
+    pub reproducible: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Material3MF {
+    pub name: String,
+    /// Display color "#RRGGBB" or "#RRGGBBAA"
+    #[serde(default)]
+    pub color: Option<String>,
+    /// 1-based extruder slicers should use for objects of this material
+    #[serde(default)]
+    pub extruder: Option<u32>,
+}
+
+impl Material3MF {
+    fn display_color(&self) -> &str {
+        self.color
+            .as_deref()
+            .filter(|c| {
+                c.starts_with('#')
+                    && matches!(c.len(), 7 | 9)
+                    && c[1..].chars().all(|ch| ch.is_ascii_hexdigit())
+            })
+            .unwrap_or(DEFAULT_MATERIAL_COLOR)
+    }
+}
+
+// Layer → material mapping resolved from the export options
+#[derive(Clone, Default)]
+struct MaterialAssignment {
+    materials: Vec<Material3MF>,
+    layer_materials: HashMap<String, usize>,
+}
+
+impl MaterialAssignment {
+    /// Material index for a mesh name; exact label match first, then case-insensitive
+    fn material_for(&self, name: &str) -> Option<usize> {
+        self.layer_materials
+            .get(name)
+            .or_else(|| {
+                self.layer_materials
+                    .iter()
+                    .find(|(label, _)| label.eq_ignore_ascii_case(name))
+                    .map(|(_, index)| index)
+            })
+            .copied()
+            .filter(|index| *index < self.materials.len())
+    }
+
+    /// First free resource id; the base material group takes id 1 when present
+    fn first_object_id(&self) -> usize {
+        if self.materials.is_empty() {
+            BASE_MATERIALS_ID
+        } else {
+            BASE_MATERIALS_ID + 1
+        }
+    }
+}
+
+/// Export of per-vertex colors. Triangles take the color most of their vertices share.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorGrouping {
+    /// Colors are dropped; one object per mesh
+    #[default]
+    None,
+    /// One object per mesh whose triangles reference a shared 3MF color group
+    ColorGroups,
+    /// One object per distinct color, which MMU/AMS slicer workflows assign to filaments
+    Objects,
+}
+
+/// Millimeters per mesh unit when the longest model side is printed at `model_size_mm`,
+/// for the terrain scale of `process_id`. Without a model size, mesh units are millimeters
+/// when the process was generated at a real-world model scale.
+pub(crate) fn millimeters_per_unit(
+    model_size_mm: Option<f64>,
+    process_id: Option<&str>,
+) -> Option<f64> {
+    let scale = process_scale(process_id);
+    match model_size_mm.filter(|size| size.is_finite() && *size > 0.0) {
+        Some(size) => Some(size / scale.size),
+        None => scale.model_scale.map(|_| 1.0),
+    }
+}
+
+impl Model3MFData {
+    /// Stable hash of the names, positions, indices and colors of `meshes`
+    fn content_hash(&self) -> String {
+        let mut hasher = ContentHasher::new();
+        for mesh in &self.meshes {
+            hasher.write_str(mesh.name.as_deref().unwrap_or_default());
+            hasher.write_f32s(&mesh.vertices);
+            hasher.write_u32s(&mesh.indices);
+            hasher.write_f32s(mesh.colors.as_deref().unwrap_or_default());
+        }
+        hasher.finish()
+    }
+
+    fn material_assignment(&self) -> MaterialAssignment {
+        MaterialAssignment {
+            materials: self.materials.clone(),
+            layer_materials: self.layer_materials.clone(),
+        }
+    }
+
+    /// Millimeters per mesh unit, or None when no model size was requested
+    pub(crate) fn millimeters_per_unit(&self) -> Option<f64> {
+        millimeters_per_unit(self.model_size_mm, self.process_id.as_deref())
+    }
+
+    /// Denominator N of the real-world scale 1:N (e.g. 25000 for 1:25000)
+    fn real_world_scale(&self) -> Option<f64> {
+        let mm_per_unit = self.millimeters_per_unit()?;
+        let bbox = self.bbox.as_deref().filter(|b| b.len() == 4)?;
+        let units_per_meter =
+            process_scale(self.process_id.as_deref()).size / bbox_size_meters(bbox);
+        if !units_per_meter.is_finite() || units_per_meter <= 0.0 {
+            return None;
+        }
+        let real_world_mm_per_unit = 1000.0 / units_per_meter;
+        Some(real_world_mm_per_unit / mm_per_unit)
+    }
+
+    fn archive_compression_level(&self) -> u32 {
+        self.compression_level
+            .unwrap_or(DEFAULT_COMPRESSION_LEVEL)
+            .min(9)
+    }
+
+    /// Meshes in export order: terrain first, then layers following `layer_order`,
+    /// then any remaining meshes in their input order
+    pub(crate) fn ordered_meshes(&self) -> Vec<&Mesh3MFData> {
+        let rank = |mesh: &Mesh3MFData| -> usize {
+            let name = mesh.name.as_deref().unwrap_or("");
+            if name.eq_ignore_ascii_case(TERRAIN_OBJECT_NAME) {
+                return 0;
+            }
+            self.layer_order
+                .as_ref()
+                .and_then(|order| order.iter().position(|label| label == name))
+                .map(|position| position + 1)
+                .unwrap_or(usize::MAX)
+        };
+
+        let mut meshes: Vec<&Mesh3MFData> = self.meshes.iter().collect();
+        // Stable sort keeps the input order among meshes with the same rank
+        meshes.sort_by_key(|mesh| rank(mesh));
+        meshes
+    }
+}
+
+impl Mesh3MFData {
+    /// Object name shown by slicers; falls back to a numbered name for unnamed meshes
+    pub(crate) fn object_name(&self, object_id: usize) -> String {
+        object_name(self.name.as_deref(), object_id)
+    }
+}
+
+fn object_name(name: Option<&str>, object_id: usize) -> String {
+    match name.map(str::trim).filter(|n| !n.is_empty()) {
+        Some(name) => name.to_string(),
+        None => format!("Object {}", object_id),
+    }
 }
 
 /// Generate 3MF XML content from geometry data
@@ -28,6 +255,120 @@ pub fn generate_3mf_model_xml(input_json: &str) -> Result<String, JsValue> {
         .map_err(|e| JsValue::from_str(&format!("Failed to create model XML: {}", e)))
 }
 
+/// Generate a complete 3MF archive (ZIP bytes) from geometry data
+#[wasm_bindgen]
+pub fn generate_3mf_archive(input_json: &str) -> Result<Vec<u8>, JsValue> {
+    let model_data: Model3MFData = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+
+    write_3mf_archive(&model_data, Vec::new())
+        .map_err(|e| JsValue::from_str(&format!("Failed to create 3MF archive: {}", e)))
+}
+
+/// Stream a 3MF archive to JavaScript in chunks instead of building it in memory.
+/// `on_chunk` is called with a Uint8Array for each chunk; returns the total archive size.
+#[wasm_bindgen]
+pub fn stream_3mf_archive(input_json: &str, on_chunk: &js_sys::Function) -> Result<f64, JsValue> {
+    let model_data: Model3MFData = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+
+    let sink = ChunkSink::new(ARCHIVE_CHUNK_SIZE, |chunk: &[u8]| {
+        let array = js_sys::Uint8Array::from(chunk);
+        on_chunk
+            .call1(&JsValue::NULL, &array)
+            .map(|_| ())
+            .map_err(|e| io::Error::other(format!("{:?}", e)))
+    });
+
+    let sink = write_3mf_archive(&model_data, sink)
+        .map_err(|e| JsValue::from_str(&format!("Failed to stream 3MF archive: {}", e)))?;
+    let total = sink
+        .finish()
+        .map_err(|e| JsValue::from_str(&format!("Failed to stream 3MF archive: {}", e)))?;
+    Ok(total as f64)
+}
+
+type ChunkCallback = Box<dyn FnMut(&[u8]) -> io::Result<()>>;
+type JsChunkSink = ChunkSink<ChunkCallback>;
+
+/// Incremental 3MF export for very large models. Meshes are added one layer (or layer
+/// chunk) at a time and compressed straight into the archive stream, so JavaScript never
+/// needs to hold every layer's triangles or the finished archive in memory at once.
+/// Objects keep the order they are added in; add the terrain first.
+#[wasm_bindgen]
+pub struct Export3MFSession {
+    writer: Option<Incremental3MFWriter<JsChunkSink>>,
+}
+
+#[wasm_bindgen]
+impl Export3MFSession {
+    /// `options_json` takes the same fields as `generate_3mf_archive` (meshes are ignored);
+    /// `on_chunk` receives a Uint8Array for each chunk of the archive.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        options_json: &str,
+        on_chunk: js_sys::Function,
+    ) -> Result<Export3MFSession, JsValue> {
+        let options: Model3MFData = serde_json::from_str(options_json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+
+        let callback: ChunkCallback = Box::new(move |chunk: &[u8]| {
+            let array = js_sys::Uint8Array::from(chunk);
+            on_chunk
+                .call1(&JsValue::NULL, &array)
+                .map(|_| ())
+                .map_err(|e| io::Error::other(format!("{:?}", e)))
+        });
+        let writer =
+            Incremental3MFWriter::new(&options, ChunkSink::new(ARCHIVE_CHUNK_SIZE, callback))
+                .map_err(|e| JsValue::from_str(&format!("Failed to start 3MF export: {}", e)))?;
+
+        Ok(Export3MFSession {
+            writer: Some(writer),
+        })
+    }
+
+    /// Append one mesh as a new object (several with `colorGrouping: "objects"`); returns
+    /// the first 3MF object id. `colors` are optional per-vertex RGB(A) values and
+    /// `transform` an optional column-major 4x4 matrix.
+    pub fn add_mesh(
+        &mut self,
+        name: Option<String>,
+        vertices: &[f32],
+        indices: &[u32],
+        colors: Option<Vec<f32>>,
+        transform: Option<Vec<f64>>,
+    ) -> Result<u32, JsValue> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| JsValue::from_str("3MF export session is already finished"))?;
+        writer
+            .add_mesh(
+                name.as_deref(),
+                vertices,
+                indices,
+                colors.as_deref(),
+                transform,
+            )
+            .map(|object_id| object_id as u32)
+            .map_err(|e| JsValue::from_str(&format!("Failed to write mesh: {}", e)))
+    }
+
+    /// Write the build section and central directory; returns the total archive size.
+    pub fn finish(&mut self) -> Result<f64, JsValue> {
+        let writer = self
+            .writer
+            .take()
+            .ok_or_else(|| JsValue::from_str("3MF export session is already finished"))?;
+        let total = writer
+            .finish()
+            .and_then(|sink| sink.finish())
+            .map_err(|e| JsValue::from_str(&format!("Failed to finish 3MF export: {}", e)))?;
+        Ok(total as f64)
+    }
+}
+
 /// Generate content types XML for 3MF
 #[wasm_bindgen]
 pub fn generate_3mf_content_types_xml() -> String {
@@ -56,93 +397,582 @@ fn create_rels_xml() -> String {
 }
 
 fn create_model_xml(model_data: &Model3MFData) -> Result<String, String> {
-    let mut xml = String::new();
+    let mut buffer = Vec::new();
+    write_model_xml(model_data, &mut buffer).map_err(|e| e.to_string())?;
+    String::from_utf8(buffer).map_err(|e| e.to_string())
+}
 
+// Write the 3D model part incrementally so large meshes never need a full XML string in memory.
+// Returns the (object id, material index) assignments for the slicer config.
+fn write_model_xml<W: Write + ?Sized>(
+    model_data: &Model3MFData,
+    out: &mut W,
+) -> io::Result<Vec<(usize, usize)>> {
+    write_model_header(model_data, out)?;
+
+    let assignment = model_data.material_assignment();
+    let mut next_id = assignment.first_object_id();
+    let mut items: Vec<(usize, Option<&[f64]>)> = Vec::new();
+    let mut assigned: Vec<(usize, usize)> = Vec::new();
+    for mesh in model_data.ordered_meshes() {
+        let name = mesh.object_name(next_id);
+        let repaired = repair_for_export(
+            model_data.repair.as_ref(),
+            &name,
+            &mesh.vertices,
+            &mesh.indices,
+            mesh.colors.as_deref(),
+        )?;
+        let (vertices, indices, colors) = match &repaired {
+            Some(r) => (&r.vertices[..], &r.indices[..], r.colors.as_deref()),
+            None => (
+                &mesh.vertices[..],
+                &mesh.indices[..],
+                mesh.colors.as_deref(),
+            ),
+        };
+        require_exportable_mesh(&name, vertices, indices)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let material = assignment.material_for(&name);
+        let object_ids = write_mesh_resources(
+            out,
+            &mut next_id,
+            &name,
+            vertices,
+            indices,
+            colors,
+            model_data.color_grouping,
+            material,
+        )?;
+        if let Some(material) = material {
+            assigned.extend(object_ids.iter().map(|id| (*id, material)));
+        }
+        items.extend(
+            object_ids
+                .into_iter()
+                .map(|id| (id, mesh.transform.as_deref())),
+        );
+    }
+
+    write_model_build(out, &items, model_data.millimeters_per_unit())?;
+    Ok(assigned)
+}
+
+// Repaired copy of a mesh when the export asks for the repair pass
+fn repair_for_export(
+    options: Option<&RepairOptions>,
+    name: &str,
+    vertices: &[f32],
+    indices: &[u32],
+    colors: Option<&[f32]>,
+) -> io::Result<Option<RepairedMesh>> {
+    let Some(options) = options else {
+        return Ok(None);
+    };
+    repair_mesh(vertices, Some(indices), colors, options)
+        .map(|(repaired, _)| Some(repaired))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", name, e)))
+}
+
+// XML declaration, root element, metadata and the opening <resources> tag
+fn write_model_header<W: Write + ?Sized>(model_data: &Model3MFData, out: &mut W) -> io::Result<()> {
     // XML declaration and root element
-    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
-<model unit="millimeter" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
-"#);
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    let materials_ns = match model_data.color_grouping {
+        ColorGrouping::ColorGroups => format!(r#" xmlns:m="{}""#, MATERIALS_NS),
+        _ => String::new(),
+    };
+    writeln!(
+        out,
+        r#"<model unit="millimeter" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02" xmlns:stlmaps="{}"{}>"#,
+        STLMAPS_METADATA_NS, materials_ns
+    )?;
 
     // Metadata
     let title = model_data.title.as_deref().unwrap_or("STLMaps 3D Model");
-    xml.push_str(&format!(
-        r#"  <metadata name="Title">{}</metadata>
-"#,
+    writeln!(
+        out,
+        r#"  <metadata name="Title">{}</metadata>"#,
         escape_xml(title)
-    ));
+    )?;
 
     if let Some(ref description) = model_data.description {
-        xml.push_str(&format!(
-            r#"  <metadata name="Description">{}</metadata>
-"#,
+        writeln!(
+            out,
+            r#"  <metadata name="Description">{}</metadata>"#,
             escape_xml(description)
-        ));
+        )?;
+    }
+
+    // Physical size and real-world scale, so slicers and users can verify the import size
+    let mm_per_unit = model_data.millimeters_per_unit();
+    if let Some(size) = model_data.model_size_mm.filter(|_| mm_per_unit.is_some()) {
+        writeln!(
+            out,
+            r#"  <metadata name="stlmaps:ModelSizeMillimeters">{}</metadata>"#,
+            size
+        )?;
+    }
+    if let Some(scale) = model_data.real_world_scale() {
+        writeln!(
+            out,
+            r#"  <metadata name="stlmaps:RealWorldScale">1:{}</metadata>"#,
+            scale.round()
+        )?;
+    }
+
+    if model_data.reproducible && !model_data.meshes.is_empty() {
+        writeln!(
+            out,
+            r#"  <metadata name="stlmaps:ContentHash">{}</metadata>"#,
+            model_data.content_hash()
+        )?;
+    }
+
+    // Data attribution required by the tile licenses, and where the data came from
+    if let Some(provenance) = &model_data.provenance {
+        let stripped;
+        let provenance = if model_data.reproducible {
+            stripped = without_retrieval_times(provenance);
+            &stripped
+        } else {
+            provenance
+        };
+        if !provenance.attribution.is_empty() {
+            writeln!(
+                out,
+                r#"  <metadata name="stlmaps:Attribution">{}</metadata>"#,
+                escape_xml(&provenance.attribution.join("; "))
+            )?;
+        }
+        let json = serde_json::to_string(provenance).map_err(io::Error::other)?;
+        writeln!(
+            out,
+            r#"  <metadata name="stlmaps:Provenance">{}</metadata>"#,
+            escape_xml(&json)
+        )?;
     }
 
     // Resources
-    xml.push_str("  <resources>\n");
-
-    // Process each mesh
-    for (mesh_id, mesh) in model_data.meshes.iter().enumerate() {
-        let object_id = mesh_id + 1;
-
-        xml.push_str(&format!(
-            r#"    <object id="{}" type="model">
-      <mesh>
-        <vertices>
-"#,
-            object_id
-        ));
-
-        // Vertices
-        for i in (0..mesh.vertices.len()).step_by(3) {
-            if i + 2 < mesh.vertices.len() {
-                xml.push_str(&format!(
-                    r#"          <vertex x="{}" y="{}" z="{}"/>
-"#,
-                    mesh.vertices[i],
-                    mesh.vertices[i + 1],
-                    mesh.vertices[i + 2]
-                ));
-            }
+    writeln!(out, "  <resources>")?;
+    if !model_data.materials.is_empty() {
+        writeln!(out, r#"    <basematerials id="{}">"#, BASE_MATERIALS_ID)?;
+        for material in &model_data.materials {
+            writeln!(
+                out,
+                r#"      <base name="{}" displaycolor="{}"/>"#,
+                escape_xml(&material.name),
+                material.display_color()
+            )?;
         }
+        writeln!(out, "    </basematerials>")?;
+    }
+    Ok(())
+}
+
+// PrusaSlicer-style per-object extruder settings; nothing is written without extruders
+fn write_slicer_config<W: Write>(
+    zip: &mut ZipStreamWriter<W>,
+    level: u32,
+    assigned: &[(usize, usize)],
+    materials: &[Material3MF],
+) -> io::Result<()> {
+    let extruders: Vec<(usize, u32)> = assigned
+        .iter()
+        .filter_map(|(object_id, material)| {
+            materials[*material]
+                .extruder
+                .filter(|e| *e > 0)
+                .map(|e| (*object_id, e))
+        })
+        .collect();
+    if extruders.is_empty() {
+        return Ok(());
+    }
+
+    zip.write_entry(SLICER_CONFIG_PATH, level, |w| {
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, "<config>")?;
+        for (object_id, extruder) in &extruders {
+            writeln!(w, r#" <object id="{}" instances_count="1">"#, object_id)?;
+            writeln!(
+                w,
+                r#"  <metadata type="object" key="extruder" value="{}"/>"#,
+                extruder
+            )?;
+            writeln!(w, " </object>")?;
+        }
+        write!(w, "</config>")
+    })
+}
+
+// Property resource (base materials or color group) referenced by an object
+struct ObjectProperties<'a> {
+    pid: usize,
+    pindex: usize,
+    // Per-triangle indices into the resource, overriding `pindex`
+    triangles: Option<&'a [usize]>,
+}
 
-        xml.push_str("        </vertices>\n        <triangles>\n");
+// Per-triangle 0xRRGGBB colors plus the distinct colors in first-use order;
+// None when the mesh carries no usable per-vertex colors
+fn triangle_colors(
+    vertices: &[f32],
+    indices: &[u32],
+    colors: Option<&[f32]>,
+) -> Option<(Vec<usize>, Vec<u32>)> {
+    let vertex_count = vertices.len() / 3;
+    let colors = colors.filter(|c| vertex_count > 0 && !c.is_empty())?;
+    // three.js color attributes are RGB or RGBA
+    let item_size = colors.len() / vertex_count;
+    if !(3..=4).contains(&item_size) || colors.len() != item_size * vertex_count {
+        return None;
+    }
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u32;
+    let vertex_color = |i: u32| {
+        let c = &colors[i as usize * item_size..i as usize * item_size + 3];
+        (channel(c[0]) << 16) | (channel(c[1]) << 8) | channel(c[2])
+    };
+
+    let mut palette: Vec<u32> = Vec::new();
+    let mut palette_index: HashMap<u32, usize> = HashMap::new();
+    let mut per_triangle = Vec::with_capacity(indices.len() / 3);
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            vertex_color(triangle[0]),
+            vertex_color(triangle[1]),
+            vertex_color(triangle[2]),
+        ];
+        let color = if b == c { b } else { a };
+        let index = *palette_index.entry(color).or_insert_with(|| {
+            palette.push(color);
+            palette.len() - 1
+        });
+        per_triangle.push(index);
+    }
+    Some((per_triangle, palette))
+}
+
+// Writes the resources of one mesh according to the color grouping and returns the ids
+// of the objects to place in the build; `next_id` is the next free resource id.
+// Objects without a color group reference `material` from the base materials.
+#[allow(clippy::too_many_arguments)]
+fn write_mesh_resources<W: Write + ?Sized>(
+    out: &mut W,
+    next_id: &mut usize,
+    name: &str,
+    vertices: &[f32],
+    indices: &[u32],
+    colors: Option<&[f32]>,
+    grouping: ColorGrouping,
+    material: Option<usize>,
+) -> io::Result<Vec<usize>> {
+    let material_properties = material.map(|pindex| ObjectProperties {
+        pid: BASE_MATERIALS_ID,
+        pindex,
+        triangles: None,
+    });
+    let grouped = match grouping {
+        ColorGrouping::None => None,
+        _ => triangle_colors(vertices, indices, colors),
+    };
+    let Some((per_triangle, palette)) = grouped else {
+        let object_id = *next_id;
+        *next_id += 1;
+        write_mesh_object(
+            out,
+            object_id,
+            name,
+            vertices,
+            indices,
+            material_properties.as_ref(),
+        )?;
+        return Ok(vec![object_id]);
+    };
 
-        // Triangles
-        for i in (0..mesh.indices.len()).step_by(3) {
-            if i + 2 < mesh.indices.len() {
-                xml.push_str(&format!(
-                    r#"          <triangle v1="{}" v2="{}" v3="{}"/>
-"#,
-                    mesh.indices[i],
-                    mesh.indices[i + 1],
-                    mesh.indices[i + 2]
-                ));
+    match grouping {
+        ColorGrouping::ColorGroups => {
+            let group_id = *next_id;
+            writeln!(out, r#"    <m:colorgroup id="{}">"#, group_id)?;
+            for color in &palette {
+                writeln!(out, r##"      <m:color color="#{:06X}"/>"##, color)?;
             }
+            writeln!(out, "    </m:colorgroup>")?;
+
+            let object_id = group_id + 1;
+            *next_id += 2;
+            let properties = ObjectProperties {
+                pid: group_id,
+                pindex: per_triangle.first().copied().unwrap_or(0),
+                triangles: Some(&per_triangle),
+            };
+            write_mesh_object(out, object_id, name, vertices, indices, Some(&properties))?;
+            Ok(vec![object_id])
         }
+        _ => {
+            let mut object_ids = Vec::with_capacity(palette.len());
+            for (color_index, color) in palette.iter().enumerate() {
+                // Compact the vertex list to the triangles of this color
+                let mut remap: HashMap<u32, u32> = HashMap::new();
+                let mut sub_vertices: Vec<f32> = Vec::new();
+                let mut sub_indices: Vec<u32> = Vec::new();
+                for (triangle, _) in indices
+                    .chunks_exact(3)
+                    .zip(&per_triangle)
+                    .filter(|(_, c)| **c == color_index)
+                {
+                    for &i in triangle {
+                        let mapped = *remap.entry(i).or_insert_with(|| {
+                            let start = i as usize * 3;
+                            sub_vertices.extend_from_slice(&vertices[start..start + 3]);
+                            (sub_vertices.len() / 3 - 1) as u32
+                        });
+                        sub_indices.push(mapped);
+                    }
+                }
 
-        xml.push_str("        </triangles>\n      </mesh>\n    </object>\n");
+                let object_id = *next_id;
+                *next_id += 1;
+                let object_name = if palette.len() > 1 {
+                    format!("{} #{:06X}", name, color)
+                } else {
+                    name.to_string()
+                };
+                write_mesh_object(
+                    out,
+                    object_id,
+                    &object_name,
+                    &sub_vertices,
+                    &sub_indices,
+                    material_properties.as_ref(),
+                )?;
+                object_ids.push(object_id);
+            }
+            Ok(object_ids)
+        }
     }
+}
 
-    xml.push_str("  </resources>\n");
+// One <object> resource with its vertex and triangle lists
+fn write_mesh_object<W: Write + ?Sized>(
+    out: &mut W,
+    object_id: usize,
+    name: &str,
+    vertices: &[f32],
+    indices: &[u32],
+    properties: Option<&ObjectProperties>,
+) -> io::Result<()> {
+    match properties {
+        Some(properties) => writeln!(
+            out,
+            r#"    <object id="{}" type="model" name="{}" pid="{}" pindex="{}">"#,
+            object_id,
+            escape_xml(name),
+            properties.pid,
+            properties.pindex
+        )?,
+        None => writeln!(
+            out,
+            r#"    <object id="{}" type="model" name="{}">"#,
+            object_id,
+            escape_xml(name)
+        )?,
+    }
+    writeln!(out, "      <mesh>\n        <vertices>")?;
+
+    // Vertices
+    for vertex in vertices.chunks_exact(3) {
+        writeln!(
+            out,
+            r#"          <vertex x="{}" y="{}" z="{}"/>"#,
+            vertex[0], vertex[1], vertex[2]
+        )?;
+    }
+
+    writeln!(out, "        </vertices>\n        <triangles>")?;
+
+    // Triangles
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        match properties
+            .and_then(|p| p.triangles)
+            .and_then(|per_triangle| per_triangle.get(t))
+        {
+            Some(p1) => writeln!(
+                out,
+                r#"          <triangle v1="{}" v2="{}" v3="{}" p1="{}"/>"#,
+                triangle[0], triangle[1], triangle[2], p1
+            )?,
+            None => writeln!(
+                out,
+                r#"          <triangle v1="{}" v2="{}" v3="{}"/>"#,
+                triangle[0], triangle[1], triangle[2]
+            )?,
+        }
+    }
+
+    writeln!(out, "        </triangles>\n      </mesh>\n    </object>")
+}
+
+// Closing </resources> plus the build section with one item per (object id, transform)
+fn write_model_build<W: Write + ?Sized>(
+    out: &mut W,
+    items: &[(usize, Option<&[f64]>)],
+    mm_per_unit: Option<f64>,
+) -> io::Result<()> {
+    writeln!(out, "  </resources>")?;
 
     // Build section - use a simple build approach
-    xml.push_str("  <build>\n");
+    writeln!(out, "  <build>")?;
+
+    // Add all objects to the build, carrying the per-mesh transform and model size scale
+    for (object_id, transform) in items {
+        match build_item_transform(*transform, mm_per_unit) {
+            Some(transform) => writeln!(
+                out,
+                r#"    <item objectid="{}" transform="{}"/>"#,
+                object_id, transform
+            )?,
+            None => writeln!(out, r#"    <item objectid="{}"/>"#, object_id)?,
+        }
+    }
+
+    write!(out, "  </build>\n</model>")
+}
+
+// Write a complete 3MF package (content types, relationships and model) as a ZIP stream
+pub(crate) fn write_3mf_archive<W: Write>(model_data: &Model3MFData, out: W) -> io::Result<W> {
+    let level = model_data.archive_compression_level();
+    let mut zip = ZipStreamWriter::new(out);
+    write_package_parts(&mut zip, level)?;
+    let mut assigned = Vec::new();
+    zip.write_entry("3D/3dmodel.model", level, |w| {
+        assigned = write_model_xml(model_data, w)?;
+        Ok(())
+    })?;
+    write_slicer_config(&mut zip, level, &assigned, &model_data.materials)?;
+    zip.finish()
+}
+
+// Content types and relationships entries shared by all archive exports
+fn write_package_parts<W: Write>(zip: &mut ZipStreamWriter<W>, level: u32) -> io::Result<()> {
+    zip.write_entry("[Content_Types].xml", level, |w| {
+        w.write_all(create_content_types_xml().as_bytes())
+    })?;
+    zip.write_entry("_rels/.rels", level, |w| {
+        w.write_all(create_rels_xml().as_bytes())
+    })
+}
+
+// Archive writer that keeps the model entry open while meshes are appended;
+// only the build items (object id and transform) are retained for the build section
+struct Incremental3MFWriter<W: Write> {
+    zip: ZipStreamWriter<W>,
+    level: u32,
+    items: Vec<(usize, Option<Vec<f64>>)>,
+    assigned: Vec<(usize, usize)>,
+    next_id: usize,
+    mm_per_unit: Option<f64>,
+    color_grouping: ColorGrouping,
+    assignment: MaterialAssignment,
+    repair: Option<RepairOptions>,
+}
+
+impl<W: Write> Incremental3MFWriter<W> {
+    fn new(model_data: &Model3MFData, out: W) -> io::Result<Self> {
+        let level = model_data.archive_compression_level();
+        let mut zip = ZipStreamWriter::new(out);
+        write_package_parts(&mut zip, level)?;
+        zip.start_entry("3D/3dmodel.model", level)?;
+        write_model_header(model_data, &mut zip)?;
+
+        let assignment = model_data.material_assignment();
+        Ok(Self {
+            zip,
+            level,
+            items: Vec::new(),
+            assigned: Vec::new(),
+            next_id: assignment.first_object_id(),
+            mm_per_unit: model_data.millimeters_per_unit(),
+            color_grouping: model_data.color_grouping,
+            assignment,
+            repair: model_data.repair.clone(),
+        })
+    }
+
+    // Returns the id of the first object written for the mesh
+    fn add_mesh(
+        &mut self,
+        name: Option<&str>,
+        vertices: &[f32],
+        indices: &[u32],
+        colors: Option<&[f32]>,
+        transform: Option<Vec<f64>>,
+    ) -> io::Result<usize> {
+        let name = object_name(name, self.next_id);
+        let repaired = repair_for_export(self.repair.as_ref(), &name, vertices, indices, colors)?;
+        let (vertices, indices, colors) = match &repaired {
+            Some(r) => (&r.vertices[..], &r.indices[..], r.colors.as_deref()),
+            None => (vertices, indices, colors),
+        };
+        require_exportable_mesh(&name, vertices, indices)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let material = self.assignment.material_for(&name);
+        let object_ids = write_mesh_resources(
+            &mut self.zip,
+            &mut self.next_id,
+            &name,
+            vertices,
+            indices,
+            colors,
+            self.color_grouping,
+            material,
+        )?;
+        if let Some(material) = material {
+            self.assigned
+                .extend(object_ids.iter().map(|id| (*id, material)));
+        }
+        let first_id = object_ids[0];
+        self.items
+            .extend(object_ids.into_iter().map(|id| (id, transform.clone())));
+        Ok(first_id)
+    }
 
-    // Add all objects to the build directly (3MF viewers should handle positioning correctly)
-    for mesh_id in 0..model_data.meshes.len() {
-        let object_id = mesh_id + 1;
-        xml.push_str(&format!(
-            r#"    <item objectid="{}"/>
-"#,
-            object_id
-        ));
+    fn finish(mut self) -> io::Result<W> {
+        let items: Vec<(usize, Option<&[f64]>)> = self
+            .items
+            .iter()
+            .map(|(id, transform)| (*id, transform.as_deref()))
+            .collect();
+        write_model_build(&mut self.zip, &items, self.mm_per_unit)?;
+        self.zip.end_entry()?;
+        write_slicer_config(
+            &mut self.zip,
+            self.level,
+            &self.assigned,
+            &self.assignment.materials,
+        )?;
+        self.zip.finish()
     }
+}
 
-    xml.push_str("  </build>\n</model>");
+// Build a 3MF item transform ("m00 m01 m02 m10 m11 m12 m20 m21 m22 m30 m31 m32").
+// `transform` is a column-major 4x4 matrix (three.js Matrix4.elements layout); its upper
+// 3x4 part maps directly onto the 3MF row-vector convention. The uniform model scale is
+// applied after the mesh transform. Returns None when the result is the identity.
+fn build_item_transform(transform: Option<&[f64]>, scale: Option<f64>) -> Option<String> {
+    let base: [f64; 12] = match transform.filter(|m| m.len() == 16) {
+        Some(m) => [
+            m[0], m[1], m[2], m[4], m[5], m[6], m[8], m[9], m[10], m[12], m[13], m[14],
+        ],
+        None => [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+    };
+    let scale = scale.unwrap_or(1.0);
+    if transform.is_none() && scale == 1.0 {
+        return None;
+    }
 
-    Ok(xml)
+    let values: Vec<String> = base.iter().map(|v| format!("{}", v * scale)).collect();
+    Some(values.join(" "))
 }
 
 fn escape_xml(text: &str) -> String {
@@ -154,3 +984,249 @@ fn escape_xml(text: &str) -> String {
 }
 
 // Removed unused helper functions for now
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model_with_size(model_size_mm: Option<f64>, bbox: Option<Vec<f64>>) -> Model3MFData {
+        Model3MFData {
+            meshes: vec![Mesh3MFData {
+                vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+                indices: vec![0, 1, 2],
+                colors: None,
+                name: Some("terrain".to_string()),
+                transform: None,
+            }],
+            title: None,
+            description: None,
+            model_size_mm,
+            process_id: None,
+            bbox,
+            compression_level: None,
+            layer_order: None,
+            color_grouping: ColorGrouping::None,
+            materials: Vec::new(),
+            layer_materials: HashMap::new(),
+            provenance: None,
+            repair: None,
+            reproducible: false,
+        }
+    }
+
+    fn named_mesh(name: Option<&str>) -> Mesh3MFData {
+        Mesh3MFData {
+            vertices: vec![0.0; 9],
+            indices: vec![0, 1, 2],
+            colors: None,
+            name: name.map(str::to_string),
+            transform: None,
+        }
+    }
+
+    #[test]
+    fn test_model_size_scales_build_items() {
+        let xml = create_model_xml(&model_with_size(Some(100.0), None)).unwrap();
+        assert!(xml.contains(r#"unit="millimeter""#));
+        assert!(xml.contains(r#"<item objectid="1" transform="0.5 0 0 0 0.5 0 0 0 0.5 0 0 0"/>"#));
+        assert!(xml.contains(r#"<metadata name="stlmaps:ModelSizeMillimeters">100</metadata>"#));
+        assert!(!xml.contains("stlmaps:RealWorldScale"));
+    }
+
+    #[test]
+    fn test_real_world_scale_metadata() {
+        // ~1.1km square at the equator printed at 110mm is roughly 1:10000
+        let model = model_with_size(Some(110.0), Some(vec![0.0, 0.0, 0.01, 0.01]));
+        let scale = model.real_world_scale().unwrap();
+        assert!((scale - 10_108.0).abs() < 10.0);
+        let xml = create_model_xml(&model).unwrap();
+        assert!(xml.contains("<metadata name=\"stlmaps:RealWorldScale\">1:"));
+    }
+
+    #[test]
+    fn test_model_size_uses_process_terrain_scale() {
+        // The exporting thread's active scale stays the default; the process's size counts
+        crate::projection::store_terrain_scale(
+            "export-3mf-scale-test",
+            crate::projection::TerrainScale {
+                size: 100.0,
+                model_scale: None,
+            },
+        );
+        let mut model = model_with_size(Some(110.0), Some(vec![0.0, 0.0, 0.01, 0.01]));
+        model.process_id = Some("export-3mf-scale-test".to_string());
+        assert_eq!(model.millimeters_per_unit(), Some(1.1));
+        let scale = model.real_world_scale().unwrap();
+        let xml = create_model_xml(&model).unwrap();
+        crate::module_state::ModuleState::with_mut(|state| {
+            state.clear_process_data("export-3mf-scale-test")
+        });
+        assert!((scale - 10_108.0).abs() < 10.0);
+        assert!(xml.contains(r#"transform="1.1 0 0"#));
+    }
+
+    #[test]
+    fn test_archive_contains_model_entry() {
+        for level in [0, 6, 9] {
+            let mut model = model_with_size(Some(100.0), None);
+            model.compression_level = Some(level);
+            let archive = write_3mf_archive(&model, Vec::new()).unwrap();
+            let entries = crate::zip_writer::read_entries(&archive).unwrap();
+            let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(
+                names,
+                vec!["[Content_Types].xml", "_rels/.rels", "3D/3dmodel.model"]
+            );
+            assert_eq!(entries[2].1, create_model_xml(&model).unwrap().into_bytes());
+            let report = crate::export_validation::validate_3mf(&archive);
+            assert!(report.valid, "{:?}", report.errors);
+        }
+    }
+
+    #[test]
+    fn test_objects_named_and_ordered_terrain_first() {
+        let mut model = model_with_size(None, None);
+        model.meshes = vec![
+            named_mesh(Some("Roads")),
+            named_mesh(None),
+            named_mesh(Some("Buildings")),
+            named_mesh(Some("Terrain")),
+        ];
+        model.layer_order = Some(vec!["Buildings".to_string(), "Roads".to_string()]);
+
+        let xml = create_model_xml(&model).unwrap();
+        let position = |needle: &str| xml.find(needle).unwrap();
+        assert!(
+            position(r#"id="1" type="model" name="Terrain""#) < position(r#"name="Buildings""#)
+        );
+        assert!(position(r#"id="2" type="model" name="Buildings""#) < position(r#"name="Roads""#));
+        assert!(xml.contains(r#"id="3" type="model" name="Roads""#));
+        assert!(xml.contains(r#"id="4" type="model" name="Object 4""#));
+    }
+
+    #[test]
+    fn test_incremental_archive_matches_single_pass_export() {
+        let mut model = model_with_size(Some(100.0), None);
+        model.meshes.push(named_mesh(Some("Buildings")));
+        let expected = write_3mf_archive(&model, Vec::new()).unwrap();
+
+        let mut writer = Incremental3MFWriter::new(&model, Vec::new()).unwrap();
+        for mesh in &model.meshes {
+            writer
+                .add_mesh(
+                    mesh.name.as_deref(),
+                    &mesh.vertices,
+                    &mesh.indices,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        assert!(writer
+            .add_mesh(None, &[0.0; 3], &[0, 1, 2], None, None)
+            .is_err());
+        assert_eq!(writer.finish().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_color_grouping_modes() {
+        // Two triangles, red and blue
+        let mut mesh = named_mesh(Some("Layers"));
+        mesh.vertices = vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0];
+        mesh.indices = vec![0, 1, 2, 1, 3, 2];
+        mesh.colors = Some(vec![
+            1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
+        ]);
+        let mut model = model_with_size(None, None);
+        model.meshes = vec![mesh];
+
+        model.color_grouping = ColorGrouping::ColorGroups;
+        let xml = create_model_xml(&model).unwrap();
+        assert!(xml.contains(r##"<m:color color="#0000FF"/>"##));
+        assert!(xml.contains(r#"<object id="2" type="model" name="Layers" pid="1" pindex="0">"#));
+        assert!(xml.contains(r#"v1="0" v2="1" v3="2" p1="0""#));
+        assert!(xml.contains(r#"v1="1" v2="3" v3="2" p1="1""#));
+        assert!(xml.contains(r#"<item objectid="2"/>"#));
+
+        model.color_grouping = ColorGrouping::Objects;
+        let archive = write_3mf_archive(&model, Vec::new()).unwrap();
+        let report = crate::export_validation::validate_3mf(&archive);
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.object_count, 2);
+        let xml = create_model_xml(&model).unwrap();
+        assert!(xml.contains(r##"<object id="1" type="model" name="Layers #FF0000">"##));
+        assert!(xml.contains(r##"<object id="2" type="model" name="Layers #0000FF">"##));
+        assert!(xml.contains(r#"<item objectid="2"/>"#));
+    }
+
+    #[test]
+    fn test_layer_materials_and_extruders() {
+        let mut model = model_with_size(None, None);
+        model.meshes.push(named_mesh(Some("water")));
+        model.materials = vec![
+            Material3MF {
+                name: "Brown PLA".to_string(),
+                color: Some("#8B5A2B".to_string()),
+                extruder: Some(1),
+            },
+            Material3MF {
+                name: "Clear PETG".to_string(),
+                color: Some("not a color".to_string()),
+                extruder: Some(2),
+            },
+        ];
+        model.layer_materials =
+            HashMap::from([("Terrain".to_string(), 0), ("water".to_string(), 1)]);
+
+        let xml = create_model_xml(&model).unwrap();
+        assert!(xml.contains(r##"<base name="Clear PETG" displaycolor="#808080"/>"##));
+        assert!(xml.contains(r#"<object id="2" type="model" name="terrain" pid="1" pindex="0">"#));
+        assert!(xml.contains(r#"<object id="3" type="model" name="water" pid="1" pindex="1">"#));
+
+        let archive = write_3mf_archive(&model, Vec::new()).unwrap();
+        let entries = crate::zip_writer::read_entries(&archive).unwrap();
+        let config = entries
+            .iter()
+            .find(|(name, _)| name == SLICER_CONFIG_PATH)
+            .map(|(_, data)| String::from_utf8_lossy(data).to_string())
+            .unwrap();
+        assert!(config.contains(r#"<object id="3" instances_count="1">"#));
+        assert!(config.contains(r#"key="extruder" value="2""#));
+        let report = crate::export_validation::validate_3mf(&archive);
+        assert!(report.valid, "{:?}", report.errors);
+    }
+
+    #[test]
+    fn test_no_model_size_keeps_plain_items() {
+        let xml = create_model_xml(&model_with_size(None, None)).unwrap();
+        assert!(xml.contains(r#"<item objectid="1"/>"#));
+    }
+
+    #[test]
+    fn test_reproducible_archive_drops_retrieval_times() {
+        let provenance = Provenance {
+            attribution: vec!["© OpenStreetMap contributors".to_string()],
+            tiles: vec![crate::provenance::TileProvenance {
+                source: "vector".to_string(),
+                url: "https://example.com/14/1/2.pbf".to_string(),
+                zoom: 14,
+                retrieved_at: Some(1_700_000_000_000.0),
+            }],
+        };
+        let mut model = model_with_size(None, None);
+        model.provenance = Some(provenance);
+        assert!(create_model_xml(&model).unwrap().contains("retrievedAt&quot;:17"));
+        assert!(!create_model_xml(&model).unwrap().contains("ContentHash"));
+
+        model.reproducible = true;
+        let first = write_3mf_archive(&model, Vec::new()).unwrap();
+        model.provenance.as_mut().unwrap().tiles[0].retrieved_at = Some(1_800_000_000_000.0);
+        assert_eq!(write_3mf_archive(&model, Vec::new()).unwrap(), first);
+        let xml = create_model_xml(&model).unwrap();
+        assert!(xml.contains("retrievedAt&quot;:null"));
+        assert!(xml.contains(&format!(
+            r#"<metadata name="stlmaps:ContentHash">{}</metadata>"#,
+            model.content_hash()
+        )));
+    }
+}
@@ -0,0 +1,517 @@
+// GLB export of the generated scene straight from WASM. Every layer becomes a named node
+// whose children carry its meshes; the layer's color becomes a PBR base color and
+// per-vertex colors are kept as COLOR_0. All vertex data goes into the single binary
+// chunk of the .glb, so the asset is self-contained.
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::export_3mf::millimeters_per_unit;
+use crate::export_validation::require_exportable_mesh;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: &[u8; 4] = b"JSON";
+const CHUNK_BIN: &[u8; 4] = b"BIN\0";
+// Accessor component types and buffer view targets
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+// Mesh name used by the app for the terrain base; always exported as the first layer
+const TERRAIN_LAYER_NAME: &str = "terrain";
+const DEFAULT_LAYER_NAME: &str = "mesh";
+const DEFAULT_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+#[derive(Deserialize)]
+pub struct GltfMeshData {
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+    /// Per-vertex RGB or RGBA colors in 0..1
+    #[serde(default)]
+    pub colors: Option<Vec<f32>>,
+    #[serde(default)]
+    pub normals: Option<Vec<f32>>,
+    /// Layer label; meshes with the same name are grouped under one layer node
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Layer color "#RRGGBB"
+    #[serde(default)]
+    pub color: Option<String>,
+    /// 4x4 column-major transform (three.js Matrix4.elements layout)
+    #[serde(default)]
+    pub transform: Option<Vec<f64>>,
+    /// Meshes with the same id share one geometry, so its accessors and buffer views are
+    /// written once
+    #[serde(default, rename = "geometryId")]
+    pub geometry_id: Option<String>,
+}
+
+/// Provenance of a layer, written to the `extras` of its node
+#[derive(Deserialize, Default)]
+pub struct GltfLayerInfo {
+    #[serde(default, rename = "sourceLayer")]
+    pub source_layer: Option<String>,
+    #[serde(default, rename = "featureCount")]
+    pub feature_count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct GltfSceneData {
+    #[serde(default)]
+    pub meshes: Vec<GltfMeshData>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Layer labels in configuration order; layers follow the terrain in this order
+    #[serde(default, rename = "layerOrder")]
+    pub layer_order: Option<Vec<String>>,
+    /// Layer label → provenance of that layer
+    #[serde(default)]
+    pub layers: HashMap<String, GltfLayerInfo>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct GltfExportOptions {
+    /// Physical size in millimeters of the longest model side; the asset is then in meters
+    /// at print size instead of mesh units
+    #[serde(default, rename = "modelSizeMm")]
+    pub model_size_mm: Option<f64>,
+    /// Process the scene was generated in, whose terrain scale sizes the model
+    #[serde(default, rename = "processId")]
+    pub process_id: Option<String>,
+    /// Keep the mesh Z-up instead of rotating it into glTF's Y-up convention
+    #[serde(default, rename = "zUp")]
+    pub z_up: bool,
+    /// Drop per-vertex colors and keep only layer colors
+    #[serde(default, rename = "skipVertexColors")]
+    pub skip_vertex_colors: bool,
+}
+
+/// "#RRGGBB" as linear RGB, as glTF base colors are linear
+fn linear_color(hex: Option<&str>) -> [f32; 3] {
+    let parsed = hex
+        .and_then(|c| c.strip_prefix('#'))
+        .filter(|c| c.len() >= 6)
+        .and_then(|c| u32::from_str_radix(&c[..6], 16).ok());
+    match parsed {
+        Some(rgb) => [16, 8, 0].map(|shift| {
+            let srgb = ((rgb >> shift) & 0xff) as f32 / 255.0;
+            if srgb <= 0.04045 {
+                srgb / 12.92
+            } else {
+                ((srgb + 0.055) / 1.055).powf(2.4)
+            }
+        }),
+        None => DEFAULT_COLOR,
+    }
+}
+
+/// Binary chunk plus the buffer views and accessors that point into it
+#[derive(Default)]
+struct BufferBuilder {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl BufferBuilder {
+    /// Append a float attribute of `components` per element; returns its accessor index
+    fn float_attribute(&mut self, data: &[f32], components: usize, with_bounds: bool) -> usize {
+        let kind = match components {
+            4 => "VEC4",
+            _ => "VEC3",
+        };
+        let mut accessor = json!({
+            "bufferView": self.push_view(bytemuck::cast_slice(data), ARRAY_BUFFER),
+            "componentType": FLOAT,
+            "count": data.len() / components,
+            "type": kind,
+        });
+        // POSITION accessors must declare their bounds
+        if with_bounds {
+            let (mut min, mut max) = (
+                vec![f32::INFINITY; components],
+                vec![f32::NEG_INFINITY; components],
+            );
+            for element in data.chunks_exact(components) {
+                for (axis, value) in element.iter().enumerate() {
+                    min[axis] = min[axis].min(*value);
+                    max[axis] = max[axis].max(*value);
+                }
+            }
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn index_accessor(&mut self, indices: &[u32]) -> usize {
+        let view = self.push_view(bytemuck::cast_slice(indices), ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        // Float and u32 data keep 4-byte alignment as long as every view is padded
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.views.len() - 1
+    }
+}
+
+/// Layer names in export order: terrain first, then `layer_order`, then first appearance
+pub(crate) fn layer_names(scene: &GltfSceneData) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for mesh in &scene.meshes {
+        let name = mesh_layer_name(mesh);
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    let rank = |name: &str| -> usize {
+        if name.eq_ignore_ascii_case(TERRAIN_LAYER_NAME) {
+            return 0;
+        }
+        scene
+            .layer_order
+            .as_ref()
+            .and_then(|order| order.iter().position(|label| label == name))
+            .map(|position| position + 1)
+            .unwrap_or(usize::MAX)
+    };
+    // Stable sort keeps the input order among unlisted layers
+    names.sort_by_key(|name| rank(name));
+    names
+}
+
+pub(crate) fn mesh_layer_name(mesh: &GltfMeshData) -> &str {
+    mesh.name
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or(DEFAULT_LAYER_NAME)
+}
+
+/// Write the vertex attributes and indices of a mesh; returns the primitive's attributes
+/// object and index accessor
+fn write_primitive(
+    buffer: &mut BufferBuilder,
+    mesh: &GltfMeshData,
+    options: &GltfExportOptions,
+) -> (Value, usize) {
+    let vertex_count = mesh.vertices.len() / 3;
+
+    let mut attributes = json!({
+        "POSITION": buffer.float_attribute(&mesh.vertices, 3, true),
+    });
+    if let Some(normals) = mesh
+        .normals
+        .as_deref()
+        .filter(|n| n.len() == mesh.vertices.len())
+    {
+        attributes["NORMAL"] = json!(buffer.float_attribute(normals, 3, false));
+    }
+    let colors = mesh
+        .colors
+        .as_deref()
+        .filter(|_| !options.skip_vertex_colors && vertex_count > 0)
+        .filter(|c| c.len() == vertex_count * 3 || c.len() == vertex_count * 4);
+    if let Some(colors) = colors {
+        attributes["COLOR_0"] =
+            json!(buffer.float_attribute(colors, colors.len() / vertex_count, false));
+    }
+
+    (attributes, buffer.index_accessor(&mesh.indices))
+}
+
+/// glTF JSON document and binary chunk for a scene
+fn build_gltf(
+    scene: &GltfSceneData,
+    options: &GltfExportOptions,
+) -> Result<(Value, Vec<u8>), String> {
+    let mut buffer = BufferBuilder::default();
+    let mut nodes: Vec<Value> = Vec::new();
+    let mut meshes: Vec<Value> = Vec::new();
+    let mut materials: Vec<Value> = Vec::new();
+    let mut layer_nodes: Vec<usize> = Vec::new();
+    // Geometry id → primitive attributes and index accessor already written for it
+    let mut shared: HashMap<&str, (Value, usize)> = HashMap::new();
+
+    for layer in layer_names(scene) {
+        let layer_meshes: Vec<&GltfMeshData> = scene
+            .meshes
+            .iter()
+            .filter(|mesh| mesh_layer_name(mesh) == layer && !mesh.indices.is_empty())
+            .collect();
+        if layer_meshes.is_empty() {
+            continue;
+        }
+
+        let layer_color = layer_meshes.iter().find_map(|mesh| mesh.color.as_deref());
+        let [r, g, b] = linear_color(layer_color);
+        materials.push(json!({
+            "name": layer,
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [r, g, b, 1.0],
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+        }));
+        let material = materials.len() - 1;
+
+        let mut children = Vec::with_capacity(layer_meshes.len());
+        for (index, mesh) in layer_meshes.iter().enumerate() {
+            let name = format!("{}_{}", layer, index);
+            require_exportable_mesh(&name, &mesh.vertices, &mesh.indices)?;
+            let (attributes, indices) =
+                match mesh.geometry_id.as_deref().and_then(|id| shared.get(id)) {
+                    Some(primitive) => primitive.clone(),
+                    None => {
+                        let primitive = write_primitive(&mut buffer, mesh, options);
+                        if let Some(id) = mesh.geometry_id.as_deref() {
+                            shared.insert(id, primitive.clone());
+                        }
+                        primitive
+                    }
+                };
+
+            meshes.push(json!({
+                "name": name,
+                "primitives": [{
+                    "attributes": attributes,
+                    "indices": indices,
+                    "material": material,
+                }],
+            }));
+            let mut node = json!({ "name": name, "mesh": meshes.len() - 1 });
+            if let Some(matrix) = mesh.transform.as_deref().filter(|m| m.len() == 16) {
+                node["matrix"] = json!(matrix);
+            }
+            nodes.push(node);
+            children.push(nodes.len() - 1);
+        }
+
+        let mut extras = json!({ "label": layer, "meshCount": layer_meshes.len() });
+        if let Some(color) = layer_color {
+            extras["color"] = json!(color);
+        }
+        if let Some(info) = scene.layers.get(&layer) {
+            if let Some(source_layer) = &info.source_layer {
+                extras["sourceLayer"] = json!(source_layer);
+            }
+            if let Some(feature_count) = info.feature_count {
+                extras["featureCount"] = json!(feature_count);
+            }
+        }
+        nodes.push(json!({ "name": layer, "children": children, "extras": extras }));
+        layer_nodes.push(nodes.len() - 1);
+    }
+    if layer_nodes.is_empty() {
+        return Err("Nothing to export: no meshes with triangles".to_string());
+    }
+
+    let mut root = json!({
+        "name": scene.title.as_deref().unwrap_or("STLMaps Model"),
+        "children": layer_nodes,
+    });
+    if !options.z_up {
+        // Z-up mesh space to glTF Y-up: -90° about X
+        root["rotation"] = json!([
+            -std::f64::consts::FRAC_1_SQRT_2,
+            0.0,
+            0.0,
+            std::f64::consts::FRAC_1_SQRT_2
+        ]);
+    }
+    if let Some(mm_per_unit) =
+        millimeters_per_unit(options.model_size_mm, options.process_id.as_deref())
+    {
+        let meters = mm_per_unit / 1000.0;
+        root["scale"] = json!([meters, meters, meters]);
+    }
+    nodes.push(root);
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "STLMaps" },
+        "scene": 0,
+        "scenes": [{ "name": scene.title.as_deref().unwrap_or("STLMaps Model"), "nodes": [nodes.len() - 1] }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "accessors": buffer.accessors,
+        "bufferViews": buffer.views,
+        "buffers": [{ "byteLength": buffer.bin.len() }],
+    });
+    Ok((document, buffer.bin))
+}
+
+fn write_chunk(glb: &mut Vec<u8>, kind: &[u8; 4], data: &[u8], padding: u8) {
+    let padded = data.len().next_multiple_of(4);
+    glb.extend_from_slice(&(padded as u32).to_le_bytes());
+    glb.extend_from_slice(kind);
+    glb.extend_from_slice(data);
+    glb.resize(glb.len() + padded - data.len(), padding);
+}
+
+/// Binary glTF container with the JSON chunk padded by spaces and the BIN chunk by zeros
+pub(crate) fn write_glb(
+    scene: &GltfSceneData,
+    options: &GltfExportOptions,
+) -> Result<Vec<u8>, String> {
+    let (document, bin) = build_gltf(scene, options)?;
+    let json = serde_json::to_vec(&document).map_err(|e| e.to_string())?;
+
+    let mut glb = Vec::with_capacity(12 + 8 + json.len() + 3 + 8 + bin.len());
+    glb.extend_from_slice(GLB_MAGIC);
+    glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+    // Total length, filled in below
+    glb.extend_from_slice(&0u32.to_le_bytes());
+    write_chunk(&mut glb, CHUNK_JSON, &json, b' ');
+    write_chunk(&mut glb, CHUNK_BIN, &bin, 0);
+    let length = glb.len() as u32;
+    glb[8..12].copy_from_slice(&length.to_le_bytes());
+    Ok(glb)
+}
+
+/// Serialize the terrain and layer meshes into a self-contained .glb.
+/// `scene_json` is `{ meshes: [{ vertices, indices, colors?, normals?, name?, color?,
+/// transform?, geometryId? }], title?, layerOrder?, layers? }`; meshes sharing a name become
+/// one layer node, whose extras take `sourceLayer` and `featureCount` from `layers[name]`.
+/// `options_json` is `{ modelSizeMm?, zUp?, skipVertexColors? }`.
+#[wasm_bindgen]
+pub fn export_gltf(scene_json: &str, options_json: Option<String>) -> Result<Vec<u8>, JsValue> {
+    let scene: GltfSceneData = serde_json::from_str(scene_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse scene: {}", e)))?;
+    let options: GltfExportOptions = match options_json.as_deref() {
+        Some(json) if !json.trim().is_empty() => serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse options: {}", e)))?,
+        _ => GltfExportOptions::default(),
+    };
+    write_glb(&scene, &options)
+        .map_err(|e| JsValue::from_str(&format!("Failed to create GLB: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(name: &str, color: Option<&str>, colors: Option<Vec<f32>>) -> GltfMeshData {
+        GltfMeshData {
+            vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 2.0],
+            indices: vec![0, 1, 2],
+            colors,
+            normals: None,
+            name: Some(name.to_string()),
+            color: color.map(str::to_string),
+            transform: None,
+            geometry_id: None,
+        }
+    }
+
+    #[test]
+    fn test_glb_layout_and_layer_nodes() {
+        let scene = GltfSceneData {
+            meshes: vec![
+                triangle("buildings", Some("#ff0000"), None),
+                triangle("terrain", None, Some(vec![1.0; 9])),
+                triangle("buildings", Some("#ff0000"), None),
+            ],
+            title: None,
+            layer_order: None,
+            layers: HashMap::new(),
+        };
+        let glb = write_glb(&scene, &GltfExportOptions::default()).unwrap();
+
+        assert_eq!(&glb[..4], GLB_MAGIC);
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(&glb[16..20], CHUNK_JSON);
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        let bin_header = 20 + json_len;
+        let bin_len = u32::from_le_bytes(glb[bin_header..bin_header + 4].try_into().unwrap());
+        assert_eq!(&glb[bin_header + 4..bin_header + 8], CHUNK_BIN);
+        assert_eq!(
+            document["buffers"][0]["byteLength"].as_u64(),
+            Some(bin_len as u64)
+        );
+
+        // Root node with terrain first, then the buildings layer with both meshes
+        let nodes = document["nodes"].as_array().unwrap();
+        let root = &nodes[document["scenes"][0]["nodes"][0].as_u64().unwrap() as usize];
+        let layers: Vec<&Value> = root["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| &nodes[i.as_u64().unwrap() as usize])
+            .collect();
+        assert_eq!(layers[0]["name"], "terrain");
+        assert_eq!(layers[1]["name"], "buildings");
+        assert_eq!(layers[1]["extras"]["meshCount"], 2);
+        assert_eq!(layers[1]["extras"]["color"], "#ff0000");
+
+        let terrain_mesh = &document["meshes"][0]["primitives"][0];
+        assert!(terrain_mesh["attributes"]["COLOR_0"].is_number());
+        let position = &document["accessors"]
+            [terrain_mesh["attributes"]["POSITION"].as_u64().unwrap() as usize];
+        assert_eq!(position["max"], json!([1.0, 1.0, 2.0]));
+        assert_eq!(
+            document["materials"][1]["pbrMetallicRoughness"]["baseColorFactor"],
+            json!([1.0, 0.0, 0.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn test_shared_geometry_and_layer_extras() {
+        let shared = |x: f64| GltfMeshData {
+            geometry_id: Some("building".to_string()),
+            transform: Some(vec![
+                1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, x, 0.0, 0.0, 1.0,
+            ]),
+            ..triangle("buildings", Some("#ff0000"), None)
+        };
+        let scene = GltfSceneData {
+            meshes: vec![shared(0.0), shared(5.0)],
+            title: None,
+            layer_order: None,
+            layers: HashMap::from([(
+                "buildings".to_string(),
+                GltfLayerInfo {
+                    source_layer: Some("building".to_string()),
+                    feature_count: Some(12),
+                },
+            )]),
+        };
+        let (document, _) = build_gltf(&scene, &GltfExportOptions::default()).unwrap();
+
+        // Both meshes point at the same accessors; only positions and indices are written
+        let first = &document["meshes"][0]["primitives"][0];
+        let second = &document["meshes"][1]["primitives"][0];
+        assert_eq!(first["attributes"], second["attributes"]);
+        assert_eq!(first["indices"], second["indices"]);
+        assert_eq!(document["bufferViews"].as_array().unwrap().len(), 2);
+        assert_eq!(document["nodes"][1]["matrix"][12], 5.0);
+
+        let layer = &document["nodes"][2];
+        assert_eq!(layer["name"], "buildings");
+        assert_eq!(layer["extras"]["sourceLayer"], "building");
+        assert_eq!(layer["extras"]["featureCount"], 12);
+        assert_eq!(layer["extras"]["meshCount"], 2);
+    }
+}
@@ -0,0 +1,259 @@
+// OBJ + MTL export for tools without glTF support, e.g. Blender's stock importer. Takes the
+// same scene input as the GLB export; every layer becomes one `o`/`g` group using its own
+// material, whose diffuse color is the layer color.
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use wasm_bindgen::prelude::*;
+
+use crate::export_3mf::millimeters_per_unit;
+use crate::export_gltf::{layer_names, mesh_layer_name, GltfSceneData};
+use crate::export_stl::transform_point;
+use crate::export_validation::require_exportable_mesh;
+
+const DEFAULT_MTL_FILE: &str = "model.mtl";
+const DEFAULT_DIFFUSE: [f64; 3] = [0.5, 0.5, 0.5];
+
+#[derive(Deserialize, Default)]
+pub struct ObjExportOptions {
+    /// Physical size in millimeters of the longest model side; coordinates are then in mm
+    #[serde(default, rename = "modelSizeMm")]
+    pub model_size_mm: Option<f64>,
+    /// Process the scene was generated in, whose terrain scale sizes the model
+    #[serde(default, rename = "processId")]
+    pub process_id: Option<String>,
+    /// Keep the mesh Z-up instead of writing the Y-up axes OBJ importers expect
+    #[serde(default, rename = "zUp")]
+    pub z_up: bool,
+    /// Name the OBJ uses to reference the MTL file; defaults to "model.mtl"
+    #[serde(default, rename = "mtlFileName")]
+    pub mtl_file_name: Option<String>,
+    /// Drop per-vertex colors (written as the common `v x y z r g b` extension)
+    #[serde(default, rename = "skipVertexColors")]
+    pub skip_vertex_colors: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ObjExport {
+    pub obj: String,
+    pub mtl: String,
+    #[serde(rename = "mtlFileName")]
+    pub mtl_file_name: String,
+}
+
+/// Object and material names end at whitespace in OBJ/MTL
+fn obj_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// "#RRGGBB" as 0..1 RGB, which MTL readers treat as display color
+fn diffuse_color(hex: Option<&str>) -> [f64; 3] {
+    hex.and_then(|c| c.strip_prefix('#'))
+        .filter(|c| c.len() >= 6)
+        .and_then(|c| u32::from_str_radix(&c[..6], 16).ok())
+        .map(|rgb| [16, 8, 0].map(|shift| ((rgb >> shift) & 0xff) as f64 / 255.0))
+        .unwrap_or(DEFAULT_DIFFUSE)
+}
+
+pub(crate) fn write_obj(
+    scene: &GltfSceneData,
+    options: &ObjExportOptions,
+) -> Result<ObjExport, String> {
+    let scale =
+        millimeters_per_unit(options.model_size_mm, options.process_id.as_deref()).unwrap_or(1.0);
+    let mtl_file_name = options
+        .mtl_file_name
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or(DEFAULT_MTL_FILE)
+        .to_string();
+    // Z-up mesh space to Y-up: (x, y, z) -> (x, z, -y)
+    let axes = |[x, y, z]: [f64; 3]| {
+        if options.z_up {
+            [x, y, z]
+        } else {
+            [x, z, -y]
+        }
+    };
+
+    let mut obj = String::new();
+    let mut mtl = String::new();
+    let _ = writeln!(obj, "# STLMaps OBJ export");
+    if let Some(title) = scene.title.as_deref() {
+        let _ = writeln!(obj, "# {}", title.replace('\n', " "));
+    }
+    let _ = writeln!(obj, "mtllib {}", mtl_file_name);
+    let _ = writeln!(mtl, "# STLMaps materials");
+
+    // OBJ indices are 1-based and global across the file
+    let (mut vertex_base, mut normal_base) = (1usize, 1usize);
+    let mut groups = 0;
+    for layer in layer_names(scene) {
+        let meshes: Vec<_> = scene
+            .meshes
+            .iter()
+            .filter(|mesh| mesh_layer_name(mesh) == layer && !mesh.indices.is_empty())
+            .collect();
+        if meshes.is_empty() {
+            continue;
+        }
+        groups += 1;
+
+        let name = obj_name(&layer);
+        let [r, g, b] = diffuse_color(meshes.iter().find_map(|mesh| mesh.color.as_deref()));
+        let _ = writeln!(
+            mtl,
+            "\nnewmtl {}\nKa 0 0 0\nKd {:.6} {:.6} {:.6}\nKs 0 0 0\nd 1\nillum 1",
+            name, r, g, b
+        );
+        let _ = writeln!(obj, "\no {}\ng {}\nusemtl {}", name, name, name);
+
+        for (index, mesh) in meshes.iter().enumerate() {
+            require_exportable_mesh(
+                &format!("{}_{}", layer, index),
+                &mesh.vertices,
+                &mesh.indices,
+            )?;
+            let vertex_count = mesh.vertices.len() / 3;
+            let transform = mesh.transform.as_deref();
+            let colors = mesh
+                .colors
+                .as_deref()
+                .filter(|_| !options.skip_vertex_colors && vertex_count > 0)
+                .filter(|c| c.len() == vertex_count * 3 || c.len() == vertex_count * 4);
+            for (i, v) in mesh.vertices.chunks_exact(3).enumerate() {
+                let [x, y, z] = axes(transform_point(
+                    transform,
+                    [v[0], v[1], v[2]].map(f64::from),
+                ))
+                .map(|c| c * scale);
+                match colors {
+                    Some(colors) => {
+                        let item = colors.len() / vertex_count;
+                        let c = &colors[i * item..i * item + 3];
+                        let _ = writeln!(
+                            obj,
+                            "v {} {} {} {:.4} {:.4} {:.4}",
+                            x, y, z, c[0], c[1], c[2]
+                        );
+                    }
+                    None => {
+                        let _ = writeln!(obj, "v {} {} {}", x, y, z);
+                    }
+                }
+            }
+
+            // Normals only rotate with the transform; the translation part must not apply
+            let normals = mesh
+                .normals
+                .as_deref()
+                .filter(|n| n.len() == mesh.vertices.len());
+            if let Some(normals) = normals {
+                let origin = transform_point(transform, [0.0; 3]);
+                for n in normals.chunks_exact(3) {
+                    let moved = transform_point(transform, [n[0], n[1], n[2]].map(f64::from));
+                    let [x, y, z] = axes([0, 1, 2].map(|a| moved[a] - origin[a]));
+                    let length = (x * x + y * y + z * z).sqrt().max(f64::EPSILON);
+                    let _ = writeln!(obj, "vn {} {} {}", x / length, y / length, z / length);
+                }
+            }
+
+            for t in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| i as usize);
+                if normals.is_some() {
+                    let _ = writeln!(
+                        obj,
+                        "f {}//{} {}//{} {}//{}",
+                        vertex_base + a,
+                        normal_base + a,
+                        vertex_base + b,
+                        normal_base + b,
+                        vertex_base + c,
+                        normal_base + c
+                    );
+                } else {
+                    let _ = writeln!(
+                        obj,
+                        "f {} {} {}",
+                        vertex_base + a,
+                        vertex_base + b,
+                        vertex_base + c
+                    );
+                }
+            }
+            vertex_base += vertex_count;
+            if normals.is_some() {
+                normal_base += vertex_count;
+            }
+        }
+    }
+    if groups == 0 {
+        return Err("Nothing to export: no meshes with triangles".to_string());
+    }
+
+    Ok(ObjExport {
+        obj,
+        mtl,
+        mtl_file_name,
+    })
+}
+
+/// Export the terrain and layer meshes as OBJ text plus its MTL file. Takes the same
+/// `scene_json` as `export_gltf`; `options_json` is `{ modelSizeMm?, zUp?, mtlFileName?,
+/// skipVertexColors? }`. Returns `{ obj, mtl, mtlFileName }`; save the MTL under
+/// `mtlFileName` next to the OBJ so importers find the materials.
+#[wasm_bindgen]
+pub fn export_obj(scene_json: &str, options_json: Option<String>) -> Result<JsValue, JsValue> {
+    let scene: GltfSceneData = serde_json::from_str(scene_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse scene: {}", e)))?;
+    let options: ObjExportOptions = match options_json.as_deref() {
+        Some(json) if !json.trim().is_empty() => serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse options: {}", e)))?,
+        _ => ObjExportOptions::default(),
+    };
+    let export = write_obj(&scene, &options)
+        .map_err(|e| JsValue::from_str(&format!("Failed to create OBJ: {}", e)))?;
+    Ok(serde_wasm_bindgen::to_value(&export)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obj_groups_layers_with_materials() {
+        let scene: GltfSceneData = serde_json::from_str(
+            r##"{ "meshes": [
+                { "vertices": [0, 0, 0, 1, 0, 0, 0, 1, 0], "indices": [0, 1, 2],
+                  "name": "water areas", "color": "#0000ff" },
+                { "vertices": [0, 0, 0, 1, 0, 0, 0, 1, 2], "indices": [0, 1, 2],
+                  "name": "terrain" }
+            ] }"##,
+        )
+        .unwrap();
+        let export = write_obj(&scene, &ObjExportOptions::default()).unwrap();
+
+        let lines: Vec<&str> = export.obj.lines().collect();
+        assert!(lines.contains(&"mtllib model.mtl"));
+        let objects: Vec<&str> = lines
+            .iter()
+            .filter(|l| l.starts_with("o "))
+            .copied()
+            .collect();
+        assert_eq!(objects, vec!["o terrain", "o water_areas"]);
+        // Y-up: the terrain's (0, 1, 2) corner becomes (0, 2, -1)
+        assert!(lines.contains(&"v 0 2 -1"));
+        // Faces of the second group continue after the terrain's three vertices
+        let faces: Vec<&str> = lines
+            .iter()
+            .filter(|l| l.starts_with("f "))
+            .copied()
+            .collect();
+        assert_eq!(faces, vec!["f 1 2 3", "f 4 5 6"]);
+
+        assert!(export
+            .mtl
+            .contains("newmtl water_areas\nKa 0 0 0\nKd 0.000000 0.000000 1.000000"));
+        assert!(export.mtl.contains("newmtl terrain"));
+    }
+}
@@ -0,0 +1,204 @@
+// STL export of the same mesh input as the 3MF export. STL has no objects, transforms or
+// units, so meshes are baked in export order into one triangle list, in millimeters when a
+// model size is given. Meshes pass the same pre-export check as 3MF and the written file
+// is validated before it is returned.
+use std::fmt::Write as _;
+use wasm_bindgen::prelude::*;
+
+use crate::export_3mf::{Mesh3MFData, Model3MFData};
+use crate::export_validation::{require_exportable_mesh, validate_stl};
+
+const STL_HEADER_SIZE: usize = 80;
+// Binary headers must not start with "solid", which readers take as ASCII STL
+const BINARY_HEADER_PREFIX: &str = "STLMaps binary STL";
+const DEFAULT_SOLID_NAME: &str = "stlmaps";
+
+/// Apply a column-major 4x4 transform (three.js Matrix4.elements layout) to a point;
+/// anything but 16 values leaves it unchanged
+pub(crate) fn transform_point(matrix: Option<&[f64]>, [x, y, z]: [f64; 3]) -> [f64; 3] {
+    match matrix.filter(|m| m.len() == 16) {
+        Some(m) => [
+            m[0] * x + m[4] * y + m[8] * z + m[12],
+            m[1] * x + m[5] * y + m[9] * z + m[13],
+            m[2] * x + m[6] * y + m[10] * z + m[14],
+        ],
+        None => [x, y, z],
+    }
+}
+
+/// Triangle corners of a mesh with its transform and the model size scale applied
+fn baked_triangles(mesh: &Mesh3MFData, scale: f64) -> Vec<[[f32; 3]; 3]> {
+    let point = |i: u32| -> [f32; 3] {
+        let v = &mesh.vertices[i as usize * 3..i as usize * 3 + 3];
+        let p = transform_point(mesh.transform.as_deref(), [v[0], v[1], v[2]].map(f64::from));
+        p.map(|c| (c * scale) as f32)
+    };
+    mesh.indices
+        .chunks_exact(3)
+        .map(|t| [point(t[0]), point(t[1]), point(t[2])])
+        .collect()
+}
+
+/// Unit facet normal from the winding; zero for degenerate triangles
+fn facet_normal([a, b, c]: &[[f32; 3]; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        n.map(|c| c / length)
+    } else {
+        [0.0; 3]
+    }
+}
+
+fn collect_triangles(model: &Model3MFData) -> Result<Vec<[[f32; 3]; 3]>, String> {
+    let scale = model.millimeters_per_unit().unwrap_or(1.0);
+    let mut triangles = Vec::new();
+    for (index, mesh) in model.ordered_meshes().into_iter().enumerate() {
+        let name = mesh.object_name(index + 1);
+        require_exportable_mesh(&name, &mesh.vertices, &mesh.indices)?;
+        triangles.extend(baked_triangles(mesh, scale));
+    }
+    if triangles.is_empty() {
+        return Err("Nothing to export: no triangles".to_string());
+    }
+    Ok(triangles)
+}
+
+pub(crate) fn write_binary_stl(model: &Model3MFData) -> Result<Vec<u8>, String> {
+    let triangles = collect_triangles(model)?;
+    let mut header = match model.title.as_deref().map(str::trim) {
+        Some(title) if !title.is_empty() => format!("{}: {}", BINARY_HEADER_PREFIX, title),
+        _ => BINARY_HEADER_PREFIX.to_string(),
+    }
+    .into_bytes();
+    header.resize(STL_HEADER_SIZE, b' ');
+
+    let mut stl = Vec::with_capacity(STL_HEADER_SIZE + 4 + triangles.len() * 50);
+    stl.extend_from_slice(&header);
+    stl.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for triangle in &triangles {
+        for value in facet_normal(triangle)
+            .iter()
+            .chain(triangle.iter().flatten())
+        {
+            stl.extend_from_slice(&value.to_le_bytes());
+        }
+        // Attribute byte count
+        stl.extend_from_slice(&[0, 0]);
+    }
+    Ok(stl)
+}
+
+pub(crate) fn write_ascii_stl(model: &Model3MFData) -> Result<String, String> {
+    let triangles = collect_triangles(model)?;
+    // The solid name runs to the end of the line and must not contain whitespace breaks
+    let name = model
+        .title
+        .as_deref()
+        .map(|t| t.split_whitespace().collect::<Vec<_>>().join("_"))
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| DEFAULT_SOLID_NAME.to_string());
+
+    let mut stl = String::with_capacity(triangles.len() * 256);
+    let _ = writeln!(stl, "solid {}", name);
+    for triangle in &triangles {
+        let [nx, ny, nz] = facet_normal(triangle);
+        let _ = writeln!(
+            stl,
+            "  facet normal {:e} {:e} {:e}\n    outer loop",
+            nx, ny, nz
+        );
+        for [x, y, z] in triangle {
+            let _ = writeln!(stl, "      vertex {:e} {:e} {:e}", x, y, z);
+        }
+        let _ = writeln!(stl, "    endloop\n  endfacet");
+    }
+    let _ = writeln!(stl, "endsolid {}", name);
+    Ok(stl)
+}
+
+/// Export meshes as STL bytes, binary or (with `ascii`) ASCII. Takes the same input JSON as
+/// `generate_3mf_archive` (`meshes`, `modelSizeMm`, `layerOrder`, `title`); transforms and
+/// the model size scale are baked into the vertices. Meshes that fail the export check and
+/// files that fail `validate_export` are rejected with an error.
+#[wasm_bindgen]
+pub fn export_stl(input_json: &str, ascii: bool) -> Result<Vec<u8>, JsValue> {
+    let model: Model3MFData = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+
+    let stl = if ascii {
+        write_ascii_stl(&model).map(String::into_bytes)
+    } else {
+        write_binary_stl(&model)
+    }
+    .map_err(|e| JsValue::from_str(&format!("Failed to create STL: {}", e)))?;
+
+    let report = validate_stl(&stl);
+    if !report.valid {
+        return Err(JsValue::from_str(&format!(
+            "Exported STL failed validation: {}",
+            report.errors.join("; ")
+        )));
+    }
+    Ok(stl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron(transform: Option<Vec<f64>>) -> Mesh3MFData {
+        Mesh3MFData {
+            vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            indices: vec![0, 2, 1, 0, 1, 3, 1, 2, 3, 0, 3, 2],
+            colors: None,
+            name: Some("buildings".to_string()),
+            transform,
+        }
+    }
+
+    fn model(meshes: Vec<Mesh3MFData>) -> Model3MFData {
+        let mut model: Model3MFData = serde_json::from_str(r#"{"title": "Test model"}"#).unwrap();
+        model.meshes = meshes;
+        model
+    }
+
+    #[test]
+    fn test_binary_and_ascii_stl_validate_and_bake_transforms() {
+        // Translate by (10, 0, 0)
+        let mut translate = vec![0.0; 16];
+        for i in [0, 5, 10, 15] {
+            translate[i] = 1.0;
+        }
+        translate[12] = 10.0;
+        let mut input = model(vec![tetrahedron(None), tetrahedron(Some(translate))]);
+        input.model_size_mm = Some(200.0);
+
+        let binary = write_binary_stl(&input).unwrap();
+        assert!(!binary.starts_with(b"solid"));
+        let report = validate_stl(&binary);
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.triangle_count, 8);
+        // First vertex of the first facet of the translated mesh; 1 mm per unit at 200 mm
+        let offset = 84 + 4 * 50 + 12;
+        let x = f32::from_le_bytes(binary[offset..offset + 4].try_into().unwrap());
+        assert_eq!(x, 10.0);
+
+        let ascii = write_ascii_stl(&input).unwrap();
+        assert!(ascii.starts_with("solid Test_model\n"));
+        let report = validate_stl(ascii.as_bytes());
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.triangle_count, 8);
+
+        // Same rejection as the 3MF export
+        let mut broken = tetrahedron(None);
+        broken.indices[0] = 7;
+        assert!(write_binary_stl(&model(vec![broken])).is_err());
+    }
+}
@@ -0,0 +1,713 @@
+// Structural self-check for exported files, run after export so corrupt output is
+// reported before the file reaches a slicer. This is not a full schema validator:
+// it checks the rules slicers actually trip over (missing package parts, unbalanced
+// XML, out-of-range triangle references, STL size/count mismatches).
+use serde::Serialize;
+use std::collections::HashSet;
+use wasm_bindgen::prelude::*;
+
+use crate::console::{self, LogLevel};
+use crate::mesh_manifold::analyze_manifold;
+use crate::zip_writer::read_entries;
+
+// Keep reports small even for badly broken files
+const MAX_REPORTED_ISSUES: usize = 50;
+const STL_HEADER_SIZE: usize = 84;
+const STL_TRIANGLE_SIZE: usize = 50;
+const VALID_3MF_UNITS: [&str; 6] = [
+    "micron",
+    "millimeter",
+    "centimeter",
+    "inch",
+    "foot",
+    "meter",
+];
+
+#[derive(Serialize, Default, Debug)]
+pub struct ExportValidationReport {
+    pub format: String,
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    #[serde(rename = "objectCount")]
+    pub object_count: usize,
+    #[serde(rename = "vertexCount")]
+    pub vertex_count: usize,
+    #[serde(rename = "triangleCount")]
+    pub triangle_count: usize,
+    #[serde(rename = "suppressedIssues")]
+    pub suppressed_issues: usize,
+}
+
+impl ExportValidationReport {
+    pub(crate) fn new(format: &str) -> Self {
+        Self {
+            format: format.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        if self.errors.len() < MAX_REPORTED_ISSUES {
+            self.errors.push(message);
+        } else {
+            self.suppressed_issues += 1;
+        }
+    }
+
+    fn warning(&mut self, message: String) {
+        if self.warnings.len() < MAX_REPORTED_ISSUES {
+            self.warnings.push(message);
+        } else {
+            self.suppressed_issues += 1;
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Self {
+        self.valid = self.errors.is_empty();
+        self
+    }
+}
+
+/// Validate exported file bytes and return a JSON report.
+/// `format` is "3mf", "stl" or "auto" (detected from the file signature).
+#[wasm_bindgen]
+pub fn validate_export(bytes: &[u8], format: &str) -> Result<String, JsValue> {
+    let report = match format.to_ascii_lowercase().as_str() {
+        "3mf" => validate_3mf(bytes),
+        "stl" => validate_stl(bytes),
+        "auto" | "" => {
+            if bytes.starts_with(b"PK\x03\x04") {
+                validate_3mf(bytes)
+            } else {
+                validate_stl(bytes)
+            }
+        }
+        other => {
+            return Err(JsValue::from_str(&format!(
+                "Unsupported export format: {}",
+                other
+            )))
+        }
+    };
+
+    serde_json::to_string(&report)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize report: {}", e)))
+}
+
+pub(crate) fn validate_3mf(bytes: &[u8]) -> ExportValidationReport {
+    let mut report = ExportValidationReport::new("3mf");
+
+    let entries = match read_entries(bytes) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.error(format!("Archive could not be read: {}", e));
+            return report.finish();
+        }
+    };
+    let find = |name: &str| {
+        entries
+            .iter()
+            .find(|(entry, _)| entry.trim_start_matches('/').eq_ignore_ascii_case(name))
+            .map(|(_, data)| data.as_slice())
+    };
+
+    match find("[Content_Types].xml") {
+        Some(data) => {
+            if !String::from_utf8_lossy(data).contains("3dmanufacturing-3dmodel+xml") {
+                report.error(
+                    "[Content_Types].xml does not declare the 3D model content type".to_string(),
+                );
+            }
+        }
+        None => report.error("Missing [Content_Types].xml".to_string()),
+    }
+
+    // The package relationship points at the model part
+    let model_path = match find("_rels/.rels") {
+        Some(data) => {
+            let rels = String::from_utf8_lossy(data).to_string();
+            let target = scan_xml(&rels)
+                .ok()
+                .and_then(|tags| {
+                    tags.into_iter().find(|tag| {
+                        tag.name == "Relationship"
+                            && tag.attr("Type").is_some_and(|t| t.ends_with("/3dmodel"))
+                    })
+                })
+                .and_then(|tag| tag.attr("Target").map(str::to_string));
+            match target {
+                Some(target) => target.trim_start_matches('/').to_string(),
+                None => {
+                    report.error("_rels/.rels has no 3D model relationship".to_string());
+                    "3D/3dmodel.model".to_string()
+                }
+            }
+        }
+        None => {
+            report.error("Missing _rels/.rels".to_string());
+            "3D/3dmodel.model".to_string()
+        }
+    };
+
+    match find(&model_path) {
+        Some(data) => match std::str::from_utf8(data) {
+            Ok(xml) => validate_model_xml(xml, &mut report),
+            Err(_) => report.error(format!("{} is not valid UTF-8", model_path)),
+        },
+        None => report.error(format!("Missing model part {}", model_path)),
+    }
+
+    report.finish()
+}
+
+// Per-object state while walking a <mesh>
+#[derive(Default)]
+struct MeshState {
+    vertices: usize,
+    triangles: usize,
+    max_index: Option<u64>,
+}
+
+fn validate_model_xml(xml: &str, report: &mut ExportValidationReport) {
+    let tags = match scan_xml(xml) {
+        Ok(tags) => tags,
+        Err(e) => {
+            report.error(format!("Model XML is malformed: {}", e));
+            return;
+        }
+    };
+
+    let mut stack: Vec<&str> = Vec::new();
+    let mut object_ids: HashSet<String> = HashSet::new();
+    let mut build_refs: Vec<String> = Vec::new();
+    let mut current_object: Option<String> = None;
+    let mut mesh: Option<MeshState> = None;
+
+    for tag in &tags {
+        if tag.closing {
+            match stack.pop() {
+                Some(open) if open == tag.name => {}
+                Some(open) => {
+                    report.error(format!("Mismatched </{}>, expected </{}>", tag.name, open));
+                    return;
+                }
+                None => {
+                    report.error(format!("Unexpected </{}>", tag.name));
+                    return;
+                }
+            }
+            match tag.name {
+                "mesh" => {
+                    if let Some(state) = mesh.take() {
+                        finish_mesh(current_object.as_deref(), &state, report);
+                    }
+                }
+                "object" => current_object = None,
+                _ => {}
+            }
+            continue;
+        }
+
+        if stack.is_empty() {
+            if tag.name != "model" {
+                report.error(format!("Root element is <{}>, expected <model>", tag.name));
+                return;
+            }
+            match tag.attr("unit") {
+                Some(unit) if !VALID_3MF_UNITS.contains(&unit) => {
+                    report.error(format!("Unknown model unit \"{}\"", unit))
+                }
+                None => report.warning("Model has no unit; slicers assume millimeters".to_string()),
+                _ => {}
+            }
+        }
+
+        match tag.name {
+            "object" => {
+                report.object_count += 1;
+                match tag.attr("id") {
+                    Some(id) if id.parse::<u32>().is_ok_and(|id| id > 0) => {
+                        if !object_ids.insert(id.to_string()) {
+                            report.error(format!("Duplicate object id {}", id));
+                        }
+                        current_object = Some(id.to_string());
+                    }
+                    Some(id) => report.error(format!("Invalid object id \"{}\"", id)),
+                    None => report.error("Object without id".to_string()),
+                }
+            }
+            "mesh" => mesh = Some(MeshState::default()),
+            "vertex" => {
+                report.vertex_count += 1;
+                if let Some(state) = mesh.as_mut() {
+                    state.vertices += 1;
+                }
+                let finite = ["x", "y", "z"].iter().all(|axis| {
+                    tag.attr(axis)
+                        .and_then(|v| v.parse::<f64>().ok())
+                        .is_some_and(f64::is_finite)
+                });
+                if !finite {
+                    report.error(format!(
+                        "Object {}: vertex {} has missing or non-finite coordinates",
+                        current_object.as_deref().unwrap_or("?"),
+                        report.vertex_count - 1
+                    ));
+                }
+            }
+            "triangle" => {
+                report.triangle_count += 1;
+                let indices: Vec<Option<u64>> = ["v1", "v2", "v3"]
+                    .iter()
+                    .map(|key| tag.attr(key).and_then(|v| v.parse::<u64>().ok()))
+                    .collect();
+                let object = current_object.as_deref().unwrap_or("?");
+                if indices.iter().any(Option::is_none) {
+                    report.error(format!(
+                        "Object {}: triangle with invalid vertex indices",
+                        object
+                    ));
+                    continue;
+                }
+                let [a, b, c] = [
+                    indices[0].unwrap(),
+                    indices[1].unwrap(),
+                    indices[2].unwrap(),
+                ];
+                if a == b || b == c || a == c {
+                    report.error(format!(
+                        "Object {}: triangle {} {} {} repeats a vertex",
+                        object, a, b, c
+                    ));
+                }
+                if let Some(state) = mesh.as_mut() {
+                    state.triangles += 1;
+                    state.max_index = Some(state.max_index.unwrap_or(0).max(a.max(b).max(c)));
+                }
+            }
+            "item" => match tag.attr("objectid") {
+                Some(id) => {
+                    build_refs.push(id.to_string());
+                    if let Some(transform) = tag.attr("transform") {
+                        let values: Vec<Option<f64>> = transform
+                            .split_whitespace()
+                            .map(|v| v.parse::<f64>().ok().filter(|v| v.is_finite()))
+                            .collect();
+                        if values.len() != 12 || values.iter().any(Option::is_none) {
+                            report.error(format!("Build item {} has an invalid transform", id));
+                        }
+                    }
+                }
+                None => report.error("Build item without objectid".to_string()),
+            },
+            _ => {}
+        }
+
+        if !tag.self_closing {
+            stack.push(tag.name);
+        }
+    }
+
+    if let Some(open) = stack.last() {
+        report.error(format!("Unclosed <{}>", open));
+    }
+    if build_refs.is_empty() {
+        report.error("Build section has no items".to_string());
+    }
+    for id in &build_refs {
+        if !object_ids.contains(id) {
+            report.error(format!("Build item references missing object {}", id));
+        }
+    }
+}
+
+fn finish_mesh(object: Option<&str>, state: &MeshState, report: &mut ExportValidationReport) {
+    let object = object.unwrap_or("?");
+    if state.triangles == 0 {
+        report.warning(format!("Object {} has an empty mesh", object));
+    }
+    if let Some(max_index) = state.max_index {
+        if max_index >= state.vertices as u64 {
+            report.error(format!(
+                "Object {}: triangle references vertex {} but only {} vertices exist",
+                object, max_index, state.vertices
+            ));
+        }
+    }
+}
+
+pub(crate) fn validate_stl(bytes: &[u8]) -> ExportValidationReport {
+    let mut report = ExportValidationReport::new("stl");
+
+    // Binary STL headers may also start with "solid", so prefer the size check
+    let binary_count = bytes
+        .get(80..84)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    // In u64 so any declared count fits, even past the address space of wasm32
+    let binary_size =
+        |count: u32| STL_HEADER_SIZE as u64 + u64::from(count) * STL_TRIANGLE_SIZE as u64;
+    let binary_consistent =
+        binary_count.is_some_and(|count| bytes.len() as u64 == binary_size(count));
+
+    if !binary_consistent && bytes.trim_ascii_start().starts_with(b"solid") {
+        validate_ascii_stl(&String::from_utf8_lossy(bytes), &mut report);
+        return report.finish();
+    }
+
+    let count = match binary_count {
+        Some(count) => count,
+        None => {
+            report.error(format!(
+                "File is {} bytes, shorter than the 84-byte binary STL header",
+                bytes.len()
+            ));
+            return report.finish();
+        }
+    };
+    if !binary_consistent {
+        report.error(format!(
+            "Header declares {} triangles ({} bytes) but the file has {} bytes",
+            count,
+            binary_size(count),
+            bytes.len()
+        ));
+        return report.finish();
+    }
+    // The file holds all `count` records, so the count fits in usize
+    let count = count as usize;
+
+    report.object_count = 1;
+    report.triangle_count = count;
+    report.vertex_count = count * 3;
+    let mut degenerate = 0;
+    for (index, record) in bytes[STL_HEADER_SIZE..]
+        .chunks_exact(STL_TRIANGLE_SIZE)
+        .enumerate()
+    {
+        let floats: Vec<f32> = record[..48]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        if floats.iter().any(|v| !v.is_finite()) {
+            report.error(format!("Triangle {} has non-finite values", index));
+            continue;
+        }
+        if is_degenerate(&floats[3..6], &floats[6..9], &floats[9..12]) {
+            degenerate += 1;
+        }
+    }
+    if degenerate > 0 {
+        report.warning(format!("{} degenerate (zero-area) triangles", degenerate));
+    }
+
+    report.finish()
+}
+
+fn validate_ascii_stl(text: &str, report: &mut ExportValidationReport) {
+    let mut tokens = text.split_whitespace();
+    let mut facet_vertices = 0;
+    let mut in_facet = false;
+    let mut ended = false;
+    let mut degenerate = 0;
+    let mut corners: Vec<f32> = Vec::with_capacity(9);
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "facet" => {
+                if in_facet {
+                    report.error(format!("Facet {} is not closed", report.triangle_count));
+                }
+                in_facet = true;
+                facet_vertices = 0;
+                corners.clear();
+                report.triangle_count += 1;
+            }
+            "vertex" => {
+                facet_vertices += 1;
+                report.vertex_count += 1;
+                for _ in 0..3 {
+                    match tokens.next().and_then(|v| v.parse::<f32>().ok()) {
+                        Some(v) if v.is_finite() => corners.push(v),
+                        _ => {
+                            report.error(format!(
+                                "Facet {}: vertex with invalid coordinates",
+                                report.triangle_count
+                            ));
+                            return;
+                        }
+                    }
+                }
+            }
+            "endfacet" => {
+                if facet_vertices != 3 {
+                    report.error(format!(
+                        "Facet {} has {} vertices, expected 3",
+                        report.triangle_count, facet_vertices
+                    ));
+                } else if is_degenerate(&corners[0..3], &corners[3..6], &corners[6..9]) {
+                    degenerate += 1;
+                }
+                in_facet = false;
+            }
+            "endsolid" => {
+                ended = true;
+                report.object_count += 1;
+            }
+            _ => {}
+        }
+    }
+
+    if in_facet {
+        report.error("File ends inside a facet".to_string());
+    }
+    if !ended {
+        report.error("Missing endsolid".to_string());
+    }
+    if report.triangle_count == 0 {
+        report.error("File contains no facets".to_string());
+    }
+    if degenerate > 0 {
+        report.warning(format!("{} degenerate (zero-area) triangles", degenerate));
+    }
+}
+
+/// Check a mesh before it is written, so the exporters reject the same input: incomplete
+/// triangles, out-of-range indices and non-finite coordinates are errors; the problems
+/// `analyze_manifold` finds (open or non-manifold edges, flipped faces, duplicates) are
+/// warnings, as such meshes may still slice.
+pub(crate) fn check_export_mesh(
+    name: &str,
+    vertices: &[f32],
+    indices: &[u32],
+    report: &mut ExportValidationReport,
+) {
+    let vertex_count = vertices.len() / 3;
+    report.object_count += 1;
+    report.vertex_count += vertex_count;
+    report.triangle_count += indices.len() / 3;
+    if !vertices.len().is_multiple_of(3) || !indices.len().is_multiple_of(3) {
+        report.error(format!(
+            "Mesh {}: {} coordinates and {} indices do not form whole vertices and triangles",
+            name,
+            vertices.len(),
+            indices.len()
+        ));
+    }
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+        report.error(format!(
+            "Mesh {}: index {} out of range for {} vertices",
+            name, index, vertex_count
+        ));
+        return;
+    }
+    if vertices.iter().any(|v| !v.is_finite()) {
+        report.error(format!("Mesh {} has non-finite vertex coordinates", name));
+        return;
+    }
+
+    match analyze_manifold(vertices, Some(indices), None) {
+        Ok(manifold) => {
+            for problem in manifold.problems() {
+                report.warning(format!("Mesh {}: {}", name, problem));
+            }
+        }
+        Err(e) => report.error(format!("Mesh {}: {}", name, e)),
+    }
+}
+
+/// Run `check_export_mesh` on one mesh about to be exported: errors reject the mesh,
+/// warnings are logged
+pub(crate) fn require_exportable_mesh(
+    name: &str,
+    vertices: &[f32],
+    indices: &[u32],
+) -> Result<(), String> {
+    let mut report = ExportValidationReport::new("mesh");
+    check_export_mesh(name, vertices, indices, &mut report);
+    for warning in &report.warnings {
+        console::record(LogLevel::Warn, "export", None, warning.clone());
+    }
+    if report.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(report.errors.join("; "))
+    }
+}
+
+fn is_degenerate(a: &[f32], b: &[f32], c: &[f32]) -> bool {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let cross = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    cross.iter().map(|c| c * c).sum::<f32>() <= f32::EPSILON * f32::EPSILON
+}
+
+// A start or end tag with its attributes, borrowed from the document
+struct XmlTag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl<'a> XmlTag<'a> {
+    fn attr(&self, key: &str) -> Option<&'a str> {
+        self.attributes
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| *value)
+    }
+}
+
+// Minimal tag scanner: skips declarations, comments and text, and reports unterminated
+// tags or attributes. Element nesting is checked by the caller.
+fn scan_xml(xml: &str) -> Result<Vec<XmlTag<'_>>, String> {
+    let mut tags = Vec::new();
+    let mut rest = xml;
+    let mut offset = 0;
+
+    while let Some(start) = rest.find('<') {
+        let tag_start = offset + start;
+        rest = &rest[start..];
+        let skip_to = |marker: &str| {
+            rest.find(marker)
+                .map(|end| end + marker.len())
+                .ok_or_else(|| format!("unterminated markup at byte {}", tag_start))
+        };
+        let consumed = if rest.starts_with("<?") {
+            skip_to("?>")?
+        } else if rest.starts_with("<!--") {
+            skip_to("-->")?
+        } else if rest.starts_with("<!") {
+            skip_to(">")?
+        } else {
+            // Find the closing '>' outside quoted attribute values
+            let mut quote: Option<char> = None;
+            let end = rest
+                .char_indices()
+                .find(|&(_, ch)| match quote {
+                    Some(q) if ch == q => {
+                        quote = None;
+                        false
+                    }
+                    Some(_) => false,
+                    None if ch == '"' || ch == '\'' => {
+                        quote = Some(ch);
+                        false
+                    }
+                    None => ch == '>',
+                })
+                .map(|(i, _)| i)
+                .ok_or_else(|| format!("unterminated tag at byte {}", tag_start))?;
+            tags.push(parse_tag(&rest[1..end], tag_start)?);
+            end + 1
+        };
+        rest = &rest[consumed..];
+        offset = tag_start + consumed;
+    }
+    Ok(tags)
+}
+
+fn parse_tag(body: &str, position: usize) -> Result<XmlTag<'_>, String> {
+    let closing = body.starts_with('/');
+    let self_closing = !closing && body.ends_with('/');
+    let body = body.trim_start_matches('/').trim_end_matches('/').trim();
+    let name_end = body.find(char::is_whitespace).unwrap_or(body.len());
+    let name = &body[..name_end];
+    if name.is_empty() {
+        return Err(format!("empty tag name at byte {}", position));
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = body[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest
+            .find('=')
+            .ok_or_else(|| format!("attribute without value in <{}>", name))?;
+        let key = rest[..eq].trim();
+        let value_part = rest[eq + 1..].trim_start();
+        let quote = value_part
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("unquoted attribute {} in <{}>", key, name))?;
+        let value_end = value_part[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated attribute {} in <{}>", key, name))?;
+        attributes.push((key, &value_part[1..1 + value_end]));
+        rest = value_part[value_end + 2..].trim_start();
+    }
+
+    Ok(XmlTag {
+        name,
+        attributes,
+        closing,
+        self_closing,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model_xml(triangle: &str, item: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter"><resources><object id="1" type="model"><mesh><vertices>
+<vertex x="0" y="0" z="0"/><vertex x="1" y="0" z="0"/><vertex x="0" y="1" z="0"/>
+</vertices><triangles>{}</triangles></mesh></object></resources><build>{}</build></model>"#,
+            triangle, item
+        )
+    }
+
+    #[test]
+    fn test_model_xml_reference_checks() {
+        let mut report = ExportValidationReport::new("3mf");
+        validate_model_xml(
+            &model_xml(
+                r#"<triangle v1="0" v2="1" v3="2"/>"#,
+                r#"<item objectid="1"/>"#,
+            ),
+            &mut report,
+        );
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!((report.vertex_count, report.triangle_count), (3, 1));
+
+        let mut report = ExportValidationReport::new("3mf");
+        validate_model_xml(
+            &model_xml(
+                r#"<triangle v1="0" v2="1" v3="3"/>"#,
+                r#"<item objectid="2"/>"#,
+            ),
+            &mut report,
+        );
+        assert_eq!(report.errors.len(), 2, "{:?}", report.errors);
+    }
+
+    #[test]
+    fn test_binary_stl_count_mismatch() {
+        let mut stl = vec![0u8; STL_HEADER_SIZE + STL_TRIANGLE_SIZE];
+        stl[80..84].copy_from_slice(&2u32.to_le_bytes());
+        let report = validate_stl(&stl);
+        assert!(!report.valid);
+
+        stl[80..84].copy_from_slice(&1u32.to_le_bytes());
+        let report = validate_stl(&stl);
+        assert!(report.valid);
+        assert_eq!(report.warnings.len(), 1); // all-zero triangle is degenerate
+
+        // A count whose byte size overflows 32 bits is reported, not wrapped
+        stl[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        let report = validate_stl(&stl);
+        assert!(!report.valid);
+        assert!(
+            report.errors[0].contains("214748364834 bytes"),
+            "{:?}",
+            report.errors
+        );
+    }
+}
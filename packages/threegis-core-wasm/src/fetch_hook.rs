@@ -0,0 +1,230 @@
+// Transport used for all tile downloads. Hosts can register their own fetch handler
+// (service worker cache, Node fs, Capacitor, ...) instead of defining the global
+// `wasmJsHelpers.fetch`, which remains the fallback when no handler is registered.
+// Concurrent requests for the same URL share one download.
+use futures::future::{FutureExt, LocalBoxFuture, Shared};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::module_state::{ModuleState, TileValidators};
+use crate::offline::is_offline_mode;
+use crate::rate_limit;
+
+thread_local! {
+    // JS functions are not Send, so the handler lives outside ModuleState
+    static FETCH_HANDLER: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+    // Downloads in progress by URL
+    static IN_FLIGHT: Rc<InFlight<Result<JsValue, JsValue>>> = Rc::new(InFlight::new());
+}
+
+/// Route tile downloads through `handler(url, options)`. It must return (a promise of)
+/// the same response object as `wasmJsHelpers.fetch`: `{ width, height, pixelData }` for
+/// raster tiles and `{ rawData }` for vector tiles, optionally with `etag` and
+/// `lastModified`. `options.headers` carries conditional request headers on
+/// revalidation; answer those with `{ notModified: true }` (HTTP 304).
+#[wasm_bindgen]
+pub fn register_fetch_handler(handler: js_sys::Function) {
+    FETCH_HANDLER.with(|h| *h.borrow_mut() = Some(handler));
+}
+
+/// Go back to the global `wasmJsHelpers.fetch`
+#[wasm_bindgen]
+pub fn unregister_fetch_handler() {
+    FETCH_HANDLER.with(|h| *h.borrow_mut() = None);
+}
+
+/// Drop the registered handler and forget requests in progress; callers already
+/// waiting on them still receive their results
+pub(crate) fn reset_fetch_state() {
+    unregister_fetch_handler();
+    IN_FLIGHT.with(|in_flight| in_flight.requests.borrow_mut().clear());
+}
+
+#[wasm_bindgen]
+pub fn has_fetch_handler() -> bool {
+    FETCH_HANDLER.with(|h| h.borrow().is_some())
+}
+
+/// Fetch through the registered handler or the global helper, unless offline mode
+/// forbids network access
+pub(crate) async fn network_fetch(url: &str) -> Result<JsValue, JsValue> {
+    network_fetch_with_headers(url, &[]).await
+}
+
+/// `network_fetch` sending extra request headers, e.g. the API key of a tile source
+pub(crate) async fn network_fetch_with_headers(
+    url: &str,
+    headers: &[(String, String)],
+) -> Result<JsValue, JsValue> {
+    let options = request_options(headers)?;
+    let owned_url = url.to_string();
+    let request = async move { send(&owned_url, options).await };
+    let in_flight = IN_FLIGHT.with(Rc::clone);
+    in_flight.coalesce(url, request).await
+}
+
+/// `{ headers }` fetch options, or None without headers
+fn request_options(headers: &[(String, String)]) -> Result<Option<JsValue>, JsValue> {
+    if headers.is_empty() {
+        return Ok(None);
+    }
+    let object = js_sys::Object::new();
+    for (name, value) in headers {
+        js_sys::Reflect::set(&object, &name.into(), &value.into())?;
+    }
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &"headers".into(), &object)?;
+    Ok(Some(options.into()))
+}
+
+/// Requests in progress by key, shared with every caller arriving before they complete
+struct InFlight<T> {
+    requests: RefCell<HashMap<String, Shared<LocalBoxFuture<'static, T>>>>,
+}
+
+impl<T: Clone + 'static> InFlight<T> {
+    fn new() -> Self {
+        Self {
+            requests: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Await the in-flight request for `key`, or start `request`
+    async fn coalesce<F>(&self, key: &str, request: F) -> T
+    where
+        F: Future<Output = T> + 'static,
+    {
+        let existing = self.requests.borrow().get(key).cloned();
+        let (shared, started) = match existing {
+            Some(shared) => (shared, false),
+            None => {
+                let shared = request.boxed_local().shared();
+                self.requests
+                    .borrow_mut()
+                    .insert(key.to_string(), shared.clone());
+                (shared, true)
+            }
+        };
+        let result = shared.await;
+        if started {
+            self.requests.borrow_mut().remove(key);
+        }
+        result
+    }
+}
+
+/// Conditional request revalidating a cached response, along with the source's headers
+pub(crate) async fn network_fetch_conditional(
+    url: &str,
+    validators: &TileValidators,
+    headers: &[(String, String)],
+) -> Result<JsValue, JsValue> {
+    let mut headers = headers.to_vec();
+    if let Some(etag) = &validators.etag {
+        headers.push(("If-None-Match".to_string(), etag.clone()));
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        headers.push(("If-Modified-Since".to_string(), last_modified.clone()));
+    }
+    send(url, request_options(&headers)?).await
+}
+
+async fn send(url: &str, options: Option<JsValue>) -> Result<JsValue, JsValue> {
+    if is_offline_mode() {
+        return Err(JsValue::from_str(&format!(
+            "Offline mode: network fetch of {} is disabled",
+            url
+        )));
+    }
+    rate_limit::acquire(url).await;
+
+    let handler = FETCH_HANDLER.with(|h| h.borrow().clone());
+    let promise = match (handler, options) {
+        (Some(handler), options) => {
+            let options = options.unwrap_or(JsValue::UNDEFINED);
+            let value = handler.call2(&JsValue::NULL, &JsValue::from_str(url), &options)?;
+            // Synchronous handlers may return the response directly
+            match value.dyn_into::<js_sys::Promise>() {
+                Ok(promise) => promise,
+                Err(value) => js_sys::Promise::resolve(&value),
+            }
+        }
+        (None, Some(options)) => crate::fetch_with_options(url, &options)?,
+        (None, None) => crate::fetch(url)?,
+    };
+    JsFuture::from(promise).await
+}
+
+fn string_field(response: &JsValue, name: &str) -> Option<String> {
+    js_sys::Reflect::get(response, &JsValue::from_str(name))
+        .ok()
+        .and_then(|v| v.as_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Remember the ETag/Last-Modified of a response for later revalidation
+pub(crate) fn record_validators(url: &str, response: &JsValue) {
+    let validators = TileValidators {
+        etag: string_field(response, "etag"),
+        last_modified: string_field(response, "lastModified"),
+    };
+    ModuleState::with_mut(|state| {
+        if validators == TileValidators::default() {
+            state.tile_validators.remove(url);
+        } else {
+            state.tile_validators.insert(url.to_string(), validators);
+        }
+    });
+}
+
+/// Whether a conditional request was answered with 304 Not Modified
+pub(crate) fn is_not_modified(response: &JsValue) -> bool {
+    js_sys::Reflect::get(response, &JsValue::from_str("notModified"))
+        .ok()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::task::Poll;
+
+    #[test]
+    fn test_concurrent_requests_share_one_download() {
+        let in_flight = InFlight::new();
+        let downloads = Rc::new(Cell::new(0));
+        let request = |downloads: Rc<Cell<u32>>| async move {
+            downloads.set(downloads.get() + 1);
+            // Still downloading when the second caller arrives
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if std::mem::replace(&mut yielded, true) {
+                    Poll::Ready(())
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await;
+            downloads.get()
+        };
+        let url = "https://tiles.example.org/1/0/0.pbf";
+
+        let (first, second) = futures::executor::block_on(futures::future::join(
+            in_flight.coalesce(url, request(downloads.clone())),
+            in_flight.coalesce(url, request(downloads.clone())),
+        ));
+        assert_eq!((first, second), (1, 1));
+
+        // Finished requests are not cached: a later request downloads again
+        let third = futures::executor::block_on(in_flight.coalesce(url, request(downloads)));
+        assert_eq!(third, 2);
+    }
+}
@@ -0,0 +1,360 @@
+// MapLibre expression filters for VtDataSet.filter, so filters can be copied from a map
+// style. Legacy filter arrays are still evaluated by `vectortile::evaluate_filter`; this
+// module decides which syntax a filter uses the same way MapLibre does and evaluates the
+// expression subset that makes sense for features: property access, comparisons, logic,
+// match / case / coalesce, type conversion, arithmetic, step / interpolate and ["zoom"].
+use serde_json::Value;
+
+/// What an expression can refer to: the feature and the zoom level of its tile
+pub(crate) struct FilterContext<'a> {
+    pub properties: &'a Value,
+    pub geometry_type: &'a str,
+    pub zoom: f64,
+}
+
+/// Whether `filter` uses expression syntax rather than the legacy filter syntax
+pub(crate) fn is_expression_filter(filter: &Value) -> bool {
+    let Some(array) = filter.as_array() else {
+        return filter.is_boolean();
+    };
+    let Some(operator) = array.first().and_then(Value::as_str) else {
+        return false;
+    };
+    match operator {
+        "has" => array.len() >= 2 && array[1] != "$id" && array[1] != "$type",
+        "in" => array.len() >= 3 && (!array[1].is_string() || array[2].is_array()),
+        "!in" | "!has" | "none" => false,
+        "==" | "!=" | ">" | ">=" | "<" | "<=" => {
+            array.len() != 3 || array[1].is_array() || array[2].is_array()
+        }
+        "any" | "all" => array[1..]
+            .iter()
+            .all(|filter| filter.is_boolean() || is_expression_filter(filter)),
+        _ => true,
+    }
+}
+
+/// Evaluate an expression filter; anything but `true` rejects the feature
+pub(crate) fn evaluate_filter_expression(filter: &Value, context: &FilterContext) -> bool {
+    evaluate(filter, context) == Value::Bool(true)
+}
+
+/// Value of an expression for a feature, e.g. the color of a `colorExpression`
+pub(crate) fn evaluate_expression(expression: &Value, context: &FilterContext) -> Value {
+    evaluate(expression, context)
+}
+
+fn evaluate(expression: &Value, context: &FilterContext) -> Value {
+    let Some(array) = expression.as_array() else {
+        return expression.clone();
+    };
+    let Some(operator) = array.first().and_then(Value::as_str) else {
+        return expression.clone();
+    };
+    let args = &array[1..];
+    let arg = |i: usize| args.get(i).map_or(Value::Null, |a| evaluate(a, context));
+    let number = |i: usize| to_number(&arg(i));
+    let truthy = |value: &Value| *value == Value::Bool(true);
+
+    match operator {
+        "literal" => args.first().cloned().unwrap_or(Value::Null),
+        "get" => match arg(0).as_str() {
+            Some(key) => context.properties.get(key).cloned().unwrap_or(Value::Null),
+            None => Value::Null,
+        },
+        "has" => Value::Bool(
+            arg(0)
+                .as_str()
+                .is_some_and(|key| context.properties.get(key).is_some()),
+        ),
+        "zoom" => Value::from(context.zoom),
+        "geometry-type" => Value::from(context.geometry_type),
+
+        // Logic
+        "!" => Value::Bool(!truthy(&arg(0))),
+        "all" => Value::Bool(args.iter().all(|a| truthy(&evaluate(a, context)))),
+        "any" => Value::Bool(args.iter().any(|a| truthy(&evaluate(a, context)))),
+
+        // Comparison
+        "==" => Value::Bool(values_equal(&arg(0), &arg(1))),
+        "!=" => Value::Bool(!values_equal(&arg(0), &arg(1))),
+        "<" | ">" | "<=" | ">=" => Value::Bool(compare(&arg(0), &arg(1), operator)),
+        "in" => {
+            let needle = arg(0);
+            Value::Bool(match arg(1) {
+                Value::Array(items) => items.iter().any(|item| values_equal(item, &needle)),
+                Value::String(haystack) => needle.as_str().is_some_and(|n| haystack.contains(n)),
+                _ => false,
+            })
+        }
+
+        // Branching; labels of match are literals, a label array matches any of its values
+        "match" => {
+            let input = arg(0);
+            let branches = args.get(1..).unwrap_or_default();
+            for branch in branches.chunks(2) {
+                match branch {
+                    [label, output] => {
+                        let matched = match label {
+                            Value::Array(labels) => labels.iter().any(|l| values_equal(l, &input)),
+                            label => values_equal(label, &input),
+                        };
+                        if matched {
+                            return evaluate(output, context);
+                        }
+                    }
+                    [fallback] => return evaluate(fallback, context),
+                    _ => {}
+                }
+            }
+            Value::Null
+        }
+        "case" => {
+            for branch in args.chunks(2) {
+                match branch {
+                    [condition, output] if truthy(&evaluate(condition, context)) => {
+                        return evaluate(output, context);
+                    }
+                    [fallback] => return evaluate(fallback, context),
+                    _ => {}
+                }
+            }
+            Value::Null
+        }
+        "coalesce" => args
+            .iter()
+            .map(|a| evaluate(a, context))
+            .find(|value| !value.is_null())
+            .unwrap_or(Value::Null),
+
+        // Type conversion
+        "to-number" => args
+            .iter()
+            .find_map(|a| to_number(&evaluate(a, context)))
+            .map_or(Value::Null, Value::from),
+        "to-string" => Value::from(match arg(0) {
+            Value::Null => String::new(),
+            Value::String(s) => s,
+            value => value.to_string(),
+        }),
+        "to-boolean" => Value::Bool(match arg(0) {
+            Value::Null => false,
+            Value::Bool(b) => b,
+            Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0 && !n.is_nan()),
+            Value::String(s) => !s.is_empty(),
+            _ => true,
+        }),
+
+        // Arithmetic
+        "+" | "*" => {
+            let values: Option<Vec<f64>> = (0..args.len()).map(number).collect();
+            values.map_or(Value::Null, |values| {
+                Value::from(if operator == "+" {
+                    values.iter().sum::<f64>()
+                } else {
+                    values.iter().product::<f64>()
+                })
+            })
+        }
+        "-" if args.len() == 1 => number(0).map_or(Value::Null, |a| Value::from(-a)),
+        "-" | "/" | "%" => match (number(0), number(1)) {
+            (Some(a), Some(b)) => Value::from(match operator {
+                "-" => a - b,
+                "/" => a / b,
+                _ => a % b,
+            }),
+            _ => Value::Null,
+        },
+
+        // Ramps over a numeric input, usually ["zoom"]
+        "step" => {
+            let Some(input) = number(0) else {
+                return Value::Null;
+            };
+            let mut output = args.get(1);
+            for stop in args.get(2..).unwrap_or_default().chunks(2) {
+                if let [at, value] = stop {
+                    if at.as_f64().is_some_and(|at| input >= at) {
+                        output = Some(value);
+                    }
+                }
+            }
+            output.map_or(Value::Null, |output| evaluate(output, context))
+        }
+        "interpolate" => interpolate(args, context),
+        _ => Value::Null,
+    }
+}
+
+// ["interpolate", ["linear"] | ["exponential", base] | ["cubic-bezier", ...], input,
+// stop, output, ...] for numeric outputs; cubic-bezier curves are interpolated linearly
+fn interpolate(args: &[Value], context: &FilterContext) -> Value {
+    let base = match args.first().and_then(Value::as_array).map(Vec::as_slice) {
+        Some([kind, base]) if kind == "exponential" => base.as_f64().unwrap_or(1.0),
+        _ => 1.0,
+    };
+    let Some(input) = args.get(1).and_then(|a| to_number(&evaluate(a, context))) else {
+        return Value::Null;
+    };
+    let stops: Option<Vec<(f64, f64)>> = args
+        .get(2..)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|stop| Some((stop[0].as_f64()?, to_number(&evaluate(&stop[1], context))?)))
+        .collect();
+    let Some(stops) = stops.filter(|stops| !stops.is_empty()) else {
+        return Value::Null;
+    };
+
+    let (first, last) = (stops[0], stops[stops.len() - 1]);
+    if input <= first.0 {
+        return Value::from(first.1);
+    }
+    if input >= last.0 {
+        return Value::from(last.1);
+    }
+    let upper = stops
+        .iter()
+        .position(|stop| stop.0 > input)
+        .unwrap_or(stops.len() - 1);
+    let ((x0, y0), (x1, y1)) = (stops[upper - 1], stops[upper]);
+    let t = if (base - 1.0).abs() < 1e-12 {
+        (input - x0) / (x1 - x0)
+    } else {
+        (base.powf(input - x0) - 1.0) / (base.powf(x1 - x0) - 1.0)
+    };
+    Value::from(y0 + (y1 - y0) * t)
+}
+
+fn to_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Value::Null => Some(0.0),
+        _ => None,
+    }
+}
+
+// Numbers compare by value, so 14 equals 14.0
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn compare(a: &Value, b: &Value, operator: &str) -> bool {
+    let ordering = match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match operator {
+        "<" => ordering.is_lt(),
+        ">" => ordering.is_gt(),
+        "<=" => ordering.is_le(),
+        _ => ordering.is_ge(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn matches(filter: Value, zoom: f64) -> bool {
+        let properties = json!({ "class": "primary", "rank": 7, "name": "Main Street" });
+        let context = FilterContext {
+            properties: &properties,
+            geometry_type: "LineString",
+            zoom,
+        };
+        evaluate_filter_expression(&filter, &context)
+    }
+
+    #[test]
+    fn test_expression_syntax_is_detected() {
+        assert!(!is_expression_filter(&json!([
+            "in",
+            "class",
+            "primary",
+            "secondary"
+        ])));
+        assert!(!is_expression_filter(&json!(["==", "$type", "Polygon"])));
+        assert!(!is_expression_filter(&json!([
+            "all",
+            ["==", "class", "x"],
+            ["!has", "y"]
+        ])));
+        assert!(is_expression_filter(&json!([
+            "==",
+            ["get", "class"],
+            "primary"
+        ])));
+        assert!(is_expression_filter(&json!([
+            "match",
+            ["get", "class"],
+            "a",
+            true,
+            false
+        ])));
+        assert!(is_expression_filter(&json!([
+            "all",
+            ["has", "class"],
+            ["in", ["get", "class"], ["literal", ["a"]]]
+        ])));
+    }
+
+    #[test]
+    fn test_expressions_evaluate_against_feature_and_zoom() {
+        assert!(matches(json!(["==", ["get", "class"], "primary"]), 14.0));
+        assert!(matches(json!(["==", ["get", "rank"], 7.0]), 14.0));
+        assert!(matches(
+            json!(["in", ["get", "class"], ["literal", ["primary", "trunk"]]]),
+            14.0
+        ));
+        assert!(matches(
+            json!([
+                "match",
+                ["get", "class"],
+                ["motorway", "primary"],
+                true,
+                false
+            ]),
+            14.0
+        ));
+        assert!(!matches(
+            json!(["match", ["get", "class"], "service", true, false]),
+            14.0
+        ));
+        assert!(matches(
+            json!([
+                "case",
+                [">", ["get", "rank"], 10],
+                false,
+                ["==", ["geometry-type"], "LineString"]
+            ]),
+            14.0
+        ));
+
+        // Rank limit growing with zoom: 5 at z10 up to 9 at z14
+        let by_zoom = json!([
+            "<=",
+            ["get", "rank"],
+            ["interpolate", ["linear"], ["zoom"], 10, 5, 14, 9]
+        ]);
+        assert!(matches(by_zoom.clone(), 14.0));
+        assert!(matches(by_zoom.clone(), 12.0));
+        assert!(!matches(by_zoom, 11.0));
+        assert!(matches(json!([">=", ["zoom"], 13]), 14.0));
+        assert!(matches(
+            json!(["==", ["step", ["zoom"], "low", 13, "high"], "high"]),
+            14.0
+        ));
+        assert!(!matches(json!(["get", "class"]), 14.0));
+    }
+}
@@ -0,0 +1,157 @@
+// 2.5D flat map mode: elevation is ignored and every layer is extruded to a constant
+// thickness on a flat base plate. Layers are stacked by stack order, each level resting
+// on the levels below it, so overlapping layers show the higher level on top while every
+// layer still reaches down to the plate and prints without overhangs.
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlatMapLayer {
+    /// Layer label (vtDataSet label, or source layer when unlabeled)
+    pub label: String,
+    /// Thickness of this layer's level in terrain units
+    pub thickness: f64,
+    /// Stacking level; layers with the same order share a level
+    #[serde(default, rename = "stackOrder")]
+    pub stack_order: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlatMapConfig {
+    /// Base plate thickness in terrain units
+    #[serde(rename = "baseThickness")]
+    pub base_thickness: f64,
+    pub layers: Vec<FlatMapLayer>,
+}
+
+/// Bottom and top Z of a layer's extrusion
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LayerLevel {
+    pub bottom: f64,
+    pub top: f64,
+}
+
+impl FlatMapConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.base_thickness.is_finite() || self.base_thickness <= 0.0 {
+            return Err(format!(
+                "baseThickness must be positive, got {}",
+                self.base_thickness
+            ));
+        }
+        if let Some(layer) = self
+            .layers
+            .iter()
+            .find(|l| !l.thickness.is_finite() || l.thickness <= 0.0)
+        {
+            return Err(format!(
+                "Layer '{}' needs a positive thickness, got {}",
+                layer.label, layer.thickness
+            ));
+        }
+        Ok(())
+    }
+
+    /// Levels of all layers in stacking order
+    pub fn levels(&self) -> Vec<(String, LayerLevel)> {
+        let mut layers: Vec<&FlatMapLayer> = self.layers.iter().collect();
+        layers.sort_by_key(|l| l.stack_order);
+
+        let mut levels = Vec::with_capacity(layers.len());
+        let mut level_bottom = self.base_thickness;
+        let mut next_bottom = level_bottom;
+        let mut current_order = None;
+        for layer in layers {
+            if current_order != Some(layer.stack_order) {
+                level_bottom = next_bottom;
+                current_order = Some(layer.stack_order);
+            }
+            let top = level_bottom + layer.thickness;
+            next_bottom = next_bottom.max(top);
+            levels.push((
+                layer.label.clone(),
+                LayerLevel {
+                    bottom: self.base_thickness,
+                    top,
+                },
+            ));
+        }
+        levels
+    }
+
+    pub fn level_of(&self, label: &str) -> Option<LayerLevel> {
+        self.levels()
+            .into_iter()
+            .find(|(l, _)| l == label)
+            .map(|(_, level)| level)
+    }
+}
+
+/// Bottom and top Z of every layer of a flat map config, as JSON keyed by label
+#[wasm_bindgen]
+pub fn flat_map_layer_levels(config_json: &str) -> Result<String, JsValue> {
+    let config: FlatMapConfig = serde_json::from_str(config_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    config.validate().map_err(|e| JsValue::from_str(&e))?;
+    let levels: serde_json::Map<String, serde_json::Value> = config
+        .levels()
+        .into_iter()
+        .map(|(label, level)| (label, serde_json::json!(level)))
+        .collect();
+    serde_json::to_string(&levels)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize levels: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_stack_by_order() {
+        let config: FlatMapConfig = serde_json::from_str(
+            r#"{
+                "baseThickness": 2,
+                "layers": [
+                    {"label": "buildings", "thickness": 3, "stackOrder": 2},
+                    {"label": "water", "thickness": 0.5, "stackOrder": 0},
+                    {"label": "park", "thickness": 1, "stackOrder": 0},
+                    {"label": "roads", "thickness": 0.5, "stackOrder": 1}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let level = |label| config.level_of(label).unwrap();
+        // Same order shares a level; the next level starts on the thickest of them
+        assert_eq!(
+            level("water"),
+            LayerLevel {
+                bottom: 2.0,
+                top: 2.5
+            }
+        );
+        assert_eq!(
+            level("park"),
+            LayerLevel {
+                bottom: 2.0,
+                top: 3.0
+            }
+        );
+        assert_eq!(
+            level("roads"),
+            LayerLevel {
+                bottom: 2.0,
+                top: 3.5
+            }
+        );
+        assert_eq!(
+            level("buildings"),
+            LayerLevel {
+                bottom: 2.0,
+                top: 6.5
+            }
+        );
+        assert!(config.level_of("rail").is_none());
+    }
+}
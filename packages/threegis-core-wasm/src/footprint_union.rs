@@ -0,0 +1,134 @@
+// 2D union of feature footprints before extrusion. Overlapping polygons that would be
+// extruded alike are merged, so the layer gets one clean solid per connected part instead
+// of coplanar overlaps (z-fighting) and duplicate interior walls.
+use crate::parallel;
+use crate::polygon_geometry::GeometryData;
+use geo::{BooleanOps, Coord, LineString, MultiPolygon, Polygon};
+use std::collections::BTreeMap;
+
+/// Union the Polygon features of each group given by `group_key`; features of one group
+/// must extrude alike. Merged features keep the properties of the first feature of their
+/// group. Other geometry types are kept as they are.
+pub(crate) fn union_footprints<K, F>(features: Vec<GeometryData>, group_key: F) -> Vec<GeometryData>
+where
+    K: Ord,
+    F: Fn(&GeometryData) -> K,
+{
+    // Ordered so merged features come out in the same order every run
+    let mut groups: BTreeMap<K, Vec<GeometryData>> = BTreeMap::new();
+    let mut result = Vec::new();
+    for feature in features {
+        if feature.r#type.as_deref() == Some("Polygon") {
+            groups.entry(group_key(&feature)).or_default().push(feature);
+        } else {
+            result.push(feature);
+        }
+    }
+
+    for group in groups.into_values() {
+        if group.len() == 1 {
+            result.extend(group);
+            continue;
+        }
+        let footprints: Vec<MultiPolygon<f64>> = group
+            .iter()
+            .filter_map(feature_polygon)
+            .map(|polygon| MultiPolygon(vec![polygon]))
+            .collect();
+        let Some(union) = parallel::reduce_pairwise(footprints, |a, b| a.union(&b)) else {
+            continue;
+        };
+        let template = &group[0];
+        result.extend(union.0.into_iter().map(|polygon| {
+            let holes: Vec<Vec<Vec<f64>>> = polygon.interiors().iter().map(ring_coords).collect();
+            GeometryData {
+                geometry: ring_coords(polygon.exterior()),
+                holes: if holes.is_empty() { None } else { Some(holes) },
+                ..template.clone()
+            }
+        }));
+    }
+    result
+}
+
+// Footprint of a polygon feature; None when the exterior has fewer than 3 points
+fn feature_polygon(feature: &GeometryData) -> Option<Polygon<f64>> {
+    let exterior = coords_ring(&feature.geometry);
+    if exterior.0.len() < 3 {
+        return None;
+    }
+    let holes = feature
+        .holes
+        .iter()
+        .flatten()
+        .map(|hole| coords_ring(hole))
+        .filter(|hole| hole.0.len() >= 3)
+        .collect();
+    Some(Polygon::new(exterior, holes))
+}
+
+fn coords_ring(ring: &[Vec<f64>]) -> LineString<f64> {
+    ring.iter()
+        .filter(|p| p.len() >= 2 && p[0].is_finite() && p[1].is_finite())
+        .map(|p| Coord { x: p[0], y: p[1] })
+        .collect()
+}
+
+fn ring_coords(ring: &LineString<f64>) -> Vec<Vec<f64>> {
+    ring.coords().map(|c| vec![c.x, c.y]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Area;
+
+    fn square(x: f64, y: f64, size: f64, height: f64) -> GeometryData {
+        GeometryData {
+            geometry: vec![
+                vec![x, y],
+                vec![x + size, y],
+                vec![x + size, y + size],
+                vec![x, y + size],
+                vec![x, y],
+            ],
+            holes: None,
+            r#type: Some("Polygon".to_string()),
+            height: Some(height),
+            layer: Some("building".to_string()),
+            label: None,
+            tags: None,
+            properties: None,
+        }
+    }
+
+    #[test]
+    fn test_overlapping_footprints_of_one_group_merge() {
+        let line = GeometryData {
+            r#type: Some("LineString".to_string()),
+            ..square(0.0, 0.0, 1.0, 10.0)
+        };
+        let features = vec![
+            square(0.0, 0.0, 2.0, 10.0),
+            square(1.0, 1.0, 2.0, 10.0),
+            square(5.0, 5.0, 1.0, 10.0),
+            square(0.5, 0.5, 1.0, 20.0),
+            line,
+        ];
+        let merged = union_footprints(features, |feature| {
+            (feature.height.unwrap_or(0.0) * 1000.0) as i64
+        });
+
+        // The line, two parts at 10 and the taller square on its own
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged[0].r#type.as_deref(), Some("LineString"));
+        let mut areas: Vec<f64> = merged[1..3]
+            .iter()
+            .map(|feature| feature_polygon(feature).unwrap().unsigned_area())
+            .collect();
+        areas.sort_by(f64::total_cmp);
+        assert!((areas[0] - 1.0).abs() < 1e-9);
+        assert!((areas[1] - 7.0).abs() < 1e-9);
+        assert_eq!(merged[3].height, Some(20.0));
+    }
+}
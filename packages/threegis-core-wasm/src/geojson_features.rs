@@ -1,4 +1,13 @@
+// GeoJSON import: custom user data stored in the process feature cache as a layer, so
+// process_polygon_geometry can extrude it without a tile server.
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::cache_keys::{make_inner_key_from_filter, make_process_cache_key};
+use crate::console::{self, LogLevel};
+use crate::module_state::ModuleState;
+use crate::polygon_geometry;
 
 // Structure for the GeometryData that we extract from geojson features
 #[derive(Serialize, Deserialize, Clone)]
@@ -12,4 +21,268 @@ pub struct GeometryData {
     pub properties: Option<serde_json::Value>, // Original properties
 }
 
-// Add any additional functionality related to GeoJSON feature processing here
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GeoJsonImportSummary {
+    /// Features in the document
+    pub features: usize,
+    /// Geometries stored; multi-part geometries count once per part
+    pub geometries: usize,
+    /// Features without a usable geometry
+    pub skipped: usize,
+    /// [minLng, minLat, maxLng, maxLat] of the stored geometries
+    pub bbox: Option<[f64; 4]>,
+}
+
+/// [lng, lat] of a GeoJSON position; altitude is dropped
+fn position(value: &Value) -> Option<Vec<f64>> {
+    let coords = value.as_array()?;
+    let lng = coords.first()?.as_f64()?;
+    let lat = coords.get(1)?.as_f64()?;
+    (lng.is_finite() && lat.is_finite()).then(|| vec![lng, lat])
+}
+
+fn positions(value: &Value) -> Option<Vec<Vec<f64>>> {
+    value.as_array()?.iter().map(position).collect()
+}
+
+type Ring = Vec<Vec<f64>>;
+
+/// Exterior ring and holes of a GeoJSON polygon
+fn polygon_rings(value: &Value) -> Option<(Ring, Vec<Ring>)> {
+    let mut rings = value
+        .as_array()?
+        .iter()
+        .map(positions)
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .filter(|ring| ring.len() >= 3);
+    let exterior = rings.next()?;
+    Some((exterior, rings.collect()))
+}
+
+/// Height from the same properties vector tile extraction uses
+fn feature_height(properties: &Value) -> f64 {
+    ["height", "render_height", "ele"]
+        .iter()
+        .find_map(|name| properties.get(name).and_then(Value::as_f64).filter(|&h| h > 0.0))
+        .unwrap_or(0.0)
+}
+
+/// Append the parts of a GeoJSON geometry; Multi* geometries and GeometryCollections
+/// are split into one entry per part as in vector tile extraction
+fn append_geometry(
+    geometry: &Value,
+    properties: &Value,
+    layer_name: &str,
+    out: &mut Vec<polygon_geometry::GeometryData>,
+) {
+    let coordinates = &geometry["coordinates"];
+    let mut push = |kind: &str, points: Ring, holes: Vec<Ring>| {
+        out.push(polygon_geometry::GeometryData {
+            geometry: points,
+            holes: (!holes.is_empty()).then_some(holes),
+            r#type: Some(kind.to_string()),
+            height: Some(feature_height(properties)),
+            layer: Some(layer_name.to_string()),
+            label: None,
+            tags: None,
+            properties: Some(properties.clone()),
+        });
+    };
+    let parts = || coordinates.as_array().into_iter().flatten();
+    match geometry["type"].as_str() {
+        Some("Point") => {
+            if let Some(point) = position(coordinates) {
+                push("Point", vec![point], Vec::new());
+            }
+        }
+        Some("MultiPoint") => {
+            for point in parts().filter_map(position) {
+                push("Point", vec![point], Vec::new());
+            }
+        }
+        Some("LineString") => {
+            if let Some(line) = positions(coordinates).filter(|line| line.len() >= 2) {
+                push("LineString", line, Vec::new());
+            }
+        }
+        Some("MultiLineString") => {
+            for line in parts().filter_map(positions).filter(|line| line.len() >= 2) {
+                push("LineString", line, Vec::new());
+            }
+        }
+        Some("Polygon") => {
+            if let Some((exterior, holes)) = polygon_rings(coordinates) {
+                push("Polygon", exterior, holes);
+            }
+        }
+        Some("MultiPolygon") => {
+            for (exterior, holes) in parts().filter_map(polygon_rings) {
+                push("Polygon", exterior, holes);
+            }
+        }
+        Some("GeometryCollection") => {
+            for part in geometry["geometries"].as_array().into_iter().flatten() {
+                append_geometry(part, properties, layer_name, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Geometries of a GeoJSON FeatureCollection, Feature or bare geometry
+pub(crate) fn parse_geojson_features(
+    geojson_str: &str,
+    layer_name: &str,
+) -> Result<(Vec<polygon_geometry::GeometryData>, GeoJsonImportSummary), String> {
+    let geojson: Value =
+        serde_json::from_str(geojson_str).map_err(|e| format!("Invalid GeoJSON: {}", e))?;
+    let features: Vec<(&Value, Value)> = match geojson["type"].as_str() {
+        Some("FeatureCollection") => geojson["features"]
+            .as_array()
+            .ok_or("FeatureCollection has no features array")?
+            .iter()
+            .map(|feature| (&feature["geometry"], feature["properties"].clone()))
+            .collect(),
+        Some("Feature") => vec![(&geojson["geometry"], geojson["properties"].clone())],
+        Some(_) => vec![(&geojson, Value::Null)],
+        None => return Err("GeoJSON object has no type".to_string()),
+    };
+
+    let mut geometries = Vec::new();
+    let mut skipped = 0;
+    for (geometry, properties) in &features {
+        let properties = match properties {
+            Value::Object(_) => properties.clone(),
+            _ => Value::Object(Default::default()),
+        };
+        let before = geometries.len();
+        append_geometry(geometry, &properties, layer_name, &mut geometries);
+        if geometries.len() == before {
+            skipped += 1;
+        }
+    }
+
+    let bbox = geometries
+        .iter()
+        .flat_map(|g| g.geometry.iter())
+        .fold(None, |bbox: Option<[f64; 4]>, p| {
+            let [min_lng, min_lat, max_lng, max_lat] =
+                bbox.unwrap_or([p[0], p[1], p[0], p[1]]);
+            Some([
+                min_lng.min(p[0]),
+                min_lat.min(p[1]),
+                max_lng.max(p[0]),
+                max_lat.max(p[1]),
+            ])
+        });
+    let summary = GeoJsonImportSummary {
+        features: features.len(),
+        geometries: geometries.len(),
+        skipped,
+        bbox,
+    };
+    Ok((geometries, summary))
+}
+
+/// Store features as layer `layer_name` of a process, under the key process_polygon_geometry
+/// reads for a VtDataSet with that `sourceLayer` and no filter
+pub(crate) fn store_layer_features(
+    process_id: &str,
+    layer_name: &str,
+    geometries: &[polygon_geometry::GeometryData],
+) -> Result<(), String> {
+    let json = serde_json::to_string(geometries)
+        .map_err(|e| format!("Failed to serialize features: {}", e))?;
+    let data_key = make_process_cache_key(
+        process_id,
+        &make_inner_key_from_filter(layer_name, None, None),
+    );
+    ModuleState::with_mut(|state| state.add_process_feature_data(process_id, &data_key, json));
+    Ok(())
+}
+
+/// Store the features of a GeoJSON document (FeatureCollection, Feature or geometry) as
+/// layer `layer_name` of a process. A VtDataSet with `sourceLayer` set to `layer_name`
+/// and no filter then extrudes them in process_polygon_geometry; properties are kept for
+/// height and per-feature styling. Importing a layer again replaces its features.
+/// Returns a summary with the feature counts and bbox.
+#[wasm_bindgen]
+pub fn import_geojson_features(
+    process_id: &str,
+    layer_name: &str,
+    geojson_str: &str,
+) -> Result<JsValue, JsValue> {
+    if layer_name.is_empty() {
+        return Err(JsValue::from_str("Layer name must not be empty"));
+    }
+    let (geometries, summary) = parse_geojson_features(geojson_str, layer_name)
+        .map_err(|e| JsValue::from_str(&format!("Failed to import GeoJSON: {}", e)))?;
+    if summary.skipped > 0 {
+        console::record(
+            LogLevel::Warn,
+            "geojson",
+            Some(process_id),
+            format!(
+                "Skipped {} of {} GeoJSON features without a usable geometry",
+                summary.skipped, summary.features
+            ),
+        );
+    }
+
+    store_layer_features(process_id, layer_name, &geometries).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&summary)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_feature_collection() {
+        let geojson = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "properties": {"height": 12, "name": "a"},
+                 "geometry": {"type": "Polygon", "coordinates": [
+                    [[0, 0], [4, 0], [4, 4], [0, 4], [0, 0]],
+                    [[1, 1], [2, 1], [2, 2], [1, 1]]]}},
+                {"type": "Feature", "properties": null,
+                 "geometry": {"type": "MultiLineString", "coordinates": [
+                    [[5, 5, 100], [6, 6, 100]], [[7, 7]]]}},
+                {"type": "Feature", "properties": {},
+                 "geometry": {"type": "GeometryCollection", "geometries": [
+                    {"type": "Point", "coordinates": [-1, -2]},
+                    {"type": "MultiPolygon", "coordinates": [
+                        [[[8, 8], [9, 8], [9, 9], [8, 8]]]]}]}},
+                {"type": "Feature", "properties": {}, "geometry": null}
+            ]
+        }"#;
+        let (geometries, summary) = parse_geojson_features(geojson, "custom").unwrap();
+        assert_eq!(
+            summary,
+            GeoJsonImportSummary {
+                features: 4,
+                geometries: 4,
+                skipped: 1,
+                bbox: Some([-1.0, -2.0, 9.0, 9.0]),
+            }
+        );
+
+        let kinds: Vec<_> = geometries.iter().map(|g| g.r#type.as_deref()).collect();
+        assert_eq!(
+            kinds,
+            [Some("Polygon"), Some("LineString"), Some("Point"), Some("Polygon")]
+        );
+        assert_eq!(geometries[0].height, Some(12.0));
+        assert_eq!(geometries[0].holes.as_ref().map(Vec::len), Some(1));
+        assert_eq!(geometries[1].geometry, vec![vec![5.0, 5.0], vec![6.0, 6.0]]);
+        assert_eq!(geometries[1].height, Some(0.0));
+        assert_eq!(geometries[3].layer.as_deref(), Some("custom"));
+
+        let (point, _) =
+            parse_geojson_features(r#"{"type": "Point", "coordinates": [3, 4]}"#, "p").unwrap();
+        assert_eq!(point[0].geometry, vec![vec![3.0, 4.0]]);
+        assert!(parse_geojson_features(r#"{"features": []}"#, "x").is_err());
+    }
+}
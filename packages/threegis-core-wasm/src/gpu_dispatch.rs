@@ -0,0 +1,104 @@
+// Dispatch scheduling for large GPU workloads. A workload is split into slices of at most
+// MAX_WORKGROUPS_PER_SUBMISSION workgroups, each submitted and awaited on its own, and the
+// process cancellation token is checked between submissions so a cancelled process stops
+// occupying the GPU after the slice in flight instead of after the whole workload.
+use wasm_bindgen::prelude::*;
+use wgpu::{Device, Queue};
+
+use crate::cancellation::{get_cancellation_token, CancellationToken};
+
+// Small enough that one submission finishes within a frame on integrated GPUs
+pub(crate) const MAX_WORKGROUPS_PER_SUBMISSION: u32 = 256;
+
+/// Range of workgroups along the sliced dispatch dimension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct DispatchSlice {
+    pub first: u32,
+    pub count: u32,
+}
+
+/// Split `total` workgroups into consecutive slices of at most `per_slice`
+pub(crate) fn dispatch_slices(total: u32, per_slice: u32) -> impl Iterator<Item = DispatchSlice> {
+    let per_slice = per_slice.max(1);
+    (0..total.div_ceil(per_slice)).map(move |i| {
+        let first = i * per_slice;
+        DispatchSlice {
+            first,
+            count: per_slice.min(total - first),
+        }
+    })
+}
+
+/// Workgroup rows per slice of a 2D dispatch `workgroups_x` wide; at least one row
+pub(crate) fn rows_per_slice(workgroups_x: u32) -> u32 {
+    (MAX_WORKGROUPS_PER_SUBMISSION / workgroups_x.max(1)).max(1)
+}
+
+/// Cancellation state of the process a GPU workload belongs to. Processes are cancelled
+/// with `cancel_operation(processId)` on a token created by `create_cancellation_token`.
+pub(crate) struct GpuCancellation {
+    token: Option<CancellationToken>,
+}
+
+impl GpuCancellation {
+    pub fn for_process(process_id: Option<&str>) -> Self {
+        GpuCancellation {
+            token: process_id.and_then(get_cancellation_token),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.as_ref().is_some_and(|t| t.is_cancelled())
+    }
+
+    /// Error out before the next submission once the process has been cancelled
+    pub fn check(&self) -> Result<(), JsValue> {
+        match &self.token {
+            Some(token) if token.is_cancelled() => Err(JsValue::from_str(&format!(
+                "GPU work for {} was cancelled",
+                token.id
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Resolve once the GPU has finished all work submitted to `queue` so far
+pub(crate) async fn submitted_work_done(device: &Device, queue: &Queue) {
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    queue.on_submitted_work_done(move || {
+        let _ = sender.send(());
+    });
+    // Blocks natively; in the browser the callback fires from the event loop instead
+    device.poll(wgpu::Maintain::Wait);
+    let _ = receiver.await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancellation::{cancel_operation, create_cancellation_token};
+
+    #[test]
+    fn test_slices_cover_the_dispatch_and_cancellation_is_seen() {
+        let slices: Vec<DispatchSlice> = dispatch_slices(10, 4).collect();
+        assert_eq!(
+            slices,
+            vec![
+                DispatchSlice { first: 0, count: 4 },
+                DispatchSlice { first: 4, count: 4 },
+                DispatchSlice { first: 8, count: 2 },
+            ]
+        );
+        assert_eq!(dispatch_slices(0, 4).count(), 0);
+        assert_eq!(rows_per_slice(32), 8);
+        assert_eq!(rows_per_slice(1000), 1);
+
+        create_cancellation_token("gpu-dispatch-test");
+        let cancellation = GpuCancellation::for_process(Some("gpu-dispatch-test"));
+        assert!(cancellation.check().is_ok());
+        cancel_operation("gpu-dispatch-test");
+        assert!(cancellation.is_cancelled());
+        assert!(!GpuCancellation::for_process(None).is_cancelled());
+    }
+}
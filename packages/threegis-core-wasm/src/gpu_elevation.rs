@@ -9,7 +9,12 @@ use wgpu::{
 use wgpu::util::DeviceExt;
 use bytemuck::{Pod, Zeroable};
 
-use crate::elevation::{ElevationProcessingInput, ElevationProcessingResult, GridSize};
+use crate::elevation::{ElevationEncoding, ElevationProcessingInput, ElevationProcessingResult, GridSize};
+use std::future::Future;
+
+use crate::gpu_dispatch::{dispatch_slices, rows_per_slice, submitted_work_done, GpuCancellation};
+use crate::gpu_manager::{self, DeviceLoss, GpuProcessor};
+use crate::gpu_profiler::{self, PassTimer};
 use crate::module_state::TileData;
 
 // GPU-compatible data structures using bytemuck for zero-copy serialization
@@ -38,7 +43,11 @@ struct GridParams {
     bbox_max_lng: f32,
     bbox_max_lat: f32,
     num_tiles: u32,
-    _padding: u32, // Align to 16-byte boundary
+    row_offset: u32, // First grid row of the current dispatch slice
+    // Elevation = encoding_offset + (R * 65536 + G * 256 + B) * encoding_scale
+    encoding_scale: f32,
+    encoding_offset: f32,
+    _padding: [u32; 2], // Keep the uniform a multiple of 16 bytes
 }
 
 #[repr(C)]
@@ -168,13 +177,17 @@ struct GridParams {
     bbox_max_lng: f32,
     bbox_max_lat: f32,
     num_tiles: u32,
-    padding: u32,
+    row_offset: u32,
+    encoding_scale: f32,
+    encoding_offset: f32,
+    padding: vec2<u32>,
 }
 
-// Convert RGBA pixel to elevation using Mapbox Terrain-RGB encoding
+// Convert RGBA pixel to elevation; Mapbox Terrain-RGB, Terrarium and custom encodings are
+// all a scale and offset of the 24-bit RGB value
 fn pixel_to_elevation(r: u32, g: u32, b: u32) -> f32 {
     let value = r * 65536u + g * 256u + b;
-    return -10000.0 + f32(value) * 0.1;
+    return params.encoding_offset + f32(value) * params.encoding_scale;
 }
 
 // Unpack RGBA from u32 (assumes little-endian RGBA)
@@ -235,7 +248,7 @@ fn sample_tile_elevation(tile_idx: u32, frac_x: f32, frac_y: f32) -> f32 {
 @compute @workgroup_size(8, 8, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let gx = global_id.x;
-    let gy = global_id.y;
+    let gy = global_id.y + params.row_offset;
 
     if (gx >= params.grid_width || gy >= params.grid_height) {
         return;
@@ -292,12 +305,29 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 pub struct GpuElevationProcessor {
     device: Device,
     queue: Queue,
+    device_loss: DeviceLoss,
     compute_pipeline: ComputePipeline,
     bind_group_layout: BindGroupLayout,
+    #[allow(dead_code)]
     vertex_alignment_pipeline: Option<ComputePipeline>,
+    #[allow(dead_code)]
     vertex_alignment_bind_group_layout: Option<BindGroupLayout>,
 }
 
+impl GpuProcessor for GpuElevationProcessor {
+    fn create() -> impl Future<Output = Result<Self, JsValue>> {
+        Self::new()
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn device_loss(&self) -> &DeviceLoss {
+        &self.device_loss
+    }
+}
+
 impl GpuElevationProcessor {
     pub async fn new() -> Result<Self, JsValue> {
 
@@ -320,13 +350,14 @@ impl GpuElevationProcessor {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("GPU Elevation Device"),
-                    required_features: wgpu::Features::empty(),
+                    required_features: gpu_profiler::optional_features(&adapter),
                     required_limits: wgpu::Limits::downlevel_webgl2_defaults(),
                 },
                 None,
             )
             .await
             .map_err(|e| JsValue::from_str(&format!("Failed to create device: {:?}", e)))?;
+        let device_loss = DeviceLoss::watch(&device);
 
         // Create compute shader
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -474,6 +505,7 @@ impl GpuElevationProcessor {
         Ok(Self {
             device,
             queue,
+            device_loss,
             compute_pipeline,
             bind_group_layout,
             vertex_alignment_pipeline: Some(vertex_alignment_pipeline),
@@ -485,6 +517,7 @@ impl GpuElevationProcessor {
         &self,
         input: &ElevationProcessingInput,
         tile_data: &[TileData],
+        encoding: ElevationEncoding,
     ) -> Result<ElevationProcessingResult, JsValue> {
 
         let grid_width = input.grid_width as usize;
@@ -532,7 +565,10 @@ impl GpuElevationProcessor {
             bbox_max_lng: input.max_lng as f32,
             bbox_max_lat: input.max_lat as f32,
             num_tiles: tile_data.len() as u32,
-            _padding: 0,
+            row_offset: 0,
+            encoding_scale: encoding.scale_offset().0 as f32,
+            encoding_offset: encoding.scale_offset().1 as f32,
+            _padding: [0; 2],
         };
 
         // Create GPU buffers
@@ -551,7 +587,7 @@ impl GpuElevationProcessor {
         let params_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Grid Params Buffer"),
             contents: bytemuck::cast_slice(&[grid_params]),
-            usage: BufferUsages::UNIFORM,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
         let elevation_buffer = self.device.create_buffer(&BufferDescriptor {
@@ -596,28 +632,46 @@ impl GpuElevationProcessor {
             ],
         });
 
-        // Dispatch compute shader
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Elevation Compute Encoder"),
-        });
-
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Elevation Compute Pass"),
-                timestamp_writes: None,
+        // Dispatch compute shader in row slices, one submission each, checking for
+        // cancellation of the process in between
+        let cancellation = GpuCancellation::for_process(Some(&input.process_id));
+        let workgroup_size = 8;
+        let num_workgroups_x = grid_width.div_ceil(workgroup_size) as u32;
+        let num_workgroups_y = grid_height.div_ceil(workgroup_size) as u32;
+
+        let timer = PassTimer::new(&self.device, &self.queue);
+        for slice in dispatch_slices(num_workgroups_y, rows_per_slice(num_workgroups_x)) {
+            cancellation.check()?;
+            let slice_params = GridParams {
+                row_offset: slice.first * workgroup_size as u32,
+                ..grid_params
+            };
+            self.queue.write_buffer(&params_buffer, 0, bytemuck::bytes_of(&slice_params));
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Elevation Compute Encoder"),
             });
-
-            compute_pass.set_pipeline(&self.compute_pipeline);
-            compute_pass.set_bind_group(0, &bind_group, &[]);
-
-            // Dispatch with appropriate workgroup size
-            let workgroup_size_x = 8;
-            let workgroup_size_y = 8;
-            let num_workgroups_x = (grid_width + workgroup_size_x - 1) / workgroup_size_x;
-            let num_workgroups_y = (grid_height + workgroup_size_y - 1) / workgroup_size_y;
-
-            compute_pass.dispatch_workgroups(num_workgroups_x as u32, num_workgroups_y as u32, 1);
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("Elevation Compute Pass"),
+                    timestamp_writes: timer.as_ref().map(PassTimer::timestamp_writes),
+                });
+
+                compute_pass.set_pipeline(&self.compute_pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(num_workgroups_x, slice.count, 1);
+            }
+            if let Some(timer) = &timer {
+                timer.resolve(&mut encoder);
+            }
+            self.queue.submit(std::iter::once(encoder.finish()));
+            submitted_work_done(&self.device, &self.queue).await;
+            self.device_loss.check()?;
+            if let Some(timer) = &timer {
+                timer.record(&self.device, "Elevation Compute Pass", Some(&input.process_id)).await;
+            }
         }
+        cancellation.check()?;
 
         // Create staging buffers to read back results
         let elevation_staging = self.device.create_buffer(&BufferDescriptor {
@@ -634,6 +688,9 @@ impl GpuElevationProcessor {
             mapped_at_creation: false,
         });
 
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Elevation Readback Encoder"),
+        });
         encoder.copy_buffer_to_buffer(&elevation_buffer, 0, &elevation_staging, 0, elevation_buffer.size());
         encoder.copy_buffer_to_buffer(&coverage_buffer, 0, &coverage_staging, 0, coverage_buffer.size());
 
@@ -648,6 +705,8 @@ impl GpuElevationProcessor {
 
         self.device.poll(wgpu::Maintain::Wait);
 
+        self.device_loss.check()?;
+
         let elevation_data = elevation_slice.get_mapped_range();
         let coverage_data = coverage_slice.get_mapped_range();
 
@@ -659,8 +718,8 @@ impl GpuElevationProcessor {
         let mut min_elevation = f64::INFINITY;
         let mut max_elevation = f64::NEG_INFINITY;
 
-        for y in 0..grid_height {
-            for x in 0..grid_width {
+        for (y, row) in elevation_grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
                 let idx = y * grid_width + x;
                 let elevation = elevation_values[idx] as f64;
                 let coverage = coverage_values[idx] as f64;
@@ -671,7 +730,7 @@ impl GpuElevationProcessor {
                     0.0 // Default for uncovered areas
                 };
 
-                elevation_grid[y][x] = final_elevation;
+                *cell = final_elevation;
                 min_elevation = min_elevation.min(final_elevation);
                 max_elevation = max_elevation.max(final_elevation);
             }
@@ -692,7 +751,8 @@ impl GpuElevationProcessor {
         })
     }
 
-    pub async fn align_vertices_to_terrain_gpu(
+    #[allow(dead_code)]
+    async fn align_vertices_to_terrain_gpu(
         &self,
         vertices: &mut [f32],
         elevation_grid: &[f32],
@@ -747,6 +807,7 @@ impl GpuElevationProcessor {
         });
 
         // Dispatch compute shader
+        let timer = PassTimer::new(&self.device, &self.queue);
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Vertex Alignment Encoder"),
         });
@@ -754,7 +815,7 @@ impl GpuElevationProcessor {
         {
             let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
                 label: Some("Vertex Alignment Compute Pass"),
-                timestamp_writes: None,
+                timestamp_writes: timer.as_ref().map(PassTimer::timestamp_writes),
             });
 
             compute_pass.set_pipeline(vertex_alignment_pipeline);
@@ -763,10 +824,13 @@ impl GpuElevationProcessor {
             // Dispatch with workgroup size of 64
             let num_vertices = alignment_params.num_vertices;
             let workgroup_size = 64;
-            let num_workgroups = (num_vertices + workgroup_size - 1) / workgroup_size;
+            let num_workgroups = num_vertices.div_ceil(workgroup_size);
 
             compute_pass.dispatch_workgroups(num_workgroups, 1, 1);
         }
+        if let Some(timer) = &timer {
+            timer.resolve(&mut encoder);
+        }
 
         // Create staging buffer to read back results
         let vertex_staging = self.device.create_buffer(&BufferDescriptor {
@@ -783,6 +847,10 @@ impl GpuElevationProcessor {
         let vertex_slice = vertex_staging.slice(..);
         vertex_slice.map_async(wgpu::MapMode::Read, |_| {});
         self.device.poll(wgpu::Maintain::Wait);
+        self.device_loss.check()?;
+        if let Some(timer) = &timer {
+            timer.record(&self.device, "Vertex Alignment Compute Pass", None).await;
+        }
 
         let vertex_data = vertex_slice.get_mapped_range();
         let aligned_vertices: &[f32] = bytemuck::cast_slice(&vertex_data);
@@ -794,39 +862,34 @@ impl GpuElevationProcessor {
     }
 }
 
-// Global GPU processor instance
-static mut GPU_PROCESSOR: Option<GpuElevationProcessor> = None;
-
-// Check if WebGPU is available and initialize GPU processor
+// Check if WebGPU is available and initialize GPU processor; it is recreated
+// automatically after device loss
 #[wasm_bindgen]
 pub async fn init_gpu_elevation_processor() -> Result<bool, JsValue> {
-    match GpuElevationProcessor::new().await {
-        Ok(processor) => {
-            unsafe {
-                GPU_PROCESSOR = Some(processor);
-            }
-            Ok(true)
-        }
-        Err(e) => {
-            Ok(false)
-        }
-    }
+    Ok(gpu_manager::elevation_gpu().init().await)
 }
 
-// GPU-accelerated elevation processing function
+// Destroy the GPU device and drop its pipelines; false if none was initialized
+pub(crate) fn release_gpu_elevation_processor() -> bool {
+    gpu_manager::elevation_gpu().release()
+}
+
+// GPU-accelerated elevation processing function. Errors when no GPU is usable, so the
+// caller can process on the CPU instead.
 pub async fn process_elevation_gpu(
     input: &ElevationProcessingInput,
     tile_data: &[TileData],
+    encoding: ElevationEncoding,
 ) -> Result<ElevationProcessingResult, JsValue> {
-    unsafe {
-        match &GPU_PROCESSOR {
-            Some(processor) => processor.process_elevation_gpu(input, tile_data).await,
-            None => Err(JsValue::from_str("GPU processor not initialized")),
-        }
-    }
+    let processor = gpu_manager::elevation_gpu()
+        .acquire()
+        .await
+        .ok_or_else(|| JsValue::from_str("GPU elevation processing unavailable"))?;
+    processor.process_elevation_gpu(input, tile_data, encoding).await
 }
 
 // GPU-accelerated vertex alignment function
+#[allow(dead_code, clippy::too_many_arguments)]
 pub async fn align_vertices_to_terrain_gpu(
     vertices: &mut [f32],
     elevation_grid: &[f32],
@@ -842,27 +905,24 @@ pub async fn align_vertices_to_terrain_gpu(
     grid_height: u32,
     terrain_size: f64,
 ) -> Result<(), JsValue> {
-    unsafe {
-        match &GPU_PROCESSOR {
-            Some(processor) => {
-                let alignment_params = AlignmentParams {
-                    bbox_min_lng: bbox_min_lng as f32,
-                    bbox_min_lat: bbox_min_lat as f32,
-                    bbox_max_lng: bbox_max_lng as f32,
-                    bbox_max_lat: bbox_max_lat as f32,
-                    min_elevation: min_elevation as f32,
-                    max_elevation: max_elevation as f32,
-                    vertical_exaggeration: vertical_exaggeration as f32,
-                    terrain_base_height: terrain_base_height as f32,
-                    grid_width,
-                    grid_height,
-                    num_vertices: (vertices.len() / 3) as u32,
-                    terrain_size: terrain_size as f32,
-                };
-
-                processor.align_vertices_to_terrain_gpu(vertices, elevation_grid, alignment_params).await
-            }
-            None => Err(JsValue::from_str("GPU processor not initialized")),
-        }
-    }
-}
\ No newline at end of file
+    let processor = gpu_manager::elevation_gpu()
+        .acquire()
+        .await
+        .ok_or_else(|| JsValue::from_str("GPU elevation processing unavailable"))?;
+    let alignment_params = AlignmentParams {
+        bbox_min_lng: bbox_min_lng as f32,
+        bbox_min_lat: bbox_min_lat as f32,
+        bbox_max_lng: bbox_max_lng as f32,
+        bbox_max_lat: bbox_max_lat as f32,
+        min_elevation: min_elevation as f32,
+        max_elevation: max_elevation as f32,
+        vertical_exaggeration: vertical_exaggeration as f32,
+        terrain_base_height: terrain_base_height as f32,
+        grid_width,
+        grid_height,
+        num_vertices: (vertices.len() / 3) as u32,
+        terrain_size: terrain_size as f32,
+    };
+
+    processor.align_vertices_to_terrain_gpu(vertices, elevation_grid, alignment_params).await
+}
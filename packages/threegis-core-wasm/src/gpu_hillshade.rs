@@ -0,0 +1,400 @@
+// GPU-accelerated terrain shading (hillshade, slope, aspect) using WebGPU compute shaders
+use wasm_bindgen::prelude::*;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BufferBindingType,
+    BufferDescriptor, BufferUsages, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, Queue, ShaderStages,
+};
+use wgpu::util::DeviceExt;
+use bytemuck::{Pod, Zeroable};
+use std::future::Future;
+
+use crate::gpu_dispatch::{dispatch_slices, rows_per_slice, submitted_work_done, GpuCancellation};
+use crate::gpu_manager::{self, DeviceLoss, GpuProcessor};
+use crate::gpu_profiler::{self, PassTimer};
+use crate::hillshade::{ShadingImages, ShadingParams};
+
+// GPU-compatible data structures
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ShaderParams {
+    grid_width: u32,
+    grid_height: u32,
+    row_offset: u32, // First grid row of the current dispatch slice
+    cell_width: f32,
+    cell_height: f32,
+    azimuth: f32,
+    zenith: f32,
+    _padding: u32, // Keep the uniform a multiple of 16 bytes
+}
+
+// WebGPU compute shader writing the three images as packed RGBA pixels: hillshade, then
+// slope, then aspect, each north-up
+const HILLSHADE_COMPUTE_SHADER: &str = r#"
+@group(0) @binding(0) var<storage, read> elevation_grid: array<f32>;
+@group(0) @binding(1) var<uniform> params: ShaderParams;
+@group(0) @binding(2) var<storage, read_write> pixels: array<u32>;
+
+struct ShaderParams {
+    grid_width: u32,
+    grid_height: u32,
+    row_offset: u32,
+    cell_width: f32,
+    cell_height: f32,
+    azimuth: f32,
+    zenith: f32,
+    padding: u32,
+}
+
+const TAU: f32 = 6.283185307179586;
+const HALF_PI: f32 = 1.5707963267948966;
+
+// Elevation with coordinates clamped to the grid, so border cells repeat their edge
+fn elevation_at(x: i32, y: i32) -> f32 {
+    let cx = u32(clamp(x, 0, i32(params.grid_width) - 1));
+    let cy = u32(clamp(y, 0, i32(params.grid_height) - 1));
+    return elevation_grid[cy * params.grid_width + cx];
+}
+
+fn gray_pixel(level: u32) -> u32 {
+    return level | (level << 8u) | (level << 16u) | (255u << 24u);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y + params.row_offset;
+
+    if (x >= params.grid_width || y >= params.grid_height) {
+        return;
+    }
+
+    // Horn's gradient, +x east and +y north (grid row 0 is the southern edge)
+    let xi = i32(x);
+    let yi = i32(y);
+    let dzdx = ((elevation_at(xi + 1, yi + 1) + 2.0 * elevation_at(xi + 1, yi) + elevation_at(xi + 1, yi - 1))
+        - (elevation_at(xi - 1, yi + 1) + 2.0 * elevation_at(xi - 1, yi) + elevation_at(xi - 1, yi - 1)))
+        / (8.0 * params.cell_width);
+    let dzdy = ((elevation_at(xi - 1, yi + 1) + 2.0 * elevation_at(xi, yi + 1) + elevation_at(xi + 1, yi + 1))
+        - (elevation_at(xi - 1, yi - 1) + 2.0 * elevation_at(xi, yi - 1) + elevation_at(xi + 1, yi - 1)))
+        / (8.0 * params.cell_height);
+
+    let slope = atan(sqrt(dzdx * dzdx + dzdy * dzdy));
+    var aspect = atan2(-dzdx, -dzdy);
+    if (aspect < 0.0) {
+        aspect = aspect + TAU;
+    }
+    let shade = cos(params.zenith) * cos(slope)
+        + sin(params.zenith) * sin(slope) * cos(params.azimuth - aspect);
+
+    let shade_level = u32(round(clamp(shade, 0.0, 1.0) * 255.0));
+    let slope_level = u32(round(clamp(slope / HALF_PI, 0.0, 1.0) * 255.0));
+    var aspect_level = 0u;
+    if (dzdx != 0.0 || dzdy != 0.0) {
+        aspect_level = 1u + u32(round(min(aspect / TAU, 1.0) * 254.0));
+    }
+
+    // Images are north-up
+    let pixel = (params.grid_height - 1u - y) * params.grid_width + x;
+    let count = params.grid_width * params.grid_height;
+    pixels[pixel] = gray_pixel(shade_level);
+    pixels[count + pixel] = gray_pixel(slope_level);
+    pixels[2u * count + pixel] = gray_pixel(aspect_level);
+}
+"#;
+
+pub struct GpuHillshadeProcessor {
+    device: Device,
+    queue: Queue,
+    device_loss: DeviceLoss,
+    compute_pipeline: ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl GpuProcessor for GpuHillshadeProcessor {
+    fn create() -> impl Future<Output = Result<Self, JsValue>> {
+        Self::new()
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn device_loss(&self) -> &DeviceLoss {
+        &self.device_loss
+    }
+}
+
+impl GpuHillshadeProcessor {
+    pub async fn new() -> Result<Self, JsValue> {
+        // Request WebGPU adapter and device
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::BROWSER_WEBGPU,
+            ..Default::default()
+        });
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await
+            .ok_or_else(|| JsValue::from_str("Failed to find WebGPU adapter"))?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("GPU Hillshade Device"),
+                    required_features: gpu_profiler::optional_features(&adapter),
+                    required_limits: wgpu::Limits::downlevel_webgl2_defaults(),
+                },
+                None,
+            )
+            .await
+            .map_err(|e| JsValue::from_str(&format!("Failed to create device: {:?}", e)))?;
+        let device_loss = DeviceLoss::watch(&device);
+
+        // Create compute shader
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Hillshade Compute Shader"),
+            source: wgpu::ShaderSource::Wgsl(HILLSHADE_COMPUTE_SHADER.into()),
+        });
+
+        // Create bind group layout
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Hillshade Bind Group Layout"),
+            entries: &[
+                // Elevation grid
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Shading parameters
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Pixels output
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // Create compute pipeline
+        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Hillshade Compute Pipeline"),
+            layout: Some(&device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("Hillshade Pipeline Layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                },
+            )),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            device_loss,
+            compute_pipeline,
+            bind_group_layout,
+        })
+    }
+
+    pub async fn shade_grid_gpu(
+        &self,
+        grid: &[Vec<f64>],
+        params: &ShadingParams,
+        process_id: Option<&str>,
+    ) -> Result<ShadingImages, JsValue> {
+        let grid_height = grid.len();
+        let grid_width = grid.first().map_or(0, Vec::len);
+        let cell_count = grid_width * grid_height;
+
+        // Non-finite elevations shade like the CPU path, as zero
+        let elevations: Vec<f32> = grid
+            .iter()
+            .flatten()
+            .map(|&e| if e.is_finite() { e as f32 } else { 0.0 })
+            .collect();
+
+        let shader_params = ShaderParams {
+            grid_width: grid_width as u32,
+            grid_height: grid_height as u32,
+            row_offset: 0,
+            cell_width: params.cell_width as f32,
+            cell_height: params.cell_height as f32,
+            azimuth: params.azimuth as f32,
+            zenith: params.zenith as f32,
+            _padding: 0,
+        };
+
+        // Create GPU buffers
+        let elevation_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Hillshade Elevation Buffer"),
+            contents: bytemuck::cast_slice(&elevations),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let params_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Hillshade Params Buffer"),
+            contents: bytemuck::cast_slice(&[shader_params]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let pixel_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Hillshade Pixel Buffer"),
+            size: (3 * cell_count * std::mem::size_of::<u32>()) as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // Create bind group
+        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Hillshade Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: elevation_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: pixel_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // Dispatch compute shader in row slices, one submission each, checking for
+        // cancellation of the process in between
+        let cancellation = GpuCancellation::for_process(process_id);
+        let workgroup_size = 8;
+        let num_workgroups_x = grid_width.div_ceil(workgroup_size) as u32;
+        let num_workgroups_y = grid_height.div_ceil(workgroup_size) as u32;
+
+        let timer = PassTimer::new(&self.device, &self.queue);
+        for slice in dispatch_slices(num_workgroups_y, rows_per_slice(num_workgroups_x)) {
+            cancellation.check()?;
+            let slice_params = ShaderParams {
+                row_offset: slice.first * workgroup_size as u32,
+                ..shader_params
+            };
+            self.queue.write_buffer(&params_buffer, 0, bytemuck::bytes_of(&slice_params));
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Hillshade Compute Encoder"),
+            });
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("Hillshade Compute Pass"),
+                    timestamp_writes: timer.as_ref().map(PassTimer::timestamp_writes),
+                });
+
+                compute_pass.set_pipeline(&self.compute_pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(num_workgroups_x, slice.count, 1);
+            }
+            if let Some(timer) = &timer {
+                timer.resolve(&mut encoder);
+            }
+            self.queue.submit(std::iter::once(encoder.finish()));
+            submitted_work_done(&self.device, &self.queue).await;
+            self.device_loss.check()?;
+            if let Some(timer) = &timer {
+                timer.record(&self.device, "Hillshade Compute Pass", process_id).await;
+            }
+        }
+        cancellation.check()?;
+
+        // Create staging buffer to read back results
+        let pixel_staging = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Hillshade Staging Buffer"),
+            size: pixel_buffer.size(),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Hillshade Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&pixel_buffer, 0, &pixel_staging, 0, pixel_buffer.size());
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        // Read back results
+        let pixel_slice = pixel_staging.slice(..);
+        pixel_slice.map_async(wgpu::MapMode::Read, |_| {});
+
+        self.device.poll(wgpu::Maintain::Wait);
+
+        self.device_loss.check()?;
+
+        // Packed pixels are little-endian, so their bytes are already R, G, B, A
+        let pixel_data = pixel_slice.get_mapped_range();
+        let image_bytes = cell_count * 4;
+        let (hillshade, rest) = pixel_data.split_at(image_bytes);
+        let (slope, aspect) = rest.split_at(image_bytes);
+
+        Ok(ShadingImages {
+            width: grid_width,
+            height: grid_height,
+            hillshade: hillshade.to_vec(),
+            slope: slope.to_vec(),
+            aspect: aspect.to_vec(),
+        })
+    }
+}
+
+// Check if WebGPU is available and initialize GPU processor; it is recreated
+// automatically after device loss
+#[wasm_bindgen]
+pub async fn init_gpu_hillshade_processor() -> Result<bool, JsValue> {
+    Ok(gpu_manager::hillshade_gpu().init().await)
+}
+
+// Destroy the GPU device and drop its pipelines; false if none was initialized
+pub(crate) fn release_gpu_hillshade_processor() -> bool {
+    gpu_manager::hillshade_gpu().release()
+}
+
+// GPU-accelerated terrain shading function. Errors when no GPU is usable, so the caller
+// can shade on the CPU instead.
+pub async fn shade_grid_gpu(
+    grid: &[Vec<f64>],
+    params: &ShadingParams,
+    process_id: Option<&str>,
+) -> Result<ShadingImages, JsValue> {
+    let processor = gpu_manager::hillshade_gpu()
+        .acquire()
+        .await
+        .ok_or_else(|| JsValue::from_str("GPU terrain shading unavailable"))?;
+    processor.shade_grid_gpu(grid, params, process_id).await
+}
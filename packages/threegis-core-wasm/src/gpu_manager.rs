@@ -0,0 +1,194 @@
+// Lifecycle of the GPU processors. Each processor watches its device for loss (tab
+// backgrounding, driver reset); a lost processor is dropped and its device and pipelines
+// are recreated on the next request, and a request that finds no usable processor runs
+// on the CPU instead of failing.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::prelude::*;
+use wgpu::Device;
+
+use crate::console::{self, LogLevel};
+use crate::gpu_elevation::GpuElevationProcessor;
+use crate::gpu_hillshade::GpuHillshadeProcessor;
+use crate::gpu_polygon::GpuPolygonProcessor;
+use crate::gpu_terrain::GpuTerrainProcessor;
+
+thread_local! {
+    static ELEVATION_GPU: Rc<GpuSlot<GpuElevationProcessor>> = Rc::new(GpuSlot::new("elevation"));
+    static POLYGON_GPU: Rc<GpuSlot<GpuPolygonProcessor>> = Rc::new(GpuSlot::new("polygon"));
+    static TERRAIN_GPU: Rc<GpuSlot<GpuTerrainProcessor>> = Rc::new(GpuSlot::new("terrain"));
+    static HILLSHADE_GPU: Rc<GpuSlot<GpuHillshadeProcessor>> = Rc::new(GpuSlot::new("hillshade"));
+}
+
+pub(crate) fn elevation_gpu() -> Rc<GpuSlot<GpuElevationProcessor>> {
+    ELEVATION_GPU.with(Rc::clone)
+}
+
+pub(crate) fn polygon_gpu() -> Rc<GpuSlot<GpuPolygonProcessor>> {
+    POLYGON_GPU.with(Rc::clone)
+}
+
+pub(crate) fn terrain_gpu() -> Rc<GpuSlot<GpuTerrainProcessor>> {
+    TERRAIN_GPU.with(Rc::clone)
+}
+
+pub(crate) fn hillshade_gpu() -> Rc<GpuSlot<GpuHillshadeProcessor>> {
+    HILLSHADE_GPU.with(Rc::clone)
+}
+
+/// Loss state of a device, set from its device-lost callback with the reported reason
+#[derive(Clone, Default)]
+pub(crate) struct DeviceLoss(Arc<Mutex<Option<String>>>);
+
+impl DeviceLoss {
+    pub fn watch(device: &Device) -> Self {
+        let loss = DeviceLoss::default();
+        let flag = loss.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            if let Ok(mut lost) = flag.0.lock() {
+                *lost = Some(format!("{:?}: {}", reason, message));
+            }
+        });
+        loss
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        self.0.lock().ok().and_then(|lost| lost.clone())
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.0.lock().map_or(true, |lost| lost.is_some())
+    }
+
+    /// Abort a request whose device was lost before its results are read back
+    pub fn check(&self) -> Result<(), JsValue> {
+        if self.is_lost() {
+            Err(JsValue::from_str("GPU device lost"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A GPU processor owning its device and pipelines
+pub(crate) trait GpuProcessor: Sized {
+    fn create() -> impl Future<Output = Result<Self, JsValue>>;
+    fn device(&self) -> &Device;
+    fn device_loss(&self) -> &DeviceLoss;
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct GpuStatus {
+    /// GPU use was requested with `init_gpu_*_processor`
+    pub enabled: bool,
+    /// A processor with a live device exists
+    pub available: bool,
+    #[serde(rename = "deviceLosses")]
+    pub device_losses: u32,
+    #[serde(rename = "lastLossReason")]
+    pub last_loss_reason: Option<String>,
+}
+
+/// Holds the processor of one kind and recreates it after device loss
+pub(crate) struct GpuSlot<P> {
+    name: &'static str,
+    processor: RefCell<Option<Rc<P>>>,
+    status: RefCell<GpuStatus>,
+}
+
+impl<P: GpuProcessor> GpuSlot<P> {
+    fn new(name: &'static str) -> Self {
+        GpuSlot {
+            name,
+            processor: RefCell::new(None),
+            status: RefCell::new(GpuStatus::default()),
+        }
+    }
+
+    /// Create the processor and keep recreating it after device loss; false if no GPU
+    /// device could be created
+    pub async fn init(&self) -> bool {
+        self.status.borrow_mut().enabled = true;
+        self.recreate().await
+    }
+
+    async fn recreate(&self) -> bool {
+        // Concurrent requests run on the CPU while the device is being recreated
+        *self.processor.borrow_mut() = None;
+        let created = P::create().await.ok().map(Rc::new);
+        let available = created.is_some();
+        *self.processor.borrow_mut() = created;
+        self.status.borrow_mut().available = available;
+        available
+    }
+
+    /// Usable processor, recreated first if its device was lost. None when GPU use was
+    /// not enabled or no device is available; the caller then runs on the CPU.
+    pub async fn acquire(&self) -> Option<Rc<P>> {
+        let current = self.processor.borrow().clone()?;
+        if !current.device_loss().is_lost() {
+            return Some(current);
+        }
+
+        let reason = current.device_loss().reason();
+        drop(current);
+        console::record(
+            LogLevel::Warn,
+            "gpu",
+            None,
+            format!(
+                "GPU {} device lost ({}), recreating it",
+                self.name,
+                reason.as_deref().unwrap_or("unknown reason")
+            ),
+        );
+        {
+            let mut status = self.status.borrow_mut();
+            status.device_losses += 1;
+            status.last_loss_reason = reason;
+        }
+        if self.recreate().await {
+            self.processor.borrow().clone()
+        } else {
+            None
+        }
+    }
+
+    /// Destroy the device and drop the pipelines; false if there was no processor
+    pub fn release(&self) -> bool {
+        self.status.replace(GpuStatus::default());
+        let processor = self.processor.borrow_mut().take();
+        processor
+            .map(|processor| processor.device().destroy())
+            .is_some()
+    }
+
+    pub fn status(&self) -> GpuStatus {
+        self.status.borrow().clone()
+    }
+}
+
+#[derive(Serialize)]
+struct GpuStatusReport {
+    elevation: GpuStatus,
+    polygon: GpuStatus,
+    terrain: GpuStatus,
+    hillshade: GpuStatus,
+}
+
+/// State of the GPU processors as JSON: `{ elevation, polygon, terrain, hillshade }`, each with
+/// `enabled`, `available`, `deviceLosses` and `lastLossReason`
+#[wasm_bindgen]
+pub fn get_gpu_status() -> Result<String, JsValue> {
+    let report = GpuStatusReport {
+        elevation: elevation_gpu().status(),
+        polygon: polygon_gpu().status(),
+        terrain: terrain_gpu().status(),
+        hillshade: hillshade_gpu().status(),
+    };
+    serde_json::to_string(&report)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize GPU status: {}", e)))
+}
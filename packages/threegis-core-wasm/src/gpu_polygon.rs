@@ -8,6 +8,12 @@ use wgpu::{
 };
 use wgpu::util::DeviceExt;
 use bytemuck::{Pod, Zeroable};
+use std::future::Future;
+
+use crate::gpu_manager::{self, DeviceLoss, GpuProcessor};
+use crate::gpu_dispatch::{
+    dispatch_slices, submitted_work_done, GpuCancellation, MAX_WORKGROUPS_PER_SUBMISSION,
+};
 
 // Note: These imports would be used for future polygon processing integrations
 // use crate::polygon_geometry::{GeometryData, BufferGeometry, GridSize};
@@ -43,7 +49,8 @@ struct PolygonClipParams {
     bbox: BoundingBox,
     num_polygons: u32,
     max_points_per_polygon: u32,
-    _padding: [u32; 2],
+    polygon_offset: u32, // First polygon of the current dispatch slice
+    _padding: u32,
 }
 
 // WebGPU compute shader for LineString buffering
@@ -76,7 +83,9 @@ fn calculate_perpendicular(p1: vec2<f32>, p2: vec2<f32>, distance: f32) -> vec2<
 
 // Calculate bisector for smooth corners
 fn calculate_bisector(prev_dir: vec2<f32>, next_dir: vec2<f32>, distance: f32) -> vec2<f32> {
-    let bisector = normalize(prev_dir + next_dir);
+    // Perpendicular to the averaged direction, on the same side as calculate_perpendicular
+    let average = normalize(prev_dir + next_dir);
+    let bisector = vec2<f32>(-average.y, average.x);
     let dot_product = dot(prev_dir, next_dir);
 
     // Avoid extreme scaling for sharp angles
@@ -149,7 +158,8 @@ struct PolygonClipParams {
     bbox: BoundingBox,
     num_polygons: u32,
     max_points_per_polygon: u32,
-    padding: array<u32, 2>,
+    polygon_offset: u32,
+    padding: u32,
 }
 
 // Check if point is inside clipping edge
@@ -191,7 +201,7 @@ fn compute_intersection(p1: vec2<f32>, p2: vec2<f32>, edge_type: u32, clip_value
 
 @compute @workgroup_size(32, 1, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let polygon_idx = global_id.x;
+    let polygon_idx = global_id.x + params.polygon_offset;
 
     if (polygon_idx >= params.num_polygons) {
         return;
@@ -279,12 +289,27 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 pub struct GpuPolygonProcessor {
     device: Device,
     queue: Queue,
+    device_loss: DeviceLoss,
     linestring_pipeline: ComputePipeline,
     polygon_clip_pipeline: ComputePipeline,
     linestring_bind_group_layout: BindGroupLayout,
     polygon_clip_bind_group_layout: BindGroupLayout,
 }
 
+impl GpuProcessor for GpuPolygonProcessor {
+    fn create() -> impl Future<Output = Result<Self, JsValue>> {
+        Self::new()
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn device_loss(&self) -> &DeviceLoss {
+        &self.device_loss
+    }
+}
+
 impl GpuPolygonProcessor {
     pub async fn new() -> Result<Self, JsValue> {
 
@@ -314,6 +339,7 @@ impl GpuPolygonProcessor {
             )
             .await
             .map_err(|e| JsValue::from_str(&format!("Failed to create device: {:?}", e)))?;
+        let device_loss = DeviceLoss::watch(&device);
 
         // Create LineString buffer shader
         let linestring_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -470,6 +496,7 @@ impl GpuPolygonProcessor {
         Ok(Self {
             device,
             queue,
+            device_loss,
             linestring_pipeline,
             polygon_clip_pipeline,
             linestring_bind_group_layout,
@@ -557,7 +584,7 @@ impl GpuPolygonProcessor {
             compute_pass.set_bind_group(0, &bind_group, &[]);
 
             // Dispatch with appropriate workgroup size (64 threads per workgroup)
-            let num_workgroups = (points.len() as u32 + 63) / 64;
+            let num_workgroups = (points.len() as u32).div_ceil(64);
             compute_pass.dispatch_workgroups(num_workgroups, 1, 1);
         }
 
@@ -576,6 +603,7 @@ impl GpuPolygonProcessor {
         let buffer_slice = staging_buffer.slice(..);
         buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
         self.device.poll(wgpu::Maintain::Wait);
+        self.device_loss.check()?;
 
         let data = buffer_slice.get_mapped_range();
         let result_points: &[Point2D] = bytemuck::cast_slice(&data);
@@ -594,6 +622,7 @@ impl GpuPolygonProcessor {
         &self,
         polygons: &[Vec<[f64; 2]>],
         bbox: &[f64; 4],
+        process_id: Option<&str>,
     ) -> Result<Vec<Vec<[f64; 2]>>, JsValue> {
         if polygons.is_empty() {
             return Ok(Vec::new());
@@ -627,7 +656,8 @@ impl GpuPolygonProcessor {
             },
             num_polygons: polygons.len() as u32,
             max_points_per_polygon: max_points_per_polygon as u32,
-            _padding: [0; 2],
+            polygon_offset: 0,
+            _padding: 0,
         };
 
         // Create GPU buffers
@@ -652,7 +682,7 @@ impl GpuPolygonProcessor {
         let params_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Polygon Clip Params Buffer"),
             contents: bytemuck::cast_slice(&[params]),
-            usage: BufferUsages::UNIFORM,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
         let output_buffer = self.device.create_buffer(&BufferDescriptor {
@@ -701,24 +731,37 @@ impl GpuPolygonProcessor {
             ],
         });
 
-        // Dispatch compute shader
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Polygon Clip Compute Encoder"),
-        });
-
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Polygon Clip Compute Pass"),
-                timestamp_writes: None,
+        // Dispatch compute shader in slices, one submission each, checking for
+        // cancellation of the process in between (32 threads per workgroup)
+        let cancellation = GpuCancellation::for_process(process_id);
+        let num_workgroups = (polygons.len() as u32).div_ceil(32);
+
+        for slice in dispatch_slices(num_workgroups, MAX_WORKGROUPS_PER_SUBMISSION) {
+            cancellation.check()?;
+            let slice_params = PolygonClipParams {
+                polygon_offset: slice.first * 32,
+                ..params
+            };
+            self.queue.write_buffer(&params_buffer, 0, bytemuck::bytes_of(&slice_params));
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Polygon Clip Compute Encoder"),
             });
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("Polygon Clip Compute Pass"),
+                    timestamp_writes: None,
+                });
 
-            compute_pass.set_pipeline(&self.polygon_clip_pipeline);
-            compute_pass.set_bind_group(0, &bind_group, &[]);
-
-            // Dispatch with appropriate workgroup size (32 threads per workgroup)
-            let num_workgroups = (polygons.len() as u32 + 31) / 32;
-            compute_pass.dispatch_workgroups(num_workgroups, 1, 1);
+                compute_pass.set_pipeline(&self.polygon_clip_pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(slice.count, 1, 1);
+            }
+            self.queue.submit(std::iter::once(encoder.finish()));
+            submitted_work_done(&self.device, &self.queue).await;
+            self.device_loss.check()?;
         }
+        cancellation.check()?;
 
         // Create staging buffers
         let points_staging = self.device.create_buffer(&BufferDescriptor {
@@ -735,6 +778,9 @@ impl GpuPolygonProcessor {
             mapped_at_creation: false,
         });
 
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Polygon Clip Readback Encoder"),
+        });
         encoder.copy_buffer_to_buffer(&output_buffer, 0, &points_staging, 0, output_buffer.size());
         encoder.copy_buffer_to_buffer(&output_counts_buffer, 0, &counts_staging, 0, output_counts_buffer.size());
 
@@ -749,6 +795,8 @@ impl GpuPolygonProcessor {
 
         self.device.poll(wgpu::Maintain::Wait);
 
+        self.device_loss.check()?;
+
         let points_data = points_slice.get_mapped_range();
         let counts_data = counts_slice.get_mapped_range();
 
@@ -778,47 +826,179 @@ impl GpuPolygonProcessor {
     }
 }
 
-// Global GPU polygon processor instance
-static mut GPU_POLYGON_PROCESSOR: Option<GpuPolygonProcessor> = None;
-
-// Initialize GPU polygon processor
+// Initialize GPU polygon processor; it is recreated automatically after device loss
 #[wasm_bindgen]
 pub async fn init_gpu_polygon_processor() -> Result<bool, JsValue> {
-    match GpuPolygonProcessor::new().await {
-        Ok(processor) => {
-            unsafe {
-                GPU_POLYGON_PROCESSOR = Some(processor);
-            }
-            Ok(true)
-        }
-        Err(e) => {
-            Ok(false)
+    Ok(gpu_manager::polygon_gpu().init().await)
+}
+
+// Destroy the GPU device and drop its pipelines; false if none was initialized
+pub(crate) fn release_gpu_polygon_processor() -> bool {
+    gpu_manager::polygon_gpu().release()
+}
+
+// CPU version of LINESTRING_BUFFER_SHADER: left offsets followed by the reversed right offsets
+fn buffer_linestring_cpu(points: &[[f64; 2]], buffer_distance: f64) -> Vec<[f64; 2]> {
+    let n = points.len();
+    if n < 2 {
+        return Vec::new();
+    }
+
+    let perpendicular = |p1: [f64; 2], p2: [f64; 2]| {
+        let (dx, dy) = (p2[0] - p1[0], p2[1] - p1[1]);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length < 1e-6 {
+            [0.0, 0.0]
+        } else {
+            [-dy / length * buffer_distance, dx / length * buffer_distance]
         }
+    };
+    let direction = |p1: [f64; 2], p2: [f64; 2]| {
+        let (dx, dy) = (p2[0] - p1[0], p2[1] - p1[1]);
+        let length = (dx * dx + dy * dy).sqrt().max(1e-12);
+        [dx / length, dy / length]
+    };
+
+    let mut output = vec![[0.0; 2]; n * 2];
+    for i in 0..n {
+        let offset = if i == 0 {
+            perpendicular(points[0], points[1])
+        } else if i == n - 1 {
+            perpendicular(points[i - 1], points[i])
+        } else {
+            // Perpendicular of the averaged direction, limiting the scale at sharp angles
+            let prev_dir = direction(points[i - 1], points[i]);
+            let next_dir = direction(points[i], points[i + 1]);
+            let sum = [prev_dir[0] + next_dir[0], prev_dir[1] + next_dir[1]];
+            let sum_length = (sum[0] * sum[0] + sum[1] * sum[1]).sqrt().max(1e-12);
+            let dot = prev_dir[0] * next_dir[0] + prev_dir[1] * next_dir[1];
+            let scale = buffer_distance / ((1.0 + dot) * 0.5).sqrt().max(0.1);
+            [-sum[1] / sum_length * scale, sum[0] / sum_length * scale]
+        };
+        output[i] = [points[i][0] + offset[0], points[i][1] + offset[1]];
+        output[n * 2 - 1 - i] = [points[i][0] - offset[0], points[i][1] - offset[1]];
     }
+    output
 }
 
-// GPU-accelerated LineString buffering function
+// CPU version of POLYGON_CLIP_SHADER (Sutherland-Hodgman against the bbox edges)
+fn clip_polygons_cpu(polygons: &[Vec<[f64; 2]>], bbox: &[f64; 4]) -> Vec<Vec<[f64; 2]>> {
+    const MAX_POINTS_PER_POLYGON: usize = 128;
+    // (axis, clip value, keep points above the value)
+    let edges = [
+        (0, bbox[0], true),
+        (0, bbox[2], false),
+        (1, bbox[1], true),
+        (1, bbox[3], false),
+    ];
+
+    polygons
+        .iter()
+        .map(|polygon| {
+            if polygon.len() < 3 {
+                return Vec::new();
+            }
+            let mut current = polygon.clone();
+            for &(axis, clip_value, keep_above) in &edges {
+                let inside = |p: &[f64; 2]| {
+                    if keep_above {
+                        p[axis] >= clip_value
+                    } else {
+                        p[axis] <= clip_value
+                    }
+                };
+                let intersection = |p1: [f64; 2], p2: [f64; 2]| {
+                    let delta = p2[axis] - p1[axis];
+                    if delta.abs() < 1e-10 {
+                        return p1;
+                    }
+                    let t = (clip_value - p1[axis]) / delta;
+                    let mut point = [p1[0] + t * (p2[0] - p1[0]), p1[1] + t * (p2[1] - p1[1])];
+                    point[axis] = clip_value;
+                    point
+                };
+
+                let mut clipped = Vec::with_capacity(current.len());
+                let mut prev = match current.last() {
+                    Some(&point) => point,
+                    None => break,
+                };
+                for &curr in &current {
+                    if inside(&curr) {
+                        if !inside(&prev) {
+                            clipped.push(intersection(prev, curr));
+                        }
+                        clipped.push(curr);
+                    } else if inside(&prev) {
+                        clipped.push(intersection(prev, curr));
+                    }
+                    prev = curr;
+                }
+                current = clipped;
+            }
+            current.truncate(MAX_POINTS_PER_POLYGON);
+            current
+        })
+        .collect()
+}
+
+// LineString buffering on the GPU, or on the CPU when no GPU device is usable
 pub async fn buffer_linestring_gpu(
     points: &[[f64; 2]],
     buffer_distance: f64,
 ) -> Result<Vec<[f64; 2]>, JsValue> {
-    unsafe {
-        match &GPU_POLYGON_PROCESSOR {
-            Some(processor) => processor.buffer_linestring_gpu(points, buffer_distance).await,
-            None => Err(JsValue::from_str("GPU polygon processor not initialized")),
+    if let Some(processor) = gpu_manager::polygon_gpu().acquire().await {
+        // A device lost mid-request is recreated on the next one
+        if let Ok(buffered) = processor.buffer_linestring_gpu(points, buffer_distance).await {
+            return Ok(buffered);
         }
     }
+    Ok(buffer_linestring_cpu(points, buffer_distance))
 }
 
-// GPU-accelerated polygon clipping function
+// Polygon clipping on the GPU, or on the CPU when no GPU device is usable; cancellable
+// through the token of `process_id`
 pub async fn clip_polygons_gpu(
     polygons: &[Vec<[f64; 2]>],
     bbox: &[f64; 4],
+    process_id: Option<&str>,
 ) -> Result<Vec<Vec<[f64; 2]>>, JsValue> {
-    unsafe {
-        match &GPU_POLYGON_PROCESSOR {
-            Some(processor) => processor.clip_polygons_gpu(polygons, bbox).await,
-            None => Err(JsValue::from_str("GPU polygon processor not initialized")),
+    if let Some(processor) = gpu_manager::polygon_gpu().acquire().await {
+        match processor.clip_polygons_gpu(polygons, bbox, process_id).await {
+            Ok(clipped) => return Ok(clipped),
+            Err(e) if GpuCancellation::for_process(process_id).is_cancelled() => return Err(e),
+            Err(_) => {}
         }
     }
-}
\ No newline at end of file
+    Ok(clip_polygons_cpu(polygons, bbox))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_fallback_without_gpu_device() {
+        // No processor was initialized, so both requests run on the CPU
+        let square = vec![[-1.0, -1.0], [3.0, -1.0], [3.0, 3.0], [-1.0, 3.0]];
+        let clipped = futures::executor::block_on(clip_polygons_gpu(
+            &[square, vec![[0.0, 0.0], [1.0, 1.0]]],
+            &[0.0, 0.0, 2.0, 2.0],
+            None,
+        ))
+        .unwrap();
+        assert_eq!(clipped.len(), 2);
+        assert!(clipped[1].is_empty());
+        assert_eq!(clipped[0].len(), 4);
+        assert!(clipped[0]
+            .iter()
+            .all(|p| (0.0..=2.0).contains(&p[0]) && (0.0..=2.0).contains(&p[1])));
+
+        let buffered = futures::executor::block_on(buffer_linestring_gpu(
+            &[[0.0, 0.0], [1.0, 0.0], [2.0, 0.0]],
+            0.5,
+        ))
+        .unwrap();
+        assert_eq!(buffered, vec![[0.0, 0.5], [1.0, 0.5], [2.0, 0.5], [2.0, -0.5], [1.0, -0.5], [0.0, -0.5]]);
+    }
+}
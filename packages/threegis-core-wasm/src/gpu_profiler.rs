@@ -0,0 +1,258 @@
+// GPU pass profiling with timestamp queries. Devices request TIMESTAMP_QUERY when the
+// adapter supports it; while profiling is enabled with `set_gpu_profiling`, each compute
+// pass writes begin and end timestamps and its GPU duration is recorded for
+// `get_gpu_profile_report`.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use wasm_bindgen::prelude::*;
+use wgpu::{Adapter, Buffer, CommandEncoder, ComputePassTimestampWrites, Device, QuerySet, Queue};
+
+// Pass timings kept for the report; older ones are dropped
+const PROFILE_CAPACITY: usize = 1000;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PassTiming {
+    /// Label of the compute pass, e.g. "Terrain Vertex Compute Pass"
+    pub pass: String,
+    #[serde(rename = "processId")]
+    pub process_id: Option<String>,
+    #[serde(rename = "gpuMs")]
+    pub gpu_ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PassSummary {
+    pub pass: String,
+    /// Timed submissions of the pass; sliced dispatches count once per slice
+    pub count: usize,
+    #[serde(rename = "totalMs")]
+    pub total_ms: f64,
+    #[serde(rename = "meanMs")]
+    pub mean_ms: f64,
+    #[serde(rename = "minMs")]
+    pub min_ms: f64,
+    #[serde(rename = "maxMs")]
+    pub max_ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GpuProfileReport {
+    pub enabled: bool,
+    /// Per-pass totals, most expensive first
+    pub passes: Vec<PassSummary>,
+    pub samples: Vec<PassTiming>,
+}
+
+#[derive(Default)]
+struct ProfileState {
+    enabled: bool,
+    samples: VecDeque<PassTiming>,
+}
+
+thread_local! {
+    static PROFILE: RefCell<ProfileState> = RefCell::new(ProfileState::default());
+}
+
+/// Features to request for a device so its passes can be profiled when enabled
+pub(crate) fn optional_features(adapter: &Adapter) -> wgpu::Features {
+    adapter.features() & wgpu::Features::TIMESTAMP_QUERY
+}
+
+fn profiling_enabled() -> bool {
+    PROFILE.with(|profile| profile.borrow().enabled)
+}
+
+fn record_pass_timing(timing: PassTiming) {
+    PROFILE.with(|profile| {
+        let samples = &mut profile.borrow_mut().samples;
+        if samples.len() == PROFILE_CAPACITY {
+            samples.pop_front();
+        }
+        samples.push_back(timing);
+    });
+}
+
+/// Timestamp queries for one compute pass at a time
+pub(crate) struct PassTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    period_ns: f64,
+}
+
+impl PassTimer {
+    /// None unless profiling is enabled and the device supports timestamp queries
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !profiling_enabled() || !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let size = 2 * std::mem::size_of::<u64>() as u64;
+        Some(PassTimer {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass Timestamp Queries"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Pass Timestamp Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Pass Timestamp Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            period_ns: queue.get_timestamp_period() as f64,
+        })
+    }
+
+    pub fn timestamp_writes(&self) -> ComputePassTimestampWrites<'_> {
+        ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    /// Copy the timestamps of the pass for readback; call after the pass, before submitting
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            self.resolve_buffer.size(),
+        );
+    }
+
+    /// Read the timestamps of the submitted pass and record its GPU duration
+    pub async fn record(&self, device: &Device, pass: &str, process_id: Option<&str>) {
+        let slice = self.readback_buffer.slice(..);
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result.is_ok());
+        });
+        device.poll(wgpu::Maintain::Wait);
+        if receiver.await != Ok(true) {
+            return;
+        }
+
+        let timestamps: Vec<u64> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        self.readback_buffer.unmap();
+        // Timestamps can go backwards across power state changes; such samples are dropped
+        if let [begin, end] = timestamps[..] {
+            if end >= begin {
+                record_pass_timing(PassTiming {
+                    pass: pass.to_string(),
+                    process_id: process_id.map(str::to_string),
+                    gpu_ms: (end - begin) as f64 * self.period_ns / 1_000_000.0,
+                });
+            }
+        }
+    }
+}
+
+fn summarize(samples: &[PassTiming]) -> Vec<PassSummary> {
+    let mut by_pass: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for sample in samples {
+        by_pass.entry(&sample.pass).or_default().push(sample.gpu_ms);
+    }
+    let mut passes: Vec<PassSummary> = by_pass
+        .into_iter()
+        .map(|(pass, times)| {
+            let total_ms: f64 = times.iter().sum();
+            PassSummary {
+                pass: pass.to_string(),
+                count: times.len(),
+                total_ms,
+                mean_ms: total_ms / times.len() as f64,
+                min_ms: times.iter().copied().fold(f64::INFINITY, f64::min),
+                max_ms: times.iter().copied().fold(0.0, f64::max),
+            }
+        })
+        .collect();
+    passes.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms));
+    passes
+}
+
+/// Enable or disable timestamp queries around GPU compute passes. Only devices created
+/// on adapters with timestamp query support produce timings.
+#[wasm_bindgen]
+pub fn set_gpu_profiling(enabled: bool) {
+    PROFILE.with(|profile| profile.borrow_mut().enabled = enabled);
+}
+
+/// Recorded GPU pass timings as JSON (`{ enabled, passes, samples }`), optionally only
+/// those of one process
+#[wasm_bindgen]
+pub fn get_gpu_profile_report(process_id: Option<String>) -> Result<String, JsValue> {
+    let report = PROFILE.with(|profile| {
+        let profile = profile.borrow();
+        let samples: Vec<PassTiming> = profile
+            .samples
+            .iter()
+            .filter(|s| process_id.is_none() || s.process_id == process_id)
+            .cloned()
+            .collect();
+        GpuProfileReport {
+            enabled: profile.enabled,
+            passes: summarize(&samples),
+            samples,
+        }
+    });
+    serde_json::to_string(&report)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize GPU profile: {}", e)))
+}
+
+/// Drop recorded GPU pass timings and disable profiling
+#[wasm_bindgen]
+pub fn clear_gpu_profile() {
+    PROFILE.with(|profile| *profile.borrow_mut() = ProfileState::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_summarizes_passes_per_process() {
+        clear_gpu_profile();
+        set_gpu_profiling(true);
+        for (pass, process_id, gpu_ms) in [
+            ("Elevation Compute Pass", "p1", 2.0),
+            ("Elevation Compute Pass", "p1", 4.0),
+            ("Terrain Vertex Compute Pass", "p1", 1.0),
+            ("Terrain Vertex Compute Pass", "p2", 9.0),
+        ] {
+            record_pass_timing(PassTiming {
+                pass: pass.to_string(),
+                process_id: Some(process_id.to_string()),
+                gpu_ms,
+            });
+        }
+
+        let report: serde_json::Value =
+            serde_json::from_str(&get_gpu_profile_report(Some("p1".into())).unwrap()).unwrap();
+        assert_eq!(report["enabled"], true);
+        assert_eq!(report["samples"].as_array().unwrap().len(), 3);
+        let elevation = &report["passes"][0];
+        assert_eq!(elevation["pass"], "Elevation Compute Pass");
+        assert_eq!(elevation["count"], 2);
+        assert_eq!(elevation["meanMs"], 3.0);
+        assert_eq!(
+            (elevation["minMs"].as_f64(), elevation["maxMs"].as_f64()),
+            (Some(2.0), Some(4.0))
+        );
+
+        let all: serde_json::Value =
+            serde_json::from_str(&get_gpu_profile_report(None).unwrap()).unwrap();
+        assert_eq!(all["passes"][0]["pass"], "Terrain Vertex Compute Pass");
+        assert_eq!(all["passes"][0]["totalMs"], 10.0);
+        clear_gpu_profile();
+    }
+}
@@ -8,9 +8,15 @@ use wgpu::{
 };
 use wgpu::util::DeviceExt;
 use bytemuck::{Pod, Zeroable};
+use std::future::Future;
 
 use crate::elevation::ElevationProcessingResult;
+use crate::gpu_dispatch::{submitted_work_done, GpuCancellation};
+use crate::gpu_manager::{self, DeviceLoss, GpuProcessor};
+use crate::gpu_profiler::{self, PassTimer};
+use crate::projection;
 use crate::terrain::{TerrainGeometryParams, TerrainGeometryResult};
+use crate::vertical_datum::{VerticalDatum, MIN_TERRAIN_THICKNESS};
 
 // GPU-compatible data structures
 #[repr(C)]
@@ -26,7 +32,8 @@ struct TerrainParams {
     max_elevation: f32,
     elevation_range: f32,
     min_terrain_thickness: f32,
-    _padding: [u32; 2],
+    terrain_size: f32,
+    _padding: u32,
 }
 
 #[repr(C)]
@@ -55,7 +62,8 @@ struct TerrainParams {
     max_elevation: f32,
     elevation_range: f32,
     min_terrain_thickness: f32,
-    padding: array<u32, 2>,
+    terrain_size: f32,
+    padding: u32,
 }
 
 struct Vertex {
@@ -141,8 +149,8 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let top_z = calculate_terrain_height(elevation);
 
     // Calculate mesh coordinates (terrain is 200x200 units centered at origin)
-    let mesh_x = (normalized_x - 0.5) * 200.0;
-    let mesh_y = (normalized_y - 0.5) * 200.0;
+    let mesh_x = (normalized_x - 0.5) * params.terrain_size;
+    let mesh_y = (normalized_y - 0.5) * params.terrain_size;
 
     // Calculate normalized elevation for coloring
     let normalized_elevation = clamp((elevation - params.min_elevation) / params.elevation_range, 0.0, 1.0);
@@ -192,7 +200,8 @@ struct TerrainParams {
     max_elevation: f32,
     elevation_range: f32,
     min_terrain_thickness: f32,
-    padding: array<u32, 2>,
+    terrain_size: f32,
+    padding: u32,
 }
 
 @compute @workgroup_size(8, 8, 1)
@@ -267,7 +276,8 @@ struct TerrainParams {
     max_elevation: f32,
     elevation_range: f32,
     min_terrain_thickness: f32,
-    padding: array<u32, 2>,
+    terrain_size: f32,
+    padding: u32,
 }
 
 @compute @workgroup_size(64, 1, 1)
@@ -338,7 +348,8 @@ struct TerrainParams {
     max_elevation: f32,
     elevation_range: f32,
     min_terrain_thickness: f32,
-    padding: array<u32, 2>,
+    terrain_size: f32,
+    padding: u32,
 }
 
 @compute @workgroup_size(64, 1, 1)
@@ -374,6 +385,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 pub struct GpuTerrainProcessor {
     device: Device,
     queue: Queue,
+    device_loss: DeviceLoss,
     vertex_pipeline: ComputePipeline,
     index_pipeline: ComputePipeline,
     normal_pipeline: ComputePipeline,
@@ -384,6 +396,20 @@ pub struct GpuTerrainProcessor {
     normal_normalize_bind_group_layout: BindGroupLayout,
 }
 
+impl GpuProcessor for GpuTerrainProcessor {
+    fn create() -> impl Future<Output = Result<Self, JsValue>> {
+        Self::new()
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
+    fn device_loss(&self) -> &DeviceLoss {
+        &self.device_loss
+    }
+}
+
 impl GpuTerrainProcessor {
     pub async fn new() -> Result<Self, JsValue> {
 
@@ -406,13 +432,14 @@ impl GpuTerrainProcessor {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("GPU Terrain Device"),
-                    required_features: wgpu::Features::empty(),
+                    required_features: gpu_profiler::optional_features(&adapter),
                     required_limits: wgpu::Limits::downlevel_webgl2_defaults(),
                 },
                 None,
             )
             .await
             .map_err(|e| JsValue::from_str(&format!("Failed to create device: {:?}", e)))?;
+        let device_loss = DeviceLoss::watch(&device);
 
         // Create shaders
         let vertex_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -627,6 +654,7 @@ impl GpuTerrainProcessor {
         Ok(Self {
             device,
             queue,
+            device_loss,
             vertex_pipeline,
             index_pipeline,
             normal_pipeline,
@@ -646,10 +674,17 @@ impl GpuTerrainProcessor {
 
         let source_width = elevation_data.grid_size.width as usize;
         let source_height = elevation_data.grid_size.height as usize;
-        let target_width = source_width.min(64).max(2); // Reasonable target resolution
-        let target_height = source_height.min(64).max(2);
-
-        let elevation_range = f64::max(1.0, elevation_data.max_elevation - elevation_data.min_elevation);
+        let target_width = source_width.clamp(2, 64); // Reasonable target resolution
+        let target_height = source_height.clamp(2, 64);
+
+        let terrain_scale = projection::activate_process(&params.process_id);
+        let datum = VerticalDatum::new(
+            params.terrain_base_height,
+            params.vertical_exaggeration,
+            elevation_data.min_elevation,
+            elevation_data.max_elevation,
+            terrain_scale.model_scale,
+        );
 
         // Flatten elevation grid for GPU
         let flattened_elevation: Vec<f32> = elevation_data
@@ -663,14 +698,16 @@ impl GpuTerrainProcessor {
             grid_height: source_height as u32,
             target_width: target_width as u32,
             target_height: target_height as u32,
-            // Apply scale factor to make exaggeration values more visible (15x multiplier)
-            vertical_exaggeration: (params.vertical_exaggeration * 15.0) as f32,
-            terrain_base_height: params.terrain_base_height as f32,
-            min_elevation: elevation_data.min_elevation as f32,
-            max_elevation: elevation_data.max_elevation as f32,
-            elevation_range: elevation_range as f32,
-            min_terrain_thickness: 0.3,
-            _padding: [0; 2],
+            // The shader takes the relief height directly so it matches the CPU mesh; it used
+            // to scale the exaggeration by 15 instead of EXAGGERATION_SCALE_FACTOR (5)
+            vertical_exaggeration: datum.relief_height() as f32,
+            terrain_base_height: datum.terrain_base_height as f32,
+            min_elevation: datum.min_elevation as f32,
+            max_elevation: datum.max_elevation as f32,
+            elevation_range: datum.elevation_range() as f32,
+            min_terrain_thickness: MIN_TERRAIN_THICKNESS as f32,
+            terrain_size: terrain_scale.size as f32,
+            _padding: 0,
         };
 
         // Create GPU buffers
@@ -772,74 +809,62 @@ impl GpuTerrainProcessor {
             ],
         });
 
-        // Execute compute shaders
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Terrain Compute Encoder"),
-        });
-
-        // Generate vertices
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Terrain Vertex Compute Pass"),
-                timestamp_writes: None,
-            });
-
-            compute_pass.set_pipeline(&self.vertex_pipeline);
-            compute_pass.set_bind_group(0, &vertex_bind_group, &[]);
-
-            let workgroup_size_x = 8;
-            let workgroup_size_y = 8;
-            let num_workgroups_x = (target_width + workgroup_size_x - 1) / workgroup_size_x;
-            let num_workgroups_y = (target_height + workgroup_size_y - 1) / workgroup_size_y;
-
-            compute_pass.dispatch_workgroups(num_workgroups_x as u32, num_workgroups_y as u32, 1);
-        }
-
-        // Generate indices
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Terrain Index Compute Pass"),
-                timestamp_writes: None,
-            });
-
-            compute_pass.set_pipeline(&self.index_pipeline);
-            compute_pass.set_bind_group(0, &index_bind_group, &[]);
-
-            let workgroup_size_x = 8;
-            let workgroup_size_y = 8;
-            let num_workgroups_x = ((target_width - 1) + workgroup_size_x - 1) / workgroup_size_x;
-            let num_workgroups_y = ((target_height - 1) + workgroup_size_y - 1) / workgroup_size_y;
-
-            compute_pass.dispatch_workgroups(num_workgroups_x as u32, num_workgroups_y as u32, 1);
-        }
-
-        // Calculate normals
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Terrain Normal Compute Pass"),
-                timestamp_writes: None,
+        // Execute compute shaders, one submission per pass, checking for cancellation of
+        // the process in between
+        let cancellation = GpuCancellation::for_process(Some(&params.process_id));
+        let passes = [
+            (
+                "Terrain Vertex Compute Pass",
+                &self.vertex_pipeline,
+                &vertex_bind_group,
+                (target_width.div_ceil(8), target_height.div_ceil(8)),
+            ),
+            (
+                "Terrain Index Compute Pass",
+                &self.index_pipeline,
+                &index_bind_group,
+                ((target_width - 1).div_ceil(8), (target_height - 1).div_ceil(8)),
+            ),
+            (
+                "Terrain Normal Compute Pass",
+                &self.normal_pipeline,
+                &normal_bind_group,
+                (triangle_count.div_ceil(64), 1),
+            ),
+            (
+                "Terrain Normal Normalize Compute Pass",
+                &self.normal_normalize_pipeline,
+                &normal_normalize_bind_group,
+                (vertex_count.div_ceil(64), 1),
+            ),
+        ];
+        let timer = PassTimer::new(&self.device, &self.queue);
+        for (label, pipeline, bind_group, (num_workgroups_x, num_workgroups_y)) in passes {
+            cancellation.check()?;
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Terrain Compute Encoder"),
             });
-
-            compute_pass.set_pipeline(&self.normal_pipeline);
-            compute_pass.set_bind_group(0, &normal_bind_group, &[]);
-
-            let num_workgroups = (triangle_count + 63) / 64;
-            compute_pass.dispatch_workgroups(num_workgroups as u32, 1, 1);
-        }
-
-        // Normalize normals
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Terrain Normal Normalize Compute Pass"),
-                timestamp_writes: None,
-            });
-
-            compute_pass.set_pipeline(&self.normal_normalize_pipeline);
-            compute_pass.set_bind_group(0, &normal_normalize_bind_group, &[]);
-
-            let num_workgroups = (vertex_count + 63) / 64;
-            compute_pass.dispatch_workgroups(num_workgroups as u32, 1, 1);
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some(label),
+                    timestamp_writes: timer.as_ref().map(PassTimer::timestamp_writes),
+                });
+
+                compute_pass.set_pipeline(pipeline);
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                compute_pass.dispatch_workgroups(num_workgroups_x as u32, num_workgroups_y as u32, 1);
+            }
+            if let Some(timer) = &timer {
+                timer.resolve(&mut encoder);
+            }
+            self.queue.submit(std::iter::once(encoder.finish()));
+            submitted_work_done(&self.device, &self.queue).await;
+            self.device_loss.check()?;
+            if let Some(timer) = &timer {
+                timer.record(&self.device, label, Some(&params.process_id)).await;
+            }
         }
+        cancellation.check()?;
 
         // Create staging buffers
         let vertices_staging = self.device.create_buffer(&BufferDescriptor {
@@ -856,6 +881,9 @@ impl GpuTerrainProcessor {
             mapped_at_creation: false,
         });
 
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Terrain Readback Encoder"),
+        });
         encoder.copy_buffer_to_buffer(&vertices_buffer, 0, &vertices_staging, 0, vertices_buffer.size());
         encoder.copy_buffer_to_buffer(&indices_buffer, 0, &indices_staging, 0, indices_buffer.size());
 
@@ -870,6 +898,8 @@ impl GpuTerrainProcessor {
 
         self.device.poll(wgpu::Maintain::Wait);
 
+        self.device_loss.check()?;
+
         let vertices_data = vertices_slice.get_mapped_range();
         let indices_data = indices_slice.get_mapped_range();
 
@@ -907,34 +937,26 @@ impl GpuTerrainProcessor {
     }
 }
 
-// Global GPU terrain processor instance
-static mut GPU_TERRAIN_PROCESSOR: Option<GpuTerrainProcessor> = None;
-
-// Initialize GPU terrain processor
+// Initialize GPU terrain processor; it is recreated automatically after device loss
 #[wasm_bindgen]
 pub async fn init_gpu_terrain_processor() -> Result<bool, JsValue> {
-    match GpuTerrainProcessor::new().await {
-        Ok(processor) => {
-            unsafe {
-                GPU_TERRAIN_PROCESSOR = Some(processor);
-            }
-            Ok(true)
-        }
-        Err(e) => {
-            Ok(false)
-        }
-    }
+    Ok(gpu_manager::terrain_gpu().init().await)
 }
 
-// GPU-accelerated terrain generation function
+// Destroy the GPU device and drop its pipelines; false if none was initialized
+pub(crate) fn release_gpu_terrain_processor() -> bool {
+    gpu_manager::terrain_gpu().release()
+}
+
+// GPU-accelerated terrain generation function. Errors when no GPU is usable, so the
+// caller can generate the mesh on the CPU instead.
 pub async fn generate_terrain_mesh_gpu(
     elevation_data: &ElevationProcessingResult,
     params: &TerrainGeometryParams,
 ) -> Result<TerrainGeometryResult, JsValue> {
-    unsafe {
-        match &GPU_TERRAIN_PROCESSOR {
-            Some(processor) => processor.generate_terrain_mesh_gpu(elevation_data, params).await,
-            None => Err(JsValue::from_str("GPU terrain processor not initialized")),
-        }
-    }
-}
\ No newline at end of file
+    let processor = gpu_manager::terrain_gpu()
+        .acquire()
+        .await
+        .ok_or_else(|| JsValue::from_str("GPU terrain generation unavailable"))?;
+    processor.generate_terrain_mesh_gpu(elevation_data, params).await
+}
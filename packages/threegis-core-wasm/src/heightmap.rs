@@ -0,0 +1,201 @@
+// Heightmap export: the processed elevation grid as a 16-bit grayscale PNG for
+// displacement workflows in Blender, Unity and similar tools. Elevations are stretched
+// over the full 0..65535 range; the meters they map to are stored in tEXt chunks.
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::io::Write;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::{ElevationExtent, ModuleState};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Encode 16-bit grayscale samples (row-major, top row first) as a PNG with tEXt chunks
+pub(crate) fn encode_gray16_png(
+    width: usize,
+    height: usize,
+    samples: &[u16],
+    text: &[(&str, String)],
+) -> Result<Vec<u8>, String> {
+    if width == 0 || height == 0 || samples.len() != width * height {
+        return Err(format!(
+            "Expected {}x{} samples, got {}",
+            width,
+            height,
+            samples.len()
+        ));
+    }
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 16-bit depth, grayscale, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[16, 0, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    for (keyword, value) in text {
+        let mut data = keyword.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(value.as_bytes());
+        write_chunk(&mut png, b"tEXt", &data);
+    }
+
+    // Every row uses the Up filter, which suits smooth terrain well
+    let row_bytes = width * 2;
+    let mut previous = vec![0u8; row_bytes];
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in samples.chunks_exact(width) {
+        let current: Vec<u8> = row.iter().flat_map(|s| s.to_be_bytes()).collect();
+        let filtered: Vec<u8> = std::iter::once(2)
+            .chain(
+                current
+                    .iter()
+                    .zip(&previous)
+                    .map(|(c, p)| c.wrapping_sub(*p)),
+            )
+            .collect();
+        encoder
+            .write_all(&filtered)
+            .map_err(|e| format!("Failed to compress heightmap: {}", e))?;
+        previous = current;
+    }
+    let image_data = encoder
+        .finish()
+        .map_err(|e| format!("Failed to compress heightmap: {}", e))?;
+    write_chunk(&mut png, b"IDAT", &image_data);
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+/// Heightmap PNG of an elevation grid; the top row of the image is the northern edge
+pub(crate) fn heightmap_png(
+    grid: &[Vec<f64>],
+    extent: &ElevationExtent,
+) -> Result<Vec<u8>, String> {
+    let height = grid.len();
+    let width = grid.first().map_or(0, Vec::len);
+    if grid.iter().any(|row| row.len() != width) {
+        return Err("Elevation grid rows differ in length".to_string());
+    }
+
+    let (min, max) = (extent.min_elevation, extent.max_elevation);
+    let range = (max - min).max(f64::EPSILON);
+    // Grid row 0 is the southern edge
+    let samples: Vec<u16> = grid
+        .iter()
+        .rev()
+        .flatten()
+        .map(|&elevation| {
+            let normalized = if elevation.is_finite() {
+                ((elevation - min) / range).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            (normalized * u16::MAX as f64).round() as u16
+        })
+        .collect();
+
+    let [min_lng, min_lat, max_lng, max_lat] = extent.bbox;
+    let text = [
+        ("Software", "STLMaps".to_string()),
+        ("minElevation", min.to_string()),
+        ("maxElevation", max.to_string()),
+        (
+            "bbox",
+            format!("{},{},{},{}", min_lng, min_lat, max_lng, max_lat),
+        ),
+    ];
+    encode_gray16_png(width, height, &samples, &text)
+}
+
+/// 16-bit grayscale PNG of the processed elevation grid of `process_id`, or of the most
+/// recently processed one when omitted. Black is `minElevation` and white `maxElevation`
+/// (meters), both stored as tEXt metadata together with the bbox.
+#[wasm_bindgen]
+pub fn export_heightmap_png(process_id: Option<String>) -> Result<Vec<u8>, JsValue> {
+    let png = ModuleState::with(|state| {
+        state
+            .get_elevation_grid_with_extent(process_id.as_deref())
+            .map(|(grid, extent)| heightmap_png(grid, extent))
+    })
+    .ok_or_else(|| JsValue::from_str("No elevation grid cached; process elevation first"))?;
+    png.map_err(|e| JsValue::from_str(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut pos = PNG_SIGNATURE.len();
+        let mut chunks = Vec::new();
+        while pos < png.len() {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = String::from_utf8(png[pos + 4..pos + 8].to_vec()).unwrap();
+            let mut crc = Crc::new();
+            crc.update(&png[pos + 4..pos + 8 + len]);
+            let stored = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(crc.sum(), stored, "CRC of {}", kind);
+            chunks.push((kind, png[pos + 8..pos + 8 + len].to_vec()));
+            pos += 12 + len;
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_heightmap_is_north_up_16_bit_with_metadata() {
+        let extent = ElevationExtent {
+            bbox: [10.0, 50.0, 11.0, 51.0],
+            min_elevation: 100.0,
+            max_elevation: 300.0,
+        };
+        // Row 0 is the southern edge
+        let grid = vec![vec![100.0, 150.0], vec![200.0, 300.0]];
+        let png = heightmap_png(&grid, &extent).unwrap();
+        assert_eq!(png[..8], PNG_SIGNATURE);
+
+        let chunks = chunks(&png);
+        assert_eq!(chunks[0].0, "IHDR");
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 2, 16, 0, 0, 0, 0]);
+        assert!(chunks
+            .iter()
+            .any(|(kind, data)| kind == "tEXt" && data[..] == b"maxElevation\x00300"[..]));
+        assert_eq!(chunks.last().unwrap().0, "IEND");
+
+        let idat = &chunks.iter().find(|(kind, _)| kind == "IDAT").unwrap().1;
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&idat[..]).read_to_end(&mut raw).unwrap();
+        // Undo the Up filter
+        let mut rows: Vec<Vec<u8>> = Vec::new();
+        for filtered in raw.chunks_exact(5) {
+            assert_eq!(filtered[0], 2);
+            let prev = rows.last().cloned().unwrap_or(vec![0; 4]);
+            rows.push(
+                filtered[1..]
+                    .iter()
+                    .zip(prev)
+                    .map(|(f, p)| f.wrapping_add(p))
+                    .collect(),
+            );
+        }
+        let samples: Vec<u16> = rows
+            .concat()
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples, vec![32768, 65535, 0, 16384]);
+    }
+}
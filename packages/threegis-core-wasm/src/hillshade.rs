@@ -0,0 +1,276 @@
+// Terrain shading rasters: hillshade, slope and aspect of a cached elevation grid as
+// north-up RGBA images the frontend can drape over the terrain mesh. Gradients use Horn's
+// 3x3 kernel in meters, border cells repeating their edge neighbours. The images are
+// computed on the GPU when a shading processor was initialized, otherwise on the CPU.
+use std::f64::consts::{FRAC_PI_2, TAU};
+use wasm_bindgen::prelude::*;
+
+use crate::console::{self, LogLevel};
+use crate::gpu_dispatch::GpuCancellation;
+use crate::module_state::{ElevationExtent, ModuleState};
+use crate::{gpu_hillshade, gpu_manager};
+
+/// Meters per degree of latitude
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Light direction and cell size of a shading pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ShadingParams {
+    /// Direction the light comes from, radians clockwise from north
+    pub azimuth: f64,
+    /// Angle between the light and straight up, radians
+    pub zenith: f64,
+    /// Cell size in meters
+    pub cell_width: f64,
+    pub cell_height: f64,
+}
+
+impl ShadingParams {
+    /// Light from `azimuth` degrees clockwise from north at `altitude` degrees above the
+    /// horizon, over a `width` x `height` grid covering `extent`
+    pub(crate) fn new(
+        azimuth: f64,
+        altitude: f64,
+        extent: &ElevationExtent,
+        width: usize,
+        height: usize,
+    ) -> Result<Self, String> {
+        if !azimuth.is_finite() || !(0.0..=90.0).contains(&altitude) {
+            return Err(format!(
+                "Invalid light direction: azimuth {}, altitude {} (0..90 degrees)",
+                azimuth, altitude
+            ));
+        }
+        let [min_lng, min_lat, max_lng, max_lat] = extent.bbox;
+        let latitude = ((min_lat + max_lat) / 2.0).to_radians();
+        let cell = |span: f64, cells: usize| {
+            (span.abs() * METERS_PER_DEGREE / cells.max(2).saturating_sub(1) as f64)
+                .max(f64::EPSILON)
+        };
+        Ok(ShadingParams {
+            azimuth: azimuth.rem_euclid(360.0).to_radians(),
+            zenith: (90.0 - altitude).to_radians(),
+            cell_width: cell((max_lng - min_lng) * latitude.cos(), width),
+            cell_height: cell(max_lat - min_lat, height),
+        })
+    }
+}
+
+/// Hillshade, slope and aspect as RGBA images (gray, opaque), top row at the northern edge
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ShadingImages {
+    pub width: usize,
+    pub height: usize,
+    /// Lighting from black (facing away from the light) to white
+    pub hillshade: Vec<u8>,
+    /// Slope from black (flat) to white (vertical), linear in degrees
+    pub slope: Vec<u8>,
+    /// Compass direction the slope faces downhill, clockwise from 1 (north) to 255; 0
+    /// where flat
+    pub aspect: Vec<u8>,
+}
+
+/// Gray levels of hillshade, slope and aspect for the gradient of a cell in meters per
+/// meter, with +x east and +y north
+pub(crate) fn shade_cell(dzdx: f64, dzdy: f64, params: &ShadingParams) -> [u8; 3] {
+    let slope = dzdx.hypot(dzdy).atan();
+    let aspect = (-dzdx).atan2(-dzdy).rem_euclid(TAU);
+    let shade = params.zenith.cos() * slope.cos()
+        + params.zenith.sin() * slope.sin() * (params.azimuth - aspect).cos();
+    let level = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let aspect_level = if dzdx == 0.0 && dzdy == 0.0 {
+        0
+    } else {
+        1 + (aspect / TAU * 254.0).round() as u8
+    };
+    [level(shade), level(slope / FRAC_PI_2), aspect_level]
+}
+
+/// Shading images of an elevation grid on the CPU
+pub(crate) fn shade_grid(grid: &[Vec<f64>], params: &ShadingParams) -> ShadingImages {
+    let height = grid.len();
+    let width = grid.first().map_or(0, Vec::len);
+    let z = |x: isize, y: isize| {
+        let row = &grid[y.clamp(0, height as isize - 1) as usize];
+        let elevation = row[x.clamp(0, width as isize - 1) as usize];
+        if elevation.is_finite() {
+            elevation
+        } else {
+            0.0
+        }
+    };
+
+    let mut images = ShadingImages {
+        width,
+        height,
+        hillshade: vec![0; width * height * 4],
+        slope: vec![0; width * height * 4],
+        aspect: vec![0; width * height * 4],
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let (x_, y_) = (x as isize, y as isize);
+            let dzdx = ((z(x_ + 1, y_ + 1) + 2.0 * z(x_ + 1, y_) + z(x_ + 1, y_ - 1))
+                - (z(x_ - 1, y_ + 1) + 2.0 * z(x_ - 1, y_) + z(x_ - 1, y_ - 1)))
+                / (8.0 * params.cell_width);
+            let dzdy = ((z(x_ - 1, y_ + 1) + 2.0 * z(x_, y_ + 1) + z(x_ + 1, y_ + 1))
+                - (z(x_ - 1, y_ - 1) + 2.0 * z(x_, y_ - 1) + z(x_ + 1, y_ - 1)))
+                / (8.0 * params.cell_height);
+            let levels = shade_cell(dzdx, dzdy, params);
+
+            // Grid row 0 is the southern edge
+            let pixel = ((height - 1 - y) * width + x) * 4;
+            for (image, level) in [&mut images.hillshade, &mut images.slope, &mut images.aspect]
+                .into_iter()
+                .zip(levels)
+            {
+                image[pixel..pixel + 4].copy_from_slice(&[level, level, level, 255]);
+            }
+        }
+    }
+    images
+}
+
+/// Shading images of an elevation grid, on the GPU when available
+pub(crate) async fn shade_elevation(
+    grid: &[Vec<f64>],
+    extent: &ElevationExtent,
+    azimuth: f64,
+    altitude: f64,
+    process_id: Option<&str>,
+) -> Result<ShadingImages, JsValue> {
+    let height = grid.len();
+    let width = grid.first().map_or(0, Vec::len);
+    if width == 0 || grid.iter().any(|row| row.len() != width) {
+        return Err(JsValue::from_str(
+            "Elevation grid is empty or its rows differ in length",
+        ));
+    }
+    let params = ShadingParams::new(azimuth, altitude, extent, width, height)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    // Only tried when GPU shading was requested with `init_gpu_hillshade_processor`
+    if gpu_manager::hillshade_gpu().status().enabled {
+        match gpu_hillshade::shade_grid_gpu(grid, &params, process_id).await {
+            Ok(images) => return Ok(images),
+            // A cancelled process must not continue on the CPU
+            Err(e) if GpuCancellation::for_process(process_id).is_cancelled() => return Err(e),
+            Err(e) => console::record(
+                LogLevel::Warn,
+                "hillshade",
+                process_id,
+                format!(
+                    "GPU terrain shading failed, using CPU: {}",
+                    e.as_string().unwrap_or_default()
+                ),
+            ),
+        }
+    }
+    Ok(shade_grid(grid, &params))
+}
+
+/// Hillshade, slope and aspect images of the elevation grid cached for
+/// `elevation_bbox_key` (a process id), or of the most recently processed grid when
+/// omitted. The light comes from `azimuth` degrees clockwise from north at `altitude`
+/// degrees above the horizon (315 and 45 give the usual north-west lighting).
+/// Returns `{ width, height, hillshade, slope, aspect }` with each image an RGBA
+/// Uint8Array, top row at the northern edge. Slope is linear from 0 (black) to 90 degrees
+/// (white); aspect maps north..clockwise..north to 1..255, with 0 for flat cells.
+#[wasm_bindgen]
+pub async fn compute_hillshade(
+    elevation_bbox_key: Option<String>,
+    azimuth: f64,
+    altitude: f64,
+) -> Result<JsValue, JsValue> {
+    let (grid, extent) = ModuleState::with(|state| {
+        state
+            .get_elevation_grid_with_extent(elevation_bbox_key.as_deref())
+            .map(|(grid, extent)| (grid.clone(), *extent))
+    })
+    .ok_or_else(|| JsValue::from_str("No elevation grid cached; process elevation first"))?;
+    let images = shade_elevation(
+        &grid,
+        &extent,
+        azimuth,
+        altitude,
+        elevation_bbox_key.as_deref(),
+    )
+    .await?;
+
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("width"),
+        &JsValue::from(images.width as u32),
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("height"),
+        &JsValue::from(images.height as u32),
+    )?;
+    for (name, image) in [
+        ("hillshade", &images.hillshade),
+        ("slope", &images.slope),
+        ("aspect", &images.aspect),
+    ] {
+        js_sys::Reflect::set(
+            &result,
+            &JsValue::from_str(name),
+            &js_sys::Uint8Array::from(image.as_slice()),
+        )?;
+    }
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent() -> ElevationExtent {
+        ElevationExtent {
+            bbox: [0.0, 0.0, 0.01, 0.01],
+            min_elevation: 0.0,
+            max_elevation: 100.0,
+        }
+    }
+
+    fn gray(image: &[u8], width: usize, x: usize, y: usize) -> u8 {
+        let pixel = (y * width + x) * 4;
+        assert_eq!(image[pixel + 3], 255);
+        image[pixel]
+    }
+
+    #[test]
+    fn test_plane_facing_east() {
+        // Descending eastwards one meter per meter: 45 degree slope facing east
+        let params = ShadingParams::new(90.0, 45.0, &extent(), 5, 5).unwrap();
+        let grid: Vec<Vec<f64>> = (0..5)
+            .map(|_| (0..5).map(|x| -(x as f64) * params.cell_width).collect())
+            .collect();
+        let images = shade_grid(&grid, &params);
+
+        assert_eq!((images.width, images.height), (5, 5));
+        assert!((127..=128).contains(&gray(&images.slope, 5, 2, 2)));
+        assert_eq!(gray(&images.aspect, 5, 2, 2), 65);
+        // Lit head-on from the east; lit from the west it would face away
+        assert_eq!(gray(&images.hillshade, 5, 2, 2), 255);
+        let west = ShadingParams::new(270.0, 45.0, &extent(), 5, 5).unwrap();
+        assert_eq!(gray(&shade_grid(&grid, &west).hillshade, 5, 2, 2), 0);
+
+        // Flat cells have no aspect
+        let flat = shade_grid(&vec![vec![10.0; 3]; 3], &params);
+        assert_eq!(gray(&flat.aspect, 3, 1, 1), 0);
+        assert_eq!(gray(&flat.hillshade, 3, 1, 1), 180);
+    }
+
+    #[test]
+    fn test_images_are_north_up() {
+        // A ridge along the southern edge: its north face is the top of the image
+        let params = ShadingParams::new(0.0, 30.0, &extent(), 3, 4).unwrap();
+        let grid = vec![vec![50.0; 3], vec![40.0; 3], vec![40.0; 3], vec![40.0; 3]];
+        let images = shade_grid(&grid, &params);
+        // Row 1 of the grid faces north and appears in row 2 of the image
+        assert_eq!(gray(&images.aspect, 3, 1, 2), 1);
+        assert_eq!(gray(&images.slope, 3, 1, 0), 0);
+    }
+}
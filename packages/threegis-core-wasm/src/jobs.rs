@@ -0,0 +1,389 @@
+// Job queue for hosts that run the module in a single worker and drive several scene
+// generations at once. A job wraps one of the async generation exports and is tracked
+// by id: `submit_job` queues it, `poll_job` reports its status and `get_job_result`
+// collects the outcome. Jobs of the same process run one after another, so they never
+// interleave on that process's ModuleState entries; jobs of different processes (and
+// jobs without a process) run concurrently, interleaving at their yield points.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::cancellation::job_error;
+use crate::chunking::now_ms;
+use crate::{elevation, scene, terrain, terrain_only, vectortile};
+
+/// Generation export a job runs
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JobKind {
+    /// `process_elevation_data_async`
+    Elevation,
+    /// `fetch_vector_tiles`
+    VectorTiles,
+    /// `extract_features_from_vector_tiles`
+    ExtractFeatures,
+    /// `process_polygon_geometry`
+    PolygonGeometry,
+    /// `create_terrain_geometry`
+    Terrain,
+    /// `generate_terrain_model`
+    TerrainModel,
+    /// `generate_scene`
+    Scene,
+}
+
+impl JobKind {
+    pub fn parse(kind: &str) -> Result<Self, String> {
+        match kind {
+            "elevation" => Ok(JobKind::Elevation),
+            "vectorTiles" => Ok(JobKind::VectorTiles),
+            "extractFeatures" => Ok(JobKind::ExtractFeatures),
+            "polygonGeometry" => Ok(JobKind::PolygonGeometry),
+            "terrain" => Ok(JobKind::Terrain),
+            "terrainModel" => Ok(JobKind::TerrainModel),
+            "scene" => Ok(JobKind::Scene),
+            other => Err(format!(
+                "Unknown job kind '{}', expected elevation, vectorTiles, extractFeatures, \
+                 polygonGeometry, terrain, terrainModel or scene",
+                other
+            )),
+        }
+    }
+
+    // Exports taking a JSON string rather than an object
+    fn takes_json(self) -> bool {
+        matches!(self, JobKind::Elevation | JobKind::TerrainModel | JobKind::Scene)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    /// Waiting for an earlier job of the same process
+    Queued,
+    Running,
+    /// Finished; the result is kept until `get_job_result` collects it
+    Done,
+    Failed,
+}
+
+/// Status of a job as reported by `poll_job`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    pub id: String,
+    pub kind: JobKind,
+    pub process_id: Option<String>,
+    pub status: JobStatus,
+    /// Error message of a failed job
+    pub error: Option<String>,
+    /// Jobs of the same process that run before this queued one
+    pub queue_position: Option<usize>,
+    pub submitted_ms: f64,
+    pub started_ms: Option<f64>,
+    pub finished_ms: Option<f64>,
+}
+
+/// Job bookkeeping, separate from the JS payloads and results so it can be tested natively
+#[derive(Default)]
+pub(crate) struct JobQueue {
+    jobs: HashMap<String, JobInfo>,
+    /// Queued jobs by process, in submission order
+    waiting: HashMap<String, VecDeque<String>>,
+    /// Running job by process
+    busy: HashMap<String, String>,
+    next_id: u64,
+}
+
+impl JobQueue {
+    /// Register a job; returns its id and whether it can start right away
+    pub fn submit(
+        &mut self,
+        kind: JobKind,
+        process_id: Option<String>,
+        now: f64,
+    ) -> (String, bool) {
+        self.next_id += 1;
+        let id = format!("job-{}", self.next_id);
+        let start_now = match &process_id {
+            Some(process) if self.busy.contains_key(process) => {
+                self.waiting
+                    .entry(process.clone())
+                    .or_default()
+                    .push_back(id.clone());
+                false
+            }
+            Some(process) => {
+                self.busy.insert(process.clone(), id.clone());
+                true
+            }
+            None => true,
+        };
+        self.jobs.insert(
+            id.clone(),
+            JobInfo {
+                id: id.clone(),
+                kind,
+                process_id,
+                status: if start_now {
+                    JobStatus::Running
+                } else {
+                    JobStatus::Queued
+                },
+                error: None,
+                queue_position: None,
+                submitted_ms: now,
+                started_ms: start_now.then_some(now),
+                finished_ms: None,
+            },
+        );
+        (id, start_now)
+    }
+
+    /// Record the outcome of a running job; returns the next job of its process, which
+    /// is marked running and has to be started by the caller
+    pub fn finish(&mut self, id: &str, outcome: Result<(), String>, now: f64) -> Option<String> {
+        let job = self.jobs.get_mut(id)?;
+        job.finished_ms = Some(now);
+        match outcome {
+            Ok(()) => job.status = JobStatus::Done,
+            Err(message) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(message);
+            }
+        }
+        let process = job.process_id.clone()?;
+        if self.busy.get(&process).map(String::as_str) != Some(id) {
+            return None;
+        }
+        let next = self.waiting.get_mut(&process).and_then(VecDeque::pop_front);
+        match &next {
+            Some(next_id) => {
+                self.busy.insert(process.clone(), next_id.clone());
+                if let Some(next_job) = self.jobs.get_mut(next_id) {
+                    next_job.status = JobStatus::Running;
+                    next_job.started_ms = Some(now);
+                }
+            }
+            None => {
+                self.busy.remove(&process);
+                self.waiting.remove(&process);
+            }
+        }
+        next
+    }
+
+    pub fn info(&self, id: &str) -> Option<JobInfo> {
+        let mut info = self.jobs.get(id)?.clone();
+        if info.status == JobStatus::Queued {
+            info.queue_position = info
+                .process_id
+                .as_ref()
+                .and_then(|process| self.waiting.get(process))
+                .and_then(|queue| queue.iter().position(|queued| queued == id))
+                .map(|position| position + 1);
+        }
+        Some(info)
+    }
+
+    /// Forget a finished job, returning its final status
+    pub fn take_finished(&mut self, id: &str) -> Result<JobInfo, String> {
+        match self.jobs.get(id).map(|job| job.status) {
+            None => Err(format!("Unknown job '{}'", id)),
+            Some(JobStatus::Queued | JobStatus::Running) => {
+                Err(format!("Job '{}' has not finished yet", id))
+            }
+            Some(_) => Ok(self.jobs.remove(id).expect("job was just looked up")),
+        }
+    }
+
+    /// Drop every job; running ones finish without being recorded. Returns the count.
+    pub fn clear(&mut self) -> usize {
+        let count = self.jobs.len();
+        self.jobs.clear();
+        self.waiting.clear();
+        self.busy.clear();
+        count
+    }
+}
+
+thread_local! {
+    static JOBS: RefCell<JobQueue> = RefCell::new(JobQueue::default());
+    // JS values are not Send, so payloads and results live next to the queue
+    static PAYLOADS: RefCell<HashMap<String, JsValue>> = RefCell::new(HashMap::new());
+    static RESULTS: RefCell<HashMap<String, JsValue>> = RefCell::new(HashMap::new());
+}
+
+// `processId` of an input object or JSON string
+fn payload_process_id(payload: &JsValue) -> Option<String> {
+    let object = match payload.as_string() {
+        Some(json) => js_sys::JSON::parse(&json).ok()?,
+        None => payload.clone(),
+    };
+    if !object.is_object() {
+        return None;
+    }
+    js_sys::Reflect::get(&object, &"processId".into())
+        .ok()?
+        .as_string()
+        .filter(|id| !id.is_empty())
+}
+
+// The payload in the form the job's export takes: a JSON string or an object
+fn payload_for(kind: JobKind, payload: JsValue) -> Result<JsValue, JsValue> {
+    match (kind.takes_json(), payload.as_string()) {
+        (true, Some(_)) => Ok(payload),
+        (true, None) => js_sys::JSON::stringify(&payload).map(JsValue::from),
+        (false, Some(json)) => js_sys::JSON::parse(&json),
+        (false, None) => Ok(payload),
+    }
+}
+
+async fn run_job(kind: JobKind, payload: JsValue) -> Result<JsValue, JsValue> {
+    let payload = payload_for(kind, payload)?;
+    let json = payload.as_string().unwrap_or_default();
+    match kind {
+        JobKind::Elevation => elevation::process_elevation_data_async(&json).await,
+        JobKind::VectorTiles => vectortile::fetch_vector_tiles(payload).await,
+        JobKind::ExtractFeatures => vectortile::extract_features_from_vector_tiles(payload).await,
+        JobKind::PolygonGeometry => crate::process_polygon_geometry(payload).await,
+        JobKind::Terrain => terrain::create_terrain_geometry(payload).await,
+        JobKind::TerrainModel => terrain_only::generate_terrain_model(&json).await,
+        JobKind::Scene => scene::generate_scene(&json).await,
+    }
+}
+
+// Error message of a failed job; cancelled jobs keep their "Cancelled" prefix
+fn error_message(error: &JsValue) -> String {
+    error
+        .as_string()
+        .or_else(|| error.dyn_ref::<js_sys::Error>().map(|e| e.message().into()))
+        .unwrap_or_else(|| "Job failed".to_string())
+}
+
+fn start_job(id: String) {
+    let Some(kind) = JOBS.with(|jobs| jobs.borrow().jobs.get(&id).map(|job| job.kind)) else {
+        return;
+    };
+    let payload = PAYLOADS
+        .with(|payloads| payloads.borrow_mut().remove(&id))
+        .unwrap_or(JsValue::UNDEFINED);
+    wasm_bindgen_futures::spawn_local(async move {
+        let outcome = match run_job(kind, payload).await {
+            Ok(result) => {
+                RESULTS.with(|results| results.borrow_mut().insert(id.clone(), result));
+                Ok(())
+            }
+            Err(error) => Err(error_message(&error)),
+        };
+        let next = JOBS.with(|jobs| jobs.borrow_mut().finish(&id, outcome, now_ms()));
+        // A job dropped by reset_module has nowhere to keep its result
+        if JOBS.with(|jobs| !jobs.borrow().jobs.contains_key(&id)) {
+            RESULTS.with(|results| results.borrow_mut().remove(&id));
+        }
+        if let Some(next) = next {
+            start_job(next);
+        }
+    });
+}
+
+/// Queue a generation job and return its id. `kind` is one of "elevation",
+/// "vectorTiles", "extractFeatures", "polygonGeometry", "terrain", "terrainModel" or
+/// "scene"; `payload` is the input of the matching export, as an object or a JSON
+/// string. Jobs whose payload has the same `processId` run in submission order.
+#[wasm_bindgen]
+pub fn submit_job(kind: &str, payload: JsValue) -> Result<String, JsValue> {
+    let kind = JobKind::parse(kind).map_err(|e| JsValue::from_str(&e))?;
+    let process_id = payload_process_id(&payload);
+    let (id, start_now) = JOBS.with(|jobs| jobs.borrow_mut().submit(kind, process_id, now_ms()));
+    PAYLOADS.with(|payloads| payloads.borrow_mut().insert(id.clone(), payload));
+    if start_now {
+        start_job(id.clone());
+    }
+    Ok(id)
+}
+
+/// Status of a job as a `JobInfo` JSON object
+#[wasm_bindgen]
+pub fn poll_job(job_id: &str) -> Result<String, JsValue> {
+    let info = JOBS
+        .with(|jobs| jobs.borrow().info(job_id))
+        .ok_or_else(|| JsValue::from_str(&format!("Unknown job '{}'", job_id)))?;
+    serde_json::to_string(&info)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize job status: {}", e)))
+}
+
+/// Result of a finished job, which is forgotten afterwards. A failed job rejects with
+/// its error (an Error named "Cancelled" when its process was cancelled); a job still
+/// queued or running is an error and stays registered.
+#[wasm_bindgen]
+pub fn get_job_result(job_id: &str) -> Result<JsValue, JsValue> {
+    let info = JOBS
+        .with(|jobs| jobs.borrow_mut().take_finished(job_id))
+        .map_err(|e| JsValue::from_str(&e))?;
+    let result = RESULTS.with(|results| results.borrow_mut().remove(job_id));
+    match info.error {
+        Some(message) => Err(job_error(&message)),
+        None => Ok(result.unwrap_or(JsValue::UNDEFINED)),
+    }
+}
+
+/// Forget all jobs and their results, e.g. on module reset; returns how many there were
+pub(crate) fn clear_jobs() -> usize {
+    PAYLOADS.with(|payloads| payloads.borrow_mut().clear());
+    RESULTS.with(|results| results.borrow_mut().clear());
+    JOBS.with(|jobs| jobs.borrow_mut().clear())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_of_a_process_run_in_submission_order() {
+        let mut queue = JobQueue::default();
+        let a = Some("a".to_string());
+        let (first, first_starts) = queue.submit(JobKind::Elevation, a.clone(), 1.0);
+        let (second, second_starts) = queue.submit(JobKind::Terrain, a.clone(), 2.0);
+        let (third, third_starts) = queue.submit(JobKind::PolygonGeometry, a, 3.0);
+        let (other, other_starts) = queue.submit(JobKind::Elevation, Some("b".into()), 4.0);
+        let (free, free_starts) = queue.submit(JobKind::VectorTiles, None, 5.0);
+        assert!(first_starts && !second_starts && !third_starts && other_starts && free_starts);
+        assert_eq!(queue.info(&third).unwrap().queue_position, Some(2));
+
+        assert_eq!(queue.finish(&first, Ok(()), 6.0), Some(second.clone()));
+        assert_eq!(queue.info(&second).unwrap().status, JobStatus::Running);
+        assert_eq!(queue.info(&third).unwrap().queue_position, Some(1));
+        assert_eq!(
+            queue.finish(&second, Err("boom".into()), 7.0),
+            Some(third.clone())
+        );
+        assert_eq!(queue.finish(&third, Ok(()), 8.0), None);
+        assert_eq!(queue.finish(&other, Ok(()), 9.0), None);
+        assert_eq!(queue.finish(&free, Ok(()), 9.0), None);
+
+        // The process is idle again, so a new job starts at once
+        assert!(queue.submit(JobKind::Terrain, Some("a".into()), 10.0).1);
+    }
+
+    #[test]
+    fn finished_jobs_are_taken_once() {
+        let mut queue = JobQueue::default();
+        let (id, _) = queue.submit(JobKind::Terrain, Some("p".into()), 0.0);
+        assert!(queue
+            .take_finished(&id)
+            .unwrap_err()
+            .contains("not finished"));
+        queue.finish(&id, Err("Cancelled: process p was cancelled".into()), 1.0);
+        let info = queue.take_finished(&id).unwrap();
+        assert_eq!(info.status, JobStatus::Failed);
+        assert_eq!(info.finished_ms, Some(1.0));
+        assert!(queue
+            .take_finished(&id)
+            .unwrap_err()
+            .starts_with("Unknown job"));
+        assert!(JobKind::parse("mesh").is_err());
+    }
+}
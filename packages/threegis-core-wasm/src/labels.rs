@@ -0,0 +1,693 @@
+// Text labels for feature names (street names, peaks, ...) placed on the terrain surface.
+// Glyphs come from the caller's font data: outlines (font units, y up, baseline at 0) are
+// extruded into embossed text that prints with the model; atlas quads with texture
+// coordinates give flat labels for the preview. Points are labelled at the point, lines
+// at their middle along the line direction and polygons at their centroid.
+use earcutr::earcut;
+use serde::Deserialize;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::clip_shape;
+use crate::polygon_buffer::PolygonRings;
+use crate::polygon_geometry::{
+    tag_layer_metadata, transform_to_mesh_coordinates, BufferGeometry, GeometryData,
+    PolygonGeometryInput,
+};
+use crate::projection::{self, terrain_size};
+use crate::vectortile::point_in_ring;
+
+/// Font data: glyphs keyed by character. `contours` are closed outlines in font units,
+/// `quad` is the glyph rectangle [x0, y0, x1, y1] in font units and `uv` its rectangle
+/// [u0, v0, u1, v1] in the atlas texture.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FontData {
+    units_per_em: f64,
+    glyphs: HashMap<char, Glyph>,
+}
+
+#[derive(Deserialize)]
+struct Glyph {
+    advance: f64,
+    #[serde(default)]
+    contours: Vec<Vec<Vec<f64>>>,
+    #[serde(default)]
+    quad: Option<[f64; 4]>,
+    #[serde(default)]
+    uv: Option<[f64; 4]>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum LabelMode {
+    /// Glyph outlines extruded from below the surface to `textDepth` above it
+    #[default]
+    Extruded,
+    /// Flat text just above the surface: atlas quads, else the glyph outlines
+    Flat,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LabelOptions {
+    /// Feature property holding the label text
+    #[serde(default = "default_text_property")]
+    text_property: String,
+    /// Size of one em in terrain units
+    #[serde(default = "default_text_size")]
+    text_size: f64,
+    #[serde(default)]
+    mode: LabelMode,
+    /// Height of extruded text above the highest terrain point under it, in terrain units
+    #[serde(default = "default_text_depth")]
+    text_depth: f64,
+    /// Labels with the same text closer than this (terrain units) are placed once, so a
+    /// street split into many pieces is not labelled on every piece
+    #[serde(default = "default_min_distance")]
+    min_distance: f64,
+}
+
+impl Default for LabelOptions {
+    fn default() -> Self {
+        LabelOptions {
+            text_property: default_text_property(),
+            text_size: default_text_size(),
+            mode: LabelMode::default(),
+            text_depth: default_text_depth(),
+            min_distance: default_min_distance(),
+        }
+    }
+}
+
+fn default_text_property() -> String {
+    "name".to_string()
+}
+fn default_text_size() -> f64 {
+    4.0
+}
+fn default_text_depth() -> f64 {
+    0.6
+}
+fn default_min_distance() -> f64 {
+    40.0
+}
+
+#[derive(Deserialize, Default)]
+struct LabelLayerOptions {
+    #[serde(default)]
+    labels: LabelOptions,
+}
+
+/// Glyphs of a label laid out in mesh XY, before its heights are known
+struct GlyphLayout {
+    /// Glyph polygons, exterior ring first and holes after it
+    polygons: Vec<PolygonRings>,
+    /// Atlas quads: corners counter-clockwise from the bottom left, and the uv rectangle
+    quads: Vec<([[f64; 2]; 4], [f64; 4])>,
+    /// Corners of the label rectangle, where the terrain under the label is sampled
+    footprint: [[f64; 2]; 4],
+}
+
+struct LabelLayout {
+    text: String,
+    feature: usize,
+    anchor: [f64; 2],
+    glyphs: GlyphLayout,
+}
+
+/// Where a label goes on a feature and the direction its text runs, in mesh coordinates
+fn label_anchor(feature: &GeometryData, bbox: &[f64]) -> Option<([f64; 2], f64)> {
+    let points: Vec<[f64; 2]> = feature
+        .geometry
+        .iter()
+        .filter(|p| p.len() >= 2 && p[0].is_finite() && p[1].is_finite())
+        .map(|p| transform_to_mesh_coordinates(p[0], p[1], bbox))
+        .collect();
+    match feature.r#type.as_deref() {
+        Some("Point") => points.first().map(|p| (*p, 0.0)),
+        Some("LineString") => line_midpoint(&points),
+        Some("Polygon") => polygon_centroid(&points).map(|c| (c, 0.0)),
+        _ => None,
+    }
+}
+
+// Point halfway along a line and the direction of the line there, turned so text placed
+// along it reads left to right
+fn line_midpoint(points: &[[f64; 2]]) -> Option<([f64; 2], f64)> {
+    let length: f64 = points
+        .windows(2)
+        .map(|w| (w[1][0] - w[0][0]).hypot(w[1][1] - w[0][1]))
+        .sum();
+    if length <= 0.0 {
+        return None;
+    }
+    let mut remaining = length / 2.0;
+    for w in points.windows(2) {
+        let (dx, dy) = (w[1][0] - w[0][0], w[1][1] - w[0][1]);
+        let segment = dx.hypot(dy);
+        if segment <= 0.0 || remaining > segment {
+            remaining -= segment;
+            continue;
+        }
+        let t = remaining / segment;
+        let mut angle = dy.atan2(dx);
+        if angle > std::f64::consts::FRAC_PI_2 {
+            angle -= std::f64::consts::PI;
+        } else if angle <= -std::f64::consts::FRAC_PI_2 {
+            angle += std::f64::consts::PI;
+        }
+        return Some(([w[0][0] + dx * t, w[0][1] + dy * t], angle));
+    }
+    None
+}
+
+// Area centroid of a ring; the mean of its points when it has no area
+fn polygon_centroid(points: &[[f64; 2]]) -> Option<[f64; 2]> {
+    if points.is_empty() {
+        return None;
+    }
+    let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        let cross = a[0] * b[1] - b[0] * a[1];
+        area += cross;
+        cx += (a[0] + b[0]) * cross;
+        cy += (a[1] + b[1]) * cross;
+    }
+    if area.abs() < 1e-12 {
+        let n = points.len() as f64;
+        let sum = points
+            .iter()
+            .fold([0.0, 0.0], |s, p| [s[0] + p[0], s[1] + p[1]]);
+        return Some([sum[0] / n, sum[1] / n]);
+    }
+    Some([cx / (3.0 * area), cy / (3.0 * area)])
+}
+
+/// Lay `text` out centered on `anchor`, running in direction `angle`. Characters without
+/// a glyph are skipped; None when nothing of the text can be drawn.
+fn layout_label(
+    text: &str,
+    font: &FontData,
+    text_size: f64,
+    anchor: [f64; 2],
+    angle: f64,
+) -> Option<GlyphLayout> {
+    let scale = text_size / font.units_per_em;
+    // Glyph geometry in font units along the baseline
+    let mut polygons = Vec::new();
+    let mut quads = Vec::new();
+    let mut pen = 0.0;
+    for c in text.chars() {
+        let Some(glyph) = font.glyphs.get(&c) else {
+            pen += font.units_per_em / 2.0;
+            continue;
+        };
+        let shifted: Vec<Vec<Vec<f64>>> = glyph
+            .contours
+            .iter()
+            .map(|ring| {
+                ring.iter()
+                    .filter(|p| p.len() >= 2)
+                    .map(|p| vec![p[0] + pen, p[1]])
+                    .collect()
+            })
+            .collect();
+        polygons.extend(nest_contours(shifted));
+        if let (Some(quad), Some(uv)) = (glyph.quad, glyph.uv) {
+            let [x0, y0, x1, y1] = quad;
+            quads.push((
+                [
+                    [x0 + pen, y0],
+                    [x1 + pen, y0],
+                    [x1 + pen, y1],
+                    [x0 + pen, y1],
+                ],
+                uv,
+            ));
+        }
+        pen += glyph.advance;
+    }
+
+    let mut min = [f64::INFINITY; 2];
+    let mut max = [f64::NEG_INFINITY; 2];
+    let outline_points = polygons.iter().flatten().flatten().map(|p| [p[0], p[1]]);
+    let quad_points = quads
+        .iter()
+        .flat_map(|(corners, _)| corners.iter().copied());
+    for p in outline_points.chain(quad_points) {
+        min = [min[0].min(p[0]), min[1].min(p[1])];
+        max = [max[0].max(p[0]), max[1].max(p[1])];
+    }
+    if !min[0].is_finite() {
+        return None;
+    }
+
+    // Center on the glyph bounds, scale to terrain units and turn along the feature
+    let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    let (sin, cos) = angle.sin_cos();
+    let place = |x: f64, y: f64| -> [f64; 2] {
+        let (x, y) = ((x - center[0]) * scale, (y - center[1]) * scale);
+        [anchor[0] + x * cos - y * sin, anchor[1] + x * sin + y * cos]
+    };
+    let polygons = polygons
+        .into_iter()
+        .map(|rings| {
+            rings
+                .into_iter()
+                .map(|ring| ring.iter().map(|p| place(p[0], p[1]).to_vec()).collect())
+                .collect()
+        })
+        .collect();
+    let quads = quads
+        .into_iter()
+        .map(|(corners, uv)| (corners.map(|p| place(p[0], p[1])), uv))
+        .collect();
+    let footprint = [
+        place(min[0], min[1]),
+        place(max[0], min[1]),
+        place(max[0], max[1]),
+        place(min[0], max[1]),
+    ];
+    Some(GlyphLayout {
+        polygons,
+        quads,
+        footprint,
+    })
+}
+
+/// Group the contours of a glyph into polygons. A contour inside an odd number of others
+/// is a hole of the smallest contour containing it, any other contour is an exterior.
+fn nest_contours(contours: Vec<Vec<Vec<f64>>>) -> Vec<PolygonRings> {
+    let contours: Vec<Vec<Vec<f64>>> = contours.into_iter().filter(|c| c.len() >= 3).collect();
+    let areas: Vec<f64> = contours.iter().map(|c| ring_area(c).abs()).collect();
+    let parents: Vec<Vec<usize>> = (0..contours.len())
+        .map(|i| {
+            (0..contours.len())
+                .filter(|&j| j != i && point_in_ring(&contours[i][0], &contours[j]))
+                .collect()
+        })
+        .collect();
+
+    let mut polygons: Vec<PolygonRings> = Vec::new();
+    let mut polygon_of: HashMap<usize, usize> = HashMap::new();
+    for i in (0..contours.len()).filter(|&i| parents[i].len().is_multiple_of(2)) {
+        polygon_of.insert(i, polygons.len());
+        polygons.push(vec![oriented(contours[i].clone(), true)]);
+    }
+    for i in (0..contours.len()).filter(|&i| parents[i].len() % 2 == 1) {
+        let owner = parents[i]
+            .iter()
+            .filter(|j| polygon_of.contains_key(j))
+            .min_by(|a, b| areas[**a].total_cmp(&areas[**b]));
+        if let Some(owner) = owner {
+            polygons[polygon_of[owner]].push(oriented(contours[i].clone(), false));
+        }
+    }
+    polygons
+}
+
+fn ring_area(ring: &[Vec<f64>]) -> f64 {
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (&ring[i], &ring[(i + 1) % ring.len()]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+// Ring without a closing point, wound counter-clockwise (`ccw`) or clockwise
+fn oriented(mut ring: Vec<Vec<f64>>, ccw: bool) -> Vec<Vec<f64>> {
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    if (ring_area(&ring) > 0.0) != ccw {
+        ring.reverse();
+    }
+    ring
+}
+
+/// Triangle mesh of a label with per-face normals
+#[derive(Default)]
+struct LabelMesh {
+    vertices: Vec<f32>,
+    normals: Vec<f32>,
+    uvs: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl LabelMesh {
+    fn push_vertex(&mut self, p: [f64; 3], normal: [f64; 3]) -> u32 {
+        let index = (self.vertices.len() / 3) as u32;
+        self.vertices.extend(p.map(|v| v as f32));
+        self.normals.extend(normal.map(|v| v as f32));
+        index
+    }
+
+    /// Cap of a polygon at height `z`, facing up or down
+    fn push_cap(&mut self, rings: &[Vec<Vec<f64>>], z: f64, up: bool) {
+        let mut data = Vec::new();
+        let mut hole_indices = Vec::new();
+        for (i, ring) in rings.iter().enumerate() {
+            if i > 0 {
+                hole_indices.push(data.len() / 2);
+            }
+            data.extend(ring.iter().flat_map(|p| [p[0], p[1]]));
+        }
+        let Ok(triangles) = earcut(&data, &hole_indices, 2) else {
+            return;
+        };
+        let normal = [0.0, 0.0, if up { 1.0 } else { -1.0 }];
+        let base = (self.vertices.len() / 3) as u32;
+        for p in data.chunks(2) {
+            self.push_vertex([p[0], p[1], z], normal);
+        }
+        for t in triangles.chunks(3) {
+            let (a, b, c) = (t[0], t[1], t[2]);
+            let cross = (data[2 * b] - data[2 * a]) * (data[2 * c + 1] - data[2 * a + 1])
+                - (data[2 * b + 1] - data[2 * a + 1]) * (data[2 * c] - data[2 * a]);
+            // Counter-clockwise seen from the side the cap faces
+            let (b, c) = if (cross > 0.0) == up { (b, c) } else { (c, b) };
+            self.indices
+                .extend([base + a as u32, base + b as u32, base + c as u32]);
+        }
+    }
+
+    /// Closed prism of a polygon from `bottom` to `top`. Exteriors are wound
+    /// counter-clockwise and holes clockwise, so every wall faces out of the solid.
+    fn push_prism(&mut self, rings: &[Vec<Vec<f64>>], bottom: f64, top: f64) {
+        self.push_cap(rings, top, true);
+        self.push_cap(rings, bottom, false);
+        for ring in rings {
+            for i in 0..ring.len() {
+                let (a, b) = (&ring[i], &ring[(i + 1) % ring.len()]);
+                let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+                let length = dx.hypot(dy);
+                if length <= 0.0 {
+                    continue;
+                }
+                let normal = [dy / length, -dx / length, 0.0];
+                let corners = [
+                    [a[0], a[1], bottom],
+                    [b[0], b[1], bottom],
+                    [b[0], b[1], top],
+                    [a[0], a[1], top],
+                ];
+                let first = self.push_vertex(corners[0], normal);
+                for corner in &corners[1..] {
+                    self.push_vertex(*corner, normal);
+                }
+                self.indices
+                    .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+            }
+        }
+    }
+
+    /// Atlas quad at height `z`; texture v runs down the atlas image
+    fn push_quad(&mut self, corners: &[[f64; 2]; 4], uv: [f64; 4], z: f64) {
+        let [u0, v0, u1, v1] = uv;
+        let corner_uvs = [[u0, v1], [u1, v1], [u1, v0], [u0, v0]];
+        let first = (self.vertices.len() / 3) as u32;
+        for (corner, corner_uv) in corners.iter().zip(corner_uvs) {
+            self.push_vertex([corner[0], corner[1], z], [0.0, 0.0, 1.0]);
+            self.uvs.extend(corner_uv.map(|v| v as f32));
+        }
+        self.indices
+            .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+fn label_text(feature: &GeometryData, property: &str) -> Option<String> {
+    let value = feature
+        .properties
+        .as_ref()
+        .and_then(|p| p.get(property))
+        .or_else(|| feature.tags.as_ref().and_then(|t| t.get(property)))?;
+    let text = match value {
+        serde_json::Value::String(s) => s.trim().to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// Lay out one label per named feature, dropping repeats of the same text close by and
+/// labels reaching past the edge of the terrain or outside the `clip` outline
+fn layout_labels(
+    features: &[GeometryData],
+    bbox: &[f64],
+    font: &FontData,
+    options: &LabelOptions,
+    clip: Option<&[[f64; 2]]>,
+) -> Vec<LabelLayout> {
+    let half = terrain_size() / 2.0;
+    let mut placed: Vec<LabelLayout> = Vec::new();
+    for (index, feature) in features.iter().enumerate() {
+        let Some(text) = label_text(feature, &options.text_property) else {
+            continue;
+        };
+        let Some((anchor, angle)) = label_anchor(feature, bbox) else {
+            continue;
+        };
+        let repeated = placed.iter().any(|label| {
+            label.text == text
+                && (anchor[0] - label.anchor[0]).hypot(anchor[1] - label.anchor[1])
+                    < options.min_distance
+        });
+        if repeated {
+            continue;
+        }
+        let Some(glyphs) = layout_label(&text, font, options.text_size, anchor, angle) else {
+            continue;
+        };
+        if glyphs.footprint.iter().any(|p| {
+            p[0].abs() > half
+                || p[1].abs() > half
+                || clip.is_some_and(|clip| !clip_shape::contains(clip, *p, 0.0))
+        }) {
+            continue;
+        }
+        placed.push(LabelLayout {
+            text,
+            feature: index,
+            anchor,
+            glyphs,
+        });
+    }
+    placed
+}
+
+fn build_labels(
+    mut input: PolygonGeometryInput,
+    font: &FontData,
+    options: &LabelOptions,
+) -> Result<Vec<BufferGeometry>, String> {
+    if !font.units_per_em.is_finite() || font.units_per_em <= 0.0 {
+        return Err("Font unitsPerEm must be positive".to_string());
+    }
+    if !options.text_size.is_finite() || options.text_size <= 0.0 {
+        return Err(format!(
+            "textSize must be positive, got {}",
+            options.text_size
+        ));
+    }
+    if options.mode == LabelMode::Extruded && font.glyphs.values().all(|g| g.contours.is_empty()) {
+        return Err(
+            "Extruded labels need glyph contours; use mode 'flat' for atlas fonts".to_string(),
+        );
+    }
+    projection::activate_process(&input.process_id);
+    let clip = clip_shape::process_clip_footprint(&input.process_id, &input.bbox)?;
+    let labels = layout_labels(&input.polygons, &input.bbox, font, options, clip.as_deref());
+
+    // Terrain under the corners and the middle of every label
+    let samples: Vec<[f64; 2]> = labels
+        .iter()
+        .flat_map(|label| {
+            let f = label.glyphs.footprint;
+            let middle = [(f[0][0] + f[2][0]) / 2.0, (f[0][1] + f[2][1]) / 2.0];
+            [f[0], f[1], f[2], f[3], middle]
+        })
+        .collect();
+    let heights = input.surface_heights(&samples)?;
+    let clearance = input.min_clearance();
+    let submerge = input.submerge_offset();
+
+    let mut geometries = Vec::with_capacity(labels.len());
+    for (label, heights) in labels.iter().zip(heights.chunks(5)) {
+        let lowest = heights.iter().copied().fold(f64::INFINITY, f64::min);
+        let highest = heights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mut mesh = LabelMesh::default();
+        match options.mode {
+            LabelMode::Extruded => {
+                for rings in &label.glyphs.polygons {
+                    mesh.push_prism(rings, lowest - submerge, highest + options.text_depth);
+                }
+            }
+            LabelMode::Flat if !label.glyphs.quads.is_empty() => {
+                for (corners, uv) in &label.glyphs.quads {
+                    mesh.push_quad(corners, *uv, highest + clearance);
+                }
+            }
+            LabelMode::Flat => {
+                for rings in &label.glyphs.polygons {
+                    mesh.push_cap(rings, highest + clearance, true);
+                }
+            }
+        }
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let feature = &input.polygons[label.feature];
+        let mut properties: Option<HashMap<String, serde_json::Value>> = feature
+            .properties
+            .as_ref()
+            .and_then(|p| serde_json::from_value(p.clone()).ok());
+        properties.get_or_insert_with(HashMap::new).insert(
+            "text".to_string(),
+            serde_json::Value::String(label.text.clone()),
+        );
+        tag_layer_metadata(&mut properties, &input.vt_data_set);
+        let vertex_count = mesh.vertices.len() / 3;
+        geometries.push(BufferGeometry {
+            colors: input
+                .vt_data_set
+                .feature_color(feature)
+                .map(|rgb| rgb.repeat(vertex_count)),
+            vertices: mesh.vertices,
+            normals: Some(mesh.normals),
+            indices: Some(mesh.indices),
+            uvs: if mesh.uvs.is_empty() {
+                None
+            } else {
+                Some(mesh.uvs)
+            },
+            has_data: true,
+            properties,
+        });
+    }
+    Ok(geometries)
+}
+
+// Text label geometry for the named features of a layer. `layer` is a layer input as for
+// process_polygon_geometry with an optional `labels` object ({ textProperty, textSize,
+// mode: "extruded" | "flat", textDepth, minDistance }); features come from `polygons` or
+// the process cache. `font_json` is { unitsPerEm, glyphs: { "A": { advance, contours?,
+// quad?, uv? } } }. Returns one geometry per label with its text in `properties.text`.
+#[wasm_bindgen]
+pub fn generate_labels(layer: JsValue, font_json: &str) -> Result<JsValue, JsValue> {
+    let options: LabelLayerOptions = match layer.as_string() {
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid label options: {}", e)))?,
+        None => serde_wasm_bindgen::from_value(layer.clone())
+            .map_err(|e| JsValue::from_str(&format!("Invalid label options: {}", e)))?,
+    };
+    let font: FontData = serde_json::from_str(font_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse font: {}", e)))?;
+    let mut input = crate::parse_layer_input(layer)?;
+    if input.bbox.len() != 4 {
+        return Err(JsValue::from_str(
+            "Invalid 'bbox': must contain [minLng, minLat, maxLng, maxLat]",
+        ));
+    }
+    if input.polygons.is_empty() {
+        input.polygons = crate::cached_layer_features(&input);
+    }
+    let geometries =
+        build_labels(input, &font, &options.labels).map_err(|e| JsValue::from_str(&e))?;
+    Ok(crate::geometries_to_js(&geometries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64) -> Vec<Vec<f64>> {
+        vec![
+            vec![min, min],
+            vec![max, min],
+            vec![max, max],
+            vec![min, max],
+        ]
+    }
+
+    #[test]
+    fn test_contours_nest_into_polygons_with_holes() {
+        // An "O" with a dot inside its counter
+        let mut counter = square(2.0, 8.0);
+        counter.reverse();
+        let polygons = nest_contours(vec![square(0.0, 10.0), counter, square(4.0, 6.0)]);
+        assert_eq!(polygons.len(), 2);
+        assert_eq!(polygons[0].len(), 2);
+        assert!(ring_area(&polygons[0][0]) > 0.0);
+        assert!(ring_area(&polygons[0][1]) < 0.0);
+        assert_eq!(polygons[1].len(), 1);
+        assert!((ring_area(&polygons[1][0]) - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_line_labels_read_left_to_right() {
+        // Drawn right to left, the label still runs along +x
+        let (anchor, angle) = line_midpoint(&[[10.0, 0.0], [4.0, 0.0], [0.0, 0.0]]).unwrap();
+        assert_eq!(anchor, [5.0, 0.0]);
+        assert!(angle.abs() < 1e-12);
+
+        let font = FontData {
+            units_per_em: 1000.0,
+            glyphs: HashMap::from([(
+                'I',
+                Glyph {
+                    advance: 400.0,
+                    contours: vec![vec![
+                        vec![100.0, 0.0],
+                        vec![300.0, 0.0],
+                        vec![300.0, 700.0],
+                        vec![100.0, 700.0],
+                    ]],
+                    quad: None,
+                    uv: None,
+                },
+            )]),
+        };
+        let glyphs = layout_label("II", &font, 10.0, anchor, angle).unwrap();
+        assert_eq!(glyphs.polygons.len(), 2);
+        let footprint = glyphs.footprint;
+        // 0.6 em wide and 0.7 em high, centered on the anchor
+        assert!((footprint[0][0] - 2.0).abs() < 1e-9);
+        assert!((footprint[2][0] - 8.0).abs() < 1e-9);
+        assert!((footprint[0][1] + 3.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prism_is_closed() {
+        let mut hole = square(1.0, 3.0);
+        hole.reverse();
+        let mut mesh = LabelMesh::default();
+        mesh.push_prism(&[square(0.0, 4.0), hole], -1.0, 2.0);
+
+        // Volume by the divergence theorem is the ring area times the height when every
+        // triangle faces out of the solid
+        let v = |i: u32| {
+            let i = i as usize * 3;
+            [
+                mesh.vertices[i] as f64,
+                mesh.vertices[i + 1] as f64,
+                mesh.vertices[i + 2] as f64,
+            ]
+        };
+        let volume: f64 = mesh
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let (a, b, c) = (v(t[0]), v(t[1]), v(t[2]));
+                a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0])
+            })
+            .sum::<f64>()
+            / 6.0;
+        assert!((volume - 12.0 * 3.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,211 @@
+// Finished per-layer geometry kept in ModuleState, keyed by the hash of the layer's
+// config and terrain generation. Regenerating a layer with an unchanged config is served from here,
+// and the UI can fetch a layer again (toggling, re-exporting subsets) without recompute.
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::{BufferGeometry, PolygonGeometryInput};
+
+pub struct CachedLayerGeometry {
+    pub config_hash: String,
+    /// Vertices follow the terrain surface individually (alignVerticesToTerrain)
+    pub terrain_aligned: bool,
+    pub geometries: Vec<BufferGeometry>,
+}
+
+/// Feeds serialized JSON straight into a hasher without building the string
+struct HashWriter<'a>(&'a mut DefaultHasher);
+
+impl std::io::Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Hasher::write(self.0, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash of the serialized generation input of a layer
+pub(crate) fn config_hash<T: Serialize + ?Sized>(input: &T) -> String {
+    let mut hasher = DefaultHasher::new();
+    // Writing into the hasher cannot fail; unserializable input hashes what was written
+    let _ = serde_json::to_writer(HashWriter(&mut hasher), input);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Terrain a layer is generated on, without its elevation or vertex data
+#[derive(Serialize)]
+struct TerrainIdentity {
+    /// Changes of the stored elevation grid and terrain mesh of the process
+    generation: u64,
+    /// Sizes of the terrain data sent along with the layer input
+    elevation_rows: usize,
+    vertices: usize,
+    vertices_base64: usize,
+}
+
+/// Hash of a layer's config (bbox, vtDataSet, terrain settings) and the terrain it is
+/// generated on. The serialized input leaves out the terrain arrays, which would be
+/// rehashed on every call; the terrain generation of the process stands in for them.
+pub(crate) fn layer_config_hash(input: &PolygonGeometryInput) -> String {
+    let terrain = TerrainIdentity {
+        generation: ModuleState::with(|state| state.terrain_generation(&input.process_id)),
+        elevation_rows: input.elevation_grid.len(),
+        vertices: input.terrain_vertices.len(),
+        vertices_base64: input.terrain_vertices_base64.len(),
+    };
+    config_hash(&(input, terrain))
+}
+
+pub(crate) fn store_layer_geometry(
+    process_id: &str,
+    layer_label: &str,
+    config_hash: String,
+    terrain_aligned: bool,
+    geometries: Vec<BufferGeometry>,
+) {
+    ModuleState::with_mut(|state| {
+        state
+            .layer_geometries
+            .entry(process_id.to_string())
+            .or_default()
+            .insert(
+                layer_label.to_string(),
+                CachedLayerGeometry {
+                    config_hash,
+                    terrain_aligned,
+                    geometries,
+                },
+            );
+    });
+}
+
+/// Run `f` on the cached geometry of a layer when it was generated from the same config
+pub(crate) fn with_cached_layer<R>(
+    process_id: &str,
+    layer_label: &str,
+    config_hash: &str,
+    f: impl FnOnce(&[BufferGeometry]) -> R,
+) -> Option<R> {
+    ModuleState::with(|state| {
+        state
+            .layer_geometries
+            .get(process_id)
+            .and_then(|layers| layers.get(layer_label))
+            .filter(|cached| cached.config_hash == config_hash)
+            .map(|cached| f(&cached.geometries))
+    })
+}
+
+/// Cached geometry of a generated layer in the same shape `process_polygon_geometry`
+/// returns, or null when the layer has not been generated for this process
+#[wasm_bindgen]
+pub fn get_layer_geometry(process_id: &str, layer: &str) -> JsValue {
+    ModuleState::with(|state| {
+        state
+            .layer_geometries
+            .get(process_id)
+            .and_then(|layers| layers.get(layer))
+            .map(|cached| crate::geometries_to_js(&cached.geometries))
+            .unwrap_or(JsValue::NULL)
+    })
+}
+
+/// `get_layer_geometry` in the packed binary layout (see packed_geometry), or undefined
+#[wasm_bindgen]
+pub fn get_layer_geometry_packed(process_id: &str, layer: &str) -> Option<Vec<u8>> {
+    ModuleState::with(|state| {
+        state
+            .layer_geometries
+            .get(process_id)
+            .and_then(|layers| layers.get(layer))
+            .map(|cached| crate::packed_geometry::pack_geometries(&cached.geometries))
+    })
+}
+
+/// Labels of the layers with cached geometry for a process
+#[wasm_bindgen]
+pub fn get_cached_layers(process_id: &str) -> Vec<String> {
+    ModuleState::with(|state| {
+        let mut labels: Vec<String> = state
+            .layer_geometries
+            .get(process_id)
+            .map(|layers| layers.keys().cloned().collect())
+            .unwrap_or_default();
+        labels.sort();
+        labels
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_layer_requires_matching_config() {
+        let geometry = BufferGeometry {
+            vertices: vec![0.0; 9],
+            normals: None,
+            colors: None,
+            indices: None,
+            uvs: None,
+            has_data: true,
+            properties: None,
+        };
+        let hash = config_hash(r#"{"vtDataSet":{"sourceLayer":"water"}}"#);
+        store_layer_geometry(
+            "layer-cache-test",
+            "water",
+            hash.clone(),
+            true,
+            vec![geometry],
+        );
+
+        let count = |hash: &str| {
+            with_cached_layer("layer-cache-test", "water", hash, |geometries| {
+                geometries.len()
+            })
+        };
+        assert_eq!(count(&hash), Some(1));
+        assert_eq!(
+            count(&config_hash(r#"{"vtDataSet":{"sourceLayer":"road"}}"#)),
+            None
+        );
+        assert_eq!(
+            get_cached_layers("layer-cache-test"),
+            vec!["water".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_layer_hash_follows_config_and_terrain_generation() {
+        let input = |source_layer: &str, elevation: f64| -> PolygonGeometryInput {
+            serde_json::from_value(serde_json::json!({
+                "bbox": [8.0, 47.0, 8.01, 47.01],
+                "elevationGrid": [[elevation, elevation], [elevation, elevation]],
+                "gridSize": { "width": 2, "height": 2 },
+                "vtDataSet": { "sourceLayer": source_layer },
+                "processId": "layer-hash-test"
+            }))
+            .unwrap()
+        };
+        let hash = layer_config_hash(&input("water", 100.0));
+        // The grid values are not hashed, the terrain generation of the process is
+        assert_eq!(layer_config_hash(&input("water", 250.0)), hash);
+        assert_ne!(layer_config_hash(&input("landuse", 100.0)), hash);
+
+        crate::store_terrain_mesh("layer-hash-test", vec![0.0; 9], vec![0, 1, 2]);
+        let stored = layer_config_hash(&input("water", 100.0));
+        assert_ne!(stored, hash);
+        crate::store_terrain_mesh("layer-hash-test", vec![0.0; 9], vec![0, 1, 2]);
+        assert_eq!(layer_config_hash(&input("water", 100.0)), stored);
+        crate::store_terrain_mesh("layer-hash-test", vec![1.0; 9], vec![0, 1, 2]);
+        assert_ne!(layer_config_hash(&input("water", 100.0)), stored);
+        ModuleState::with_mut(|state| state.clear_process_data("layer-hash-test"));
+    }
+}
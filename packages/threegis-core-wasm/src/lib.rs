@@ -12,27 +12,66 @@ use wasm_bindgen::prelude::*;
 pub mod console;
 // Import our elevation processing module
 mod elevation;
+// Import reuse of cached elevation samples across overlapping bboxes
+mod elevation_reuse;
 // Import our GPU acceleration modules
+mod gpu_dispatch;
 mod gpu_elevation;
+mod gpu_hillshade;
+mod gpu_manager;
 mod gpu_polygon;
+mod gpu_profiler;
 mod gpu_terrain;
 // Import our module state management
 mod module_state;
 // Import our models
 mod models;
 // Import our cache manager
+mod cache_budget;
 mod cache_keys;
 mod cache_manager;
 // Import our terrain geometry generation module
 mod terrain;
 // Import our new mesh-based terrain generation module
 mod terrain_mesh_gen;
+// Import terrain texture coordinates and raster tile atlases
+mod terrain_uv;
+// Import stitched and resampled raster tile mosaics
+mod raster_mosaic;
 // Import our vector tile processing module
 mod vectortile;
+// Import MapLibre expression filters for vector tile layers
+mod filter_expression;
 // Import our geojson features module
 pub mod geojson_features;
 // Import our polygon geometry module
 mod polygon_geometry;
+// Import geographic, Web Mercator and mesh coordinate transforms
+mod projection;
+// Import real-world model scales with mesh units in millimeters
+mod model_scale;
+// Import custom clip shapes for the terrain, base and layers
+mod clip_shape;
+// Import polygon offsetting (grow / shrink with holes)
+mod polygon_buffer;
+// Import the 2D footprint union run before extrusion
+mod footprint_union;
+// Import road network snapping and joining for transportation layers
+mod road_network;
+// Import text label geometry for feature names
+mod labels;
+// Import pitched roof shapes for tagged buildings
+mod roof;
+// Import the triangle mesh builder for generated solids
+mod solid_mesh;
+// Import bridge and tunnel handling for transportation layers
+mod brunnel;
+// Import flat water surfaces recessed into the terrain
+mod water;
+// Import terrain-following shells for draped layers
+mod drape;
+// Import the shared elevation/height → mesh Z mapping
+mod vertical_datum;
 // Import our bbox filter module
 mod bbox_filter;
 // Import our geometry functions
@@ -44,6 +83,94 @@ mod csg_union;
 mod cancellation;
 // Import 3MF export functionality
 mod export_3mf;
+// Import STL export functionality
+mod export_stl;
+// Import glTF (GLB) export functionality
+mod export_gltf;
+// Import OBJ + MTL export functionality
+mod export_obj;
+// Import post-export structural validation
+mod export_validation;
+// Import packed binary geometry results
+mod packed_geometry;
+// Import mesh comparison utilities
+mod mesh_diff;
+// Import mesh size and complexity metrics
+mod mesh_metrics;
+// Import manifold / watertightness validation
+mod mesh_manifold;
+// Import the mesh repair module
+mod mesh_repair;
+// Import quadric error metric mesh decimation
+mod mesh_simplify;
+// Import print material and time estimation
+mod print_estimate;
+// Import print orientation suggestion
+mod print_orientation;
+// Import feature picking against generated geometry
+mod picking;
+// Import bounding volumes of generated output
+mod bounds;
+// Import the finished per-layer geometry cache
+mod layer_cache;
+// Import vertical exaggeration rescaling of existing results
+mod exaggeration;
+// Import 16-bit heightmap PNG export
+mod heightmap;
+// Import hillshade, slope and aspect images of the elevation grid
+mod hillshade;
+// Import ASCII Grid / GeoTIFF DEM export
+mod dem_export;
+// Import GeoTIFF DEM loading
+mod dem_import;
+// Import MBTiles archive loading
+mod mbtiles;
+// Import direct Overpass API ingestion
+mod overpass;
+// Import background tile prefetching
+mod prefetch;
+// Import offline mode and cache injection
+mod offline;
+// Import the pluggable fetch transport
+mod fetch_hook;
+// Import per-host request rate limiting
+mod rate_limit;
+// Import TileJSON source metadata
+mod tilejson;
+// Import attribution and data provenance tracking
+mod provenance;
+// Import the spatial index for terrain mesh sampling
+mod terrain_index;
+// Import the terrain-only generation fast path
+mod terrain_only;
+// Import the 2.5D flat map layer levels
+mod flat_map;
+// Import terrain engraving of negative-extrusion layers
+mod engraving;
+// Import cross-section slicing of the finished model
+mod cross_section;
+// Import horizontal clipping-plane previews
+mod clip_preview;
+// Import adaptive chunking for long generation loops
+mod chunking;
+// Import automatic zoom level selection
+mod zoom_select;
+// Import the model manifest of generated layers
+mod manifest;
+// Import conditional revalidation of cached tiles
+mod tile_revalidation;
+// Import full module reset
+mod reset;
+// Import streaming ZIP writer used by archive exports
+mod zip_writer;
+// Import the optional worker thread pool
+mod parallel;
+// Import the job queue for hosts running the module in one worker
+mod jobs;
+// Import the single-call scene pipeline
+mod scene;
+// Import the reproducible output mode
+mod reproducible;
 mod repro_test;
 
 use models::{CacheStats, RustResponse};
@@ -58,12 +185,21 @@ extern "C" {
     // JavaScript function to fetch data from URL
     #[wasm_bindgen(js_namespace = wasmJsHelpers, catch)]
     pub fn fetch(url: &str) -> Result<js_sys::Promise, JsValue>;
+
+    // Same helper with request options, e.g. conditional request headers
+    #[wasm_bindgen(js_namespace = wasmJsHelpers, js_name = fetch, catch)]
+    pub fn fetch_with_options(url: &str, options: &JsValue) -> Result<js_sys::Promise, JsValue>;
 }
 
-// Use the macro from our console module
+// Record an info message in the "general" log category
 #[macro_export]
 macro_rules! console_log {
-    ($($t:tt)*) => (crate::console::log(&format!($($t)*)))
+    ($($t:tt)*) => ($crate::console::record(
+        $crate::console::LogLevel::Info,
+        "general",
+        None,
+        format!($($t)*),
+    ))
 }
 
 use std::sync::Once;
@@ -135,6 +271,7 @@ pub fn get_cache_stats() -> Result<JsValue, JsValue> {
         max_vector,
         total_requests,
         cache_hits,
+        (memory_bytes, memory_limit_bytes, evictions),
     ) = ModuleState::with(|state| {
         let (raster_count, vector_count, elevation_count, max_raster, max_vector, total_requests) =
             state.get_stats();
@@ -146,6 +283,11 @@ pub fn get_cache_stats() -> Result<JsValue, JsValue> {
             max_vector,
             total_requests,
             state.cache_hits,
+            (
+                state.cache_usage.total_bytes(),
+                state.cache_usage.limit_bytes,
+                state.cache_usage.evictions,
+            ),
         )
     });
 
@@ -163,6 +305,9 @@ pub fn get_cache_stats() -> Result<JsValue, JsValue> {
         max_vector_tiles: max_vector,
         total_requests,
         hit_rate,
+        memory_bytes,
+        memory_limit_bytes,
+        evictions,
     };
 
     Ok(to_value(&stats)?)
@@ -208,6 +353,29 @@ pub fn clear_process_cache_js(process_id: &str) -> bool {
     true
 }
 
+/// Store the rendered terrain mesh of a process once; its layers sample it when their
+/// input carries no terrainVertices. A different mesh drops the cached layer geometry
+/// of the process, which was aligned to the old one.
+#[wasm_bindgen]
+pub fn store_terrain_mesh(process_id: &str, vertices: Vec<f32>, indices: Vec<u32>) {
+    ModuleState::with_mut(|state| {
+        let (added, replaced) = match state.terrain_meshes.get(process_id) {
+            Some(mesh) => (false, mesh.vertices != vertices),
+            None => (true, false),
+        };
+        if replaced {
+            state.layer_geometries.remove(process_id);
+        }
+        if added || replaced {
+            state.bump_terrain_generation(process_id);
+        }
+        state.terrain_meshes.insert(
+            process_id.to_string(),
+            module_state::TerrainMesh { vertices, indices },
+        );
+    });
+}
+
 /// Get list of cached process IDs
 #[wasm_bindgen]
 pub fn get_cached_process_ids_js() -> JsValue {
@@ -242,10 +410,137 @@ pub use vectortile::fetch_vector_tiles;
 pub use gpu_elevation::{init_gpu_elevation_processor};
 pub use gpu_polygon::{init_gpu_polygon_processor, buffer_linestring_gpu, clip_polygons_gpu};
 pub use gpu_terrain::{init_gpu_terrain_processor, generate_terrain_mesh_gpu};
-pub use elevation::check_gpu_support;
+pub use gpu_hillshade::init_gpu_hillshade_processor;
+pub use elevation::{check_gpu_support, query_elevation, query_elevation_batch};
+
+// Re-export GPU status
+pub use gpu_manager::get_gpu_status;
+
+// Re-export GPU pass profiling
+pub use gpu_profiler::{clear_gpu_profile, get_gpu_profile_report, set_gpu_profiling};
 
 // Re-export 3MF export functions
-pub use export_3mf::{generate_3mf_model_xml, generate_3mf_content_types_xml, generate_3mf_rels_xml};
+pub use export_3mf::{
+    generate_3mf_archive, generate_3mf_content_types_xml, generate_3mf_model_xml, Export3MFSession,
+    generate_3mf_rels_xml, stream_3mf_archive,
+};
+
+// Re-export STL export
+pub use export_stl::export_stl;
+
+// Re-export glTF export
+pub use export_gltf::export_gltf;
+
+// Re-export OBJ export
+pub use export_obj::export_obj;
+
+// Re-export export validation
+pub use export_validation::validate_export;
+
+// Re-export mesh diff
+pub use mesh_diff::diff_meshes;
+
+// Re-export mesh metrics and manifold validation
+pub use mesh_metrics::compute_mesh_metrics;
+pub use mesh_manifold::validate_mesh_manifold;
+pub use mesh_repair::repair_geometry;
+pub use mesh_simplify::simplify_geometry;
+pub use print_estimate::estimate_print_material;
+pub use print_orientation::suggest_print_orientation;
+
+// Re-export feature picking
+pub use picking::pick_feature;
+
+// Re-export bounding volume queries
+pub use bounds::get_model_bounds;
+
+// Re-export cached layer geometry access
+pub use layer_cache::{get_cached_layers, get_layer_geometry, get_layer_geometry_packed};
+
+// Re-export tile prefetching and revalidation
+pub use prefetch::prefetch_tiles;
+pub use tile_revalidation::revalidate_tiles;
+
+// Re-export offline mode and cache injection
+pub use offline::{
+    inject_elevation_grid, inject_raster_tile, inject_vector_tile, is_offline_mode,
+    set_offline_mode,
+};
+
+// Re-export fetch handler registration and rate limits
+pub use fetch_hook::{has_fetch_handler, register_fetch_handler, unregister_fetch_handler};
+pub use rate_limit::{clear_host_rate_limit, set_host_rate_limit};
+
+// Re-export TileJSON source configuration
+pub use tilejson::{
+    clamp_source_zoom, clear_tilejson, list_vector_tile_sources, load_tilejson,
+    register_vector_tile_source, remove_vector_tile_source, set_tilejson, set_vector_tile_source,
+};
+
+// Re-export provenance lookup
+pub use provenance::get_provenance;
+
+// Re-export terrain-only model generation
+pub use terrain_only::generate_terrain_model;
+
+// Re-export flat map level planning
+pub use flat_map::flat_map_layer_levels;
+
+// Re-export terrain engraving
+pub use engraving::engrave_terrain;
+
+// Re-export cross-section slicing
+pub use cross_section::slice_model;
+
+// Re-export clipping-plane previews
+pub use clip_preview::preview_clipping_plane;
+
+// Re-export automatic zoom selection
+pub use zoom_select::select_zoom_levels;
+
+// Re-export the model manifest
+pub use manifest::get_model_manifest;
+
+// Re-export structured log access
+pub use console::{clear_log_records, get_log_records};
+
+// Re-export bbox pre-filtering
+pub use bbox_filter::filter_geometries_by_bbox;
+
+// Re-export cache key precision control
+pub use cache_keys::set_cache_key_precision;
+
+// Re-export full module reset
+pub use reset::reset_module;
+
+// Re-export heightmap export
+pub use heightmap::export_heightmap_png;
+// Re-export terrain shading images
+pub use hillshade::compute_hillshade;
+// Re-export terrain texturing helpers
+pub use terrain_uv::{build_raster_atlas, generate_terrain_uvs};
+// Re-export raster tile mosaics
+pub use raster_mosaic::mosaic_raster_tiles;
+// Re-export coordinate transforms and per-process terrain size
+pub use projection::{get_terrain_size, set_terrain_size, transform_coordinates};
+// Re-export real-world model scale configuration
+pub use model_scale::set_model_scale;
+// Re-export clip shape configuration
+pub use clip_shape::set_clip_shape;
+
+// Re-export DEM export
+pub use dem_export::export_elevation_grid;
+// Re-export GeoTIFF DEM loading
+pub use dem_import::load_dem_geotiff;
+// Re-export MBTiles archive loading
+pub use mbtiles::{load_mbtiles, unload_mbtiles};
+// Re-export GeoJSON feature import
+pub use geojson_features::import_geojson_features;
+// Re-export Overpass API ingestion
+pub use overpass::fetch_overpass_features;
+
+// Re-export vertical exaggeration rescaling
+pub use exaggeration::{rescale_layers_exaggeration, rescale_terrain_exaggeration};
 
 // Example of a simple function that will be exposed to JavaScript
 #[wasm_bindgen]
@@ -254,20 +549,35 @@ pub fn add(a: i32, b: i32) -> i32 {
 }
 
 // Buffer a LineString by a distance (in coordinate units) with parallel processing
-// Returns a serialized array of polygon coordinates.
+// Returns a serialized array of polygon coordinates. `style` is an optional JSON object
+// `{ lineJoin: "miter" | "bevel" | "round", lineCap: "butt" | "square" | "round",
+// miterLimit }`; without it the line is offset with CavalierContours as before.
 #[wasm_bindgen]
-pub fn buffer_line_string_direct(coordinates: &[f64], dist: f64) -> String {
+pub fn buffer_line_string_direct(coordinates: &[f64], dist: f64, style: Option<String>) -> String {
     // Defensive: non-positive or NaN distances return empty geometry
     if !dist.is_finite() || dist == 0.0 || coordinates.len() < 4 {
         return "[]".to_string();
     }
     let dist = dist.abs();
 
-    // Convert flat coordinate array to CavalierContours Polyline
-    if coordinates.len() % 2 != 0 {
+    if !coordinates.len().is_multiple_of(2) {
         return "[]".to_string();
     }
 
+    if let Some(style) = style {
+        let style: polygon_geometry::LineStyle = match serde_json::from_str(&style) {
+            Ok(style) => style,
+            Err(_) => return "[]".to_string(),
+        };
+        let line: Vec<Vec<f64>> = coordinates.chunks_exact(2).map(|c| c.to_vec()).collect();
+        let ring = polygon_geometry::buffer_polyline(&line, dist, &style);
+        if ring.is_empty() {
+            return "[]".to_string();
+        }
+        return serde_json::to_string(&vec![ring]).unwrap_or_else(|_| "[]".to_string());
+    }
+
+    // Convert flat coordinate array to CavalierContours Polyline
     let mut ply = Polyline::new();
     for chunk in coordinates.chunks_exact(2) {
         ply.add_vertex(PlineVertex::new(chunk[0], chunk[1], 0.0));
@@ -314,6 +624,19 @@ pub fn buffer_line_string_direct(coordinates: &[f64], dist: f64) -> String {
     serde_json::to_string(&result).unwrap_or_else(|_| "[]".to_string())
 }
 
+// Grow (positive `dist`) or shrink (negative `dist`) a polygon given as JSON rings
+// `[[[x, y], ...], ...]`, the exterior followed by its holes, in coordinate units.
+// Returns MultiPolygon coordinates, as shrinking may split a polygon or remove it.
+#[wasm_bindgen]
+pub fn buffer_polygon(coords: &str, dist: f64) -> String {
+    let rings: Vec<Vec<Vec<f64>>> = match serde_json::from_str(coords) {
+        Ok(rings) => rings,
+        Err(_) => return "[]".to_string(),
+    };
+    let polygons = polygon_buffer::offset_polygon(&rings, dist);
+    serde_json::to_string(&polygons).unwrap_or_else(|_| "[]".to_string())
+}
+
 // Legacy buffer function for backward compatibility - uses direct coordinate processing
 #[wasm_bindgen]
 pub fn buffer_line_string(geojson_str: &str, dist: f64) -> String {
@@ -339,7 +662,7 @@ pub fn buffer_line_string(geojson_str: &str, dist: f64) -> String {
                         }
 
                         // Call the optimized direct function
-                        let result = buffer_line_string_direct(&flat_coords, dist);
+                        let result = buffer_line_string_direct(&flat_coords, dist, None);
 
                         // Convert back to GeoJSON format for backward compatibility
                         match serde_json::from_str::<Vec<Vec<Vec<f64>>>>(&result) {
@@ -434,6 +757,25 @@ pub fn free_cache_group(group_id: &str) -> Result<(), JsValue> {
 // Export CSG union functionality with parallel processing
 #[wasm_bindgen]
 pub fn merge_geometries_with_csg_union(geometries_json: &str) -> Result<JsValue, JsValue> {
+    let result = merge_geometries_json(geometries_json)?;
+
+    // Serialize result
+    let json = serde_json::to_string(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e)))?;
+
+    Ok(JsValue::from_str(&json))
+}
+
+// merge_geometries_with_csg_union returning the packed binary layout (see packed_geometry)
+// as a Uint8Array instead of a JSON string
+#[wasm_bindgen]
+pub fn merge_geometries_with_csg_union_packed(geometries_json: &str) -> Result<JsValue, JsValue> {
+    Ok(packed_geometry::packed_to_js(&merge_geometries_json(geometries_json)?))
+}
+
+fn merge_geometries_json(
+    geometries_json: &str,
+) -> Result<Vec<crate::polygon_geometry::BufferGeometry>, JsValue> {
     // Parse input geometries
     let geometries: Vec<crate::polygon_geometry::BufferGeometry> =
         serde_json::from_str(geometries_json)
@@ -443,18 +785,35 @@ pub fn merge_geometries_with_csg_union(geometries_json: &str) -> Result<JsValue,
     let merged_by_layer = csg_union::merge_geometries_by_layer(geometries);
 
     // Convert to output format with sequential optimization
-    let result: Vec<crate::polygon_geometry::BufferGeometry> = merged_by_layer
-        .into_iter()
-        .map(|(_layer_name, geometry)| {
+    let result: Vec<crate::polygon_geometry::BufferGeometry> = merged_by_layer.into_values().map(|geometry| {
             csg_union::optimize_geometry(geometry, 0.01) // 1cm tolerance
         })
         .collect();
 
-    // Serialize result
-    let json = serde_json::to_string(&result)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e)))?;
+    Ok(result)
+}
 
-    Ok(JsValue::from_str(&json))
+#[derive(serde::Deserialize)]
+struct MeshBooleanInput {
+    op: String,
+    target: crate::polygon_geometry::BufferGeometry,
+    #[serde(default)]
+    tools: Vec<crate::polygon_geometry::BufferGeometry>,
+}
+
+/// 3D boolean between closed meshes: `input_json` is `{ op, target, tools }` with `op`
+/// "union", "subtract" or "intersect" and geometries shaped like
+/// `process_polygon_geometry` output. The target is combined with the union of the tools;
+/// returns an array with the resulting geometry, empty when nothing remains.
+#[wasm_bindgen]
+pub fn mesh_boolean(input_json: &str) -> Result<JsValue, JsValue> {
+    let input: MeshBooleanInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let op = csg_union::BooleanOp::parse(&input.op).map_err(|e| JsValue::from_str(&e))?;
+    let result: Vec<_> = csg_union::boolean_geometries(&input.target, &input.tools, op)
+        .into_iter()
+        .collect();
+    Ok(geometries_to_js(&result))
 }
 
 // Batch buffer multiple LineStrings in parallel for optimal performance
@@ -490,7 +849,7 @@ pub fn buffer_line_strings_batch(geojson_features_json: &str, dist: f64) -> Stri
                         }
 
                         if flat_coords.len() >= 4 {
-                            Some(buffer_line_string_direct(&flat_coords, dist))
+                            Some(buffer_line_string_direct(&flat_coords, dist, None))
                         } else {
                             None
                         }
@@ -513,11 +872,20 @@ pub fn buffer_line_strings_batch(geojson_features_json: &str, dist: f64) -> Stri
 // Get information about WASM module capabilities including GPU support
 #[wasm_bindgen]
 pub fn get_wasm_info() -> String {
+    let threads = parallel::threads_enabled();
     serde_json::to_string(&serde_json::json!({
-        "parallel_processing": false,
+        "parallel_processing": threads,
+        "threads_feature": cfg!(feature = "threads"),
+        "thread_count": parallel::thread_count(),
         "gpu_acceleration": true,
         "webgpu_support": "Available via init_gpu_elevation_processor()",
-        "reason": "Sequential CPU processing with GPU acceleration for elevation data",
+        "reason": if threads {
+            "Polygon chunks, CSG unions and elevation grids run on the worker thread pool"
+        } else if cfg!(feature = "threads") {
+            "Sequential CPU processing until init_thread_pool() has resolved"
+        } else {
+            "Sequential CPU processing with GPU acceleration for elevation data"
+        },
         "performance_optimizations": [
             "GPU-accelerated elevation processing",
             "WebGPU compute shaders for parallel interpolation",
@@ -536,12 +904,14 @@ pub async fn init_all_gpu_processors() -> String {
     let elevation_gpu = init_gpu_elevation_processor().await.unwrap_or(false);
     let polygon_gpu = init_gpu_polygon_processor().await.unwrap_or(false);
     let terrain_gpu = init_gpu_terrain_processor().await.unwrap_or(false);
+    let hillshade_gpu = init_gpu_hillshade_processor().await.unwrap_or(false);
 
     serde_json::to_string(&serde_json::json!({
         "elevation_processing": elevation_gpu,
         "polygon_processing": polygon_gpu,
         "terrain_generation": terrain_gpu,
-        "overall_gpu_support": elevation_gpu || polygon_gpu || terrain_gpu
+        "terrain_shading": hillshade_gpu,
+        "overall_gpu_support": elevation_gpu || polygon_gpu || terrain_gpu || hillshade_gpu
     }))
     .unwrap_or_else(|_| "{}".to_string())
 }
@@ -587,80 +957,234 @@ pub fn test_initialization() -> String {
     "Initialization test passed - no panics occurred".to_string()
 }
 
-// Export the polygon geometry creation function with cached feature retrieval
+// Export the polygon geometry creation function with cached feature retrieval.
+// Takes the input as a plain object; a JSON string is still accepted. In an object,
+// `elevationGrid` may be a flat row-major Float32Array sized by `gridSize`, and the
+// terrain mesh is passed as `terrainVertices` / `terrainIndices` typed arrays.
+#[wasm_bindgen]
+pub async fn process_polygon_geometry(input: JsValue) -> Result<JsValue, JsValue> {
+    build_polygon_geometry(input, None, geometries_to_js).await
+}
+
+// Like process_polygon_geometry, but returns all geometries packed into one Uint8Array
+// (see packed_geometry) whose buffer can be transferred and viewed as typed arrays.
 #[wasm_bindgen]
-pub fn process_polygon_geometry(input_json: &str) -> Result<JsValue, JsValue> {
-    // Parse input JSON to extract bbox and vtDataSet
-    let mut input_val: serde_json::Value = serde_json::from_str(input_json)
-        .map_err(|e| JsValue::from_str(&format!("Invalid input JSON: {}", e)))?;
-    // Extract bbox coordinates
-    let bbox = input_val
-        .get("bbox")
-        .and_then(|v| v.as_array())
-        .ok_or_else(|| JsValue::from_str("Missing or invalid 'bbox' field"))?;
-    if bbox.len() != 4 {
+pub async fn process_polygon_geometry_packed(input: JsValue) -> Result<JsValue, JsValue> {
+    build_polygon_geometry(input, None, packed_geometry::packed_to_js).await
+}
+
+// Like process_polygon_geometry, but calls `on_chunk(geometries, progress)` with the
+// geometry of every processed chunk of features and the fraction of features done, so
+// the UI can render incrementally. Chunk geometry is not merged or clipped yet; the
+// returned layer geometry replaces it. Cached layers are reported as one chunk.
+#[wasm_bindgen]
+pub async fn process_polygon_geometry_streaming(
+    input: JsValue,
+    on_chunk: js_sys::Function,
+) -> Result<JsValue, JsValue> {
+    build_polygon_geometry(input, Some(&on_chunk), geometries_to_js).await
+}
+
+fn report_chunk(on_chunk: &js_sys::Function, geometries: JsValue, progress: f64) -> Result<(), JsValue> {
+    on_chunk
+        .call2(&JsValue::NULL, &geometries, &JsValue::from_f64(progress))
+        .map(|_| ())
+}
+
+// Typed arrays of a layer input object: `elevationGrid` (Float32Array), `terrainVertices`
+// (Float32Array) and `terrainIndices` (Uint32Array)
+#[derive(Default)]
+struct TypedArrayInputs {
+    elevation_grid: Option<Vec<f32>>,
+    terrain_vertices: Option<Vec<f32>>,
+    terrain_indices: Option<Vec<u32>>,
+}
+
+fn take_typed_array<T: JsCast>(object: &js_sys::Object, name: &str) -> Result<Option<T>, JsValue> {
+    let value = js_sys::Reflect::get(object, &name.into())?;
+    if !value.is_instance_of::<T>() {
+        return Ok(None);
+    }
+    js_sys::Reflect::delete_property(object, &name.into())?;
+    Ok(Some(value.unchecked_into()))
+}
+
+// Take the typed arrays out of an input object, so they are copied in one go instead of
+// being deserialized value by value. The caller's object is left untouched.
+fn split_typed_arrays(input: JsValue) -> Result<(JsValue, TypedArrayInputs), JsValue> {
+    if !input.is_object() {
+        return Ok((input, TypedArrayInputs::default()));
+    }
+    let copy = js_sys::Object::assign(&js_sys::Object::new(), input.unchecked_ref());
+    let typed = TypedArrayInputs {
+        elevation_grid: take_typed_array::<Float32Array>(&copy, "elevationGrid")?.map(|a| a.to_vec()),
+        terrain_vertices: take_typed_array::<Float32Array>(&copy, "terrainVertices")?.map(|a| a.to_vec()),
+        terrain_indices: take_typed_array::<js_sys::Uint32Array>(&copy, "terrainIndices")?.map(|a| a.to_vec()),
+    };
+    Ok((copy.into(), typed))
+}
+
+async fn build_polygon_geometry(
+    input: JsValue,
+    on_chunk: Option<&js_sys::Function>,
+    encode: fn(&[polygon_geometry::BufferGeometry]) -> JsValue,
+) -> Result<JsValue, JsValue> {
+    let input = parse_layer_input(input)?;
+    build_layer_geometry(input, on_chunk, encode).await
+}
+
+// Parse a layer input given as a JSON string or an object, typed arrays included
+pub(crate) fn parse_layer_input(
+    input: JsValue,
+) -> Result<polygon_geometry::PolygonGeometryInput, JsValue> {
+    let (input, typed) = split_typed_arrays(input)?;
+    let mut input: polygon_geometry::PolygonGeometryInput = match input.as_string() {
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid input JSON: {}", e)))?,
+        None => serde_wasm_bindgen::from_value(input)
+            .map_err(|e| JsValue::from_str(&format!("Invalid input: {}", e)))?,
+    };
+    if let Some(values) = typed.elevation_grid {
+        input
+            .set_flat_elevation_grid(&values)
+            .map_err(|e| JsValue::from_str(&e))?;
+    }
+    if let Some(vertices) = typed.terrain_vertices {
+        input.terrain_vertices = vertices;
+    }
+    if let Some(indices) = typed.terrain_indices {
+        input.terrain_indices = indices;
+    }
+    Ok(input)
+}
+
+// Build the geometry of one parsed layer input; shared with `generate_scene`
+async fn build_layer_geometry(
+    mut input: polygon_geometry::PolygonGeometryInput,
+    on_chunk: Option<&js_sys::Function>,
+    encode: fn(&[polygon_geometry::BufferGeometry]) -> JsValue,
+) -> Result<JsValue, JsValue> {
+    if input.bbox.len() != 4 {
         return Err(JsValue::from_str(
             "Invalid 'bbox': must contain [minLng, minLat, maxLng, maxLat]",
         ));
     }
-    let _min_lng = bbox[0].as_f64().unwrap_or(0.0);
-    let _min_lat = bbox[1].as_f64().unwrap_or(0.0);
-    let _max_lng = bbox[2].as_f64().unwrap_or(0.0);
-    let _max_lat = bbox[3].as_f64().unwrap_or(0.0);
-    // Extract process_id from input
-    let process_id = input_val
-        .get("processId")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| JsValue::from_str("Missing 'processId' field"))?
-        .to_string();
-    // Determine source layer from vtDataSet
-    let source_layer = input_val
-        .get("vtDataSet")
-        .and_then(|v| v.get("sourceLayer"))
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| JsValue::from_str("Missing 'vtDataSet.sourceLayer' field"))?;
-
-    // Assemble inner cache key using central function (no filter currently)
-    let inner_key = make_inner_key_from_filter(
-        source_layer,
-        input_val.get("vtDataSet").and_then(|v| v.get("filter")),
+    if input.vt_data_set.source_layer.is_empty() {
+        return Err(JsValue::from_str("Missing 'vtDataSet.sourceLayer' field"));
+    }
+    let process_id = input.process_id.clone();
+    let layer_label = input.vt_data_set.get_label().to_string();
+
+    // Finished geometry for an identical config is served from the layer cache
+    let config_hash = layer_cache::layer_config_hash(&input);
+    let cached = layer_cache::with_cached_layer(
+        &process_id,
+        &layer_label,
+        &config_hash,
+        encode,
     );
+    if let Some(result) = cached {
+        if let Some(on_chunk) = on_chunk {
+            report_chunk(on_chunk, result.clone(), 1.0)?;
+        }
+        return Ok(result);
+    }
 
-    // Retrieve features from process-based cache
-    let process_data_key = cache_keys::make_process_cache_key(&process_id, &inner_key);
-    let features: Vec<crate::polygon_geometry::GeometryData> = ModuleState::with(|state| {
-        if let Some(js_val) = state.get_process_feature_data(&process_id, &process_data_key) {
-            let json_str = js_val.as_string().unwrap_or_else(|| "[]".to_string());
-            serde_json::from_str::<Vec<crate::polygon_geometry::GeometryData>>(&json_str)
-                .unwrap_or_default()
-        } else {
-            Vec::new()
+    input.polygons = cached_layer_features(&input);
+    if input.reproducible {
+        reproducible::sort_features(&mut input.polygons);
+    }
+
+    let feature_count = input.polygons.len();
+    let vt_data_set = input.vt_data_set.clone();
+    let timer = console::LogTimer::start("layer", Some(&process_id));
+    let scale = manifest::ScaleInfo::from_input(&input);
+
+    // Build the layer geometry with cached features applied; malformed features are
+    // skipped and reported in the model manifest
+    let reproducible = input.reproducible;
+    let polygon_geometry::PolygonGeometryOutput {
+        mut geometries,
+        skipped,
+    } = match on_chunk {
+        Some(on_chunk) => {
+            let mut observer = |batch: &[polygon_geometry::BufferGeometry], processed: usize, total: usize| {
+                report_chunk(on_chunk, encode(batch), processed as f64 / total as f64)
+                    .map_err(|e| format!("Chunk callback failed: {:?}", e))
+            };
+            polygon_geometry::generate_polygon_geometry_streaming(input, Some(&mut observer)).await
         }
-    });
+        None => polygon_geometry::generate_polygon_geometry(input).await,
+    }
+    .map_err(|e| cancellation::job_error(&e))?;
+    if let (Some(on_chunk), 0) = (on_chunk, feature_count) {
+        report_chunk(on_chunk, js_sys::Array::new().into(), 1.0)?;
+    }
+    if reproducible {
+        reproducible::stamp_geometries(&mut geometries);
+    }
+
+    timer.finish(
+        if skipped.is_empty() { console::LogLevel::Info } else { console::LogLevel::Warn },
+        format!(
+            "Generated layer '{}': {} geometries from {} features, {} skipped",
+            layer_label,
+            geometries.len(),
+            feature_count,
+            skipped.len()
+        ),
+    );
 
-    // Insert retrieved features into 'polygons' field
-    let features_value: serde_json::Value = serde_json::to_value(&features)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize features: {}", e)))?;
-    input_val["polygons"] = features_value;
-    // Ensure processId is in input
-    input_val["processId"] = serde_json::Value::String(process_id.clone());
+    // Index the generated features for picking and record their bounds
+    picking::register_layer_geometries(&process_id, &layer_label, &geometries);
+    bounds::register_layer_bounds(&process_id, &layer_label, &geometries);
+
+    let result = encode(&geometries);
+    manifest::record_layer(
+        &process_id,
+        scale,
+        &vt_data_set,
+        feature_count,
+        &geometries,
+        skipped,
+    );
+    layer_cache::store_layer_geometry(
+        &process_id,
+        &layer_label,
+        config_hash,
+        vt_data_set.is_terrain_aligned(),
+        geometries,
+    );
 
-    // Serialize modified input for geometry creation
-    let new_input = serde_json::to_string(&input_val)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize input: {}", e)))?;
+    Ok(result)
+}
 
-    // Call create_polygon_geometry with cached features applied
-    let json_string = polygon_geometry::create_polygon_geometry(&new_input)
-        .map_err(|e| JsValue::from_str(&e))?;
+// Features of a layer extracted for its process, looked up with the layer's filter
+pub(crate) fn cached_layer_features(
+    input: &polygon_geometry::PolygonGeometryInput,
+) -> Vec<polygon_geometry::GeometryData> {
+    // Assemble inner cache key using central function
+    let inner_key = make_inner_key_from_filter(
+        &input.vt_data_set.source_layer,
+        input.vt_data_set.filter.as_ref(),
+        input.vt_data_set.source.as_deref(),
+    );
 
-    // Parse the JSON output back to Vec<BufferGeometry> in Rust (fast)
-    let geometries: Vec<polygon_geometry::BufferGeometry> = serde_json::from_str(&json_string)
-        .map_err(|e| JsValue::from_str(&format!("Failed to parse geometry output: {}", e)))?;
+    // Retrieve features from process-based cache
+    let process_data_key = cache_keys::make_process_cache_key(&input.process_id, &inner_key);
+    ModuleState::with(|state| {
+        state
+            .get_process_feature_data(&input.process_id, &process_data_key)
+            .and_then(|js_val| js_val.as_string())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    })
+}
 
-    // Build the JS result using TypedArrays directly
+// Build the JS result for a layer using TypedArrays directly
+pub(crate) fn geometries_to_js(geometries: &[polygon_geometry::BufferGeometry]) -> JsValue {
     let result_array = js_sys::Array::new_with_length(geometries.len() as u32);
 
-
     for (i, geom) in geometries.iter().enumerate() {
         let obj = js_sys::Object::new();
 
@@ -692,6 +1216,17 @@ pub fn process_polygon_geometry(input_json: &str) -> Result<JsValue, JsValue> {
             js_sys::Reflect::set(&obj, &"indices".into(), &JsValue::null()).unwrap();
         }
 
+        // boundingVolume → { min, max, center, radius } or null
+        let volume = bounds::BoundingVolume::from_positions(&geom.vertices);
+        match volume.map(|v| serde_wasm_bindgen::to_value(&v)) {
+            Some(Ok(volume_js)) => {
+                js_sys::Reflect::set(&obj, &"boundingVolume".into(), &volume_js).unwrap();
+            }
+            _ => {
+                js_sys::Reflect::set(&obj, &"boundingVolume".into(), &JsValue::null()).unwrap();
+            }
+        }
+
         // hasData → boolean
         js_sys::Reflect::set(&obj, &"hasData".into(), &JsValue::from_bool(geom.has_data)).unwrap();
 
@@ -711,8 +1246,7 @@ pub fn process_polygon_geometry(input_json: &str) -> Result<JsValue, JsValue> {
 
         result_array.set(i as u32, obj.into());
     }
-    // Logging removed to fix compilation error (undefined start times)
 
-    Ok(result_array.into())
+    result_array.into()
 }
 
@@ -0,0 +1,211 @@
+// Machine-readable model manifest: what each generated layer looks like (color, label,
+// heights, feature counts, filter) and the model scale, recorded as layers are generated
+// so legends and layer panels don't have to re-parse the layer configs.
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::{BufferGeometry, PolygonGeometryInput, SkippedFeature, VtDataSet};
+use crate::projection;
+use crate::vertical_datum::meters_to_terrain_units;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LayerManifest {
+    pub label: String,
+    #[serde(rename = "sourceLayer")]
+    pub source_layer: String,
+    pub color: String,
+    /// Configured extrusion depth in meters; negative for engraved layers
+    #[serde(rename = "extrusionDepth")]
+    pub extrusion_depth: Option<f64>,
+    #[serde(rename = "zOffset")]
+    pub z_offset: Option<f64>,
+    pub filter: Option<serde_json::Value>,
+    #[serde(rename = "terrainAligned")]
+    pub terrain_aligned: bool,
+    /// Cutter geometry subtracted from the terrain instead of raised features
+    pub engraved: bool,
+    /// Source features of the layer within the bbox
+    #[serde(rename = "featureCount")]
+    pub feature_count: usize,
+    /// Output geometries after merging
+    #[serde(rename = "geometryCount")]
+    pub geometry_count: usize,
+    /// Z range of the generated geometry in model units
+    #[serde(rename = "minZ")]
+    pub min_z: Option<f32>,
+    #[serde(rename = "maxZ")]
+    pub max_z: Option<f32>,
+    /// Features left out because they could not be built, with the reason
+    #[serde(rename = "skippedFeatures")]
+    pub skipped_features: Vec<SkippedFeature>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScaleInfo {
+    /// [minLng, minLat, maxLng, maxLat]
+    pub bbox: Vec<f64>,
+    /// Edge length of the model in mesh units
+    #[serde(rename = "modelSize")]
+    pub model_size: f64,
+    /// Real-world meters per horizontal mesh unit
+    #[serde(rename = "metersPerUnit")]
+    pub meters_per_unit: f64,
+    #[serde(rename = "verticalExaggeration")]
+    pub vertical_exaggeration: f64,
+    #[serde(rename = "terrainBaseHeight")]
+    pub terrain_base_height: f64,
+    /// Denominator N of the real-world model scale 1:N, when mesh units are millimeters
+    #[serde(default, rename = "modelScale")]
+    pub model_scale: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ModelManifest {
+    pub scale: Option<ScaleInfo>,
+    /// Generated layers sorted by label
+    pub layers: Vec<LayerManifest>,
+}
+
+impl ScaleInfo {
+    /// Scale of a layer generation input; None for an invalid bbox
+    pub fn from_input(input: &PolygonGeometryInput) -> Option<ScaleInfo> {
+        if input.bbox.len() != 4 {
+            return None;
+        }
+        let terrain_scale = projection::process_scale(Some(&input.process_id));
+        Some(ScaleInfo {
+            bbox: input.bbox.clone(),
+            model_size: terrain_scale.size,
+            meters_per_unit: 1.0 / meters_to_terrain_units(&input.bbox),
+            vertical_exaggeration: input.vertical_exaggeration,
+            terrain_base_height: input.terrain_base_height,
+            model_scale: terrain_scale.model_scale,
+        })
+    }
+}
+
+impl LayerManifest {
+    fn new(
+        vt_data_set: &VtDataSet,
+        feature_count: usize,
+        geometries: &[BufferGeometry],
+        skipped_features: Vec<SkippedFeature>,
+    ) -> Self {
+        let z_values: Vec<f32> = geometries
+            .iter()
+            .flat_map(|g| g.vertices.iter().skip(2).step_by(3).copied())
+            .collect();
+        LayerManifest {
+            label: vt_data_set.get_label().to_string(),
+            source_layer: vt_data_set.source_layer.clone(),
+            color: vt_data_set.color.clone(),
+            extrusion_depth: vt_data_set.extrusion_depth,
+            z_offset: vt_data_set.z_offset,
+            filter: vt_data_set.filter.clone(),
+            terrain_aligned: vt_data_set.is_terrain_aligned(),
+            engraved: vt_data_set.is_engraved(),
+            feature_count,
+            geometry_count: geometries.len(),
+            min_z: z_values.iter().copied().reduce(f32::min),
+            max_z: z_values.iter().copied().reduce(f32::max),
+            skipped_features,
+        }
+    }
+}
+
+/// Record a generated layer, and the model scale of its input, in the process manifest
+pub(crate) fn record_layer(
+    process_id: &str,
+    scale: Option<ScaleInfo>,
+    vt_data_set: &VtDataSet,
+    feature_count: usize,
+    geometries: &[BufferGeometry],
+    skipped_features: Vec<SkippedFeature>,
+) {
+    let layer = LayerManifest::new(vt_data_set, feature_count, geometries, skipped_features);
+    ModuleState::with_mut(|state| {
+        let manifest = state
+            .model_manifests
+            .entry(process_id.to_string())
+            .or_default();
+        if scale.is_some() {
+            manifest.scale = scale;
+        }
+        manifest
+            .layers
+            .retain(|existing| existing.label != layer.label);
+        manifest.layers.push(layer);
+        manifest.layers.sort_by(|a, b| a.label.cmp(&b.label));
+    });
+}
+
+/// Manifest of the layers generated for a process as JSON (`{ scale, layers }`)
+#[wasm_bindgen]
+pub fn get_model_manifest(process_id: &str) -> Result<String, JsValue> {
+    let manifest = ModuleState::with(|state| {
+        state
+            .model_manifests
+            .get(process_id)
+            .cloned()
+            .unwrap_or_default()
+    });
+    serde_json::to_string(&manifest)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize manifest: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_records_layers_and_scale() {
+        let input: PolygonGeometryInput = serde_json::from_value(serde_json::json!({
+            "bbox": [13.0, 52.0, 13.1, 52.1],
+            "processId": "manifest-test",
+            "verticalExaggeration": 2.0,
+            "terrainBaseHeight": 3.0,
+            "vtDataSet": {"sourceLayer": "water", "color": "#0000ff", "extrusionDepth": -0.5}
+        }))
+        .unwrap();
+        let scale = ScaleInfo::from_input(&input);
+        let vt_data_set = &input.vt_data_set;
+        let geometry = BufferGeometry {
+            vertices: vec![0.0, 0.0, 1.0, 1.0, 0.0, 4.0, 0.0, 1.0, 2.5],
+            normals: None,
+            colors: None,
+            indices: None,
+            uvs: None,
+            has_data: true,
+            properties: None,
+        };
+        record_layer(
+            "manifest-test",
+            scale.clone(),
+            vt_data_set,
+            7,
+            std::slice::from_ref(&geometry),
+            Vec::new(),
+        );
+        record_layer(
+            "manifest-test",
+            scale,
+            vt_data_set,
+            3,
+            &[geometry],
+            Vec::new(),
+        );
+
+        let manifest = ModuleState::with(|state| state.model_manifests["manifest-test"].clone());
+        assert_eq!(manifest.layers.len(), 1);
+        let water = &manifest.layers[0];
+        assert_eq!(water.feature_count, 3);
+        assert_eq!((water.min_z, water.max_z), (Some(1.0), Some(4.0)));
+        assert!(water.engraved && water.terrain_aligned);
+
+        let scale = manifest.scale.unwrap();
+        assert_eq!(scale.vertical_exaggeration, 2.0);
+        // ~6.8 km wide, 11.1 km tall at 52°N over 200 units
+        assert!((scale.meters_per_unit - 45.0).abs() < 1.0);
+    }
+}
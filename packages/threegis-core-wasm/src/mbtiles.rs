@@ -0,0 +1,865 @@
+// MBTiles archives loaded from user-provided file bytes (e.g. a file dropped into the
+// browser). The SQLite file is read directly: its tiles are indexed once and then served
+// into the tile caches in place of network fetches, so the pipeline can run offline.
+// Vector archives hold (gzipped) MVT tiles; raster archives must hold PNG DEM tiles.
+use flate2::read::ZlibDecoder;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::ops::Range;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::{ModuleState, TileData};
+use crate::prefetch::TileSource;
+use crate::tilejson::{vector_source_key, TileJson};
+use crate::vectortile::TileRequest;
+
+// Deepest zoom level indexed from an archive
+const MAX_TILE_ZOOM: u32 = 24;
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// Largest PNG tile side decoded; tiles are 256 or 512 pixels
+const MAX_PNG_SIDE: usize = 4096;
+
+/// Byte ranges of the file holding one value
+type TileRanges = Vec<Range<usize>>;
+
+/// A loaded archive: the file bytes and where each tile's data lies in them
+pub struct MbTilesArchive {
+    data: Vec<u8>,
+    // Keyed by (z, x, y) in XYZ order; tile data may span several overflow pages
+    tiles: HashMap<(u32, u32, u32), TileRanges>,
+    format: String,
+    name: Option<String>,
+    metadata: TileJson,
+}
+
+impl MbTilesArchive {
+    fn tile(&self, tile: &TileRequest) -> Option<Vec<u8>> {
+        let ranges = self.tiles.get(&(tile.z, tile.x, tile.y))?;
+        Some(
+            ranges
+                .iter()
+                .flat_map(|r| &self.data[r.clone()])
+                .copied()
+                .collect(),
+        )
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct MbTilesSummary {
+    /// Source the archive serves: "raster", "vector" or "vector:<name>"
+    pub source: String,
+    pub format: String,
+    pub name: Option<String>,
+    #[serde(rename = "tileCount")]
+    pub tile_count: usize,
+    pub minzoom: u32,
+    pub maxzoom: u32,
+    pub bounds: Option<[f64; 4]>,
+    pub attribution: Option<String>,
+}
+
+// ---------------------------------------------------------------------------------------
+// SQLite file reading
+// ---------------------------------------------------------------------------------------
+
+/// A column value; blobs stay in the file and are referenced by their payload position
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob { offset: usize, len: usize },
+}
+
+impl Value {
+    fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(v) => Some(*v),
+            Value::Real(v) if v.fract() == 0.0 => Some(*v as i64),
+            Value::Text(text) => text.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_text(&self) -> Option<String> {
+        match self {
+            Value::Text(text) => Some(text.clone()),
+            Value::Integer(v) => Some(v.to_string()),
+            Value::Real(v) => Some(v.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Record bytes of a table row, as ranges of the file
+struct Payload {
+    ranges: Vec<Range<usize>>,
+    size: usize,
+}
+
+impl Payload {
+    /// File ranges of `len` payload bytes starting at `offset`
+    fn slice(&self, mut offset: usize, mut len: usize) -> Result<Vec<Range<usize>>, String> {
+        if offset + len > self.size {
+            return Err("Record value extends past its payload".to_string());
+        }
+        let mut ranges = Vec::new();
+        for range in &self.ranges {
+            if len == 0 {
+                break;
+            }
+            if offset >= range.len() {
+                offset -= range.len();
+                continue;
+            }
+            let start = range.start + offset;
+            let take = (range.len() - offset).min(len);
+            ranges.push(start..start + take);
+            len -= take;
+            offset = 0;
+        }
+        Ok(ranges)
+    }
+}
+
+struct Row {
+    rowid: i64,
+    payload: Payload,
+}
+
+struct Database<'a> {
+    data: &'a [u8],
+    page_size: usize,
+    // Page bytes not reserved for extensions
+    usable: usize,
+}
+
+impl<'a> Database<'a> {
+    fn open(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < 100 || &data[..16] != SQLITE_MAGIC {
+            return Err("Not an SQLite database".to_string());
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        if page_size < 512 || !page_size.is_power_of_two() {
+            return Err(format!("Invalid SQLite page size {}", page_size));
+        }
+        let text_encoding = u32::from_be_bytes([data[56], data[57], data[58], data[59]]);
+        if text_encoding > 1 {
+            return Err("UTF-16 SQLite databases are not supported".to_string());
+        }
+        Ok(Database {
+            data,
+            page_size,
+            usable: page_size - data[20] as usize,
+        })
+    }
+
+    fn bytes(&self, range: Range<usize>) -> Result<&'a [u8], String> {
+        self.data
+            .get(range.clone())
+            .ok_or_else(|| format!("SQLite file truncated at byte {}", range.end))
+    }
+
+    fn u16_at(&self, at: usize) -> Result<usize, String> {
+        let b = self.bytes(at..at + 2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]) as usize)
+    }
+
+    fn u32_at(&self, at: usize) -> Result<u32, String> {
+        let b = self.bytes(at..at + 4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Byte offset of a page (numbered from 1)
+    fn page_start(&self, page: u32) -> Result<usize, String> {
+        let start = (page as usize)
+            .checked_sub(1)
+            .map(|index| index * self.page_size)
+            .filter(|start| start + self.page_size <= self.data.len())
+            .ok_or_else(|| format!("SQLite page {} out of range", page))?;
+        Ok(start)
+    }
+
+    fn varint(&self, at: usize) -> Result<(i64, usize), String> {
+        varint_in(self.data.get(at..).unwrap_or_default())
+    }
+
+    /// All rows of the table b-tree rooted at `root`, in rowid order
+    fn table_rows(&self, root: u32) -> Result<Vec<Row>, String> {
+        let page_count = self.data.len() / self.page_size;
+        let mut rows = Vec::new();
+        let mut stack = vec![root];
+        let mut visited = 0;
+        while let Some(page) = stack.pop() {
+            visited += 1;
+            if visited > page_count {
+                return Err("SQLite table b-tree is corrupt (page loop)".to_string());
+            }
+            let start = self.page_start(page)?;
+            // Page 1 starts with the file header
+            let header = start + if page == 1 { 100 } else { 0 };
+            let kind = self.bytes(header..header + 1)?[0];
+            let cells = self.u16_at(header + 3)?;
+            match kind {
+                0x0d => {
+                    for i in 0..cells {
+                        let cell = start + self.u16_at(header + 8 + i * 2)?;
+                        rows.push(self.leaf_row(cell)?);
+                    }
+                }
+                0x05 => {
+                    stack.push(self.u32_at(header + 8)?);
+                    let mut children = Vec::with_capacity(cells);
+                    for i in 0..cells {
+                        let cell = start + self.u16_at(header + 12 + i * 2)?;
+                        children.push(self.u32_at(cell)?);
+                    }
+                    stack.extend(children.into_iter().rev());
+                }
+                _ => {
+                    return Err(format!(
+                        "Unexpected SQLite page type {} in a table b-tree",
+                        kind
+                    ))
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    /// A table leaf cell, following overflow pages for large records
+    fn leaf_row(&self, cell: usize) -> Result<Row, String> {
+        let (size, size_len) = self.varint(cell)?;
+        let (rowid, rowid_len) = self.varint(cell + size_len)?;
+        let size = usize::try_from(size).map_err(|_| "Invalid SQLite record size")?;
+        let local_start = cell + size_len + rowid_len;
+
+        // Local part of the payload as defined by the file format
+        let max_local = self.usable - 35;
+        let local = if size <= max_local {
+            size
+        } else {
+            let min_local = (self.usable - 12) * 32 / 255 - 23;
+            let local = min_local + (size - min_local) % (self.usable - 4);
+            if local <= max_local {
+                local
+            } else {
+                min_local
+            }
+        };
+        self.bytes(local_start..local_start + local)?;
+        let mut ranges = Vec::new();
+        ranges.push(local_start..local_start + local);
+
+        let mut remaining = size - local;
+        let mut next = if remaining > 0 {
+            self.u32_at(local_start + local)?
+        } else {
+            0
+        };
+        while remaining > 0 {
+            if next == 0 || ranges.len() > self.data.len() / self.page_size {
+                return Err("SQLite overflow chain is corrupt".to_string());
+            }
+            let start = self.page_start(next)?;
+            let take = remaining.min(self.usable - 4);
+            ranges.push(start + 4..start + 4 + take);
+            remaining -= take;
+            next = self.u32_at(start)?;
+        }
+        Ok(Row {
+            rowid,
+            payload: Payload { ranges, size },
+        })
+    }
+
+    fn read(&self, payload: &Payload, offset: usize, len: usize) -> Result<Vec<u8>, String> {
+        Ok(payload
+            .slice(offset, len)?
+            .into_iter()
+            .flat_map(|range| &self.data[range])
+            .copied()
+            .collect())
+    }
+
+    /// Decode the values of a record
+    fn record(&self, payload: &Payload) -> Result<Vec<Value>, String> {
+        let head = self.read(payload, 0, payload.size.min(9))?;
+        let (header_size, _) = varint_in(&head)?;
+        let header_size = header_size as usize;
+        let header = self.read(payload, 0, header_size)?;
+        let (_, mut at) = varint_in(&header)?;
+
+        let mut offset = header_size;
+        let mut values = Vec::new();
+        while at < header.len() {
+            let (serial_type, len) = varint_in(&header[at..])?;
+            at += len;
+            let value_len = match serial_type {
+                0 | 8 | 9 => 0,
+                1..=4 => serial_type as usize,
+                5 => 6,
+                6 | 7 => 8,
+                n if n >= 12 => (n as usize - 12) / 2,
+                n => return Err(format!("Invalid SQLite serial type {}", n)),
+            };
+            let value = match serial_type {
+                0 => Value::Null,
+                8 => Value::Integer(0),
+                9 => Value::Integer(1),
+                1..=6 => {
+                    let bytes = self.read(payload, offset, value_len)?;
+                    // Big-endian two's complement, sign-extended from the first byte
+                    let first = if bytes[0] & 0x80 != 0 { -1i64 } else { 0 };
+                    Value::Integer(bytes.iter().fold(first, |v, b| (v << 8) | *b as i64))
+                }
+                7 => {
+                    let bytes = self.read(payload, offset, 8)?;
+                    Value::Real(f64::from_be_bytes(bytes.try_into().unwrap()))
+                }
+                n if n % 2 == 0 => Value::Blob {
+                    offset,
+                    len: value_len,
+                },
+                _ => Value::Text(
+                    String::from_utf8_lossy(&self.read(payload, offset, value_len)?).into_owned(),
+                ),
+            };
+            values.push(value);
+            offset += value_len;
+        }
+        Ok(values)
+    }
+}
+
+fn varint_in(bytes: &[u8]) -> Result<(i64, usize), String> {
+    let mut value: u64 = 0;
+    for (i, byte) in bytes.iter().take(9).enumerate() {
+        if i == 8 {
+            return Ok((((value << 8) | *byte as u64) as i64, 9));
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok((value as i64, i + 1));
+        }
+    }
+    Err("Truncated SQLite varint".to_string())
+}
+
+/// Column names of a CREATE TABLE statement, flagging an INTEGER PRIMARY KEY, which
+/// SQLite stores as the rowid instead of in the record
+fn table_columns(sql: &str) -> Vec<(String, bool)> {
+    let (Some(open), Some(close)) = (sql.find('('), sql.rfind(')')) else {
+        return Vec::new();
+    };
+    let mut definitions = Vec::new();
+    let (mut depth, mut start) = (0, open + 1);
+    for (i, c) in sql[..close].char_indices().skip_while(|(i, _)| *i <= open) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                definitions.push(&sql[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    definitions.push(&sql[start..close]);
+
+    definitions
+        .into_iter()
+        .filter_map(|definition| {
+            let definition = definition.trim();
+            let name = definition.split_whitespace().next()?;
+            let upper = definition.to_ascii_uppercase();
+            let constraint = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"]
+                .iter()
+                .any(|keyword| upper.starts_with(keyword));
+            if constraint {
+                return None;
+            }
+            let name = name.trim_matches(|c| matches!(c, '"' | '`' | '[' | ']' | '\''));
+            let integer_key = upper.contains("INTEGER") && upper.contains("PRIMARY KEY");
+            Some((name.to_ascii_lowercase(), integer_key))
+        })
+        .collect()
+}
+
+/// A table's rows as named columns
+struct Table {
+    columns: Vec<(String, bool)>,
+    rows: Vec<(Row, Vec<Value>)>,
+}
+
+impl Table {
+    fn read(db: &Database, root: u32, sql: &str) -> Result<Self, String> {
+        let columns = table_columns(sql);
+        let rows = db
+            .table_rows(root)?
+            .into_iter()
+            .map(|row| db.record(&row.payload).map(|values| (row, values)))
+            .collect::<Result<_, _>>()?;
+        Ok(Table { columns, rows })
+    }
+
+    fn column(&self, name: &str) -> Result<usize, String> {
+        self.columns
+            .iter()
+            .position(|(column, _)| column == name)
+            .ok_or_else(|| format!("MBTiles table lacks column '{}'", name))
+    }
+
+    /// Value of a column, with the rowid for an INTEGER PRIMARY KEY
+    fn value(&self, row: &(Row, Vec<Value>), column: usize) -> Value {
+        match row.1.get(column) {
+            Some(Value::Null) | None if self.columns[column].1 => Value::Integer(row.0.rowid),
+            Some(value) => value.clone(),
+            None => Value::Null,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------------------
+// MBTiles
+// ---------------------------------------------------------------------------------------
+
+fn read_archive(data: Vec<u8>) -> Result<MbTilesArchive, String> {
+    let db = Database::open(&data)?;
+    let mut tables: HashMap<String, (u32, String)> = HashMap::new();
+    for row in db.table_rows(1)? {
+        let values = db.record(&row.payload)?;
+        let text = |i: usize| values.get(i).and_then(Value::as_text).unwrap_or_default();
+        if text(0) == "table" {
+            let root = values.get(3).and_then(Value::as_integer).unwrap_or(0) as u32;
+            tables.insert(text(1).to_ascii_lowercase(), (root, text(4)));
+        }
+    }
+    let table = |name: &str| -> Result<Option<Table>, String> {
+        tables
+            .get(name)
+            .map(|(root, sql)| Table::read(&db, *root, sql))
+            .transpose()
+    };
+
+    let mut metadata: HashMap<String, String> = HashMap::new();
+    if let Some(table) = table("metadata")? {
+        let (name, value) = (table.column("name")?, table.column("value")?);
+        for row in &table.rows {
+            if let (Some(name), Some(value)) = (
+                table.value(row, name).as_text(),
+                table.value(row, value).as_text(),
+            ) {
+                metadata.insert(name, value);
+            }
+        }
+    }
+
+    // Tile data per (z, column, TMS row); either a tiles table or the deduplicated
+    // map + images layout (where tiles is a view)
+    let mut tms_tiles: Vec<((i64, i64, i64), TileRanges)> = Vec::new();
+    let blob = |row: &(Row, Vec<Value>), value: Value| match value {
+        Value::Blob { offset, len } => row.0.payload.slice(offset, len).ok(),
+        _ => None,
+    };
+    if let Some(tiles) = table("tiles")? {
+        let columns = ["zoom_level", "tile_column", "tile_row", "tile_data"]
+            .map(|name| tiles.column(name))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in &tiles.rows {
+            let coords: Vec<Option<i64>> = columns[..3]
+                .iter()
+                .map(|c| tiles.value(row, *c).as_integer())
+                .collect();
+            if let ([Some(z), Some(x), Some(y)], Some(ranges)) = (
+                [coords[0], coords[1], coords[2]],
+                blob(row, tiles.value(row, columns[3])),
+            ) {
+                tms_tiles.push(((z, x, y), ranges));
+            }
+        }
+    } else if let (Some(map), Some(images)) = (table("map")?, table("images")?) {
+        let (image_id, image_data) = (images.column("tile_id")?, images.column("tile_data")?);
+        let mut image_ranges: HashMap<String, TileRanges> = HashMap::new();
+        for row in &images.rows {
+            if let (Some(id), Some(ranges)) = (
+                images.value(row, image_id).as_text(),
+                blob(row, images.value(row, image_data)),
+            ) {
+                image_ranges.insert(id, ranges);
+            }
+        }
+        let columns = ["zoom_level", "tile_column", "tile_row", "tile_id"]
+            .map(|name| map.column(name))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in &map.rows {
+            let value = |i: usize| map.value(row, columns[i]);
+            if let (Some(z), Some(x), Some(y), Some(ranges)) = (
+                value(0).as_integer(),
+                value(1).as_integer(),
+                value(2).as_integer(),
+                value(3).as_text().and_then(|id| image_ranges.get(&id)),
+            ) {
+                tms_tiles.push(((z, x, y), ranges.clone()));
+            }
+        }
+    } else {
+        return Err("MBTiles file has no tiles table".to_string());
+    }
+
+    // MBTiles rows count from the south (TMS); the caches use XYZ
+    let mut tiles = HashMap::new();
+    for ((z, x, row), ranges) in tms_tiles {
+        if !(0..=MAX_TILE_ZOOM as i64).contains(&z) {
+            continue;
+        }
+        let count = 1i64 << z;
+        if (0..count).contains(&x) && (0..count).contains(&row) {
+            tiles.insert((z as u32, x as u32, (count - 1 - row) as u32), ranges);
+        }
+    }
+    if tiles.is_empty() {
+        return Err("MBTiles file contains no tiles".to_string());
+    }
+
+    let format = match metadata.get("format") {
+        Some(format) => format.trim().to_ascii_lowercase(),
+        // Guess from the first tile's bytes
+        None => {
+            let ranges = tiles.values().next().unwrap();
+            let start = ranges.first().map_or(&[][..], |range| &data[range.clone()]);
+            if start.starts_with(&PNG_SIGNATURE) {
+                "png".to_string()
+            } else {
+                "pbf".to_string()
+            }
+        }
+    };
+    let zooms = tiles.keys().map(|(z, _, _)| *z);
+    let (min_tile_zoom, max_tile_zoom) = (zooms.clone().min().unwrap(), zooms.max().unwrap());
+    let zoom = |key: &str| metadata.get(key).and_then(|z| z.trim().parse::<u32>().ok());
+    let bounds = metadata.get("bounds").and_then(|bounds| {
+        let values: Vec<f64> = bounds
+            .split(',')
+            .filter_map(|v| v.trim().parse().ok())
+            .collect();
+        <[f64; 4]>::try_from(values.as_slice())
+            .ok()
+            .filter(|[w, s, e, n]| w < e && s < n)
+    });
+    let encoding = metadata
+        .get("encoding")
+        .and_then(|e| serde_json::from_value(serde_json::Value::String(e.clone())).ok());
+    let tilejson = TileJson {
+        tiles: vec!["mbtiles://{z}/{x}/{y}".to_string()],
+        minzoom: zoom("minzoom").unwrap_or(min_tile_zoom).min(max_tile_zoom),
+        maxzoom: zoom("maxzoom")
+            .unwrap_or(max_tile_zoom)
+            .clamp(min_tile_zoom, MAX_TILE_ZOOM),
+        bounds,
+        attribution: metadata.get("attribution").cloned(),
+        scheme: None,
+        encoding,
+    };
+    Ok(MbTilesArchive {
+        data,
+        tiles,
+        format,
+        name: metadata.get("name").cloned(),
+        metadata: tilejson,
+    })
+}
+
+// ---------------------------------------------------------------------------------------
+// PNG decoding of raster tiles
+// ---------------------------------------------------------------------------------------
+
+/// Decode an 8-bit, non-interlaced PNG into RGBA pixels
+fn decode_png_rgba(png: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        return Err("Not a PNG image".to_string());
+    }
+    let (mut width, mut height, mut color_type) = (0usize, 0usize, 0u8);
+    let (mut palette, mut alpha, mut compressed) = (Vec::new(), Vec::new(), Vec::new());
+    let mut at = PNG_SIGNATURE.len();
+    while at + 8 <= png.len() {
+        let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+        let kind = &png[at + 4..at + 8];
+        let data = (at + 8)
+            .checked_add(len)
+            .and_then(|end| png.get(at + 8..end))
+            .ok_or_else(|| "Truncated PNG chunk".to_string())?;
+        match kind {
+            b"IHDR" if len >= 13 => {
+                width = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+                height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+                color_type = data[9];
+                if data[8] != 8 || data[12] != 0 {
+                    return Err("Only 8-bit, non-interlaced PNG tiles are supported".to_string());
+                }
+            }
+            b"PLTE" => palette = data.to_vec(),
+            b"tRNS" => alpha = data.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        at += 12 + len;
+    }
+    let channels = match color_type {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => return Err(format!("Unsupported PNG color type {}", color_type)),
+    };
+    if width == 0 || height == 0 {
+        return Err("PNG has no image header".to_string());
+    }
+    if width.max(height) > MAX_PNG_SIDE {
+        return Err(format!(
+            "PNG of {}x{} pixels is too large; tiles may be at most {} pixels wide",
+            width, height, MAX_PNG_SIDE
+        ));
+    }
+
+    // Each row is a filter byte followed by the samples
+    let stride = width
+        .checked_mul(channels)
+        .ok_or_else(|| "PNG row size overflows".to_string())?;
+    let image_len = stride
+        .checked_add(1)
+        .and_then(|row| row.checked_mul(height))
+        .ok_or_else(|| "PNG image size overflows".to_string())?;
+    let mut raw = Vec::new();
+    ZlibDecoder::new(&compressed[..])
+        .take(image_len as u64)
+        .read_to_end(&mut raw)
+        .map_err(|e| format!("Failed to inflate PNG data: {}", e))?;
+    if raw.len() < image_len {
+        return Err("PNG image data is truncated".to_string());
+    }
+
+    let mut pixels = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, rest) = pixels.split_at_mut(y * stride);
+        let previous = if y > 0 {
+            &done[(y - 1) * stride..]
+        } else {
+            &[][..]
+        };
+        let current = &mut rest[..stride];
+        for i in 0..stride {
+            let a = if i >= channels {
+                current[i - channels]
+            } else {
+                0
+            };
+            let b = previous.get(i).copied().unwrap_or(0);
+            let c = if i >= channels {
+                previous.get(i - channels).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            let prediction = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("Invalid PNG filter {}", filter)),
+            };
+            current[i] = line[i].wrapping_add(prediction);
+        }
+    }
+
+    let rgba = match color_type {
+        6 => pixels,
+        2 => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        0 => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        4 => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        _ => pixels
+            .iter()
+            .flat_map(|&i| {
+                let i = i as usize;
+                let rgb = palette.get(i * 3..i * 3 + 3).unwrap_or(&[0, 0, 0]);
+                [rgb[0], rgb[1], rgb[2], alpha.get(i).copied().unwrap_or(255)]
+            })
+            .collect(),
+    };
+    Ok((width as u32, height as u32, rgba))
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// ---------------------------------------------------------------------------------------
+// Serving tiles
+// ---------------------------------------------------------------------------------------
+
+/// Zoom range, bounds and attribution of the archive loaded for a source key
+pub(crate) fn archive_metadata(key: &str) -> Option<TileJson> {
+    ModuleState::with(|state| {
+        state
+            .mbtiles_archives
+            .get(key)
+            .map(|archive| archive.metadata.clone())
+    })
+}
+
+/// Tile data of an archive loaded for `key`: None without an archive, Some(None) when the
+/// archive lacks the tile (MBTiles usually omit empty tiles)
+pub(crate) fn archive_tile(key: &str, tile: &TileRequest) -> Option<Option<Vec<u8>>> {
+    ModuleState::with(|state| {
+        state
+            .mbtiles_archives
+            .get(key)
+            .map(|archive| archive.tile(tile))
+    })
+}
+
+/// A raster tile from the loaded raster archive, decoded and cached; None when no archive
+/// is loaded or it lacks the tile
+pub(crate) fn load_raster_tile(x: u32, y: u32, z: u32) -> Result<Option<TileData>, JsValue> {
+    let tile = TileRequest { x, y, z };
+    let Some(Some(png)) = archive_tile(TileSource::Raster.name(), &tile) else {
+        return Ok(None);
+    };
+    let (width, height, rgba) = decode_png_rgba(&png)
+        .map_err(|e| JsValue::from_str(&format!("MBTiles raster tile {}/{}/{}: {}", z, x, y, e)))?;
+    Ok(Some(crate::offline::cache_raster_pixels(
+        x, y, z, width, height, rgba,
+    )))
+}
+
+/// Load an MBTiles file and serve its tiles instead of fetching them. `source` is
+/// "raster", "vector" or the name of a vector source for layers with a `source`; by
+/// default it follows the archive format (pbf: vector, png: raster). Raster archives must
+/// hold PNG elevation tiles. Returns `{ source, format, name, tileCount, minzoom,
+/// maxzoom, bounds, attribution }`.
+#[wasm_bindgen]
+pub fn load_mbtiles(bytes: Vec<u8>, source: Option<String>) -> Result<JsValue, JsValue> {
+    let archive = read_archive(bytes)
+        .map_err(|e| JsValue::from_str(&format!("Failed to load MBTiles: {}", e)))?;
+    let vector_format = matches!(archive.format.as_str(), "pbf" | "mvt");
+    let key = match source.as_deref().filter(|s| !s.is_empty()) {
+        None if vector_format => TileSource::Vector.name().to_string(),
+        None | Some("raster") => TileSource::Raster.name().to_string(),
+        Some(name) => vector_source_key(Some(name).filter(|n| *n != TileSource::Vector.name())),
+    };
+    let raster = key == TileSource::Raster.name();
+    if raster && archive.format != "png" {
+        return Err(JsValue::from_str(&format!(
+            "MBTiles raster format '{}' is not supported; use PNG elevation tiles",
+            archive.format
+        )));
+    }
+    if !raster && !vector_format {
+        return Err(JsValue::from_str(&format!(
+            "MBTiles format '{}' does not hold vector tiles",
+            archive.format
+        )));
+    }
+
+    let summary = MbTilesSummary {
+        source: key.clone(),
+        format: archive.format.clone(),
+        name: archive.name.clone(),
+        tile_count: archive.tiles.len(),
+        minzoom: archive.metadata.minzoom,
+        maxzoom: archive.metadata.maxzoom,
+        bounds: archive.metadata.bounds,
+        attribution: archive.metadata.attribution.clone(),
+    };
+    ModuleState::with_mut(|state| state.mbtiles_archives.insert(key, archive));
+    Ok(serde_wasm_bindgen::to_value(&summary)?)
+}
+
+/// Stop serving tiles from the archive loaded for `source`; returns whether one was loaded
+#[wasm_bindgen]
+pub fn unload_mbtiles(source: &str) -> bool {
+    let key = match source {
+        "raster" | "vector" => source.to_string(),
+        name => vector_source_key(Some(name)),
+    };
+    ModuleState::with_mut(|state| state.mbtiles_archives.remove(&key).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mbtiles_index_and_png_tiles() {
+        let archive = read_archive(include_bytes!("testdata/tiny.mbtiles").to_vec()).unwrap();
+        assert_eq!(archive.format, "png");
+        assert_eq!(archive.tiles.len(), 18);
+        assert_eq!((archive.metadata.minzoom, archive.metadata.maxzoom), (0, 2));
+        assert_eq!(archive.metadata.attribution.as_deref(), Some("test data"));
+
+        // TMS row 0 at zoom 2 is the southernmost XYZ row 3; tiles span interior pages
+        let tile = archive.tile(&TileRequest { x: 3, y: 3, z: 2 }).unwrap();
+        assert_eq!(tile, [3, 0].repeat(20));
+        // A record spilling onto overflow pages
+        let tile = archive.tile(&TileRequest { x: 1, y: 1, z: 1 }).unwrap();
+        assert_eq!(tile, (0..=255u8).collect::<Vec<_>>().repeat(6));
+
+        let png = archive.tile(&TileRequest { x: 0, y: 0, z: 0 }).unwrap();
+        let (width, height, rgba) = decode_png_rgba(&png).unwrap();
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(&rgba[8..16], &[70, 80, 90, 255, 100, 110, 120, 255]);
+
+        assert!(read_archive(b"not a database".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_truncated_and_oversized_png() {
+        let archive = read_archive(include_bytes!("testdata/tiny.mbtiles").to_vec()).unwrap();
+        let png = archive.tile(&TileRequest { x: 0, y: 0, z: 0 }).unwrap();
+
+        // Every cut before the image data is complete fails without panicking
+        let idat = png.windows(4).position(|w| w == b"IDAT").unwrap();
+        let idat_len = u32::from_be_bytes(png[idat - 4..idat].try_into().unwrap()) as usize;
+        for len in 0..idat + 4 + idat_len {
+            assert!(decode_png_rgba(&png[..len]).is_err(), "cut at {}", len);
+        }
+        let error = decode_png_rgba(&png[..24]).err().unwrap();
+        assert_eq!(error, "Truncated PNG chunk");
+
+        // IHDR width and height of 2^31 - 1
+        let mut huge = png.clone();
+        huge[16..24].copy_from_slice(&[0x7f, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff]);
+        let error = decode_png_rgba(&huge).err().unwrap();
+        assert!(error.contains("too large"), "{}", error);
+    }
+}
@@ -0,0 +1,319 @@
+// Compare two generated models (e.g. before/after a config change, or two OSM snapshots)
+// and report where they differ. Both sets are binned into an XY grid in mesh space;
+// per-cell surface height and triangle area are compared, and neighbouring cells with
+// the same kind of change are merged into regions with bounding boxes.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use wasm_bindgen::prelude::*;
+
+use crate::polygon_geometry::BufferGeometry;
+
+const DEFAULT_CELL_SIZE: f64 = 2.0;
+const DEFAULT_HEIGHT_TOLERANCE: f64 = 0.05;
+// Relative change in surface area per cell that counts as a modification
+const AREA_TOLERANCE: f64 = 0.1;
+
+#[derive(Deserialize)]
+pub struct MeshDiffInput {
+    pub before: Vec<BufferGeometry>,
+    pub after: Vec<BufferGeometry>,
+    /// Grid cell size in mesh units (the terrain spans 200 units)
+    #[serde(default, rename = "cellSize")]
+    pub cell_size: Option<f64>,
+    /// Minimum top-surface height change in mesh units to report a cell as changed
+    #[serde(default, rename = "heightTolerance")]
+    pub height_tolerance: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffRegion {
+    pub kind: ChangeKind,
+    /// [minX, minY, minZ, maxX, maxY, maxZ] in mesh units
+    pub bbox: [f64; 6],
+    #[serde(rename = "cellCount")]
+    pub cell_count: usize,
+    /// Largest top-surface height change within the region (after - before)
+    #[serde(rename = "heightDelta")]
+    pub height_delta: f64,
+    /// Layer labels of the geometries touching the region
+    pub layers: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct MeshDiffSummary {
+    #[serde(rename = "addedCells")]
+    pub added_cells: usize,
+    #[serde(rename = "removedCells")]
+    pub removed_cells: usize,
+    #[serde(rename = "changedCells")]
+    pub changed_cells: usize,
+    #[serde(rename = "unchangedCells")]
+    pub unchanged_cells: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MeshDiffResult {
+    #[serde(rename = "cellSize")]
+    pub cell_size: f64,
+    pub regions: Vec<DiffRegion>,
+    pub summary: MeshDiffSummary,
+}
+
+// Accumulated geometry falling into one grid cell
+#[derive(Clone)]
+struct CellStats {
+    area: f64,
+    min: [f64; 3],
+    max: [f64; 3],
+    layers: BTreeSet<String>,
+}
+
+impl CellStats {
+    fn new() -> Self {
+        Self {
+            area: 0.0,
+            min: [f64::INFINITY; 3],
+            max: [f64::NEG_INFINITY; 3],
+            layers: BTreeSet::new(),
+        }
+    }
+}
+
+type Cell = (i64, i64);
+
+/// Compare two sets of BufferGeometries and return a JSON diff report
+#[wasm_bindgen]
+pub fn diff_meshes(input_json: &str) -> Result<String, JsValue> {
+    let input: MeshDiffInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+
+    let result = compute_mesh_diff(&input);
+    serde_json::to_string(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize diff: {}", e)))
+}
+
+pub(crate) fn compute_mesh_diff(input: &MeshDiffInput) -> MeshDiffResult {
+    let cell_size = input
+        .cell_size
+        .filter(|size| size.is_finite() && *size > 0.0)
+        .unwrap_or(DEFAULT_CELL_SIZE);
+    let height_tolerance = input
+        .height_tolerance
+        .filter(|tolerance| tolerance.is_finite() && *tolerance >= 0.0)
+        .unwrap_or(DEFAULT_HEIGHT_TOLERANCE);
+
+    let before = bin_geometries(&input.before, cell_size);
+    let after = bin_geometries(&input.after, cell_size);
+
+    let mut summary = MeshDiffSummary::default();
+    // Changed cells with their merged stats and height delta
+    let mut changes: HashMap<Cell, (ChangeKind, CellStats, f64)> = HashMap::new();
+
+    for (cell, new) in &after {
+        match before.get(cell) {
+            None => {
+                summary.added_cells += 1;
+                changes.insert(*cell, (ChangeKind::Added, new.clone(), new.max[2]));
+            }
+            Some(old) => {
+                let height_delta = new.max[2] - old.max[2];
+                let area_change = (new.area - old.area).abs() / old.area.max(new.area).max(1e-12);
+                if height_delta.abs() > height_tolerance || area_change > AREA_TOLERANCE {
+                    summary.changed_cells += 1;
+                    changes.insert(*cell, (ChangeKind::Changed, merge(old, new), height_delta));
+                } else {
+                    summary.unchanged_cells += 1;
+                }
+            }
+        }
+    }
+    for (cell, old) in &before {
+        if !after.contains_key(cell) {
+            summary.removed_cells += 1;
+            changes.insert(*cell, (ChangeKind::Removed, old.clone(), -old.max[2]));
+        }
+    }
+
+    MeshDiffResult {
+        cell_size,
+        regions: group_regions(changes),
+        summary,
+    }
+}
+
+fn bin_geometries(geometries: &[BufferGeometry], cell_size: f64) -> HashMap<Cell, CellStats> {
+    let mut cells: HashMap<Cell, CellStats> = HashMap::new();
+
+    for geometry in geometries.iter().filter(|g| g.has_data) {
+        let label = geometry.layer_label();
+        let vertex = |i: usize| -> Option<[f64; 3]> {
+            let v = geometry.vertices.get(i * 3..i * 3 + 3)?;
+            Some([v[0] as f64, v[1] as f64, v[2] as f64])
+        };
+        let triangle_count = match &geometry.indices {
+            Some(indices) => indices.len() / 3,
+            None => geometry.vertices.len() / 9,
+        };
+
+        for t in 0..triangle_count {
+            let corner = |k: usize| match &geometry.indices {
+                Some(indices) => vertex(indices[t * 3 + k] as usize),
+                None => vertex(t * 3 + k),
+            };
+            let (Some(a), Some(b), Some(c)) = (corner(0), corner(1), corner(2)) else {
+                continue;
+            };
+
+            let centroid_x = (a[0] + b[0] + c[0]) / 3.0;
+            let centroid_y = (a[1] + b[1] + c[1]) / 3.0;
+            let cell = (
+                (centroid_x / cell_size).floor() as i64,
+                (centroid_y / cell_size).floor() as i64,
+            );
+
+            let stats = cells.entry(cell).or_insert_with(CellStats::new);
+            stats.area += triangle_area(&a, &b, &c);
+            for point in [a, b, c] {
+                for (axis, value) in point.iter().enumerate() {
+                    stats.min[axis] = stats.min[axis].min(*value);
+                    stats.max[axis] = stats.max[axis].max(*value);
+                }
+            }
+            if let Some(label) = label {
+                stats.layers.insert(label.to_string());
+            }
+        }
+    }
+
+    cells
+}
+
+fn merge(a: &CellStats, b: &CellStats) -> CellStats {
+    let mut merged = a.clone();
+    merged.area = a.area.max(b.area);
+    for axis in 0..3 {
+        merged.min[axis] = a.min[axis].min(b.min[axis]);
+        merged.max[axis] = a.max[axis].max(b.max[axis]);
+    }
+    merged.layers.extend(b.layers.iter().cloned());
+    merged
+}
+
+// Flood-fill 4-connected cells of the same change kind into regions
+fn group_regions(mut changes: HashMap<Cell, (ChangeKind, CellStats, f64)>) -> Vec<DiffRegion> {
+    let mut seeds: Vec<Cell> = changes.keys().copied().collect();
+    seeds.sort_unstable();
+
+    let mut regions = Vec::new();
+    for seed in seeds {
+        let Some((kind, first, first_delta)) = changes.remove(&seed) else {
+            continue;
+        };
+        let mut region = DiffRegion {
+            kind,
+            bbox: [
+                first.min[0],
+                first.min[1],
+                first.min[2],
+                first.max[0],
+                first.max[1],
+                first.max[2],
+            ],
+            cell_count: 1,
+            height_delta: first_delta,
+            layers: Vec::new(),
+        };
+        let mut layers = first.layers;
+
+        let mut stack = vec![seed];
+        while let Some((x, y)) = stack.pop() {
+            for neighbour in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if !matches!(changes.get(&neighbour), Some((k, _, _)) if *k == kind) {
+                    continue;
+                }
+                let (_, stats, delta) = changes.remove(&neighbour).unwrap();
+                for axis in 0..3 {
+                    region.bbox[axis] = region.bbox[axis].min(stats.min[axis]);
+                    region.bbox[axis + 3] = region.bbox[axis + 3].max(stats.max[axis]);
+                }
+                if delta.abs() > region.height_delta.abs() {
+                    region.height_delta = delta;
+                }
+                region.cell_count += 1;
+                layers.extend(stats.layers);
+                stack.push(neighbour);
+            }
+        }
+
+        region.layers = layers.into_iter().collect();
+        regions.push(region);
+    }
+
+    // Largest regions first
+    regions.sort_by(|a, b| b.cell_count.cmp(&a.cell_count).then(a.kind.cmp(&b.kind)));
+    regions
+}
+
+fn triangle_area(a: &[f64; 3], b: &[f64; 3], c: &[f64; 3]) -> f64 {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let cross = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    0.5 * (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit square at (x, y) split into two triangles at height z
+    fn square(x: f32, y: f32, z: f32) -> BufferGeometry {
+        BufferGeometry {
+            vertices: vec![x, y, z, x + 1.0, y, z, x + 1.0, y + 1.0, z, x, y + 1.0, z],
+            normals: None,
+            colors: None,
+            indices: Some(vec![0, 1, 2, 0, 2, 3]),
+            uvs: None,
+            has_data: true,
+            properties: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_added_removed_and_changed_regions() {
+        let input = MeshDiffInput {
+            before: vec![square(0.0, 0.0, 1.0), square(10.0, 10.0, 1.0)],
+            after: vec![square(0.0, 0.0, 3.0), square(20.0, 0.0, 1.0)],
+            cell_size: Some(2.0),
+            height_tolerance: None,
+        };
+        let result = compute_mesh_diff(&input);
+
+        assert_eq!(result.summary.changed_cells, 1);
+        assert_eq!(result.summary.added_cells, 1);
+        assert_eq!(result.summary.removed_cells, 1);
+        let changed = result
+            .regions
+            .iter()
+            .find(|r| r.kind == ChangeKind::Changed)
+            .unwrap();
+        assert!((changed.height_delta - 2.0).abs() < 1e-6);
+        let added = result
+            .regions
+            .iter()
+            .find(|r| r.kind == ChangeKind::Added)
+            .unwrap();
+        assert_eq!(added.bbox, [20.0, 0.0, 1.0, 21.0, 1.0, 1.0]);
+    }
+}
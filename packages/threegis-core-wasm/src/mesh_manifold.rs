@@ -0,0 +1,306 @@
+// Manifold and watertightness analysis of a triangle mesh, run before printing exports.
+// Vertices are welded by position first, because generated meshes often duplicate
+// vertices per face (e.g. CSG output) while still being closed surfaces.
+use serde::Serialize;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::polygon_geometry::BufferGeometry;
+
+// Positions closer than this (mesh units) are welded into one vertex
+const WELD_EPSILON: f64 = 1e-5;
+// Keep the example list short even for badly broken meshes
+const MAX_REPORTED_EDGES: usize = 20;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct ManifoldReport {
+    #[serde(rename = "vertexCount")]
+    pub vertex_count: usize,
+    /// Distinct vertex positions after welding
+    #[serde(rename = "weldedVertexCount")]
+    pub welded_vertex_count: usize,
+    #[serde(rename = "triangleCount")]
+    pub triangle_count: usize,
+    #[serde(rename = "degenerateTriangles")]
+    pub degenerate_triangles: usize,
+    /// Triangles using the same three vertices as an earlier one, in any order
+    #[serde(rename = "duplicateFaces")]
+    pub duplicate_faces: usize,
+    /// Edges used by a single triangle: holes in the surface
+    #[serde(rename = "boundaryEdges")]
+    pub boundary_edges: usize,
+    /// Edges shared by more than two triangles
+    #[serde(rename = "nonManifoldEdges")]
+    pub non_manifold_edges: usize,
+    /// Edges whose two triangles traverse it in the same direction, i.e. one of them is
+    /// flipped relative to its neighbor
+    #[serde(rename = "inconsistentEdges")]
+    pub inconsistent_edges: usize,
+    /// Triangles whose stored vertex normals point against their winding
+    #[serde(rename = "normalsAgainstWinding")]
+    pub normals_against_winding: usize,
+    /// Enclosed volume by the divergence theorem; negative for an inside-out closed mesh
+    #[serde(rename = "signedVolume")]
+    pub signed_volume: f64,
+    /// No boundary and no non-manifold edges
+    #[serde(rename = "isWatertight")]
+    pub is_watertight: bool,
+    /// Watertight, consistently wound and facing outward
+    #[serde(rename = "isManifold")]
+    pub is_manifold: bool,
+    /// Closed and consistently wound, but with every face pointing inward
+    #[serde(rename = "isInsideOut")]
+    pub is_inside_out: bool,
+    /// Welded vertex positions of some offending edges
+    #[serde(rename = "exampleEdges")]
+    pub example_edges: Vec<[[f32; 3]; 2]>,
+}
+
+impl ManifoldReport {
+    /// Human-readable problems, empty for a printable manifold mesh
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let counts = [
+            (self.boundary_edges, "open boundary edges"),
+            (self.non_manifold_edges, "non-manifold edges"),
+            (
+                self.inconsistent_edges,
+                "edges between oppositely wound faces",
+            ),
+            (self.duplicate_faces, "duplicate faces"),
+            (
+                self.degenerate_triangles,
+                "degenerate (zero-area) triangles",
+            ),
+            (
+                self.normals_against_winding,
+                "triangles with normals against their winding",
+            ),
+        ];
+        for (count, what) in counts {
+            if count > 0 {
+                problems.push(format!("{} {}", count, what));
+            }
+        }
+        if self.is_inside_out {
+            problems.push("faces point inward (inside-out mesh)".to_string());
+        }
+        problems
+    }
+}
+
+fn sorted3(mut t: [u32; 3]) -> [u32; 3] {
+    t.sort_unstable();
+    t
+}
+
+/// Analyze a mesh; non-indexed when `indices` is None. Errors on malformed input.
+pub(crate) fn analyze_manifold(
+    vertices: &[f32],
+    indices: Option<&[u32]>,
+    normals: Option<&[f32]>,
+) -> Result<ManifoldReport, String> {
+    let vertex_count = vertices.len() / 3;
+    let indices: Vec<u32> = match indices {
+        Some(indices) => indices.to_vec(),
+        None => (0..(vertex_count - vertex_count % 3) as u32).collect(),
+    };
+    if let Some(bad) = indices.iter().find(|i| **i as usize >= vertex_count) {
+        return Err(format!(
+            "Index {} out of range for {} vertices",
+            bad, vertex_count
+        ));
+    }
+
+    // Weld by quantized position
+    let mut welded_index: HashMap<(i64, i64, i64), u32> = HashMap::new();
+    let mut welded_positions: Vec<[f32; 3]> = Vec::new();
+    let weld: Vec<u32> = vertices
+        .chunks_exact(3)
+        .map(|p| {
+            let key = (
+                (p[0] as f64 / WELD_EPSILON).round() as i64,
+                (p[1] as f64 / WELD_EPSILON).round() as i64,
+                (p[2] as f64 / WELD_EPSILON).round() as i64,
+            );
+            *welded_index.entry(key).or_insert_with(|| {
+                welded_positions.push([p[0], p[1], p[2]]);
+                (welded_positions.len() - 1) as u32
+            })
+        })
+        .collect();
+    let position = |i: u32| welded_positions[i as usize].map(f64::from);
+
+    let mut report = ManifoldReport {
+        vertex_count,
+        welded_vertex_count: welded_positions.len(),
+        triangle_count: indices.len() / 3,
+        ..Default::default()
+    };
+    let normals = normals.filter(|n| n.len() == vertices.len());
+
+    // Directed uses per undirected edge: (forward, backward) relative to (min, max)
+    let mut edges: HashMap<(u32, u32), (u32, u32)> = HashMap::new();
+    let mut faces: HashMap<[u32; 3], usize> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        let t = [
+            weld[triangle[0] as usize],
+            weld[triangle[1] as usize],
+            weld[triangle[2] as usize],
+        ];
+        if t[0] == t[1] || t[1] == t[2] || t[2] == t[0] {
+            report.degenerate_triangles += 1;
+            continue;
+        }
+        let [a, b, c] = t.map(position);
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let cross = [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ];
+        if cross.iter().map(|c| c * c).sum::<f64>() <= f64::EPSILON * f64::EPSILON {
+            report.degenerate_triangles += 1;
+            continue;
+        }
+        let duplicates = faces.entry(sorted3(t)).or_insert(0);
+        *duplicates += 1;
+        if *duplicates > 1 {
+            report.duplicate_faces += 1;
+        }
+
+        report.signed_volume += (a[0] * (b[1] * c[2] - b[2] * c[1])
+            - a[1] * (b[0] * c[2] - b[2] * c[0])
+            + a[2] * (b[0] * c[1] - b[1] * c[0]))
+            / 6.0;
+
+        if let Some(normals) = normals {
+            let stored: [f64; 3] = triangle.iter().fold([0.0; 3], |sum, &i| {
+                let n = &normals[i as usize * 3..i as usize * 3 + 3];
+                [
+                    sum[0] + n[0] as f64,
+                    sum[1] + n[1] as f64,
+                    sum[2] + n[2] as f64,
+                ]
+            });
+            if stored[0] * cross[0] + stored[1] * cross[1] + stored[2] * cross[2] < 0.0 {
+                report.normals_against_winding += 1;
+            }
+        }
+
+        for (from, to) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+            let uses = edges.entry((from.min(to), from.max(to))).or_insert((0, 0));
+            if from < to {
+                uses.0 += 1;
+            } else {
+                uses.1 += 1;
+            }
+        }
+    }
+
+    let mut offending: Vec<(u32, u32)> = Vec::new();
+    for (&edge, &(forward, backward)) in &edges {
+        let problem = match forward + backward {
+            1 => {
+                report.boundary_edges += 1;
+                true
+            }
+            2 if forward != backward => {
+                report.inconsistent_edges += 1;
+                true
+            }
+            2 => false,
+            _ => {
+                report.non_manifold_edges += 1;
+                true
+            }
+        };
+        if problem {
+            offending.push(edge);
+        }
+    }
+    // Deterministic examples regardless of hash order
+    offending.sort_unstable();
+    report.example_edges = offending
+        .into_iter()
+        .take(MAX_REPORTED_EDGES)
+        .map(|(a, b)| [welded_positions[a as usize], welded_positions[b as usize]])
+        .collect();
+
+    report.is_watertight =
+        report.triangle_count > 0 && report.boundary_edges == 0 && report.non_manifold_edges == 0;
+    let consistent = report.is_watertight && report.inconsistent_edges == 0;
+    report.is_inside_out = consistent && report.signed_volume < 0.0;
+    report.is_manifold = consistent && !report.is_inside_out;
+    Ok(report)
+}
+
+/// Check a BufferGeometry (as JSON, shaped like `process_polygon_geometry` output) for
+/// open boundaries, non-manifold edges, inconsistently wound or inside-out faces, normals
+/// against the winding and duplicate faces. Returns the report as JSON.
+#[wasm_bindgen]
+pub fn validate_mesh_manifold(geometry_json: &str) -> Result<String, JsValue> {
+    let geometry: BufferGeometry = serde_json::from_str(geometry_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse geometry: {}", e)))?;
+    let report = analyze_manifold(
+        &geometry.vertices,
+        geometry.indices.as_deref(),
+        geometry.normals.as_deref(),
+    )
+    .map_err(|e| JsValue::from_str(&e))?;
+    serde_json::to_string(&report)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize report: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TETRA_VERTICES: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+    const TETRA_INDICES: [u32; 12] = [0, 2, 1, 0, 1, 3, 1, 2, 3, 0, 3, 2];
+
+    #[test]
+    fn test_manifold_checks() {
+        let closed = analyze_manifold(&TETRA_VERTICES, Some(&TETRA_INDICES), None).unwrap();
+        assert!(closed.is_manifold, "{:?}", closed);
+        assert!(closed.problems().is_empty());
+        assert!((closed.signed_volume - 1.0 / 6.0).abs() < 1e-9);
+
+        // Same surface with a vertex per face corner still welds into a closed mesh
+        let soup: Vec<f32> = TETRA_INDICES
+            .iter()
+            .flat_map(|&i| TETRA_VERTICES[i as usize * 3..i as usize * 3 + 3].to_vec())
+            .collect();
+        assert!(analyze_manifold(&soup, None, None).unwrap().is_manifold);
+
+        let mut flipped = TETRA_INDICES;
+        flipped.swap(1, 2);
+        let report = analyze_manifold(&TETRA_VERTICES, Some(&flipped), None).unwrap();
+        assert_eq!(report.inconsistent_edges, 3);
+        assert!(!report.is_manifold);
+
+        let inverted: Vec<u32> = TETRA_INDICES
+            .chunks(3)
+            .flat_map(|t| [t[0], t[2], t[1]])
+            .collect();
+        let report = analyze_manifold(&TETRA_VERTICES, Some(&inverted), None).unwrap();
+        assert!(report.is_inside_out && !report.is_manifold);
+
+        let report = analyze_manifold(&TETRA_VERTICES, Some(&TETRA_INDICES[..9]), None).unwrap();
+        assert_eq!(report.boundary_edges, 3);
+        assert!(!report.is_watertight);
+
+        let mut doubled = TETRA_INDICES.to_vec();
+        doubled.extend_from_slice(&TETRA_INDICES[..3]);
+        let report = analyze_manifold(&TETRA_VERTICES, Some(&doubled), None).unwrap();
+        assert_eq!(report.duplicate_faces, 1);
+        assert_eq!(report.non_manifold_edges, 3);
+
+        // Every vertex normal pointing up: only the bottom face opposes its winding
+        let normals: Vec<f32> = [0.0, 0.0, 1.0].repeat(4);
+        let report =
+            analyze_manifold(&TETRA_VERTICES, Some(&TETRA_INDICES), Some(&normals)).unwrap();
+        assert_eq!(report.normals_against_winding, 1);
+    }
+}
@@ -0,0 +1,189 @@
+// Size and complexity metrics of a single mesh, in mesh units and, when a print
+// size is given, in millimeters at the same scale the 3MF export uses.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::export_3mf::millimeters_per_unit;
+
+#[derive(Deserialize)]
+pub struct MeshMetricsInput {
+    pub vertices: Vec<f32>,
+    /// Triangle indices; non-indexed triangle soup when omitted
+    #[serde(default)]
+    pub indices: Option<Vec<u32>>,
+    /// Physical size in millimeters of the longest model side (as in the 3MF export)
+    #[serde(default, rename = "modelSizeMm")]
+    pub model_size_mm: Option<f64>,
+    /// Process the mesh was generated in, whose terrain scale sizes the model
+    #[serde(default, rename = "processId")]
+    pub process_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PhysicalMetrics {
+    #[serde(rename = "mmPerUnit")]
+    pub mm_per_unit: f64,
+    /// Surface area in mm²
+    #[serde(rename = "surfaceArea")]
+    pub surface_area: f64,
+    /// Enclosed volume in mm³
+    pub volume: f64,
+    /// Extent along x, y, z in mm
+    pub dimensions: [f64; 3],
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MeshMetrics {
+    #[serde(rename = "vertexCount")]
+    pub vertex_count: usize,
+    #[serde(rename = "triangleCount")]
+    pub triangle_count: usize,
+    #[serde(rename = "degenerateTriangles")]
+    pub degenerate_triangles: usize,
+    /// Every edge is shared by exactly two triangles, so `volume` is meaningful
+    #[serde(rename = "isClosed")]
+    pub is_closed: bool,
+    #[serde(rename = "surfaceArea")]
+    pub surface_area: f64,
+    /// Absolute signed volume (divergence theorem), in cubic mesh units
+    pub volume: f64,
+    pub dimensions: [f64; 3],
+    pub millimeters: Option<PhysicalMetrics>,
+}
+
+/// Compute surface area, volume, counts and dimensions of a mesh; returns JSON
+#[wasm_bindgen]
+pub fn compute_mesh_metrics(input_json: &str) -> Result<String, JsValue> {
+    let input: MeshMetricsInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let metrics = mesh_metrics(&input).map_err(|e| JsValue::from_str(&e))?;
+    serde_json::to_string(&metrics)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize metrics: {}", e)))
+}
+
+pub(crate) fn mesh_metrics(input: &MeshMetricsInput) -> Result<MeshMetrics, String> {
+    let vertex_count = input.vertices.len() / 3;
+    let indices: Vec<u32> = match &input.indices {
+        Some(indices) => indices.clone(),
+        None => (0..(vertex_count - vertex_count % 3) as u32).collect(),
+    };
+    if let Some(bad) = indices.iter().find(|i| **i as usize >= vertex_count) {
+        return Err(format!(
+            "Index {} out of range for {} vertices",
+            bad, vertex_count
+        ));
+    }
+
+    let vertex = |i: u32| -> [f64; 3] {
+        let v = &input.vertices[i as usize * 3..i as usize * 3 + 3];
+        [v[0] as f64, v[1] as f64, v[2] as f64]
+    };
+
+    let mut surface_area = 0.0;
+    let mut signed_volume = 0.0;
+    let mut degenerate_triangles = 0;
+    let mut edge_uses: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            vertex(triangle[0]),
+            vertex(triangle[1]),
+            vertex(triangle[2]),
+        ];
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let cross = [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ];
+        let area = 0.5 * (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+        if area <= f64::EPSILON {
+            degenerate_triangles += 1;
+        }
+        surface_area += area;
+        // Signed volume of the tetrahedron (origin, a, b, c)
+        signed_volume += (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+            + a[2] * (b[0] * c[1] - b[1] * c[0]))
+            / 6.0;
+
+        for (from, to) in [
+            (triangle[0], triangle[1]),
+            (triangle[1], triangle[2]),
+            (triangle[2], triangle[0]),
+        ] {
+            *edge_uses.entry((from.min(to), from.max(to))).or_insert(0) += 1;
+        }
+    }
+    let triangle_count = indices.len() / 3;
+    let is_closed = triangle_count > 0 && edge_uses.values().all(|uses| *uses == 2);
+
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for point in input.vertices.chunks_exact(3) {
+        for (axis, value) in point.iter().enumerate() {
+            min[axis] = min[axis].min(*value as f64);
+            max[axis] = max[axis].max(*value as f64);
+        }
+    }
+    let dimensions = if vertex_count == 0 {
+        [0.0; 3]
+    } else {
+        [0, 1, 2].map(|axis| max[axis] - min[axis])
+    };
+    let volume = signed_volume.abs();
+
+    let millimeters =
+        millimeters_per_unit(input.model_size_mm, input.process_id.as_deref()).map(|mm| {
+            PhysicalMetrics {
+                mm_per_unit: mm,
+                surface_area: surface_area * mm * mm,
+                volume: volume * mm * mm * mm,
+                dimensions: dimensions.map(|d| d * mm),
+            }
+        });
+
+    Ok(MeshMetrics {
+        vertex_count,
+        triangle_count,
+        degenerate_triangles,
+        is_closed,
+        surface_area,
+        volume,
+        dimensions,
+        millimeters,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_cube_metrics() {
+        let vertices = vec![
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, //
+            0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0,
+        ];
+        let indices = vec![
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, //
+            1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+        ];
+        let input = MeshMetricsInput {
+            vertices,
+            indices: Some(indices),
+            model_size_mm: Some(400.0),
+            process_id: None,
+        };
+        let metrics = mesh_metrics(&input).unwrap();
+
+        assert_eq!(metrics.triangle_count, 12);
+        assert!(metrics.is_closed);
+        assert!((metrics.surface_area - 6.0).abs() < 1e-9);
+        assert!((metrics.volume - 1.0).abs() < 1e-9);
+        let mm = metrics.millimeters.unwrap();
+        assert_eq!(mm.mm_per_unit, 2.0);
+        assert!((mm.volume - 8.0).abs() < 1e-9);
+        assert_eq!(mm.dimensions, [2.0, 2.0, 2.0]);
+    }
+}
@@ -0,0 +1,642 @@
+// Repair pass for meshes that fail the manifold check: weld vertices within a tolerance,
+// drop degenerate and duplicate triangles, make the winding consistent and outward facing,
+// and close small holes. Runs on demand and optionally inside the 3MF export.
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use wasm_bindgen::prelude::*;
+
+use crate::mesh_manifold::{analyze_manifold, ManifoldReport};
+use crate::polygon_geometry::BufferGeometry;
+
+const DEFAULT_WELD_TOLERANCE: f64 = 1e-5;
+const DEFAULT_MAX_HOLE_EDGES: usize = 32;
+// Distance (mesh units) within which a vertex counts as lying on an edge
+const T_JUNCTION_TOLERANCE: f64 = 1e-4;
+// Splitting one edge per triangle and pass, three passes cover all edges of a triangle
+const MAX_T_JUNCTION_PASSES: usize = 8;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_hole_edges() -> usize {
+    DEFAULT_MAX_HOLE_EDGES
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RepairOptions {
+    /// Vertices closer than this (mesh units) are merged
+    #[serde(default, rename = "weldTolerance")]
+    pub weld_tolerance: Option<f64>,
+    /// Drop zero-area and duplicate triangles
+    #[serde(default = "default_true", rename = "removeDegenerate")]
+    pub remove_degenerate: bool,
+    /// Orient every triangle like its neighbors and closed parts outward
+    #[serde(default = "default_true", rename = "fixWinding")]
+    pub fix_winding: bool,
+    #[serde(default = "default_true", rename = "closeHoles")]
+    pub close_holes: bool,
+    /// Holes with more boundary edges than this are left open
+    #[serde(default = "default_max_hole_edges", rename = "maxHoleEdges")]
+    pub max_hole_edges: usize,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            weld_tolerance: None,
+            remove_degenerate: true,
+            fix_winding: true,
+            close_holes: true,
+            max_hole_edges: DEFAULT_MAX_HOLE_EDGES,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RepairReport {
+    #[serde(rename = "mergedVertices")]
+    pub merged_vertices: usize,
+    #[serde(rename = "removedDegenerate")]
+    pub removed_degenerate: usize,
+    #[serde(rename = "removedDuplicates")]
+    pub removed_duplicates: usize,
+    #[serde(rename = "flippedTriangles")]
+    pub flipped_triangles: usize,
+    #[serde(rename = "closedHoles")]
+    pub closed_holes: usize,
+    /// Boundary loops left open because they are too long or not simple
+    #[serde(rename = "openHoles")]
+    pub open_holes: usize,
+    #[serde(rename = "addedTriangles")]
+    pub added_triangles: usize,
+    pub before: ManifoldReport,
+    pub after: ManifoldReport,
+}
+
+/// Indexed mesh after repair; normals are dropped since faces may have changed
+#[derive(Debug, Clone)]
+pub struct RepairedMesh {
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub colors: Option<Vec<f32>>,
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(u: [f64; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ]
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
+
+fn triangle_edges(t: &[u32; 3]) -> [(u32, u32); 3] {
+    [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])]
+}
+
+/// Vertices merged by position, with the index of each input vertex in `positions`
+pub(crate) struct WeldedVertices {
+    pub positions: Vec<[f64; 3]>,
+    /// Colors of the kept vertices, `color_size` values each; empty without colors
+    pub colors: Vec<f32>,
+    pub color_size: Option<usize>,
+    pub remap: Vec<u32>,
+}
+
+/// Weld by position quantized to `tolerance`; the first vertex of a cell keeps its color.
+/// `colors` are used when they hold 3 or 4 values per vertex.
+pub(crate) fn weld_vertices(
+    vertices: &[f32],
+    colors: Option<&[f32]>,
+    tolerance: f64,
+) -> WeldedVertices {
+    let vertex_count = vertices.len() / 3;
+    let colors = colors.filter(|c| {
+        vertex_count > 0 && c.len() % vertex_count == 0 && matches!(c.len() / vertex_count, 3 | 4)
+    });
+    let color_size = colors.map(|c| c.len() / vertex_count);
+
+    let mut cells: HashMap<(i64, i64, i64), u32> = HashMap::new();
+    let mut positions: Vec<[f64; 3]> = Vec::new();
+    let mut out_colors: Vec<f32> = Vec::new();
+    let remap = vertices
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(i, p)| {
+            let key = (
+                (p[0] as f64 / tolerance).round() as i64,
+                (p[1] as f64 / tolerance).round() as i64,
+                (p[2] as f64 / tolerance).round() as i64,
+            );
+            *cells.entry(key).or_insert_with(|| {
+                positions.push([p[0], p[1], p[2]].map(f64::from));
+                if let (Some(size), Some(colors)) = (color_size, colors) {
+                    out_colors.extend_from_slice(&colors[i * size..(i + 1) * size]);
+                }
+                (positions.len() - 1) as u32
+            })
+        })
+        .collect();
+    WeldedVertices {
+        positions,
+        colors: out_colors,
+        color_size,
+        remap,
+    }
+}
+
+/// Repair a mesh; non-indexed when `indices` is None. `colors` are optional per-vertex
+/// RGB(A) values that follow the welded vertices.
+pub(crate) fn repair_mesh(
+    vertices: &[f32],
+    indices: Option<&[u32]>,
+    colors: Option<&[f32]>,
+    options: &RepairOptions,
+) -> Result<(RepairedMesh, RepairReport), String> {
+    let before = analyze_manifold(vertices, indices, None)?;
+    let vertex_count = vertices.len() / 3;
+    let indices: Vec<u32> = match indices {
+        Some(indices) => indices.to_vec(),
+        None => (0..(vertex_count - vertex_count % 3) as u32).collect(),
+    };
+    let mut report = RepairReport {
+        before,
+        ..Default::default()
+    };
+
+    let tolerance = options
+        .weld_tolerance
+        .filter(|t| t.is_finite() && *t > 0.0)
+        .unwrap_or(DEFAULT_WELD_TOLERANCE);
+    let WeldedVertices {
+        mut positions,
+        colors: mut out_colors,
+        color_size,
+        remap: weld,
+    } = weld_vertices(vertices, colors, tolerance);
+    report.merged_vertices = vertex_count - positions.len();
+
+    let mut triangles: Vec<[u32; 3]> = Vec::with_capacity(indices.len() / 3);
+    let mut seen: HashSet<[u32; 3]> = HashSet::new();
+    for t in indices.chunks_exact(3) {
+        let t = [
+            weld[t[0] as usize],
+            weld[t[1] as usize],
+            weld[t[2] as usize],
+        ];
+        if options.remove_degenerate {
+            let [a, b, c] = t.map(|i| positions[i as usize]);
+            let n = cross(sub(b, a), sub(c, a));
+            if t[0] == t[1]
+                || t[1] == t[2]
+                || t[2] == t[0]
+                || n.iter().map(|v| v * v).sum::<f64>() <= f64::EPSILON * f64::EPSILON
+            {
+                report.removed_degenerate += 1;
+                continue;
+            }
+            let mut key = t;
+            key.sort_unstable();
+            if !seen.insert(key) {
+                report.removed_duplicates += 1;
+                continue;
+            }
+        }
+        triangles.push(t);
+    }
+
+    let original = triangles.clone();
+    if options.fix_winding {
+        orient_triangles(&mut triangles, &positions);
+    }
+    if options.close_holes {
+        close_holes(
+            &mut triangles,
+            &mut positions,
+            &mut out_colors,
+            color_size,
+            options.max_hole_edges,
+            &mut report,
+        );
+        // Patches can turn an open, inward-facing part into a closed one
+        if options.fix_winding && report.closed_holes > 0 {
+            orient_triangles(&mut triangles, &positions);
+        }
+    }
+    report.flipped_triangles = original
+        .iter()
+        .zip(&triangles)
+        .filter(|(a, b)| a != b)
+        .count();
+
+    let repaired = RepairedMesh {
+        vertices: positions.iter().flat_map(|p| p.map(|c| c as f32)).collect(),
+        indices: triangles.into_iter().flatten().collect(),
+        colors: color_size.map(|_| out_colors),
+    };
+    report.after = analyze_manifold(&repaired.vertices, Some(&repaired.indices), None)?;
+    Ok((repaired, report))
+}
+
+/// Flood each connected part over its two-triangle edges so neighbors traverse shared edges
+/// in opposite directions, then turn closed parts with negative volume outward
+fn orient_triangles(triangles: &mut [[u32; 3]], positions: &[[f64; 3]]) {
+    let mut edges: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    for (index, t) in triangles.iter().enumerate() {
+        for (a, b) in triangle_edges(t) {
+            edges.entry(edge_key(a, b)).or_default().push(index);
+        }
+    }
+
+    let mut visited = vec![false; triangles.len()];
+    for start in 0..triangles.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut component = vec![start];
+        let mut closed = true;
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            for (a, b) in triangle_edges(&triangles[current]) {
+                let sharing = &edges[&edge_key(a, b)];
+                if sharing.len() != 2 {
+                    // Open or non-manifold edges give no reliable orientation
+                    closed = false;
+                    continue;
+                }
+                let neighbor = if sharing[0] == current {
+                    sharing[1]
+                } else {
+                    sharing[0]
+                };
+                if visited[neighbor] {
+                    continue;
+                }
+                visited[neighbor] = true;
+                if triangle_edges(&triangles[neighbor]).contains(&(a, b)) {
+                    triangles[neighbor].swap(1, 2);
+                }
+                component.push(neighbor);
+                queue.push_back(neighbor);
+            }
+        }
+
+        if closed {
+            let volume: f64 = component
+                .iter()
+                .map(|&i| {
+                    let [a, b, c] = triangles[i].map(|v| positions[v as usize]);
+                    let n = cross(b, c);
+                    a[0] * n[0] + a[1] * n[1] + a[2] * n[2]
+                })
+                .sum();
+            if volume < 0.0 {
+                for &i in &component {
+                    triangles[i].swap(1, 2);
+                }
+            }
+        }
+    }
+}
+
+/// Fill boundary loops of up to `max_edges` edges: a single triangle for three edges,
+/// otherwise a fan around the loop centroid. Winding follows the surrounding faces.
+fn close_holes(
+    triangles: &mut Vec<[u32; 3]>,
+    positions: &mut Vec<[f64; 3]>,
+    colors: &mut Vec<f32>,
+    color_size: Option<usize>,
+    max_edges: usize,
+    report: &mut RepairReport,
+) {
+    let mut uses: HashMap<(u32, u32), usize> = HashMap::new();
+    for t in triangles.iter() {
+        for (a, b) in triangle_edges(t) {
+            *uses.entry(edge_key(a, b)).or_insert(0) += 1;
+        }
+    }
+    // Directed boundary edges, keyed by start vertex; branching vertices are ambiguous
+    let mut next: HashMap<u32, Vec<u32>> = HashMap::new();
+    for t in triangles.iter() {
+        for (a, b) in triangle_edges(t) {
+            if uses[&edge_key(a, b)] == 1 {
+                next.entry(a).or_default().push(b);
+            }
+        }
+    }
+    let mut starts: Vec<u32> = next.keys().copied().collect();
+    starts.sort_unstable();
+
+    let mut used: HashSet<u32> = HashSet::new();
+    for start in starts {
+        if used.contains(&start) {
+            continue;
+        }
+        let mut hole = vec![start];
+        let mut current = start;
+        let closed = loop {
+            match next.get(&current).map(Vec::as_slice) {
+                Some([to]) if *to == start => break true,
+                Some([to]) if !used.contains(to) && !hole.contains(to) => {
+                    hole.push(*to);
+                    current = *to;
+                }
+                _ => break false,
+            }
+        };
+        used.extend(hole.iter().copied());
+        if !closed || hole.len() > max_edges {
+            report.open_holes += 1;
+            continue;
+        }
+
+        // Surrounding faces run along the loop, so the patch runs against it
+        if hole.len() == 3 {
+            triangles.push([hole[0], hole[2], hole[1]]);
+            report.added_triangles += 1;
+        } else {
+            let count = hole.len() as f64;
+            let centroid = hole.iter().fold([0.0; 3], |sum, &v| {
+                let p = positions[v as usize];
+                [
+                    sum[0] + p[0] / count,
+                    sum[1] + p[1] / count,
+                    sum[2] + p[2] / count,
+                ]
+            });
+            positions.push(centroid);
+            if let Some(size) = color_size {
+                for channel in 0..size {
+                    let mean = hole
+                        .iter()
+                        .map(|&v| colors[v as usize * size + channel])
+                        .sum::<f32>()
+                        / hole.len() as f32;
+                    colors.push(mean);
+                }
+            }
+            let center = (positions.len() - 1) as u32;
+            for (i, &from) in hole.iter().enumerate() {
+                let to = hole[(i + 1) % hole.len()];
+                triangles.push([to, from, center]);
+            }
+            report.added_triangles += hole.len();
+        }
+        report.closed_holes += 1;
+    }
+}
+
+/// Open-edge vertices strictly inside the segment `a`-`b`, ordered from `a` to `b`
+fn vertices_on_edge(a: u32, b: u32, open: &[u32], positions: &[[f64; 3]]) -> Vec<u32> {
+    let (pa, pb) = (positions[a as usize], positions[b as usize]);
+    let d = sub(pb, pa);
+    let length_sq = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+    if length_sq <= T_JUNCTION_TOLERANCE * T_JUNCTION_TOLERANCE {
+        return Vec::new();
+    }
+    let length = length_sq.sqrt();
+    let mut on_edge: Vec<(f64, u32)> = open
+        .iter()
+        .filter(|&&v| v != a && v != b)
+        .filter_map(|&v| {
+            let w = sub(positions[v as usize], pa);
+            let along = (w[0] * d[0] + w[1] * d[1] + w[2] * d[2]) / length;
+            if along <= T_JUNCTION_TOLERANCE || along >= length - T_JUNCTION_TOLERANCE {
+                return None;
+            }
+            let off = cross(w, d);
+            let distance = (off[0] * off[0] + off[1] * off[1] + off[2] * off[2]).sqrt() / length;
+            (distance <= T_JUNCTION_TOLERANCE).then_some((along, v))
+        })
+        .collect();
+    on_edge.sort_by(|x, y| x.0.total_cmp(&y.0));
+    on_edge.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Split triangles at vertices lying on their open edges (T-junctions, as BSP boolean
+/// operations leave them), so neighboring faces share their edges again. Returns the
+/// number of split triangles.
+fn split_t_junctions(triangles: &mut Vec<[u32; 3]>, positions: &[[f64; 3]]) -> usize {
+    let mut splits = 0;
+    for _ in 0..MAX_T_JUNCTION_PASSES {
+        let mut uses: HashMap<(u32, u32), usize> = HashMap::new();
+        for t in triangles.iter() {
+            for (a, b) in triangle_edges(t) {
+                *uses.entry(edge_key(a, b)).or_insert(0) += 1;
+            }
+        }
+        let mut open: Vec<u32> = uses
+            .iter()
+            .filter(|(_, &count)| count == 1)
+            .flat_map(|(&(a, b), _)| [a, b])
+            .collect();
+        open.sort_unstable();
+        open.dedup();
+        if open.is_empty() {
+            break;
+        }
+
+        let mut split = Vec::with_capacity(triangles.len());
+        let mut pass_splits = 0;
+        for t in triangles.iter() {
+            // One open edge per pass; further ones are split in the next passes
+            let junction = (0..3).find_map(|i| {
+                let (a, b, c) = (t[i], t[(i + 1) % 3], t[(i + 2) % 3]);
+                if uses[&edge_key(a, b)] != 1 {
+                    return None;
+                }
+                let on_edge = vertices_on_edge(a, b, &open, positions);
+                (!on_edge.is_empty()).then_some((a, b, c, on_edge))
+            });
+            match junction {
+                Some((a, b, c, on_edge)) => {
+                    let mut from = a;
+                    for to in on_edge.into_iter().chain([b]) {
+                        split.push([from, to, c]);
+                        from = to;
+                    }
+                    pass_splits += 1;
+                }
+                None => split.push(*t),
+            }
+        }
+        *triangles = split;
+        splits += pass_splits;
+        if pass_splits == 0 {
+            break;
+        }
+    }
+    splits
+}
+
+/// Result of a boolean operation with its T-junctions split so the surface is closed
+/// again. Every triangle keeps its own corners and flat normal, like the CSG output.
+pub(crate) fn close_t_junctions(geometry: BufferGeometry) -> BufferGeometry {
+    let WeldedVertices {
+        positions, remap, ..
+    } = weld_vertices(&geometry.vertices, None, DEFAULT_WELD_TOLERANCE);
+    let corners: Vec<u32> = match &geometry.indices {
+        Some(indices) => indices.clone(),
+        None => (0..(geometry.vertices.len() / 9 * 3) as u32).collect(),
+    };
+    let mut triangles: Vec<[u32; 3]> = corners
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]].map(|i| remap[i as usize]))
+        .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+        .collect();
+    if split_t_junctions(&mut triangles, &positions) == 0 {
+        return geometry;
+    }
+
+    let mut vertices = Vec::with_capacity(triangles.len() * 9);
+    let mut normals = Vec::with_capacity(triangles.len() * 9);
+    for t in &triangles {
+        let [a, b, c] = t.map(|i| positions[i as usize]);
+        let n = cross(sub(b, a), sub(c, a));
+        let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2])
+            .sqrt()
+            .max(f64::EPSILON);
+        for p in [a, b, c] {
+            vertices.extend(p.map(|v| v as f32));
+            normals.extend(n.map(|v| (v / length) as f32));
+        }
+    }
+    BufferGeometry {
+        indices: Some((0..triangles.len() as u32 * 3).collect()),
+        has_data: !vertices.is_empty(),
+        vertices,
+        normals: Some(normals),
+        colors: None,
+        uvs: None,
+        properties: geometry.properties,
+    }
+}
+
+/// Repair a BufferGeometry (as JSON, shaped like `process_polygon_geometry` output).
+/// `options_json` is `{ weldTolerance?, removeDegenerate?, fixWinding?, closeHoles?,
+/// maxHoleEdges? }`, everything enabled by default. Returns `{ geometry, report }` with an
+/// indexed geometry without normals and the manifold reports before and after.
+#[wasm_bindgen]
+pub fn repair_geometry(
+    geometry_json: &str,
+    options_json: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let geometry: BufferGeometry = serde_json::from_str(geometry_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse geometry: {}", e)))?;
+    let options: RepairOptions = match options_json.as_deref() {
+        Some(json) if !json.trim().is_empty() => serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse options: {}", e)))?,
+        _ => RepairOptions::default(),
+    };
+    let (repaired, report) = repair_mesh(
+        &geometry.vertices,
+        geometry.indices.as_deref(),
+        geometry.colors.as_deref(),
+        &options,
+    )
+    .map_err(|e| JsValue::from_str(&format!("Failed to repair geometry: {}", e)))?;
+
+    let has_data = !repaired.indices.is_empty();
+    let output = BufferGeometry {
+        vertices: repaired.vertices,
+        normals: None,
+        colors: repaired.colors,
+        indices: Some(repaired.indices),
+        uvs: None,
+        has_data,
+        properties: geometry.properties,
+    };
+    let geometries = crate::geometries_to_js(&[output]);
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &result,
+        &"geometry".into(),
+        &js_sys::Reflect::get(&geometries, &0.into())?,
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &"report".into(),
+        &serde_wasm_bindgen::to_value(&report)?,
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TETRA_VERTICES: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+    const TETRA_INDICES: [u32; 12] = [0, 2, 1, 0, 1, 3, 1, 2, 3, 0, 3, 2];
+
+    #[test]
+    fn test_repair_welds_orients_and_closes() {
+        // Triangle soup with one face flipped, a degenerate and a duplicate face, every
+        // face inverted and the last face missing
+        let mut soup_indices: Vec<u32> = TETRA_INDICES[..9]
+            .chunks(3)
+            .flat_map(|t| [t[0], t[2], t[1]])
+            .collect();
+        soup_indices.swap(4, 5);
+        soup_indices.extend_from_slice(&[0, 0, 1]);
+        soup_indices.extend_from_within(..3);
+        let soup: Vec<f32> = soup_indices
+            .iter()
+            .flat_map(|&i| {
+                // Jitter below the weld tolerance
+                TETRA_VERTICES[i as usize * 3..i as usize * 3 + 3]
+                    .iter()
+                    .map(|c| c + 1e-7)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let colors = vec![0.5; soup.len()];
+
+        let (repaired, report) =
+            repair_mesh(&soup, None, Some(&colors), &RepairOptions::default()).unwrap();
+        assert!(!report.before.is_manifold);
+        assert_eq!(report.removed_degenerate, 1);
+        assert_eq!(report.removed_duplicates, 1);
+        assert_eq!(report.closed_holes, 1);
+        assert_eq!(report.added_triangles, 1);
+        assert!(report.after.is_manifold, "{:?}", report.after);
+        assert_eq!(repaired.vertices.len(), 12);
+        assert_eq!(repaired.indices.len(), 12);
+        assert_eq!(repaired.colors.unwrap().len(), 12);
+        assert!((report.after.signed_volume - 1.0 / 6.0).abs() < 1e-6);
+
+        // A square hole takes a centroid fan
+        let cube_top_open: Vec<f32> = vec![
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0,
+            1.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0,
+        ];
+        let walls: Vec<u32> = vec![
+            0, 2, 1, 0, 3, 2, // bottom
+            0, 1, 5, 0, 5, 4, 1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+        ];
+        let (_, report) = repair_mesh(
+            &cube_top_open,
+            Some(&walls),
+            None,
+            &RepairOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.added_triangles, 4);
+        assert!(report.after.is_manifold, "{:?}", report.after);
+
+        let options = RepairOptions {
+            max_hole_edges: 3,
+            ..Default::default()
+        };
+        let (_, report) = repair_mesh(&cube_top_open, Some(&walls), None, &options).unwrap();
+        assert_eq!((report.closed_holes, report.open_holes), (0, 1));
+    }
+}
@@ -0,0 +1,586 @@
+// Quadric error metric decimation (Garland & Heckbert): edges are collapsed cheapest first,
+// where the cost of moving a vertex is its summed squared distance to the planes of the
+// faces it started on. Open borders stay fixed so tile edges and building bases keep their
+// outline, and collapses that would fold a face over or pinch the surface are skipped.
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use wasm_bindgen::prelude::*;
+
+use crate::mesh_repair::{weld_vertices, WeldedVertices};
+use crate::polygon_geometry::BufferGeometry;
+
+const WELD_TOLERANCE: f64 = 1e-5;
+const DEFAULT_TARGET_RATIO: f64 = 0.5;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SimplifyOptions {
+    /// Fraction of the triangles to keep, 0..1; defaults to 0.5 unless `maxError` is given
+    #[serde(default, rename = "targetRatio")]
+    pub target_ratio: Option<f64>,
+    /// Largest allowed deviation from the original surface in mesh units
+    #[serde(default, rename = "maxError")]
+    pub max_error: Option<f64>,
+    /// Let border vertices move as well; by default open borders are kept exactly
+    #[serde(default, rename = "simplifyBorders")]
+    pub simplify_borders: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SimplifyReport {
+    #[serde(rename = "originalTriangles")]
+    pub original_triangles: usize,
+    #[serde(rename = "resultTriangles")]
+    pub result_triangles: usize,
+    #[serde(rename = "originalVertices")]
+    pub original_vertices: usize,
+    #[serde(rename = "resultVertices")]
+    pub result_vertices: usize,
+    /// Largest surface deviation of an applied collapse, in mesh units
+    #[serde(rename = "maxError")]
+    pub max_error: f64,
+}
+
+/// Indexed mesh after decimation; normals need recomputing
+#[derive(Debug, Clone)]
+pub struct SimplifiedMesh {
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub colors: Option<Vec<f32>>,
+}
+
+/// Symmetric 4x4 error quadric stored as its upper triangle
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane([a, b, c]: [f64; 3], d: f64) -> Self {
+        Quadric([
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ])
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = self.0;
+        for (s, o) in sum.iter_mut().zip(other.0) {
+            *s += o;
+        }
+        Quadric(sum)
+    }
+
+    /// Squared distance of `p` to the planes, never negative
+    fn error(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let q = &self.0;
+        let value = q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9];
+        value.max(0.0)
+    }
+
+    /// Point of least error, when the quadric is well conditioned
+    fn minimum(&self) -> Option<[f64; 3]> {
+        let q = &self.0;
+        let m = [[q[0], q[1], q[2]], [q[1], q[4], q[5]], [q[2], q[5], q[7]]];
+        let rhs = [-q[3], -q[6], -q[8]];
+        let det = |m: [[f64; 3]; 3]| {
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        };
+        let scale = q[0] + q[4] + q[7];
+        let d = det(m);
+        if !d.is_finite() || d.abs() <= 1e-9 * scale * scale * scale {
+            return None;
+        }
+        // Cramer's rule
+        let solve = |column: usize| {
+            let mut replaced = m;
+            for row in 0..3 {
+                replaced[row][column] = rhs[row];
+            }
+            det(replaced) / d
+        };
+        Some([solve(0), solve(1), solve(2)])
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(u: [f64; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ]
+}
+
+fn dot(u: [f64; 3], v: [f64; 3]) -> f64 {
+    u[0] * v[0] + u[1] * v[1] + u[2] * v[2]
+}
+
+/// Edge collapse candidate; the heap pops the lowest cost first
+struct Collapse {
+    cost: f64,
+    keep: u32,
+    remove: u32,
+    target: [f64; 3],
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| (other.keep, other.remove).cmp(&(self.keep, self.remove)))
+    }
+}
+
+struct Decimator {
+    positions: Vec<[f64; 3]>,
+    colors: Vec<f32>,
+    color_size: Option<usize>,
+    quadrics: Vec<Quadric>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    vertex_triangles: Vec<Vec<usize>>,
+    removed: Vec<bool>,
+    locked: Vec<bool>,
+    versions: Vec<u32>,
+}
+
+impl Decimator {
+    fn neighbors(&self, v: u32) -> HashSet<u32> {
+        self.vertex_triangles[v as usize]
+            .iter()
+            .filter(|&&t| self.alive[t])
+            .flat_map(|&t| self.triangles[t])
+            .filter(|&n| n != v)
+            .collect()
+    }
+
+    fn candidate(&self, a: u32, b: u32) -> Option<Collapse> {
+        let (locked_a, locked_b) = (self.locked[a as usize], self.locked[b as usize]);
+        if locked_a && locked_b {
+            return None;
+        }
+        // A locked vertex stays put and absorbs the other one
+        let (keep, remove) = if locked_b { (b, a) } else { (a, b) };
+        let quadric = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+        let target = if locked_a || locked_b {
+            self.positions[keep as usize]
+        } else {
+            let midpoint = [0, 1, 2].map(|i| (pa[i] + pb[i]) * 0.5);
+            quadric
+                .minimum()
+                // The optimum of a nearly flat patch can lie far away from the edge
+                .filter(|p| {
+                    dot(sub(*p, midpoint), sub(*p, midpoint)) <= dot(sub(pa, pb), sub(pa, pb))
+                })
+                .unwrap_or_else(|| {
+                    [pa, pb, midpoint]
+                        .into_iter()
+                        .min_by(|x, y| quadric.error(*x).total_cmp(&quadric.error(*y)))
+                        .unwrap()
+                })
+        };
+        Some(Collapse {
+            cost: quadric.error(target),
+            keep,
+            remove,
+            target,
+            versions: (self.versions[keep as usize], self.versions[remove as usize]),
+        })
+    }
+
+    /// Collapsing keeps the surface a manifold and no face flips over
+    fn can_collapse(&self, collapse: &Collapse) -> bool {
+        let (keep, remove) = (collapse.keep, collapse.remove);
+        let shared = self.vertex_triangles[remove as usize]
+            .iter()
+            .filter(|&&t| self.alive[t] && self.triangles[t].contains(&keep))
+            .count();
+        let common = self
+            .neighbors(keep)
+            .intersection(&self.neighbors(remove))
+            .count();
+        if shared == 0 || common != shared {
+            return false;
+        }
+
+        for &moved in &[keep, remove] {
+            for &t in &self.vertex_triangles[moved as usize] {
+                let triangle = self.triangles[t];
+                if !self.alive[t] || (triangle.contains(&keep) && triangle.contains(&remove)) {
+                    continue;
+                }
+                let corners = triangle.map(|v| self.positions[v as usize]);
+                let after = triangle.map(|v| {
+                    if v == moved {
+                        collapse.target
+                    } else {
+                        self.positions[v as usize]
+                    }
+                });
+                let normal_before = cross(sub(corners[1], corners[0]), sub(corners[2], corners[0]));
+                let normal_after = cross(sub(after[1], after[0]), sub(after[2], after[0]));
+                if dot(normal_before, normal_after) <= 0.0 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Merge `remove` into `keep`; returns how many triangles disappeared
+    fn collapse(&mut self, collapse: &Collapse) -> usize {
+        let (keep, remove) = (collapse.keep as usize, collapse.remove as usize);
+        self.positions[keep] = collapse.target;
+        self.quadrics[keep] = self.quadrics[keep].add(&self.quadrics[remove]);
+        if let Some(size) = self.color_size {
+            for channel in 0..size {
+                self.colors[keep * size + channel] = (self.colors[keep * size + channel]
+                    + self.colors[remove * size + channel])
+                    * 0.5;
+            }
+        }
+
+        let mut dropped = 0;
+        for t in std::mem::take(&mut self.vertex_triangles[remove]) {
+            if !self.alive[t] {
+                continue;
+            }
+            if self.triangles[t].contains(&collapse.keep) {
+                self.alive[t] = false;
+                dropped += 1;
+            } else {
+                for v in self.triangles[t].iter_mut() {
+                    if *v == collapse.remove {
+                        *v = collapse.keep;
+                    }
+                }
+                self.vertex_triangles[keep].push(t);
+            }
+        }
+        let alive = &self.alive;
+        self.vertex_triangles[keep].retain(|&t| alive[t]);
+        self.removed[remove] = true;
+        self.versions[keep] += 1;
+        dropped
+    }
+}
+
+/// Decimate a mesh; non-indexed when `indices` is None. `colors` are optional per-vertex
+/// RGB(A) values, averaged along collapsed edges.
+pub(crate) fn simplify_mesh(
+    vertices: &[f32],
+    indices: Option<&[u32]>,
+    colors: Option<&[f32]>,
+    options: &SimplifyOptions,
+) -> Result<(SimplifiedMesh, SimplifyReport), String> {
+    let vertex_count = vertices.len() / 3;
+    let indices: Vec<u32> = match indices {
+        Some(indices) => indices.to_vec(),
+        None => (0..(vertex_count - vertex_count % 3) as u32).collect(),
+    };
+    if let Some(bad) = indices.iter().find(|i| **i as usize >= vertex_count) {
+        return Err(format!(
+            "Index {} out of range for {} vertices",
+            bad, vertex_count
+        ));
+    }
+    let ratio = match (options.target_ratio, options.max_error) {
+        (Some(ratio), _) => ratio,
+        (None, Some(_)) => 0.0,
+        (None, None) => DEFAULT_TARGET_RATIO,
+    };
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(format!(
+            "targetRatio must be between 0 and 1, got {}",
+            ratio
+        ));
+    }
+    let max_cost = options
+        .max_error
+        .filter(|e| e.is_finite() && *e >= 0.0)
+        .map(|e| e * e)
+        .unwrap_or(f64::INFINITY);
+
+    let WeldedVertices {
+        positions,
+        colors,
+        color_size,
+        remap,
+    } = weld_vertices(vertices, colors, WELD_TOLERANCE);
+    let triangles: Vec<[u32; 3]> = indices
+        .chunks_exact(3)
+        .map(|t| {
+            [
+                remap[t[0] as usize],
+                remap[t[1] as usize],
+                remap[t[2] as usize],
+            ]
+        })
+        .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+        .collect();
+    let mut report = SimplifyReport {
+        original_triangles: indices.len() / 3,
+        original_vertices: vertex_count,
+        ..Default::default()
+    };
+
+    // Plane quadrics per vertex; edges with one or more than two faces lock their ends
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    let mut vertex_triangles = vec![Vec::new(); positions.len()];
+    let mut edge_uses: HashMap<(u32, u32), usize> = HashMap::new();
+    for (index, t) in triangles.iter().enumerate() {
+        let [a, b, c] = t.map(|v| positions[v as usize]);
+        let normal = cross(sub(b, a), sub(c, a));
+        let length = dot(normal, normal).sqrt();
+        if length > 0.0 {
+            let unit = normal.map(|n| n / length);
+            let plane = Quadric::plane(unit, -dot(unit, a));
+            for &v in t {
+                quadrics[v as usize] = quadrics[v as usize].add(&plane);
+            }
+        }
+        for (i, &v) in t.iter().enumerate() {
+            vertex_triangles[v as usize].push(index);
+            let w = t[(i + 1) % 3];
+            *edge_uses.entry((v.min(w), v.max(w))).or_insert(0) += 1;
+        }
+    }
+    let mut locked = vec![false; positions.len()];
+    if !options.simplify_borders {
+        for (&(a, b), &uses) in &edge_uses {
+            if uses != 2 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+    }
+
+    let mut decimator = Decimator {
+        alive: vec![true; triangles.len()],
+        removed: vec![false; positions.len()],
+        versions: vec![0; positions.len()],
+        positions,
+        colors,
+        color_size,
+        quadrics,
+        triangles,
+        vertex_triangles,
+        locked,
+    };
+    let mut edges: Vec<(u32, u32)> = edge_uses.into_keys().collect();
+    edges.sort_unstable();
+    let mut heap: BinaryHeap<Collapse> = edges
+        .into_iter()
+        .filter_map(|(a, b)| decimator.candidate(a, b))
+        .collect();
+
+    let target = (decimator.triangles.len() as f64 * ratio).ceil() as usize;
+    let mut live = decimator.triangles.len();
+    while live > target {
+        let Some(collapse) = heap.pop() else {
+            break;
+        };
+        let (keep, remove) = (collapse.keep as usize, collapse.remove as usize);
+        if decimator.removed[keep]
+            || decimator.removed[remove]
+            || collapse.versions != (decimator.versions[keep], decimator.versions[remove])
+        {
+            continue;
+        }
+        if collapse.cost > max_cost {
+            break;
+        }
+        if !decimator.can_collapse(&collapse) {
+            continue;
+        }
+        live -= decimator.collapse(&collapse);
+        report.max_error = report.max_error.max(collapse.cost.sqrt());
+        let mut neighbors: Vec<u32> = decimator.neighbors(collapse.keep).into_iter().collect();
+        neighbors.sort_unstable();
+        for neighbor in neighbors {
+            if let Some(next) = decimator.candidate(collapse.keep, neighbor) {
+                heap.push(next);
+            }
+        }
+    }
+
+    // Compact the surviving vertices
+    let mut new_index = vec![u32::MAX; decimator.positions.len()];
+    let mut mesh = SimplifiedMesh {
+        vertices: Vec::new(),
+        indices: Vec::with_capacity(live * 3),
+        colors: color_size.map(|_| Vec::new()),
+    };
+    for (t, triangle) in decimator.triangles.iter().enumerate() {
+        if !decimator.alive[t] {
+            continue;
+        }
+        for &v in triangle {
+            let v = v as usize;
+            if new_index[v] == u32::MAX {
+                new_index[v] = (mesh.vertices.len() / 3) as u32;
+                mesh.vertices
+                    .extend(decimator.positions[v].map(|c| c as f32));
+                if let (Some(size), Some(colors)) = (color_size, mesh.colors.as_mut()) {
+                    colors.extend_from_slice(&decimator.colors[v * size..(v + 1) * size]);
+                }
+            }
+            mesh.indices.push(new_index[v]);
+        }
+    }
+    report.result_triangles = mesh.indices.len() / 3;
+    report.result_vertices = mesh.vertices.len() / 3;
+    Ok((mesh, report))
+}
+
+/// Reduce the triangle count of a BufferGeometry (as JSON, shaped like
+/// `process_polygon_geometry` output). `options_json` is `{ targetRatio?, maxError?,
+/// simplifyBorders? }`: collapse edges until `targetRatio` of the triangles remain (0.5 by
+/// default) or the next collapse would move the surface by more than `maxError` mesh units.
+/// Returns `{ geometry, report }` with an indexed geometry without normals.
+#[wasm_bindgen]
+pub fn simplify_geometry(
+    geometry_json: &str,
+    options_json: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let geometry: BufferGeometry = serde_json::from_str(geometry_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse geometry: {}", e)))?;
+    let options: SimplifyOptions = match options_json.as_deref() {
+        Some(json) if !json.trim().is_empty() => serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse options: {}", e)))?,
+        _ => SimplifyOptions::default(),
+    };
+    let (simplified, report) = simplify_mesh(
+        &geometry.vertices,
+        geometry.indices.as_deref(),
+        geometry.colors.as_deref(),
+        &options,
+    )
+    .map_err(|e| JsValue::from_str(&format!("Failed to simplify geometry: {}", e)))?;
+
+    let has_data = !simplified.indices.is_empty();
+    let output = BufferGeometry {
+        vertices: simplified.vertices,
+        normals: None,
+        colors: simplified.colors,
+        indices: Some(simplified.indices),
+        uvs: None,
+        has_data,
+        properties: geometry.properties,
+    };
+    let geometries = crate::geometries_to_js(&[output]);
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &result,
+        &"geometry".into(),
+        &js_sys::Reflect::get(&geometries, &0.into())?,
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &"report".into(),
+        &serde_wasm_bindgen::to_value(&report)?,
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_manifold::analyze_manifold;
+
+    /// Closed box from a `n` x `n` grid on the top face down to a flat bottom
+    fn gridded_box(n: usize) -> (Vec<f32>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        for y in 0..=n {
+            for x in 0..=n {
+                vertices.extend([x as f32, y as f32, 1.0]);
+            }
+        }
+        let mut indices = Vec::new();
+        let at = |x: usize, y: usize| (y * (n + 1) + x) as u32;
+        for y in 0..n {
+            for x in 0..n {
+                indices.extend([at(x, y), at(x + 1, y), at(x + 1, y + 1)]);
+                indices.extend([at(x, y), at(x + 1, y + 1), at(x, y + 1)]);
+            }
+        }
+        // Walls down to a bottom centre vertex keep the solid closed
+        let bottom = (vertices.len() / 3) as u32;
+        vertices.extend([n as f32 / 2.0, n as f32 / 2.0, 0.0]);
+        let ring: Vec<u32> = (0..n)
+            .map(|x| at(x, 0))
+            .chain((0..n).map(|y| at(n, y)))
+            .chain((0..n).map(|x| at(n - x, n)))
+            .chain((0..n).map(|y| at(0, n - y)))
+            .collect();
+        for (i, &from) in ring.iter().enumerate() {
+            indices.extend([ring[(i + 1) % ring.len()], from, bottom]);
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn test_flat_grid_decimates_without_error() {
+        let (vertices, indices) = gridded_box(8);
+        let before = analyze_manifold(&vertices, Some(&indices), None).unwrap();
+        assert!(before.is_manifold, "{:?}", before);
+
+        let options = SimplifyOptions {
+            target_ratio: Some(0.3),
+            ..Default::default()
+        };
+        let (mesh, report) = simplify_mesh(&vertices, Some(&indices), None, &options).unwrap();
+        assert!(report.result_triangles < report.original_triangles / 2);
+        assert!(report.max_error < 1e-6, "{}", report.max_error);
+        let after = analyze_manifold(&mesh.vertices, Some(&mesh.indices), None).unwrap();
+        assert!(after.is_manifold, "{:?}", after);
+        assert!((after.signed_volume - before.signed_volume).abs() < 1e-6);
+
+        // An error budget alone still allows the coplanar collapses
+        let options = SimplifyOptions {
+            max_error: Some(1e-6),
+            ..Default::default()
+        };
+        let (_, report) = simplify_mesh(&vertices, Some(&indices), None, &options).unwrap();
+        assert!(report.max_error < 1e-6);
+        assert!(report.result_triangles < report.original_triangles);
+    }
+}
@@ -0,0 +1,144 @@
+// Real-world scale output: a process generated at a model scale 1:N has its mesh units in
+// millimeters. The terrain spans the bbox at that scale, building heights in meters shrink
+// by the same factor and the terrain relief is the true relief times the vertical
+// exaggeration, so exported meshes print at the chosen scale without resizing.
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::projection::{store_terrain_scale, TerrainScale};
+use crate::vertical_datum::bbox_size_meters;
+
+/// Model scale of a process, given either as the scale or as the printed base width
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelScaleOptions {
+    /// [minLng, minLat, maxLng, maxLat] of the model
+    pub bbox: Vec<f64>,
+    /// Denominator N of the scale 1:N, e.g. 10000
+    #[serde(default, rename = "modelScale")]
+    pub model_scale: Option<f64>,
+    /// Printed side length of the square base in millimeters
+    #[serde(default, rename = "baseWidthMm")]
+    pub base_width_mm: Option<f64>,
+}
+
+impl ModelScaleOptions {
+    /// Terrain scale with mesh units in millimeters
+    pub(crate) fn terrain_scale(&self) -> Result<TerrainScale, String> {
+        if self.bbox.len() != 4 || !(self.bbox[0] < self.bbox[2] && self.bbox[1] < self.bbox[3]) {
+            return Err("Invalid bbox: must contain [minLng, minLat, maxLng, maxLat]".to_string());
+        }
+        // The square terrain spans the average side of the bbox, like building heights
+        let real_size_mm = bbox_size_meters(&self.bbox) * 1000.0;
+        let positive = |v: f64| v.is_finite() && v > 0.0;
+        let (size, scale) = match (self.model_scale, self.base_width_mm) {
+            (Some(_), Some(_)) => {
+                return Err("Pass either modelScale or baseWidthMm, not both".to_string())
+            }
+            (Some(scale), None) if positive(scale) => (real_size_mm / scale, scale),
+            (None, Some(width)) if positive(width) => (width, real_size_mm / width),
+            _ => return Err("modelScale or baseWidthMm must be a positive number".to_string()),
+        };
+        Ok(TerrainScale {
+            size,
+            model_scale: Some(scale),
+        })
+    }
+}
+
+/// Generate the terrain and layers of `process_id` at a real-world model scale, with mesh
+/// units in millimeters. `options` is `{ bbox, modelScale }` for a scale of 1:modelScale,
+/// or `{ bbox, baseWidthMm }` for the scale at which the base prints that wide. Terrain
+/// relief becomes the true relief times the vertical exaggeration, building heights are
+/// true to scale and exports are in millimeters without a `modelSizeMm`.
+/// Returns `{ terrainSize, modelScale }`; `set_terrain_size` returns the process to the
+/// abstract terrain.
+#[wasm_bindgen]
+pub fn set_model_scale(process_id: &str, options: JsValue) -> Result<JsValue, JsValue> {
+    let options: ModelScaleOptions = serde_wasm_bindgen::from_value(options)?;
+    let scale = options.terrain_scale().map_err(|e| JsValue::from_str(&e))?;
+    store_terrain_scale(process_id, scale);
+
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("terrainSize"),
+        &JsValue::from_f64(scale.size),
+    )?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("modelScale"),
+        &JsValue::from_f64(scale.model_scale.unwrap_or_default()),
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::set_active_scale;
+    use crate::vertical_datum::{meters_to_terrain_units, VerticalDatum};
+
+    fn options(model_scale: Option<f64>, base_width_mm: Option<f64>) -> ModelScaleOptions {
+        ModelScaleOptions {
+            bbox: vec![8.0, 47.0, 8.02, 47.01],
+            model_scale,
+            base_width_mm,
+        }
+    }
+
+    #[test]
+    fn test_scale_and_base_width_agree() {
+        let by_scale = options(Some(10_000.0), None).terrain_scale().unwrap();
+        let by_width = options(None, Some(by_scale.size)).terrain_scale().unwrap();
+        assert!((by_width.model_scale.unwrap() - 10_000.0).abs() < 1e-6);
+        // ~1.3km on average at 1:10000 prints at ~130mm
+        assert!((100.0..160.0).contains(&by_scale.size));
+
+        assert!(options(Some(10_000.0), Some(100.0))
+            .terrain_scale()
+            .is_err());
+        assert!(options(Some(0.0), None).terrain_scale().is_err());
+        assert!(options(None, None).terrain_scale().is_err());
+    }
+
+    #[test]
+    fn test_heights_in_real_millimeters() {
+        let model = options(Some(10_000.0), None);
+        let scale = model.terrain_scale().unwrap();
+        set_active_scale(scale);
+
+        // 500m of relief is 50mm at 1:10000, doubled by the exaggeration
+        let datum = VerticalDatum::new(2.0, 2.0, 100.0, 600.0, scale.model_scale);
+        assert!((datum.relief_height() - 100.0).abs() < 1e-9);
+        // A 10m building is 1mm tall
+        assert!((meters_to_terrain_units(&model.bbox) * 10.0 - 1.0).abs() < 1e-9);
+
+        set_active_scale(TerrainScale::DEFAULT);
+        assert_eq!(
+            VerticalDatum::new(2.0, 2.0, 100.0, 600.0, None).relief_height(),
+            10.0
+        );
+    }
+
+    #[test]
+    fn test_new_scale_drops_cached_layers() {
+        let cache = |model_scale: f64| {
+            crate::layer_cache::store_layer_geometry(
+                "model-scale-test",
+                "buildings",
+                String::new(),
+                true,
+                Vec::new(),
+            );
+            let scale = options(Some(model_scale), None).terrain_scale().unwrap();
+            store_terrain_scale("model-scale-test", scale);
+            crate::layer_cache::get_cached_layers("model-scale-test")
+        };
+        assert!(cache(10_000.0).is_empty());
+        assert_eq!(cache(10_000.0).len(), 1);
+        assert!(cache(25_000.0).is_empty());
+        crate::module_state::ModuleState::with_mut(|state| {
+            state.clear_process_data("model-scale-test")
+        });
+    }
+}
@@ -10,6 +10,10 @@ pub struct CacheStats {
     pub max_vector_tiles: usize,
     pub total_requests: usize,
     pub hit_rate: f64,
+    // Approximate bytes held by tile and elevation caches, see cache_budget
+    pub memory_bytes: usize,
+    pub memory_limit_bytes: Option<usize>,
+    pub evictions: usize,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -7,11 +7,12 @@ use wasm_bindgen::prelude::*;
 // Removed JsValue import: storing JSON strings instead
 
 // We need JsValue for caching objects
+use crate::cache_budget::{self, CacheKind, CacheUsage};
+use crate::cache_keys;
+use crate::clip_shape::ClipShape;
+use crate::projection::TerrainScale;
 use crate::vectortile::ParsedMvtTile;
 
-// Import the console_log macro
-#[allow(unused_imports)]
-
 // Cache size limit
 #[allow(dead_code)]
 pub const CACHE_SIZE_LIMIT: usize = 100;
@@ -50,6 +51,7 @@ pub struct VectorTileData {
 
 // Elevation data for a bbox
 #[derive(Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
 pub struct ElevationData {
     pub bbox_key: String,
     pub elevation_grid: Vec<Vec<f64>>,
@@ -60,8 +62,25 @@ pub struct ElevationData {
     pub timestamp: f64,
 }
 
+// Geographic extent and value range of a cached elevation grid
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ElevationExtent {
+    // [minLng, minLat, maxLng, maxLat]; grid row 0 is the southern edge
+    pub bbox: [f64; 4],
+    pub min_elevation: f64,
+    pub max_elevation: f64,
+}
+
+// HTTP validators of a cached tile response, used for conditional revalidation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TileValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
 // Feature data for a layer
 #[derive(Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
 pub struct FeatureData {
     pub source_layer: String,
     pub bbox_key: String,
@@ -69,6 +88,13 @@ pub struct FeatureData {
     pub timestamp: f64,
 }
 
+// Terrain mesh of a process, sampled by its layers instead of a mesh sent per layer
+#[allow(dead_code)]
+pub struct TerrainMesh {
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
 // Module state to keep cached resources
 pub struct ModuleState {
     // Cache for raster DEM tiles
@@ -80,6 +106,12 @@ pub struct ModuleState {
     // Cache for processed data like elevation grids
     pub elevation_grids: HashMap<String, Vec<Vec<f64>>>,
 
+    // Extent of each cached elevation grid, keyed like elevation_grids
+    pub elevation_extents: HashMap<String, ElevationExtent>,
+
+    // Key of the most recently stored elevation grid
+    pub latest_elevation_key: Option<String>,
+
     // Process-based cache for vector tile data: process_id -> tiles
     pub process_vector_tiles: HashMap<String, Vec<TileData>>,
 
@@ -89,6 +121,58 @@ pub struct ModuleState {
     // Process-based cache for extracted feature data: process_id -> data_key -> JSON string
     pub process_feature_data: HashMap<String, HashMap<String, String>>,
 
+    // Picking BVHs of generated layer geometry: process_id -> layer label -> index
+    pub pick_indices: HashMap<String, HashMap<String, crate::picking::LayerPickIndex>>,
+
+    // Bounding volumes of generated output: process_id -> layer label (or "terrain") -> volume
+    pub model_bounds: HashMap<String, HashMap<String, crate::bounds::BoundingVolume>>,
+
+    // Finished layer geometry: process_id -> layer label -> geometry and its config hash
+    pub layer_geometries: HashMap<String, HashMap<String, crate::layer_cache::CachedLayerGeometry>>,
+
+    // Rendered terrain mesh of each process, keyed by process_id
+    pub terrain_meshes: HashMap<String, TerrainMesh>,
+
+    // Counts changes of the elevation grid or terrain mesh of each process, keyed by
+    // process_id; identifies the terrain layers were generated on
+    pub terrain_generations: HashMap<String, u64>,
+
+    // Triangle index of the terrain mesh each process samples, keyed by process_id
+    pub terrain_mesh_indexes: HashMap<String, std::sync::Arc<crate::terrain_index::TerrainIndex>>,
+
+    // ETag/Last-Modified of fetched tiles, keyed by tile URL
+    pub tile_validators: HashMap<String, TileValidators>,
+
+    // Download time of fetched tiles, keyed by tile URL
+    pub tile_retrievals: HashMap<String, f64>,
+
+    // Tiles and attribution each process was built from
+    pub process_provenance: HashMap<String, crate::provenance::Provenance>,
+
+    // Manifest of the generated layers and model scale, keyed by process_id
+    pub model_manifests: HashMap<String, crate::manifest::ModelManifest>,
+
+    // Terrain size and model scale of processes not using the default, keyed by process_id
+    pub terrain_scales: HashMap<String, TerrainScale>,
+
+    // Shapes the terrain and layers of a process are clipped to, keyed by process_id
+    pub clip_shapes: HashMap<String, ClipShape>,
+
+    // TileJSON metadata replacing the built-in tile URLs, keyed by source ("raster"/"vector")
+    pub tile_sources: HashMap<String, crate::tilejson::TileJson>,
+
+    // Request headers and query parameters of configured tile sources, keyed like tile_sources
+    pub tile_source_requests: HashMap<String, crate::tilejson::SourceRequest>,
+
+    // MBTiles archives serving tiles in place of the network, keyed like tile_sources
+    pub mbtiles_archives: HashMap<String, crate::mbtiles::MbTilesArchive>,
+
+    // Forbid network fetches; tiles and elevation grids must be injected (kiosk/offline)
+    pub offline_mode: bool,
+
+    // Byte sizes and recency of tile and elevation cache entries, for the memory budget
+    pub cache_usage: CacheUsage,
+
     // Configuration for cache limits
     pub max_raster_tiles: usize,
     pub max_vector_tiles: usize,
@@ -110,9 +194,28 @@ impl ModuleState {
             raster_tiles: HashMap::new(),
             vector_tiles: HashMap::new(),
             elevation_grids: HashMap::new(),
+            elevation_extents: HashMap::new(),
+            latest_elevation_key: None,
             process_vector_tiles: HashMap::new(),
             mvt_parsed_tiles: HashMap::new(),
             process_feature_data: HashMap::new(),
+            pick_indices: HashMap::new(),
+            model_bounds: HashMap::new(),
+            layer_geometries: HashMap::new(),
+            terrain_meshes: HashMap::new(),
+            terrain_generations: HashMap::new(),
+            terrain_mesh_indexes: HashMap::new(),
+            tile_validators: HashMap::new(),
+            tile_retrievals: HashMap::new(),
+            process_provenance: HashMap::new(),
+            model_manifests: HashMap::new(),
+            terrain_scales: HashMap::new(),
+            clip_shapes: HashMap::new(),
+            tile_sources: HashMap::new(),
+            tile_source_requests: HashMap::new(),
+            mbtiles_archives: HashMap::new(),
+            offline_mode: false,
+            cache_usage: CacheUsage::default(),
             max_raster_tiles: 100,
             max_vector_tiles: 50,
             cache_hits: 0,
@@ -151,16 +254,27 @@ impl ModuleState {
 
             if let Some(oldest) = oldest_key {
                 self.raster_tiles.remove(&oldest);
+                self.cache_usage
+                    .forget(CacheKind::RasterTile, &raster_usage_key(&oldest));
             }
         }
 
+        let usage_key = raster_usage_key(&key);
+        self.cache_usage.record(
+            CacheKind::RasterTile,
+            &usage_key,
+            cache_budget::tile_bytes(&data),
+        );
         self.raster_tiles.insert(key, data);
+        self.enforce_memory_budget(Some((CacheKind::RasterTile, &usage_key)));
     }
 
     // Get a raster tile from the cache
     pub fn get_raster_tile(&mut self, key: &TileKey) -> Option<&TileData> {
         if self.raster_tiles.contains_key(key) {
             self.cache_hits += 1;
+            self.cache_usage
+                .touch(CacheKind::RasterTile, &raster_usage_key(key));
             self.raster_tiles.get(key)
         } else {
             self.cache_misses += 1;
@@ -196,26 +310,75 @@ impl ModuleState {
 
     // Store a processed elevation grid
     pub fn store_elevation_grid(&mut self, key: String, grid: Vec<Vec<f64>>) {
-        self.elevation_grids.insert(key, grid);
+        self.cache_usage.record(
+            CacheKind::ElevationGrid,
+            &key,
+            cache_budget::elevation_grid_bytes(&grid),
+        );
+        self.elevation_grids.insert(key.clone(), grid);
+        self.bump_terrain_generation(&key);
+        self.enforce_memory_budget(Some((CacheKind::ElevationGrid, &key)));
+    }
+
+    // Store a processed elevation grid together with its extent
+    pub fn store_elevation_grid_with_extent(
+        &mut self,
+        key: String,
+        grid: Vec<Vec<f64>>,
+        extent: ElevationExtent,
+    ) {
+        self.elevation_extents.insert(key.clone(), extent);
+        self.latest_elevation_key = Some(key.clone());
+        self.store_elevation_grid(key, grid);
+    }
+
+    // Get a processed elevation grid and its extent; falls back to the latest grid without a key
+    pub fn get_elevation_grid_with_extent(
+        &self,
+        key: Option<&str>,
+    ) -> Option<(&Vec<Vec<f64>>, &ElevationExtent)> {
+        let key = key.or(self.latest_elevation_key.as_deref())?;
+        self.cache_usage.touch(CacheKind::ElevationGrid, key);
+        Some((self.elevation_grids.get(key)?, self.elevation_extents.get(key)?))
+    }
+
+    // Record a change of the terrain of a process (elevation grid or terrain mesh)
+    pub fn bump_terrain_generation(&mut self, process_id: &str) {
+        *self
+            .terrain_generations
+            .entry(process_id.to_string())
+            .or_default() += 1;
+    }
+
+    // Generation of the terrain of a process, 0 before any was stored
+    pub fn terrain_generation(&self, process_id: &str) -> u64 {
+        self.terrain_generations
+            .get(process_id)
+            .copied()
+            .unwrap_or_default()
     }
 
     // Get a processed elevation grid
     pub fn get_elevation_grid(&self, key: &str) -> Option<&Vec<Vec<f64>>> {
+        self.cache_usage.touch(CacheKind::ElevationGrid, key);
         self.elevation_grids.get(key)
     }
 
     // Get a cached parsed vector tile by cache key
     pub fn get_parsed_mvt_tile(&self, key: &str) -> Option<ParsedMvtTile> {
-        if let Some(tile) = self.mvt_parsed_tiles.get(key) {
-            Some(tile.clone())
-        } else {
-            None
-        }
+        self.cache_usage.touch(CacheKind::ParsedMvt, key);
+        self.mvt_parsed_tiles.get(key).cloned()
     }
 
     // Store a parsed vector tile in cache by cache key
     pub fn set_parsed_mvt_tile(&mut self, key: &str, tile: ParsedMvtTile) {
+        self.cache_usage.record(
+            CacheKind::ParsedMvt,
+            key,
+            cache_budget::parsed_mvt_bytes(&tile),
+        );
         self.mvt_parsed_tiles.insert(key.to_string(), tile);
+        self.enforce_memory_budget(Some((CacheKind::ParsedMvt, key)));
     }
 
     // Store fetched vector tiles under bbox_key
@@ -245,13 +408,15 @@ impl ModuleState {
             tile_list.push(tile_data);
         }
         // Legacy method - storing in process cache instead
-        self.process_vector_tiles
-            .insert(bbox_key.to_string(), tile_list);
+        self.store_process_vector_tiles(&cache_keys::normalize_bbox_key(bbox_key), tile_list);
     }
 
     // Retrieve cached vector tiles by bbox_key
     pub fn get_vector_tiles(&self, bbox_key: &str) -> Option<&Vec<TileData>> {
-        if let Some(tiles) = self.process_vector_tiles.get(bbox_key) {
+        if let Some(tiles) = self
+            .process_vector_tiles
+            .get(&cache_keys::normalize_bbox_key(bbox_key))
+        {
             Some(tiles)
         } else {
             None
@@ -326,12 +491,19 @@ impl ModuleState {
 
     /// Store vector tiles for a specific process
     pub fn store_process_vector_tiles(&mut self, process_id: &str, tiles: Vec<TileData>) {
+        self.cache_usage.record(
+            CacheKind::VectorTiles,
+            process_id,
+            tiles.iter().map(cache_budget::tile_bytes).sum(),
+        );
         self.process_vector_tiles
             .insert(process_id.to_string(), tiles);
+        self.enforce_memory_budget(Some((CacheKind::VectorTiles, process_id)));
     }
 
     /// Retrieve vector tiles for a specific process
     pub fn get_process_vector_tiles(&self, process_id: &str) -> Option<&Vec<TileData>> {
+        self.cache_usage.touch(CacheKind::VectorTiles, process_id);
         self.process_vector_tiles.get(process_id)
     }
 
@@ -340,7 +512,7 @@ impl ModuleState {
         let entry = self
             .process_feature_data
             .entry(process_id.to_string())
-            .or_insert_with(HashMap::new);
+            .or_default();
         entry.insert(data_key.to_string(), json);
     }
 
@@ -355,7 +527,18 @@ impl ModuleState {
     /// Clear all data for a specific process
     pub fn clear_process_data(&mut self, process_id: &str) {
         self.process_vector_tiles.remove(process_id);
+        self.cache_usage.forget(CacheKind::VectorTiles, process_id);
         self.process_feature_data.remove(process_id);
+        self.pick_indices.remove(process_id);
+        self.model_bounds.remove(process_id);
+        self.layer_geometries.remove(process_id);
+        self.terrain_meshes.remove(process_id);
+        self.terrain_generations.remove(process_id);
+        self.terrain_mesh_indexes.remove(process_id);
+        self.process_provenance.remove(process_id);
+        self.model_manifests.remove(process_id);
+        self.terrain_scales.remove(process_id);
+        self.clip_shapes.remove(process_id);
     }
 
     /// Get list of cached process IDs
@@ -374,21 +557,21 @@ impl ModuleState {
     #[allow(dead_code)]
     pub fn add_feature_data(&mut self, bbox_key: &str, inner_key: &str, json: String) {
         // Redirect to process-based caching
-        self.add_process_feature_data(bbox_key, inner_key, json);
+        self.add_process_feature_data(&cache_keys::normalize_bbox_key(bbox_key), inner_key, json);
     }
 
     /// Retrieve stored feature data by bbox_key and inner_key
     #[allow(dead_code)]
     pub fn get_feature_data(&self, bbox_key: &str, inner_key: &str) -> Option<JsValue> {
         // Redirect to process-based caching
-        self.get_process_feature_data(bbox_key, inner_key)
+        self.get_process_feature_data(&cache_keys::normalize_bbox_key(bbox_key), inner_key)
     }
 
     /// Clear all feature data entries for a given bbox_key
     #[allow(dead_code)]
     pub fn clear_feature_data_for_bbox(&mut self, bbox_key: &str) {
         // Redirect to process-based clearing
-        self.clear_process_data(bbox_key);
+        self.clear_process_data(&cache_keys::normalize_bbox_key(bbox_key));
     }
 
     // Get cache statistics
@@ -408,13 +591,68 @@ impl ModuleState {
         self.raster_tiles.clear();
         self.vector_tiles.clear();
         self.elevation_grids.clear();
+        self.elevation_extents.clear();
+        self.latest_elevation_key = None;
         self.process_vector_tiles.clear();
         self.mvt_parsed_tiles.clear();
         self.process_feature_data.clear();
+        self.pick_indices.clear();
+        self.model_bounds.clear();
+        self.layer_geometries.clear();
+        self.terrain_meshes.clear();
+        self.terrain_generations.clear();
+        self.terrain_mesh_indexes.clear();
+        self.tile_validators.clear();
+        self.tile_retrievals.clear();
+        self.process_provenance.clear();
+        self.model_manifests.clear();
+        self.terrain_scales.clear();
+        self.clip_shapes.clear();
+        self.cache_usage.clear();
         // Reset stats
         self.cache_hits = 0;
         self.cache_misses = 0;
     }
+
+    /// Evict least recently used tile and elevation entries until the accounted memory
+    /// fits the budget, never `keep` (the entry just stored). Returns the eviction count.
+    pub fn enforce_memory_budget(&mut self, keep: Option<(CacheKind, &str)>) -> usize {
+        let mut evicted = 0;
+        while let Some((kind, key)) = self.cache_usage.next_eviction(keep) {
+            self.remove_cache_entry(kind, &key);
+            evicted += 1;
+        }
+        self.cache_usage.evictions += evicted;
+        evicted
+    }
+
+    /// Remove an accounted entry from its cache; returns whether it was cached
+    pub fn remove_cache_entry(&mut self, kind: CacheKind, key: &str) -> bool {
+        let removed = match kind {
+            CacheKind::RasterTile => {
+                let before = self.raster_tiles.len();
+                self.raster_tiles
+                    .retain(|tile_key, _| raster_usage_key(tile_key) != key);
+                self.raster_tiles.len() < before
+            }
+            CacheKind::VectorTiles => self.process_vector_tiles.remove(key).is_some(),
+            CacheKind::ParsedMvt => self.mvt_parsed_tiles.remove(key).is_some(),
+            CacheKind::ElevationGrid => {
+                self.elevation_extents.remove(key);
+                if self.latest_elevation_key.as_deref() == Some(key) {
+                    self.latest_elevation_key = None;
+                }
+                self.elevation_grids.remove(key).is_some()
+            }
+        };
+        self.cache_usage.forget(kind, key);
+        removed
+    }
+}
+
+// Key of a raster tile in the cache accounting
+fn raster_usage_key(key: &TileKey) -> String {
+    format!("{}/{}/{}", key.z, key.x, key.y)
 }
 
 // Wrapper functions to interact with the module state from wasm-bindgen exports
@@ -0,0 +1,201 @@
+// Offline operation: tiles and elevation grids are injected straight into the caches,
+// and an offline flag makes every network fetch fail instead of reaching a server.
+use js_sys::Date;
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::{create_tile_key, ElevationExtent, ModuleState, TileData};
+use crate::vectortile::{decompress_gzip, enhanced_parse_mvt_data, TileRequest};
+
+// Deepest zoom level accepted for injected tiles
+const MAX_TILE_ZOOM: u32 = 24;
+
+/// Forbid (or allow again) all network fetches
+#[wasm_bindgen]
+pub fn set_offline_mode(enabled: bool) {
+    ModuleState::with_mut(|state| state.offline_mode = enabled);
+}
+
+#[wasm_bindgen]
+pub fn is_offline_mode() -> bool {
+    ModuleState::with(|state| state.offline_mode)
+}
+
+fn validate_tile_coords(z: u32, x: u32, y: u32) -> Result<(), String> {
+    if z > MAX_TILE_ZOOM {
+        return Err(format!(
+            "Zoom {} exceeds the maximum of {}",
+            z, MAX_TILE_ZOOM
+        ));
+    }
+    let tiles_per_axis = 1u64 << z;
+    if x as u64 >= tiles_per_axis || y as u64 >= tiles_per_axis {
+        return Err(format!("Tile {}/{}/{} is outside the zoom level", z, x, y));
+    }
+    Ok(())
+}
+
+/// Inject a decoded elevation raster tile (RGBA pixels in Terrain-RGB encoding)
+#[wasm_bindgen]
+pub fn inject_raster_tile(
+    z: u32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> Result<(), JsValue> {
+    validate_tile_coords(z, x, y).map_err(|e| JsValue::from_str(&e))?;
+    let expected = width as usize * height as usize * 4;
+    if width == 0 || height == 0 || rgba.len() != expected {
+        return Err(JsValue::from_str(&format!(
+            "Raster tile {}/{}/{}: expected {}x{} RGBA ({} bytes), got {} bytes",
+            z,
+            x,
+            y,
+            width,
+            height,
+            expected,
+            rgba.len()
+        )));
+    }
+
+    cache_raster_pixels(x, y, z, width, height, rgba.to_vec());
+    Ok(())
+}
+
+/// Store decoded raster pixels in the raster tile cache
+pub(crate) fn cache_raster_pixels(
+    x: u32,
+    y: u32,
+    z: u32,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+) -> TileData {
+    let tile_data = TileData {
+        width,
+        height,
+        x,
+        y,
+        z,
+        data: rgba.clone(),
+        timestamp: Date::now(),
+        key: format!("{}/{}/{}", z, x, y),
+        buffer: rgba,
+        parsed_layers: None,
+        rust_parsed_mvt: None,
+    };
+    ModuleState::with_mut(|state| {
+        state.add_raster_tile(create_tile_key(x, y, z), tile_data.clone());
+    });
+    tile_data
+}
+
+/// Inject a raw (optionally gzipped) MVT tile; returns the number of layers it contains
+#[wasm_bindgen]
+pub fn inject_vector_tile(z: u32, x: u32, y: u32, data: &[u8]) -> Result<usize, JsValue> {
+    validate_tile_coords(z, x, y).map_err(|e| JsValue::from_str(&e))?;
+    let tile = TileRequest { x, y, z };
+    let decoded = decompress_gzip(data)
+        .and_then(|data| enhanced_parse_mvt_data(&data, &tile))
+        .map_err(|e| JsValue::from_str(&format!("Vector tile {}/{}/{}: {}", z, x, y, e)))?;
+    if decoded.layers.is_empty() {
+        return Err(JsValue::from_str(&format!(
+            "Vector tile {}/{}/{} contains no layers",
+            z, x, y
+        )));
+    }
+
+    let layer_count = decoded.layers.len();
+    ModuleState::with_mut(|state| {
+        state.set_parsed_mvt_tile(&format!("{}/{}/{}", z, x, y), decoded);
+    });
+    Ok(layer_count)
+}
+
+#[derive(Deserialize)]
+pub struct ElevationGridInjection {
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    /// [minLng, minLat, maxLng, maxLat]
+    pub bbox: [f64; 4],
+    /// Elevations in meters, row 0 on the southern edge
+    pub grid: Vec<Vec<f64>>,
+    #[serde(default, rename = "minElevation")]
+    pub min_elevation: Option<f64>,
+    #[serde(default, rename = "maxElevation")]
+    pub max_elevation: Option<f64>,
+}
+
+impl ElevationGridInjection {
+    fn validate(&self) -> Result<ElevationExtent, String> {
+        let [min_lng, min_lat, max_lng, max_lat] = self.bbox;
+        if !self.bbox.iter().all(|v| v.is_finite()) || min_lng >= max_lng || min_lat >= max_lat {
+            return Err("Invalid bbox: must be [minLng, minLat, maxLng, maxLat]".to_string());
+        }
+        let width = self.grid.first().map_or(0, |row| row.len());
+        if self.grid.len() < 2 || width < 2 {
+            return Err("Elevation grid must be at least 2x2".to_string());
+        }
+        if let Some(row) = self.grid.iter().position(|row| row.len() != width) {
+            return Err(format!(
+                "Elevation grid row {} has {} values, expected {}",
+                row,
+                self.grid[row].len(),
+                width
+            ));
+        }
+        if self.grid.iter().flatten().any(|v| !v.is_finite()) {
+            return Err("Elevation grid contains non-finite values".to_string());
+        }
+
+        let values = self.grid.iter().flatten().copied();
+        Ok(ElevationExtent {
+            bbox: self.bbox,
+            min_elevation: self
+                .min_elevation
+                .unwrap_or_else(|| values.clone().fold(f64::INFINITY, f64::min)),
+            max_elevation: self
+                .max_elevation
+                .unwrap_or_else(|| values.fold(f64::NEG_INFINITY, f64::max)),
+        })
+    }
+}
+
+/// Inject a processed elevation grid for a process, used by terrain and layer generation
+#[wasm_bindgen]
+pub fn inject_elevation_grid(input_json: &str) -> Result<(), JsValue> {
+    let input: ElevationGridInjection = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let extent = input.validate().map_err(|e| JsValue::from_str(&e))?;
+    ModuleState::with_mut(|state| {
+        state.store_elevation_grid_with_extent(input.process_id, input.grid, extent);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_injection_validation() {
+        assert!(validate_tile_coords(2, 3, 3).is_ok());
+        assert!(validate_tile_coords(2, 4, 0).is_err());
+
+        let mut injection = ElevationGridInjection {
+            process_id: "offline-test".to_string(),
+            bbox: [8.0, 50.0, 8.1, 50.1],
+            grid: vec![vec![100.0, 120.0], vec![90.0, 110.0]],
+            min_elevation: None,
+            max_elevation: None,
+        };
+        let extent = injection.validate().unwrap();
+        assert_eq!(extent.min_elevation, 90.0);
+        assert_eq!(extent.max_elevation, 120.0);
+
+        injection.grid[1].pop();
+        assert!(injection.validate().is_err());
+    }
+}
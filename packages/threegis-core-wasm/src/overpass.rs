@@ -0,0 +1,455 @@
+// Direct Overpass API ingestion: OSM nodes, ways and multipolygon relations of a small
+// area converted into layer features of a process, bypassing vector tiles entirely.
+use js_sys::Uint8Array;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+use crate::bbox_filter::is_point_in_polygon;
+use crate::console::{self, LogLevel};
+use crate::fetch_hook::network_fetch;
+use crate::geojson_features::store_layer_features;
+use crate::polygon_geometry::GeometryData;
+use crate::tilejson::encode_component;
+
+const DEFAULT_ENDPOINT: &str = "https://overpass-api.de/api/interpreter";
+// Largest bbox in square degrees queried from a bbox (about 20 x 20 km in mid latitudes);
+// larger areas should come from vector tiles
+const MAX_BBOX_AREA: f64 = 0.05;
+const QUERY_TIMEOUT_SECONDS: u32 = 60;
+// Height per building level when only building:levels is tagged
+pub(crate) const LEVEL_HEIGHT: f64 = 3.0;
+
+/// Tag selectors of each layer: "key" matches any value, "key=value" only that value
+type LayerMapping = BTreeMap<String, Vec<String>>;
+type Ring = Vec<Vec<f64>>;
+
+#[derive(Deserialize)]
+struct OverpassResponse {
+    #[serde(default)]
+    elements: Vec<Element>,
+}
+
+#[derive(Deserialize)]
+struct Element {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    id: u64,
+    #[serde(default)]
+    lat: Option<f64>,
+    #[serde(default)]
+    lon: Option<f64>,
+    /// Node positions of a way with `out geom`
+    #[serde(default)]
+    geometry: Vec<Option<LatLon>>,
+    #[serde(default)]
+    members: Vec<Member>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Member {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    role: String,
+    #[serde(default)]
+    geometry: Vec<Option<LatLon>>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct LatLon {
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OverpassImportSummary {
+    /// Elements in the Overpass response
+    pub elements: usize,
+    /// Geometries stored per layer
+    pub layers: BTreeMap<String, usize>,
+    /// Elements matching no layer
+    pub unmatched: usize,
+}
+
+fn matches_selector(selector: &str, tags: &BTreeMap<String, String>) -> bool {
+    match selector.split_once('=') {
+        Some((key, value)) => tags.get(key.trim()).is_some_and(|v| v == value.trim()),
+        None => tags.contains_key(selector.trim()),
+    }
+}
+
+/// Overpass QL tag filter of a selector, e.g. `["building"]` or `["natural"="water"]`
+fn tag_filter(selector: &str) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.trim().replace('"', "\\\""));
+    match selector.split_once('=') {
+        Some((key, value)) => format!("[{}={}]", quote(key), quote(value)),
+        None => format!("[{}]", quote(selector)),
+    }
+}
+
+/// Query for all elements matching a layer selector within `bbox`
+fn bbox_query(bbox: [f64; 4], mapping: &LayerMapping) -> String {
+    let [min_lng, min_lat, max_lng, max_lat] = bbox;
+    let area = format!("({},{},{},{})", min_lat, min_lng, max_lat, max_lng);
+    let mut filters: Vec<String> = mapping.values().flatten().map(|s| tag_filter(s)).collect();
+    filters.sort();
+    filters.dedup();
+    let statements: String = filters
+        .iter()
+        .map(|filter| format!("nwr{}{};", filter, area))
+        .collect();
+    format!(
+        "[out:json][timeout:{}];({});out geom;",
+        QUERY_TIMEOUT_SECONDS, statements
+    )
+}
+
+/// Overpass query from a `[minLng, minLat, maxLng, maxLat]` JSON bbox or a QL query
+fn overpass_query(query_or_bbox: &str, mapping: &LayerMapping) -> Result<String, String> {
+    let Ok(bbox) = serde_json::from_str::<Vec<f64>>(query_or_bbox.trim()) else {
+        return Ok(query_or_bbox.trim().to_string());
+    };
+    let bbox = <[f64; 4]>::try_from(bbox.as_slice())
+        .map_err(|_| "Invalid bbox: must be [minLng, minLat, maxLng, maxLat]".to_string())?;
+    let [min_lng, min_lat, max_lng, max_lat] = bbox;
+    if min_lng >= max_lng || min_lat >= max_lat {
+        return Err("Invalid bbox: must be [minLng, minLat, maxLng, maxLat]".to_string());
+    }
+    let area = (max_lng - min_lng) * (max_lat - min_lat);
+    if area > MAX_BBOX_AREA {
+        return Err(format!(
+            "Bbox of {:.3} square degrees is too large for Overpass (max {}); use vector tiles",
+            area, MAX_BBOX_AREA
+        ));
+    }
+    if mapping.values().all(Vec::is_empty) {
+        return Err("Layer mapping has no tag selectors".to_string());
+    }
+    Ok(bbox_query(bbox, mapping))
+}
+
+/// Metres of an OSM length such as "12", "12.5 m" or "40 ft"
+pub(crate) fn parse_length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let number: f64 = value[..end].parse().ok()?;
+    let unit = value[end..].trim();
+    let metres = match unit {
+        "" | "m" => number,
+        "ft" | "'" => number * 0.3048,
+        _ => return None,
+    };
+    Some(metres).filter(|h| h.is_finite() && *h > 0.0)
+}
+
+/// Building height from height, building:height or the number of levels
+fn osm_height(tags: &BTreeMap<String, String>) -> Option<f64> {
+    let level_count = |key: &str| {
+        tags.get(key)
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|l| l.is_finite() && *l >= 0.0)
+    };
+    ["height", "building:height"]
+        .iter()
+        .find_map(|key| tags.get(*key).and_then(|v| parse_length(v)))
+        .or_else(|| {
+            let levels = level_count("building:levels")?;
+            let roof_levels = level_count("roof:levels").unwrap_or(0.0);
+            Some((levels + roof_levels) * LEVEL_HEIGHT).filter(|h| *h > 0.0)
+        })
+}
+
+fn line_points(geometry: &[Option<LatLon>]) -> Option<Ring> {
+    geometry
+        .iter()
+        .map(|p| p.map(|p| vec![p.lon, p.lat]))
+        .collect()
+}
+
+fn is_closed(ring: &Ring) -> bool {
+    ring.len() >= 4 && ring.first() == ring.last()
+}
+
+/// Closed ways are areas unless they are roads or barriers, or tagged area=no
+fn way_is_area(ring: &Ring, tags: &BTreeMap<String, String>) -> bool {
+    is_closed(ring)
+        && match tags.get("area").map(String::as_str) {
+            Some("yes") => true,
+            Some("no") => false,
+            _ => !tags.contains_key("highway") && !tags.contains_key("barrier"),
+        }
+}
+
+/// Join way segments sharing end nodes into closed rings; unclosed chains are dropped
+fn assemble_rings(mut segments: Vec<Ring>) -> Vec<Ring> {
+    segments.retain(|segment| segment.len() >= 2);
+    let mut rings = Vec::new();
+    while let Some(mut ring) = segments.pop() {
+        while !is_closed(&ring) {
+            let end = ring[ring.len() - 1].clone();
+            let Some(next) = segments
+                .iter()
+                .position(|s| s.first() == Some(&end) || s.last() == Some(&end))
+            else {
+                break;
+            };
+            let mut segment = segments.swap_remove(next);
+            if segment.first() != Some(&end) {
+                segment.reverse();
+            }
+            ring.extend(segment.into_iter().skip(1));
+        }
+        if is_closed(&ring) {
+            rings.push(ring);
+        }
+    }
+    rings
+}
+
+/// Outer rings of a multipolygon relation with the inner rings inside them as holes
+fn multipolygon_parts(members: &[Member]) -> Vec<(Ring, Vec<Ring>)> {
+    let segments = |inner: bool| {
+        members
+            .iter()
+            .filter(|m| m.kind == "way" && (m.role == "inner") == inner)
+            .filter_map(|m| line_points(&m.geometry))
+            .collect::<Vec<_>>()
+    };
+    let mut parts: Vec<(Ring, Vec<Ring>)> = assemble_rings(segments(false))
+        .into_iter()
+        .map(|outer| (outer, Vec::new()))
+        .collect();
+    for inner in assemble_rings(segments(true)) {
+        if let Some((_, holes)) = parts
+            .iter_mut()
+            .find(|(outer, _)| is_point_in_polygon(&inner[0], outer))
+        {
+            holes.push(inner);
+        }
+    }
+    parts
+}
+
+/// Geometry parts of an element as (type, points, holes)
+fn element_parts(element: &Element) -> Vec<(&'static str, Ring, Vec<Ring>)> {
+    match element.kind.as_str() {
+        "node" => match (element.lon, element.lat) {
+            (Some(lon), Some(lat)) => vec![("Point", vec![vec![lon, lat]], Vec::new())],
+            _ => Vec::new(),
+        },
+        "way" => match line_points(&element.geometry) {
+            Some(ring) if way_is_area(&ring, &element.tags) => {
+                vec![("Polygon", ring, Vec::new())]
+            }
+            Some(line) if line.len() >= 2 => vec![("LineString", line, Vec::new())],
+            _ => Vec::new(),
+        },
+        "relation"
+            if matches!(
+                element.tags.get("type").map(String::as_str),
+                Some("multipolygon") | Some("building")
+            ) =>
+        {
+            multipolygon_parts(&element.members)
+                .into_iter()
+                .map(|(outer, holes)| ("Polygon", outer, holes))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Layer features of an Overpass JSON response; elements land in every layer with a
+/// matching selector, and every mapped layer gets an entry
+fn features_from_response(
+    json: &str,
+    mapping: &LayerMapping,
+) -> Result<(BTreeMap<String, Vec<GeometryData>>, OverpassImportSummary), String> {
+    let response: OverpassResponse = serde_json::from_str(json).map_err(|e| {
+        format!(
+            "Invalid Overpass response (queries need [out:json] and out geom): {}",
+            e
+        )
+    })?;
+    let mut layers: BTreeMap<String, Vec<GeometryData>> = mapping
+        .keys()
+        .map(|name| (name.clone(), Vec::new()))
+        .collect();
+    let mut unmatched = 0;
+    for element in &response.elements {
+        let matching: Vec<&String> = mapping
+            .iter()
+            .filter(|(_, selectors)| selectors.iter().any(|s| matches_selector(s, &element.tags)))
+            .map(|(name, _)| name)
+            .collect();
+        if matching.is_empty() {
+            unmatched += 1;
+            continue;
+        }
+        let mut properties: serde_json::Map<String, Value> = element
+            .tags
+            .iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect();
+        properties.insert(
+            "osm_id".to_string(),
+            Value::String(format!("{}/{}", element.kind, element.id)),
+        );
+        let height = osm_height(&element.tags).unwrap_or(0.0);
+        for (kind, points, holes) in element_parts(element) {
+            for name in &matching {
+                layers.get_mut(*name).unwrap().push(GeometryData {
+                    geometry: points.clone(),
+                    holes: (!holes.is_empty()).then(|| holes.clone()),
+                    r#type: Some(kind.to_string()),
+                    height: Some(height),
+                    layer: Some((*name).clone()),
+                    label: None,
+                    tags: None,
+                    properties: Some(Value::Object(properties.clone())),
+                });
+            }
+        }
+    }
+    let summary = OverpassImportSummary {
+        elements: response.elements.len(),
+        layers: layers
+            .iter()
+            .map(|(name, features)| (name.clone(), features.len()))
+            .collect(),
+        unmatched,
+    };
+    Ok((layers, summary))
+}
+
+/// Query the Overpass API and store the result as layers of a process, as
+/// `import_geojson_features` does. `query_or_bbox` is a `[minLng, minLat, maxLng,
+/// maxLat]` JSON bbox of a small area or an Overpass QL query with `[out:json]` and
+/// `out geom`. `layer_mapping_json` maps layer names to tag selectors, e.g.
+/// `{"buildings": ["building"], "water": ["natural=water"]}`; building heights come from
+/// the height, building:height and building:levels tags. Returns the feature counts.
+#[wasm_bindgen]
+pub async fn fetch_overpass_features(
+    process_id: String,
+    query_or_bbox: String,
+    layer_mapping_json: String,
+    endpoint: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let mapping: LayerMapping = serde_json::from_str(&layer_mapping_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse layer mapping: {}", e)))?;
+    let query = overpass_query(&query_or_bbox, &mapping).map_err(|e| JsValue::from_str(&e))?;
+    let endpoint = endpoint
+        .filter(|e| !e.is_empty())
+        .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+    let url = format!("{}?data={}", endpoint, encode_component(&query));
+
+    let response = network_fetch(&url).await?;
+    let raw_data = js_sys::Reflect::get(&response, &JsValue::from_str("rawData"))?;
+    if raw_data.is_undefined() || raw_data.is_null() {
+        return Err(JsValue::from_str("No data received from the Overpass API"));
+    }
+    let text = String::from_utf8(Uint8Array::new(&raw_data).to_vec())
+        .map_err(|_| JsValue::from_str("Overpass response is not UTF-8"))?;
+    let (layers, summary) =
+        features_from_response(&text, &mapping).map_err(|e| JsValue::from_str(&e))?;
+
+    for (name, features) in &layers {
+        store_layer_features(&process_id, name, features).map_err(|e| JsValue::from_str(&e))?;
+    }
+    console::record(
+        LogLevel::Info,
+        "overpass",
+        Some(&process_id),
+        format!(
+            "Stored {} Overpass elements in {} layers, {} unmatched",
+            summary.elements - summary.unmatched,
+            layers.len(),
+            summary.unmatched
+        ),
+    );
+    Ok(serde_wasm_bindgen::to_value(&summary)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overpass_elements_to_layers() {
+        let mapping: LayerMapping = serde_json::from_str(
+            r#"{"buildings": ["building"], "water": ["natural=water"], "roads": ["highway"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            overpass_query("[8.5, 47.3, 8.6, 47.4]", &mapping).unwrap(),
+            "[out:json][timeout:60];(nwr[\"building\"](47.3,8.5,47.4,8.6);\
+             nwr[\"highway\"](47.3,8.5,47.4,8.6);\
+             nwr[\"natural\"=\"water\"](47.3,8.5,47.4,8.6););out geom;"
+        );
+        assert!(overpass_query("[0, 0, 1, 1]", &mapping).is_err());
+        assert_eq!(
+            overpass_query(" [out:json];way(1);out geom; ", &mapping).unwrap(),
+            "[out:json];way(1);out geom;"
+        );
+
+        let square = |x: f64, size: f64| {
+            format!(
+                "[{{\"lat\":0,\"lon\":{x}}},{{\"lat\":0,\"lon\":{}}},{{\"lat\":{size},\"lon\":{}}},{{\"lat\":{size},\"lon\":{x}}},{{\"lat\":0,\"lon\":{x}}}]",
+                x + size,
+                x + size
+            )
+        };
+        let response = format!(
+            r#"{{"elements": [
+                {{"type": "way", "id": 1, "tags": {{"building": "yes", "building:levels": "4"}},
+                  "geometry": {}}},
+                {{"type": "way", "id": 2, "tags": {{"highway": "residential"}},
+                  "geometry": {}}},
+                {{"type": "relation", "id": 3,
+                  "tags": {{"type": "multipolygon", "natural": "water", "height": "12 m"}},
+                  "members": [
+                    {{"type": "way", "role": "outer", "geometry": [{{"lat":0,"lon":10}},{{"lat":0,"lon":14}},{{"lat":4,"lon":14}}]}},
+                    {{"type": "way", "role": "outer", "geometry": [{{"lat":0,"lon":10}},{{"lat":4,"lon":10}},{{"lat":4,"lon":14}}]}},
+                    {{"type": "way", "role": "inner", "geometry": {}}}
+                  ]}},
+                {{"type": "node", "id": 4, "lat": 1, "lon": 2, "tags": {{"amenity": "bench"}}}}
+            ]}}"#,
+            square(0.0, 1.0),
+            square(5.0, 1.0),
+            square(11.0, 1.0)
+        );
+        let (layers, summary) = features_from_response(&response, &mapping).unwrap();
+        assert_eq!(
+            summary.layers,
+            BTreeMap::from([
+                ("buildings".to_string(), 1),
+                ("roads".to_string(), 1),
+                ("water".to_string(), 1)
+            ])
+        );
+        assert_eq!((summary.elements, summary.unmatched), (4, 1));
+
+        let building = &layers["buildings"][0];
+        assert_eq!(building.r#type.as_deref(), Some("Polygon"));
+        assert_eq!(building.height, Some(12.0));
+        assert_eq!(building.properties.as_ref().unwrap()["osm_id"], "way/1");
+        // Closed highway ways stay lines
+        assert_eq!(layers["roads"][0].r#type.as_deref(), Some("LineString"));
+
+        let lake = &layers["water"][0];
+        assert_eq!(lake.geometry.len(), 5);
+        assert!(lake.geometry.iter().all(|p| p[0] == 10.0 || p[0] == 14.0));
+        assert_eq!(lake.holes.as_ref().map(Vec::len), Some(1));
+        assert_eq!(lake.height, Some(12.0));
+
+        assert_eq!(parse_length("40 ft"), Some(40.0 * 0.3048));
+        assert_eq!(parse_length("approx"), None);
+    }
+}
@@ -0,0 +1,156 @@
+// Binary geometry results: all buffers of a geometry list packed into one ArrayBuffer
+// with a small header, so JavaScript builds BufferAttributes from typed array views on
+// it without parsing JSON. The buffer can be transferred between workers as a whole.
+//
+// Layout (little-endian, all offsets in bytes from the start of the buffer):
+//   header   "TGPK", u32 version, u32 geometry count
+//   entries  one per geometry: (u32 offset, u32 element count) for vertices, normals,
+//            colors, indices, uvs and properties, then u32 flags
+//   data     the sections, each starting 4-byte aligned; f32 for vertices, normals,
+//            colors and uvs, u32 for indices, UTF-8 JSON for properties
+// Flag bit 0 is hasData; bits 1-5 mark which of normals..properties are present.
+use js_sys::Uint8Array;
+use wasm_bindgen::prelude::*;
+
+use crate::polygon_geometry::BufferGeometry;
+
+const MAGIC: &[u8; 4] = b"TGPK";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 12;
+const SECTION_COUNT: usize = 6;
+const ENTRY_SIZE: usize = SECTION_COUNT * 8 + 4;
+
+fn put_u32(out: &mut [u8], pos: usize, value: u32) {
+    out[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Append a section of `count` elements and record it in the entry at `entry`
+fn push_section(out: &mut Vec<u8>, entry: usize, section: usize, count: usize, bytes: &[u8]) {
+    let offset = out.len();
+    put_u32(out, entry + section * 8, offset as u32);
+    put_u32(out, entry + section * 8 + 4, count as u32);
+    out.extend_from_slice(bytes);
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+pub(crate) fn pack_geometries(geometries: &[BufferGeometry]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + geometries.len() * ENTRY_SIZE);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(geometries.len() as u32).to_le_bytes());
+    out.resize(HEADER_SIZE + geometries.len() * ENTRY_SIZE, 0);
+
+    let floats = |values: &[f32]| {
+        values
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<u8>>()
+    };
+    for (i, geometry) in geometries.iter().enumerate() {
+        let entry = HEADER_SIZE + i * ENTRY_SIZE;
+        let mut flags = u32::from(geometry.has_data);
+        let vertices = &geometry.vertices;
+        push_section(&mut out, entry, 0, vertices.len(), &floats(vertices));
+
+        let indices = geometry
+            .indices
+            .as_deref()
+            .map(|v| (v.iter().flat_map(|i| i.to_le_bytes()).collect(), v.len()));
+        let properties = geometry
+            .properties
+            .as_ref()
+            .and_then(|p| serde_json::to_vec(p).ok())
+            .map(|json| {
+                let len = json.len();
+                (json, len)
+            });
+        let optional: [Option<(Vec<u8>, usize)>; SECTION_COUNT - 1] = [
+            geometry.normals.as_deref().map(|v| (floats(v), v.len())),
+            geometry.colors.as_deref().map(|v| (floats(v), v.len())),
+            indices,
+            geometry.uvs.as_deref().map(|v| (floats(v), v.len())),
+            properties,
+        ];
+        for (section, data) in optional.into_iter().enumerate() {
+            if let Some((bytes, count)) = data {
+                flags |= 1 << (section + 1);
+                push_section(&mut out, entry, section + 1, count, &bytes);
+            }
+        }
+        put_u32(&mut out, entry + SECTION_COUNT * 8, flags);
+    }
+    out
+}
+
+/// Packed geometries as a Uint8Array that owns its ArrayBuffer
+pub(crate) fn packed_to_js(geometries: &[BufferGeometry]) -> JsValue {
+    Uint8Array::from(pack_geometries(geometries).as_slice()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_pack_geometries_layout() {
+        let geometries = vec![
+            BufferGeometry {
+                vertices: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.5],
+                normals: None,
+                colors: None,
+                indices: Some(vec![0, 1, 2]),
+                uvs: None,
+                has_data: true,
+                properties: Some(
+                    [("__label".to_string(), serde_json::json!("roads"))]
+                        .into_iter()
+                        .collect(),
+                ),
+            },
+            BufferGeometry {
+                vertices: Vec::new(),
+                normals: None,
+                colors: None,
+                indices: None,
+                uvs: None,
+                has_data: false,
+                properties: None,
+            },
+        ];
+        let data = pack_geometries(&geometries);
+        assert_eq!(&data[..4], MAGIC);
+        assert_eq!(read_u32(&data, 8), 2);
+        assert_eq!(data.len() % 4, 0);
+
+        let entry = HEADER_SIZE;
+        let flags = read_u32(&data, entry + SECTION_COUNT * 8);
+        // hasData, indices and properties
+        assert_eq!(flags, 1 | 1 << 3 | 1 << 5);
+
+        let (offset, count) = (read_u32(&data, entry), read_u32(&data, entry + 4));
+        assert_eq!((offset as usize, count), (HEADER_SIZE + 2 * ENTRY_SIZE, 9));
+        let last = offset as usize + 8 * 4;
+        assert_eq!(
+            f32::from_le_bytes(data[last..last + 4].try_into().unwrap()),
+            8.5
+        );
+
+        let indices = read_u32(&data, entry + 3 * 8) as usize;
+        assert_eq!(indices % 4, 0);
+        assert_eq!(read_u32(&data, indices + 8), 2);
+
+        let properties = read_u32(&data, entry + 5 * 8) as usize;
+        let len = read_u32(&data, entry + 5 * 8 + 4) as usize;
+        let json: serde_json::Value =
+            serde_json::from_slice(&data[properties..properties + len]).unwrap();
+        assert_eq!(json["__label"], "roads");
+
+        let empty = HEADER_SIZE + ENTRY_SIZE;
+        assert_eq!(read_u32(&data, empty + SECTION_COUNT * 8), 0);
+        assert_eq!(read_u32(&data, empty + 4), 0);
+    }
+}
@@ -0,0 +1,161 @@
+// Optional multithreading for the `threads` feature. The rayon pool runs on web workers
+// sharing the module's memory (SharedArrayBuffer), started from JS with
+// `init_thread_pool(n)`. Until the pool is running, and in builds without the feature,
+// every helper here runs sequentially on the calling thread, so callers need no cfg
+// gates of their own. Parallel calls block the calling thread while the pool works, so
+// the module must itself run in a worker, never on the browser main thread.
+#[cfg(feature = "threads")]
+use rayon::prelude::*;
+#[cfg(all(feature = "threads", target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use wasm_bindgen::prelude::*;
+
+#[cfg(all(feature = "threads", target_arch = "wasm32"))]
+static POOL_READY: AtomicBool = AtomicBool::new(false);
+
+/// Start the worker thread pool with `num_threads` threads (0 picks
+/// `navigator.hardwareConcurrency`). Resolves to whether parallel processing is active;
+/// false in builds without the `threads` feature.
+#[wasm_bindgen]
+pub async fn init_thread_pool(num_threads: usize) -> Result<bool, JsValue> {
+    #[cfg(all(feature = "threads", target_arch = "wasm32"))]
+    {
+        let num_threads = if num_threads == 0 {
+            // navigator exists on both window and worker globals
+            js_sys::Reflect::get(&js_sys::global(), &"navigator".into())
+                .and_then(|navigator| {
+                    js_sys::Reflect::get(&navigator, &"hardwareConcurrency".into())
+                })
+                .ok()
+                .and_then(|n| n.as_f64())
+                .map_or(4, |n| (n as usize).max(1))
+        } else {
+            num_threads
+        };
+        wasm_bindgen_futures::JsFuture::from(wasm_bindgen_rayon::init_thread_pool(num_threads))
+            .await?;
+        POOL_READY.store(true, Ordering::Release);
+        Ok(true)
+    }
+    #[cfg(not(all(feature = "threads", target_arch = "wasm32")))]
+    {
+        let _ = num_threads;
+        Ok(threads_enabled())
+    }
+}
+
+/// Whether work is spread over the thread pool. Natively rayon starts its own pool.
+pub(crate) fn threads_enabled() -> bool {
+    #[cfg(all(feature = "threads", target_arch = "wasm32"))]
+    {
+        POOL_READY.load(Ordering::Acquire)
+    }
+    #[cfg(all(feature = "threads", not(target_arch = "wasm32")))]
+    {
+        true
+    }
+    #[cfg(not(feature = "threads"))]
+    {
+        false
+    }
+}
+
+/// Number of threads work is spread over, 1 when running sequentially
+pub(crate) fn thread_count() -> usize {
+    #[cfg(feature = "threads")]
+    if threads_enabled() {
+        return rayon::current_num_threads();
+    }
+    1
+}
+
+/// `f(index, item)` for every item, results in item order. `init` runs on each pool
+/// thread before it maps a batch, to install thread-local state the caller already has.
+pub(crate) fn map_slice<T, R, I, F>(items: &[T], init: I, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    I: Fn() + Sync + Send,
+    F: Fn(usize, &T) -> R + Sync + Send,
+{
+    #[cfg(feature = "threads")]
+    if threads_enabled() {
+        return items
+            .par_iter()
+            .enumerate()
+            .map_init(&init, |_, (i, item)| f(i, item))
+            .collect();
+    }
+    let _ = init;
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| f(i, item))
+        .collect()
+}
+
+/// `f(i)` for `i` in `0..len`, results in order
+pub(crate) fn map_range<R, F>(len: usize, f: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(usize) -> R + Sync + Send,
+{
+    #[cfg(feature = "threads")]
+    if threads_enabled() {
+        return (0..len).into_par_iter().map(f).collect();
+    }
+    (0..len).map(f).collect()
+}
+
+/// Combine `items` with the associative `op` as a balanced tree, so the operands of every
+/// step stay similar in size; None when empty. Independent steps run on the pool. The tree
+/// splits at fixed midpoints, so results never depend on how the pool schedules work.
+pub(crate) fn reduce_pairwise<T, F>(items: Vec<T>, op: F) -> Option<T>
+where
+    T: Send,
+    F: Fn(T, T) -> T + Sync + Send,
+{
+    fn reduce<T: Send, F: Fn(T, T) -> T + Sync + Send>(mut items: Vec<T>, op: &F) -> T {
+        if items.len() == 1 {
+            return items.pop().expect("one item");
+        }
+        let right = items.split_off(items.len() / 2);
+        #[cfg(feature = "threads")]
+        if threads_enabled() {
+            let (a, b) = rayon::join(|| reduce(items, op), || reduce(right, op));
+            return op(a, b);
+        }
+        let a = reduce(items, op);
+        op(a, reduce(right, op))
+    }
+    if items.is_empty() {
+        return None;
+    }
+    Some(reduce(items, &op))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_slice_keeps_item_order() {
+        let items: Vec<u32> = (0..1000).collect();
+        let mapped = map_slice(&items, || {}, |i, v| (i as u32) + v);
+        assert_eq!(mapped, (0..1000).map(|v| v * 2).collect::<Vec<_>>());
+        assert_eq!(map_range(4, |i| i * i), vec![0, 1, 4, 9]);
+    }
+
+    #[test]
+    fn reduce_pairwise_combines_every_item_in_order() {
+        let words: Vec<String> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            reduce_pairwise(words, |a, b| a + &b).as_deref(),
+            Some("abcde")
+        );
+        assert_eq!(reduce_pairwise(Vec::<String>::new(), |a, b| a + &b), None);
+    }
+}
@@ -0,0 +1,433 @@
+// Feature picking against generated layer geometry. Every geometry returned by
+// process_polygon_geometry is one feature, so its triangles are indexed together
+// with the feature properties in a per-layer BVH kept in ModuleState.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::BufferGeometry;
+
+// Triangles per BVH leaf
+const LEAF_SIZE: usize = 4;
+// Height above the model from which 2D picks are cast straight down
+const TOP_DOWN_RAY_Z: f64 = 1.0e4;
+const RAY_EPSILON: f64 = 1e-9;
+
+type Vec3 = [f64; 3];
+type FeatureProperties = Option<HashMap<String, serde_json::Value>>;
+
+#[derive(Clone, Copy)]
+struct Triangle {
+    vertices: [Vec3; 3],
+    feature: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    fn empty() -> Self {
+        Self {
+            min: [f64::INFINITY; 3],
+            max: [f64::NEG_INFINITY; 3],
+        }
+    }
+
+    fn grow(&mut self, point: &Vec3) {
+        for (axis, value) in point.iter().enumerate() {
+            self.min[axis] = self.min[axis].min(*value);
+            self.max[axis] = self.max[axis].max(*value);
+        }
+    }
+
+    // Slab test; returns the entry distance when the ray hits the box before max_t
+    fn ray_entry(&self, origin: &Vec3, inv_dir: &Vec3, max_t: f64) -> Option<f64> {
+        let mut t_min = 0.0f64;
+        let mut t_max = max_t;
+        for axis in 0..3 {
+            let t1 = (self.min[axis] - origin[axis]) * inv_dir[axis];
+            let t2 = (self.max[axis] - origin[axis]) * inv_dir[axis];
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+        (t_min <= t_max).then_some(t_min)
+    }
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Inner {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Inner { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Triangle BVH over the features of one generated layer
+pub struct LayerPickIndex {
+    triangles: Vec<Triangle>,
+    nodes: Vec<BvhNode>,
+    features: Vec<FeatureProperties>,
+}
+
+struct TriangleHit {
+    distance: f64,
+    triangle: usize,
+}
+
+impl LayerPickIndex {
+    pub fn build(geometries: &[&BufferGeometry]) -> Self {
+        let mut triangles = Vec::new();
+        let mut features = Vec::with_capacity(geometries.len());
+
+        for geometry in geometries {
+            let feature = features.len();
+            features.push(geometry.properties.clone());
+            let vertex = |i: usize| -> Option<Vec3> {
+                let v = geometry.vertices.get(i * 3..i * 3 + 3)?;
+                Some([v[0] as f64, v[1] as f64, v[2] as f64])
+            };
+            let triangle_count = match &geometry.indices {
+                Some(indices) => indices.len() / 3,
+                None => geometry.vertices.len() / 9,
+            };
+            for t in 0..triangle_count {
+                let corner = |k: usize| match &geometry.indices {
+                    Some(indices) => vertex(indices[t * 3 + k] as usize),
+                    None => vertex(t * 3 + k),
+                };
+                if let (Some(a), Some(b), Some(c)) = (corner(0), corner(1), corner(2)) {
+                    triangles.push(Triangle {
+                        vertices: [a, b, c],
+                        feature,
+                    });
+                }
+            }
+        }
+
+        let mut index = Self {
+            triangles,
+            nodes: Vec::new(),
+            features,
+        };
+        if !index.triangles.is_empty() {
+            index.build_node(0, index.triangles.len());
+        }
+        index
+    }
+
+    // Median split on the longest centroid axis; returns the node index
+    fn build_node(&mut self, start: usize, end: usize) -> usize {
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for triangle in &self.triangles[start..end] {
+            for vertex in &triangle.vertices {
+                bounds.grow(vertex);
+            }
+            centroid_bounds.grow(&centroid(triangle));
+        }
+
+        let node_index = self.nodes.len();
+        if end - start <= LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf { bounds, start, end });
+            return node_index;
+        }
+
+        let extent: Vec<f64> = (0..3)
+            .map(|axis| centroid_bounds.max[axis] - centroid_bounds.min[axis])
+            .collect();
+        let axis = (0..3)
+            .max_by(|a, b| extent[*a].total_cmp(&extent[*b]))
+            .unwrap_or(0);
+        let mid = start + (end - start) / 2;
+        self.triangles[start..end].select_nth_unstable_by(mid - start, |a, b| {
+            centroid(a)[axis].total_cmp(&centroid(b)[axis])
+        });
+
+        // Reserve the slot, then fill it once both children exist
+        self.nodes.push(BvhNode::Leaf { bounds, start, end });
+        let left = self.build_node(start, mid);
+        let right = self.build_node(mid, end);
+        self.nodes[node_index] = BvhNode::Inner {
+            bounds,
+            left,
+            right,
+        };
+        node_index
+    }
+
+    fn intersect(&self, origin: &Vec3, direction: &Vec3, max_t: f64) -> Option<TriangleHit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inv_dir = direction.map(|d| 1.0 / d);
+        let mut best: Option<TriangleHit> = None;
+        let mut stack = vec![0usize];
+
+        while let Some(node_index) = stack.pop() {
+            let limit = best.as_ref().map_or(max_t, |hit| hit.distance);
+            let node = &self.nodes[node_index];
+            if node.bounds().ray_entry(origin, &inv_dir, limit).is_none() {
+                continue;
+            }
+            match node {
+                BvhNode::Leaf { start, end, .. } => {
+                    for triangle_index in *start..*end {
+                        let triangle = &self.triangles[triangle_index];
+                        let limit = best.as_ref().map_or(max_t, |hit| hit.distance);
+                        if let Some(t) = intersect_triangle(origin, direction, &triangle.vertices) {
+                            if t <= limit {
+                                best = Some(TriangleHit {
+                                    distance: t,
+                                    triangle: triangle_index,
+                                });
+                            }
+                        }
+                    }
+                }
+                BvhNode::Inner { left, right, .. } => {
+                    stack.push(*left);
+                    stack.push(*right);
+                }
+            }
+        }
+
+        best
+    }
+}
+
+fn centroid(triangle: &Triangle) -> Vec3 {
+    let [a, b, c] = triangle.vertices;
+    [
+        (a[0] + b[0] + c[0]) / 3.0,
+        (a[1] + b[1] + c[1]) / 3.0,
+        (a[2] + b[2] + c[2]) / 3.0,
+    ]
+}
+
+fn sub(a: &Vec3, b: &Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: &Vec3, b: &Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: &Vec3, b: &Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// Möller–Trumbore, double-sided; returns the ray parameter of the hit
+fn intersect_triangle(origin: &Vec3, direction: &Vec3, vertices: &[Vec3; 3]) -> Option<f64> {
+    let edge1 = sub(&vertices[1], &vertices[0]);
+    let edge2 = sub(&vertices[2], &vertices[0]);
+    let p = cross(direction, &edge2);
+    let det = dot(&edge1, &p);
+    if det.abs() < RAY_EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = sub(origin, &vertices[0]);
+    let u = dot(&s, &p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = cross(&s, &edge1);
+    let v = dot(direction, &q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = dot(&edge2, &q) * inv_det;
+    (t >= 0.0).then_some(t)
+}
+
+/// Replace the pick index of one generated layer of a process
+pub(crate) fn register_layer_geometries(
+    process_id: &str,
+    layer_label: &str,
+    geometries: &[BufferGeometry],
+) {
+    let layer: Vec<&BufferGeometry> = geometries.iter().filter(|g| g.has_data).collect();
+    let index = LayerPickIndex::build(&layer);
+
+    ModuleState::with_mut(|state| {
+        state
+            .pick_indices
+            .entry(process_id.to_string())
+            .or_default()
+            .insert(layer_label.to_string(), index);
+    });
+}
+
+#[derive(Deserialize)]
+pub struct PickRay {
+    pub origin: [f64; 3],
+    pub direction: [f64; 3],
+}
+
+#[derive(Deserialize)]
+pub struct PickInput {
+    /// Process whose geometry to pick against; all cached processes when omitted
+    #[serde(default, rename = "processId")]
+    pub process_id: Option<String>,
+    /// Ray in mesh space
+    #[serde(default)]
+    pub ray: Option<PickRay>,
+    /// 2D mesh coordinate [x, y], picked top-down
+    #[serde(default)]
+    pub point: Option<[f64; 2]>,
+    /// Restrict picking to these layer labels
+    #[serde(default)]
+    pub layers: Option<Vec<String>>,
+    #[serde(default, rename = "maxDistance")]
+    pub max_distance: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PickResult {
+    pub layer: String,
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    #[serde(rename = "featureIndex")]
+    pub feature_index: usize,
+    pub properties: FeatureProperties,
+    /// Hit point in mesh space
+    pub point: [f64; 3],
+    pub distance: f64,
+}
+
+/// Pick the closest generated feature along a ray or below a 2D mesh coordinate.
+/// Returns the hit as JSON, or "null" when nothing was hit.
+#[wasm_bindgen]
+pub fn pick_feature(input_json: &str) -> Result<String, JsValue> {
+    let input: PickInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let result = pick(&input).map_err(|e| JsValue::from_str(&e))?;
+    serde_json::to_string(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize pick result: {}", e)))
+}
+
+pub(crate) fn pick(input: &PickInput) -> Result<Option<PickResult>, String> {
+    let (origin, direction) = match (&input.ray, input.point) {
+        (Some(ray), _) => (ray.origin, ray.direction),
+        (None, Some([x, y])) => ([x, y, TOP_DOWN_RAY_Z], [0.0, 0.0, -1.0]),
+        (None, None) => return Err("Pick input needs either 'ray' or 'point'".to_string()),
+    };
+    let length = dot(&direction, &direction).sqrt();
+    if !length.is_finite() || length < RAY_EPSILON || origin.iter().any(|c| !c.is_finite()) {
+        return Err("Pick ray must have a finite origin and non-zero direction".to_string());
+    }
+    let direction = direction.map(|d| d / length);
+    let max_t = input
+        .max_distance
+        .filter(|d| *d > 0.0)
+        .unwrap_or(f64::INFINITY);
+
+    Ok(ModuleState::with(|state| {
+        let mut best: Option<PickResult> = None;
+        for (process_id, layers) in &state.pick_indices {
+            if input.process_id.as_ref().is_some_and(|id| id != process_id) {
+                continue;
+            }
+            for (label, index) in layers {
+                if input.layers.as_ref().is_some_and(|l| !l.contains(label)) {
+                    continue;
+                }
+                let limit = best.as_ref().map_or(max_t, |hit| hit.distance);
+                let Some(hit) = index.intersect(&origin, &direction, limit) else {
+                    continue;
+                };
+                let feature_index = index.triangles[hit.triangle].feature;
+                best = Some(PickResult {
+                    layer: label.clone(),
+                    process_id: process_id.clone(),
+                    feature_index,
+                    properties: index.features[feature_index].clone(),
+                    point: [0, 1, 2].map(|axis| origin[axis] + direction[axis] * hit.distance),
+                    distance: hit.distance,
+                });
+            }
+        }
+        best
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Flat unit square at (x, y) and height z
+    fn square(x: f32, y: f32, z: f32, id: i64) -> BufferGeometry {
+        let mut properties = HashMap::new();
+        properties.insert("id".to_string(), serde_json::json!(id));
+        BufferGeometry {
+            vertices: vec![x, y, z, x + 1.0, y, z, x + 1.0, y + 1.0, z, x, y + 1.0, z],
+            normals: None,
+            colors: None,
+            indices: Some(vec![0, 1, 2, 0, 2, 3]),
+            uvs: None,
+            has_data: true,
+            properties: Some(properties),
+        }
+    }
+
+    #[test]
+    fn test_pick_returns_topmost_feature() {
+        let roads: Vec<BufferGeometry> = (0..20)
+            .map(|i| square(i as f32 * 2.0, 0.0, 1.0, i))
+            .collect();
+        register_layer_geometries("pick-test", "roads", &roads);
+        let buildings = [square(4.0, 0.0, 3.0, 99)];
+        register_layer_geometries("pick-test", "buildings", &buildings);
+
+        let input = PickInput {
+            process_id: Some("pick-test".to_string()),
+            ray: None,
+            point: Some([4.5, 0.5]),
+            layers: None,
+            max_distance: None,
+        };
+        let hit = pick(&input).unwrap().unwrap();
+        assert_eq!(hit.layer, "buildings");
+        assert!((hit.point[2] - 3.0).abs() < 1e-9);
+
+        let roads_only = PickInput {
+            layers: Some(vec!["roads".to_string()]),
+            ..input
+        };
+        let hit = pick(&roads_only).unwrap().unwrap();
+        assert_eq!(hit.properties.unwrap()["id"], serde_json::json!(2));
+
+        let miss = PickInput {
+            process_id: Some("pick-test".to_string()),
+            ray: Some(PickRay {
+                origin: [1.5, 0.5, 5.0],
+                direction: [0.0, 0.0, -1.0],
+            }),
+            point: None,
+            layers: None,
+            max_distance: None,
+        };
+        assert!(pick(&miss).unwrap().is_none());
+    }
+}
@@ -0,0 +1,250 @@
+// Polygon offsetting: positive distances grow a polygon, negative ones shrink it. The
+// exterior and its holes are offset together as one CavalierContours shape, so holes
+// shrink while the exterior grows, and parts that split off or vanish are handled.
+// Rounded corners of grown polygons come back as arcs and are flattened to segments.
+use crate::polygon_geometry::GeometryData;
+use crate::vectortile::point_in_ring;
+use cavalier_contours::polyline::{PlineSource, PlineSourceMut, PlineVertex, Polyline};
+use cavalier_contours::shape_algorithms::{Shape, ShapeOffsetOptions};
+
+/// Longest step along a flattened arc, in radians
+const ARC_STEP: f64 = std::f64::consts::PI / 8.0;
+
+/// Mean earth radius used for the local meter frame of `buffer_feature_meters`
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Polygon coordinates: the exterior ring followed by its holes, each ring closed
+pub(crate) type PolygonRings = Vec<Vec<Vec<f64>>>;
+
+/// Offset `rings` (exterior first, then holes) by `distance` coordinate units. The result
+/// has one entry per polygon: shrinking may split a polygon or remove it entirely.
+pub(crate) fn offset_polygon(rings: &[Vec<Vec<f64>>], distance: f64) -> Vec<PolygonRings> {
+    let Some(exterior) = rings.first().and_then(|ring| ring_to_pline(ring, true)) else {
+        return Vec::new();
+    };
+    let holes = rings[1..]
+        .iter()
+        .filter_map(|ring| ring_to_pline(ring, false));
+    let shape = Shape::from_plines(std::iter::once(exterior).chain(holes));
+    if !distance.is_finite() || distance == 0.0 {
+        return shape_to_polygons(&shape);
+    }
+    // CavalierContours offsets counter-clockwise rings inwards for positive distances
+    let offset = shape.parallel_offset(-distance, ShapeOffsetOptions::default());
+    shape_to_polygons(&offset)
+}
+
+/// `feature` grown or shrunk by `meters` in a local metric frame around its first point.
+/// Features other than Polygons are returned unchanged; a split polygon becomes several
+/// features sharing the properties, a vanished one none.
+pub(crate) fn buffer_feature_meters(feature: GeometryData, meters: f64) -> Vec<GeometryData> {
+    if feature.r#type.as_deref() != Some("Polygon") {
+        return vec![feature];
+    }
+    let Some(origin) = feature.geometry.first().filter(|p| p.len() >= 2) else {
+        return vec![feature];
+    };
+    let (lng0, lat0) = (origin[0], origin[1]);
+    let meters_per_lat = EARTH_RADIUS_M * std::f64::consts::PI / 180.0;
+    let meters_per_lng = meters_per_lat * lat0.to_radians().cos().max(1e-6);
+
+    let to_local = |ring: &Vec<Vec<f64>>| -> Vec<Vec<f64>> {
+        ring.iter()
+            .filter(|p| p.len() >= 2)
+            .map(|p| {
+                vec![
+                    (p[0] - lng0) * meters_per_lng,
+                    (p[1] - lat0) * meters_per_lat,
+                ]
+            })
+            .collect()
+    };
+    let to_geographic = |ring: Vec<Vec<f64>>| -> Vec<Vec<f64>> {
+        ring.into_iter()
+            .map(|p| vec![lng0 + p[0] / meters_per_lng, lat0 + p[1] / meters_per_lat])
+            .collect()
+    };
+
+    let rings: Vec<Vec<Vec<f64>>> = std::iter::once(&feature.geometry)
+        .chain(feature.holes.iter().flatten())
+        .map(to_local)
+        .collect();
+    offset_polygon(&rings, meters)
+        .into_iter()
+        .map(|polygon| {
+            let mut rings = polygon.into_iter().map(to_geographic);
+            let geometry = rings.next().unwrap_or_default();
+            let holes: Vec<Vec<Vec<f64>>> = rings.collect();
+            GeometryData {
+                geometry,
+                holes: if holes.is_empty() { None } else { Some(holes) },
+                ..feature.clone()
+            }
+        })
+        .collect()
+}
+
+// Closed polyline of a ring wound counter-clockwise (`ccw`) or clockwise; None for rings
+// with fewer than 3 distinct points or no area
+fn ring_to_pline(ring: &[Vec<f64>], ccw: bool) -> Option<Polyline<f64>> {
+    let mut points: Vec<(f64, f64)> = ring
+        .iter()
+        .filter(|p| p.len() >= 2 && p[0].is_finite() && p[1].is_finite())
+        .map(|p| (p[0], p[1]))
+        .collect();
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    if points.len() < 3 {
+        return None;
+    }
+    let mut pline = Polyline::new();
+    for (x, y) in points {
+        pline.add_vertex(PlineVertex::new(x, y, 0.0));
+    }
+    pline.set_is_closed(true);
+    let area = pline.area();
+    if area == 0.0 {
+        return None;
+    }
+    if (area > 0.0) != ccw {
+        pline.invert_direction_mut();
+    }
+    Some(pline)
+}
+
+// Polygons of a shape: every counter-clockwise ring with the clockwise rings inside it.
+// A hole belongs to the smallest exterior containing it.
+fn shape_to_polygons(shape: &Shape<f64>) -> Vec<PolygonRings> {
+    let mut polygons: Vec<(PolygonRings, f64)> = shape
+        .ccw_plines
+        .iter()
+        .map(|indexed| {
+            let ring = flatten_pline(&indexed.polyline);
+            (vec![ring], indexed.polyline.area().abs())
+        })
+        .collect();
+    for indexed in &shape.cw_plines {
+        let hole = flatten_pline(&indexed.polyline);
+        let owner = polygons
+            .iter()
+            .enumerate()
+            .filter(|(_, (rings, _))| point_in_ring(&hole[0], &rings[0]))
+            .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+            .map(|(index, _)| index);
+        if let Some(owner) = owner {
+            polygons[owner].0.push(hole);
+        }
+    }
+    polygons.into_iter().map(|(rings, _)| rings).collect()
+}
+
+// Closed ring of a closed polyline, arc segments replaced by points at most ARC_STEP apart
+fn flatten_pline(pline: &Polyline<f64>) -> Vec<Vec<f64>> {
+    let n = pline.vertex_count();
+    let mut ring = Vec::with_capacity(n + 1);
+    for i in 0..n {
+        let start = pline.at(i);
+        let end = pline.at((i + 1) % n);
+        ring.push(vec![start.x, start.y]);
+        if start.bulge.abs() < 1e-12 {
+            continue;
+        }
+        // The bulge is tan(sweep / 4); positive bulges run counter-clockwise
+        let sweep = 4.0 * start.bulge.atan();
+        let (dx, dy) = (end.x - start.x, end.y - start.y);
+        let chord = (dx * dx + dy * dy).sqrt();
+        if chord < 1e-12 {
+            continue;
+        }
+        let center_offset = (1.0 - start.bulge * start.bulge) / (4.0 * start.bulge);
+        let center_x = (start.x + end.x) / 2.0 - dy * center_offset;
+        let center_y = (start.y + end.y) / 2.0 + dx * center_offset;
+        let radius = (start.x - center_x).hypot(start.y - center_y);
+        let start_angle = (start.y - center_y).atan2(start.x - center_x);
+        let steps = (sweep.abs() / ARC_STEP).ceil() as usize;
+        for k in 1..steps {
+            let angle = start_angle + sweep * k as f64 / steps as f64;
+            ring.push(vec![
+                center_x + angle.cos() * radius,
+                center_y + angle.sin() * radius,
+            ]);
+        }
+    }
+    if let Some(first) = ring.first().cloned() {
+        ring.push(first);
+    }
+    ring
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64) -> Vec<Vec<f64>> {
+        vec![
+            vec![min, min],
+            vec![max, min],
+            vec![max, max],
+            vec![min, max],
+            vec![min, min],
+        ]
+    }
+
+    fn area(ring: &[Vec<f64>]) -> f64 {
+        ring.windows(2)
+            .map(|w| w[0][0] * w[1][1] - w[1][0] * w[0][1])
+            .sum::<f64>()
+            / 2.0
+    }
+
+    #[test]
+    fn test_grow_rounds_corners() {
+        let grown = offset_polygon(&[square(0.0, 10.0)], 1.0);
+        assert_eq!(grown.len(), 1);
+        assert_eq!(grown[0].len(), 1);
+        let exterior = &grown[0][0];
+        // 10x10 square, four 10x1 sides and a full circle of radius 1 from the corners
+        let expected = 100.0 + 40.0 + std::f64::consts::PI;
+        assert!((area(exterior) - expected).abs() < 0.1);
+        assert!(exterior
+            .iter()
+            .all(|p| p[0] >= -1.0 - 1e-9 && p[0] <= 11.0 + 1e-9));
+    }
+
+    #[test]
+    fn test_shrink_grows_holes_and_removes_small_polygons() {
+        let mut hole = square(4.0, 6.0);
+        hole.reverse();
+        let shrunk = offset_polygon(&[square(0.0, 10.0), hole], -0.5);
+        assert_eq!(shrunk.len(), 1);
+        assert_eq!(shrunk[0].len(), 2);
+        assert!((area(&shrunk[0][0]) - 81.0).abs() < 1e-6);
+        // The hole grows by 0.5 with rounded corners
+        let hole_area = area(&shrunk[0][1]).abs();
+        assert!((hole_area - (4.0 + 4.0 + std::f64::consts::PI * 0.25)).abs() < 0.05);
+
+        assert!(offset_polygon(&[square(0.0, 10.0)], -6.0).is_empty());
+    }
+
+    #[test]
+    fn test_buffer_feature_in_meters() {
+        // About 111 m square at the equator, shrunk by 10 m on every side
+        let feature = GeometryData {
+            geometry: square(0.0, 0.001),
+            holes: None,
+            r#type: Some("Polygon".to_string()),
+            height: Some(12.0),
+            layer: Some("building".to_string()),
+            label: None,
+            tags: None,
+            properties: None,
+        };
+        let shrunk = buffer_feature_meters(feature, -10.0);
+        assert_eq!(shrunk.len(), 1);
+        assert_eq!(shrunk[0].height, Some(12.0));
+        let inset = shrunk[0].geometry[0][0].min(shrunk[0].geometry[0][1]);
+        assert!((inset - 10.0 / 111_195.0).abs() < 1e-6);
+    }
+}
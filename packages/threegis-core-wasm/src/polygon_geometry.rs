@@ -1,23 +1,43 @@
 use crate::bbox_filter::polygon_intersects_bbox;
+use crate::brunnel::{self, BridgeParams, Brunnel, BrunnelConfig, TunnelMode};
+use crate::cancellation::ProcessCancellation;
+use crate::chunking::{now_ms, yield_now, AdaptiveChunker};
+use crate::console::{self, LogLevel};
+use crate::drape;
 use crate::extrude;
+use crate::filter_expression::{self, FilterContext};
+use crate::flat_map::{FlatMapConfig, LayerLevel};
+use crate::footprint_union;
+use crate::module_state::ModuleState;
+use crate::parallel;
+use crate::polygon_buffer;
+use crate::projection::{self, terrain_size};
+use crate::road_network;
+use crate::roof;
+use crate::solid_mesh::SolidMesh;
+use crate::terrain_index::{process_terrain_index, TerrainIndex};
+use crate::vertical_datum::{
+    fixed_meters_to_units, meters_to_terrain_units, sample_grid_bilinear, VerticalDatum,
+};
+use crate::water::WaterSurface;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::cell::RefCell;
-use js_sys::{Array, Float32Array};
-use serde_wasm_bindgen::to_value;
-use wasm_bindgen::prelude::JsValue;
+use std::sync::Arc;
 
 // Thread-local cache for the parsed terrain mesh vertices and grid dimensions.
-// Populated once at the start of `create_polygon_geometry` and cleared when done.
+// Populated once at the start of `generate_polygon_geometry` and cleared when done.
 // Stores the flat [x,y,z, x,y,z, …] array from the actual rendered terrain mesh.
 thread_local! {
-    static TERRAIN_MESH_VERTS: RefCell<Vec<f32>> = RefCell::new(Vec::new());
+    static TERRAIN_MESH_VERTS: RefCell<Option<Arc<Vec<f32>>>> = const { RefCell::new(None) };
     /// Width (columns) of the vertex grid. For CPU layout W = gridSize.width; for GPU W ≤ 64.
-    static TERRAIN_GRID_W: RefCell<usize> = RefCell::new(0);
+    static TERRAIN_GRID_W: RefCell<usize> = const { RefCell::new(0) };
     /// Height (rows) of the vertex grid. For CPU layout H = gridSize.height; for GPU H ≤ 64.
-    static TERRAIN_GRID_H: RefCell<usize> = RefCell::new(0);
+    static TERRAIN_GRID_H: RefCell<usize> = const { RefCell::new(0) };
     /// True when the vertex array uses the GPU interleaved layout (even = top, odd = bottom).
-    static TERRAIN_IS_GPU_LAYOUT: RefCell<bool> = RefCell::new(false);
+    static TERRAIN_IS_GPU_LAYOUT: RefCell<bool> = const { RefCell::new(false) };
+    /// Triangle index of the mesh when its indices are known; sampled before the vertex grid.
+    static TERRAIN_INDEX: RefCell<Option<Arc<TerrainIndex>>> = const { RefCell::new(None) };
 }
 
 
@@ -31,33 +51,52 @@ const BUILDING_SUBMERGE_OFFSET: f64 = 0.05; // How far buildings are embedded in
 const MIN_HEIGHT: f64 = 0.01; // Avoid zero or negative height for robust geometry
 const MAX_HEIGHT: f64 = 500.0;
 const MIN_CLEARANCE: f64 = 0.1; // Minimum clearance above terrain to avoid z-fighting and mesh intersections
-// Scale factor to make vertical exaggeration values more visible (must match terrain_mesh_gen.rs)
-const EXAGGERATION_SCALE_FACTOR: f64 = 5.0;
+const STACK_ORDER_STEP: f64 = 0.05; // Z separation per stackOrder level for overlapping terrain-aligned layers
+const MAX_STACK_ORDER: i32 = 20;
+const ENGRAVE_OVERSHOOT: f64 = 1.0; // How far engraving cutters reach above the terrain surface
+const ROAD_SNAP_TOLERANCE: f64 = 0.5; // Default distance (meters) within which road ends are snapped
+const METERS_PER_DEGREE: f64 = 111_195.0; // Meters per degree of latitude
 // Maximum edge length for subdivision (ensures terrain-aligned geometries follow terrain properly)
-// TERRAIN_SIZE is 200.0, terrain has ~255 segments (~0.78 units/segment)
+// The terrain is 200 units across by default with ~255 segments (~0.78 units/segment)
 // Increased from 0.5 to 2.0 for ~4x faster processing while maintaining acceptable terrain alignment
 const MAX_EDGE_LENGTH: f64 = 2.0;
 
 
-// Helper function to decode base64 string to f32 vector
+// Decode base64 (standard or URL-safe alphabet, padding optional) of little-endian f32 values
 fn decode_base64_to_f32_vec(base64_data: &str) -> Result<Vec<f32>, String> {
-    // For now, we'll implement a simple base64 decoder
-    // In a real implementation, you'd use a proper base64 library
-    // This is a placeholder implementation that assumes the data was properly encoded
-
-    // Simple approach: split by comma and parse as floats (assuming CSV format)
-    if base64_data.contains(',') {
-        let result: Result<Vec<f32>, _> = base64_data
-            .split(',')
-            .map(|s| s.trim().parse::<f32>())
-            .collect();
-        result.map_err(|e| format!("Failed to parse CSV data: {}", e))
-    } else {
-        // Empty or invalid data
-        Ok(Vec::new())
+    let sextet = |c: u8| -> Result<u32, String> {
+        match c {
+            b'A'..=b'Z' => Ok((c - b'A') as u32),
+            b'a'..=b'z' => Ok((c - b'a') as u32 + 26),
+            b'0'..=b'9' => Ok((c - b'0') as u32 + 52),
+            b'+' | b'-' => Ok(62),
+            b'/' | b'_' => Ok(63),
+            _ => Err(format!("Invalid base64 character '{}'", c as char)),
+        }
+    };
+    let symbols: Vec<u8> = base64_data
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+        .collect();
+    let mut bytes = Vec::with_capacity(symbols.len() / 4 * 3 + 2);
+    for group in symbols.chunks(4) {
+        if group.len() < 2 {
+            return Err("Truncated base64 data".to_string());
+        }
+        let mut bits = 0u32;
+        for (i, &c) in group.iter().enumerate() {
+            bits |= sextet(c)? << (18 - 6 * i);
+        }
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..group.len()]);
     }
+    if bytes.len() % 4 != 0 {
+        return Err(format!("{} bytes of base64 data are not whole f32 values", bytes.len()));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
 }
-const TERRAIN_SIZE: f64 = 200.0;
 const EPSILON: f64 = 1e-9; // Small value for float comparisons
 
 // Struct to represent a 2D point
@@ -83,6 +122,34 @@ pub struct GeometryData {
 
 // Helper functions for GeometryData
 impl GeometryData {
+    fn has_non_finite_points(&self) -> bool {
+        let finite = |point: &Vec<f64>| point.iter().all(|v| v.is_finite());
+        !self.geometry.iter().all(finite)
+            || self
+                .holes
+                .iter()
+                .flatten()
+                .any(|hole| !hole.iter().all(finite))
+    }
+
+    /// Copy with non-finite points dropped from the outer ring and holes
+    fn without_non_finite_points(&self) -> GeometryData {
+        let finite_ring = |ring: &Vec<Vec<f64>>| -> Vec<Vec<f64>> {
+            ring.iter()
+                .filter(|point| point.iter().all(|v| v.is_finite()))
+                .cloned()
+                .collect()
+        };
+        GeometryData {
+            geometry: finite_ring(&self.geometry),
+            holes: self
+                .holes
+                .as_ref()
+                .map(|holes| holes.iter().map(finite_ring).collect()),
+            ..self.clone()
+        }
+    }
+
     #[allow(dead_code)]
     pub fn get_label(&self) -> &str {
         self.label
@@ -101,15 +168,15 @@ impl GeometryData {
             }
             let lng = point[0];
             let lat = point[1];
-            if lng < -180.0 || lng > 180.0 {
+            if !(-180.0..=180.0).contains(&lng) {
                 return Err(format!("invalid longitude: {}", lng));
             }
-            if lat < -90.0 || lat > 90.0 {
+            if !(-90.0..=90.0).contains(&lat) {
                 return Err(format!("invalid latitude: {}", lat));
             }
         }
         if let Some(h) = self.height {
-            if h < 0.0 || h > MAX_HEIGHT {
+            if !(0.0..=MAX_HEIGHT).contains(&h) {
                 return Err(format!("invalid height: {} (must be 0-{})", h, MAX_HEIGHT));
             }
         }
@@ -118,7 +185,7 @@ impl GeometryData {
 }
 
 // Struct to match GridSize from TypeScript
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
 pub struct GridSize {
     pub width: u32,
     pub height: u32,
@@ -127,6 +194,10 @@ pub struct GridSize {
 // Struct to match VtDataSet from TypeScript
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VtDataSet {
+    /// Vector tile source registered with `register_vector_tile_source`; the default
+    /// source when None or "default"
+    #[serde(default)]
+    pub source: Option<String>,
     #[serde(default, rename = "sourceLayer")]
     pub source_layer: String,
     #[serde(default, rename = "label")]
@@ -135,6 +206,7 @@ pub struct VtDataSet {
     pub color: String,
     #[serde(rename = "bufferSize")]
     pub buffer_size: Option<f64>,
+    /// Negative depths engrave the layer into the terrain or base plate
     #[serde(rename = "extrusionDepth")]
     pub extrusion_depth: Option<f64>,
     #[serde(rename = "minExtrusionDepth")]
@@ -147,9 +219,142 @@ pub struct VtDataSet {
     pub apply_median_height: Option<bool>,
     #[serde(rename = "addTerrainDifferenceToHeight")]
     pub add_terrain_difference_to_height: Option<bool>,
+    /// Clip the layer to the terrain with CSG booleans; the input's top-level
+    /// `csgClipping` overrides it
+    #[serde(default, rename = "csgClipping")]
+    pub csg_clipping: Option<bool>,
     pub filter: Option<serde_json::Value>,
     #[serde(rename = "fixedBufferSize")]
     pub fixed_buffer_size: Option<bool>,
+    /// Lower clamp for extrusion heights in terrain units (default MIN_HEIGHT)
+    #[serde(default, rename = "minHeight")]
+    pub min_height: Option<f64>,
+    /// Upper clamp for extrusion heights in terrain units (default MAX_HEIGHT)
+    #[serde(default, rename = "maxHeight")]
+    pub max_height: Option<f64>,
+    /// Per-layer override of the pipeline `minClearance`
+    #[serde(default, rename = "minClearance")]
+    pub min_clearance: Option<f64>,
+    /// Per-layer override of the pipeline `submergeOffset`
+    #[serde(default, rename = "submergeOffset")]
+    pub submerge_offset: Option<f64>,
+    /// Stacking priority where terrain-aligned layers overlap; higher values sit on top
+    /// (e.g. landuse 0, roads 2, footways 3). Each level adds STACK_ORDER_STEP of height.
+    #[serde(default, rename = "stackOrder")]
+    pub stack_order: Option<i32>,
+    /// Road groove mode: engrave the layer's lines into the terrain top surface
+    #[serde(default)]
+    pub groove: Option<RoadGroove>,
+    /// Cut the layer's polygons through the whole base as holes (e.g. backlit water)
+    #[serde(default, rename = "throughCut")]
+    pub through_cut: Option<bool>,
+    /// Corner style of buffered lines (default "miter")
+    #[serde(default, rename = "lineJoin")]
+    pub line_join: Option<LineJoin>,
+    /// End style of buffered lines (default "butt")
+    #[serde(default, rename = "lineCap")]
+    pub line_cap: Option<LineCap>,
+    /// Longest miter join as a multiple of the half width before it is bevelled
+    #[serde(default, rename = "miterLimit")]
+    pub miter_limit: Option<f64>,
+    /// Also apply bufferSize to Polygon features, in meters: positive values grow
+    /// footprints, negative ones shrink them. Off by default, line layers share the field.
+    #[serde(default, rename = "bufferPolygons")]
+    pub buffer_polygons: Option<bool>,
+    /// Union overlapping footprints before extrusion into one solid per connected part
+    #[serde(default, rename = "unionFootprints")]
+    pub union_footprints: Option<bool>,
+    /// Transportation preprocessing: snap road ends, join pieces split at tile boundaries
+    /// and buffer the network as one unioned polygon set
+    #[serde(default, rename = "mergeRoadNetwork")]
+    pub merge_road_network: Option<bool>,
+    /// Distance in meters within which road ends are snapped together (default 0.5)
+    #[serde(default, rename = "roadSnapTolerance")]
+    pub road_snap_tolerance: Option<f64>,
+    /// MapLibre expression giving each feature a color such as "#rrggbb", e.g.
+    /// ["match", ["get", "class"], "primary", "#e07000", "#808080"]. Fills per-vertex
+    /// colors; features it gives no valid color get the layer color. ["zoom"] is null here.
+    #[serde(default, rename = "colorExpression")]
+    pub color_expression: Option<serde_json::Value>,
+    /// Build pitched roofs for features tagged with roof:shape (gabled, hipped, pyramidal,
+    /// skillion) on top of their walls; other features keep flat tops
+    #[serde(default, rename = "roofShapes")]
+    pub roof_shapes: Option<bool>,
+    /// Bridge and tunnel handling of transportation layers. Without it bridges and tunnels
+    /// follow the terrain like other roads.
+    #[serde(default)]
+    pub brunnel: Option<BrunnelConfig>,
+    /// Draw polygons as flat water slabs recessed below their shoreline; engrave_terrain
+    /// carves the pockets they sit in out of the terrain
+    #[serde(default, rename = "waterSurface")]
+    pub water_surface: Option<WaterSurface>,
+    /// Generate polygons as shells following the terrain triangles they cover exactly,
+    /// for flat features such as parks and land use; implies alignVerticesToTerrain
+    #[serde(default)]
+    pub drape: Option<bool>,
+}
+
+/// Groove dimensions for lines engraved instead of raised
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoadGroove {
+    /// Groove width, replacing bufferSize (same units)
+    #[serde(default)]
+    pub width: Option<f64>,
+    /// Groove depth in meters
+    pub depth: f64,
+}
+
+/// Corner style of buffered lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LineJoin {
+    /// Sharp corners, bevelled beyond the miter limit
+    #[default]
+    Miter,
+    Bevel,
+    Round,
+}
+
+/// End style of buffered lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LineCap {
+    /// Ends cut square at the end points
+    #[default]
+    Butt,
+    /// Ends extended by half the width
+    Square,
+    Round,
+}
+
+// Miter limit of 2/sqrt(3): turns sharper than 60° are bevelled
+const DEFAULT_MITER_LIMIT: f64 = 1.154_700_538_379_251_7;
+// Largest angle between the points of round joins and caps
+const ROUND_STEP: f64 = std::f64::consts::PI / 8.0;
+
+/// Join, cap and miter limit of buffered lines
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LineStyle {
+    #[serde(default, rename = "lineJoin")]
+    pub join: LineJoin,
+    #[serde(default, rename = "lineCap")]
+    pub cap: LineCap,
+    #[serde(default = "default_miter_limit", rename = "miterLimit")]
+    pub miter_limit: f64,
+}
+
+fn default_miter_limit() -> f64 {
+    DEFAULT_MITER_LIMIT
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        LineStyle {
+            join: LineJoin::default(),
+            cap: LineCap::default(),
+            miter_limit: DEFAULT_MITER_LIMIT,
+        }
+    }
 }
 
 // Helper function to get display label for a VtDataSet
@@ -158,14 +363,158 @@ impl VtDataSet {
         self.label.as_deref().unwrap_or(&self.source_layer)
     }
 
+    /// Height clamp range in terrain units; invalid or missing values fall back to the defaults
+    pub fn height_limits(&self) -> (f64, f64) {
+        let min = self
+            .min_height
+            .filter(|h| h.is_finite() && *h > 0.0)
+            .unwrap_or(MIN_HEIGHT);
+        let max = self
+            .max_height
+            .filter(|h| h.is_finite() && *h >= min)
+            .unwrap_or(MAX_HEIGHT.max(min));
+        (min, max)
+    }
+
+    pub fn clamp_height(&self, height: f64) -> f64 {
+        let (min, max) = self.height_limits();
+        height.clamp(min, max)
+    }
+
+    /// Z shift in terrain units derived from `stack_order`
+    pub fn stack_offset(&self) -> f64 {
+        self.stack_order
+            .unwrap_or(0)
+            .clamp(-MAX_STACK_ORDER, MAX_STACK_ORDER) as f64
+            * STACK_ORDER_STEP
+    }
+
+    /// Depth (meters) a groove or negative extrusion_depth engraves into the terrain or base
+    pub fn engrave_depth(&self) -> Option<f64> {
+        if self.through_cut() {
+            return None;
+        }
+        self.groove
+            .as_ref()
+            .map(|groove| groove.depth)
+            .or_else(|| self.extrusion_depth.filter(|d| *d < 0.0).map(|d| -d))
+    }
+
+    /// Line buffer size; a groove width takes precedence over bufferSize
+    pub fn line_buffer_size(&self) -> Option<f64> {
+        self.groove
+            .as_ref()
+            .and_then(|groove| groove.width)
+            .or(self.buffer_size)
+    }
+
+    /// Vertex color of `feature` from colorExpression, falling back to the layer color;
+    /// None without an expression
+    pub fn feature_color(&self, feature: &GeometryData) -> Option<[f32; 3]> {
+        let expression = self.color_expression.as_ref()?;
+        let properties = feature
+            .properties
+            .as_ref()
+            .or(feature.tags.as_ref())
+            .unwrap_or(&serde_json::Value::Null);
+        let context = FilterContext {
+            properties,
+            geometry_type: feature.r#type.as_deref().unwrap_or("Unknown"),
+            zoom: f64::NAN,
+        };
+        let color = filter_expression::evaluate_expression(expression, &context);
+        color
+            .as_str()
+            .and_then(parse_hex_color)
+            .or_else(|| parse_hex_color(&self.color))
+            .or(Some([0.5, 0.5, 0.5]))
+    }
+
+    /// Polygon buffer distance in meters when bufferPolygons is set
+    pub fn polygon_buffer_size(&self) -> Option<f64> {
+        if !self.buffer_polygons.unwrap_or(false) {
+            return None;
+        }
+        self.buffer_size.filter(|d| d.is_finite() && *d != 0.0)
+    }
+
+    pub fn through_cut(&self) -> bool {
+        self.through_cut.unwrap_or(false)
+    }
+
+    pub fn roof_shapes(&self) -> bool {
+        self.roof_shapes.unwrap_or(false)
+    }
+
+    pub fn drape(&self) -> bool {
+        self.drape.unwrap_or(false)
+    }
+
+    /// Whether a feature is a bridge or tunnel handled by the layer's brunnel config
+    pub(crate) fn brunnel_of(&self, feature: &GeometryData) -> Option<Brunnel> {
+        self.brunnel.as_ref()?;
+        brunnel::brunnel_of(feature)
+    }
+
+    /// Depth in terrain units to sink a feature by, for tunnels drawn recessed
+    pub(crate) fn tunnel_recess(&self, feature: &GeometryData) -> Option<f64> {
+        let config = self.brunnel.as_ref()?;
+        (config.tunnels == TunnelMode::Recessed
+            && brunnel::brunnel_of(feature) == Some(Brunnel::Tunnel))
+        .then(|| config.tunnel_depth() * fixed_meters_to_units())
+    }
+
+    /// Line style for buffering; miter limits below 1 would bevel every corner and fall
+    /// back to the default
+    pub fn line_style(&self) -> LineStyle {
+        LineStyle {
+            join: self.line_join.unwrap_or_default(),
+            cap: self.line_cap.unwrap_or_default(),
+            miter_limit: self
+                .miter_limit
+                .filter(|limit| limit.is_finite() && *limit >= 1.0)
+                .unwrap_or(DEFAULT_MITER_LIMIT),
+        }
+    }
+
+    /// Layer generates cutter solids subtracted from the terrain instead of features
+    pub fn is_engraved(&self) -> bool {
+        self.through_cut() || self.engrave_depth().is_some()
+    }
+
+    /// Whether generated vertices follow the terrain individually; engraved layers always
+    /// do, through-cuts never
+    pub fn is_terrain_aligned(&self) -> bool {
+        !self.through_cut()
+            && (self.align_vertices_to_terrain.unwrap_or(false) || self.engrave_depth().is_some())
+    }
+
+    fn validate_groove(&self) -> Result<(), String> {
+        let Some(groove) = &self.groove else {
+            return Ok(());
+        };
+        if !groove.depth.is_finite() || groove.depth <= 0.0 {
+            return Err(format!("groove depth must be positive, got {}", groove.depth));
+        }
+        if let Some(width) = groove.width.filter(|w| !w.is_finite() || *w <= 0.0) {
+            return Err(format!("groove width must be positive, got {}", width));
+        }
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn validate(&self) -> Result<(), String> {
         if self.source_layer.is_empty() {
             return Err("source_layer cannot be empty".to_string());
         }
         if let Some(depth) = self.extrusion_depth {
-            if depth < 0.0 {
-                return Err("extrusion_depth cannot be negative".to_string());
+            if !depth.is_finite() {
+                return Err("extrusion_depth must be finite".to_string());
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_height, self.max_height) {
+            if min > max {
+                return Err(format!("min_height {} exceeds max_height {}", min, max));
             }
         }
         Ok(())
@@ -177,29 +526,57 @@ fn default_color() -> String {
     "#4B85AA".to_string() // Default blue color for water
 }
 
+/// "#rgb" or "#rrggbb" as 0..1 RGB
+fn parse_hex_color(color: &str) -> Option<[f32; 3]> {
+    let hex = color.trim().strip_prefix('#')?;
+    let expanded: String;
+    let hex = match hex.len() {
+        3 => {
+            expanded = hex.chars().flat_map(|c| [c, c]).collect();
+            &expanded
+        }
+        6 => hex,
+        _ => return None,
+    };
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([16, 8, 0].map(|shift| ((rgb >> shift) & 0xff) as f32 / 255.0))
+}
+
 // Input for the polygon geometry processing function
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PolygonGeometryInput {
     pub bbox: Vec<f64>, // [minLng, minLat, maxLng, maxLat]
+    /// Features to build; `process_polygon_geometry` fills these from the process cache
+    #[serde(default, skip_serializing)]
     pub polygons: Vec<GeometryData>,
     #[allow(dead_code)] // Part of public API structure
-    #[serde(rename = "terrainBaseHeight")]
+    #[serde(default, rename = "terrainBaseHeight")]
     pub terrain_base_height: f64,
     #[allow(dead_code)] // Part of public API structure
-    #[serde(rename = "verticalExaggeration")]
+    #[serde(default, rename = "verticalExaggeration")]
     pub vertical_exaggeration: f64,
-    #[serde(rename = "elevationGrid")]
+    /// Terrain data is left out of serialization; the layer cache identifies the terrain
+    /// by generation instead (see `layer_cache::layer_config_hash`)
+    #[serde(default, rename = "elevationGrid", skip_serializing)]
     pub elevation_grid: Vec<Vec<f64>>,
-    #[serde(rename = "gridSize")]
+    #[serde(default, rename = "gridSize")]
     pub grid_size: GridSize,
-    #[serde(rename = "minElevation")]
+    #[serde(default, rename = "minElevation")]
     pub min_elevation: f64,
-    #[serde(rename = "maxElevation")]
+    #[serde(default, rename = "maxElevation")]
     pub max_elevation: f64,
-    // Terrain mesh data as base64-encoded strings to avoid serialization issues
-    #[serde(rename = "terrainVerticesBase64", default)]
+    /// Terrain mesh vertices as flat [x, y, z, …]; `process_polygon_geometry` takes a
+    /// Float32Array here without serializing it
+    #[serde(rename = "terrainVertices", default, skip_serializing)]
+    pub terrain_vertices: Vec<f32>,
+    /// Terrain mesh triangle indices; a Uint32Array in `process_polygon_geometry`
+    #[serde(rename = "terrainIndices", default, skip_serializing)]
+    pub terrain_indices: Vec<u32>,
+    /// Terrain mesh vertices as base64 of little-endian f32 values, when not sent as
+    /// `terrainVertices`. Comma-separated values are still accepted but deprecated.
+    #[serde(rename = "terrainVerticesBase64", default, skip_serializing)]
     pub terrain_vertices_base64: String,
-    #[serde(rename = "terrainIndicesBase64", default)]
+    #[serde(rename = "terrainIndicesBase64", default, skip_serializing)]
     pub terrain_indices_base64: String,
     /// Vertex-grid width (W): number of vertex columns in the terrain mesh.
     /// For the CPU path this equals `gridSize.width`; for the GPU path it is
@@ -221,9 +598,361 @@ pub struct PolygonGeometryInput {
     #[allow(dead_code)] // Part of public API structure
     #[serde(rename = "processId")]
     pub process_id: String,
-    // Optionally override CSG clipping for this request
+    // Optionally override CSG clipping for this request (`vtDataSet.csgClipping`)
     #[serde(rename = "csgClipping")]
     pub csg_clipping: Option<bool>,
+    /// Gap kept between terrain-aligned layers and the terrain surface, in terrain units.
+    /// Larger values avoid z-fighting in the preview; 0 closes visible gaps for printing.
+    #[serde(default, rename = "minClearance")]
+    pub min_clearance: Option<f64>,
+    /// Depth that buildings and roads are embedded into the terrain, in terrain units
+    #[serde(default, rename = "submergeOffset")]
+    pub submerge_offset: Option<f64>,
+    #[serde(default, rename = "processingMode")]
+    pub processing_mode: ProcessingMode,
+    /// Layers-only mode: extrude onto a flat base plate of this thickness (terrain units)
+    /// instead of the terrain. Elevation data is ignored and may be omitted.
+    #[serde(default, rename = "flatBaseThickness")]
+    pub flat_base_thickness: Option<f64>,
+    /// 2.5D flat map mode: constant layer thicknesses stacked on a flat base plate
+    #[serde(default, rename = "flatMap")]
+    pub flat_map: Option<FlatMapConfig>,
+    /// Build features in a content-defined order and stamp the geometries with a
+    /// `contentHash` property, so identical inputs give identical output
+    #[serde(default)]
+    pub reproducible: bool,
+}
+
+impl PolygonGeometryInput {
+    /// Use a flat row-major elevation grid (gridSize.height rows of gridSize.width values,
+    /// in the row order of `elevationGrid`) passed as a typed array
+    pub fn set_flat_elevation_grid(&mut self, values: &[f32]) -> Result<(), String> {
+        let width = self.grid_size.width as usize;
+        let height = self.grid_size.height as usize;
+        if width == 0 || values.len() != width * height {
+            return Err(format!(
+                "Flat elevationGrid has {} values, gridSize is {}x{}",
+                values.len(),
+                width,
+                height
+            ));
+        }
+        self.elevation_grid = values
+            .chunks(width)
+            .map(|row| row.iter().map(|&v| f64::from(v)).collect())
+            .collect();
+        Ok(())
+    }
+
+    /// Replace the terrain by a flat surface at the base plate thickness
+    fn apply_flat_base(&mut self) -> Result<(), String> {
+        if let Some(flat_map) = &self.flat_map {
+            flat_map.validate()?;
+            self.flat_base_thickness = Some(flat_map.base_thickness);
+            // Levels are extruded as solid blocks from the plate, never draped
+            self.vt_data_set.align_vertices_to_terrain = Some(false);
+        }
+        let Some(thickness) = self.flat_base_thickness else {
+            return Ok(());
+        };
+        if !thickness.is_finite() || thickness <= 0.0 {
+            return Err(format!("flatBaseThickness must be positive, got {}", thickness));
+        }
+        self.terrain_base_height = thickness;
+        self.vertical_exaggeration = 0.0;
+        self.elevation_grid = vec![vec![0.0; 2]; 2];
+        self.grid_size = GridSize {
+            width: 2,
+            height: 2,
+        };
+        self.min_elevation = 0.0;
+        self.max_elevation = 0.0;
+        self.terrain_vertices.clear();
+        self.terrain_indices.clear();
+        self.terrain_vertices_base64.clear();
+        self.terrain_indices_base64.clear();
+        Ok(())
+    }
+
+    /// CSG clipping of the request, else of the layer; off by default
+    fn use_csg_clipping(&self) -> bool {
+        self.csg_clipping
+            .or(self.vt_data_set.csg_clipping)
+            .unwrap_or(false)
+    }
+
+    /// Terrain mesh vertices from `terrainVertices`, else decoded from `terrainVerticesBase64`,
+    /// else the mesh stored for the process with `store_terrain_mesh`
+    fn terrain_mesh_vertices(&self) -> Result<Vec<f32>, String> {
+        if self.flat_base_thickness.is_some() {
+            return Ok(Vec::new());
+        }
+        if !self.terrain_vertices.is_empty() {
+            return Ok(self.terrain_vertices.clone());
+        }
+        let encoded = self.terrain_vertices_base64.trim();
+        if encoded.is_empty() {
+            return Ok(ModuleState::with(|state| {
+                state
+                    .terrain_meshes
+                    .get(&self.process_id)
+                    .map(|mesh| mesh.vertices.clone())
+                    .unwrap_or_default()
+            }));
+        }
+        if !encoded.contains(',') {
+            return decode_base64_to_f32_vec(encoded);
+        }
+        // Deprecated CSV transfer
+        console::record(
+            LogLevel::Warn,
+            "terrain-sample",
+            Some(&self.process_id),
+            "terrainVerticesBase64 as comma-separated values is deprecated; send terrainVertices as a Float32Array".to_string(),
+        );
+        encoded
+            .split(',')
+            .map(|s| s.trim().parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| format!("Failed to parse CSV data: {}", e))
+    }
+
+    /// Triangle indices matching `terrain_mesh_vertices`; empty for base64 transfers
+    fn terrain_mesh_indices(&self) -> Vec<u32> {
+        if !self.terrain_vertices.is_empty() {
+            return self.terrain_indices.clone();
+        }
+        if !self.terrain_vertices_base64.trim().is_empty() {
+            return Vec::new();
+        }
+        ModuleState::with(|state| {
+            state
+                .terrain_meshes
+                .get(&self.process_id)
+                .map(|mesh| mesh.indices.clone())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Engraved layers produce cutter solids that follow the surface per vertex;
+    /// through-cut cutters are solid blocks
+    fn apply_engraving(&mut self) -> Result<(), String> {
+        self.vt_data_set.validate_groove()?;
+        if self.vt_data_set.is_engraved() {
+            self.vt_data_set.align_vertices_to_terrain = Some(self.vt_data_set.is_terrain_aligned());
+        }
+        Ok(())
+    }
+
+    /// Draped layers sit on the terrain like terrain-aligned ones
+    fn apply_drape(&mut self) {
+        if self.vt_data_set.drape() && !self.vt_data_set.is_engraved() {
+            self.vt_data_set.align_vertices_to_terrain = Some(true);
+        }
+    }
+
+    /// Grow or shrink Polygon features by the layer's polygon buffer. Polygons that split
+    /// become several features and vanished ones are dropped, so feature indices in skip
+    /// reports refer to the buffered features.
+    fn apply_polygon_buffer(&mut self) {
+        let Some(meters) = self.vt_data_set.polygon_buffer_size() else {
+            return;
+        };
+        self.polygons = std::mem::take(&mut self.polygons)
+            .into_iter()
+            .flat_map(|feature| polygon_buffer::buffer_feature_meters(feature, meters))
+            .collect();
+    }
+
+    /// Half width of a buffered line in geographic units
+    fn line_buffer_distance(&self, is_major_road: bool) -> f64 {
+        let config_buffer_size = self
+            .vt_data_set
+            .line_buffer_size()
+            .unwrap_or(if is_major_road { 2.0 } else { 1.5 });
+        let bbox_lng_span = (self.bbox[2] - self.bbox[0]).abs().max(1e-10);
+        // When fixedBufferSize is set, use fixed geographic scale; otherwise scale by bbox (visual width)
+        if self.vt_data_set.fixed_buffer_size.unwrap_or(false) {
+            return config_buffer_size * 0.00001;
+        }
+        // Dynamic scaling: 0.5 for small bboxes (zoomed in), 0.3 for large bboxes (zoomed out)
+        // Formula: starts at 0.5, decays to 0.3 as span increases to 1.0
+        let strength = (0.5 - (bbox_lng_span * 0.2)).clamp(0.3, 0.5);
+        let factor = (bbox_lng_span / terrain_size()) * strength;
+        let scaled_config = config_buffer_size * factor;
+        if config_buffer_size < 0.001 {
+            scaled_config
+        } else {
+            let min_scaled = 0.4 * factor;
+            scaled_config.clamp(min_scaled, scaled_config.max(min_scaled))
+        }
+    }
+
+    /// Leave out tunnels when the layer's brunnel config skips them
+    fn apply_brunnels(&mut self) {
+        let skip_tunnels = self
+            .vt_data_set
+            .brunnel
+            .as_ref()
+            .is_some_and(|config| config.tunnels == TunnelMode::Skip);
+        if skip_tunnels {
+            self.polygons
+                .retain(|feature| brunnel::brunnel_of(feature) != Some(Brunnel::Tunnel));
+        }
+    }
+
+    /// Join the layer's road pieces into a network when mergeRoadNetwork is set, then
+    /// union the buffered network so no seams or gaps remain between tile pieces
+    fn apply_road_network(&mut self) {
+        if !self.vt_data_set.merge_road_network.unwrap_or(false) {
+            return;
+        }
+        let meters = self
+            .vt_data_set
+            .road_snap_tolerance
+            .filter(|t| t.is_finite() && *t >= 0.0)
+            .unwrap_or(ROAD_SNAP_TOLERANCE);
+        // Degrees of latitude; snapping along longitude is a little tighter
+        let tolerance = meters / METERS_PER_DEGREE;
+        self.polygons = road_network::merge_road_network(
+            std::mem::take(&mut self.polygons),
+            tolerance,
+            |feature| {
+                (
+                    self.footprint_group(feature),
+                    is_major_road_feature(feature),
+                )
+            },
+        );
+        self.vt_data_set.union_footprints = Some(true);
+    }
+
+    /// Union the footprints of features that extrude alike, when unionFootprints is set,
+    /// so overlapping roads or building parts become one solid without interior walls.
+    /// Lines are buffered to polygons first and no longer take the quad strip path; bridges
+    /// stay lines for their decks. Merged features keep the properties of the first
+    /// feature of their group.
+    fn apply_footprint_union(&mut self) {
+        if !self.vt_data_set.union_footprints.unwrap_or(false) {
+            return;
+        }
+        let style = self.vt_data_set.line_style();
+        let features: Vec<GeometryData> = std::mem::take(&mut self.polygons)
+            .into_iter()
+            .map(|feature| {
+                if feature.r#type.as_deref() != Some("LineString")
+                    || self.vt_data_set.brunnel_of(&feature) == Some(Brunnel::Bridge)
+                {
+                    return feature;
+                }
+                let distance = self.line_buffer_distance(is_major_road_feature(&feature));
+                let ring =
+                    create_linestring_buffer(&feature.geometry, distance, &self.bbox, &style);
+                GeometryData {
+                    geometry: ring.iter().map(|p| vec![p.x, p.y]).collect(),
+                    holes: None,
+                    r#type: Some("Polygon".to_string()),
+                    ..feature
+                }
+            })
+            .collect();
+        self.polygons =
+            footprint_union::union_footprints(features, |feature| self.footprint_group(feature));
+    }
+
+    // Features of one group get the same extrusion height
+    fn footprint_group(&self, feature: &GeometryData) -> String {
+        if self.vt_data_set.is_engraved() || self.vt_data_set.extrusion_depth.is_some() {
+            return String::new();
+        }
+        // Only buildings with the same roof share a footprint (and ridge)
+        let roof_spec = roof::roof_spec(feature).filter(|_| self.vt_data_set.roof_shapes());
+        let mut roof_key = match roof_spec {
+            Some(spec) => format!(";roof:{:?}", spec),
+            None => String::new(),
+        };
+        // Bridges and tunnels are not merged into the roads at ground level
+        if let Some(brunnel) = self.vt_data_set.brunnel_of(feature) {
+            roof_key.push_str(&format!(";brunnel:{:?}", brunnel));
+        }
+        if let Some(height) = feature.height.filter(|h| *h > 0.0) {
+            return format!("height:{:.3}{}", height, roof_key);
+        }
+        let class = feature
+            .properties
+            .as_ref()
+            .and_then(|properties| properties.get("class"))
+            .and_then(|class| class.as_str())
+            .unwrap_or("unknown");
+        format!("class:{}{}", class, roof_key)
+    }
+
+    /// Extrusion level of this layer in flat map mode; engraved layers have none
+    fn flat_map_level(&self) -> Result<Option<LayerLevel>, String> {
+        let Some(flat_map) = &self.flat_map else {
+            return Ok(None);
+        };
+        if self.vt_data_set.is_engraved() {
+            return Ok(None);
+        }
+        let label = self.vt_data_set.get_label();
+        flat_map
+            .level_of(label)
+            .map(Some)
+            .ok_or_else(|| format!("Layer '{}' is not part of the flat map", label))
+    }
+
+    /// Elevation→Z mapping shared with terrain generation
+    fn vertical_datum(&self) -> VerticalDatum {
+        VerticalDatum::new(
+            self.terrain_base_height,
+            self.vertical_exaggeration,
+            self.min_elevation,
+            self.max_elevation,
+            projection::process_scale(Some(&self.process_id)).model_scale,
+        )
+    }
+
+    /// Terrain surface Z at mesh-space points, sampled from the terrain mesh (or flat base
+    /// plate) the same way terrain-aligned layers are placed
+    pub(crate) fn surface_heights(&mut self, points: &[[f64; 2]]) -> Result<Vec<f64>, String> {
+        projection::activate_process(&self.process_id);
+        self.apply_flat_base()?;
+        let mesh = decode_terrain_mesh(self);
+        install_terrain_mesh(mesh.as_ref());
+        let datum = self.vertical_datum();
+        let heights = points
+            .iter()
+            .map(|p| sample_terrain_mesh_height_at_point(p[0], p[1], &self.elevation_grid, &datum))
+            .collect();
+        clear_terrain_mesh();
+        Ok(heights)
+    }
+
+    /// Terrain clearance for this layer: layer override, then pipeline setting, then default
+    pub(crate) fn min_clearance(&self) -> f64 {
+        let valid = |v: &f64| v.is_finite() && *v >= 0.0;
+        self.vt_data_set
+            .min_clearance
+            .filter(valid)
+            .or(self.min_clearance.filter(valid))
+            .unwrap_or(MIN_CLEARANCE)
+    }
+
+    /// Clearance including the layer's stacking shift, never below the terrain surface
+    fn stacked_clearance(&self) -> f64 {
+        (self.min_clearance() + self.vt_data_set.stack_offset()).max(0.0)
+    }
+
+    /// Submerge depth for this layer: layer override, then pipeline setting, then default
+    pub(crate) fn submerge_offset(&self) -> f64 {
+        let valid = |v: &f64| v.is_finite() && *v >= 0.0;
+        self.vt_data_set
+            .submerge_offset
+            .filter(valid)
+            .or(self.submerge_offset.filter(valid))
+            .unwrap_or(BUILDING_SUBMERGE_OFFSET)
+    }
 }
 
 // Output struct for the polygon geometry
@@ -240,10 +969,20 @@ pub struct BufferGeometry {
     pub properties: Option<std::collections::HashMap<String, serde_json::Value>>,
 }
 
+impl BufferGeometry {
+    /// Label of the VtDataSet that produced this geometry (see `tag_layer_metadata`)
+    pub fn layer_label(&self) -> Option<&str> {
+        self.properties
+            .as_ref()
+            .and_then(|p| p.get("__label"))
+            .and_then(|v| v.as_str())
+    }
+}
+
 /// Sample the terrain surface Z at a mesh-space (x, y) point by querying the **actual
 /// terrain mesh vertices** that were produced by `terrain_mesh_gen.rs` / `gpu_terrain.rs`
-/// and sent from TypeScript as a flat `[x0,y0,z0, x1,y1,z1, …]` CSV in
-/// `terrain_vertices_base64`.
+/// and sent from TypeScript as a flat `[x0,y0,z0, x1,y1,z1, …]` Float32Array in
+/// `terrain_vertices`.
 ///
 /// Supports both vertex layouts produced by the two terrain backends:
 ///
@@ -289,9 +1028,9 @@ fn sample_terrain_height_from_mesh(
     };
 
     // ── Bilinear interpolation ────────────────────────────────────────────────
-    let half = TERRAIN_SIZE / 2.0;
-    let nx = ((mesh_x + half) / TERRAIN_SIZE).clamp(0.0, 1.0);
-    let ny = ((mesh_y + half) / TERRAIN_SIZE).clamp(0.0, 1.0);
+    let half = terrain_size() / 2.0;
+    let nx = ((mesh_x + half) / terrain_size()).clamp(0.0, 1.0);
+    let ny = ((mesh_y + half) / terrain_size()).clamp(0.0, 1.0);
 
     let fx = nx * (w - 1) as f64;
     let fy = ny * (h - 1) as f64;
@@ -315,67 +1054,45 @@ fn sample_terrain_height_from_mesh(
     Some(z0 * (1.0 - dy) + z1 * dy)
 }
 
-/// Sample the terrain surface Z at a mesh-space (x, y) point by bilinear
-/// interpolation of the actual rendered terrain mesh vertices stored in the
-/// thread-local `TERRAIN_MESH_VERTS` (and companion dimension/layout thread-locals).
+/// Sample the terrain surface Z at a mesh-space (x, y) point on the actual rendered
+/// terrain mesh: the triangle under the point from `TERRAIN_INDEX` when the mesh came
+/// with indices, else bilinear interpolation of the vertices in `TERRAIN_MESH_VERTS`
+/// (and companion dimension/layout thread-locals).
+/// Falls back to the shared vertical datum applied to the elevation grid when no
+/// terrain mesh was supplied.
 fn sample_terrain_mesh_height_at_point(
     mesh_x: f64,
     mesh_y: f64,
-    _elevation_grid: &[Vec<f64>],
-    _grid_size: &GridSize,
-    _bbox: &[f64],
-    _min_elevation: f64,
-    _max_elevation: f64,
-    _vertical_exaggeration: f64,
-    _terrain_base_height: f64,
+    elevation_grid: &[Vec<f64>],
+    datum: &VerticalDatum,
 ) -> f64 {
-    TERRAIN_MESH_VERTS.with(|verts| {
-        let borrowed = verts.borrow();
+    let from_index = TERRAIN_INDEX.with(|index| {
+        index
+            .borrow()
+            .as_ref()
+            .and_then(|index| index.height_at(mesh_x, mesh_y))
+    });
+    if let Some(z) = from_index {
+        return z;
+    }
+    let from_mesh = TERRAIN_MESH_VERTS.with(|verts| {
+        let guard = verts.borrow();
+        let borrowed = guard.as_deref().map_or(&[][..], Vec::as_slice);
         let w = TERRAIN_GRID_W.with(|c| *c.borrow());
         let h = TERRAIN_GRID_H.with(|c| *c.borrow());
         let is_gpu = TERRAIN_IS_GPU_LAYOUT.with(|c| *c.borrow());
-        match sample_terrain_height_from_mesh(mesh_x, mesh_y, &borrowed, w, h, is_gpu) {
-            Some(z) => z,
-            None => {
-                0.0
-            }
-        }
-    })
-}
-
-// Used only by the removed DEM-formula path; kept so other modules can reference
-// it if needed in the future.
-#[allow(dead_code)]
-fn sample_elevation_from_grid(
-    normalized_x: f64,
-    normalized_y: f64,
-    elevation_grid: &[Vec<f64>],
-    grid_size: &GridSize,
-) -> f64 {
-    let source_width = grid_size.width as usize;
-    let source_height = grid_size.height as usize;
-
-    let src_x = normalized_x * (source_width - 1) as f64;
-    let src_y = normalized_y * (source_height - 1) as f64;
-
-    let x0 = src_x.floor() as usize;
-    let y0 = src_y.floor() as usize;
-    let x1 = (x0 + 1).min(source_width - 1);
-    let y1 = (y0 + 1).min(source_height - 1);
-
-    let dx = src_x - x0 as f64;
-    let dy = src_y - y0 as f64;
-
-    // Bilinear interpolation of elevation values
-    let v00 = elevation_grid[y0][x0];
-    let v10 = elevation_grid[y0][x1];
-    let v01 = elevation_grid[y1][x0];
-    let v11 = elevation_grid[y1][x1];
-
-    let v0 = v00 * (1.0 - dx) + v10 * dx;
-    let v1 = v01 * (1.0 - dx) + v11 * dx;
+        sample_terrain_height_from_mesh(mesh_x, mesh_y, borrowed, w, h, is_gpu)
+    });
 
-    v0 * (1.0 - dy) + v1 * dy
+    from_mesh.unwrap_or_else(|| {
+        let half = terrain_size() / 2.0;
+        let elevation = sample_grid_bilinear(
+            elevation_grid,
+            (mesh_x + half) / terrain_size(),
+            (mesh_y + half) / terrain_size(),
+        );
+        datum.elevation_to_z(elevation)
+    })
 }
 
 /// Subdivide polygon edges to ensure no edge is longer than max_length.
@@ -823,200 +1540,26 @@ fn clip_mesh_to_bbox_3d(
     (new_vertices, new_indices)
 }
 
-// Sample a terrain elevation at a specific geographic point with proper scaling.
-// Kept for reference / debugging but superseded by sample_terrain_mesh_height_at_point.
+// Transform geographic coordinates to mesh coordinates
+pub(crate) fn transform_to_mesh_coordinates(lng: f64, lat: f64, bbox: &[f64]) -> [f64; 2] {
+    projection::lng_lat_to_mesh(lng, lat, bbox, terrain_size())
+}
+
+// Terrain units per meter of elevation, using the same relief scaling as terrain_mesh_gen.rs
 #[allow(dead_code)]
-fn sample_terrain_elevation_at_point(
-    lng: f64,
-    lat: f64,
-    elevation_grid: &[Vec<f64>],
-    grid_size: &GridSize,
-    bbox: &[f64],
+pub(crate) fn calculate_building_vertical_scale(
     min_elevation: f64,
     max_elevation: f64,
     vertical_exaggeration: f64,
-    terrain_base_height: f64,
 ) -> f64 {
-    let min_lng = bbox[0];
-    let min_lat = bbox[1];
-    let max_lng = bbox[2];
-    let max_lat = bbox[3];
-
-    // Normalize coordinates to 0-1 range within the grid
-    let nx = (lng - min_lng) / (max_lng - min_lng);
-    let ny = (lat - min_lat) / (max_lat - min_lat);
-
-    // Convert to grid indices
-    let grid_width = grid_size.width as usize;
-    let grid_height = grid_size.height as usize;
-
-    let x = (nx * (grid_width as f64 - 1.0)).clamp(0.0, (grid_width as f64) - 1.001);
-    let y = (ny * (grid_height as f64 - 1.0)).clamp(0.0, (grid_height as f64) - 1.001);
-
-    let x0 = x.floor() as usize;
-    let y0 = y.floor() as usize;
-    let x1 = (x0 + 1).min(grid_width - 1);
-    let y1 = (y0 + 1).min(grid_height - 1);
-
-    let dx = x - x0 as f64;
-    let dy = y - y0 as f64;
-
-    // Bilinear interpolation of elevation values
-    let v00 = elevation_grid[y0][x0];
-    let v10 = elevation_grid[y0][x1];
-    let v01 = elevation_grid[y1][x0];
-    let v11 = elevation_grid[y1][x1];
-
-    let v0 = v00 * (1.0 - dx) + v10 * dx;
-    let v1 = v01 * (1.0 - dx) + v11 * dx;
-
-    let elevation = v0 * (1.0 - dy) + v1 * dy;
-
-    // Apply the EXACT same scaling as terrain_mesh_gen.rs (must match exactly)
-    let elevation_range = f64::max(1.0, max_elevation - min_elevation);
-    // CRITICAL: clamp normalized_elevation to [0,1] exactly as terrain_mesh_gen.rs does
-    let normalized_elevation = ((elevation - min_elevation) / elevation_range).clamp(0.0, 1.0);
-    let scaled_exaggeration = vertical_exaggeration * EXAGGERATION_SCALE_FACTOR;
-    let elevation_variation = normalized_elevation * scaled_exaggeration;
-
-    // Calculate final terrain height: base + elevation variation
-    let new_z = terrain_base_height + elevation_variation;
-
-    // Apply the SAME minimum constraint as terrain_mesh_gen.rs (MIN_TERRAIN_THICKNESS = 0.3)
-    const MIN_TERRAIN_THICKNESS_LOCAL: f64 = 0.3;
-    new_z.max(MIN_TERRAIN_THICKNESS_LOCAL)
-}
-
-/// Sample raw elevation (in meters) at a geographic point WITHOUT vertical exaggeration.
-/// Used for computing elevation differences for building height adjustments.
-fn sample_raw_elevation_at_point(
-    lng: f64,
-    lat: f64,
-    elevation_grid: &[Vec<f64>],
-    grid_size: &GridSize,
-    bbox: &[f64],
-) -> f64 {
-    let min_lng = bbox[0];
-    let min_lat = bbox[1];
-    let max_lng = bbox[2];
-    let max_lat = bbox[3];
-
-    // Normalize coordinates to 0-1 range within the grid
-    let nx = (lng - min_lng) / (max_lng - min_lng);
-    let ny = (lat - min_lat) / (max_lat - min_lat);
-
-    // Convert to grid indices
-    let grid_width = grid_size.width as usize;
-    let grid_height = grid_size.height as usize;
-
-    let x = (nx * (grid_width as f64 - 1.0)).clamp(0.0, (grid_width as f64) - 1.001);
-    let y = (ny * (grid_height as f64 - 1.0)).clamp(0.0, (grid_height as f64) - 1.001);
-
-    let x0 = x.floor() as usize;
-    let y0 = y.floor() as usize;
-    let x1 = (x0 + 1).min(grid_width - 1);
-    let y1 = (y0 + 1).min(grid_height - 1);
-
-    let dx = x - x0 as f64;
-    let dy = y - y0 as f64;
-
-    // Bilinear interpolation of elevation values (raw meters)
-    let v00 = elevation_grid[y0][x0];
-    let v10 = elevation_grid[y0][x1];
-    let v01 = elevation_grid[y1][x0];
-    let v11 = elevation_grid[y1][x1];
-
-    let v0 = v00 * (1.0 - dx) + v10 * dx;
-    let v1 = v01 * (1.0 - dx) + v11 * dx;
-
-    // Return raw elevation in meters (no exaggeration applied)
-    v0 * (1.0 - dy) + v1 * dy
-}
-
-// Sample elevation from processed elevation grid (no additional scaling needed)
-fn sample_processed_terrain_elevation(
-    lng: f64,
-    lat: f64,
-    elevation_grid: &[Vec<f64>],
-    grid_size: &GridSize,
-    bbox: &[f64],
-) -> f64 {
-    let min_lng = bbox[0];
-    let min_lat = bbox[1];
-    let max_lng = bbox[2];
-    let max_lat = bbox[3];
-
-    // Normalize coordinates to 0-1 range within the grid
-    let nx = (lng - min_lng) / (max_lng - min_lng);
-    let ny = (lat - min_lat) / (max_lat - min_lat);
-
-    // Convert to grid indices
-    let grid_width = grid_size.width as usize;
-    let grid_height = grid_size.height as usize;
-
-    let x = (nx * (grid_width as f64 - 1.0)).clamp(0.0, (grid_width as f64) - 1.001);
-    let y = (ny * (grid_height as f64 - 1.0)).clamp(0.0, (grid_height as f64) - 1.001);
-
-    let x0 = x.floor() as usize;
-    let y0 = y.floor() as usize;
-    let x1 = (x0 + 1).min(grid_width - 1);
-    let y1 = (y0 + 1).min(grid_height - 1);
-
-    let dx = x - x0 as f64;
-    let dy = y - y0 as f64;
-
-    // Bilinear interpolation of processed elevation values (already scaled)
-    let v00 = elevation_grid[y0][x0];
-    let v10 = elevation_grid[y0][x1];
-    let v01 = elevation_grid[y1][x0];
-    let v11 = elevation_grid[y1][x1];
-
-    let v0 = v00 * (1.0 - dx) + v10 * dx;
-    let v1 = v01 * (1.0 - dx) + v11 * dx;
-
-    // Return the interpolated processed elevation (no scaling needed)
-    v0 * (1.0 - dy) + v1 * dy
-}
-
-// Transform geographic coordinates to mesh coordinates
-fn transform_to_mesh_coordinates(lng: f64, lat: f64, bbox: &[f64]) -> [f64; 2] {
-    let min_lng = bbox[0];
-    let min_lat = bbox[1];
-    let max_lng = bbox[2];
-    let max_lat = bbox[3];
-
-    // Convert from geographic coords to normalized 0-1 space
-    let normalized_x = (lng - min_lng) / (max_lng - min_lng);
-    let normalized_y = (lat - min_lat) / (max_lat - min_lat);
-
-    // Convert to mesh coordinates (terrain is 200x200 units centered at origin)
-    let mesh_x = (normalized_x * TERRAIN_SIZE) - (TERRAIN_SIZE / 2.0);
-    let mesh_y = (normalized_y * TERRAIN_SIZE) - (TERRAIN_SIZE / 2.0);
-
-    [mesh_x, mesh_y]
-}
-
-// Calculate scaling factor to convert real-world meters to terrain units
-fn calculate_meters_to_terrain_units(bbox: &[f64]) -> f64 {
-    // Calculate the real-world dimensions of the bbox in meters
-    let lat_center = (bbox[1] + bbox[3]) / 2.0;
-    let lat_rad = lat_center.to_radians();
-
-    // Earth's radius in meters
-    const EARTH_RADIUS_M: f64 = 6_371_000.0;
-
-    // Calculate width and height in meters
-    let lng_diff = bbox[2] - bbox[0];
-    let lat_diff = bbox[3] - bbox[1];
-
-    let width_m = lng_diff.to_radians() * EARTH_RADIUS_M * lat_rad.cos();
-    let height_m = lat_diff.to_radians() * EARTH_RADIUS_M;
-
-    // Use average dimension for consistent scaling
-    let avg_dimension_m = (width_m + height_m) / 2.0;
-
-    // Return terrain units per meter
-    TERRAIN_SIZE / avg_dimension_m
+    VerticalDatum::new(
+        0.0,
+        vertical_exaggeration,
+        min_elevation,
+        max_elevation,
+        projection::model_scale(),
+    )
+    .units_per_elevation_meter()
 }
 
 // Check if points are ordered clockwise
@@ -1065,8 +1608,7 @@ fn is_valid_polygon(points: &[Vector2]) -> bool {
 
         // Check if all points are on the same line
         let mut all_collinear = true;
-        for i in 2..points.len() {
-            let p = points[i];
+        for &p in &points[2..] {
 
             // Cross product should be near zero if collinear
             let cross = (p.y - p0.y) * dx - (p.x - p0.x) * dy;
@@ -1108,8 +1650,7 @@ fn clean_polygon_footprint(points: &[Vector2]) -> Vec<Vector2> {
     cleaned.push(points[0]); // Start with the first point
 
     // Remove consecutive duplicates
-    for i in 1..points.len() {
-        let current_point = points[i];
+    for &current_point in &points[1..] {
         // Safe unwrap: cleaned always has at least one element here
         let last_added_point = *cleaned.last().unwrap();
 
@@ -1160,7 +1701,7 @@ fn clip_polygon_to_bbox_2d(
 
     // Use robust polygon-bbox intersection check for early rejection
     let polygon_coords: Vec<Vec<f64>> = unique_shape_points.iter().map(|p| vec![p.x, p.y]).collect();
-    if !crate::bbox_filter::polygon_intersects_bbox(&polygon_coords, mesh_bbox_coords) {
+    if !crate::bbox_filter::polygon_intersects_bbox(&polygon_coords, &[], mesh_bbox_coords) {
         return Vec::new();
     }
 
@@ -1170,7 +1711,7 @@ fn clip_polygon_to_bbox_2d(
         ccw_points.reverse();
     }
 
-    return simple_clip_polygon(&ccw_points, mesh_bbox_coords);
+    simple_clip_polygon(&ccw_points, mesh_bbox_coords)
 }
 
 // Simple clipping fallback when CSG fails
@@ -1191,7 +1732,7 @@ fn simple_clip_polygon(points: &[Vector2], bbox: &[f64; 4]) -> Vec<Vector2> {
     let bbox_array = [min_x, min_y, max_x, max_y];
 
     // Use the robust polygon-bbox intersection check
-    if !polygon_intersects_bbox(&polygon_coords, &bbox_array) {
+    if !polygon_intersects_bbox(&polygon_coords, &[], &bbox_array) {
         return Vec::new();
     }
 
@@ -1402,16 +1943,16 @@ fn clean_polygon_for_triangulation(points: &[Vector2]) -> Option<Vec<Vector2>> {
 
 /// Create extruded geometry from a pre-triangulated quad strip (for terrain-aligned roads)
 /// This bypasses earcut entirely, creating geometry with small quads that follow terrain well
+#[allow(clippy::too_many_arguments)]
 fn create_extruded_shape_from_quad_strip(
     quad_mesh: &LineStringMesh,
     height: f64,
     bbox: &[f64],
     elevation_grid: &[Vec<f64>],
-    grid_size: &GridSize,
-    min_elevation: f64,
-    max_elevation: f64,
-    vertical_exaggeration: f64,
-    terrain_base_height: f64,
+    _grid_size: &GridSize,
+    datum: &VerticalDatum,
+    submerge_offset: f64,
+    stack_offset: f64,
     _terrain_vertices_base64: &str,
     _terrain_indices_base64: &str,
     properties: Option<std::collections::HashMap<String, serde_json::Value>>,
@@ -1429,6 +1970,7 @@ fn create_extruded_shape_from_quad_strip(
         };
     }
 
+
     // Transform 2D vertices to mesh coordinates and sample terrain elevation
     let mut bottom_verts: Vec<[f32; 3]> = Vec::with_capacity(num_2d_verts);
     let mut top_verts: Vec<[f32; 3]> = Vec::with_capacity(num_2d_verts);
@@ -1441,21 +1983,13 @@ fn create_extruded_shape_from_quad_strip(
         let [mesh_x, mesh_y] = transform_to_mesh_coordinates(geo_x, geo_y, bbox);
 
         // Sample terrain elevation at this point using grid-based method
-        let terrain_z = sample_terrain_mesh_height_at_point(
-            mesh_x,
-            mesh_y,
-            elevation_grid,
-            grid_size,
-            bbox,
-            min_elevation,
-            max_elevation,
-            vertical_exaggeration,
-            terrain_base_height,
-        ) as f32;
+        let terrain_z =
+            sample_terrain_mesh_height_at_point(mesh_x, mesh_y, elevation_grid, datum) as f32;
 
         // Small offset to embed bottom slightly into terrain
-        let bottom_z = terrain_z - 0.05;
-        let top_z = terrain_z + height as f32;
+        let bottom_z = terrain_z - submerge_offset as f32;
+        // Stacking raises only the top so overlapping strips stay anchored in the terrain
+        let top_z = terrain_z + (height + stack_offset).max(MIN_HEIGHT) as f32;
 
         bottom_verts.push([mesh_x as f32, mesh_y as f32, bottom_z]);
         top_verts.push([mesh_x as f32, mesh_y as f32, top_z]);
@@ -1584,26 +2118,25 @@ fn create_extruded_shape_from_quad_strip(
 }
 
 // REVISED: Improved implementation of an extruded shape using the extrude_geometry function
+#[allow(clippy::too_many_arguments)]
 fn create_extruded_shape(
     unique_shape_points: &[Vector2],
     holes: Option<&Vec<Vec<Vec<f64>>>>,
     height: f64,
     z_offset: f64,
+    clearance: f64,
     properties: Option<std::collections::HashMap<String, serde_json::Value>>,
     align_vertices_to_terrain: bool,
     elevation_grid: Option<&[Vec<f64>]>,
     grid_size: Option<&GridSize>,
     bbox: Option<&[f64]>,
-    min_elevation: Option<f64>,
-    max_elevation: Option<f64>,
-    vertical_exaggeration: Option<f64>,
-    terrain_base_height: Option<f64>,
+    datum: Option<&VerticalDatum>,
     _source_layer: Option<&str>,
-    terrain_vertices_base64: Option<&str>,
-    terrain_indices_base64: Option<&str>,
+    _terrain_vertices_base64: Option<&str>,
+    _terrain_indices_base64: Option<&str>,
 ) -> BufferGeometry {
-    // Basic validation
-    if height < MIN_HEIGHT {
+    // Basic validation; callers clamp to the layer's height limits beforehand
+    if height <= 0.0 || height.is_nan() {
         return BufferGeometry {
             vertices: Vec::new(),
             normals: None,
@@ -1647,6 +2180,7 @@ fn create_extruded_shape(
                 None,
                 height,
                 z_offset,
+                clearance,
                 None,
                 false,
                 None,
@@ -1656,9 +2190,6 @@ fn create_extruded_shape(
                 None,
                 None,
                 None,
-                None,
-                None,
-                None,
             );
         } else if unique_shape_points.len() == 2 {
             // For two points, create a thin rectangle along the line
@@ -1716,6 +2247,7 @@ fn create_extruded_shape(
                 None,
                 height,
                 z_offset,
+                clearance,
                 None,
                 false,
                 None,
@@ -1725,9 +2257,6 @@ fn create_extruded_shape(
                 None,
                 None,
                 None,
-                None,
-                None,
-                None,
             );
         }
 
@@ -1775,138 +2304,23 @@ fn create_extruded_shape(
     // Create an array of shapes (only one shape for now)
     let shapes = vec![shape_with_rings];
 
-    // Create options for extrusion
-    let options = serde_json::json!({
-        "depth": height,
-        "steps": 1,
-    });
-
-    // Convert inputs to JsValue
-    let _shapes_js = match to_value(&shapes) {
-        Ok(val) => val,
-        Err(_e) => {
-            return BufferGeometry {
-                vertices: Vec::new(),
-                normals: None,
-                colors: None,
-                indices: None,
-                uvs: None,
-                has_data: false,
-                properties: None,
-            };
-        }
-    };
-
-    let _options_js = match to_value(&options) {
-        Ok(val) => val,
-        Err(_e) => {
-            return BufferGeometry {
-                vertices: Vec::new(),
-                normals: None,
-                colors: None,
-                indices: None,
-                uvs: None,
-                has_data: false,
-                properties: None,
-            };
-        }
-    };
-
     // Call the extrude_shape function with native Rust types
     // Always include bottom faces for manifold geometry (required for 3D printing)
     let skip_bottom_face = false; // Always generate bottom faces for manifold geometry
-    let extruded_js = match extrude::extrude_shape_with_options(shapes, height, 1, skip_bottom_face)
-    {
-        Ok(val) => val,
-        Err(_e) => {
-            return BufferGeometry {
-                vertices: Vec::new(),
-                normals: None,
-                colors: None,
-                indices: None,
-                uvs: None,
-                has_data: false,
-                properties: None,
-            };
-        }
-    };
+    let extrude::ExtrudedBuffers {
+        mut vertices,
+        normals,
+        mut indices,
+        uvs,
+    } = extrude::extrude_shape_buffers(shapes, height, 1, skip_bottom_face);
 
     // Apply z_offset to the vertices ONLY if NOT using per-vertex terrain alignment
     // When align_vertices_to_terrain is true, we'll handle Z positioning per-vertex later
     if z_offset != 0.0 && !align_vertices_to_terrain {
-        // Get position array from extruded_js
-        let position_js = js_sys::Reflect::get(&extruded_js, &JsValue::from_str("position"))
-            .unwrap_or(JsValue::null());
-        if position_js.is_null() {
-            return BufferGeometry {
-                vertices: Vec::new(),
-                normals: None,
-                colors: None,
-                indices: None,
-                uvs: None,
-                has_data: false,
-                properties: None,
-            };
-        }
-
-        let position_array = Float32Array::from(position_js);
-        let mut vertices = vec![0.0; position_array.length() as usize];
-        position_array.copy_to(&mut vertices);
-
         // Apply z_offset to each z value (every 3rd element)
         for i in (2..vertices.len()).step_by(3) {
             vertices[i] += z_offset as f32;
         }
-
-        // Replace the position array in the result
-        let new_position = Float32Array::from(vertices.as_slice());
-        js_sys::Reflect::set(&extruded_js, &JsValue::from_str("position"), &new_position).unwrap();
-    }
-
-    // Convert the extrusion result to our BufferGeometry struct
-    let position_js = js_sys::Reflect::get(&extruded_js, &JsValue::from_str("position"))
-        .unwrap_or(JsValue::null());
-    let normal_js =
-        js_sys::Reflect::get(&extruded_js, &JsValue::from_str("normal")).unwrap_or(JsValue::null());
-    let index_js =
-        js_sys::Reflect::get(&extruded_js, &JsValue::from_str("index")).unwrap_or(JsValue::null());
-    let uv_js =
-        js_sys::Reflect::get(&extruded_js, &JsValue::from_str("uv")).unwrap_or(JsValue::null());
-
-    let mut vertices = Vec::new();
-    let mut normals = Vec::new();
-    let mut indices = Vec::new();
-    let mut uvs = Vec::new();
-
-    // Extract position
-    if !position_js.is_null() {
-        let position_array = Float32Array::from(position_js);
-        vertices = vec![0.0; position_array.length() as usize];
-        position_array.copy_to(&mut vertices);
-    }
-
-    // Extract normals
-    if !normal_js.is_null() {
-        let normal_array = Float32Array::from(normal_js);
-        normals = vec![0.0; normal_array.length() as usize];
-        normal_array.copy_to(&mut normals);
-    }
-
-    // Extract indices
-    if !index_js.is_null() {
-        let index_array = Array::from(&index_js);
-        indices = Vec::with_capacity(index_array.length() as usize);
-        for i in 0..index_array.length() {
-            let value = index_array.get(i);
-            indices.push(value.as_f64().unwrap_or(0.0) as u32);
-        }
-    }
-
-    // Extract uvs
-    if !uv_js.is_null() {
-        let uv_array = Float32Array::from(uv_js);
-        uvs = vec![0.0; uv_array.length() as usize];
-        uv_array.copy_to(&mut uvs);
     }
 
     // IMPORTANT: Subdivide the 3D mesh BEFORE terrain alignment if enabled
@@ -1924,19 +2338,11 @@ fn create_extruded_shape(
         let has_elevation_data = elevation_grid.is_some() 
             && grid_size.is_some() 
             && bbox.is_some()
-            && min_elevation.is_some()
-            && max_elevation.is_some()
-            && vertical_exaggeration.is_some()
-            && terrain_base_height.is_some();
+            && datum.is_some();
 
         if has_elevation_data {
             let elev_grid = elevation_grid.unwrap();
-            let g_size = grid_size.unwrap();
-            let b_box = bbox.unwrap();
-            let min_elev = min_elevation.unwrap();
-            let max_elev = max_elevation.unwrap();
-            let vert_exag = vertical_exaggeration.unwrap();
-            let base_height = terrain_base_height.unwrap();
+            let datum = datum.unwrap();
 
             // Find the original geometry's min and max Z to determine the extrusion height.
             // The extrude function produces Z=0 for bottom and Z=height for top vertices.
@@ -1961,17 +2367,8 @@ fn create_extruded_shape(
                 let current_z = vertices[base_idx + 2];
 
                 // Sample the terrain height at THIS SPECIFIC X,Y position
-                let terrain_height_at_this_point = sample_terrain_mesh_height_at_point(
-                    mesh_x,
-                    mesh_y,
-                    elev_grid,
-                    g_size,
-                    b_box,
-                    min_elev,
-                    max_elev,
-                    vert_exag,
-                    base_height,
-                );
+                let terrain_height_at_this_point =
+                    sample_terrain_mesh_height_at_point(mesh_x, mesh_y, elev_grid, datum);
 
                 // Compute a normalized height fraction [0..1] relative to the original
                 // extrusion range. This correctly handles side-wall midpoint vertices
@@ -1988,10 +2385,10 @@ fn create_extruded_shape(
                 // adds the full extrusion height.  Side-wall midpoints are interpolated,
                 // which keeps the geometry continuous across extreme exaggeration values.
                 // base_clearance:
-                //   buildings  → -BUILDING_SUBMERGE_OFFSET  (embeds slightly into terrain)
-                //   other layers → user_z_offset + MIN_CLEARANCE (floats above terrain)
+                //   buildings  → -submerge offset  (embeds slightly into terrain)
+                //   other layers → configured clearance (floats above terrain)
                 vertices[base_idx + 2] =
-                    (terrain_height_at_this_point + MIN_CLEARANCE + t * extrusion_height) as f32;
+                    (terrain_height_at_this_point + clearance + t * extrusion_height) as f32;
             }
         }
     }
@@ -2014,95 +2411,222 @@ fn create_extruded_shape(
         },
         colors: None,
         uvs: if uvs.is_empty() { None } else { Some(uvs) },
-        has_data: has_data,
+        has_data,
         properties,
     }
 }
 
+// Attach the source layer and display label so downstream grouping and exports
+// (object names in 3MF/STL) can identify which VtDataSet produced a geometry
+pub(crate) fn tag_layer_metadata(
+    properties: &mut Option<HashMap<String, serde_json::Value>>,
+    vt_data_set: &VtDataSet,
+) {
+    let props = properties.get_or_insert_with(HashMap::new);
+    props
+        .entry("__sourceLayer".to_string())
+        .or_insert_with(|| serde_json::Value::String(vt_data_set.source_layer.clone()));
+    props
+        .entry("__label".to_string())
+        .or_insert_with(|| serde_json::Value::String(vt_data_set.get_label().to_string()));
+    if vt_data_set.is_engraved() {
+        props.insert("__engrave".to_string(), serde_json::Value::Bool(true));
+    } else if vt_data_set.water_surface.is_some() {
+        props.insert("__water".to_string(), serde_json::Value::Bool(true));
+    }
+}
+
 // Process the polygon geometry input and produce a buffer geometry output
 // Constants for performance optimization
-const MAX_CHUNK_SIZE: usize = 500; // Larger chunks for better throughput with faster per-polygon processing
 #[allow(dead_code)]
 const MIN_AREA_THRESHOLD: f64 = 0.0001; // Skip very small polygons for performance
 
-pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
-    // Parse the input JSON
-    let input: PolygonGeometryInput = match serde_json::from_str(input_json) {
-        Ok(data) => data,
-        Err(e) => return Err(format!("Failed to parse input JSON: {}", e)),
-    };
+/// How malformed input features are handled
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProcessingMode {
+    /// Repair what can be repaired, skip and report the rest
+    #[default]
+    Lenient,
+    /// Fail the layer on the first invalid feature (CI and data-quality checks)
+    Strict,
+}
 
-    // ── Load actual terrain mesh vertices into thread-local for sampling ──────
-    // This is the Float32Array produced by terrain_mesh_gen / gpu_terrain and
-    // sent back as a comma-separated CSV in `terrain_vertices_base64`.
-    // All height sampling will query these real mesh Z values instead of
-    // re-running the DEM formula, which may differ from the GPU terrain path.
-    if !input.terrain_vertices_base64.is_empty() {
-        match decode_base64_to_f32_vec(&input.terrain_vertices_base64) {
-            Ok(verts) if !verts.is_empty() => {
-                // Determine and cache grid dimensions.
-                // TypeScript sends explicit terrainGridWidth / terrainGridHeight when known.
-                // Fall back to deriving from vertex count (assumes square grid) only when 0.
-                let (w, h) = if input.terrain_grid_width > 0 && input.terrain_grid_height > 0 {
-                    (input.terrain_grid_width as usize, input.terrain_grid_height as usize)
-                } else {
-                    // Auto-detect: total_verts = 2 * W * H (both layouts)
-                    let total_verts = verts.len() / 3;
-                    let layer = total_verts / 2;
-                    let w_auto = (layer as f64).sqrt().round() as usize;
-                    (w_auto, w_auto)
-                };
+/// Why a feature was left out of a generated layer
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SkipReason {
+    NanCoordinates,
+    InvalidRing,
+    TriangulationFailure,
+}
 
-                // Detect layout: if TypeScript sent the flag, trust it.
-                // Otherwise fall back to structural check: for GPU layout vertex 0
-                // (top vertex) has z == terrain_base_height + elevation_variation > 0.
-                // For CPU layout vertex 0 is a bottom vertex with z == 0.
-                // We compare x,y of vertex 0 and vertex 1: GPU interleaving means
-                // the bottom vertex (v1) shares the same x,y as the top vertex (v0).
-                let is_gpu = if input.terrain_grid_width > 0 {
-                    // Explicit flag sent from TypeScript
-                    input.terrain_is_gpu_layout
-                } else if verts.len() >= 6 {
-                    // Structural heuristic: GPU layout pairs top+bottom at same (x,y).
-                    // So vertices 0 and 1 have identical x and y.
-                    let x0 = verts[0];
-                    let y0 = verts[1];
-                    let x1 = verts[3];
-                    let y1 = verts[4];
-                    (x0 - x1).abs() < 1e-4 && (y0 - y1).abs() < 1e-4
-                } else {
-                    false
-                };
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SkippedFeature {
+    /// Index of the feature in the layer input
+    pub index: usize,
+    pub reason: SkipReason,
+    pub message: String,
+}
+
+impl SkippedFeature {
+    fn new(index: usize, reason: SkipReason, message: impl Into<String>) -> Self {
+        SkippedFeature {
+            index,
+            reason,
+            message: message.into(),
+        }
+    }
+}
+
+/// Terrain mesh vertices sampled for layer heights, see `TERRAIN_MESH_VERTS`
+struct TerrainMeshSample {
+    verts: Arc<Vec<f32>>,
+    width: usize,
+    height: usize,
+    is_gpu: bool,
+    index: Option<Arc<TerrainIndex>>,
+}
 
-                // Log what we detected so it's visible in the browser console.
-                web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(&format!(
-                    "[terrain-sample] verts={} floats, w={}, h={}, gpu_layout={}, \
+/// Decode the terrain mesh sent with a layer input. This is the Float32Array produced by
+/// terrain_mesh_gen / gpu_terrain and sent back as `terrainVertices` (or base64 in
+/// `terrainVerticesBase64`). All height sampling will query these real mesh Z values
+/// instead of re-running the DEM formula, which may differ from the GPU terrain path.
+fn decode_terrain_mesh(input: &PolygonGeometryInput) -> Option<TerrainMeshSample> {
+    match input.terrain_mesh_vertices() {
+        Ok(verts) if !verts.is_empty() => {
+            // Determine and cache grid dimensions.
+            // TypeScript sends explicit terrainGridWidth / terrainGridHeight when known.
+            // Fall back to deriving from vertex count (assumes square grid) only when 0.
+            let (w, h) = if input.terrain_grid_width > 0 && input.terrain_grid_height > 0 {
+                (input.terrain_grid_width as usize, input.terrain_grid_height as usize)
+            } else {
+                // Auto-detect: total_verts = 2 * W * H (both layouts)
+                let total_verts = verts.len() / 3;
+                let layer = total_verts / 2;
+                let w_auto = (layer as f64).sqrt().round() as usize;
+                (w_auto, w_auto)
+            };
+
+            // Detect layout: if TypeScript sent the flag, trust it.
+            // Otherwise fall back to structural check: for GPU layout vertex 0
+            // (top vertex) has z == terrain_base_height + elevation_variation > 0.
+            // For CPU layout vertex 0 is a bottom vertex with z == 0.
+            // We compare x,y of vertex 0 and vertex 1: GPU interleaving means
+            // the bottom vertex (v1) shares the same x,y as the top vertex (v0).
+            let is_gpu = if input.terrain_grid_width > 0 {
+                // Explicit flag sent from TypeScript
+                input.terrain_is_gpu_layout
+            } else if verts.len() >= 6 {
+                // Structural heuristic: GPU layout pairs top+bottom at same (x,y).
+                // So vertices 0 and 1 have identical x and y.
+                let x0 = verts[0];
+                let y0 = verts[1];
+                let x1 = verts[3];
+                let y1 = verts[4];
+                (x0 - x1).abs() < 1e-4 && (y0 - y1).abs() < 1e-4
+            } else {
+                false
+            };
+
+            // Log what we detected so it's visible in the browser console.
+            console::record(
+                LogLevel::Debug,
+                "terrain-sample",
+                Some(&input.process_id),
+                format!(
+                    "verts={} floats, w={}, h={}, gpu_layout={}, \
                      v0=({:.2},{:.2},{:.2}), v1=({:.2},{:.2},{:.2})",
                     verts.len(), w, h, is_gpu,
                     verts[0], verts[1], verts[2],
                     verts.get(3).copied().unwrap_or(0.0),
                     verts.get(4).copied().unwrap_or(0.0),
                     verts.get(5).copied().unwrap_or(0.0),
-                )));
-                TERRAIN_GRID_W.with(|c| *c.borrow_mut() = w);
-                TERRAIN_GRID_H.with(|c| *c.borrow_mut() = h);
-                TERRAIN_IS_GPU_LAYOUT.with(|c| *c.borrow_mut() = is_gpu);
-                TERRAIN_MESH_VERTS.with(|cell| {
-                    *cell.borrow_mut() = verts;
-                });
-            }
-            _ => {
-                // Parsing failed or empty – clear any stale data so fallback is used
-                TERRAIN_MESH_VERTS.with(|cell| cell.borrow_mut().clear());
-                TERRAIN_GRID_W.with(|c| *c.borrow_mut() = 0);
-                TERRAIN_GRID_H.with(|c| *c.borrow_mut() = 0);
-            }
+                ),
+            );
+            let indices = input.terrain_mesh_indices();
+            let index = if indices.is_empty() {
+                None
+            } else {
+                process_terrain_index(&input.process_id, &verts, &indices)
+            };
+            Some(TerrainMeshSample {
+                verts: Arc::new(verts),
+                width: w,
+                height: h,
+                is_gpu,
+                index,
+            })
         }
-    } else {
-        TERRAIN_MESH_VERTS.with(|cell| cell.borrow_mut().clear());
-        TERRAIN_GRID_W.with(|c| *c.borrow_mut() = 0);
-        TERRAIN_GRID_H.with(|c| *c.borrow_mut() = 0);
+        // Parsing failed or empty – the elevation grid fallback is used
+        _ => None,
     }
+}
+
+/// Make `mesh` the terrain sampled on this thread; None clears stale data so the
+/// elevation grid fallback is used
+fn install_terrain_mesh(mesh: Option<&TerrainMeshSample>) {
+    TERRAIN_GRID_W.with(|c| *c.borrow_mut() = mesh.map_or(0, |m| m.width));
+    TERRAIN_GRID_H.with(|c| *c.borrow_mut() = mesh.map_or(0, |m| m.height));
+    TERRAIN_IS_GPU_LAYOUT.with(|c| *c.borrow_mut() = mesh.is_some_and(|m| m.is_gpu));
+    TERRAIN_INDEX.with(|c| *c.borrow_mut() = mesh.and_then(|m| m.index.clone()));
+    TERRAIN_MESH_VERTS.with(|cell| {
+        *cell.borrow_mut() = mesh.map(|m| Arc::clone(&m.verts));
+    });
+}
+
+/// Forget the terrain mesh sample of the last layer generation
+pub(crate) fn clear_terrain_mesh() {
+    install_terrain_mesh(None);
+}
+
+/// Generated geometry of a layer and the features that could not be built
+pub struct PolygonGeometryOutput {
+    pub geometries: Vec<BufferGeometry>,
+    pub skipped: Vec<SkippedFeature>,
+}
+
+/// Receives the geometry built from each chunk of features before merging, with the
+/// number of features processed so far and the feature count; an error aborts the layer
+pub type ChunkObserver<'a> =
+    &'a mut dyn FnMut(&[BufferGeometry], usize, usize) -> Result<(), String>;
+
+/// Build the geometry of a layer. In lenient mode malformed features are repaired or
+/// skipped and reported; in strict mode the first one fails the layer.
+/// Features are processed in adaptively sized chunks, yielding to the event loop between them.
+pub async fn generate_polygon_geometry(
+    input: PolygonGeometryInput,
+) -> Result<PolygonGeometryOutput, String> {
+    generate_polygon_geometry_streaming(input, None).await
+}
+
+/// `generate_polygon_geometry` handing every chunk's geometry to `on_chunk` as soon as it
+/// is built, so callers can show partial results and progress
+pub async fn generate_polygon_geometry_streaming(
+    mut input: PolygonGeometryInput,
+    mut on_chunk: Option<ChunkObserver<'_>>,
+) -> Result<PolygonGeometryOutput, String> {
+    // cancel_process stops the layer between chunks
+    let cancellation = ProcessCancellation::for_process(&input.process_id);
+    // Terrain scale of this process, re-installed with the terrain mesh below
+    let terrain_scale = projection::activate_process(&input.process_id);
+    input.apply_flat_base()?;
+    input.apply_engraving()?;
+    input.apply_drape();
+    input.apply_polygon_buffer();
+    input.apply_brunnels();
+    input.apply_road_network();
+    input.apply_footprint_union();
+    let flat_map_level = input.flat_map_level()?;
+    let engrave_depth = input.vt_data_set.engrave_depth();
+    let is_engraved = input.vt_data_set.is_engraved();
+
+    // ── Load actual terrain mesh vertices into thread-local for sampling ──────
+    // Re-installed after every yield, another layer may have been generated meanwhile
+    let terrain_mesh = decode_terrain_mesh(&input);
+    install_terrain_mesh(terrain_mesh.as_ref());
+
+    let datum = input.vertical_datum();
 
     // Compute dataset terrain extremes by sampling the elevation grid
     const SAMPLE_COUNT: usize = 10;
@@ -2111,20 +2635,15 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
     for i in 0..SAMPLE_COUNT {
         let t_i = i as f64 / ((SAMPLE_COUNT - 1) as f64);
         // Sample in mesh coordinates (−100 … +100) so the thread-local mesh path is used
-        let sample_mesh_x = (t_i * TERRAIN_SIZE) - TERRAIN_SIZE / 2.0;
+        let sample_mesh_x = (t_i * terrain_size()) - terrain_size() / 2.0;
         for j in 0..SAMPLE_COUNT {
             let t_j = j as f64 / ((SAMPLE_COUNT - 1) as f64);
-            let sample_mesh_y = (t_j * TERRAIN_SIZE) - TERRAIN_SIZE / 2.0;
+            let sample_mesh_y = (t_j * terrain_size()) - terrain_size() / 2.0;
             let elev = sample_terrain_mesh_height_at_point(
                 sample_mesh_x,
                 sample_mesh_y,
                 &input.elevation_grid,
-                &input.grid_size,
-                &input.bbox,
-                input.min_elevation,
-                input.max_elevation,
-                input.vertical_exaggeration,
-                input.terrain_base_height,
+                &datum,
             );
             dataset_lowest_z = dataset_lowest_z.min(elev);
             dataset_highest_z = dataset_highest_z.max(elev);
@@ -2133,16 +2652,10 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
     let _dataset_range = dataset_highest_z - dataset_lowest_z + 0.1;
 
     if input.polygons.is_empty() {
-        return Ok(serde_json::to_string(&BufferGeometry {
-            vertices: Vec::new(),
-            normals: None,
-            colors: None,
-            indices: None,
-            uvs: None,
-            has_data: false,
-            properties: None,
-        })
-        .unwrap());
+        return Ok(PolygonGeometryOutput {
+            geometries: Vec::new(),
+            skipped: Vec::new(),
+        });
     }
 
     // Convert all polygons to Vector2 format
@@ -2157,84 +2670,71 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
         }
     }
 
-    let total_polygons = input.polygons.len();
     let use_same_z_offset = input.use_same_z_offset;
 
     // Implement chunked processing to prevent timeouts on large datasets
     let mut all_geometries: Vec<BufferGeometry> = Vec::new();
-    let chunk_count = (total_polygons + MAX_CHUNK_SIZE - 1) / MAX_CHUNK_SIZE; // Ceiling division
-
-    // Process polygons in chunks to prevent timeouts
-
-    for (chunk_index, chunk) in input.polygons.chunks(MAX_CHUNK_SIZE).enumerate() {
-        let chunk_start = chunk_index * MAX_CHUNK_SIZE;
-        let geometries_result: Result<Vec<_>, String> = chunk
-            .iter()
-            .enumerate()
-            .map(
-                |(chunk_i, polygon_data)| -> Result<Option<BufferGeometry>, String> {
-                    let i = chunk_start + chunk_i; // Global polygon index
+    let mut skipped: Vec<SkippedFeature> = Vec::new();
+    let strict = input.processing_mode == ProcessingMode::Strict;
+
+    // Process polygons in chunks sized from the measured time per feature
+    let mut chunker = AdaptiveChunker::new();
+    let mut chunk_start = 0;
+    while chunk_start < input.polygons.len() {
+        cancellation.check()?;
+        let chunk_end = (chunk_start + chunker.size()).min(input.polygons.len());
+        let chunk = &input.polygons[chunk_start..chunk_end];
+        let chunk_started_ms = now_ms();
+        // Pool threads sample the same terrain mesh as this one
+        let feature_results: Vec<Result<Option<BufferGeometry>, SkippedFeature>> = parallel::map_slice(
+            chunk,
+            || {
+                projection::set_active_scale(terrain_scale);
+                install_terrain_mesh(terrain_mesh.as_ref())
+            },
+                |chunk_i, polygon_data| -> Result<Option<BufferGeometry>, SkippedFeature> {
+                    let feature_index = chunk_start + chunk_i; // Global polygon index
+                    let skip = |reason, message: &str| Err(SkippedFeature::new(feature_index, reason, message));
+
+                    // Lenient mode drops non-finite points and builds what remains
+                    let repaired;
+                    let polygon_data = if polygon_data.has_non_finite_points() {
+                        if strict {
+                            return skip(SkipReason::NanCoordinates, "Feature has non-finite coordinates");
+                        }
+                        repaired = polygon_data.without_non_finite_points();
+                        &repaired
+                    } else {
+                        polygon_data
+                    };
 
                     // No filtering - process all geometries within bbox as requested
                     // As requested by user: "I want everything that is inside the bbox with at least one vertex"
 
                     // Debug: log the first few polygon properties to see what's available
-                    if i < 3 {}
-
-                    // Debug: Track primary and secondary roads specifically
-                    if let Some(ref props) = polygon_data.properties {
-                        if let serde_json::Value::Object(obj) = props {
-                            if let Some(serde_json::Value::String(class)) = obj.get("class") {
-                                if class == "primary" || class == "secondary" {}
-                            }
-                        }
-                    }
+                    
 
                     // Calculate if this is a major road (for logging purposes)
-                    let is_major_road = if let Some(ref props) = polygon_data.properties {
-                        if let serde_json::Value::Object(obj) = props {
-                            if let Some(serde_json::Value::String(class)) = obj.get("class") {
-                                class == "primary"
-                                    || class == "secondary"
-                                    || class == "motorway"
-                                    || class == "trunk"
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    };
+                    let is_major_road = is_major_road_feature(polygon_data);
+
+                    // Bridges stand on piers above the terrain instead of following it
+                    let is_bridge = polygon_data.r#type.as_deref() == Some("LineString")
+                        && !is_engraved
+                        && flat_map_level.is_none()
+                        && input.vt_data_set.brunnel_of(polygon_data) == Some(Brunnel::Bridge);
+                    if is_bridge {
+                        return Ok(build_bridge(&input, polygon_data, is_major_road, &datum));
+                    }
 
                     // SPECIAL PATH: For terrain-aligned LineStrings, use quad-strip mesh for better terrain following
+                    // Engraved linestrings are buffered into polygon cutters below instead
                     let is_terrain_aligned_linestring = polygon_data.r#type.as_deref() == Some("LineString")
-                        && input.vt_data_set.align_vertices_to_terrain.unwrap_or(false);
+                        && input.vt_data_set.align_vertices_to_terrain.unwrap_or(false)
+                        && !is_engraved;
 
                     if is_terrain_aligned_linestring && polygon_data.geometry.len() >= 2 {
                         // Use buffer size from layer configuration
-                        let config_buffer_size = input
-                            .vt_data_set
-                            .buffer_size
-                            .unwrap_or(if is_major_road { 2.0 } else { 1.5 });
-                        let bbox_lng_span = (input.bbox[2] - input.bbox[0]).abs().max(1e-10);
-                        // When fixedBufferSize is set, use fixed geographic scale; otherwise scale by bbox (visual width)
-                        let buffer_distance = if input.vt_data_set.fixed_buffer_size.unwrap_or(false) {
-                             config_buffer_size * 0.00001
-                        } else {
-                            // Dynamic scaling: 0.5 for small bboxes (zoomed in), 0.3 for large bboxes (zoomed out)
-                            // Formula: starts at 0.5, decays to 0.3 as span increases to 1.0
-                            let strength = (0.5 - (bbox_lng_span * 0.2)).clamp(0.3, 0.5);
-                            let factor = (bbox_lng_span / TERRAIN_SIZE) * strength;
-                            let scaled_config = config_buffer_size * factor;
-                            if config_buffer_size < 0.001 {
-                                scaled_config
-                            } else {
-                                let min_scaled = 0.4 * factor;
-                                scaled_config.clamp(min_scaled, scaled_config.max(min_scaled))
-                            }
-                        };
+                        let buffer_distance = input.line_buffer_distance(is_major_road);
 
                         // Create quad-strip mesh for this linestring
                         if let Some(quad_mesh) = create_linestring_quad_strip(
@@ -2245,36 +2745,21 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                         ) {
                             // Extract properties
                             let mut properties: Option<std::collections::HashMap<String, serde_json::Value>> = 
-                                if let Some(ref tags) = polygon_data.tags {
-                                    if let serde_json::Value::Object(obj) = tags {
-                                        let mut map = std::collections::HashMap::new();
-                                        for (k, v) in obj.iter() {
-                                            map.insert(k.clone(), v.clone());
-                                        }
-                                        Some(map)
-                                    } else { None }
+                                if let Some(serde_json::Value::Object(obj)) = &polygon_data.tags {
+                                    let mut map = std::collections::HashMap::new();
+                                    for (k, v) in obj.iter() {
+                                        map.insert(k.clone(), v.clone());
+                                    }
+                                    Some(map)
                                 } else { None };
 
                             // Add layer metadata
-                            let layer_name = input.vt_data_set.source_layer.clone();
-                            match properties {
-                                Some(ref mut props) => {
-                                    props.entry("__sourceLayer".to_string())
-                                        .or_insert(serde_json::Value::String(layer_name.clone()));
-                                }
-                                None => {
-                                    let mut map = std::collections::HashMap::new();
-                                    map.insert("__sourceLayer".to_string(), serde_json::Value::String(layer_name.clone()));
-                                    properties = Some(map);
-                                }
-                            }
+                            tag_layer_metadata(&mut properties, &input.vt_data_set);
 
                             // Get height from layer config
                             let height = input.vt_data_set.extrusion_depth.unwrap_or(0.3);
                             // Use FIXED scaling for extrusion to maintain constant visual height regardless of map size
-                            // TERRAIN_SIZE (200.0) / 1000.0 meters = 0.2 units per meter
-                            let fixed_meters_to_units = 0.2; 
-                            let scaled_height = (height * fixed_meters_to_units).clamp(MIN_HEIGHT, MAX_HEIGHT);
+                            let scaled_height = input.vt_data_set.clamp_height(height * fixed_meters_to_units());
 
                             // Create geometry directly from quad strip mesh
                             let mut geometry = create_extruded_shape_from_quad_strip(
@@ -2283,10 +2768,9 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                                 &input.bbox,
                                 &input.elevation_grid,
                                 &input.grid_size,
-                                input.min_elevation,
-                                input.max_elevation,
-                                input.vertical_exaggeration,
-                                input.terrain_base_height,
+                                &input.vertical_datum(),
+                                input.submerge_offset(),
+                                input.vt_data_set.stack_offset(),
                                 &input.terrain_vertices_base64,
                                 &input.terrain_indices_base64,
                                 properties,
@@ -2295,7 +2779,7 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                             // Clip the 3D mesh to the bounding box
                             if geometry.has_data {
                                 if let Some(ref indices) = geometry.indices {
-                                    let half_tile = TERRAIN_SIZE / 2.0;
+                                    let half_tile = terrain_size() / 2.0;
                                     let (clipped_vertices, clipped_indices) = clip_mesh_to_bbox_3d(
                                         &geometry.vertices,
                                         indices,
@@ -2322,54 +2806,18 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                     let points: Vec<Vector2> = if polygon_data.r#type.as_deref()
                         == Some("LineString")
                     {
-                        // Extract transportation class for better debugging
-                        let _transportation_class = if let Some(ref props) = polygon_data.properties
-                        {
-                            if let serde_json::Value::Object(obj) = props {
-                                if let Some(serde_json::Value::String(class)) = obj.get("class") {
-                                    class.clone()
-                                } else {
-                                    "unknown".to_string()
-                                }
-                            } else {
-                                "no_props".to_string()
-                            }
-                        } else {
-                            "no_props".to_string()
-                        };
-
                         // COMPLETE SOLUTION: Process all segments of LineString for complete road/footway rendering
                         if polygon_data.geometry.len() >= 2 {
-                            // Create complete buffered polygon from all LineString segments
-                            let mut buffered_points = Vec::new();
-
                             // Use buffer size from layer configuration, with fallback to reasonable defaults
-                            let config_buffer_size = input
-                                .vt_data_set
-                                .buffer_size
-                                .unwrap_or(if is_major_road { 2.0 } else { 1.5 });
-                            let bbox_lng_span = (input.bbox[2] - input.bbox[0]).abs().max(1e-10);
-                            // When fixedBufferSize is set, use fixed geographic scale; otherwise scale by bbox (visual width)
-                            let buffer_distance = if input.vt_data_set.fixed_buffer_size.unwrap_or(false) {
-                                 config_buffer_size * 0.00001
-                            } else {
-                                // Dynamic scaling: 0.5 for small bboxes (zoomed in), 0.3 for large bboxes (zoomed out)
-                                let strength = (0.5 - (bbox_lng_span * 0.2)).clamp(0.3, 0.5);
-                                let factor = (bbox_lng_span / TERRAIN_SIZE) * strength;
-                                let scaled_config = config_buffer_size * factor;
-                                if config_buffer_size < 0.001 {
-                                    scaled_config
-                                } else {
-                                    let min_scaled = 0.4 * factor;
-                                    scaled_config.clamp(min_scaled, scaled_config.max(min_scaled))
-                                }
-                            };
+                            let buffer_distance = input.line_buffer_distance(is_major_road);
 
                             // Use robust linestring buffering algorithm with bbox for subdivision
-                            buffered_points =
-                                create_linestring_buffer(&polygon_data.geometry, buffer_distance, &input.bbox);
-
-                            buffered_points
+                            create_linestring_buffer(
+                                &polygon_data.geometry,
+                                buffer_distance,
+                                &input.bbox,
+                                &input.vt_data_set.line_style(),
+                            )
                         } else {
                             Vec::new()
                         }
@@ -2392,27 +2840,20 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                     };
 
                     if points.len() < 3 {
-                        // Debug: Track why geometries might be skipped - THIS IS A MAJOR FILTER
-                        let transportation_class = if let Some(ref props) = polygon_data.properties
-                        {
-                            if let serde_json::Value::Object(obj) = props {
-                                if let Some(serde_json::Value::String(class)) = obj.get("class") {
-                                    class.clone()
-                                } else {
-                                    "unknown".to_string()
-                                }
-                            } else {
-                                "no_props".to_string()
-                            }
-                        } else {
-                            "no_props".to_string()
-                        };
-
-                        return Ok(None); // Skip invalid polygons
+                        return skip(
+                            SkipReason::InvalidRing,
+                            &format!("Ring has {} usable points, at least 3 are required", points.len()),
+                        );
                     }
 
                     // Determine extrusion height based on geometry type and available data
-                    let mut height = if let Some(d) = input.vt_data_set.extrusion_depth {
+                    let mut height = if let Some(d) = engrave_depth {
+                        // Engraving: the depth of the groove below the surface
+                        d
+                    } else if input.vt_data_set.through_cut() {
+                        // Replaced by the full base span below
+                        ENGRAVE_OVERSHOOT
+                    } else if let Some(d) = input.vt_data_set.extrusion_depth {
                         // Use explicitly set extrusion depth
                         d
                     } else if let Some(h) = polygon_data.height.filter(|h| *h > 0.0) {
@@ -2421,14 +2862,10 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                         h
                     } else {
                         // Determine height based on geometry type/class
-                        let geometry_class = if let Some(ref props) = polygon_data.properties {
-                            if let serde_json::Value::Object(obj) = props {
-                                obj.get("class")
-                                    .and_then(|v| v.as_str())
-                                    .unwrap_or("unknown")
-                            } else {
-                                "unknown"
-                            }
+                        let geometry_class = if let Some(serde_json::Value::Object(obj)) = &polygon_data.properties {
+                            obj.get("class")
+                                .and_then(|v| v.as_str())
+                                .unwrap_or("unknown")
                         } else {
                             "unknown"
                         };
@@ -2468,21 +2905,6 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                     }
                     // Ensure positive height values
                     if height <= 0.0 {
-                        let _transportation_class = if let Some(ref props) = polygon_data.properties
-                        {
-                            if let serde_json::Value::Object(obj) = props {
-                                if let Some(serde_json::Value::String(class)) = obj.get("class") {
-                                    class.clone()
-                                } else {
-                                    "unknown".to_string()
-                                }
-                            } else {
-                                "no_props".to_string()
-                            }
-                        } else {
-                            "no_props".to_string()
-                        };
-
                         return Ok(None); // Skip flat geometry
                     }
 
@@ -2517,7 +2939,7 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                             }
                             
                             // Clip hole to bbox (same as exterior ring)
-                            let half_tile = TERRAIN_SIZE * 0.5;
+                            let half_tile = terrain_size() * 0.5;
                             let clipped_hole = simple_clip_polygon(
                                 &cleaned_hole,
                                 &[-half_tile, -half_tile, half_tile, half_tile],
@@ -2535,29 +2957,14 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                     // Clean and validate the polygon
                     let cleaned_points = clean_polygon_footprint(&mesh_points);
                     if cleaned_points.is_empty() {
-                        let _transportation_class = if let Some(ref props) = polygon_data.properties
-                        {
-                            if let serde_json::Value::Object(obj) = props {
-                                if let Some(serde_json::Value::String(class)) = obj.get("class") {
-                                    class.clone()
-                                } else {
-                                    "unknown".to_string()
-                                }
-                            } else {
-                                "no_props".to_string()
-                            }
-                        } else {
-                            "no_props".to_string()
-                        };
-
-                        return Ok(None); // Skip invalid polygon after cleaning
+                        return skip(SkipReason::InvalidRing, "Ring is degenerate after removing duplicate points");
                     }
 
                     // Clip against the overall terrain tile bounds (include any shape that overlaps)
-                    let half_tile = TERRAIN_SIZE * 0.5;
+                    let half_tile = terrain_size() * 0.5;
 
                     // Apply clipping to all polygons
-                    let use_csg = input.csg_clipping.unwrap_or(false);
+                    let use_csg = input.use_csg_clipping();
 
                     let clipped_points = if use_csg {
                         // CSG-based clipping for smoother results
@@ -2615,21 +3022,6 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                         final_points
                     };
 
-                    // SUCCESS: This geometry made it through all filters
-                    let _transportation_class = if let Some(ref props) = polygon_data.properties {
-                        if let serde_json::Value::Object(obj) = props {
-                            if let Some(serde_json::Value::String(class)) = obj.get("class") {
-                                class.clone()
-                            } else {
-                                "unknown".to_string()
-                            }
-                        } else {
-                            "no_props".to_string()
-                        }
-                    } else {
-                        "no_props".to_string()
-                    };
-
                     // Compute per-polygon terrain extremes for base alignment
                     // Sample at vertices AND interior points to capture full terrain variation
                     let mut lowest_terrain_z = f64::INFINITY;
@@ -2642,12 +3034,7 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                             mesh_x,
                             mesh_y,
                             &input.elevation_grid,
-                            &input.grid_size,
-                            &input.bbox,
-                            input.min_elevation,
-                            input.max_elevation,
-                            input.vertical_exaggeration,
-                            input.terrain_base_height,
+                            &datum,
                         );
                         lowest_terrain_z = lowest_terrain_z.min(tz);
                         highest_terrain_z = highest_terrain_z.max(tz);
@@ -2703,44 +3090,25 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                     // For terrain-aligned layers, add clearance to prevent z-fighting
                     let is_building = !input.vt_data_set.align_vertices_to_terrain.unwrap_or(false);
                     let z_offset = if is_building {
-                        lowest_terrain_z + user_z_offset - BUILDING_SUBMERGE_OFFSET
+                        lowest_terrain_z + user_z_offset - input.submerge_offset()
                     } else {
-                        lowest_terrain_z + user_z_offset + MIN_CLEARANCE
+                        lowest_terrain_z + user_z_offset + input.stacked_clearance()
                     };
 
                     // Extract properties from polygon_data for attaching to geometry
-                    let mut properties = if let Some(ref props) = polygon_data.properties {
-                        // Convert serde_json::Value to HashMap<String, serde_json::Value>
-                        if let serde_json::Value::Object(obj) = props {
-                            let mut hashmap = std::collections::HashMap::new();
-                            for (key, value) in obj.iter() {
-                                hashmap.insert(key.clone(), value.clone());
-                            }
-                            Some(hashmap)
-                        } else {
-                            None
+                    // Convert serde_json::Value to HashMap<String, serde_json::Value>
+                    let mut properties = if let Some(serde_json::Value::Object(obj)) = &polygon_data.properties {
+                        let mut hashmap = std::collections::HashMap::new();
+                        for (key, value) in obj.iter() {
+                            hashmap.insert(key.clone(), value.clone());
                         }
+                        Some(hashmap)
                     } else {
                         None
                     };
 
                     // Ensure layer metadata is available for downstream grouping
-                    let layer_name = input.vt_data_set.source_layer.clone();
-                    match properties {
-                        Some(ref mut props) => {
-                            props
-                                .entry("__sourceLayer".to_string())
-                                .or_insert(serde_json::Value::String(layer_name.clone()));
-                        }
-                        None => {
-                            let mut map = std::collections::HashMap::new();
-                            map.insert(
-                                "__sourceLayer".to_string(),
-                                serde_json::Value::String(layer_name.clone()),
-                            );
-                            properties = Some(map);
-                        }
-                    }
+                    tag_layer_metadata(&mut properties, &input.vt_data_set);
 
                     // Validate polygon before triangulation
                     // Self-intersecting polygons (common in buffered linestrings at sharp turns)
@@ -2749,7 +3117,10 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                         Some(points) => points,
                         None => {
                             // Polygon is too small or self-intersecting, skip it
-                            return Ok(None);
+                            return skip(
+                                SkipReason::TriangulationFailure,
+                                "Ring is too small or self-intersecting to triangulate",
+                            );
                         }
                     };
 
@@ -2758,14 +3129,12 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                     if is_building {
                         // Buildings: use proportional scaling based on bbox size
                         // This keeps building heights accurate in meters relative to the map
-                        let meters_to_units = calculate_meters_to_terrain_units(&input.bbox);
+                        let meters_to_units = meters_to_terrain_units(&input.bbox);
                         height *= meters_to_units;
                     } else {
                         // Non-building polygon layers: use FIXED scaling (same as linestrings)
                         // This maintains constant visual extrusion height regardless of map size
-                        // TERRAIN_SIZE (200.0) / 1000.0 meters = 0.2 units per meter
-                        let fixed_meters_to_units = 0.2;
-                        height = height * fixed_meters_to_units;
+                        height *= fixed_meters_to_units();
                     }
                     
                     // Add per-polygon terrain Z difference for buildings on slopes
@@ -2776,51 +3145,206 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
                         height += polygon_terrain_z_difference;
                     }
 
-                    // Final clamp in terrain units
-                    height = height.clamp(MIN_HEIGHT, MAX_HEIGHT);
+                    // Final clamp in terrain units (per-layer limits)
+                    height = input.vt_data_set.clamp_height(height);
+
+                    // Flat map levels replace feature heights and terrain-relative placement
+                    let (z_offset, height) = match flat_map_level {
+                        Some(level) => (level.bottom, level.top - level.bottom),
+                        None => (z_offset, height),
+                    };
+
+                    // Engraving cutters reach from the groove floor to above the surface;
+                    // through-cuts from below the base to above the highest terrain
+                    let (z_offset, clearance, height) = if input.vt_data_set.through_cut() {
+                        let terrain_top = input.vertical_datum().elevation_to_z(input.max_elevation);
+                        (-ENGRAVE_OVERSHOOT, 0.0, terrain_top + 2.0 * ENGRAVE_OVERSHOOT)
+                    } else {
+                        match engrave_depth {
+                            Some(_) => (z_offset, -height, height + ENGRAVE_OVERSHOOT),
+                            None => (z_offset, input.stacked_clearance(), height),
+                        }
+                    };
+
+                    // Water surfaces are flat slabs below the shoreline instead
+                    let water_surface = input
+                        .vt_data_set
+                        .water_surface
+                        .as_ref()
+                        .filter(|_| !is_engraved && flat_map_level.is_none());
+                    let (z_offset, clearance, height) = match water_surface {
+                        Some(water) => {
+                            let shoreline =
+                                subdivide_polygon_edges(&cleaned_points, MAX_EDGE_LENGTH);
+                            let Some(surface_z) = water.surface_z(
+                                &datum,
+                                shoreline.iter().map(|p| {
+                                    sample_terrain_mesh_height_at_point(
+                                        p.x,
+                                        p.y,
+                                        &input.elevation_grid,
+                                        &datum,
+                                    )
+                                }),
+                            ) else {
+                                return Ok(None);
+                            };
+                            let (bottom, height) =
+                                water.slab(surface_z, lowest_terrain_z, input.submerge_offset());
+                            (bottom, 0.0, height)
+                        }
+                        None => (z_offset, clearance, height),
+                    };
+
+                    // Pitched roofs take their height off the top of the walls; buildings
+                    // with courtyards keep flat tops
+                    let roof_spec = if input.vt_data_set.roof_shapes()
+                        && is_building
+                        && !is_engraved
+                        && flat_map_level.is_none()
+                        && transformed_holes.as_ref().is_none_or(|holes| holes.is_empty())
+                    {
+                        roof::roof_spec(polygon_data)
+                    } else {
+                        None
+                    };
+                    let footprint: Vec<[f64; 2]> =
+                        cleaned_points.iter().map(|p| [p.x, p.y]).collect();
+                    let pitched_roof = roof_spec
+                        .map(|spec| {
+                            let meters_to_units = meters_to_terrain_units(&input.bbox);
+                            let roof_height = roof::roof_height(&footprint, &spec, meters_to_units)
+                                .min(height * roof::MAX_ROOF_SHARE);
+                            (spec, roof_height)
+                        })
+                        .filter(|(_, roof_height)| *roof_height > 0.0);
+                    let wall_height = pitched_roof
+                        .as_ref()
+                        .map_or(height, |(_, roof_height)| height - roof_height);
+
+                    // Draped shells follow the terrain triangles under the footprint
+                    if input.vt_data_set.drape()
+                        && !is_engraved
+                        && flat_map_level.is_none()
+                        && water_surface.is_none()
+                    {
+                        let rings: Vec<Vec<[f64; 2]>> = std::iter::once(footprint)
+                            .chain(transformed_holes.iter().flatten().map(|hole| {
+                                hole.iter().map(|p| [p[0], p[1]]).collect()
+                            }))
+                            .collect();
+                        return match drape_footprint(
+                            &input,
+                            &rings,
+                            clearance,
+                            clearance + height,
+                            &datum,
+                        ) {
+                            Some(mesh) => Ok(Some(mesh.into_geometry(properties))),
+                            None => skip(
+                                SkipReason::TriangulationFailure,
+                                "Draped shell has no faces",
+                            ),
+                        };
+                    }
 
-                    let geometry = create_extruded_shape(
+                    let mut geometry = create_extruded_shape(
                         &cleaned_points,
                         transformed_holes.as_ref(),
-                        height,
+                        wall_height,
                         z_offset,
+                        clearance,
                         properties,
-                        input.vt_data_set.align_vertices_to_terrain.unwrap_or(false),
+                        input.vt_data_set.align_vertices_to_terrain.unwrap_or(false)
+                            && water_surface.is_none(),
                         Some(&input.elevation_grid),
                         Some(&input.grid_size),
                         Some(&input.bbox),
-                        Some(input.min_elevation),
-                        Some(input.max_elevation),
-                        Some(input.vertical_exaggeration),
-                        Some(input.terrain_base_height),
+                        Some(&input.vertical_datum()),
                         Some(&input.vt_data_set.source_layer),
                         Some(&input.terrain_vertices_base64),
                         Some(&input.terrain_indices_base64),
                     );
+                    if let (Some((spec, roof_height)), true) = (&pitched_roof, geometry.has_data) {
+                        let eave_z = z_offset + wall_height;
+                        if let Some(mesh) = roof::roof_mesh(&footprint, spec, eave_z, *roof_height) {
+                            mesh.append_to(&mut geometry);
+                        }
+                    }
 
                     if geometry.has_data {
                         Ok(Some(geometry))
                     } else {
-                        Ok(None)
+                        skip(SkipReason::TriangulationFailure, "Triangulation produced no faces")
                     }
                 },
-            )
-            .collect();
-
-        // Handle chunk processing results
-        let chunk_geometries = geometries_result
-            .map_err(|e| format!("Chunk {} processing error: {}", chunk_index + 1, e))?;
-        let chunk_valid_geometries: Vec<BufferGeometry> =
-            chunk_geometries.into_iter().filter_map(|opt| opt).collect();
+        );
+
+        // A failed feature is reported and skipped without affecting the rest of the chunk
+        let chunk_first_geometry = all_geometries.len();
+        for (result, feature) in feature_results.into_iter().zip(chunk) {
+            match result {
+                Ok(Some(mut geometry)) => {
+                    if let Some(depth) = input.vt_data_set.tunnel_recess(feature) {
+                        for z in geometry.vertices.iter_mut().skip(2).step_by(3) {
+                            *z -= depth as f32;
+                        }
+                    }
+                    if let Some(rgb) = input.vt_data_set.feature_color(feature) {
+                        let vertex_count = geometry.vertices.len() / 3;
+                        geometry.colors = Some(rgb.repeat(vertex_count));
+                    }
+                    all_geometries.push(geometry);
+                }
+                Ok(None) => {}
+                Err(feature) if strict => {
+                    return Err(format!(
+                        "Strict mode: feature {} rejected ({:?}): {}",
+                        feature.index, feature.reason, feature.message
+                    ));
+                }
+                Err(feature) => skipped.push(feature),
+            }
+        }
 
-        // Add chunk geometries to the overall collection
-        all_geometries.extend(chunk_valid_geometries);
+        chunker.record(chunk.len(), now_ms() - chunk_started_ms);
+        if let Some(on_chunk) = on_chunk.as_mut() {
+            on_chunk(
+                &all_geometries[chunk_first_geometry..],
+                chunk_end,
+                input.polygons.len(),
+            )?;
+        }
+        chunk_start = chunk_end;
+        if chunk_start < input.polygons.len() {
+            yield_now().await;
+            projection::set_active_scale(terrain_scale);
+            install_terrain_mesh(terrain_mesh.as_ref());
+        }
     }
 
     // Processing complete
+    cancellation.check()?;
+
+    // With CSG clipping, cut the extruded features to the terrain solid in 3D so nothing
+    // reaches below the base plate, past the tile edge or into the terrain surface, and
+    // the result stays watertight.
+    // A process clip shape always cuts them to the prism over its outline.
+    if let Some(footprint) = crate::clip_shape::process_clip_footprint(&input.process_id, &input.bbox)? {
+        all_geometries = crate::csg_union::clip_to_footprint_solid(all_geometries, &footprint);
+    } else if input.use_csg_clipping() {
+        all_geometries = crate::csg_union::clip_to_terrain_solid(
+            all_geometries,
+            terrain_size() * 0.5,
+            |min, max| terrain_solid_under(&input, min, max, &datum),
+        );
+    }
 
     if all_geometries.is_empty() {
-        return Ok(serde_json::to_string(&Vec::<BufferGeometry>::new()).unwrap());
+        return Ok(PolygonGeometryOutput {
+            geometries: Vec::new(),
+            skipped,
+        });
     }
 
     // Check if this layer uses per-vertex terrain alignment
@@ -2837,10 +3361,10 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
     // - Water: union of tile-edge-clipped polygons creates rectangles covering land areas
     if uses_terrain_alignment || is_water_layer {
         // Return geometries as-is without merging
-        match serde_json::to_string(&all_geometries) {
-            Ok(json) => return Ok(json),
-            Err(e) => return Err(format!("Failed to serialize output: {}", e)),
-        }
+        return Ok(PolygonGeometryOutput {
+            geometries: all_geometries,
+            skipped,
+        });
     }
 
     let tolerance = if input.vt_data_set.source_layer == "transportation" {
@@ -2853,20 +3377,22 @@ pub fn create_polygon_geometry(input_json: &str) -> Result<String, String> {
 
     let mut merged_geometries = Vec::new();
     for (_layer_name, geometry) in layer_merged {
-        let optimized = crate::csg_union::optimize_geometry(geometry, tolerance);
+        let mut optimized = crate::csg_union::optimize_geometry(geometry, tolerance);
         if optimized.has_data {
+            // Merging drops per-feature properties; keep the layer identity for exports
+            tag_layer_metadata(&mut optimized.properties, &input.vt_data_set);
             merged_geometries.push(optimized);
         }
     }
 
-    // Serialize merged and optimized geometries
-    match serde_json::to_string(&merged_geometries) {
-        Ok(json) => Ok(json),
-        Err(e) => Err(format!("Failed to serialize output: {}", e)),
-    }
+    Ok(PolygonGeometryOutput {
+        geometries: merged_geometries,
+        skipped,
+    })
 }
 
 // GPU-accelerated linestring buffering with CPU fallback
+#[allow(dead_code)]
 async fn create_linestring_buffer_gpu_fallback(linestring: &[Vec<f64>], buffer_distance: f64) -> Vec<Vector2> {
     let use_gpu = std::env::var("WASM_GPU_POLYGON_DISABLE").is_err();
 
@@ -2882,7 +3408,7 @@ async fn create_linestring_buffer_gpu_fallback(linestring: &[Vec<f64>], buffer_d
                 Ok(gpu_result) => {
                     return gpu_result.into_iter().map(|p| Vector2 { x: p[0], y: p[1] }).collect();
                 }
-                Err(e) => {
+                Err(_e) => {
                     // GPU fallback failed, continue with CPU implementation
                 }
             }
@@ -2890,18 +3416,177 @@ async fn create_linestring_buffer_gpu_fallback(linestring: &[Vec<f64>], buffer_d
     }
 
     // CPU fallback
-    create_linestring_buffer(linestring, buffer_distance, &[0.0, 0.0, 1.0, 1.0]) // Default bbox for GPU fallback
+    create_linestring_buffer(
+        linestring,
+        buffer_distance,
+        &[0.0, 0.0, 1.0, 1.0], // Default bbox for GPU fallback
+        &LineStyle::default(),
+    )
+}
+
+/// Shell over a footprint in mesh coordinates draped on the installed terrain mesh, or on
+/// a grid at the elevation grid spacing when the mesh came without a triangulation
+fn drape_footprint(
+    input: &PolygonGeometryInput,
+    rings: &[Vec<[f64; 2]>],
+    bottom: f64,
+    top: f64,
+    datum: &VerticalDatum,
+) -> Option<SolidMesh> {
+    let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+    for p in rings.first()? {
+        min = [min[0].min(p[0]), min[1].min(p[1])];
+        max = [max[0].max(p[0]), max[1].max(p[1])];
+    }
+    let surface = |x, y| sample_terrain_mesh_height_at_point(x, y, &input.elevation_grid, datum);
+    let triangles = terrain_triangles_in(input, min, max, datum);
+    drape::drape_mesh(rings, &triangles, surface, bottom, top)
+}
+
+/// Top triangles of the installed terrain mesh over the box between `min` and `max`, or
+/// of a grid at the elevation grid spacing when the mesh came without a triangulation
+fn terrain_triangles_in(
+    input: &PolygonGeometryInput,
+    min: [f64; 2],
+    max: [f64; 2],
+    datum: &VerticalDatum,
+) -> Vec<drape::Triangle> {
+    TERRAIN_INDEX
+        .with(|index| {
+            index
+                .borrow()
+                .as_ref()
+                .map(|index| index.top_triangles_in(min, max))
+        })
+        .unwrap_or_else(|| {
+            let surface =
+                |x, y| sample_terrain_mesh_height_at_point(x, y, &input.elevation_grid, datum);
+            let spacing = terrain_size() / (input.grid_size.width.max(2) - 1) as f64;
+            drape::grid_triangles(min, max, spacing, surface)
+        })
+}
+
+/// Terrain solid from the base (z = 0) up to the surface over the box between `min` and
+/// `max`, cut out of features with CSG clipping
+fn terrain_solid_under(
+    input: &PolygonGeometryInput,
+    min: [f64; 2],
+    max: [f64; 2],
+    datum: &VerticalDatum,
+) -> Option<BufferGeometry> {
+    let triangles = terrain_triangles_in(input, min, max, datum);
+    if triangles.is_empty() {
+        return None;
+    }
+    let positions: Vec<f32> = triangles
+        .iter()
+        .flatten()
+        .flat_map(|p| p.map(|v| v as f32))
+        .collect();
+    let indices: Vec<u32> = (0..triangles.len() as u32 * 3).collect();
+    let footprint = [[min[0], min[1]], [max[0], min[1]], [max[0], max[1]], [min[0], max[1]]];
+    let (vertices, indices) = crate::clip_shape::clip_terrain_mesh(&positions, &indices, &footprint);
+    if indices.is_empty() {
+        return None;
+    }
+    Some(BufferGeometry {
+        vertices,
+        normals: None,
+        colors: None,
+        indices: Some(indices),
+        uvs: None,
+        has_data: true,
+        properties: None,
+    })
+}
+
+/// Deck and piers of a bridge feature in mesh coordinates, clipped to the tile
+fn build_bridge(
+    input: &PolygonGeometryInput,
+    feature: &GeometryData,
+    is_major_road: bool,
+    datum: &VerticalDatum,
+) -> Option<BufferGeometry> {
+    let config = input.vt_data_set.brunnel.as_ref()?;
+    let line: Vec<[f64; 2]> = feature
+        .geometry
+        .iter()
+        .filter(|p| p.len() >= 2)
+        .map(|p| transform_to_mesh_coordinates(p[0], p[1], &input.bbox))
+        .collect();
+    let bbox_lng_span = (input.bbox[2] - input.bbox[0]).abs().max(1e-10);
+    let params = BridgeParams {
+        half_width: input.line_buffer_distance(is_major_road) * terrain_size() / bbox_lng_span,
+        end_clearance: input.stacked_clearance(),
+        clearance: config.bridge_clearance() * fixed_meters_to_units(),
+        deck_thickness: config.deck_thickness() * fixed_meters_to_units(),
+        pier_spacing: config.pier_spacing() * meters_to_terrain_units(&input.bbox),
+        submerge: input.submerge_offset(),
+    };
+    let mesh = brunnel::bridge_mesh(&line, &params, |x, y| {
+        sample_terrain_mesh_height_at_point(x, y, &input.elevation_grid, datum)
+    })?;
+
+    let mut properties = feature
+        .properties
+        .clone()
+        .and_then(|properties| serde_json::from_value(properties).ok());
+    tag_layer_metadata(&mut properties, &input.vt_data_set);
+    let mut geometry = mesh.into_geometry(properties);
+
+    let half_tile = terrain_size() / 2.0;
+    let outside = geometry
+        .vertices
+        .chunks(3)
+        .any(|v| (v[0] as f64).abs() > half_tile || (v[1] as f64).abs() > half_tile);
+    if outside {
+        let indices = geometry.indices.take().unwrap_or_default();
+        let (vertices, indices) = clip_mesh_to_bbox_3d(
+            &geometry.vertices,
+            &indices,
+            -half_tile,
+            -half_tile,
+            half_tile,
+            half_tile,
+        );
+        if indices.is_empty() {
+            return None;
+        }
+        geometry.vertices = vertices;
+        geometry.indices = Some(indices);
+        // Clear normals as they need recalculation after clipping
+        geometry.normals = None;
+    }
+    Some(geometry)
+}
+
+// Major roads get a wider default line buffer
+fn is_major_road_feature(feature: &GeometryData) -> bool {
+    if let Some(serde_json::Value::Object(obj)) = &feature.properties {
+        if let Some(serde_json::Value::String(class)) = obj.get("class") {
+            return class == "primary"
+                || class == "secondary"
+                || class == "motorway"
+                || class == "trunk";
+        }
+    }
+    false
 }
 
 // Create a proper buffered polygon from a linestring with even width throughout
-fn create_linestring_buffer(linestring: &[Vec<f64>], buffer_distance: f64, bbox: &[f64]) -> Vec<Vector2> {
+fn create_linestring_buffer(
+    linestring: &[Vec<f64>],
+    buffer_distance: f64,
+    bbox: &[f64],
+    style: &LineStyle,
+) -> Vec<Vector2> {
     if linestring.len() < 2 {
         return Vec::new();
     }
 
     // Calculate max segment length in the input coordinate space (geographic degrees)
-    // Target: segments of ~5 mesh units after transformation to mesh coords (TERRAIN_SIZE = 200)
-    // Mesh coords range is -100 to +100 (200 units total)
+    // Target: segments of ~5 mesh units after transformation to mesh coords
+    // Mesh coords range is -100 to +100 with the default terrain size (200 units total)
     // For terrain alignment, we want segments of ~5 mesh units = 2.5% of tile
     let bbox_width = if bbox.len() >= 4 { (bbox[2] - bbox[0]).abs() } else { 0.01 };
     let bbox_height = if bbox.len() >= 4 { (bbox[3] - bbox[1]).abs() } else { 0.01 };
@@ -2968,6 +3653,42 @@ fn create_linestring_buffer(linestring: &[Vec<f64>], buffer_distance: f64, bbox:
         points.push(pt);
     }
 
+    buffer_points(&points, buffer_distance, style)
+}
+
+/// Outline of `linestring` buffered by `buffer_distance` with the joins and caps of
+/// `style`, as a closed ring; empty when the line has fewer than two distinct points
+pub(crate) fn buffer_polyline(
+    linestring: &[Vec<f64>],
+    buffer_distance: f64,
+    style: &LineStyle,
+) -> Vec<Vec<f64>> {
+    let mut points: Vec<Vector2> = Vec::with_capacity(linestring.len());
+    for p in linestring {
+        if p.len() < 2 || !p[0].is_finite() || !p[1].is_finite() {
+            continue;
+        }
+        let point = Vector2 { x: p[0], y: p[1] };
+        if points.last().is_some_and(|last| {
+            (point.x - last.x).powi(2) + (point.y - last.y).powi(2) < EPSILON * EPSILON
+        }) {
+            continue;
+        }
+        points.push(point);
+    }
+    let mut ring: Vec<Vec<f64>> = buffer_points(&points, buffer_distance, style)
+        .into_iter()
+        .map(|p| vec![p.x, p.y])
+        .collect();
+    if let Some(first) = ring.first().cloned() {
+        ring.push(first);
+    }
+    ring
+}
+
+// Buffer polygon of distinct consecutive points: left side, end cap, right side
+// reversed, start cap
+fn buffer_points(points: &[Vector2], buffer_distance: f64, style: &LineStyle) -> Vec<Vector2> {
     if points.len() < 2 {
         return Vec::new();
     }
@@ -2975,8 +3696,8 @@ fn create_linestring_buffer(linestring: &[Vec<f64>], buffer_distance: f64, bbox:
     let mut polygon_points = Vec::new();
 
     // Generate parallel offset lines for left and right sides
-    let left_offsets = create_offset_line(&points, buffer_distance);
-    let right_offsets = create_offset_line(&points, -buffer_distance);
+    let left_offsets = create_styled_offset_line(points, buffer_distance, style);
+    let right_offsets = create_styled_offset_line(points, -buffer_distance, style);
 
     if left_offsets.is_empty() || right_offsets.is_empty() {
         return Vec::new();
@@ -2990,13 +3711,21 @@ fn create_linestring_buffer(linestring: &[Vec<f64>], buffer_distance: f64, bbox:
         return Vec::new();
     }
 
-    // Simple polygon construction: left side + right side (reversed) - no end caps
+    let n = points.len();
+    let distance = buffer_distance.abs();
+
     // Add left side
     polygon_points.extend(left_offsets);
 
+    // End cap, from the left side around to the right side
+    push_line_cap(&mut polygon_points, points[n - 1], points[n - 2], distance, style.cap);
+
     // Add right side (reversed) - this creates a simple closed polygon
     polygon_points.extend(right_offsets.into_iter().rev());
 
+    // Start cap, from the right side back to the left side
+    push_line_cap(&mut polygon_points, points[0], points[1], distance, style.cap);
+
     // Final validation: ensure we have enough points for a valid polygon
     if polygon_points.len() < 3 {
         return Vec::new();
@@ -3005,16 +3734,60 @@ fn create_linestring_buffer(linestring: &[Vec<f64>], buffer_distance: f64, bbox:
     polygon_points
 }
 
+// Points of the cap at line end `end`, whose segment comes from `from`; they run from the
+// side left of that direction to the side right of it, end points excluded
+fn push_line_cap(out: &mut Vec<Vector2>, end: Vector2, from: Vector2, distance: f64, cap: LineCap) {
+    let dx = end.x - from.x;
+    let dy = end.y - from.y;
+    let length = (dx * dx + dy * dy).sqrt();
+    if length < EPSILON {
+        return;
+    }
+    let (dir_x, dir_y) = (dx / length, dy / length);
+    let (left_x, left_y) = (-dir_y, dir_x);
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Square => {
+            out.push(Vector2 {
+                x: end.x + (left_x + dir_x) * distance,
+                y: end.y + (left_y + dir_y) * distance,
+            });
+            out.push(Vector2 {
+                x: end.x + (dir_x - left_x) * distance,
+                y: end.y + (dir_y - left_y) * distance,
+            });
+        }
+        LineCap::Round => {
+            // Half circle clockwise from the left side through the line direction
+            let start = left_y.atan2(left_x);
+            let steps = (std::f64::consts::PI / ROUND_STEP).ceil() as usize;
+            for k in 1..steps {
+                let angle = start - std::f64::consts::PI * k as f64 / steps as f64;
+                out.push(Vector2 {
+                    x: end.x + angle.cos() * distance,
+                    y: end.y + angle.sin() * distance,
+                });
+            }
+        }
+    }
+}
+
 // Create offset line with bevel joins at sharp angles to prevent self-intersections
 fn create_offset_line(points: &[Vector2], offset_distance: f64) -> Vec<Vector2> {
+    create_styled_offset_line(points, offset_distance, &LineStyle::default())
+}
+
+// Offset line with the corner style of `style`. Miter joins longer than the miter limit
+// are bevelled; round joins add an arc on the outside of a turn and miter the inside.
+fn create_styled_offset_line(
+    points: &[Vector2],
+    offset_distance: f64,
+    style: &LineStyle,
+) -> Vec<Vector2> {
     if points.len() < 2 {
         return Vec::new();
     }
 
-    // Miter limit - when angle is sharper than this, use bevel instead of miter
-    // cos(60°) = 0.5, meaning angles sharper than 120° will use bevel
-    const MITER_LIMIT_COS: f64 = 0.5;
-
     let mut offsets = Vec::new();
 
     for i in 0..points.len() {
@@ -3075,7 +3848,36 @@ fn create_offset_line(points: &[Vector2], offset_distance: f64) -> Vec<Vector2>
             let next_perp_x = -next_norm_y;
             let next_perp_y = next_norm_x;
 
-            if dot < MITER_LIMIT_COS {
+            // Miter length as a multiple of the offset distance
+            let miter_ratio = 1.0 / ((1.0 + dot) * 0.5).max(0.0).sqrt();
+            // The offset side lies outside the turn when it turns away from it
+            let cross = prev_norm_x * next_norm_y - prev_norm_y * next_norm_x;
+            let outside = cross * offset_distance < 0.0;
+
+            if style.join == LineJoin::Round && outside && dot < 1.0 - EPSILON {
+                // Arc around the corner point from one segment's offset to the next
+                let sign = offset_distance.signum();
+                let start = (prev_perp_y * sign).atan2(prev_perp_x * sign);
+                let end = (next_perp_y * sign).atan2(next_perp_x * sign);
+                let mut sweep = end - start;
+                if sweep > std::f64::consts::PI {
+                    sweep -= 2.0 * std::f64::consts::PI;
+                } else if sweep < -std::f64::consts::PI {
+                    sweep += 2.0 * std::f64::consts::PI;
+                }
+                let steps = (sweep.abs() / ROUND_STEP).ceil().max(1.0) as usize;
+                let radius = offset_distance.abs();
+                for k in 0..=steps {
+                    let angle = start + sweep * k as f64 / steps as f64;
+                    offsets.push(Vector2 {
+                        x: points[i].x + angle.cos() * radius,
+                        y: points[i].y + angle.sin() * radius,
+                    });
+                }
+            } else if (style.join == LineJoin::Bevel && dot < 1.0 - EPSILON)
+                || !miter_ratio.is_finite()
+                || miter_ratio > style.miter_limit
+            {
                 // Sharp angle - use bevel join (add both perpendicular points)
                 // This prevents self-intersection by not extending to the miter point
                 offsets.push(Vector2 {
@@ -3100,8 +3902,7 @@ fn create_offset_line(points: &[Vector2], offset_distance: f64) -> Vec<Vector2>
                     });
                 } else {
                     // Calculate proper miter distance
-                    let angle_factor = (1.0 / ((1.0 + dot) * 0.5).sqrt()).min(2.0);
-                    let scale = offset_distance * angle_factor;
+                    let scale = offset_distance * miter_ratio;
                     offsets.push(Vector2 {
                         x: points[i].x + (bisector_x / bisector_len) * scale,
                         y: points[i].y + (bisector_y / bisector_len) * scale,
@@ -3115,6 +3916,7 @@ fn create_offset_line(points: &[Vector2], offset_distance: f64) -> Vec<Vector2>
 }
 
 // Calculate simple perpendicular offset
+#[allow(dead_code)]
 fn calculate_simple_offset(p1: Vector2, p2: Vector2, offset_distance: f64) -> Vector2 {
     let dx = p2.x - p1.x;
     let dy = p2.y - p1.y;
@@ -3279,4 +4081,519 @@ fn create_linestring_quad_strip(
     }
 
     Some(LineStringMesh { vertices, indices })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_decode_base64_f32_values() {
+        // Standard alphabet without padding
+        assert_eq!(
+            decode_base64_to_f32_vec("AACAPwAAIMAAgMhC").unwrap(),
+            vec![1.0, -2.5, 100.25]
+        );
+        // Padding is optional, and '/' and '_' are the same symbol
+        let values = vec![3.4e38f32, -1e-3];
+        assert_eq!(decode_base64_to_f32_vec("nsl/f28Sg7o=").unwrap(), values);
+        assert_eq!(decode_base64_to_f32_vec("nsl_f28Sg7o").unwrap(), values);
+        assert_eq!(decode_base64_to_f32_vec("AACAPw==").unwrap(), vec![1.0]);
+        assert_eq!(decode_base64_to_f32_vec("AACA\nPw==").unwrap(), vec![1.0]);
+        assert!(decode_base64_to_f32_vec("").unwrap().is_empty());
+
+        assert!(decode_base64_to_f32_vec("AACA*w==")
+            .unwrap_err()
+            .contains("Invalid base64 character '*'"));
+        assert!(decode_base64_to_f32_vec("AACAP").is_err());
+        // Six bytes are one and a half f32 values
+        assert_eq!(
+            decode_base64_to_f32_vec("AACAPwAA").unwrap_err(),
+            "6 bytes of base64 data are not whole f32 values"
+        );
+    }
+
+    #[test]
+    fn test_csg_clipping_from_the_layer_config() {
+        // Shape sent by the app's layer worker: the layer config carries csgClipping
+        let mut input = serde_json::json!({
+            "terrainBaseHeight": 5.0,
+            "verticalExaggeration": 2.0,
+            "bbox": [13.0, 52.0, 13.1, 52.1],
+            "gridSize": {"width": 2, "height": 2},
+            "minElevation": 0.0,
+            "maxElevation": 10.0,
+            "terrainGridWidth": 2,
+            "terrainGridHeight": 2,
+            "terrainIsGpuLayout": false,
+            "vtDataSet": {
+                "sourceLayer": "building",
+                "color": "#aaaaaa",
+                "enabled": true,
+                "bufferSize": 2.0,
+                "fixedBufferSize": null,
+                "extrusionDepth": null,
+                "minClearance": null,
+                "stackOrder": null,
+                "alignVerticesToTerrain": null,
+                "csgClipping": true,
+                "filter": null,
+                "geometryDebugMode": false
+            },
+            "useSameZOffset": false,
+            "processId": "csg-test",
+            "minClearance": null,
+            "submergeOffset": null
+        });
+        let parse = |input: &serde_json::Value| -> PolygonGeometryInput {
+            serde_json::from_value(input.clone()).unwrap()
+        };
+        assert!(parse(&input).use_csg_clipping());
+
+        input["vtDataSet"]["csgClipping"] = serde_json::Value::Null;
+        assert!(!parse(&input).use_csg_clipping());
+        // A top-level value overrides the layer
+        input["csgClipping"] = true.into();
+        assert!(parse(&input).use_csg_clipping());
+        input["vtDataSet"]["csgClipping"] = true.into();
+        input["csgClipping"] = false.into();
+        assert!(!parse(&input).use_csg_clipping());
+    }
+
+    #[test]
+    fn test_malformed_feature_is_skipped_and_reported() {
+        let input = serde_json::json!({
+            "bbox": [13.0, 52.0, 13.1, 52.1],
+            "processId": "skip-test",
+            "flatBaseThickness": 2.0,
+            "vtDataSet": {"sourceLayer": "landuse", "extrusionDepth": 1.0},
+            "polygons": [
+                {"geometry": [[13.02, 52.02], [13.04, 52.02]], "type": "Polygon"},
+                {
+                    "geometry": [[14.02, 52.02], [14.08, 52.02], [14.08, 52.08], [14.02, 52.08]],
+                    "type": "Polygon"
+                }
+            ]
+        });
+        // The two-point ring is reported; the feature outside the bbox is dropped silently
+        let parse = |input: &serde_json::Value| -> PolygonGeometryInput {
+            serde_json::from_value(input.clone()).unwrap()
+        };
+        let output = block_on(generate_polygon_geometry(parse(&input))).unwrap();
+        assert!(output.geometries.is_empty());
+        assert_eq!(output.skipped.len(), 1);
+        assert_eq!(output.skipped[0].index, 0);
+        assert_eq!(output.skipped[0].reason, SkipReason::InvalidRing);
+
+        // Streaming reports progress per chunk up to the feature count
+        let mut progress = Vec::new();
+        let mut observer = |batch: &[BufferGeometry], processed: usize, total: usize| {
+            assert!(batch.is_empty());
+            progress.push((processed, total));
+            Ok(())
+        };
+        block_on(generate_polygon_geometry_streaming(parse(&input), Some(&mut observer))).unwrap();
+        assert_eq!(progress, [(2, 2)]);
+
+        let mut strict = input.clone();
+        strict["processingMode"] = serde_json::json!("strict");
+        let error = block_on(generate_polygon_geometry(parse(&strict)))
+            .err()
+            .unwrap();
+        assert!(error.contains("feature 0 rejected (InvalidRing)"));
+
+        let mut flat = parse(&serde_json::json!({
+            "bbox": [13.0, 52.0, 13.1, 52.1],
+            "processId": "flat-grid-test",
+            "gridSize": {"width": 3, "height": 2},
+            "vtDataSet": {"sourceLayer": "landuse"}
+        }));
+        flat.set_flat_elevation_grid(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.5]).unwrap();
+        assert_eq!(flat.elevation_grid, vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.5]]);
+        assert!(flat.set_flat_elevation_grid(&[1.0, 2.0]).is_err());
+
+        // Terrain vertices as base64 of little-endian f32: [1.0, -2.5]
+        flat.terrain_vertices_base64 = "AACAPwAAIMA=".to_string();
+        assert_eq!(flat.terrain_mesh_vertices().unwrap(), vec![1.0, -2.5]);
+        flat.terrain_vertices_base64 = "1.5, 2".to_string();
+        assert_eq!(flat.terrain_mesh_vertices().unwrap(), vec![1.5, 2.0]);
+        flat.terrain_vertices = vec![3.0];
+        assert_eq!(flat.terrain_mesh_vertices().unwrap(), vec![3.0]);
+
+        // Without a mesh in the input, the one stored for the process is sampled
+        flat.terrain_vertices.clear();
+        flat.terrain_vertices_base64.clear();
+        crate::store_terrain_mesh("flat-grid-test", vec![4.0, 5.0], Vec::new());
+        assert_eq!(flat.terrain_mesh_vertices().unwrap(), vec![4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_line_buffer_joins_and_caps() {
+        // Left turn of 90° at (10, 0)
+        let line = vec![vec![0.0, 0.0], vec![10.0, 0.0], vec![10.0, 10.0]];
+        let has_point = |ring: &[Vec<f64>], x: f64, y: f64| {
+            ring.iter()
+                .any(|p| (p[0] - x).abs() < 1e-9 && (p[1] - y).abs() < 1e-9)
+        };
+
+        // Default: the 90° corner exceeds the miter limit and is bevelled, ends are butt
+        let bevelled = buffer_polyline(&line, 1.0, &LineStyle::default());
+        assert_eq!(bevelled.len(), 9);
+        assert!(has_point(&bevelled, 10.0, -1.0) && has_point(&bevelled, 11.0, 0.0));
+
+        let style: LineStyle =
+            serde_json::from_str(r#"{"lineJoin": "miter", "miterLimit": 2, "lineCap": "square"}"#)
+                .unwrap();
+        let mitered = buffer_polyline(&line, 1.0, &style);
+        assert!(has_point(&mitered, 11.0, -1.0) && has_point(&mitered, 9.0, 1.0));
+        assert!(has_point(&mitered, -1.0, -1.0) && has_point(&mitered, -1.0, 1.0));
+        assert!(has_point(&mitered, 9.0, 11.0) && has_point(&mitered, 11.0, 11.0));
+
+        // Round joins and caps never reach further than the buffer distance
+        let style = LineStyle {
+            join: LineJoin::Round,
+            cap: LineCap::Round,
+            ..LineStyle::default()
+        };
+        let round = buffer_polyline(&line, 1.0, &style);
+        assert!(has_point(&round, -1.0, 0.0) && has_point(&round, 10.0, 11.0));
+        let distance_to_line = |p: &Vec<f64>| {
+            line.windows(2)
+                .map(|segment| {
+                    let (a, b) = (&segment[0], &segment[1]);
+                    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+                    let t = (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / (dx * dx + dy * dy))
+                        .clamp(0.0, 1.0);
+                    ((p[0] - a[0] - t * dx).powi(2) + (p[1] - a[1] - t * dy).powi(2)).sqrt()
+                })
+                .fold(f64::INFINITY, f64::min)
+        };
+        assert!(round.iter().all(|p| distance_to_line(p) <= 1.0 + 1e-9));
+        let corner = std::f64::consts::FRAC_1_SQRT_2;
+        assert!(has_point(&round, 10.0 + corner, -corner));
+    }
+
+    #[test]
+    fn test_color_expression_colors_features() {
+        let layer: VtDataSet = serde_json::from_value(serde_json::json!({
+            "sourceLayer": "building",
+            "color": "#808080",
+            "colorExpression": [
+                "match", ["get", "class"],
+                "church", "#f00",
+                ["school", "university"], "#0000ff",
+                "not a color"
+            ]
+        }))
+        .unwrap();
+        let feature = |class: &str| GeometryData {
+            geometry: vec![vec![0.0, 0.0], vec![1.0, 0.0], vec![0.0, 1.0]],
+            holes: None,
+            r#type: Some("Polygon".to_string()),
+            height: None,
+            layer: None,
+            label: None,
+            tags: None,
+            properties: Some(serde_json::json!({ "class": class })),
+        };
+        assert_eq!(
+            layer.feature_color(&feature("church")),
+            Some([1.0, 0.0, 0.0])
+        );
+        assert_eq!(
+            layer.feature_color(&feature("university")),
+            Some([0.0, 0.0, 1.0])
+        );
+        // Invalid colors fall back to the layer color
+        let grey = 128.0 / 255.0;
+        assert_eq!(
+            layer.feature_color(&feature("house")),
+            Some([grey, grey, grey])
+        );
+
+        let plain: VtDataSet =
+            serde_json::from_value(serde_json::json!({ "sourceLayer": "building" })).unwrap();
+        assert_eq!(plain.feature_color(&feature("church")), None);
+    }
+
+    #[test]
+    fn test_layer_height_limits() {
+        let layer = |config: serde_json::Value| -> VtDataSet {
+            serde_json::from_value(config).unwrap()
+        };
+        let default = layer(serde_json::json!({ "sourceLayer": "building" }));
+        assert_eq!(default.height_limits(), (MIN_HEIGHT, MAX_HEIGHT));
+        assert_eq!(default.clamp_height(900.0), MAX_HEIGHT);
+        assert_eq!(default.clamp_height(0.0), MIN_HEIGHT);
+
+        // Tall towers keep their height when the layer allows it
+        let towers = layer(serde_json::json!({
+            "sourceLayer": "building",
+            "minHeight": 2.0,
+            "maxHeight": 800.0
+        }));
+        assert_eq!(towers.clamp_height(650.0), 650.0);
+        assert_eq!(towers.clamp_height(0.5), 2.0);
+
+        // Invalid limits fall back to the defaults, never below the minimum
+        let invalid = layer(serde_json::json!({
+            "sourceLayer": "building",
+            "minHeight": -1.0,
+            "maxHeight": 0.001
+        }));
+        assert_eq!(invalid.height_limits(), (MIN_HEIGHT, MAX_HEIGHT));
+        let high_min = layer(serde_json::json!({ "sourceLayer": "building", "minHeight": 600.0 }));
+        assert_eq!(high_min.height_limits(), (600.0, 600.0));
+
+        assert!(default.validate().is_ok());
+        let inverted = layer(serde_json::json!({
+            "sourceLayer": "building",
+            "minHeight": 10.0,
+            "maxHeight": 5.0
+        }));
+        assert_eq!(
+            inverted.validate().unwrap_err(),
+            "min_height 10 exceeds max_height 5"
+        );
+    }
+
+    #[test]
+    fn test_clearance_and_submerge_offset_resolution() {
+        let mut input = serde_json::json!({
+            "bbox": [13.0, 52.0, 13.1, 52.1],
+            "processId": "clearance-test",
+            "vtDataSet": {"sourceLayer": "building"},
+            "polygons": []
+        });
+        let parse = |input: &serde_json::Value| -> PolygonGeometryInput {
+            serde_json::from_value(input.clone()).unwrap()
+        };
+        let defaults = parse(&input);
+        assert_eq!(defaults.min_clearance(), MIN_CLEARANCE);
+        assert_eq!(defaults.submerge_offset(), BUILDING_SUBMERGE_OFFSET);
+
+        // The pipeline setting overrides the default
+        input["minClearance"] = 0.0.into();
+        input["submergeOffset"] = 0.3.into();
+        assert_eq!(parse(&input).min_clearance(), 0.0);
+        assert_eq!(parse(&input).submerge_offset(), 0.3);
+
+        // The layer setting overrides the pipeline
+        input["vtDataSet"]["minClearance"] = 0.5.into();
+        input["vtDataSet"]["submergeOffset"] = 1.0.into();
+        assert_eq!(parse(&input).min_clearance(), 0.5);
+        assert_eq!(parse(&input).submerge_offset(), 1.0);
+
+        // Invalid layer values are ignored in favour of the pipeline setting
+        input["vtDataSet"]["minClearance"] = (-0.5).into();
+        input["vtDataSet"]["submergeOffset"] = (-1.0).into();
+        assert_eq!(parse(&input).min_clearance(), 0.0);
+        assert_eq!(parse(&input).submerge_offset(), 0.3);
+
+        // ...and invalid pipeline values in favour of the default
+        input["minClearance"] = (-2.0).into();
+        input["submergeOffset"] = (-2.0).into();
+        assert_eq!(parse(&input).min_clearance(), MIN_CLEARANCE);
+        assert_eq!(parse(&input).submerge_offset(), BUILDING_SUBMERGE_OFFSET);
+    }
+
+    #[test]
+    fn test_stack_order_offsets() {
+        let mut input = serde_json::json!({
+            "bbox": [13.0, 52.0, 13.1, 52.1],
+            "processId": "stack-test",
+            "minClearance": 0.2,
+            "vtDataSet": {"sourceLayer": "landuse"},
+            "polygons": []
+        });
+        let parse = |input: &serde_json::Value| -> PolygonGeometryInput {
+            serde_json::from_value(input.clone()).unwrap()
+        };
+        assert_eq!(parse(&input).vt_data_set.stack_offset(), 0.0);
+        assert_eq!(parse(&input).stacked_clearance(), 0.2);
+
+        input["vtDataSet"]["stackOrder"] = 3.into();
+        let raised = parse(&input);
+        assert!((raised.vt_data_set.stack_offset() - 3.0 * STACK_ORDER_STEP).abs() < 1e-12);
+        assert!((raised.stacked_clearance() - (0.2 + 3.0 * STACK_ORDER_STEP)).abs() < 1e-12);
+
+        // Orders past the limit clamp to the maximum shift in either direction
+        let max_offset = MAX_STACK_ORDER as f64 * STACK_ORDER_STEP;
+        input["vtDataSet"]["stackOrder"] = 1000.into();
+        assert_eq!(parse(&input).vt_data_set.stack_offset(), max_offset);
+        input["vtDataSet"]["stackOrder"] = (-1000).into();
+        let lowered = parse(&input);
+        assert_eq!(lowered.vt_data_set.stack_offset(), -max_offset);
+
+        // A negative shift never pushes the layer below the terrain surface
+        assert_eq!(lowered.stacked_clearance(), 0.0);
+    }
+
+    /// Flat-base water layer input with a grid of `count` small squares inside the bbox;
+    /// water is not merged, so the output keeps one geometry per square
+    fn square_grid_input(process_id: &str, count: usize) -> PolygonGeometryInput {
+        let polygons: Vec<serde_json::Value> = (0..count)
+            .map(|i| {
+                let lng = 13.002 + (i % 20) as f64 * 0.0045;
+                let lat = 52.002 + (i / 20) as f64 * 0.0045;
+                serde_json::json!({
+                    "geometry": [[lng, lat], [lng + 0.002, lat], [lng + 0.002, lat + 0.002], [lng, lat + 0.002]],
+                    "type": "Polygon"
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "bbox": [13.0, 52.0, 13.1, 52.1],
+            "processId": process_id,
+            "flatBaseThickness": 2.0,
+            "vtDataSet": {"sourceLayer": "water", "extrusionDepth": 1.0},
+            "polygons": polygons
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_streaming_hands_out_every_chunk() {
+        let mut progress = Vec::new();
+        let mut streamed = 0;
+        let mut observer = |batch: &[BufferGeometry], processed: usize, total: usize| {
+            assert!(batch.iter().all(|geometry| geometry.has_data));
+            streamed += batch.len();
+            progress.push((processed, total));
+            Ok(())
+        };
+        let output = block_on(generate_polygon_geometry_streaming(
+            square_grid_input("streaming-test", 150),
+            Some(&mut observer),
+        ))
+        .unwrap();
+        assert!(output.skipped.is_empty());
+        assert_eq!(output.geometries.len(), 150);
+
+        // The first chunk has the initial size; progress only grows, up to the feature count
+        assert_eq!(progress[0], (100, 150));
+        assert!(progress.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(progress.last(), Some(&(150, 150)));
+        // Every feature is streamed once, before merging
+        assert_eq!(streamed, 150);
+
+        // An observer error aborts the layer
+        let mut failing = |_: &[BufferGeometry], _: usize, _: usize| Err("stop".to_string());
+        let error = block_on(generate_polygon_geometry_streaming(
+            square_grid_input("streaming-test", 150),
+            Some(&mut failing),
+        ))
+        .err();
+        assert_eq!(error.as_deref(), Some("stop"));
+    }
+
+    #[test]
+    fn test_cancel_process_stops_the_layer_between_chunks() {
+        let process_id = "cancel-layer-test";
+        let mut progress = Vec::new();
+        let mut observer = |_: &[BufferGeometry], processed: usize, _: usize| {
+            progress.push(processed);
+            crate::cancellation::cancel_process(process_id);
+            Ok(())
+        };
+        let error = block_on(generate_polygon_geometry_streaming(
+            square_grid_input(process_id, 150),
+            Some(&mut observer),
+        ))
+        .err()
+        .unwrap();
+        assert!(error.starts_with("Cancelled"));
+        // The second chunk never started
+        assert_eq!(progress, [100]);
+
+        // Later jobs of the process start with a fresh token
+        let output = block_on(generate_polygon_geometry(square_grid_input(process_id, 10))).unwrap();
+        assert_eq!(output.geometries.len(), 10);
+    }
+
+    #[test]
+    fn test_csg_clipping_cuts_features_to_the_terrain_surface() {
+        let input = |csg_clipping: bool| -> PolygonGeometryInput {
+            serde_json::from_value(serde_json::json!({
+                "bbox": [13.0, 52.0, 13.1, 52.1],
+                "processId": "terrain-clip-test",
+                "gridSize": {"width": 2, "height": 2},
+                "elevationGrid": [[0.0, 40.0], [0.0, 40.0]],
+                "minElevation": 0.0,
+                "maxElevation": 40.0,
+                "terrainBaseHeight": 5.0,
+                "verticalExaggeration": 1.0,
+                "vtDataSet": {"sourceLayer": "building", "csgClipping": csg_clipping},
+                "polygons": [{
+                    "geometry": [[13.04, 52.04], [13.06, 52.04], [13.06, 52.06], [13.04, 52.06]],
+                    "type": "Polygon",
+                    "height": 20.0
+                }]
+            }))
+            .unwrap()
+        };
+        // Depth of the deepest vertex below the sloped terrain surface
+        let buried_depth = |csg_clipping: bool| {
+            let output = block_on(generate_polygon_geometry(input(csg_clipping))).unwrap();
+            let input = input(csg_clipping);
+            let datum = input.vertical_datum();
+            assert!(!output.geometries.is_empty());
+            output
+                .geometries
+                .iter()
+                .flat_map(|geometry| geometry.vertices.chunks(3))
+                .map(|v| {
+                    let surface = sample_terrain_mesh_height_at_point(
+                        v[0] as f64,
+                        v[1] as f64,
+                        &input.elevation_grid,
+                        &datum,
+                    );
+                    surface - v[2] as f64
+                })
+                .fold(f64::MIN, f64::max)
+        };
+        // The building stands on the lowest terrain point, so its uphill side is buried
+        assert!(buried_depth(false) > 0.5);
+        assert!(buried_depth(true) < 1e-3);
+    }
+
+    #[test]
+    fn test_csg_clipping_keeps_feature_colors() {
+        for source_layer in ["building", "landuse"] {
+            let input: PolygonGeometryInput = serde_json::from_value(serde_json::json!({
+                "bbox": [13.0, 52.0, 13.1, 52.1],
+                "processId": "color-clip-test",
+                "gridSize": {"width": 2, "height": 2},
+                "elevationGrid": [[0.0, 40.0], [0.0, 40.0]],
+                "minElevation": 0.0,
+                "maxElevation": 40.0,
+                "terrainBaseHeight": 5.0,
+                "verticalExaggeration": 1.0,
+                "vtDataSet": {
+                    "sourceLayer": source_layer,
+                    "extrusionDepth": 2.0,
+                    "csgClipping": true,
+                    "colorExpression": ["match", ["get", "class"], "park", "#f00", "#808080"]
+                },
+                // Crosses the east tile edge
+                "polygons": [{
+                    "geometry": [[13.09, 52.04], [13.11, 52.04], [13.11, 52.06], [13.09, 52.06]],
+                    "type": "Polygon",
+                    "height": 20.0,
+                    "properties": {"class": "park"}
+                }]
+            }))
+            .unwrap();
+            let output = block_on(generate_polygon_geometry(input)).unwrap();
+            assert_eq!(output.geometries.len(), 1);
+            let geometry = &output.geometries[0];
+            let half_tile = terrain_size() as f32 / 2.0;
+            assert!(geometry.vertices.chunks(3).all(|v| v[0] <= half_tile + 1e-3));
+            let colors = geometry.colors.as_ref().expect("colors");
+            assert_eq!(colors.len(), geometry.vertices.len());
+            assert!(colors.chunks(3).all(|c| c == [1.0, 0.0, 0.0]));
+        }
+    }
+}
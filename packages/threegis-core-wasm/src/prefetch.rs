@@ -0,0 +1,134 @@
+// Background warm-up of the raster and vector tile caches for an area the user is
+// still planning, so generation after confirming the selection hits warm caches.
+// Tiles are fetched one at a time, yielding to the event loop in between, and the
+// run stops early when its cancellation token is cancelled.
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::cancellation;
+use crate::elevation::fetch_raster_tile;
+use crate::module_state::{create_tile_key, ModuleState};
+use crate::rate_limit::sleep_ms;
+use crate::tilejson::source_tiles;
+use crate::vectortile::{get_tiles_for_bbox, load_vector_tile, TileRequest};
+
+/// Tile cache a prefetch or revalidation works on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TileSource {
+    Raster,
+    Vector,
+}
+
+impl TileSource {
+    pub fn parse(source: &str) -> Result<TileSource, JsValue> {
+        match source {
+            "raster" => Ok(TileSource::Raster),
+            "vector" => Ok(TileSource::Vector),
+            _ => Err(JsValue::from_str(&format!(
+                "Unknown tile source '{}', expected 'raster' or 'vector'",
+                source
+            ))),
+        }
+    }
+
+    /// "raster" (elevation) and/or "vector"; both when the list is empty
+    pub fn parse_list(sources: &[String]) -> Result<Vec<TileSource>, JsValue> {
+        if sources.is_empty() {
+            return Ok(vec![TileSource::Raster, TileSource::Vector]);
+        }
+        sources.iter().map(|source| Self::parse(source)).collect()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TileSource::Raster => "raster",
+            TileSource::Vector => "vector",
+        }
+    }
+
+    pub fn is_cached(self, tile: &TileRequest) -> bool {
+        ModuleState::with(|state| match self {
+            TileSource::Raster => state
+                .raster_tiles
+                .contains_key(&create_tile_key(tile.x, tile.y, tile.z)),
+            TileSource::Vector => state
+                .mvt_parsed_tiles
+                .contains_key(&format!("{}/{}/{}", tile.z, tile.x, tile.y)),
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct PrefetchResult {
+    /// Tiles covering the bbox, counted per requested source
+    pub requested: usize,
+    /// Tiles that were already cached
+    pub cached: usize,
+    pub fetched: usize,
+    pub failed: usize,
+    pub cancelled: bool,
+}
+
+/// Warm the tile caches for `bbox` ([minLng, minLat, maxLng, maxLat]) at `zoom`.
+/// `sources` lists "raster" (elevation) and/or "vector"; both when empty.
+/// Pass `prefetch_id` to make the run cancellable with `cancel_operation(prefetch_id)`;
+/// starting a new prefetch with the same id cancels the previous one.
+#[wasm_bindgen]
+pub async fn prefetch_tiles(
+    bbox: Vec<f64>,
+    zoom: u32,
+    sources: Vec<String>,
+    prefetch_id: Option<String>,
+) -> Result<String, JsValue> {
+    let sources = TileSource::parse_list(&sources)?;
+
+    let token = prefetch_id.as_deref().and_then(|id| {
+        cancellation::create_cancellation_token(id);
+        cancellation::get_cancellation_token(id)
+    });
+    let is_cancelled = || token.as_ref().is_some_and(|t| t.is_cancelled());
+
+    let mut result = PrefetchResult::default();
+    'sources: for source in sources {
+        for tile in &source_tiles(source, &bbox, zoom)? {
+            if is_cancelled() {
+                result.cancelled = true;
+                break 'sources;
+            }
+            result.requested += 1;
+
+            if source.is_cached(tile) {
+                result.cached += 1;
+                continue;
+            }
+
+            // Low priority: let pending generation work run before each fetch
+            sleep_ms(0.0).await;
+            let fetched = match source {
+                TileSource::Raster => fetch_raster_tile(tile.x, tile.y, tile.z).await.map(|_| ()),
+                TileSource::Vector => load_vector_tile(tile, None).await.map(|_| ()),
+            };
+            match fetched {
+                Ok(()) => result.fetched += 1,
+                Err(_) => result.failed += 1,
+            }
+        }
+    }
+
+    // A cancelled token may already have been replaced by a newer prefetch with the same id
+    if let Some(id) = prefetch_id.as_deref().filter(|_| !result.cancelled) {
+        cancellation::cleanup_cancellation_token(id);
+    }
+    serde_json::to_string(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize prefetch result: {}", e)))
+}
+
+/// Tiles covering a [minLng, minLat, maxLng, maxLat] bbox
+pub(crate) fn tiles_for_bbox(bbox: &[f64], zoom: u32) -> Result<Vec<TileRequest>, JsValue> {
+    if bbox.len() != 4 {
+        return Err(JsValue::from_str(
+            "Invalid bbox: must contain [minLng, minLat, maxLng, maxLat]",
+        ));
+    }
+    Ok(get_tiles_for_bbox(bbox[0], bbox[1], bbox[2], bbox[3], zoom))
+}
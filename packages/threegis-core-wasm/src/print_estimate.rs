@@ -0,0 +1,190 @@
+// Rough material, weight, cost and print-time estimates per layer for the export dialog.
+// Built on mesh metrics: a solid is modelled as perimeter shell plus partial infill.
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::mesh_metrics::{mesh_metrics, MeshMetricsInput};
+
+// PLA
+const DEFAULT_DENSITY_G_PER_CM3: f64 = 1.24;
+const DEFAULT_INFILL: f64 = 0.2;
+const DEFAULT_SHELL_THICKNESS_MM: f64 = 0.8;
+// Typical sustained volumetric flow of a 0.4mm FDM nozzle
+const DEFAULT_FLOW_MM3_PER_S: f64 = 8.0;
+// Travel, retraction and acceleration overhead on top of pure extrusion time
+const PRINT_TIME_OVERHEAD: f64 = 1.3;
+
+#[derive(Deserialize)]
+pub struct PrintLayerInput {
+    pub name: Option<String>,
+    pub vertices: Vec<f32>,
+    #[serde(default)]
+    pub indices: Option<Vec<u32>>,
+}
+
+#[derive(Deserialize)]
+pub struct PrintEstimateInput {
+    pub layers: Vec<PrintLayerInput>,
+    /// Physical size in millimeters of the longest model side (as in the 3MF export)
+    #[serde(rename = "modelSizeMm")]
+    pub model_size_mm: f64,
+    /// Process the layers were generated in, whose terrain scale sizes the model
+    #[serde(default, rename = "processId")]
+    pub process_id: Option<String>,
+    /// Material density in g/cm³
+    #[serde(default)]
+    pub density: Option<f64>,
+    /// Infill fraction 0..1 of the interior behind the shell; 1.0 for resin prints
+    #[serde(default)]
+    pub infill: Option<f64>,
+    #[serde(default, rename = "shellThicknessMm")]
+    pub shell_thickness_mm: Option<f64>,
+    #[serde(default, rename = "costPerKg")]
+    pub cost_per_kg: Option<f64>,
+    /// Volumetric extrusion rate used for the print-time heuristic
+    #[serde(default, rename = "flowMm3PerSecond")]
+    pub flow_mm3_per_second: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct MaterialEstimate {
+    /// Volume enclosed by the mesh in cm³
+    #[serde(rename = "solidVolumeCm3")]
+    pub solid_volume_cm3: f64,
+    /// Volume of material actually deposited in cm³
+    #[serde(rename = "materialVolumeCm3")]
+    pub material_volume_cm3: f64,
+    #[serde(rename = "weightG")]
+    pub weight_g: f64,
+    pub cost: Option<f64>,
+    #[serde(rename = "printTimeMinutes")]
+    pub print_time_minutes: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LayerEstimate {
+    pub name: String,
+    #[serde(flatten)]
+    pub estimate: MaterialEstimate,
+    /// The mesh is open, so its volume (and everything derived from it) is unreliable
+    #[serde(rename = "volumeUnreliable")]
+    pub volume_unreliable: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PrintEstimate {
+    pub layers: Vec<LayerEstimate>,
+    pub total: MaterialEstimate,
+}
+
+/// Estimate material volume, weight, cost and print time per layer; returns JSON
+#[wasm_bindgen]
+pub fn estimate_print_material(input_json: &str) -> Result<String, JsValue> {
+    let input: PrintEstimateInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let estimate = print_estimate(input).map_err(|e| JsValue::from_str(&e))?;
+    serde_json::to_string(&estimate)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize estimate: {}", e)))
+}
+
+pub(crate) fn print_estimate(input: PrintEstimateInput) -> Result<PrintEstimate, String> {
+    if !input.model_size_mm.is_finite() || input.model_size_mm <= 0.0 {
+        return Err("modelSizeMm must be a positive number".to_string());
+    }
+    let positive = |value: Option<f64>, default: f64| {
+        value
+            .filter(|v| v.is_finite() && *v > 0.0)
+            .unwrap_or(default)
+    };
+    let density = positive(input.density, DEFAULT_DENSITY_G_PER_CM3);
+    let shell_thickness = positive(input.shell_thickness_mm, DEFAULT_SHELL_THICKNESS_MM);
+    let flow = positive(input.flow_mm3_per_second, DEFAULT_FLOW_MM3_PER_S);
+    let infill = input
+        .infill
+        .filter(|v| v.is_finite())
+        .unwrap_or(DEFAULT_INFILL)
+        .clamp(0.0, 1.0);
+
+    let mut layers = Vec::with_capacity(input.layers.len());
+    let mut total = MaterialEstimate::default();
+    for (i, layer) in input.layers.into_iter().enumerate() {
+        let name = layer.name.unwrap_or_else(|| format!("layer_{}", i));
+        let metrics = mesh_metrics(&MeshMetricsInput {
+            vertices: layer.vertices,
+            indices: layer.indices,
+            model_size_mm: Some(input.model_size_mm),
+            process_id: input.process_id.clone(),
+        })
+        .map_err(|e| format!("Layer '{}': {}", name, e))?;
+        let Some(mm) = metrics.millimeters else {
+            continue;
+        };
+
+        // Shell is the outer skin; only the remaining interior is filled partially
+        let shell_mm3 = (mm.surface_area * shell_thickness).min(mm.volume);
+        let material_mm3 = shell_mm3 + (mm.volume - shell_mm3) * infill;
+        let estimate = MaterialEstimate {
+            solid_volume_cm3: mm.volume / 1000.0,
+            material_volume_cm3: material_mm3 / 1000.0,
+            weight_g: material_mm3 / 1000.0 * density,
+            cost: input
+                .cost_per_kg
+                .map(|per_kg| material_mm3 / 1000.0 * density / 1000.0 * per_kg),
+            print_time_minutes: material_mm3 / flow * PRINT_TIME_OVERHEAD / 60.0,
+        };
+
+        total.solid_volume_cm3 += estimate.solid_volume_cm3;
+        total.material_volume_cm3 += estimate.material_volume_cm3;
+        total.weight_g += estimate.weight_g;
+        total.print_time_minutes += estimate.print_time_minutes;
+        if let Some(cost) = estimate.cost {
+            total.cost = Some(total.cost.unwrap_or(0.0) + cost);
+        }
+        layers.push(LayerEstimate {
+            name,
+            estimate,
+            volume_unreliable: !metrics.is_closed,
+        });
+    }
+
+    Ok(PrintEstimate { layers, total })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solid_cube_estimate() {
+        // 10 mesh units cube printed at 200 mm for the 200-unit model → 10mm cube, 1 cm³
+        let vertices = vec![
+            0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 10.0, 10.0, 0.0, 0.0, 10.0, 0.0, //
+            0.0, 0.0, 10.0, 10.0, 0.0, 10.0, 10.0, 10.0, 10.0, 0.0, 10.0, 10.0,
+        ];
+        let indices = vec![
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, //
+            1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+        ];
+        let input = PrintEstimateInput {
+            layers: vec![PrintLayerInput {
+                name: Some("buildings".to_string()),
+                vertices,
+                indices: Some(indices),
+            }],
+            model_size_mm: 200.0,
+            process_id: None,
+            density: Some(1.0),
+            infill: Some(1.0),
+            shell_thickness_mm: None,
+            cost_per_kg: Some(20.0),
+            flow_mm3_per_second: None,
+        };
+        let estimate = print_estimate(input).unwrap();
+
+        let layer = &estimate.layers[0];
+        assert!(!layer.volume_unreliable);
+        assert!((layer.estimate.solid_volume_cm3 - 1.0).abs() < 1e-9);
+        assert!((layer.estimate.weight_g - 1.0).abs() < 1e-9);
+        assert!((estimate.total.cost.unwrap() - 0.02).abs() < 1e-9);
+    }
+}
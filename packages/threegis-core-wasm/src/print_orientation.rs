@@ -0,0 +1,303 @@
+// Print orientation suggestion. The final solid is evaluated in a handful of
+// axis-aligned candidate orientations; for each, downward-facing triangles steeper
+// than the overhang angle need support, whose volume is approximated by their
+// projected area times their height above the build plate.
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::export_3mf::millimeters_per_unit;
+
+const DEFAULT_OVERHANG_ANGLE_DEG: f64 = 45.0;
+// Faces this close to the lowest point rest on the build plate and need no support
+const PLATE_EPSILON: f64 = 1e-3;
+
+type Mat3 = [[f64; 3]; 3];
+
+#[derive(Deserialize)]
+pub struct OrientationMesh {
+    pub vertices: Vec<f32>,
+    #[serde(default)]
+    pub indices: Option<Vec<u32>>,
+}
+
+#[derive(Deserialize)]
+pub struct OrientationInput {
+    pub meshes: Vec<OrientationMesh>,
+    /// Maximum printable overhang measured from vertical, in degrees
+    #[serde(default, rename = "overhangAngle")]
+    pub overhang_angle: Option<f64>,
+    /// Reports metrics in millimeters instead of mesh units when set
+    #[serde(default, rename = "modelSizeMm")]
+    pub model_size_mm: Option<f64>,
+    /// Process the mesh was generated in, whose terrain scale sizes the model
+    #[serde(default, rename = "processId")]
+    pub process_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct OrientationCandidate {
+    pub name: &'static str,
+    /// 4x4 column-major rotation about the model center (same layout as 3MF mesh transforms)
+    pub matrix: [f64; 16],
+    #[serde(rename = "overhangArea")]
+    pub overhang_area: f64,
+    #[serde(rename = "supportVolume")]
+    pub support_volume: f64,
+    /// Print height along the build direction
+    pub height: f64,
+    /// Area resting on the build plate
+    #[serde(rename = "contactArea")]
+    pub contact_area: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrientationSuggestion {
+    pub best: OrientationCandidate,
+    /// All evaluated candidates, best first
+    pub candidates: Vec<OrientationCandidate>,
+    /// "mm" when modelSizeMm was given, otherwise "units"
+    pub unit: &'static str,
+}
+
+/// Suggest a print orientation for the combined meshes; returns JSON
+#[wasm_bindgen]
+pub fn suggest_print_orientation(input_json: &str) -> Result<String, JsValue> {
+    let input: OrientationInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    let suggestion = suggest_orientation(&input).map_err(|e| JsValue::from_str(&e))?;
+    serde_json::to_string(&suggestion)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize orientation: {}", e)))
+}
+
+fn candidate_rotations() -> [(&'static str, Mat3); 6] {
+    [
+        (
+            "upright",
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        ),
+        (
+            "upside-down",
+            [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]],
+        ),
+        // +90° about x: y → z
+        (
+            "north-up",
+            [[1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]],
+        ),
+        (
+            "south-up",
+            [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]],
+        ),
+        // -90° about y: x → z
+        (
+            "east-up",
+            [[0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+        ),
+        (
+            "west-up",
+            [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [-1.0, 0.0, 0.0]],
+        ),
+    ]
+}
+
+pub(crate) fn suggest_orientation(
+    input: &OrientationInput,
+) -> Result<OrientationSuggestion, String> {
+    let mut triangles: Vec<[[f64; 3]; 3]> = Vec::new();
+    for (mesh_index, mesh) in input.meshes.iter().enumerate() {
+        let vertex_count = mesh.vertices.len() / 3;
+        let vertex = |i: usize| -> Result<[f64; 3], String> {
+            if i >= vertex_count {
+                return Err(format!("Mesh {}: index {} out of range", mesh_index, i));
+            }
+            let v = &mesh.vertices[i * 3..i * 3 + 3];
+            Ok([v[0] as f64, v[1] as f64, v[2] as f64])
+        };
+        match &mesh.indices {
+            Some(indices) => {
+                for t in indices.chunks_exact(3) {
+                    triangles.push([
+                        vertex(t[0] as usize)?,
+                        vertex(t[1] as usize)?,
+                        vertex(t[2] as usize)?,
+                    ]);
+                }
+            }
+            None => {
+                for t in 0..vertex_count / 3 {
+                    triangles.push([vertex(t * 3)?, vertex(t * 3 + 1)?, vertex(t * 3 + 2)?]);
+                }
+            }
+        }
+    }
+    if triangles.is_empty() {
+        return Err("No triangles to orient".to_string());
+    }
+
+    let overhang_angle = input
+        .overhang_angle
+        .filter(|a| a.is_finite() && *a > 0.0 && *a < 90.0)
+        .unwrap_or(DEFAULT_OVERHANG_ANGLE_DEG);
+    // Downward faces tilted further from vertical than the overhang angle need support
+    let support_threshold = overhang_angle.to_radians().sin();
+    let mm_per_unit = millimeters_per_unit(input.model_size_mm, input.process_id.as_deref());
+    let (scale, unit) = match mm_per_unit {
+        Some(mm) => (mm, "mm"),
+        None => (1.0, "units"),
+    };
+
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for point in triangles.iter().flatten() {
+        for (axis, value) in point.iter().enumerate() {
+            min[axis] = min[axis].min(*value);
+            max[axis] = max[axis].max(*value);
+        }
+    }
+    let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+
+    let mut candidates: Vec<OrientationCandidate> = candidate_rotations()
+        .into_iter()
+        .map(|(name, rotation)| evaluate(name, &rotation, &center, &triangles, support_threshold, scale))
+        .collect();
+    // Least support first; on ties prefer larger plate contact, then lower height
+    candidates.sort_by(|a, b| {
+        a.support_volume
+            .total_cmp(&b.support_volume)
+            .then(b.contact_area.total_cmp(&a.contact_area))
+            .then(a.height.total_cmp(&b.height))
+    });
+
+    Ok(OrientationSuggestion {
+        best: candidates[0].clone(),
+        candidates,
+        unit,
+    })
+}
+
+fn evaluate(
+    name: &'static str,
+    rotation: &Mat3,
+    center: &[f64; 3],
+    triangles: &[[[f64; 3]; 3]],
+    support_threshold: f64,
+    scale: f64,
+) -> OrientationCandidate {
+    let rotate = |p: &[f64; 3]| -> [f64; 3] {
+        let d = [p[0] - center[0], p[1] - center[1], p[2] - center[2]];
+        [0, 1, 2]
+            .map(|row| rotation[row][0] * d[0] + rotation[row][1] * d[1] + rotation[row][2] * d[2])
+    };
+    let rotated: Vec<[[f64; 3]; 3]> = triangles
+        .iter()
+        .map(|t| [rotate(&t[0]), rotate(&t[1]), rotate(&t[2])])
+        .collect();
+
+    let mut min_z = f64::INFINITY;
+    let mut max_z = f64::NEG_INFINITY;
+    for point in rotated.iter().flatten() {
+        min_z = min_z.min(point[2]);
+        max_z = max_z.max(point[2]);
+    }
+
+    let mut overhang_area = 0.0;
+    let mut support_volume = 0.0;
+    let mut contact_area = 0.0;
+    for [a, b, c] in &rotated {
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let normal = [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ];
+        let double_area =
+            (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+        if double_area <= f64::EPSILON {
+            continue;
+        }
+        let area = double_area / 2.0;
+        let down = -normal[2] / double_area;
+        if down < support_threshold {
+            continue;
+        }
+        let highest = a[2].max(b[2]).max(c[2]);
+        if highest - min_z < PLATE_EPSILON {
+            contact_area += area;
+            continue;
+        }
+        overhang_area += area;
+        // Projected footprint times the mean height above the plate
+        let centroid_height = (a[2] + b[2] + c[2]) / 3.0 - min_z;
+        support_volume += area * down * centroid_height;
+    }
+
+    // Column-major 4x4 with the rotation applied about the model center
+    let translation = [0, 1, 2].map(|row| {
+        center[row]
+            - (rotation[row][0] * center[0]
+                + rotation[row][1] * center[1]
+                + rotation[row][2] * center[2])
+    });
+    let matrix = [
+        rotation[0][0],
+        rotation[1][0],
+        rotation[2][0],
+        0.0,
+        rotation[0][1],
+        rotation[1][1],
+        rotation[2][1],
+        0.0,
+        rotation[0][2],
+        rotation[1][2],
+        rotation[2][2],
+        0.0,
+        translation[0],
+        translation[1],
+        translation[2],
+        1.0,
+    ];
+
+    OrientationCandidate {
+        name,
+        matrix,
+        overhang_area: overhang_area * scale * scale,
+        support_volume: support_volume * scale * scale * scale,
+        height: (max_z - min_z) * scale,
+        contact_area: contact_area * scale * scale,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_base_prefers_upright() {
+        // Thin closed slab: lying flat needs no support, has the largest plate contact
+        // and the lowest height
+        let vertices = vec![
+            0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 10.0, 10.0, 0.0, 0.0, 10.0, 0.0, //
+            0.0, 0.0, 1.0, 10.0, 0.0, 1.0, 10.0, 10.0, 1.0, 0.0, 10.0, 1.0,
+        ];
+        let indices = vec![
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, //
+            1, 2, 6, 1, 6, 5, 2, 3, 7, 2, 7, 6, 3, 0, 4, 3, 4, 7,
+        ];
+        let input = OrientationInput {
+            meshes: vec![OrientationMesh {
+                vertices,
+                indices: Some(indices),
+            }],
+            overhang_angle: None,
+            model_size_mm: None,
+            process_id: None,
+        };
+        let suggestion = suggest_orientation(&input).unwrap();
+
+        assert_eq!(suggestion.candidates.len(), 6);
+        assert_eq!(suggestion.best.support_volume, 0.0);
+        assert!((suggestion.best.height - 1.0).abs() < 1e-9);
+        assert!((suggestion.best.contact_area - 100.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,311 @@
+// Coordinate spaces of the pipeline and the transforms between them: geographic lng/lat,
+// Web Mercator meters (EPSG:3857) and mesh units, in which a bbox covers a square terrain
+// of `terrain_size` units centered at the origin, linear in longitude and latitude. The
+// terrain size is 200 units unless set for a process with `set_terrain_size` or
+// `set_model_scale`; generation entry points make the scale of their process the active
+// one on the current thread.
+use std::cell::Cell;
+use std::f64::consts::PI;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::ModuleState;
+
+/// Mesh units across the terrain unless configured otherwise
+pub(crate) const DEFAULT_TERRAIN_SIZE: f64 = 200.0;
+/// Sphere radius of Web Mercator, in meters
+const EARTH_RADIUS: f64 = 6_378_137.0;
+/// Latitude limit of Web Mercator, where the map becomes square
+const MAX_MERCATOR_LAT: f64 = 85.051_128_78;
+
+/// Scale of the terrain of a process
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TerrainScale {
+    /// Mesh units across the terrain
+    pub size: f64,
+    /// Denominator N of the real-world model scale 1:N, at which mesh units are
+    /// millimeters; None for the abstract terrain with its fixed relief height
+    pub model_scale: Option<f64>,
+}
+
+impl TerrainScale {
+    pub(crate) const DEFAULT: TerrainScale = TerrainScale {
+        size: DEFAULT_TERRAIN_SIZE,
+        model_scale: None,
+    };
+}
+
+thread_local! {
+    static ACTIVE_SCALE: Cell<TerrainScale> = const { Cell::new(TerrainScale::DEFAULT) };
+}
+
+/// Mesh units across the terrain of the process being generated on this thread
+pub(crate) fn terrain_size() -> f64 {
+    ACTIVE_SCALE.with(Cell::get).size
+}
+
+/// Real-world model scale of the process being generated on this thread, if any
+pub(crate) fn model_scale() -> Option<f64> {
+    ACTIVE_SCALE.with(Cell::get).model_scale
+}
+
+/// Install a terrain scale on this thread, e.g. on pool threads before they map a batch
+pub(crate) fn set_active_scale(scale: TerrainScale) {
+    ACTIVE_SCALE.with(|active| active.set(scale));
+}
+
+/// Terrain scale configured for `process_id`, the default when none is
+pub(crate) fn process_scale(process_id: Option<&str>) -> TerrainScale {
+    process_id
+        .and_then(|id| ModuleState::with(|state| state.terrain_scales.get(id).copied()))
+        .unwrap_or(TerrainScale::DEFAULT)
+}
+
+pub(crate) fn process_terrain_size(process_id: Option<&str>) -> f64 {
+    process_scale(process_id).size
+}
+
+/// Make the terrain scale of `process_id` the active one on this thread and return it
+pub(crate) fn activate_process(process_id: &str) -> TerrainScale {
+    let scale = process_scale(Some(process_id));
+    set_active_scale(scale);
+    scale
+}
+
+/// Web Mercator meters of a geographic point; latitudes are clamped to the Mercator limit
+pub(crate) fn lng_lat_to_mercator(lng: f64, lat: f64) -> [f64; 2] {
+    let lat = lat.clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT).to_radians();
+    [
+        EARTH_RADIUS * lng.to_radians(),
+        EARTH_RADIUS * (PI / 4.0 + lat / 2.0).tan().ln(),
+    ]
+}
+
+pub(crate) fn mercator_to_lng_lat(x: f64, y: f64) -> [f64; 2] {
+    [
+        (x / EARTH_RADIUS).to_degrees(),
+        (2.0 * (y / EARTH_RADIUS).exp().atan() - PI / 2.0).to_degrees(),
+    ]
+}
+
+/// Fractional Web Mercator tile coordinates of a geographic point
+pub(crate) fn tile_coords(lng: f64, lat: f64, zoom: u32) -> [f64; 2] {
+    let n = 2.0_f64.powi(zoom as i32);
+    let [x, y] = lng_lat_to_mercator(lng, lat);
+    let world = 2.0 * PI * EARTH_RADIUS;
+    [(x / world + 0.5) * n, (0.5 - y / world) * n]
+}
+
+/// Mesh coordinates of a geographic point on a terrain of `size` units over `bbox`
+/// ([minLng, minLat, maxLng, maxLat])
+pub(crate) fn lng_lat_to_mesh(lng: f64, lat: f64, bbox: &[f64], size: f64) -> [f64; 2] {
+    [
+        ((lng - bbox[0]) / (bbox[2] - bbox[0]) - 0.5) * size,
+        ((lat - bbox[1]) / (bbox[3] - bbox[1]) - 0.5) * size,
+    ]
+}
+
+pub(crate) fn mesh_to_lng_lat(x: f64, y: f64, bbox: &[f64], size: f64) -> [f64; 2] {
+    let [nx, ny] = mesh_to_normalized(x, y, size);
+    [
+        bbox[0] + (bbox[2] - bbox[0]) * nx,
+        bbox[1] + (bbox[3] - bbox[1]) * ny,
+    ]
+}
+
+/// Position of a mesh point across a terrain of `size` units, 0 at the south-west and 1
+/// at the north-east edge
+pub(crate) fn mesh_to_normalized(x: f64, y: f64, size: f64) -> [f64; 2] {
+    [x / size + 0.5, y / size + 0.5]
+}
+
+/// Set the mesh units across the terrain of `process_id` (200 by default). Terrain and
+/// layers generated for the process afterwards use it; mesh heights given in meters
+/// scale along, while lengths given in mesh units (base height, extrusion heights) do not.
+/// Replaces a model scale set with `set_model_scale`.
+#[wasm_bindgen]
+pub fn set_terrain_size(process_id: &str, size: f64) -> Result<(), JsValue> {
+    configure_terrain_size(process_id, size).map_err(|e| JsValue::from_str(&e))
+}
+
+fn configure_terrain_size(process_id: &str, size: f64) -> Result<(), String> {
+    if !size.is_finite() || size <= 0.0 {
+        return Err(format!("Terrain size must be positive, got {}", size));
+    }
+    store_terrain_scale(
+        process_id,
+        TerrainScale {
+            size,
+            model_scale: None,
+        },
+    );
+    Ok(())
+}
+
+/// Store the scale of a process. A different scale drops the cached layer geometry of
+/// the process, which was generated at the old one.
+pub(crate) fn store_terrain_scale(process_id: &str, scale: TerrainScale) {
+    ModuleState::with_mut(|state| {
+        let previous = state.terrain_scales.insert(process_id.to_string(), scale);
+        if previous.unwrap_or(TerrainScale::DEFAULT) != scale {
+            state.layer_geometries.remove(process_id);
+        }
+    });
+}
+
+/// Mesh units across the terrain of `process_id`, 200 when not configured
+#[wasm_bindgen]
+pub fn get_terrain_size(process_id: Option<String>) -> f64 {
+    process_terrain_size(process_id.as_deref())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Space {
+    LngLat,
+    Mercator,
+    Mesh,
+}
+
+impl Space {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "lnglat" => Ok(Space::LngLat),
+            "mercator" => Ok(Space::Mercator),
+            "mesh" => Ok(Space::Mesh),
+            other => Err(format!(
+                "Unknown coordinate space '{}', expected 'lnglat', 'mercator' or 'mesh'",
+                other
+            )),
+        }
+    }
+}
+
+/// Transform flat [x0, y0, x1, y1, ...] `coordinates` between the spaces `from` and `to`:
+/// "lnglat" (degrees), "mercator" (EPSG:3857 meters) or "mesh" (units of the terrain of
+/// `process_id` over `bbox`, [minLng, minLat, maxLng, maxLat]; the bbox is required for
+/// mesh coordinates).
+#[wasm_bindgen]
+pub fn transform_coordinates(
+    coordinates: &[f64],
+    from: &str,
+    to: &str,
+    bbox: Option<Vec<f64>>,
+    process_id: Option<String>,
+) -> Result<Vec<f64>, JsValue> {
+    transform(
+        coordinates,
+        from,
+        to,
+        bbox.as_deref(),
+        process_id.as_deref(),
+    )
+    .map_err(|e| JsValue::from_str(&e))
+}
+
+fn transform(
+    coordinates: &[f64],
+    from: &str,
+    to: &str,
+    bbox: Option<&[f64]>,
+    process_id: Option<&str>,
+) -> Result<Vec<f64>, String> {
+    let (from, to) = (Space::parse(from)?, Space::parse(to)?);
+    if !coordinates.len().is_multiple_of(2) {
+        return Err(format!(
+            "Expected x, y pairs, got {} values",
+            coordinates.len()
+        ));
+    }
+    let bbox = match bbox {
+        Some(bbox) if bbox.len() == 4 && bbox[0] < bbox[2] && bbox[1] < bbox[3] => Some(bbox),
+        Some(_) => {
+            return Err("Invalid bbox: must contain [minLng, minLat, maxLng, maxLat]".to_string())
+        }
+        None if from == Space::Mesh || to == Space::Mesh => {
+            return Err("Mesh coordinates need the bbox of the terrain".to_string())
+        }
+        None => None,
+    };
+    let size = process_terrain_size(process_id);
+
+    Ok(coordinates
+        .chunks_exact(2)
+        .flat_map(|point| {
+            let [lng, lat] = match from {
+                Space::LngLat => [point[0], point[1]],
+                Space::Mercator => mercator_to_lng_lat(point[0], point[1]),
+                Space::Mesh => mesh_to_lng_lat(point[0], point[1], bbox.unwrap_or_default(), size),
+            };
+            match to {
+                Space::LngLat => [lng, lat],
+                Space::Mercator => lng_lat_to_mercator(lng, lat),
+                Space::Mesh => lng_lat_to_mesh(lng, lat, bbox.unwrap_or_default(), size),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips_between_spaces() {
+        let bbox = [8.0, 47.0, 9.0, 48.0];
+        let points = [8.5, 47.5, 8.0, 48.0];
+        let mesh = transform(&points, "lnglat", "mesh", Some(&bbox), None).unwrap();
+        assert_eq!(mesh, vec![0.0, 0.0, -100.0, 100.0]);
+
+        let mercator = transform(&mesh, "mesh", "mercator", Some(&bbox), None).unwrap();
+        let back = transform(&mercator, "mercator", "lnglat", None, None).unwrap();
+        assert!(back.iter().zip(points).all(|(a, b)| (a - b).abs() < 1e-9));
+
+        // Tile 0/0/0 spans the whole Mercator square
+        let [x, y] = tile_coords(-180.0, MAX_MERCATOR_LAT, 0);
+        assert!(x.abs() < 1e-9 && y.abs() < 1e-6);
+        assert!(transform(&points, "lnglat", "mesh", None, None).is_err());
+    }
+
+    #[test]
+    fn test_terrain_size_per_process() {
+        let bbox = [8.0, 47.0, 9.0, 48.0];
+        configure_terrain_size("projection-test", 50.0).unwrap();
+        assert!(configure_terrain_size("projection-test", 0.0).is_err());
+        let mesh = transform(
+            &[9.0, 48.0],
+            "lnglat",
+            "mesh",
+            Some(&bbox),
+            Some("projection-test"),
+        )
+        .unwrap();
+        assert_eq!(mesh, vec![25.0, 25.0]);
+
+        assert_eq!(activate_process("projection-test").size, 50.0);
+        assert_eq!(terrain_size(), 50.0);
+        set_active_scale(TerrainScale::DEFAULT);
+        ModuleState::with_mut(|state| state.clear_process_data("projection-test"));
+        assert_eq!(
+            get_terrain_size(Some("projection-test".to_string())),
+            DEFAULT_TERRAIN_SIZE
+        );
+    }
+
+    #[test]
+    fn test_new_terrain_size_drops_cached_layers() {
+        let cache = |size: f64| {
+            crate::layer_cache::store_layer_geometry(
+                "projection-cache-test",
+                "water",
+                String::new(),
+                true,
+                Vec::new(),
+            );
+            configure_terrain_size("projection-cache-test", size).unwrap();
+            crate::layer_cache::get_cached_layers("projection-cache-test")
+        };
+        assert_eq!(cache(DEFAULT_TERRAIN_SIZE).len(), 1);
+        assert!(cache(120.0).is_empty());
+        assert_eq!(cache(120.0).len(), 1);
+        ModuleState::with_mut(|state| state.clear_process_data("projection-cache-test"));
+    }
+}
@@ -0,0 +1,126 @@
+// Attribution and data provenance of generated models. Tile licenses usually require
+// crediting the data source, so every process records which tiles (URL, zoom, retrieval
+// time) it was built from, and exports embed the attribution of the sources used.
+use js_sys::Date;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::elevation::raster_tile_url;
+use crate::module_state::ModuleState;
+use crate::prefetch::TileSource;
+use crate::tilejson::source_metadata;
+use crate::vectortile::{vector_tile_url, TileRequest};
+
+// Attribution of the built-in vector tiles (OpenStreetMap data, ODbL)
+const DEFAULT_VECTOR_ATTRIBUTION: &str = "© OpenStreetMap contributors";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TileProvenance {
+    /// "raster" or "vector"
+    pub source: String,
+    pub url: String,
+    pub zoom: u32,
+    /// Milliseconds since the epoch when the tile was downloaded; None for injected tiles
+    #[serde(rename = "retrievedAt")]
+    pub retrieved_at: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Provenance {
+    /// Attribution strings of the sources the model was built from
+    pub attribution: Vec<String>,
+    pub tiles: Vec<TileProvenance>,
+}
+
+/// Attribution of a source: from its TileJSON, else the built-in default
+fn source_attribution(source: TileSource) -> Option<String> {
+    match source_metadata(source) {
+        Some(tilejson) => tilejson.attribution,
+        None => match source {
+            TileSource::Vector => Some(DEFAULT_VECTOR_ATTRIBUTION.to_string()),
+            TileSource::Raster => None,
+        },
+    }
+}
+
+/// Remember when a tile URL was downloaded
+pub(crate) fn record_retrieval(url: &str) {
+    ModuleState::with_mut(|state| state.tile_retrievals.insert(url.to_string(), Date::now()));
+}
+
+/// Record the tiles of `source` a process uses, replacing earlier ones of that source
+pub(crate) fn record_process_tiles(process_id: &str, source: TileSource, tiles: &[TileRequest]) {
+    let attribution = source_attribution(source);
+    // Tile URLs read the source configuration, so resolve them outside the mutable borrow
+    let urls: Vec<(String, u32)> = tiles
+        .iter()
+        .map(|tile| {
+            let url = match source {
+                TileSource::Raster => raster_tile_url(tile.x, tile.y, tile.z),
+                TileSource::Vector => vector_tile_url(tile),
+            };
+            (url, tile.z)
+        })
+        .collect();
+
+    ModuleState::with_mut(|state| {
+        let entries: Vec<TileProvenance> = urls
+            .into_iter()
+            .map(|(url, zoom)| TileProvenance {
+                source: source.name().to_string(),
+                retrieved_at: state.tile_retrievals.get(&url).copied(),
+                url,
+                zoom,
+            })
+            .collect();
+
+        let provenance = state
+            .process_provenance
+            .entry(process_id.to_string())
+            .or_default();
+        provenance.tiles.retain(|t| t.source != source.name());
+        provenance.tiles.extend(entries);
+        if let Some(attribution) = attribution {
+            if !provenance.attribution.contains(&attribution) {
+                provenance.attribution.push(attribution);
+            }
+        }
+    });
+}
+
+/// Attribution and tile provenance of a process as JSON
+#[wasm_bindgen]
+pub fn get_provenance(process_id: &str) -> Result<String, JsValue> {
+    let provenance = ModuleState::with(|state| {
+        state
+            .process_provenance
+            .get(process_id)
+            .cloned()
+            .unwrap_or_default()
+    });
+    serde_json::to_string(&provenance)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize provenance: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_provenance_per_source() {
+        let tiles = [TileRequest { x: 1, y: 2, z: 3 }];
+        record_process_tiles("provenance-test", TileSource::Vector, &tiles);
+        record_process_tiles("provenance-test", TileSource::Raster, &tiles);
+        // Re-recording a source replaces its tiles instead of appending
+        record_process_tiles("provenance-test", TileSource::Vector, &tiles);
+
+        let provenance =
+            ModuleState::with(|state| state.process_provenance["provenance-test"].clone());
+        assert_eq!(provenance.tiles.len(), 2);
+        assert_eq!(provenance.attribution, vec![DEFAULT_VECTOR_ATTRIBUTION]);
+        assert!(provenance
+            .tiles
+            .iter()
+            .all(|t| t.zoom == 3 && t.retrieved_at.is_none()));
+    }
+}
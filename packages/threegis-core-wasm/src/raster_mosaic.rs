@@ -0,0 +1,265 @@
+// Raster mosaics: cached Web Mercator tiles cropped to a bbox and resampled into one RGBA
+// image on a geographic grid (linear in longitude and latitude, like the elevation grid
+// and the terrain `uvs`). Bilinear resampling suits imagery; nearest keeps encoded values
+// such as terrain-RGB elevations intact for reprocessing.
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::{create_tile_key, ModuleState, TileData};
+use crate::projection;
+use crate::terrain_uv::{self, TileRange};
+
+/// Largest mosaic side in pixels
+const MAX_MOSAIC_SIZE: u32 = 8192;
+
+/// How mosaic pixels are sampled from the tiles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Resampling {
+    Bilinear,
+    Nearest,
+}
+
+impl Resampling {
+    fn parse(name: Option<&str>) -> Result<Self, String> {
+        match name {
+            None | Some("bilinear") => Ok(Resampling::Bilinear),
+            Some("nearest") => Ok(Resampling::Nearest),
+            Some(other) => Err(format!(
+                "Unknown resampling '{}', expected 'bilinear' or 'nearest'",
+                other
+            )),
+        }
+    }
+}
+
+/// RGBA image covering a bbox, top row at the northern edge
+pub(crate) struct Mosaic {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+    /// Tiles of the range that were not cached; pixels covered by none stay transparent
+    pub missing: usize,
+}
+
+/// Resample the tiles of `range` (`tile_size` pixels each) onto a `width` x `height`
+/// geographic grid over `bbox`
+pub(crate) fn mosaic_tiles<'a>(
+    bbox: [f64; 4],
+    range: &TileRange,
+    tile_size: usize,
+    width: usize,
+    height: usize,
+    resampling: Resampling,
+    tile: impl Fn(u32, u32) -> Option<&'a TileData>,
+) -> Mosaic {
+    let tiles: Vec<Option<&TileData>> = (range.min_y..=range.max_y)
+        .flat_map(|y| (range.min_x..=range.max_x).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            tile(x, y).filter(|t| {
+                t.width > 0 && t.height > 0 && t.data.len() >= (t.width * t.height * 4) as usize
+            })
+        })
+        .collect();
+    let (columns, rows) = (range.columns() as usize, range.rows() as usize);
+    let (range_width, range_height) = (columns * tile_size, rows * tile_size);
+
+    // Texel at pixel coordinates within the range, clamped to its edge; tiles of another
+    // size are read at the nearest pixel
+    let texel = |px: isize, py: isize| -> Option<&'a [u8]> {
+        let px = px.clamp(0, range_width as isize - 1) as usize;
+        let py = py.clamp(0, range_height as isize - 1) as usize;
+        let tile = tiles[(py / tile_size) * columns + px / tile_size]?;
+        let tx = (px % tile_size) * tile.width as usize / tile_size;
+        let ty = (py % tile_size) * tile.height as usize / tile_size;
+        let offset = (ty * tile.width as usize + tx) * 4;
+        Some(&tile.data[offset..offset + 4])
+    };
+
+    let [min_lng, min_lat, max_lng, max_lat] = bbox;
+    let mut data = vec![0u8; width * height * 4];
+    for row in 0..height {
+        let lat = max_lat - (max_lat - min_lat) * (row as f64 + 0.5) / height as f64;
+        for column in 0..width {
+            let lng = min_lng + (max_lng - min_lng) * (column as f64 + 0.5) / width as f64;
+            let [x, y] = projection::tile_coords(lng, lat, range.zoom);
+            let px = (x - range.min_x as f64) * tile_size as f64;
+            let py = (y - range.min_y as f64) * tile_size as f64;
+
+            let pixel = &mut data[(row * width + column) * 4..][..4];
+            match resampling {
+                Resampling::Nearest => {
+                    if let Some(value) = texel(px.floor() as isize, py.floor() as isize) {
+                        pixel.copy_from_slice(value);
+                    }
+                }
+                Resampling::Bilinear => {
+                    // Texel centers sit at half pixels; missing texels are left out of the
+                    // weighting so tile gaps do not darken their neighbours
+                    let (sx, sy) = (px - 0.5, py - 0.5);
+                    let (x0, y0) = (sx.floor(), sy.floor());
+                    let (fx, fy) = (sx - x0, sy - y0);
+                    let mut sum = [0.0f64; 4];
+                    let mut weight = 0.0;
+                    for (dx, dy, w) in [
+                        (0, 0, (1.0 - fx) * (1.0 - fy)),
+                        (1, 0, fx * (1.0 - fy)),
+                        (0, 1, (1.0 - fx) * fy),
+                        (1, 1, fx * fy),
+                    ] {
+                        if let Some(value) = texel(x0 as isize + dx, y0 as isize + dy) {
+                            for (s, v) in sum.iter_mut().zip(value) {
+                                *s += *v as f64 * w;
+                            }
+                            weight += w;
+                        }
+                    }
+                    if weight > 0.0 {
+                        for (p, s) in pixel.iter_mut().zip(sum) {
+                            *p = (s / weight).round().clamp(0.0, 255.0) as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Mosaic {
+        width,
+        height,
+        data,
+        missing: tiles.iter().filter(|t| t.is_none()).count(),
+    }
+}
+
+/// Crop, reproject and resample the cached raster tiles (`store_raster_tile`) of `zoom`
+/// into one `target_width` x `target_height` RGBA image covering `bbox`
+/// ([minLng, minLat, maxLng, maxLat]), linear in longitude and latitude with the top row
+/// at the northern edge, so it lines up with the terrain `uvs`. `resampling` is
+/// "bilinear" (default) or "nearest", which keeps terrain-RGB values decodable.
+/// Returns `{ width, height, data, missing }`; `missing` counts tiles not in the cache.
+#[wasm_bindgen]
+pub fn mosaic_raster_tiles(
+    bbox: Vec<f64>,
+    zoom: u32,
+    target_width: u32,
+    target_height: u32,
+    resampling: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let bbox: [f64; 4] = bbox.try_into().map_err(|_| {
+        JsValue::from_str("Invalid bbox: must contain [minLng, minLat, maxLng, maxLat]")
+    })?;
+    if !(1..=MAX_MOSAIC_SIZE).contains(&target_width)
+        || !(1..=MAX_MOSAIC_SIZE).contains(&target_height)
+    {
+        return Err(JsValue::from_str(&format!(
+            "Mosaic size must be 1..{} pixels per side, got {}x{}",
+            MAX_MOSAIC_SIZE, target_width, target_height
+        )));
+    }
+    let resampling = Resampling::parse(resampling.as_deref()).map_err(|e| JsValue::from_str(&e))?;
+    let range = TileRange::for_bbox(bbox, zoom).map_err(|e| JsValue::from_str(&e))?;
+    if range.columns() as u64 * range.rows() as u64 > MAX_MOSAIC_SIZE as u64 {
+        return Err(JsValue::from_str(
+            "Too many tiles for one mosaic; use a lower zoom",
+        ));
+    }
+
+    let mosaic = ModuleState::with_mut(|state| {
+        let tile_size = terrain_uv::cached_tile_size(state, &range)
+            .ok_or_else(|| format!("No raster tiles cached at zoom {} for this bbox", zoom))?;
+        let tiles = &state.raster_tiles;
+        Ok::<_, String>(mosaic_tiles(
+            bbox,
+            &range,
+            tile_size,
+            target_width as usize,
+            target_height as usize,
+            resampling,
+            |x, y| tiles.get(&create_tile_key(x, y, zoom)),
+        ))
+    })
+    .map_err(|e| JsValue::from_str(&e))?;
+
+    let result = js_sys::Object::new();
+    for (name, value) in [
+        ("width", mosaic.width as u32),
+        ("height", mosaic.height as u32),
+        ("missing", mosaic.missing as u32),
+    ] {
+        js_sys::Reflect::set(&result, &JsValue::from_str(name), &JsValue::from(value))?;
+    }
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("data"),
+        &js_sys::Uint8Array::from(mosaic.data.as_slice()),
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevation::{tile_x_to_lng, tile_y_to_lat};
+
+    // 4x4 tile whose pixels hold their column (red) and row (green)
+    fn tile() -> TileData {
+        TileData {
+            width: 4,
+            height: 4,
+            x: 0,
+            y: 0,
+            z: 1,
+            data: (0..16u8).flat_map(|i| [i % 4, i / 4, 0, 255]).collect(),
+            timestamp: 0.0,
+            key: String::new(),
+            buffer: Vec::new(),
+            parsed_layers: None,
+            rust_parsed_mvt: None,
+        }
+    }
+
+    #[test]
+    fn test_nearest_reprojects_rows_to_latitude() {
+        // Tile 0/0 at zoom 1: the north-west quarter of the world
+        let bbox = [
+            tile_x_to_lng(0, 1),
+            tile_y_to_lat(1, 1),
+            tile_x_to_lng(1, 1),
+            tile_y_to_lat(0, 1),
+        ];
+        let range = TileRange::for_bbox(bbox, 1).unwrap();
+        let source = tile();
+        let mosaic = mosaic_tiles(bbox, &range, 4, 4, 8, Resampling::Nearest, |_, _| {
+            Some(&source)
+        });
+
+        assert_eq!((mosaic.width, mosaic.height, mosaic.missing), (4, 8, 0));
+        let pixel = |x: usize, y: usize| &mosaic.data[(y * 4 + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(3, 7), [3, 3, 0, 255]);
+        // Halfway in latitude is far south of halfway in Mercator rows
+        assert_eq!(pixel(0, 4)[1], 3);
+    }
+
+    #[test]
+    fn test_bilinear_skips_missing_tiles() {
+        let bbox = [
+            tile_x_to_lng(0, 1),
+            tile_y_to_lat(1, 1),
+            tile_x_to_lng(2, 1),
+            tile_y_to_lat(0, 1),
+        ];
+        let range = TileRange::for_bbox(bbox, 1).unwrap();
+        assert_eq!((range.columns(), range.rows()), (2, 1));
+        let source = tile();
+        let mosaic = mosaic_tiles(bbox, &range, 4, 8, 1, Resampling::Bilinear, |x, _| {
+            (x == 0).then_some(&source)
+        });
+
+        assert_eq!(mosaic.missing, 1);
+        let pixel = |x: usize| &mosaic.data[x * 4..][..4];
+        // The last column of the western tile is not blended with the gap next to it
+        assert_eq!(pixel(3)[0], 3);
+        assert_eq!(pixel(3)[3], 255);
+        assert_eq!(pixel(4), [0, 0, 0, 0]);
+    }
+}
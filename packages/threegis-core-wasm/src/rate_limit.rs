@@ -0,0 +1,133 @@
+// Per-host request rate limits for tile downloads. Each configured host gets a token
+// bucket; requests beyond the burst are queued (delayed) in arrival order rather than
+// dropped, keeping bulk fetches within public tile servers' usage policies.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    static HOST_LIMITS: RefCell<HashMap<String, TokenBucket>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_ms: f64,
+    last_refill_ms: f64,
+}
+
+impl TokenBucket {
+    pub fn new(requests_per_second: f64, burst: u32, now_ms: f64) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_ms: requests_per_second / 1000.0,
+            last_refill_ms: now_ms,
+        }
+    }
+
+    /// Take one token and return how long the request has to wait, in ms. Tokens may go
+    /// negative: every queued request has already reserved its slot, so waits grow in
+    /// arrival order.
+    pub fn reserve(&mut self, now_ms: f64) -> f64 {
+        let elapsed = (now_ms - self.last_refill_ms).max(0.0);
+        self.tokens = (self.tokens + elapsed * self.refill_per_ms).min(self.capacity);
+        self.last_refill_ms = now_ms;
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            0.0
+        } else {
+            -self.tokens / self.refill_per_ms
+        }
+    }
+}
+
+// Host part of an http(s) URL, without credentials or path
+fn host_of(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    authority.rsplit('@').next().unwrap_or(authority)
+}
+
+/// Limit requests to `host` (e.g. "wms.wheregroup.com") to `requests_per_second`,
+/// allowing bursts of up to `burst` requests
+#[wasm_bindgen]
+pub fn set_host_rate_limit(
+    host: &str,
+    requests_per_second: f64,
+    burst: u32,
+) -> Result<(), JsValue> {
+    if !requests_per_second.is_finite() || requests_per_second <= 0.0 {
+        return Err(JsValue::from_str(
+            "requests_per_second must be a positive number",
+        ));
+    }
+    let bucket = TokenBucket::new(requests_per_second, burst, js_sys::Date::now());
+    HOST_LIMITS.with(|limits| limits.borrow_mut().insert(host.to_string(), bucket));
+    Ok(())
+}
+
+/// Remove the rate limit of a host
+#[wasm_bindgen]
+pub fn clear_host_rate_limit(host: &str) {
+    HOST_LIMITS.with(|limits| limits.borrow_mut().remove(host));
+}
+
+/// Remove the rate limits of all hosts
+pub(crate) fn clear_all_rate_limits() {
+    HOST_LIMITS.with(|limits| limits.borrow_mut().clear());
+}
+
+/// Wait until the host of `url` allows another request
+pub(crate) async fn acquire(url: &str) {
+    let host = host_of(url);
+    let wait_ms = HOST_LIMITS.with(|limits| {
+        limits
+            .borrow_mut()
+            .get_mut(host)
+            .map_or(0.0, |bucket| bucket.reserve(js_sys::Date::now()))
+    });
+    if wait_ms > 0.0 {
+        sleep_ms(wait_ms).await;
+    }
+}
+
+/// Resolve after `ms` via setTimeout. Works in both Window and Worker contexts.
+pub(crate) async fn sleep_ms(ms: f64) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let global = js_sys::global();
+        if let Ok(set_timeout) = js_sys::Reflect::get(&global, &JsValue::from_str("setTimeout")) {
+            let set_timeout_fn: js_sys::Function = set_timeout.into();
+            let _ = set_timeout_fn.call2(&global, &resolve, &JsValue::from_f64(ms));
+        } else {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_queues_beyond_burst() {
+        // 2 requests per second with a burst of 2
+        let mut bucket = TokenBucket::new(2.0, 2, 0.0);
+        assert_eq!(bucket.reserve(0.0), 0.0);
+        assert_eq!(bucket.reserve(0.0), 0.0);
+        // Queued in arrival order, one slot every 500ms
+        assert_eq!(bucket.reserve(0.0), 500.0);
+        assert_eq!(bucket.reserve(0.0), 1000.0);
+        // After the queue drained and a second passed, a token is available again
+        assert_eq!(bucket.reserve(2500.0), 0.0);
+
+        assert_eq!(
+            host_of("https://user@tiles.example.org:8080/1/2/3.pbf"),
+            "tiles.example.org:8080"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+// Reproducible output mode for regression testing: features are built in a fixed order
+// and results are stamped with a content hash, so identical inputs give byte-identical
+// layers and exports. The hash is FNV-1a rather than std's hasher, whose output may change
+// between Rust releases.
+use crate::polygon_geometry::BufferGeometry;
+use crate::polygon_geometry::GeometryData;
+use crate::provenance::Provenance;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Stable 64-bit hash of mesh data
+pub(crate) struct ContentHasher(u64);
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        ContentHasher(FNV_OFFSET)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn write_f32s(&mut self, values: &[f32]) {
+        self.write(&(values.len() as u64).to_le_bytes());
+        for value in values {
+            self.write(&value.to_le_bytes());
+        }
+    }
+
+    pub fn write_u32s(&mut self, values: &[u32]) {
+        self.write(&(values.len() as u64).to_le_bytes());
+        for value in values {
+            self.write(&value.to_le_bytes());
+        }
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write(&(value.len() as u64).to_le_bytes());
+        self.write(value.as_bytes());
+    }
+
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// Content hash of a layer's geometries: positions, indices and colors in output order
+pub(crate) fn geometries_hash(geometries: &[BufferGeometry]) -> String {
+    let mut hasher = ContentHasher::new();
+    for geometry in geometries {
+        hasher.write_f32s(&geometry.vertices);
+        hasher.write_u32s(geometry.indices.as_deref().unwrap_or_default());
+        hasher.write_f32s(geometry.colors.as_deref().unwrap_or_default());
+    }
+    hasher.finish()
+}
+
+/// Stamp every geometry with the content hash of the whole layer as `contentHash`
+pub(crate) fn stamp_geometries(geometries: &mut [BufferGeometry]) {
+    let hash = geometries_hash(geometries);
+    for geometry in geometries.iter_mut() {
+        geometry
+            .properties
+            .get_or_insert_with(Default::default)
+            .insert("contentHash".to_string(), hash.clone().into());
+    }
+}
+
+/// Order features by their content, independent of tile fetch and decode order
+pub(crate) fn sort_features(features: &mut [GeometryData]) {
+    // Property maps serialize with sorted keys, so equal features give equal keys
+    features.sort_by_cached_key(|feature| serde_json::to_string(feature).unwrap_or_default());
+}
+
+/// Provenance without download times, which differ between otherwise identical runs
+pub(crate) fn without_retrieval_times(provenance: &Provenance) -> Provenance {
+    let mut provenance = provenance.clone();
+    for tile in &mut provenance.tiles {
+        tile.retrieved_at = None;
+    }
+    provenance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(x: f64, id: u32) -> GeometryData {
+        GeometryData {
+            geometry: vec![vec![x, 0.0], vec![x + 1.0, 0.0], vec![x, 1.0]],
+            holes: None,
+            r#type: Some("Polygon".to_string()),
+            height: None,
+            layer: Some("building".to_string()),
+            label: None,
+            tags: None,
+            properties: Some(serde_json::json!({ "id": id, "class": "house" })),
+        }
+    }
+
+    #[test]
+    fn test_sorted_features_ignore_input_order() {
+        let mut a = vec![feature(2.0, 2), feature(0.0, 1), feature(5.0, 3)];
+        let mut b = vec![feature(5.0, 3), feature(2.0, 2), feature(0.0, 1)];
+        sort_features(&mut a);
+        sort_features(&mut b);
+        let ids = |features: &[GeometryData]| -> Vec<String> {
+            features
+                .iter()
+                .map(|f| serde_json::to_string(f).unwrap())
+                .collect()
+        };
+        assert_eq!(ids(&a), ids(&b));
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        let mut hasher = ContentHasher::new();
+        hasher.write(b"a");
+        // FNV-1a reference value of "a"
+        assert_eq!(hasher.finish(), "af63dc4c8601ec8c");
+
+        let geometry = BufferGeometry {
+            vertices: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            normals: None,
+            colors: None,
+            indices: Some(vec![0, 1, 2]),
+            uvs: None,
+            has_data: true,
+            properties: None,
+        };
+        let mut flipped = geometry.clone();
+        flipped.indices = Some(vec![0, 2, 1]);
+        assert_ne!(
+            geometries_hash(std::slice::from_ref(&geometry)),
+            geometries_hash(&[flipped])
+        );
+
+        let mut stamped = vec![geometry.clone(), geometry];
+        let hash = geometries_hash(&stamped);
+        stamp_geometries(&mut stamped);
+        assert!(stamped
+            .iter()
+            .all(|g| g.properties.as_ref().unwrap()["contentHash"] == hash.as_str()));
+    }
+}
@@ -0,0 +1,71 @@
+// Full teardown for long-lived pages: caches, parsed tiles, process data, GPU devices and
+// pipelines, registered handlers and settings go back to their state after loading, so
+// memory is reclaimed between sessions without reloading the page.
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::ModuleState;
+use crate::{
+    cache_keys, cache_manager, cancellation, console, fetch_hook, gpu_elevation, gpu_hillshade,
+    gpu_polygon, gpu_profiler, gpu_terrain, jobs, polygon_geometry, rate_limit,
+};
+
+/// What `reset_module` released
+#[derive(Serialize, Debug, Default)]
+pub struct ResetSummary {
+    pub processes: usize,
+    #[serde(rename = "rasterTiles")]
+    pub raster_tiles: usize,
+    #[serde(rename = "vectorTiles")]
+    pub vector_tiles: usize,
+    #[serde(rename = "elevationGrids")]
+    pub elevation_grids: usize,
+    #[serde(rename = "cancelledOperations")]
+    pub cancelled_operations: usize,
+    #[serde(rename = "gpuProcessorsReleased")]
+    pub gpu_processors_released: usize,
+    #[serde(rename = "jobsDropped")]
+    pub jobs_dropped: usize,
+}
+
+/// Tear down all module state and return a `ResetSummary` as JSON. Running operations
+/// are cancelled first; call it once they have settled, as GPU devices are destroyed.
+/// GPU processors, fetch handlers, TileJSON sources, rate limits and offline mode have
+/// to be set up again afterwards.
+#[wasm_bindgen]
+pub fn reset_module() -> Result<String, JsValue> {
+    let mut summary = ResetSummary {
+        cancelled_operations: cancellation::cancel_all_operations(),
+        ..Default::default()
+    };
+
+    ModuleState::with_mut(|state| {
+        summary.processes = state.get_cached_process_ids().len();
+        summary.raster_tiles = state.raster_tiles.len();
+        summary.vector_tiles = state.vector_tiles.len() + state.mvt_parsed_tiles.len();
+        summary.elevation_grids = state.elevation_grids.len();
+        *state = ModuleState::new();
+    });
+
+    summary.gpu_processors_released = [
+        gpu_elevation::release_gpu_elevation_processor(),
+        gpu_polygon::release_gpu_polygon_processor(),
+        gpu_terrain::release_gpu_terrain_processor(),
+        gpu_hillshade::release_gpu_hillshade_processor(),
+    ]
+    .into_iter()
+    .filter(|released| *released)
+    .count();
+
+    summary.jobs_dropped = jobs::clear_jobs();
+    polygon_geometry::clear_terrain_mesh();
+    cache_manager::free_all_groups();
+    fetch_hook::reset_fetch_state();
+    rate_limit::clear_all_rate_limits();
+    cache_keys::reset_cache_key_precision();
+    console::clear_log_records();
+    gpu_profiler::clear_gpu_profile();
+
+    serde_json::to_string(&summary)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize reset summary: {}", e)))
+}
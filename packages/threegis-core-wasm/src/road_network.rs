@@ -0,0 +1,257 @@
+// Road network preprocessing for transportation layers. Vector tiles cut roads at tile
+// boundaries and their endpoints rarely meet exactly, so buffering every piece on its own
+// leaves seams and gaps. Endpoints closer than a tolerance are snapped together, pieces
+// that simply continue each other are joined into one line, and the vertices left in the
+// middle of straight runs are dropped.
+use crate::polygon_geometry::GeometryData;
+use std::collections::HashMap;
+
+/// Sine of the largest turn still treated as straight when dropping joint vertices
+const COLLINEAR_SINE: f64 = 1e-3;
+
+/// Snap line ends within `tolerance` (coordinate units) of each other to a shared point
+/// and join lines meeting end to end where no third line does, when `merge_key` agrees.
+/// Features other than LineStrings are kept as they are; joined lines keep the properties
+/// of their first piece.
+pub(crate) fn merge_road_network<K, F>(
+    features: Vec<GeometryData>,
+    tolerance: f64,
+    merge_key: F,
+) -> Vec<GeometryData>
+where
+    K: PartialEq,
+    F: Fn(&GeometryData) -> K,
+{
+    let (mut lines, mut result): (Vec<GeometryData>, Vec<GeometryData>) =
+        features.into_iter().partition(|feature| {
+            feature.r#type.as_deref() == Some("LineString")
+                && feature.geometry.len() >= 2
+                && feature.geometry.iter().all(|p| p.len() >= 2)
+        });
+    if lines.is_empty() {
+        return result;
+    }
+
+    // Endpoint 2i is the start of line i, 2i + 1 its end
+    let endpoints: Vec<[f64; 2]> = lines
+        .iter()
+        .flat_map(|line| {
+            let (first, last) = (&line.geometry[0], &line.geometry[line.geometry.len() - 1]);
+            [[first[0], first[1]], [last[0], last[1]]]
+        })
+        .collect();
+    let (nodes, positions) = snap_endpoints(&endpoints, tolerance);
+    for (i, line) in lines.iter_mut().enumerate() {
+        let last = line.geometry.len() - 1;
+        line.geometry[0] = positions[nodes[2 * i]].to_vec();
+        line.geometry[last] = positions[nodes[2 * i + 1]].to_vec();
+    }
+
+    let mut ends_at_node: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (endpoint, node) in nodes.iter().enumerate() {
+        ends_at_node.entry(*node).or_default().push(endpoint);
+    }
+    let keys: Vec<K> = lines.iter().map(&merge_key).collect();
+    // The endpoint continuing `endpoint` through its node, if exactly two ends meet there
+    let continuation = |endpoint: usize| -> Option<usize> {
+        match ends_at_node[&nodes[endpoint]].as_slice() {
+            [a, b] => Some(if *a == endpoint { *b } else { *a }),
+            _ => None,
+        }
+    };
+
+    let mut used = vec![false; lines.len()];
+    for start in 0..lines.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        // Chain of (line, reversed) from the first piece to the last
+        let mut chain = vec![(start, false)];
+        // Extend forward from the end of the chain, then backward from its start
+        for forward in [true, false] {
+            let mut endpoint = if forward { 2 * start + 1 } else { 2 * start };
+            while let Some(next) = continuation(endpoint) {
+                let line = next / 2;
+                if used[line] || keys[line] != keys[start] {
+                    break;
+                }
+                used[line] = true;
+                // Entered at its start the piece runs on in its own direction
+                let entered_at_start = next % 2 == 0;
+                if forward {
+                    chain.push((line, !entered_at_start));
+                } else {
+                    chain.insert(0, (line, entered_at_start));
+                }
+                endpoint = next ^ 1;
+            }
+        }
+
+        let mut geometry: Vec<Vec<f64>> = Vec::new();
+        for &(line, reversed) in &chain {
+            let mut points = lines[line].geometry.clone();
+            if reversed {
+                points.reverse();
+            }
+            let skip = usize::from(!geometry.is_empty());
+            geometry.extend(points.into_iter().skip(skip));
+        }
+        let template = &lines[chain[0].0];
+        result.push(GeometryData {
+            geometry: drop_straight_vertices(geometry),
+            ..template.clone()
+        });
+    }
+    result
+}
+
+// Cluster endpoints closer than `tolerance`; returns the node of every endpoint and the
+// mean position of every node
+fn snap_endpoints(endpoints: &[[f64; 2]], tolerance: f64) -> (Vec<usize>, Vec<[f64; 2]>) {
+    let mut parent: Vec<usize> = (0..endpoints.len()).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    if tolerance > 0.0 && tolerance.is_finite() {
+        let cell_of = |p: &[f64; 2]| {
+            (
+                (p[0] / tolerance).floor() as i64,
+                (p[1] / tolerance).floor() as i64,
+            )
+        };
+        let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, p) in endpoints.iter().enumerate() {
+            let (cx, cy) = cell_of(p);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let Some(cell) = grid.get(&(cx + dx, cy + dy)) else {
+                        continue;
+                    };
+                    for &j in cell {
+                        let q = &endpoints[j];
+                        if (p[0] - q[0]).hypot(p[1] - q[1]) <= tolerance {
+                            let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                            parent[a.max(b)] = a.min(b);
+                        }
+                    }
+                }
+            }
+            grid.entry((cx, cy)).or_default().push(i);
+        }
+    } else {
+        // Only exactly coincident endpoints share a node
+        let mut seen: HashMap<(u64, u64), usize> = HashMap::new();
+        for (i, p) in endpoints.iter().enumerate() {
+            let first = *seen.entry((p[0].to_bits(), p[1].to_bits())).or_insert(i);
+            parent[i] = first;
+        }
+    }
+
+    let mut node_of_root: HashMap<usize, usize> = HashMap::new();
+    let mut sums: Vec<([f64; 2], usize)> = Vec::new();
+    let nodes: Vec<usize> = (0..endpoints.len())
+        .map(|i| {
+            let root = find(&mut parent, i);
+            let node = *node_of_root.entry(root).or_insert_with(|| {
+                sums.push(([0.0, 0.0], 0));
+                sums.len() - 1
+            });
+            sums[node].0[0] += endpoints[i][0];
+            sums[node].0[1] += endpoints[i][1];
+            sums[node].1 += 1;
+            node
+        })
+        .collect();
+    let positions = sums
+        .into_iter()
+        .map(|(sum, count)| [sum[0] / count as f64, sum[1] / count as f64])
+        .collect();
+    (nodes, positions)
+}
+
+// Remove repeated points and interior vertices where the line runs straight on
+fn drop_straight_vertices(points: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    let mut kept: Vec<Vec<f64>> = Vec::with_capacity(points.len());
+    for point in points {
+        if kept
+            .last()
+            .is_some_and(|last| last[0] == point[0] && last[1] == point[1])
+        {
+            continue;
+        }
+        if kept.len() >= 2 {
+            let (a, b) = (&kept[kept.len() - 2], &kept[kept.len() - 1]);
+            let (ux, uy) = (b[0] - a[0], b[1] - a[1]);
+            let (vx, vy) = (point[0] - b[0], point[1] - b[1]);
+            let lengths = ux.hypot(uy) * vx.hypot(vy);
+            let straight = lengths > 0.0
+                && (ux * vy - uy * vx).abs() <= COLLINEAR_SINE * lengths
+                && ux * vx + uy * vy > 0.0;
+            if straight {
+                kept.pop();
+            }
+        }
+        kept.push(point);
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn road(points: &[[f64; 2]], class: &str) -> GeometryData {
+        GeometryData {
+            geometry: points.iter().map(|p| p.to_vec()).collect(),
+            holes: None,
+            r#type: Some("LineString".to_string()),
+            height: None,
+            layer: Some("transportation".to_string()),
+            label: None,
+            tags: None,
+            properties: Some(serde_json::json!({ "class": class })),
+        }
+    }
+
+    fn class(feature: &GeometryData) -> String {
+        feature.properties.as_ref().unwrap()["class"].to_string()
+    }
+
+    #[test]
+    fn test_pieces_cut_at_tile_boundary_are_joined() {
+        // Cut at x = 1 with a small gap, the second piece stored backwards
+        let features = vec![
+            road(&[[0.0, 0.0], [1.0, 0.0]], "primary"),
+            road(&[[2.0, 1.0], [2.0, 0.0], [1.0005, 0.0]], "primary"),
+        ];
+        let merged = merge_road_network(features, 0.001, class);
+        assert_eq!(merged.len(), 1);
+        // The joint at the tile boundary runs straight on and is dropped
+        assert_eq!(
+            merged[0].geometry,
+            vec![vec![0.0, 0.0], vec![2.0, 0.0], vec![2.0, 1.0]]
+        );
+    }
+
+    #[test]
+    fn test_junctions_and_other_classes_stay_apart() {
+        let junction = vec![
+            road(&[[0.0, 0.0], [1.0, 0.0]], "primary"),
+            road(&[[1.0, 0.0], [2.0, 0.0]], "primary"),
+            road(&[[1.0, 0.0], [1.0, 1.0]], "primary"),
+        ];
+        assert_eq!(merge_road_network(junction, 0.001, class).len(), 3);
+
+        let classes = vec![
+            road(&[[0.0, 0.0], [1.0, 0.0]], "primary"),
+            road(&[[1.0, 0.0], [2.0, 0.0]], "service"),
+        ];
+        assert_eq!(merge_road_network(classes, 0.001, class).len(), 2);
+    }
+}
@@ -0,0 +1,445 @@
+// Pitched roofs for buildings tagged with roof:shape. Every supported shape is the lowest
+// of a few planes over the footprint, laid out in the footprint's minimum-area rectangle:
+// gabled roofs have two planes meeting at a ridge along the long side, hipped roofs add
+// the two end planes, pyramidal roofs rise from all four sides and skillion roofs are a
+// single plane. The roof is built as a closed solid standing on the eaves, so it prints
+// as a block on top of the walls.
+use serde_json::Value;
+
+use crate::overpass::{parse_length, LEVEL_HEIGHT};
+use crate::polygon_geometry::GeometryData;
+use crate::solid_mesh::SolidMesh;
+
+/// Pitch of gabled, hipped and pyramidal roofs without roof:height, in degrees
+const DEFAULT_ROOF_PITCH: f64 = 30.0;
+/// Pitch of skillion roofs without roof:height, in degrees
+const SKILLION_PITCH: f64 = 15.0;
+/// Largest share of the building height taken by its roof
+pub(crate) const MAX_ROOF_SHARE: f64 = 0.8;
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RoofShape {
+    Gabled,
+    Hipped,
+    Pyramidal,
+    Skillion,
+}
+
+impl RoofShape {
+    fn from_tag(tag: &str) -> Option<Self> {
+        match tag.trim() {
+            "gabled" | "saltbox" => Some(RoofShape::Gabled),
+            "hipped" | "half-hipped" | "side_hipped" => Some(RoofShape::Hipped),
+            "pyramidal" => Some(RoofShape::Pyramidal),
+            "skillion" | "lean_to" => Some(RoofShape::Skillion),
+            _ => None,
+        }
+    }
+}
+
+/// Roof of a building as tagged on its feature
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RoofSpec {
+    pub shape: RoofShape,
+    /// roof:height, else roof:levels times LEVEL_HEIGHT, in meters
+    pub height: Option<f64>,
+    /// roof:direction: compass bearing the roof slopes down towards, in degrees
+    pub direction: Option<f64>,
+    /// roof:orientation=across: the ridge runs along the short side
+    pub across: bool,
+}
+
+/// Tag value from the feature properties, else its tags; `roof:shape` is also looked
+/// up as `roof_shape` for tile schemas without colons in keys
+fn roof_tag<'a>(feature: &'a GeometryData, key: &str) -> Option<&'a Value> {
+    let underscored = key.replace(':', "_");
+    [feature.properties.as_ref(), feature.tags.as_ref()]
+        .into_iter()
+        .flatten()
+        .find_map(|values| values.get(key).or_else(|| values.get(&underscored)))
+}
+
+fn tag_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => parse_length(s),
+        _ => None,
+    }
+}
+
+/// Compass bearing of a roof:direction such as "135" or "SE"
+fn parse_direction(value: &Value) -> Option<f64> {
+    if let Some(degrees) = value.as_f64() {
+        return Some(degrees);
+    }
+    let text = value.as_str()?.trim();
+    const CARDINALS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    match CARDINALS.iter().position(|c| c.eq_ignore_ascii_case(text)) {
+        Some(i) => Some(i as f64 * 45.0),
+        None => text.parse().ok(),
+    }
+}
+
+/// The pitched roof tagged on a feature; None for flat and unsupported roof shapes
+pub(crate) fn roof_spec(feature: &GeometryData) -> Option<RoofSpec> {
+    let shape = RoofShape::from_tag(roof_tag(feature, "roof:shape")?.as_str()?)?;
+    let height = roof_tag(feature, "roof:height")
+        .and_then(tag_number)
+        .or_else(|| {
+            let levels = roof_tag(feature, "roof:levels").and_then(tag_number)?;
+            Some(levels * LEVEL_HEIGHT)
+        })
+        .filter(|h| h.is_finite() && *h > 0.0);
+    Some(RoofSpec {
+        shape,
+        height,
+        direction: roof_tag(feature, "roof:direction")
+            .and_then(parse_direction)
+            .filter(|d| d.is_finite()),
+        across: roof_tag(feature, "roof:orientation").and_then(Value::as_str) == Some("across"),
+    })
+}
+
+/// Roof layout over a footprint: the ridge axis `u`, the axis `v` across it, the center
+/// and half extents of the footprint along both axes
+struct RoofFrame {
+    center: [f64; 2],
+    u: [f64; 2],
+    v: [f64; 2],
+    half_u: f64,
+    half_v: f64,
+}
+
+impl RoofFrame {
+    fn new(footprint: &[[f64; 2]], spec: &RoofSpec) -> Option<Self> {
+        let hull = convex_hull(footprint);
+        if hull.len() < 3 {
+            return None;
+        }
+        // Skillion roofs slope down along u, towards roof:direction (0 = north = +y)
+        if let (RoofShape::Skillion, Some(bearing)) = (spec.shape, spec.direction) {
+            let (sin, cos) = bearing.to_radians().sin_cos();
+            return Some(RoofFrame::with_axis(&hull, [sin, cos]));
+        }
+        let frame = RoofFrame::with_axis(&hull, min_area_axis(&hull));
+        // Ridges run along the long side and skillion roofs slope across the short side,
+        // unless tagged across
+        let u_is_long = frame.half_u >= frame.half_v;
+        let u_should_be_long = (spec.shape != RoofShape::Skillion) != spec.across;
+        if u_is_long == u_should_be_long {
+            Some(frame)
+        } else {
+            Some(RoofFrame::with_axis(&hull, frame.v))
+        }
+    }
+
+    fn with_axis(hull: &[[f64; 2]], u: [f64; 2]) -> Self {
+        let v = [-u[1], u[0]];
+        let project = |axis: [f64; 2]| {
+            hull.iter()
+                .map(|p| p[0] * axis[0] + p[1] * axis[1])
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), d| {
+                    (lo.min(d), hi.max(d))
+                })
+        };
+        let (u_min, u_max) = project(u);
+        let (v_min, v_max) = project(v);
+        let (cu, cv) = ((u_min + u_max) / 2.0, (v_min + v_max) / 2.0);
+        RoofFrame {
+            center: [cu * u[0] + cv * v[0], cu * u[1] + cv * v[1]],
+            u,
+            v,
+            half_u: (u_max - u_min) / 2.0,
+            half_v: (v_max - v_min) / 2.0,
+        }
+    }
+
+    fn local(&self, p: [f64; 2]) -> [f64; 2] {
+        let (dx, dy) = (p[0] - self.center[0], p[1] - self.center[1]);
+        [
+            dx * self.u[0] + dy * self.u[1],
+            dx * self.v[0] + dy * self.v[1],
+        ]
+    }
+
+    /// Planes whose minimum is the roof, as (du, dv, constant) over local coordinates and
+    /// in units of the roof height
+    fn planes(&self, shape: RoofShape) -> Vec<[f64; 3]> {
+        let (a, b) = (self.half_u.max(EPSILON), self.half_v.max(EPSILON));
+        match shape {
+            RoofShape::Gabled => vec![[0.0, -1.0 / b, 1.0], [0.0, 1.0 / b, 1.0]],
+            RoofShape::Hipped => {
+                // End planes as steep as the sides, so the hips meet the ridge
+                let run = b.min(a);
+                vec![
+                    [0.0, -1.0 / b, 1.0],
+                    [0.0, 1.0 / b, 1.0],
+                    [-1.0 / run, 0.0, a / run],
+                    [1.0 / run, 0.0, a / run],
+                ]
+            }
+            RoofShape::Pyramidal => vec![
+                [0.0, -1.0 / b, 1.0],
+                [0.0, 1.0 / b, 1.0],
+                [-1.0 / a, 0.0, 1.0],
+                [1.0 / a, 0.0, 1.0],
+            ],
+            // Highest on the side facing away from the slope direction
+            RoofShape::Skillion => vec![[-1.0 / (2.0 * a), 0.0, 0.5]],
+        }
+    }
+
+    /// Roof height from the pitch when no height is tagged, in footprint units
+    fn pitched_height(&self, shape: RoofShape) -> f64 {
+        match shape {
+            RoofShape::Skillion => 2.0 * self.half_u * SKILLION_PITCH.to_radians().tan(),
+            _ => self.half_u.min(self.half_v) * DEFAULT_ROOF_PITCH.to_radians().tan(),
+        }
+    }
+}
+
+// Convex hull, counter-clockwise (monotone chain)
+fn convex_hull(points: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    let mut sorted: Vec<[f64; 2]> = points.to_vec();
+    sorted.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return sorted;
+    }
+    let cross = |o: [f64; 2], a: [f64; 2], b: [f64; 2]| {
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+    };
+    let half_hull = |points: &mut dyn Iterator<Item = &[f64; 2]>| {
+        let mut half: Vec<[f64; 2]> = Vec::new();
+        for &p in points {
+            while half.len() >= 2 && cross(half[half.len() - 2], half[half.len() - 1], p) <= 0.0 {
+                half.pop();
+            }
+            half.push(p);
+        }
+        half.pop();
+        half
+    };
+    let mut hull = half_hull(&mut sorted.iter());
+    hull.extend(half_hull(&mut sorted.iter().rev()));
+    hull
+}
+
+// Direction of the hull edge giving the smallest enclosing rectangle
+fn min_area_axis(hull: &[[f64; 2]]) -> [f64; 2] {
+    let mut best = ([1.0, 0.0], f64::INFINITY);
+    for i in 0..hull.len() {
+        let (a, b) = (hull[i], hull[(i + 1) % hull.len()]);
+        let length = (b[0] - a[0]).hypot(b[1] - a[1]);
+        if length < EPSILON {
+            continue;
+        }
+        let axis = [(b[0] - a[0]) / length, (b[1] - a[1]) / length];
+        let frame = RoofFrame::with_axis(hull, axis);
+        let area = frame.half_u * frame.half_v;
+        if area < best.1 {
+            best = (axis, area);
+        }
+    }
+    best.0
+}
+
+/// Roof height over `footprint` (mesh coordinates) in mesh units: the tagged height
+/// scaled by `meters_to_units`, else the height of the default pitch
+pub(crate) fn roof_height(footprint: &[[f64; 2]], spec: &RoofSpec, meters_to_units: f64) -> f64 {
+    match spec.height {
+        Some(meters) => meters * meters_to_units,
+        None => {
+            RoofFrame::new(footprint, spec).map_or(0.0, |frame| frame.pitched_height(spec.shape))
+        }
+    }
+}
+
+// Part of `ring` where `keep(p) >= 0`, for a `keep` linear in p (Sutherland–Hodgman)
+fn clip_half_plane(ring: &[[f64; 2]], keep: impl Fn([f64; 2]) -> f64) -> Vec<[f64; 2]> {
+    let mut clipped = Vec::with_capacity(ring.len() + 2);
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        let (da, db) = (keep(a), keep(b));
+        if da >= 0.0 {
+            clipped.push(a);
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            let t = da / (da - db);
+            clipped.push([a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]);
+        }
+    }
+    clipped
+}
+
+/// Closed roof solid over `footprint` (mesh coordinates, without holes) with its eaves at
+/// `eave_z`, rising `height`: a bottom face at the eaves, a wall up to the roof along
+/// every footprint edge (the gable ends) and one sloped face per roof plane
+pub(crate) fn roof_mesh(
+    footprint: &[[f64; 2]],
+    spec: &RoofSpec,
+    eave_z: f64,
+    height: f64,
+) -> Option<SolidMesh> {
+    let mut ring: Vec<[f64; 2]> = footprint.to_vec();
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    let area: f64 = (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum();
+    if ring.len() < 3 || area.abs() < EPSILON || height <= 0.0 {
+        return None;
+    }
+    if area < 0.0 {
+        ring.reverse();
+    }
+    let frame = RoofFrame::new(&ring, spec)?;
+    let planes = frame.planes(spec.shape);
+    let plane_at = |plane: &[f64; 3], p: [f64; 2]| {
+        let [s, t] = frame.local(p);
+        plane[0] * s + plane[1] * t + plane[2]
+    };
+    let roof_z = |p: [f64; 2]| {
+        let lowest = planes
+            .iter()
+            .map(|plane| plane_at(plane, p))
+            .fold(f64::INFINITY, f64::min);
+        eave_z + height * lowest.clamp(0.0, 1.0)
+    };
+
+    let mut mesh = SolidMesh::default();
+    mesh.push_surface(&ring, false, |_| eave_z);
+
+    // One face per plane over the part of the footprint where that plane is lowest
+    for (i, plane) in planes.iter().enumerate() {
+        let mut part = ring.clone();
+        for (j, other) in planes.iter().enumerate() {
+            if i != j && part.len() >= 3 {
+                part = clip_half_plane(&part, |p| plane_at(other, p) - plane_at(plane, p));
+            }
+        }
+        if part.len() >= 3 {
+            mesh.push_surface(&part, true, |p| {
+                eave_z + height * plane_at(plane, p).clamp(0.0, 1.0)
+            });
+        }
+    }
+
+    // Walls from the eaves up to the roof, split where the roof plane changes along the edge
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        let mut cuts = vec![0.0, 1.0];
+        for (j, p) in planes.iter().enumerate() {
+            for q in &planes[j + 1..] {
+                let (pa, pb) = (
+                    plane_at(p, a) - plane_at(q, a),
+                    plane_at(p, b) - plane_at(q, b),
+                );
+                if (pa - pb).abs() > EPSILON {
+                    let t = pa / (pa - pb);
+                    if t > EPSILON && t < 1.0 - EPSILON {
+                        cuts.push(t);
+                    }
+                }
+            }
+        }
+        cuts.sort_by(f64::total_cmp);
+        let at = |t: f64| [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+        for w in cuts.windows(2) {
+            let (p, q) = (at(w[0]), at(w[1]));
+            let (zp, zq) = (roof_z(p), roof_z(q));
+            // Seen from outside the footprint, p is on the left
+            mesh.push_triangle([[p[0], p[1], eave_z], [q[0], q[1], eave_z], [q[0], q[1], zq]]);
+            mesh.push_triangle([[p[0], p[1], eave_z], [q[0], q[1], zq], [p[0], p[1], zp]]);
+        }
+    }
+    (!mesh.is_empty()).then_some(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn building(properties: Value) -> GeometryData {
+        GeometryData {
+            geometry: Vec::new(),
+            holes: None,
+            r#type: Some("Polygon".to_string()),
+            height: Some(10.0),
+            layer: Some("building".to_string()),
+            label: None,
+            tags: None,
+            properties: Some(properties),
+        }
+    }
+
+    #[test]
+    fn test_roof_tags_are_read() {
+        let spec = roof_spec(&building(json!({
+            "roof:shape": "gabled",
+            "roof:height": "3 m",
+            "roof:orientation": "across"
+        })))
+        .unwrap();
+        assert_eq!(spec.shape, RoofShape::Gabled);
+        assert_eq!(spec.height, Some(3.0));
+        assert!(spec.across);
+
+        let spec = roof_spec(&building(
+            json!({ "roof_shape": "skillion", "roof:levels": 1, "roof:direction": "SE" }),
+        ))
+        .unwrap();
+        assert_eq!(spec.height, Some(LEVEL_HEIGHT));
+        assert_eq!(spec.direction, Some(135.0));
+        assert!(roof_spec(&building(json!({ "roof:shape": "flat" }))).is_none());
+    }
+
+    #[test]
+    fn test_roof_solids_have_the_volume_of_their_shape() {
+        // 10 x 4 footprint, roof 2 high
+        let footprint = [[0.0, 0.0], [10.0, 0.0], [10.0, 4.0], [0.0, 4.0]];
+        let spec = |shape| RoofSpec {
+            shape,
+            height: None,
+            direction: None,
+            across: false,
+        };
+        let solid = |shape| {
+            roof_mesh(&footprint, &spec(shape), 5.0, 2.0)
+                .unwrap()
+                .volume()
+        };
+
+        // Prism with the ridge along the long side: half the box
+        assert!((solid(RoofShape::Gabled) - 40.0).abs() < 1e-3);
+        // Wedge of a skillion roof: also half the box
+        assert!((solid(RoofShape::Skillion) - 40.0).abs() < 1e-3);
+        // Pyramid: a third of the box
+        assert!((solid(RoofShape::Pyramidal) - 80.0 / 3.0).abs() < 1e-3);
+        // Hipped: the gabled prism less two corner pyramids at the ends
+        let ridge_drop = 2.0 * 2.0 * 4.0 / 3.0;
+        assert!((solid(RoofShape::Hipped) - (40.0 - ridge_drop)).abs() < 1e-3);
+
+        // The gable ridge runs along x at y = 2, 2 above the eaves
+        let gabled = roof_mesh(&footprint, &spec(RoofShape::Gabled), 5.0, 2.0)
+            .unwrap()
+            .into_geometry(None);
+        let top = gabled
+            .vertices
+            .chunks(3)
+            .map(|v| v[2])
+            .fold(f32::MIN, f32::max);
+        assert!((top - 7.0).abs() < 1e-5);
+        assert!(gabled
+            .vertices
+            .chunks(3)
+            .filter(|v| (v[2] - 7.0).abs() < 1e-5)
+            .all(|v| (v[1] - 2.0).abs() < 1e-5));
+    }
+}
@@ -0,0 +1,415 @@
+// Scene generation: the whole pipeline (elevation, vector tiles, feature extraction,
+// terrain, layer extrusion, merging) in one call, for hosts that only want the finished
+// meshes. The steps run in the order the app runs them, on the same caches, so a scene
+// can be refined afterwards with the per-step exports using the same processId.
+use serde::Deserialize;
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+use crate::cancellation::{job_error, ProcessCancellation};
+use crate::elevation::{self, ElevationEncoding, ElevationProcessingInput};
+use crate::module_state::ModuleState;
+use crate::polygon_geometry::{PolygonGeometryInput, VtDataSet};
+use crate::terrain::{self, TerrainGeometryParams};
+use crate::terrain_only::elevation_tile_requests;
+use crate::tilejson::DEFAULT_VECTOR_SOURCE;
+use crate::vectortile::{self, ExtractMultiLayerInput, VectortileProcessingInput};
+
+fn default_vector_zoom() -> u32 {
+    14
+}
+
+fn default_grid_size() -> u32 {
+    256
+}
+
+fn default_exaggeration() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+pub struct SceneInput {
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    /// [minLng, minLat, maxLng, maxLat]
+    pub bbox: [f64; 4],
+    /// Elevation tile zoom; chosen like `generate_terrain_model` when omitted
+    #[serde(default, rename = "elevationZoom")]
+    pub elevation_zoom: Option<u32>,
+    #[serde(default = "default_vector_zoom", rename = "vectorZoom")]
+    pub vector_zoom: u32,
+    #[serde(default = "default_grid_size", rename = "gridWidth")]
+    pub grid_width: u32,
+    #[serde(default = "default_grid_size", rename = "gridHeight")]
+    pub grid_height: u32,
+    #[serde(default = "default_exaggeration", rename = "verticalExaggeration")]
+    pub vertical_exaggeration: f64,
+    #[serde(rename = "terrainBaseHeight")]
+    pub terrain_base_height: f64,
+    #[serde(default, rename = "elevationEncoding")]
+    pub elevation_encoding: Option<ElevationEncoding>,
+    /// Layers to extrude, in the form `process_polygon_geometry` takes as `vtDataSet`
+    #[serde(default, rename = "vtDataSets")]
+    pub vt_data_sets: Vec<VtDataSet>,
+    #[serde(default, rename = "minClearance")]
+    pub min_clearance: Option<f64>,
+    #[serde(default, rename = "submergeOffset")]
+    pub submerge_offset: Option<f64>,
+    #[serde(default, rename = "csgClipping")]
+    pub csg_clipping: Option<bool>,
+    /// Build every layer in reproducible mode, see `PolygonGeometryInput::reproducible`
+    #[serde(default)]
+    pub reproducible: bool,
+}
+
+impl SceneInput {
+    fn validate(&self) -> Result<(), String> {
+        let [min_lng, min_lat, max_lng, max_lat] = self.bbox;
+        if !self.bbox.iter().all(|v| v.is_finite()) || min_lng >= max_lng || min_lat >= max_lat {
+            return Err("Invalid bbox: must be [minLng, minLat, maxLng, maxLat]".to_string());
+        }
+        if !self.vertical_exaggeration.is_finite() || !self.terrain_base_height.is_finite() {
+            return Err("verticalExaggeration and terrainBaseHeight must be finite".to_string());
+        }
+        if let Some(layer) = self.vt_data_sets.iter().find(|l| l.source_layer.is_empty()) {
+            return Err(format!(
+                "Layer '{}' is missing 'sourceLayer'",
+                layer.get_label()
+            ));
+        }
+        Ok(())
+    }
+
+    // Vector tile sources the layers read from; None fetches the default source only
+    fn vector_sources(&self) -> Option<Vec<String>> {
+        let mut sources: Vec<String> = Vec::new();
+        for layer in &self.vt_data_sets {
+            let name = vectortile::dataset_source(layer).unwrap_or(DEFAULT_VECTOR_SOURCE);
+            if !sources.iter().any(|s| s == name) {
+                sources.push(name.to_string());
+            }
+        }
+        if sources.iter().all(|s| s == DEFAULT_VECTOR_SOURCE) {
+            None
+        } else {
+            Some(sources)
+        }
+    }
+
+    // `process_polygon_geometry` input of one layer on the scene's terrain, which was
+    // stored for the process with `store_terrain_mesh`
+    fn layer_input(
+        &self,
+        layer: &VtDataSet,
+        terrain: &terrain::TerrainGeometryResult,
+    ) -> Result<PolygonGeometryInput, String> {
+        let (terrain_grid_size, terrain_is_gpu_layout) = terrain_grid_layout(&terrain.positions);
+        let grid_height = terrain.processed_elevation_grid.len();
+        let grid_width = terrain
+            .processed_elevation_grid
+            .first()
+            .map_or(0, |row| row.len());
+        let mut input: PolygonGeometryInput = serde_json::from_value(json!({
+            "bbox": self.bbox,
+            "terrainBaseHeight": self.terrain_base_height,
+            "verticalExaggeration": self.vertical_exaggeration,
+            "gridSize": { "width": grid_width, "height": grid_height },
+            "minElevation": terrain.original_min_elevation,
+            "maxElevation": terrain.original_max_elevation,
+            "terrainGridWidth": terrain_grid_size,
+            "terrainGridHeight": terrain_grid_size,
+            "terrainIsGpuLayout": terrain_is_gpu_layout,
+            "vtDataSet": layer,
+            // Buildings each sit on their own terrain location
+            "useSameZOffset": layer.source_layer != "building",
+            "processId": self.process_id,
+            "csgClipping": self.csg_clipping,
+            "minClearance": self.min_clearance,
+            "submergeOffset": self.submerge_offset,
+            "reproducible": self.reproducible,
+        }))
+        .map_err(|e| format!("Invalid layer '{}': {}", layer.get_label(), e))?;
+        input.elevation_grid = terrain.processed_elevation_grid.clone();
+        Ok(input)
+    }
+}
+
+// Vertex-grid size of a terrain mesh and whether it uses the GPU interleaved layout. Both
+// terrain backends produce square grids with a top and a bottom vertex per grid point; in
+// the GPU layout the first two vertices share their x and y.
+fn terrain_grid_layout(positions: &[f32]) -> (u32, bool) {
+    let layer_size = positions.len() / 3 / 2;
+    let size = (layer_size as f64).sqrt().round() as u32;
+    let is_gpu_layout = positions.len() >= 6
+        && (positions[0] - positions[3]).abs() < 1e-4
+        && (positions[1] - positions[4]).abs() < 1e-4;
+    (size, is_gpu_layout)
+}
+
+fn check_cancelled(cancellation: &ProcessCancellation) -> Result<(), JsValue> {
+    cancellation.check().map_err(|e| job_error(&e))
+}
+
+/// Generate a complete scene: fetch elevation and vector tiles for `bbox`, extract the
+/// features of every layer in `vtDataSets`, build the terrain and extrude all layers onto
+/// it. Returns `{ terrain, layers: [{ label, featureCount, geometries }], provenance }`,
+/// where `terrain` has the shape `create_terrain_geometry` returns and `geometries` the
+/// shape `process_polygon_geometry` returns, merged per layer the same way. Cancel with
+/// `cancel_process(processId)`.
+#[wasm_bindgen]
+pub async fn generate_scene(config_json: &str) -> Result<JsValue, JsValue> {
+    let input: SceneInput = serde_json::from_str(config_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse scene config: {}", e)))?;
+    input.validate().map_err(|e| JsValue::from_str(&e))?;
+    let [min_lng, min_lat, max_lng, max_lat] = input.bbox;
+    let process_id = input.process_id.clone();
+    let cancellation = ProcessCancellation::for_process(&process_id);
+
+    // Elevation
+    let elevation_input = ElevationProcessingInput {
+        min_lng,
+        min_lat,
+        max_lng,
+        max_lat,
+        tiles: elevation_tile_requests(&input.bbox, input.elevation_zoom)?,
+        grid_width: input.grid_width,
+        grid_height: input.grid_height,
+        process_id: process_id.clone(),
+        encoding: input.elevation_encoding,
+    };
+    let elevation_json = serde_json::to_string(&elevation_input)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize elevation input: {}", e)))?;
+    elevation::process_elevation_data_async(&elevation_json).await?;
+    check_cancelled(&cancellation)?;
+
+    // Vector tiles and features of every layer
+    let feature_counts = if input.vt_data_sets.is_empty() {
+        Vec::new()
+    } else {
+        let vector_input = VectortileProcessingInput {
+            min_lng,
+            min_lat,
+            max_lng,
+            max_lat,
+            zoom: input.vector_zoom,
+            grid_width: input.grid_width,
+            grid_height: input.grid_height,
+            process_id: process_id.clone(),
+            sources: input.vector_sources(),
+        };
+        vectortile::fetch_vector_tiles(serde_wasm_bindgen::to_value(&vector_input)?).await?;
+        check_cancelled(&cancellation)?;
+        let extract_input = ExtractMultiLayerInput {
+            bbox: input.bbox.to_vec(),
+            vt_data_sets: input.vt_data_sets.clone(),
+            process_id: process_id.clone(),
+            elevation_process_id: Some(process_id.clone()),
+        };
+        vectortile::extract_layers(&extract_input)
+            .await?
+            .unwrap_or_else(|| vec![0; input.vt_data_sets.len()])
+    };
+    check_cancelled(&cancellation)?;
+
+    // Terrain, stored once for every layer to sample
+    let params = TerrainGeometryParams {
+        min_lng,
+        min_lat,
+        max_lng,
+        max_lat,
+        vertical_exaggeration: input.vertical_exaggeration,
+        terrain_base_height: input.terrain_base_height,
+        process_id: process_id.clone(),
+        use_simple_mesh: false,
+        flat_base_thickness: None,
+        elevation_encoding: input.elevation_encoding,
+    };
+    let geometry = terrain::generate_terrain(&params).await?;
+    crate::store_terrain_mesh(
+        &process_id,
+        geometry.positions.clone(),
+        geometry.indices.clone(),
+    );
+    check_cancelled(&cancellation)?;
+
+    // Layers
+    let layers = js_sys::Array::new();
+    for (layer, feature_count) in input.vt_data_sets.iter().zip(feature_counts) {
+        let layer_input = input
+            .layer_input(layer, &geometry)
+            .map_err(|e| JsValue::from_str(&e))?;
+        let geometries =
+            crate::build_layer_geometry(layer_input, None, crate::geometries_to_js).await?;
+        let entry = js_sys::Object::new();
+        js_sys::Reflect::set(&entry, &"label".into(), &layer.get_label().into())?;
+        js_sys::Reflect::set(
+            &entry,
+            &"featureCount".into(),
+            &JsValue::from_f64(feature_count as f64),
+        )?;
+        js_sys::Reflect::set(&entry, &"geometries".into(), &geometries)?;
+        layers.push(&entry);
+    }
+
+    let provenance = ModuleState::with(|state| {
+        state
+            .process_provenance
+            .get(&process_id)
+            .cloned()
+            .unwrap_or_default()
+    });
+    let result = js_sys::Object::new();
+    let terrain = terrain::convert_terrain_geometry_to_js(geometry, &process_id)?;
+    js_sys::Reflect::set(&result, &JsValue::from_str("terrain"), &terrain)?;
+    js_sys::Reflect::set(&result, &JsValue::from_str("layers"), &layers)?;
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("provenance"),
+        &serde_wasm_bindgen::to_value(&provenance)?,
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_input_defaults_and_sources() {
+        let input: SceneInput = serde_json::from_str(
+            r#"{"processId": "scene-test", "bbox": [7.0, 50.0, 7.1, 50.1], "terrainBaseHeight": 5,
+                "vtDataSets": [{"sourceLayer": "building"}, {"sourceLayer": "water", "source": "default"}]}"#,
+        )
+        .unwrap();
+        assert!(input.validate().is_ok());
+        assert_eq!(input.vector_zoom, 14);
+        assert_eq!(input.vector_sources(), None);
+
+        let named: SceneInput = serde_json::from_str(
+            r#"{"processId": "s", "bbox": [7.0, 50.0, 7.1, 50.1], "terrainBaseHeight": 5,
+                "vtDataSets": [{"sourceLayer": "building"}, {"sourceLayer": "trails", "source": "hiking"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            named.vector_sources(),
+            Some(vec!["default".to_string(), "hiking".to_string()])
+        );
+
+        let unnamed: SceneInput = serde_json::from_str(
+            r#"{"processId": "s", "bbox": [7.0, 50.0, 7.1, 50.1], "terrainBaseHeight": 5,
+                "vtDataSets": [{"sourceLayer": ""}]}"#,
+        )
+        .unwrap();
+        assert!(unnamed.validate().is_err());
+    }
+
+    #[test]
+    fn test_terrain_grid_layout() {
+        // 2x2 CPU layered grid: bottom layer then top layer
+        let cpu = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, //
+            0.0, 0.0, 5.0, 1.0, 0.0, 5.0, 0.0, 1.0, 5.0, 1.0, 1.0, 5.0,
+        ];
+        assert_eq!(terrain_grid_layout(&cpu), (2, false));
+
+        // GPU interleaved: top and bottom vertex of each grid point side by side
+        let gpu: Vec<f32> = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]
+            .iter()
+            .flat_map(|[x, y]| [*x, *y, 5.0, *x, *y, 0.0])
+            .collect();
+        assert_eq!(terrain_grid_layout(&gpu), (2, true));
+    }
+
+    #[test]
+    fn test_road_layer_on_scene_terrain() {
+        use crate::elevation::{ElevationProcessingResult, GridSize};
+        use crate::polygon_geometry::{generate_polygon_geometry, GeometryData};
+
+        let process_id = "scene-road-test";
+        let input: SceneInput = serde_json::from_value(json!({
+            "processId": process_id,
+            "bbox": [7.0, 50.0, 7.01, 50.01],
+            "terrainBaseHeight": 5,
+            "vtDataSets": [{"sourceLayer": "transportation", "bufferSize": 2}]
+        }))
+        .unwrap();
+
+        // A hillside rising to the east, built like the scene's CPU terrain fallback
+        let grid: Vec<Vec<f64>> = (0..17)
+            .map(|_| (0..17).map(|x| 100.0 + x as f64 * 5.0).collect())
+            .collect();
+        let elevation = ElevationProcessingResult {
+            elevation_grid: grid,
+            grid_size: GridSize {
+                width: 17,
+                height: 17,
+            },
+            min_elevation: 100.0,
+            max_elevation: 180.0,
+            processed_min_elevation: 100.0,
+            processed_max_elevation: 180.0,
+            cache_hit_rate: 1.0,
+        };
+        let params = TerrainGeometryParams {
+            min_lng: 7.0,
+            min_lat: 50.0,
+            max_lng: 7.01,
+            max_lat: 50.01,
+            vertical_exaggeration: input.vertical_exaggeration,
+            terrain_base_height: input.terrain_base_height,
+            process_id: process_id.to_string(),
+            use_simple_mesh: false,
+            flat_base_thickness: None,
+            elevation_encoding: None,
+        };
+        let terrain =
+            crate::terrain_mesh_gen::generate_terrain_with_mesh_cutting(&elevation, &params)
+                .unwrap();
+        crate::store_terrain_mesh(
+            process_id,
+            terrain.positions.clone(),
+            terrain.indices.clone(),
+        );
+
+        let mut layer = input.layer_input(&input.vt_data_sets[0], &terrain).unwrap();
+        assert_eq!(layer.grid_size.width, 17);
+        assert_eq!(layer.terrain_grid_width, 17);
+        assert!(!layer.terrain_is_gpu_layout);
+        // Roads follow the terrain as one network, unlike buildings
+        assert!(layer.use_same_z_offset);
+
+        let road = |points: &[[f64; 2]]| -> GeometryData {
+            serde_json::from_value(json!({
+                "geometry": points,
+                "type": "LineString",
+                "properties": {"class": "primary"}
+            }))
+            .unwrap()
+        };
+        layer.polygons = vec![
+            road(&[[7.001, 50.002], [7.009, 50.002]]),
+            road(&[[7.005, 50.001], [7.005, 50.009]]),
+        ];
+        let output = futures::executor::block_on(generate_polygon_geometry(layer)).unwrap();
+        ModuleState::with_mut(|state| state.clear_process_data(process_id));
+        assert!(output.skipped.is_empty());
+        assert!(!output.geometries.is_empty());
+
+        // The roads lie on the terrain: inside its footprint and above its base plate
+        let terrain_z = terrain.positions.chunks_exact(3).map(|p| p[2]);
+        let terrain_top = terrain_z.clone().fold(f32::MIN, f32::max);
+        let terrain_bottom = terrain_z.fold(f32::MAX, f32::min);
+        let half_size = crate::projection::terrain_size() as f32 / 2.0;
+        for geometry in &output.geometries {
+            assert!(geometry.has_data);
+            for p in geometry.vertices.chunks_exact(3) {
+                assert!(
+                    p[0].abs() <= half_size && p[1].abs() <= half_size,
+                    "{:?}",
+                    p
+                );
+                assert!(p[2] > terrain_bottom && p[2] < terrain_top + 5.0, "{:?}", p);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Small triangle mesh builder for solids generated directly in mesh coordinates (roofs,
+// bridge decks and piers, draped shells). Every face gets its own vertices so normals
+// stay flat.
+use earcutr::earcut;
+use std::collections::HashMap;
+
+use crate::polygon_geometry::BufferGeometry;
+
+const EPSILON: f64 = 1e-12;
+
+/// Triangle mesh with per-face normals
+#[derive(Default)]
+pub(crate) struct SolidMesh {
+    vertices: Vec<f32>,
+    normals: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl SolidMesh {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Triangle facing the side it is counter-clockwise from; degenerate triangles are
+    /// left out
+    pub(crate) fn push_triangle(&mut self, corners: [[f64; 3]; 3]) {
+        let [a, b, c] = corners;
+        let (u, v) = (
+            [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+            [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+        );
+        let n = [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ];
+        let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        if length < EPSILON {
+            return;
+        }
+        let first = (self.vertices.len() / 3) as u32;
+        for corner in corners {
+            self.vertices.extend(corner.map(|v| v as f32));
+            self.normals.extend(n.map(|v| (v / length) as f32));
+        }
+        self.indices.extend([first, first + 1, first + 2]);
+    }
+
+    /// Quad given counter-clockwise as seen from the side it faces
+    pub(crate) fn push_quad(&mut self, corners: [[f64; 3]; 4]) {
+        let [a, b, c, d] = corners;
+        self.push_triangle([a, b, c]);
+        self.push_triangle([a, c, d]);
+    }
+
+    /// Triangulate a ring at heights `z(x, y)`, counter-clockwise seen from above when
+    /// `up`, else from below
+    pub(crate) fn push_surface(
+        &mut self,
+        ring: &[[f64; 2]],
+        up: bool,
+        z: impl Fn([f64; 2]) -> f64,
+    ) {
+        self.push_surface_with_holes(&[ring.to_vec()], up, z);
+    }
+
+    /// Like `push_surface` for an exterior ring followed by its holes
+    pub(crate) fn push_surface_with_holes(
+        &mut self,
+        rings: &[Vec<[f64; 2]>],
+        up: bool,
+        z: impl Fn([f64; 2]) -> f64,
+    ) {
+        let points: Vec<[f64; 2]> = rings.iter().flatten().copied().collect();
+        let data: Vec<f64> = points.iter().flat_map(|p| [p[0], p[1]]).collect();
+        let hole_indices: Vec<usize> = rings
+            .iter()
+            .scan(0, |start, ring| {
+                *start += ring.len();
+                Some(*start)
+            })
+            .take(rings.len().saturating_sub(1))
+            .collect();
+        let Ok(triangles) = earcut(&data, &hole_indices, 2) else {
+            return;
+        };
+        for t in triangles.chunks(3) {
+            let [a, b, c] = [points[t[0]], points[t[1]], points[t[2]]];
+            let ccw = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]) > 0.0;
+            let (b, c) = if ccw == up { (b, c) } else { (c, b) };
+            self.push_triangle([a, b, c].map(|p| [p[0], p[1], z(p)]));
+        }
+    }
+
+    /// Merge into `geometry`, filling attributes this mesh does not have
+    pub(crate) fn append_to(self, geometry: &mut BufferGeometry) {
+        let offset = (geometry.vertices.len() / 3) as u32;
+        let vertex_count = self.vertices.len() / 3;
+        let indices = geometry
+            .indices
+            .get_or_insert_with(|| (0..offset).collect());
+        indices.extend(self.indices.iter().map(|i| i + offset));
+        if let Some(normals) = geometry.normals.as_mut() {
+            normals.extend(self.normals);
+        }
+        if let Some(uvs) = geometry.uvs.as_mut() {
+            uvs.resize(uvs.len() + vertex_count * 2, 0.0);
+        }
+        geometry.vertices.extend(self.vertices);
+        geometry.has_data = !geometry.vertices.is_empty();
+    }
+
+    pub(crate) fn into_geometry(
+        self,
+        properties: Option<HashMap<String, serde_json::Value>>,
+    ) -> BufferGeometry {
+        BufferGeometry {
+            has_data: !self.vertices.is_empty(),
+            vertices: self.vertices,
+            normals: Some(self.normals),
+            colors: None,
+            indices: Some(self.indices),
+            uvs: None,
+            properties,
+        }
+    }
+
+    /// Enclosed volume, positive when every face points out of the solid
+    #[cfg(test)]
+    pub(crate) fn volume(&self) -> f64 {
+        let v = |i: u32| {
+            let i = i as usize * 3;
+            [
+                self.vertices[i] as f64,
+                self.vertices[i + 1] as f64,
+                self.vertices[i + 2] as f64,
+            ]
+        };
+        self.indices
+            .chunks(3)
+            .map(|t| {
+                let (a, b, c) = (v(t[0]), v(t[1]), v(t[2]));
+                a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0])
+            })
+            .sum::<f64>()
+            / 6.0
+    }
+}
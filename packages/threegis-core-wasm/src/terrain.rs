@@ -3,9 +3,19 @@ use js_sys::{Float32Array, Object, Uint32Array};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
-use crate::elevation::ElevationProcessingResult;
+use crate::bounds::{self, BoundingVolume};
+use crate::clip_shape;
+use crate::console::{self, LogLevel, LogTimer};
+use crate::elevation::{ElevationEncoding, ElevationProcessingResult};
+use crate::gpu_dispatch::GpuCancellation;
 use crate::module_state::ModuleState;
+use crate::projection;
 use crate::terrain_mesh_gen;
+use crate::terrain_uv;
+use crate::vertical_datum::VerticalDatum;
+
+// Grid vertices per side of the levels `create_terrain_lods` returns by default
+const DEFAULT_LOD_RESOLUTIONS: [usize; 3] = [256, 128, 64];
 
 #[derive(Serialize, Deserialize)]
 pub struct TerrainGeometryParams {
@@ -17,6 +27,13 @@ pub struct TerrainGeometryParams {
     pub terrain_base_height: f64,
     pub process_id: String,
     pub use_simple_mesh: bool,
+    /// Layers-only mode: a flat base plate of this thickness replaces the terrain and no
+    /// elevation data is fetched
+    #[serde(default)]
+    pub flat_base_thickness: Option<f64>,
+    /// Pixel encoding of the elevation tiles; see `ElevationEncoding`
+    #[serde(default)]
+    pub elevation_encoding: Option<ElevationEncoding>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,6 +50,7 @@ pub struct TerrainGeometryResult {
 }
 
 // Check if GPU terrain acceleration is available
+#[allow(dead_code)]
 pub async fn check_gpu_terrain_support() -> bool {
     crate::gpu_terrain::init_gpu_terrain_processor().await.unwrap_or(false)
 }
@@ -44,10 +62,34 @@ pub async fn create_terrain_geometry(params_js: JsValue) -> Result<JsValue, JsVa
     let params: TerrainGeometryParams = serde_wasm_bindgen::from_value(params_js)?;
 
     // Check if simple mesh (flat terrain) is requested
-    if params.use_simple_mesh {
+    if let Some(thickness) = params.flat_base_thickness {
+        if !thickness.is_finite() || thickness <= 0.0 {
+            return Err(JsValue::from_str(&format!(
+                "flat_base_thickness must be positive, got {}",
+                thickness
+            )));
+        }
+    }
+    if params.use_simple_mesh || params.flat_base_thickness.is_some() {
         return create_simple_flat_terrain(&params).await;
     }
 
+    let timer = LogTimer::start("terrain", Some(&params.process_id));
+    let result = generate_terrain(&params).await?;
+    timer.finish(
+        LogLevel::Info,
+        format!(
+            "Generated terrain mesh with {} vertices",
+            result.positions.len() / 3
+        ),
+    );
+    convert_terrain_geometry_to_js(result, &params.process_id)
+}
+
+// Elevation grid of the process (processed on demand) with its elevation range
+async fn load_elevation_result(
+    params: &TerrainGeometryParams,
+) -> Result<ElevationProcessingResult, JsValue> {
     // Get elevation data
     let elevation_grid = {
         if let Some(grid) = ModuleState::with(|state| {
@@ -70,6 +112,7 @@ pub async fn create_terrain_geometry(params_js: JsValue) -> Result<JsValue, JsVa
                     grid_width: 256,   // Standard grid size
                     grid_height: 256,  // Standard grid size
                     process_id: params.process_id.clone(),
+                    encoding: params.elevation_encoding,
                 };
 
                 // Serialize input
@@ -119,10 +162,12 @@ pub async fn create_terrain_geometry(params_js: JsValue) -> Result<JsValue, JsVa
             match elevation_grid {
                 Some(grid) => grid,
                 None => {
-                    return Err(JsValue::from_str(&format!(
-                        "❌ Failed to retrieve elevation data for bbox [{}, {}, {}, {}] after {} attempts. Check your internet connection or try adjusting the bounding box.",
+                    let message = format!(
+                        "Failed to retrieve elevation data for bbox [{}, {}, {}, {}] after {} attempts. Check your internet connection or try adjusting the bounding box.",
                         params.min_lng, params.min_lat, params.max_lng, params.max_lat, max_retries
-                    )));
+                    );
+                    console::record(LogLevel::Error, "terrain", Some(&params.process_id), message.as_str());
+                    return Err(JsValue::from_str(&message));
                 }
             }
         }
@@ -152,6 +197,15 @@ pub async fn create_terrain_geometry(params_js: JsValue) -> Result<JsValue, JsVa
         cache_hit_rate: 1.0,
     };
 
+    Ok(elevation_result)
+}
+
+// Terrain mesh from the process's elevation grid (processed on demand), GPU first with CPU fallback
+pub(crate) async fn generate_terrain(
+    params: &TerrainGeometryParams,
+) -> Result<TerrainGeometryResult, JsValue> {
+    let elevation_result = load_elevation_result(params).await?;
+
     // IMPORTANT: Use manifold CPU terrain generation by default
     //
     // The GPU terrain generation (gpu_terrain.rs) creates non-manifold geometry due to:
@@ -170,36 +224,154 @@ pub async fn create_terrain_geometry(params_js: JsValue) -> Result<JsValue, JsVa
 
 
     if use_gpu_terrain {
-        match crate::gpu_terrain::generate_terrain_mesh_gpu(&elevation_result, &params).await {
-            Ok(gpu_result) => {
-                let js_result = convert_terrain_geometry_to_js(gpu_result)?;
-                return Ok(js_result);
+        match crate::gpu_terrain::generate_terrain_mesh_gpu(&elevation_result, params).await {
+            Ok(mut gpu_result) => {
+                // Cut to the clip shape of the process and shade the new faces like the CPU mesh
+                let clipped = clip_shape::clip_process_terrain(
+                    params,
+                    &mut gpu_result.positions,
+                    &mut gpu_result.indices,
+                )
+                .map_err(|e| JsValue::from_str(&e))?;
+                if clipped {
+                    let scale = projection::activate_process(&params.process_id);
+                    let datum = VerticalDatum::new(
+                        params.terrain_base_height,
+                        params.vertical_exaggeration,
+                        elevation_result.min_elevation,
+                        elevation_result.max_elevation,
+                        scale.model_scale,
+                    );
+                    gpu_result.colors =
+                        terrain_mesh_gen::generate_colors_from_positions(&gpu_result.positions, &datum);
+                    gpu_result.normals = terrain_mesh_gen::generate_triangle_normals(
+                        &gpu_result.positions,
+                        &gpu_result.indices,
+                    );
+                }
+                return Ok(gpu_result);
             }
-            Err(_e) => {
+            // A cancelled process must not continue on the CPU
+            Err(e) if GpuCancellation::for_process(Some(&params.process_id)).is_cancelled() => {
+                return Err(e);
+            }
+            Err(e) => {
                 // GPU processing failed, fall back to CPU
+                console::record(
+                    LogLevel::Warn,
+                    "terrain",
+                    Some(&params.process_id),
+                    format!(
+                        "GPU terrain generation failed, using CPU: {}",
+                        e.as_string().unwrap_or_default()
+                    ),
+                );
             }
         }
     }
 
     // Use manifold mesh-based terrain generation (CPU - produces guaranteed manifold geometry)
 
-    match terrain_mesh_gen::generate_terrain_with_mesh_cutting(&elevation_result, &params) {
-        Ok(result) => {
-            let js_result = convert_terrain_geometry_to_js(result)?;
-            Ok(js_result)
+    terrain_mesh_gen::generate_terrain_with_mesh_cutting(&elevation_result, params)
+        .map_err(|e| JsValue::from_str(&format!("Terrain generation failed: {}", e)))
+}
+
+/// Generate several terrain levels of detail from one elevation pass. `resolutions` are grid
+/// vertices per side, finest first (default 256, 128, 64). Returns `{ levels: [{ resolution,
+/// positions, indices, colors, normals }], processedElevationGrid, ... }` with the same
+/// elevation fields as `create_terrain_geometry`; levels use the manifold CPU mesh.
+#[wasm_bindgen]
+pub async fn create_terrain_lods(
+    params_js: JsValue,
+    resolutions: Option<Vec<u32>>,
+) -> Result<JsValue, JsValue> {
+    let params: TerrainGeometryParams = serde_wasm_bindgen::from_value(params_js)?;
+    if params.use_simple_mesh || params.flat_base_thickness.is_some() {
+        return Err(JsValue::from_str(
+            "Terrain LODs need elevation data; flat terrain has a single level",
+        ));
+    }
+    let resolutions: Vec<usize> = match resolutions {
+        Some(resolutions) if !resolutions.is_empty() => {
+            resolutions.into_iter().map(|r| r as usize).collect()
         }
-        Err(e) => {
-            Err(JsValue::from_str(&format!("Terrain generation failed: {}", e)))
+        _ => DEFAULT_LOD_RESOLUTIONS.to_vec(),
+    };
+
+    let timer = LogTimer::start("terrain", Some(&params.process_id));
+    let elevation_result = load_elevation_result(&params).await?;
+    let lods = terrain_mesh_gen::generate_terrain_lods(&elevation_result, &params, &resolutions)
+        .map_err(|e| JsValue::from_str(&format!("Terrain LOD generation failed: {}", e)))?;
+    timer.finish(
+        LogLevel::Info,
+        format!(
+            "Generated {} terrain LODs ({} vertices at the finest)",
+            lods.len(),
+            lods.iter().map(|lod| lod.positions.len() / 3).max().unwrap_or(0)
+        ),
+    );
+
+    // All levels share the footprint; record the finest one like a regular terrain
+    if let Some(finest) = lods.iter().max_by_key(|lod| lod.positions.len()) {
+        let volume = BoundingVolume::from_positions(&finest.positions);
+        bounds::store_bounds(&params.process_id, bounds::TERRAIN_BOUNDS_KEY, volume);
+    }
+
+    let levels = js_sys::Array::new();
+    for lod in &lods {
+        let level = Object::new();
+        js_sys::Reflect::set(
+            &level,
+            &JsValue::from_str("resolution"),
+            &JsValue::from_f64(lod.resolution as f64),
+        )?;
+        let attributes: [(&str, JsValue); 4] = [
+            ("positions", Float32Array::from(lod.positions.as_slice()).into()),
+            ("indices", Uint32Array::from(lod.indices.as_slice()).into()),
+            ("colors", Float32Array::from(lod.colors.as_slice()).into()),
+            ("normals", Float32Array::from(lod.normals.as_slice()).into()),
+        ];
+        for (key, array) in attributes {
+            js_sys::Reflect::set(&level, &JsValue::from_str(key), &array)?;
         }
+        levels.push(&level);
     }
+
+    let js_obj = Object::new();
+    js_sys::Reflect::set(&js_obj, &JsValue::from_str("levels"), &levels)?;
+    js_sys::Reflect::set(
+        &js_obj,
+        &JsValue::from_str("processedElevationGrid"),
+        &serde_wasm_bindgen::to_value(&elevation_result.elevation_grid)?,
+    )?;
+    for (key, value) in [
+        ("processedMinElevation", elevation_result.min_elevation),
+        ("processedMaxElevation", elevation_result.max_elevation),
+        ("originalMinElevation", elevation_result.min_elevation),
+        ("originalMaxElevation", elevation_result.max_elevation),
+    ] {
+        js_sys::Reflect::set(&js_obj, &JsValue::from_str(key), &JsValue::from_f64(value))?;
+    }
+    Ok(js_obj.into())
 }
 
 // Helper function to convert our Rust terrain geometry to JavaScript-friendly objects
-fn convert_terrain_geometry_to_js(result: TerrainGeometryResult) -> Result<JsValue, JsValue> {
+pub(crate) fn convert_terrain_geometry_to_js(
+    result: TerrainGeometryResult,
+    process_id: &str,
+) -> Result<JsValue, JsValue> {
+    // UVs below follow the terrain size of this process
+    projection::activate_process(process_id);
+    // Record the terrain volume alongside the layer volumes of this process
+    let volume = BoundingVolume::from_positions(&result.positions);
+    bounds::store_bounds(process_id, bounds::TERRAIN_BOUNDS_KEY, volume);
+
     let positions_array = Float32Array::from(result.positions.as_slice());
     let indices_array = Uint32Array::from(result.indices.as_slice());
     let colors_array = Float32Array::from(result.colors.as_slice());
     let normals_array = Float32Array::from(result.normals.as_slice());
+    // Texture coordinates over the bbox; see `generate_terrain_uvs` for tile imagery
+    let uvs_array = Float32Array::from(terrain_uv::bbox_uvs(&result.positions).as_slice());
 
     // Create a JavaScript object to return
     let js_obj = Object::new();
@@ -209,6 +381,7 @@ fn convert_terrain_geometry_to_js(result: TerrainGeometryResult) -> Result<JsVal
     js_sys::Reflect::set(&js_obj, &JsValue::from_str("indices"), &indices_array)?;
     js_sys::Reflect::set(&js_obj, &JsValue::from_str("colors"), &colors_array)?;
     js_sys::Reflect::set(&js_obj, &JsValue::from_str("normals"), &normals_array)?;
+    js_sys::Reflect::set(&js_obj, &JsValue::from_str("uvs"), &uvs_array)?;
 
     // Convert processed elevation grid to JS
     let processed_grid = serde_wasm_bindgen::to_value(&result.processed_elevation_grid)?;
@@ -242,21 +415,29 @@ fn convert_terrain_geometry_to_js(result: TerrainGeometryResult) -> Result<JsVal
         &JsValue::from_f64(result.original_max_elevation),
     )?;
 
+    js_sys::Reflect::set(
+        &js_obj,
+        &JsValue::from_str("boundingVolume"),
+        &serde_wasm_bindgen::to_value(&volume)?,
+    )?;
+
     Ok(js_obj.into())
 }
 
 // Create a simple flat terrain block without elevation data
 async fn create_simple_flat_terrain(params: &TerrainGeometryParams) -> Result<JsValue, JsValue> {
-    // Create a simple flat rectangular mesh at the base terrain height
-    let base_height = params.terrain_base_height;
+    // Create a simple flat rectangular mesh at the base plate thickness or base terrain height
+    let base_height = params
+        .flat_base_thickness
+        .unwrap_or(params.terrain_base_height);
 
     // Define terrain size - matching the regular terrain size
-    let terrain_size = 200.0; // Same as TERRAIN_SIZE in polygon_geometry.rs
+    let terrain_size = projection::activate_process(&params.process_id).size;
     let half_size = terrain_size / 2.0;
 
     // Create vertices for a simple flat rectangle
     // Bottom vertices (4 corners at base level)
-    let positions = vec![
+    let mut positions = vec![
         -half_size as f32, -half_size as f32, 0.0,      // Bottom-left bottom
         half_size as f32,  -half_size as f32, 0.0,      // Bottom-right bottom
         half_size as f32,  half_size as f32,  0.0,      // Top-right bottom
@@ -269,7 +450,7 @@ async fn create_simple_flat_terrain(params: &TerrainGeometryParams) -> Result<Js
     ];
 
     // Create indices for a box (12 triangles = 36 indices)
-    let indices = vec![
+    let mut indices = vec![
         // Bottom face (looking up)
         0, 1, 2,  0, 2, 3,
         // Top face (looking down)
@@ -284,16 +465,26 @@ async fn create_simple_flat_terrain(params: &TerrainGeometryParams) -> Result<Js
         3, 7, 4,  3, 4, 0,
     ];
 
+    // Cut the block to the clip shape of the process, if any
+    let clipped = clip_shape::clip_process_terrain(params, &mut positions, &mut indices)
+        .map_err(|e| JsValue::from_str(&e))?;
+
     // Create colors (uniform terrain color)
-    let colors: Vec<f32> = (0..8).flat_map(|_| vec![0.8, 0.6, 0.4]).collect(); // 8 vertices * 3 components
+    let colors: Vec<f32> = (0..positions.len() / 3)
+        .flat_map(|_| vec![0.8, 0.6, 0.4])
+        .collect();
 
     // Create normals
-    let normals = vec![
-        // Bottom vertices (pointing down)
-        0.0, 0.0, -1.0,  0.0, 0.0, -1.0,  0.0, 0.0, -1.0,  0.0, 0.0, -1.0,
-        // Top vertices (pointing up)
-        0.0, 0.0, 1.0,   0.0, 0.0, 1.0,   0.0, 0.0, 1.0,   0.0, 0.0, 1.0,
-    ];
+    let normals = if clipped {
+        terrain_mesh_gen::generate_triangle_normals(&positions, &indices)
+    } else {
+        vec![
+            // Bottom vertices (pointing down)
+            0.0, 0.0, -1.0,  0.0, 0.0, -1.0,  0.0, 0.0, -1.0,  0.0, 0.0, -1.0,
+            // Top vertices (pointing up)
+            0.0, 0.0, 1.0,   0.0, 0.0, 1.0,   0.0, 0.0, 1.0,   0.0, 0.0, 1.0,
+        ]
+    };
 
     // Create a flat elevation grid (2x2 grid with base height)
     let processed_elevation_grid = vec![
@@ -315,5 +506,5 @@ async fn create_simple_flat_terrain(params: &TerrainGeometryParams) -> Result<Js
     };
 
     // Convert to JavaScript object
-    convert_terrain_geometry_to_js(result)
+    convert_terrain_geometry_to_js(result, &params.process_id)
 }
\ No newline at end of file
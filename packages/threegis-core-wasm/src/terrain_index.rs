@@ -0,0 +1,215 @@
+// Spatial index over the rendered terrain mesh: its triangles bucketed in a uniform XY
+// grid, so the surface height under a layer vertex is found by testing the few triangles
+// of one cell instead of scanning the mesh. Built once per process mesh.
+use std::sync::Arc;
+
+use crate::module_state::ModuleState;
+
+// Grid columns and rows are limited so a huge mesh does not allocate a huge grid
+const MAX_GRID_DIMENSION: usize = 1024;
+// Triangles with a smaller XY area are walls or skirts of the terrain solid
+const MIN_PROJECTED_AREA: f32 = 1e-6;
+// Barycentric tolerance so points on shared edges hit a triangle
+const EDGE_EPSILON: f64 = 1e-9;
+
+pub(crate) struct TerrainIndex {
+    vertices: Vec<f32>,
+    triangles: Vec<[u32; 3]>,
+    min_x: f64,
+    min_y: f64,
+    cell_size: f64,
+    cols: usize,
+    rows: usize,
+    /// Triangle ids overlapping each cell, row-major
+    cells: Vec<Vec<u32>>,
+}
+
+impl TerrainIndex {
+    /// Index the non-vertical triangles of a mesh; None without any
+    pub(crate) fn build(vertices: &[f32], indices: &[u32]) -> Option<TerrainIndex> {
+        let vertex_count = vertices.len() / 3;
+        let point = |i: u32| {
+            let i = i as usize * 3;
+            [vertices[i], vertices[i + 1], vertices[i + 2]]
+        };
+        let triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .filter(|t| t.iter().all(|&i| (i as usize) < vertex_count))
+            .filter(|t| {
+                let [a, b, c] = t.map(point);
+                ((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])).abs()
+                    > MIN_PROJECTED_AREA
+            })
+            .collect();
+        if triangles.is_empty() {
+            return None;
+        }
+
+        let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for &i in triangles.iter().flatten() {
+            let [x, y, _] = point(i);
+            min_x = min_x.min(x as f64);
+            min_y = min_y.min(y as f64);
+            max_x = max_x.max(x as f64);
+            max_y = max_y.max(y as f64);
+        }
+        // About one triangle per cell
+        let extent = (max_x - min_x).max(max_y - min_y).max(f64::EPSILON);
+        let dimension =
+            ((triangles.len() as f64).sqrt().ceil() as usize).clamp(1, MAX_GRID_DIMENSION);
+        let cell_size = extent / dimension as f64;
+        let cols = (((max_x - min_x) / cell_size).floor() as usize + 1).min(MAX_GRID_DIMENSION);
+        let rows = (((max_y - min_y) / cell_size).floor() as usize + 1).min(MAX_GRID_DIMENSION);
+
+        let mut index = TerrainIndex {
+            vertices: vertices.to_vec(),
+            triangles,
+            min_x,
+            min_y,
+            cell_size,
+            cols,
+            rows,
+            cells: vec![Vec::new(); cols * rows],
+        };
+        for (id, triangle) in index.triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(point);
+            let (x0, y0) = index.cell_of(a[0].min(b[0]).min(c[0]), a[1].min(b[1]).min(c[1]));
+            let (x1, y1) = index.cell_of(a[0].max(b[0]).max(c[0]), a[1].max(b[1]).max(c[1]));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    index.cells[y * cols + x].push(id as u32);
+                }
+            }
+        }
+        Some(index)
+    }
+
+    fn cell_of(&self, x: f32, y: f32) -> (usize, usize) {
+        let cell = |value: f64, min: f64, count: usize| {
+            (((value - min) / self.cell_size).floor().max(0.0) as usize).min(count - 1)
+        };
+        (
+            cell(x as f64, self.min_x, self.cols),
+            cell(y as f64, self.min_y, self.rows),
+        )
+    }
+
+    fn point(&self, i: u32) -> [f64; 3] {
+        let i = i as usize * 3;
+        [
+            self.vertices[i] as f64,
+            self.vertices[i + 1] as f64,
+            self.vertices[i + 2] as f64,
+        ]
+    }
+
+    /// Height of the highest triangle under (x, y), so the top surface wins over the
+    /// bottom of the terrain solid; None outside the mesh
+    pub(crate) fn height_at(&self, x: f64, y: f64) -> Option<f64> {
+        let (cx, cy) = self.cell_of(x as f32, y as f32);
+        self.cells[cy * self.cols + cx]
+            .iter()
+            .filter_map(|&id| {
+                let [a, b, c] = self.triangles[id as usize].map(|i| self.point(i));
+                let det = (b[1] - c[1]) * (a[0] - c[0]) + (c[0] - b[0]) * (a[1] - c[1]);
+                let u = ((b[1] - c[1]) * (x - c[0]) + (c[0] - b[0]) * (y - c[1])) / det;
+                let v = ((c[1] - a[1]) * (x - c[0]) + (a[0] - c[0]) * (y - c[1])) / det;
+                let w = 1.0 - u - v;
+                (u >= -EDGE_EPSILON && v >= -EDGE_EPSILON && w >= -EDGE_EPSILON)
+                    .then(|| u * a[2] + v * b[2] + w * c[2])
+            })
+            .reduce(f64::max)
+    }
+
+    /// Triangles of the top surface overlapping the XY box between `min` and `max`, each
+    /// once; triangles lying under another one (the bottom of the terrain solid) are left out
+    pub(crate) fn top_triangles_in(&self, min: [f64; 2], max: [f64; 2]) -> Vec<[[f64; 3]; 3]> {
+        let (x0, y0) = self.cell_of(min[0] as f32, min[1] as f32);
+        let (x1, y1) = self.cell_of(max[0] as f32, max[1] as f32);
+        let mut ids: Vec<u32> = (y0..=y1)
+            .flat_map(|y| (x0..=x1).flat_map(move |x| self.cells[y * self.cols + x].iter()))
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.into_iter()
+            .map(|id| self.triangles[id as usize].map(|i| self.point(i)))
+            .filter(|[a, b, c]| {
+                let centroid = [(a[0] + b[0] + c[0]) / 3.0, (a[1] + b[1] + c[1]) / 3.0];
+                let z = (a[2] + b[2] + c[2]) / 3.0;
+                self.height_at(centroid[0], centroid[1])
+                    .is_some_and(|top| z >= top - 1e-6)
+            })
+            .collect()
+    }
+}
+
+/// Index of a process terrain mesh, built on first use and kept until the mesh changes
+pub(crate) fn process_terrain_index(
+    process_id: &str,
+    vertices: &[f32],
+    indices: &[u32],
+) -> Option<Arc<TerrainIndex>> {
+    let cached = ModuleState::with(|state| {
+        state
+            .terrain_mesh_indexes
+            .get(process_id)
+            .filter(|index| index.vertices == vertices)
+            .cloned()
+    });
+    if cached.is_some() {
+        return cached;
+    }
+    let index = Arc::new(TerrainIndex::build(vertices, indices)?);
+    ModuleState::with_mut(|state| {
+        state
+            .terrain_mesh_indexes
+            .insert(process_id.to_string(), index.clone())
+    });
+    Some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_height_at_follows_top_triangles() {
+        // A 3x3 vertex grid sloping in x on top of a flat bottom quad
+        let mut vertices = Vec::new();
+        for y in 0..3 {
+            for x in 0..3 {
+                vertices.extend([x as f32, y as f32, 10.0 + 2.0 * x as f32]);
+            }
+        }
+        vertices.extend([0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0, 0.0, 2.0, 2.0, 0.0]);
+        let mut indices = Vec::new();
+        for y in 0..2 {
+            for x in 0..2 {
+                let i = y * 3 + x;
+                indices.extend([i, i + 1, i + 4, i, i + 4, i + 3]);
+            }
+        }
+        // The bottom lies under the top surface; a vertical wall is not indexed
+        indices.extend([9, 11, 12, 9, 12, 10, 0, 1, 9]);
+
+        let index = TerrainIndex::build(&vertices, &indices).unwrap();
+        assert_eq!(index.triangles.len(), 10);
+        assert_eq!(index.height_at(0.5, 0.5), Some(11.0));
+        assert_eq!(index.height_at(2.0, 1.3), Some(14.0));
+        assert_eq!(index.height_at(1.0, 1.0), Some(12.0));
+        assert_eq!(index.height_at(5.0, 1.0), None);
+
+        let first = process_terrain_index("terrain-index-test", &vertices, &indices).unwrap();
+        let again = process_terrain_index("terrain-index-test", &vertices, &indices).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert!(TerrainIndex::build(&vertices, &[0, 1, 9]).is_none());
+
+        // Only the top grid triangles of the cell, not the bottom quad under it
+        let cell = index.top_triangles_in([0.2, 0.2], [0.8, 0.8]);
+        assert_eq!(cell.len(), 2);
+        assert!(cell.iter().flatten().all(|p| p[2] >= 10.0));
+    }
+}
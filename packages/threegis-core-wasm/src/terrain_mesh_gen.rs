@@ -1,56 +1,22 @@
 // Terrain mesh generation with proper manifold triangulation
+use crate::clip_shape;
 use crate::elevation::ElevationProcessingResult;
+use crate::projection::{self, terrain_size};
 use crate::terrain::{TerrainGeometryParams, TerrainGeometryResult};
+use crate::vertical_datum::{sample_grid_bilinear, VerticalDatum};
 
-// Terrain resolution is now dynamically determined from elevation data
-const MIN_TERRAIN_THICKNESS: f32 = 0.3;
-const MESH_SIZE_METERS: f32 = 200.0;
 const LIGHT_BROWN: [f32; 3] = [0.82, 0.71, 0.55];
 const DARK_BROWN: [f32; 3] = [0.66, 0.48, 0.30];
 const BOTTOM_SHADE_FACTOR: f32 = 0.6;
-// Scale factor to make vertical exaggeration values more visible
-// User value of 1 will result in ~15 units of max elevation variation
-const EXAGGERATION_SCALE_FACTOR: f64 = 5.0;
 
 /// Apply elevation data to mesh positions
 fn apply_elevation_to_positions(
     positions: &mut [f32],
     elevation_data: &ElevationProcessingResult,
-    params: &TerrainGeometryParams,
+    datum: &VerticalDatum,
     width_segments: usize,
     height_segments: usize,
 ) -> Result<(), String> {
-    let elevation_range = f64::max(
-        1.0,
-        elevation_data.max_elevation - elevation_data.min_elevation,
-    );
-
-    let source_width = elevation_data.grid_size.width as usize;
-    let source_height = elevation_data.grid_size.height as usize;
-
-    let sample_elevation = |normalized_x: f64, normalized_y: f64| -> f64 {
-        let src_x = normalized_x * (source_width - 1) as f64;
-        let src_y = normalized_y * (source_height - 1) as f64;
-
-        let x0 = src_x.floor() as usize;
-        let y0 = src_y.floor() as usize;
-        let x1 = (x0 + 1).min(source_width - 1);
-        let y1 = (y0 + 1).min(source_height - 1);
-
-        let dx = src_x - x0 as f64;
-        let dy = src_y - y0 as f64;
-
-        let v00 = elevation_data.elevation_grid[y0][x0];
-        let v10 = elevation_data.elevation_grid[y0][x1];
-        let v01 = elevation_data.elevation_grid[y1][x0];
-        let v11 = elevation_data.elevation_grid[y1][x1];
-
-        let v0 = v00 * (1.0 - dx) + v10 * dx;
-        let v1 = v01 * (1.0 - dx) + v11 * dx;
-
-        v0 * (1.0 - dy) + v1 * dy
-    };
-
     let grid_width = width_segments + 1;
     let grid_height = height_segments + 1;
     let total_vertices_per_layer = grid_width * grid_height;
@@ -64,15 +30,9 @@ fn apply_elevation_to_positions(
             let normalized_x = x as f64 / width_segments as f64;
             let normalized_y = y as f64 / height_segments as f64;
 
-            let elevation = sample_elevation(normalized_x, normalized_y);
-            let normalized_elevation = ((elevation - elevation_data.min_elevation) / elevation_range).clamp(0.0, 1.0);
-            let scaled_exaggeration = params.vertical_exaggeration * EXAGGERATION_SCALE_FACTOR;
-            let elevation_variation = (normalized_elevation * scaled_exaggeration) as f32;
-
-            let mut new_z = params.terrain_base_height as f32 + elevation_variation;
-            if new_z < MIN_TERRAIN_THICKNESS {
-                new_z = MIN_TERRAIN_THICKNESS;
-            }
+            let elevation =
+                sample_grid_bilinear(&elevation_data.elevation_grid, normalized_x, normalized_y);
+            let new_z = datum.elevation_to_z(elevation) as f32;
 
             // Update the Z coordinate of the top layer vertex
             if vertex_index + 2 < positions.len() {
@@ -85,18 +45,13 @@ fn apply_elevation_to_positions(
 }
 
 /// Generate colors based on vertex heights
-fn generate_colors_from_positions(
-    positions: &[f32],
-    params: &TerrainGeometryParams,
-) -> Vec<f32> {
+pub(crate) fn generate_colors_from_positions(positions: &[f32], datum: &VerticalDatum) -> Vec<f32> {
     let mut colors = Vec::new();
     let base_height = 0.0f32;
-    let terrain_base_height_f32 = params.terrain_base_height as f32;
-    let scaled_exaggeration = (params.vertical_exaggeration * EXAGGERATION_SCALE_FACTOR).max(1e-6) as f32;
 
     for vertex in positions.chunks_exact(3) {
         let z = vertex[2];
-        let normalized = ((z - terrain_base_height_f32) / scaled_exaggeration).clamp(0.0, 1.0);
+        let normalized = datum.z_to_normalized(z as f64) as f32;
         let inv_norm = 1.0 - normalized;
         let r = LIGHT_BROWN[0] * inv_norm + DARK_BROWN[0] * normalized;
         let g = LIGHT_BROWN[1] * inv_norm + DARK_BROWN[1] * normalized;
@@ -118,7 +73,7 @@ fn generate_colors_from_positions(
 }
 
 /// Generate normals for triangular faces (same method as buildings)
-fn generate_triangle_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+pub(crate) fn generate_triangle_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
     let mut normals = vec![0.0f32; positions.len()];
 
     // Calculate face normals and accumulate at vertices for triangles
@@ -177,6 +132,7 @@ fn create_manifold_terrain_mesh(
 
     let grid_width = width_segments + 1;
     let grid_height = height_segments + 1;
+    let mesh_size = terrain_size() as f32;
 
     // Create vertices in layers like buildings: bottom layer first, then top layer
     // This ensures proper vertex sharing for manifold edges
@@ -184,8 +140,8 @@ fn create_manifold_terrain_mesh(
     // Bottom layer vertices (z = 0)
     for y in 0..grid_height {
         for x in 0..grid_width {
-            let mesh_x = (x as f32 / width_segments as f32 - 0.5) * MESH_SIZE_METERS;
-            let mesh_y = (y as f32 / height_segments as f32 - 0.5) * MESH_SIZE_METERS;
+            let mesh_x = (x as f32 / width_segments as f32 - 0.5) * mesh_size;
+            let mesh_y = (y as f32 / height_segments as f32 - 0.5) * mesh_size;
             positions.extend_from_slice(&[mesh_x, mesh_y, 0.0]);
         }
     }
@@ -193,8 +149,8 @@ fn create_manifold_terrain_mesh(
     // Top layer vertices (z = base_height, will be displaced by elevation)
     for y in 0..grid_height {
         for x in 0..grid_width {
-            let mesh_x = (x as f32 / width_segments as f32 - 0.5) * MESH_SIZE_METERS;
-            let mesh_y = (y as f32 / height_segments as f32 - 0.5) * MESH_SIZE_METERS;
+            let mesh_x = (x as f32 / width_segments as f32 - 0.5) * mesh_size;
+            let mesh_y = (y as f32 / height_segments as f32 - 0.5) * mesh_size;
             positions.extend_from_slice(&[mesh_x, mesh_y, base_height]);
         }
     }
@@ -381,6 +337,8 @@ pub fn test_full_terrain_generation_manifold() -> Result<bool, String> {
         terrain_base_height: 1.0,
         process_id: "test".to_string(),
         use_simple_mesh: false,
+        flat_base_thickness: None,
+        elevation_encoding: None,
     };
 
     // Generate terrain using the full pipeline
@@ -430,41 +388,112 @@ pub fn test_full_terrain_generation_manifold() -> Result<bool, String> {
     Ok(csg.is_manifold())
 }
 
-/// Main function to generate terrain using the new mesh-based approach
-pub fn generate_terrain_with_mesh_cutting(
+/// One terrain mesh level of detail
+pub struct TerrainLod {
+    /// Grid vertices per side of the top surface
+    pub resolution: usize,
+    pub positions: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub colors: Vec<f32>,
+    pub normals: Vec<f32>,
+}
+
+/// Manifold terrain solid with the given number of segments per side, sampled bilinearly
+/// from the elevation grid
+fn build_terrain_lod(
     elevation_data: &ElevationProcessingResult,
     params: &TerrainGeometryParams,
-) -> Result<TerrainGeometryResult, String> {
-    // Use elevation data resolution directly to avoid interpolation issues
-    let mesh_width = (elevation_data.grid_size.width - 1) as usize;
-    let mesh_height = (elevation_data.grid_size.height - 1) as usize;
-
+    mesh_width: usize,
+    mesh_height: usize,
+) -> Result<TerrainLod, String> {
+    let scale = projection::activate_process(&params.process_id);
     // Ensure minimum resolution
     let mesh_width = mesh_width.max(3);
     let mesh_height = mesh_height.max(3);
 
     // Create base manifold mesh
-    let (mut positions, indices) = create_manifold_terrain_mesh(
+    let (mut positions, mut indices) = create_manifold_terrain_mesh(
         mesh_width,
         mesh_height,
         params.terrain_base_height as f32,
     );
 
+    let datum = VerticalDatum::new(
+        params.terrain_base_height,
+        params.vertical_exaggeration,
+        elevation_data.min_elevation,
+        elevation_data.max_elevation,
+        scale.model_scale,
+    );
+
     // Apply elevation data to displace top vertices
     apply_elevation_to_positions(
         &mut positions,
         elevation_data,
-        params,
+        &datum,
         mesh_width,
         mesh_height,
     )?;
 
+    // Cut the solid to the clip shape of the process, if any
+    clip_shape::clip_process_terrain(params, &mut positions, &mut indices)?;
+
     // Generate colors based on final vertex positions
-    let colors = generate_colors_from_positions(&positions, params);
+    let colors = generate_colors_from_positions(&positions, &datum);
 
     // Generate normals for triangular faces (same method as buildings)
     let normals = generate_triangle_normals(&positions, &indices);
 
+    Ok(TerrainLod {
+        resolution: mesh_width.max(mesh_height) + 1,
+        positions,
+        indices,
+        colors,
+        normals,
+    })
+}
+
+/// Terrain meshes at several resolutions (grid vertices per side) from one elevation grid,
+/// in the given order. The grid aspect ratio is kept, so the longer side gets `resolution`.
+pub fn generate_terrain_lods(
+    elevation_data: &ElevationProcessingResult,
+    params: &TerrainGeometryParams,
+    resolutions: &[usize],
+) -> Result<Vec<TerrainLod>, String> {
+    let grid_width = elevation_data.grid_size.width.max(2) as f64;
+    let grid_height = elevation_data.grid_size.height.max(2) as f64;
+    let longest = grid_width.max(grid_height);
+    resolutions
+        .iter()
+        .map(|&resolution| {
+            if resolution < 2 {
+                return Err(format!("LOD resolution must be at least 2, got {}", resolution));
+            }
+            let segments = (resolution - 1) as f64;
+            let mesh_width = (segments * (grid_width - 1.0) / (longest - 1.0)).round() as usize;
+            let mesh_height = (segments * (grid_height - 1.0) / (longest - 1.0)).round() as usize;
+            build_terrain_lod(elevation_data, params, mesh_width, mesh_height)
+        })
+        .collect()
+}
+
+/// Main function to generate terrain using the new mesh-based approach
+pub fn generate_terrain_with_mesh_cutting(
+    elevation_data: &ElevationProcessingResult,
+    params: &TerrainGeometryParams,
+) -> Result<TerrainGeometryResult, String> {
+    // Use elevation data resolution directly to avoid interpolation issues
+    let mesh_width = (elevation_data.grid_size.width - 1) as usize;
+    let mesh_height = (elevation_data.grid_size.height - 1) as usize;
+
+    let TerrainLod {
+        positions,
+        indices,
+        colors,
+        normals,
+        ..
+    } = build_terrain_lod(elevation_data, params, mesh_width, mesh_height)?;
+
     // Create processed elevation grid for output - use original data directly
     let processed_elevation_grid = elevation_data.elevation_grid.clone();
 
@@ -485,6 +514,50 @@ pub fn generate_terrain_with_mesh_cutting(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_terrain_lods_share_one_elevation_grid() {
+        let grid: Vec<Vec<f64>> = (0..33)
+            .map(|y| (0..33).map(|x| (x * y) as f64).collect())
+            .collect();
+        let elevation = ElevationProcessingResult {
+            elevation_grid: grid,
+            grid_size: crate::elevation::GridSize { width: 33, height: 33 },
+            min_elevation: 0.0,
+            max_elevation: 1024.0,
+            processed_min_elevation: 0.0,
+            processed_max_elevation: 1024.0,
+            cache_hit_rate: 1.0,
+        };
+        let params = TerrainGeometryParams {
+            min_lng: 0.0,
+            min_lat: 0.0,
+            max_lng: 1.0,
+            max_lat: 1.0,
+            vertical_exaggeration: 1.0,
+            terrain_base_height: 5.0,
+            process_id: "lod-test".to_string(),
+            use_simple_mesh: false,
+            flat_base_thickness: None,
+            elevation_encoding: None,
+        };
+        let lods = generate_terrain_lods(&elevation, &params, &[33, 17, 9]).unwrap();
+        let triangles: Vec<usize> = lods.iter().map(|lod| lod.indices.len() / 3).collect();
+        assert!(triangles[0] > triangles[1] && triangles[1] > triangles[2]);
+        // Same top surface height at the far corner for every level
+        let corner_z: Vec<f32> = lods
+            .iter()
+            .map(|lod| lod.positions.chunks_exact(3).map(|p| p[2]).fold(f32::MIN, f32::max))
+            .collect();
+        assert!(corner_z.iter().all(|z| (z - corner_z[0]).abs() < 1e-4));
+        for lod in &lods {
+            let report =
+                crate::mesh_manifold::analyze_manifold(&lod.positions, Some(&lod.indices), None)
+                    .unwrap();
+            assert!(report.is_watertight, "{} {:?}", lod.resolution, report);
+        }
+        assert_eq!(lods[2].resolution, 9);
+    }
+
     #[test]
     fn test_terrain_mesh_is_manifold() {
         match test_manifold_terrain_mesh() {
@@ -0,0 +1,224 @@
+// Terrain-only generation: fetch → elevation → terrain → export in one call for plain
+// topographic relief models. No vector tiles are fetched and no layer caches or layer
+// validation are involved.
+use serde::Deserialize;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::elevation::{self, ElevationEncoding, ElevationProcessingInput, TileRequest};
+use crate::export_3mf::{self, ColorGrouping, Mesh3MFData, Model3MFData};
+use crate::module_state::ModuleState;
+use crate::prefetch::TileSource;
+use crate::terrain::{self, TerrainGeometryParams};
+use crate::tilejson::source_tiles;
+use crate::zoom_select::highest_zoom_within;
+
+// Highest zoom tried when no zoom is given, and the tile budget it is lowered to fit
+const MAX_AUTO_ZOOM: u32 = 12;
+const MAX_AUTO_TILES: usize = 9;
+
+fn default_grid_size() -> u32 {
+    256
+}
+
+fn default_exaggeration() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TerrainExport {
+    /// Only return the terrain geometry
+    #[serde(rename = "none")]
+    None,
+    #[default]
+    #[serde(rename = "3mf")]
+    ThreeMf,
+}
+
+#[derive(Deserialize)]
+pub struct TerrainOnlyInput {
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    /// [minLng, minLat, maxLng, maxLat]
+    pub bbox: [f64; 4],
+    /// Elevation tile zoom; the highest zoom up to 12 covering the bbox with at most 9
+    /// tiles when omitted
+    #[serde(default)]
+    pub zoom: Option<u32>,
+    #[serde(default = "default_grid_size", rename = "gridWidth")]
+    pub grid_width: u32,
+    #[serde(default = "default_grid_size", rename = "gridHeight")]
+    pub grid_height: u32,
+    #[serde(default = "default_exaggeration", rename = "verticalExaggeration")]
+    pub vertical_exaggeration: f64,
+    #[serde(rename = "terrainBaseHeight")]
+    pub terrain_base_height: f64,
+    #[serde(default)]
+    pub export: TerrainExport,
+    #[serde(default, rename = "modelSizeMm")]
+    pub model_size_mm: Option<f64>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Pixel encoding of the elevation tiles: "mapbox-rgb" (default), "terrarium" or
+    /// `{ "custom": { "scale", "offset" } }`
+    #[serde(default, rename = "elevationEncoding")]
+    pub elevation_encoding: Option<ElevationEncoding>,
+}
+
+impl TerrainOnlyInput {
+    fn validate(&self) -> Result<(), String> {
+        let [min_lng, min_lat, max_lng, max_lat] = self.bbox;
+        if !self.bbox.iter().all(|v| v.is_finite()) || min_lng >= max_lng || min_lat >= max_lat {
+            return Err("Invalid bbox: must be [minLng, minLat, maxLng, maxLat]".to_string());
+        }
+        if !self.vertical_exaggeration.is_finite() || !self.terrain_base_height.is_finite() {
+            return Err("verticalExaggeration and terrainBaseHeight must be finite".to_string());
+        }
+        Ok(())
+    }
+
+    fn elevation_tiles(&self) -> Result<Vec<TileRequest>, JsValue> {
+        elevation_tile_requests(&self.bbox, self.zoom)
+    }
+}
+
+/// Elevation tiles covering `bbox` at `zoom`, or at the highest zoom up to 12 that covers
+/// it with at most 9 tiles
+pub(crate) fn elevation_tile_requests(
+    bbox: &[f64; 4],
+    zoom: Option<u32>,
+) -> Result<Vec<TileRequest>, JsValue> {
+    let tiles = match zoom {
+        Some(zoom) => source_tiles(TileSource::Raster, bbox, zoom)?,
+        None => highest_zoom_within(TileSource::Raster, bbox, MAX_AUTO_ZOOM, MAX_AUTO_TILES)?.1,
+    };
+    Ok(tiles
+        .into_iter()
+        .map(|t| TileRequest {
+            x: t.x,
+            y: t.y,
+            z: t.z,
+        })
+        .collect())
+}
+
+/// Build a terrain-only relief model. Returns `{ terrain, archive?, provenance }`:
+/// `terrain` has the shape `create_terrain_geometry` returns and `archive` holds the
+/// 3MF bytes unless `export` is "none".
+#[wasm_bindgen]
+pub async fn generate_terrain_model(input_json: &str) -> Result<JsValue, JsValue> {
+    let input: TerrainOnlyInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    input.validate().map_err(|e| JsValue::from_str(&e))?;
+    let [min_lng, min_lat, max_lng, max_lat] = input.bbox;
+
+    // Fetch and process elevation
+    let elevation_input = ElevationProcessingInput {
+        min_lng,
+        min_lat,
+        max_lng,
+        max_lat,
+        tiles: input.elevation_tiles()?,
+        grid_width: input.grid_width,
+        grid_height: input.grid_height,
+        process_id: input.process_id.clone(),
+        encoding: input.elevation_encoding,
+    };
+    let elevation_json = serde_json::to_string(&elevation_input)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize elevation input: {}", e)))?;
+    elevation::process_elevation_data_async(&elevation_json).await?;
+
+    // Terrain mesh
+    let params = TerrainGeometryParams {
+        min_lng,
+        min_lat,
+        max_lng,
+        max_lat,
+        vertical_exaggeration: input.vertical_exaggeration,
+        terrain_base_height: input.terrain_base_height,
+        process_id: input.process_id.clone(),
+        use_simple_mesh: false,
+        flat_base_thickness: None,
+        elevation_encoding: input.elevation_encoding,
+    };
+    let geometry = terrain::generate_terrain(&params).await?;
+    let provenance = ModuleState::with(|state| {
+        state
+            .process_provenance
+            .get(&input.process_id)
+            .cloned()
+            .unwrap_or_default()
+    });
+
+    // Export
+    let archive = match input.export {
+        TerrainExport::None => None,
+        TerrainExport::ThreeMf => {
+            let model = Model3MFData {
+                meshes: vec![Mesh3MFData {
+                    vertices: geometry.positions.clone(),
+                    indices: geometry.indices.clone(),
+                    colors: None,
+                    name: Some("terrain".to_string()),
+                    transform: None,
+                }],
+                title: input.title.clone(),
+                description: None,
+                model_size_mm: input.model_size_mm,
+                process_id: Some(input.process_id.clone()),
+                bbox: Some(input.bbox.to_vec()),
+                compression_level: None,
+                layer_order: None,
+                color_grouping: ColorGrouping::None,
+                materials: Vec::new(),
+                layer_materials: HashMap::new(),
+                provenance: Some(provenance.clone()),
+                repair: None,
+                reproducible: false,
+            };
+            let bytes = export_3mf::write_3mf_archive(&model, Vec::new())
+                .map_err(|e| JsValue::from_str(&format!("Failed to create 3MF archive: {}", e)))?;
+            Some(js_sys::Uint8Array::from(bytes.as_slice()))
+        }
+    };
+
+    let result = js_sys::Object::new();
+    let terrain = terrain::convert_terrain_geometry_to_js(geometry, &input.process_id)?;
+    js_sys::Reflect::set(&result, &JsValue::from_str("terrain"), &terrain)?;
+    if let Some(archive) = archive {
+        js_sys::Reflect::set(&result, &JsValue::from_str("archive"), &archive)?;
+    }
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("provenance"),
+        &serde_wasm_bindgen::to_value(&provenance)?,
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_defaults_and_auto_zoom() {
+        let input: TerrainOnlyInput = serde_json::from_str(
+            r#"{"processId": "terrain-only-test", "bbox": [7.0, 50.0, 7.5, 50.3], "terrainBaseHeight": 5}"#,
+        )
+        .unwrap();
+        assert_eq!(input.export, TerrainExport::ThreeMf);
+        assert_eq!((input.grid_width, input.grid_height), (256, 256));
+        assert!(input.validate().is_ok());
+
+        // Half a degree needs z10 to stay within 9 tiles
+        let tiles = input.elevation_tiles().unwrap();
+        assert!(tiles.len() <= MAX_AUTO_TILES);
+        assert!(tiles.iter().all(|t| t.z == 10));
+
+        let inverted: TerrainOnlyInput = serde_json::from_str(
+            r#"{"processId": "t", "bbox": [7.5, 50.0, 7.0, 50.3], "terrainBaseHeight": 5, "export": "none"}"#,
+        )
+        .unwrap();
+        assert!(inverted.validate().is_err());
+    }
+}
@@ -0,0 +1,297 @@
+// Texture coordinates for draping imagery over the terrain. UVs map the mesh either to
+// its bbox or, for satellite/ortho tiles, to the Web Mercator tile range of a zoom level;
+// `build_raster_atlas` stitches the cached raster tiles of that range into the matching
+// texture. V grows northwards while images have their top row at the northern edge, as
+// three.js expects with `flipY` enabled.
+use wasm_bindgen::prelude::*;
+
+use crate::module_state::{create_tile_key, ModuleState, TileData};
+use crate::projection::{self, terrain_size, tile_coords};
+
+/// Largest atlas side in pixels
+const MAX_ATLAS_SIZE: usize = 8192;
+
+/// UVs over the bbox of the terrain: (0, 0) at the south-west corner, (1, 1) at the
+/// north-east corner
+pub(crate) fn bbox_uvs(positions: &[f32]) -> Vec<f32> {
+    let size = terrain_size();
+    positions
+        .chunks_exact(3)
+        .flat_map(|p| {
+            projection::mesh_to_normalized(p[0] as f64, p[1] as f64, size)
+                .map(|v| v.clamp(0.0, 1.0) as f32)
+        })
+        .collect()
+}
+
+/// Tiles of one zoom level covering a bbox, inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TileRange {
+    pub zoom: u32,
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+}
+
+impl TileRange {
+    pub(crate) fn for_bbox(bbox: [f64; 4], zoom: u32) -> Result<Self, String> {
+        let [min_lng, min_lat, max_lng, max_lat] = bbox;
+        if zoom > 24 || !(min_lng < max_lng && min_lat < max_lat) {
+            return Err(format!("Invalid bbox {:?} or zoom {}", bbox, zoom));
+        }
+        let last = (1u32 << zoom) - 1;
+        let tile = |v: f64| (v.floor().max(0.0) as u32).min(last);
+        // A bbox on tile boundaries covers just the tiles inside them
+        let [min_x, min_y] = tile_coords(min_lng, max_lat, zoom).map(|v| v + 1e-9);
+        let [max_x, max_y] = tile_coords(max_lng, min_lat, zoom).map(|v| v - 1e-9);
+        Ok(TileRange {
+            zoom,
+            min_x: tile(min_x),
+            min_y: tile(min_y),
+            max_x: tile(max_x),
+            max_y: tile(max_y),
+        })
+    }
+
+    pub(crate) fn columns(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    pub(crate) fn rows(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
+}
+
+/// UVs into the atlas of `range` for terrain positions covering `bbox`
+pub(crate) fn tile_range_uvs(positions: &[f32], bbox: [f64; 4], range: &TileRange) -> Vec<f32> {
+    let [min_lng, min_lat, max_lng, max_lat] = bbox;
+    bbox_uvs(positions)
+        .chunks_exact(2)
+        .flat_map(|uv| {
+            let lng = min_lng + (max_lng - min_lng) * uv[0] as f64;
+            let lat = min_lat + (max_lat - min_lat) * uv[1] as f64;
+            let [x, y] = tile_coords(lng, lat, range.zoom);
+            [
+                ((x - range.min_x as f64) / range.columns() as f64) as f32,
+                (1.0 - (y - range.min_y as f64) / range.rows() as f64) as f32,
+            ]
+        })
+        .collect()
+}
+
+/// Texture coordinates for terrain `positions` (xyz, as returned by
+/// `create_terrain_geometry`) covering the bbox. Without `zoom` they span the bbox, like
+/// the `uvs` of the terrain result; with `zoom` they address the atlas that
+/// `build_raster_atlas` builds for the same bbox and zoom. `process_id` selects the
+/// terrain size the positions were generated at.
+#[wasm_bindgen]
+pub fn generate_terrain_uvs(
+    positions: &[f32],
+    min_lng: f64,
+    min_lat: f64,
+    max_lng: f64,
+    max_lat: f64,
+    zoom: Option<u32>,
+    process_id: Option<String>,
+) -> Result<Vec<f32>, JsValue> {
+    projection::set_active_scale(projection::process_scale(process_id.as_deref()));
+    let bbox = [min_lng, min_lat, max_lng, max_lat];
+    match zoom {
+        None => Ok(bbox_uvs(positions)),
+        Some(zoom) => {
+            let range = TileRange::for_bbox(bbox, zoom).map_err(|e| JsValue::from_str(&e))?;
+            Ok(tile_range_uvs(positions, bbox, &range))
+        }
+    }
+}
+
+/// Stitched RGBA image of a tile range
+pub(crate) struct RasterAtlas {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+    /// Tiles of the range that were not available and stay transparent
+    pub missing: usize,
+}
+
+/// Stitch the tiles of `range` at `tile_size` pixels each, top row at the northern edge;
+/// tiles of another size are resampled to nearest pixels
+pub(crate) fn stitch_tiles<'a>(
+    range: &TileRange,
+    tile_size: usize,
+    tile: impl Fn(u32, u32) -> Option<&'a TileData>,
+) -> Result<RasterAtlas, String> {
+    let (width, height) = (
+        range.columns() as usize * tile_size,
+        range.rows() as usize * tile_size,
+    );
+    if tile_size == 0 || width > MAX_ATLAS_SIZE || height > MAX_ATLAS_SIZE {
+        return Err(format!(
+            "Atlas of {}x{} pixels exceeds {} per side; use a lower zoom",
+            width, height, MAX_ATLAS_SIZE
+        ));
+    }
+
+    let mut atlas = RasterAtlas {
+        width,
+        height,
+        data: vec![0; width * height * 4],
+        missing: 0,
+    };
+    for y in range.min_y..=range.max_y {
+        for x in range.min_x..=range.max_x {
+            let Some(tile) = tile(x, y).filter(|t| {
+                t.width > 0 && t.height > 0 && t.data.len() >= (t.width * t.height * 4) as usize
+            }) else {
+                atlas.missing += 1;
+                continue;
+            };
+            let left = (x - range.min_x) as usize * tile_size;
+            let top = (y - range.min_y) as usize * tile_size;
+            for row in 0..tile_size {
+                let source_row = row * tile.height as usize / tile_size;
+                for column in 0..tile_size {
+                    let source = (source_row * tile.width as usize
+                        + column * tile.width as usize / tile_size)
+                        * 4;
+                    let target = ((top + row) * width + left + column) * 4;
+                    atlas.data[target..target + 4].copy_from_slice(&tile.data[source..source + 4]);
+                }
+            }
+        }
+    }
+    Ok(atlas)
+}
+
+/// Mark the cached raster tiles of `range` as used; the width of the first one, which
+/// the others are resampled to
+pub(crate) fn cached_tile_size(state: &mut ModuleState, range: &TileRange) -> Option<usize> {
+    let sizes: Vec<usize> = (range.min_y..=range.max_y)
+        .flat_map(|y| (range.min_x..=range.max_x).map(move |x| create_tile_key(x, y, range.zoom)))
+        .filter_map(|key| state.get_raster_tile(&key).map(|t| t.width as usize))
+        .collect();
+    sizes.into_iter().find(|&size| size > 0)
+}
+
+/// Stitch the cached raster tiles (`store_raster_tile`) of `zoom` covering the bbox into
+/// one RGBA texture for the UVs of `generate_terrain_uvs` with the same bbox and zoom.
+/// Returns `{ width, height, data, zoom, minX, minY, maxX, maxY, missing }`; tiles not in
+/// the cache are left transparent and counted in `missing`.
+/// The cache is keyed by tile coordinates only, so imagery shares it with elevation tiles
+/// of the same zoom.
+#[wasm_bindgen]
+pub fn build_raster_atlas(
+    min_lng: f64,
+    min_lat: f64,
+    max_lng: f64,
+    max_lat: f64,
+    zoom: u32,
+) -> Result<JsValue, JsValue> {
+    let range = TileRange::for_bbox([min_lng, min_lat, max_lng, max_lat], zoom)
+        .map_err(|e| JsValue::from_str(&e))?;
+
+    if range.columns() as usize > MAX_ATLAS_SIZE || range.rows() as usize > MAX_ATLAS_SIZE {
+        return Err(JsValue::from_str(
+            "Too many tiles for one atlas; use a lower zoom",
+        ));
+    }
+
+    let atlas = ModuleState::with_mut(|state| {
+        let tile_size = cached_tile_size(state, &range)
+            .ok_or_else(|| format!("No raster tiles cached at zoom {} for this bbox", zoom))?;
+        let tiles = &state.raster_tiles;
+        stitch_tiles(&range, tile_size, |x, y| {
+            tiles.get(&create_tile_key(x, y, zoom))
+        })
+    })
+    .map_err(|e| JsValue::from_str(&e))?;
+
+    let result = js_sys::Object::new();
+    for (name, value) in [
+        ("width", atlas.width as u32),
+        ("height", atlas.height as u32),
+        ("zoom", range.zoom),
+        ("minX", range.min_x),
+        ("minY", range.min_y),
+        ("maxX", range.max_x),
+        ("maxY", range.max_y),
+        ("missing", atlas.missing as u32),
+    ] {
+        js_sys::Reflect::set(&result, &JsValue::from_str(name), &JsValue::from(value))?;
+    }
+    js_sys::Reflect::set(
+        &result,
+        &JsValue::from_str("data"),
+        &js_sys::Uint8Array::from(atlas.data.as_slice()),
+    )?;
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevation::{tile_x_to_lng, tile_y_to_lat};
+
+    #[test]
+    fn test_tile_range_uvs_span_the_atlas() {
+        // Bbox of exactly tile 1/1 at zoom 2
+        let bbox = [
+            tile_x_to_lng(1, 2),
+            tile_y_to_lat(2, 2),
+            tile_x_to_lng(2, 2),
+            tile_y_to_lat(1, 2),
+        ];
+        let range = TileRange::for_bbox(bbox, 2).unwrap();
+        assert_eq!(
+            (range.min_x, range.min_y, range.max_x, range.max_y),
+            (1, 1, 1, 1)
+        );
+
+        let corners = [-100.0, -100.0, 0.0, 100.0, 100.0, 5.0];
+        assert_eq!(bbox_uvs(&corners), vec![0.0, 0.0, 1.0, 1.0]);
+        let uvs = tile_range_uvs(&corners, bbox, &range);
+        assert!(uvs
+            .iter()
+            .zip([0.0, 0.0, 1.0, 1.0])
+            .all(|(uv, expected)| (uv - expected).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_stitch_places_tiles_north_up() {
+        let tile = |value: u8, size: u32| TileData {
+            width: size,
+            height: size,
+            x: 0,
+            y: 0,
+            z: 1,
+            data: vec![value; (size * size * 4) as usize],
+            timestamp: 0.0,
+            key: String::new(),
+            buffer: Vec::new(),
+            parsed_layers: None,
+            rust_parsed_mvt: None,
+        };
+        let (north, south) = (tile(10, 2), tile(20, 1));
+        let range = TileRange {
+            zoom: 1,
+            min_x: 0,
+            min_y: 0,
+            max_x: 1,
+            max_y: 1,
+        };
+        let atlas = stitch_tiles(&range, 2, |x, y| match (x, y) {
+            (0, 0) => Some(&north),
+            (0, 1) => Some(&south),
+            _ => None,
+        })
+        .unwrap();
+
+        assert_eq!((atlas.width, atlas.height, atlas.missing), (4, 4, 2));
+        let pixel = |x: usize, y: usize| atlas.data[(y * 4 + x) * 4];
+        assert_eq!((pixel(0, 0), pixel(1, 1)), (10, 10));
+        // The 1x1 tile is scaled up to 2x2
+        assert_eq!((pixel(0, 2), pixel(1, 3)), (20, 20));
+        assert_eq!(pixel(3, 0), 0);
+    }
+}
@@ -0,0 +1,79 @@
+// Refresh of cached tiles for an area. Tiles fetched with an ETag or Last-Modified are
+// revalidated with a conditional request, so only tiles that changed on the server are
+// downloaded again; tiles without validators are refetched in full.
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::elevation::{cache_raster_tile_response, raster_tile_url};
+use crate::fetch_hook::{
+    is_not_modified, network_fetch_conditional, network_fetch_with_headers, record_validators,
+};
+use crate::module_state::ModuleState;
+use crate::prefetch::TileSource;
+use crate::provenance;
+use crate::tilejson::{source_headers, source_tiles};
+use crate::vectortile::{cache_vector_tile_response, vector_tile_url};
+
+#[derive(Serialize, Debug, Default)]
+pub struct RevalidationResult {
+    /// Cached tiles that were checked against the server
+    pub checked: usize,
+    #[serde(rename = "notModified")]
+    pub not_modified: usize,
+    /// Tiles whose cached data was replaced
+    pub updated: usize,
+    pub failed: usize,
+}
+
+/// Revalidate the cached tiles of `bbox` at `zoom`; uncached tiles are left alone.
+/// `sources` lists "raster" and/or "vector"; both when empty. Returns JSON counts.
+#[wasm_bindgen]
+pub async fn revalidate_tiles(
+    bbox: Vec<f64>,
+    zoom: u32,
+    sources: Vec<String>,
+) -> Result<String, JsValue> {
+    let sources = TileSource::parse_list(&sources)?;
+
+    let mut result = RevalidationResult::default();
+    for source in sources {
+        let tiles = source_tiles(source, &bbox, zoom)?;
+        for tile in tiles.iter().filter(|tile| source.is_cached(tile)) {
+            result.checked += 1;
+            let url = match source {
+                TileSource::Raster => raster_tile_url(tile.x, tile.y, tile.z),
+                TileSource::Vector => vector_tile_url(tile),
+            };
+            let validators = ModuleState::with(|state| state.tile_validators.get(&url).cloned());
+            let headers = source_headers(source.name());
+            let response = match &validators {
+                Some(validators) => network_fetch_conditional(&url, validators, &headers).await,
+                None => network_fetch_with_headers(&url, &headers).await,
+            };
+            let Ok(response) = response else {
+                result.failed += 1;
+                continue;
+            };
+            if validators.is_some() && is_not_modified(&response) {
+                result.not_modified += 1;
+                continue;
+            }
+
+            record_validators(&url, &response);
+            provenance::record_retrieval(&url);
+            let cached = match source {
+                TileSource::Raster => {
+                    cache_raster_tile_response(tile.x, tile.y, tile.z, &response).map(|_| ())
+                }
+                TileSource::Vector => cache_vector_tile_response(tile, None, &response).map(|_| ()),
+            };
+            match cached {
+                Ok(()) => result.updated += 1,
+                Err(_) => result.failed += 1,
+            }
+        }
+    }
+
+    serde_json::to_string(&result)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize revalidation result: {}", e)))
+}
@@ -0,0 +1,540 @@
+// TileJSON metadata of the raster and vector tile sources. A loaded TileJSON document
+// replaces the built-in tile URL of its source, and its zoom range and bounds clamp
+// tile requests so no tiles are requested that the server cannot serve. A source can
+// also carry request headers and query parameters (API keys) for its tile requests, and
+// further named vector sources can be registered for layers to pull from.
+use js_sys::Uint8Array;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+use crate::elevation::{tile_x_to_lng, tile_y_to_lat, ElevationEncoding};
+use crate::fetch_hook::network_fetch;
+use crate::mbtiles;
+use crate::module_state::ModuleState;
+use crate::prefetch::{tiles_for_bbox, TileSource};
+use crate::vectortile::TileRequest;
+
+// Deepest zoom level a TileJSON document may declare
+const MAX_ZOOM: u32 = 24;
+// Name layers use for the default vector tile source
+pub(crate) const DEFAULT_VECTOR_SOURCE: &str = "default";
+
+fn default_max_zoom() -> u32 {
+    22
+}
+
+/// The parts of a TileJSON 2.x/3.x document used for tile requests
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TileJson {
+    /// URL templates with {z}, {x} and {y} placeholders
+    pub tiles: Vec<String>,
+    #[serde(default)]
+    pub minzoom: u32,
+    #[serde(default = "default_max_zoom")]
+    pub maxzoom: u32,
+    /// [minLng, minLat, maxLng, maxLat]
+    #[serde(default)]
+    pub bounds: Option<[f64; 4]>,
+    #[serde(default)]
+    pub attribution: Option<String>,
+    /// "xyz" (default) or "tms" with a flipped y axis
+    #[serde(default)]
+    pub scheme: Option<String>,
+    /// Pixel encoding of raster DEM tiles ("mapbox" or "terrarium"), as in raster-dem
+    /// style sources
+    #[serde(default)]
+    pub encoding: Option<ElevationEncoding>,
+}
+
+impl TileJson {
+    fn parse(json: &str) -> Result<Self, String> {
+        let tilejson: Self =
+            serde_json::from_str(json).map_err(|e| format!("Invalid TileJSON: {}", e))?;
+        tilejson.validate()?;
+        Ok(tilejson)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.tiles.is_empty() {
+            return Err("TileJSON has no tile URL templates".to_string());
+        }
+        if let Some(template) = self
+            .tiles
+            .iter()
+            .find(|t| !(t.contains("{z}") && t.contains("{x}") && t.contains("{y}")))
+        {
+            return Err(format!(
+                "Tile URL template '{}' lacks {{z}}, {{x}} or {{y}}",
+                template
+            ));
+        }
+        if self.minzoom > self.maxzoom || self.maxzoom > MAX_ZOOM {
+            return Err(format!(
+                "Invalid zoom range {}..{}",
+                self.minzoom, self.maxzoom
+            ));
+        }
+        if let Some([min_lng, min_lat, max_lng, max_lat]) = self.bounds {
+            if min_lng >= max_lng || min_lat >= max_lat {
+                return Err("Invalid bounds: must be [minLng, minLat, maxLng, maxLat]".to_string());
+            }
+        }
+        match self.scheme.as_deref() {
+            None | Some("xyz") | Some("tms") => Ok(()),
+            Some(scheme) => Err(format!("Unsupported tile scheme '{}'", scheme)),
+        }
+    }
+
+    /// URL of a tile; multiple templates (subdomains) are used round-robin
+    pub fn tile_url(&self, x: u32, y: u32, z: u32) -> String {
+        let y = if self.scheme.as_deref() == Some("tms") {
+            (1u32 << z) - 1 - y
+        } else {
+            y
+        };
+        let template = &self.tiles[(x as usize + y as usize) % self.tiles.len()];
+        template
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string())
+    }
+
+    pub fn clamp_zoom(&self, zoom: u32) -> u32 {
+        zoom.clamp(self.minzoom, self.maxzoom)
+    }
+
+    /// Whether a tile intersects the bounds of the source
+    pub fn covers(&self, tile: &TileRequest) -> bool {
+        let Some([min_lng, min_lat, max_lng, max_lat]) = self.bounds else {
+            return true;
+        };
+        tile_x_to_lng(tile.x, tile.z) < max_lng
+            && tile_x_to_lng(tile.x + 1, tile.z) > min_lng
+            && tile_y_to_lat(tile.y + 1, tile.z) < max_lat
+            && tile_y_to_lat(tile.y, tile.z) > min_lat
+    }
+}
+
+/// Headers and query parameters sent with every tile request of a source; kept apart
+/// from the TileJSON so keys never show up in the metadata returned to JS
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SourceRequest {
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+}
+
+impl SourceRequest {
+    fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.query.is_empty()
+    }
+
+    /// Append the query parameters to a tile URL
+    fn apply(&self, url: String) -> String {
+        if self.query.is_empty() {
+            return url;
+        }
+        let query: Vec<String> = self
+            .query
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode_component(name), encode_component(value)))
+            .collect();
+        let separator = if url.contains('?') { '&' } else { '?' };
+        format!("{}{}{}", url, separator, query.join("&"))
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+pub(crate) fn encode_component(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn source_key(source: &str) -> Result<TileSource, JsValue> {
+    TileSource::parse(source)
+}
+
+/// TileJSON metadata configured for a source
+pub(crate) fn source_metadata(source: TileSource) -> Option<TileJson> {
+    mbtiles::archive_metadata(source.name()).or_else(|| metadata(source.name()))
+}
+
+fn metadata(key: &str) -> Option<TileJson> {
+    ModuleState::with(|state| state.tile_sources.get(key).cloned())
+}
+
+/// Key of a vector tile source in the source maps: "vector" for the default source (None
+/// or "default"), "vector:<name>" for sources added with `register_vector_tile_source`
+pub(crate) fn vector_source_key(name: Option<&str>) -> String {
+    match name {
+        None | Some(DEFAULT_VECTOR_SOURCE) => TileSource::Vector.name().to_string(),
+        Some(name) => format!("{}:{}", TileSource::Vector.name(), name),
+    }
+}
+
+/// URL of a tile from the configured TileJSON or template of the source with `key`
+/// (`TileSource::name` or `vector_source_key`), with its query parameters; None when
+/// nothing is configured and the built-in tile URL applies
+pub(crate) fn configured_tile_url(key: &str, x: u32, y: u32, z: u32) -> Option<String> {
+    let tilejson = metadata(key)?;
+    let url = tilejson.tile_url(x, y, z);
+    Some(ModuleState::with(|state| {
+        match state.tile_source_requests.get(key) {
+            Some(request) => request.apply(url),
+            None => url,
+        }
+    }))
+}
+
+/// Request headers configured for the source with `key`
+pub(crate) fn source_headers(key: &str) -> Vec<(String, String)> {
+    ModuleState::with(|state| {
+        state
+            .tile_source_requests
+            .get(key)
+            .map(|request| {
+                request
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Tiles of a source covering `bbox`, at `zoom` clamped to the source's zoom range and
+/// limited to its bounds
+pub(crate) fn source_tiles(
+    source: TileSource,
+    bbox: &[f64],
+    zoom: u32,
+) -> Result<Vec<TileRequest>, JsValue> {
+    tiles_of(source.name(), bbox, zoom)
+}
+
+/// `source_tiles` of a default (None) or named vector tile source
+pub(crate) fn vector_source_tiles(
+    name: Option<&str>,
+    bbox: &[f64],
+    zoom: u32,
+) -> Result<Vec<TileRequest>, JsValue> {
+    tiles_of(&vector_source_key(name), bbox, zoom)
+}
+
+fn tiles_of(key: &str, bbox: &[f64], zoom: u32) -> Result<Vec<TileRequest>, JsValue> {
+    // A loaded MBTiles archive serves the source in place of its tile server
+    match mbtiles::archive_metadata(key).or_else(|| metadata(key)) {
+        Some(tilejson) => Ok(tiles_for_bbox(bbox, tilejson.clamp_zoom(zoom))?
+            .into_iter()
+            .filter(|tile| tilejson.covers(tile))
+            .collect()),
+        None => tiles_for_bbox(bbox, zoom),
+    }
+}
+
+fn store(key: &str, tilejson: TileJson) -> Result<String, JsValue> {
+    let json = serde_json::to_string(&tilejson)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize TileJSON: {}", e)))?;
+    ModuleState::with_mut(|state| state.tile_sources.insert(key.to_string(), tilejson));
+    Ok(json)
+}
+
+/// Fetch a TileJSON document for `source` ("raster" or "vector") and use it for all
+/// further tile requests of that source. Returns the parsed metadata as JSON.
+#[wasm_bindgen]
+pub async fn load_tilejson(source: &str, url: &str) -> Result<String, JsValue> {
+    let source = source_key(source)?;
+    let response = network_fetch(url).await?;
+    let raw_data = js_sys::Reflect::get(&response, &JsValue::from_str("rawData"))?;
+    if raw_data.is_undefined() || raw_data.is_null() {
+        return Err(JsValue::from_str(&format!(
+            "No TileJSON data received from {}",
+            url
+        )));
+    }
+    let text = String::from_utf8(Uint8Array::new(&raw_data).to_vec())
+        .map_err(|_| JsValue::from_str(&format!("TileJSON from {} is not UTF-8", url)))?;
+    let tilejson = TileJson::parse(&text).map_err(|e| JsValue::from_str(&e))?;
+    store(source.name(), tilejson)
+}
+
+/// Use an already available TileJSON document for `source`
+#[wasm_bindgen]
+pub fn set_tilejson(source: &str, tilejson_json: &str) -> Result<String, JsValue> {
+    let source = source_key(source)?;
+    let tilejson = TileJson::parse(tilejson_json).map_err(|e| JsValue::from_str(&e))?;
+    store(source.name(), tilejson)
+}
+
+/// Store a URL template source with its request headers and query parameters under `key`
+fn configure_source(
+    key: &str,
+    url_template: &str,
+    scheme: Option<String>,
+    headers_json: Option<String>,
+    query_json: Option<String>,
+) -> Result<String, JsValue> {
+    let tilejson = TileJson {
+        tiles: vec![url_template.trim().to_string()],
+        minzoom: 0,
+        maxzoom: default_max_zoom(),
+        bounds: None,
+        attribution: None,
+        scheme: scheme.filter(|s| !s.is_empty()),
+        encoding: None,
+    };
+    tilejson.validate().map_err(|e| JsValue::from_str(&e))?;
+    let parse_map = |json: Option<String>, what: &str| match json.as_deref() {
+        Some(json) if !json.trim().is_empty() => serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse {}: {}", what, e))),
+        _ => Ok(BTreeMap::new()),
+    };
+    let request = SourceRequest {
+        headers: parse_map(headers_json, "headers")?,
+        query: parse_map(query_json, "query parameters")?,
+    };
+
+    let json = store(key, tilejson)?;
+    ModuleState::with_mut(|state| {
+        if request.is_empty() {
+            state.tile_source_requests.remove(key);
+        } else {
+            state.tile_source_requests.insert(key.to_string(), request);
+        }
+    });
+    Ok(json)
+}
+
+/// Fetch vector tiles from `url_template` ({z}, {x} and {y} placeholders) instead of the
+/// built-in tile server. `scheme` is "xyz" (default) or "tms" with a flipped y axis;
+/// `headers_json` is an object of request headers and `query_json` an object of query
+/// parameters added to every tile URL, e.g. `{"key": "..."}` for an API key. Returns the
+/// source metadata as JSON.
+#[wasm_bindgen]
+pub fn set_vector_tile_source(
+    url_template: &str,
+    scheme: Option<String>,
+    headers_json: Option<String>,
+    query_json: Option<String>,
+) -> Result<String, JsValue> {
+    configure_source(
+        TileSource::Vector.name(),
+        url_template,
+        scheme,
+        headers_json,
+        query_json,
+    )
+}
+
+/// Add a vector tile source under `name` next to the default one, e.g. a custom overlay
+/// beside OpenMapTiles. Layers pick it with `source` in their VtDataSet and
+/// `fetch_vector_tiles` fetches it when listed in `sources`. Other arguments as in
+/// `set_vector_tile_source`; registering a name again replaces the source.
+#[wasm_bindgen]
+pub fn register_vector_tile_source(
+    name: &str,
+    url_template: &str,
+    scheme: Option<String>,
+    headers_json: Option<String>,
+    query_json: Option<String>,
+) -> Result<String, JsValue> {
+    if name.is_empty() || name == DEFAULT_VECTOR_SOURCE || name.contains([':', '/']) {
+        return Err(JsValue::from_str(&format!(
+            "Invalid vector tile source name '{}'",
+            name
+        )));
+    }
+    configure_source(
+        &vector_source_key(Some(name)),
+        url_template,
+        scheme,
+        headers_json,
+        query_json,
+    )
+}
+
+/// Remove a named vector tile source; returns whether it was registered
+#[wasm_bindgen]
+pub fn remove_vector_tile_source(name: &str) -> bool {
+    if name == DEFAULT_VECTOR_SOURCE {
+        return false;
+    }
+    let key = vector_source_key(Some(name));
+    ModuleState::with_mut(|state| {
+        state.tile_source_requests.remove(&key);
+        state.tile_sources.remove(&key).is_some()
+    })
+}
+
+/// Names of the registered vector tile sources, without the default source
+#[wasm_bindgen]
+pub fn list_vector_tile_sources() -> Vec<String> {
+    let prefix = format!("{}:", TileSource::Vector.name());
+    let mut names: Vec<String> = ModuleState::with(|state| {
+        state
+            .tile_sources
+            .keys()
+            .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+            .collect()
+    });
+    names.sort();
+    names
+}
+
+/// Go back to the built-in tile URL of `source`, dropping its headers and query parameters
+#[wasm_bindgen]
+pub fn clear_tilejson(source: &str) -> Result<(), JsValue> {
+    let source = source_key(source)?;
+    ModuleState::with_mut(|state| {
+        state.tile_sources.remove(source.name());
+        state.tile_source_requests.remove(source.name());
+    });
+    Ok(())
+}
+
+/// The zoom tile requests of `source` will use for `zoom`
+#[wasm_bindgen]
+pub fn clamp_source_zoom(source: &str, zoom: u32) -> Result<u32, JsValue> {
+    let source = source_key(source)?;
+    Ok(source_metadata(source).map_or(zoom, |tilejson| tilejson.clamp_zoom(zoom)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tilejson_urls_and_clamping() {
+        let tilejson = TileJson::parse(
+            r#"{
+                "tilejson": "3.0.0",
+                "tiles": ["https://a.example.org/{z}/{x}/{y}.pbf", "https://b.example.org/{z}/{x}/{y}.pbf"],
+                "minzoom": 2,
+                "maxzoom": 14,
+                "bounds": [5.8, 47.2, 15.1, 55.1],
+                "attribution": "© OpenStreetMap contributors"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            tilejson.tile_url(1, 2, 3),
+            "https://b.example.org/3/1/2.pbf"
+        );
+        assert_eq!(tilejson.clamp_zoom(16), 14);
+        assert_eq!(tilejson.clamp_zoom(0), 2);
+
+        // z6 tiles over Germany and over the Atlantic
+        assert!(tilejson.covers(&TileRequest { x: 33, y: 21, z: 6 }));
+        assert!(!tilejson.covers(&TileRequest { x: 20, y: 21, z: 6 }));
+
+        let tms = TileJson {
+            scheme: Some("tms".to_string()),
+            ..tilejson
+        };
+        assert_eq!(tms.tile_url(1, 0, 2), "https://a.example.org/2/1/3.pbf");
+
+        assert!(TileJson::parse(r#"{"tiles": ["https://example.org/tiles.pbf"]}"#).is_err());
+
+        let request = SourceRequest {
+            query: BTreeMap::from([("key".to_string(), "a b&c".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            request.apply(tms.tile_url(1, 0, 2)),
+            "https://a.example.org/2/1/3.pbf?key=a%20b%26c"
+        );
+        assert_eq!(
+            request.apply("https://example.org/1/0/0.pbf?v=2".to_string()),
+            "https://example.org/1/0/0.pbf?v=2&key=a%20b%26c"
+        );
+
+        // Named sources live next to the default one under their own key
+        register_vector_tile_source(
+            "tilejson-test-overlay",
+            "https://overlay.example.org/{z}/{x}/{y}.mvt",
+            None,
+            None,
+            Some(r#"{"token": "t"}"#.to_string()),
+        )
+        .unwrap();
+        assert!(list_vector_tile_sources().contains(&"tilejson-test-overlay".to_string()));
+        assert_eq!(
+            configured_tile_url(&vector_source_key(Some("tilejson-test-overlay")), 1, 2, 3)
+                .as_deref(),
+            Some("https://overlay.example.org/3/1/2.mvt?token=t")
+        );
+        assert_eq!(vector_source_key(Some(DEFAULT_VECTOR_SOURCE)), "vector");
+        assert!(remove_vector_tile_source("tilejson-test-overlay"));
+        assert!(configured_tile_url("vector:tilejson-test-overlay", 1, 2, 3).is_none());
+    }
+
+    #[test]
+    fn test_vector_tile_source_configuration() {
+        let key = TileSource::Vector.name();
+        let metadata = set_vector_tile_source(
+            " https://tiles.example.org/{z}/{x}/{y}.pbf ",
+            Some("tms".to_string()),
+            Some(r#"{"Authorization": "Bearer secret"}"#.to_string()),
+            Some(r#"{"api_key": "k1"}"#.to_string()),
+        )
+        .unwrap();
+        // Keys stay out of the metadata returned to JS
+        assert!(!metadata.contains("secret") && !metadata.contains("k1"));
+
+        // TMS flips y: row 1 of zoom 2 is row 2 counted from the south
+        assert_eq!(
+            configured_tile_url(key, 3, 1, 2).as_deref(),
+            Some("https://tiles.example.org/2/3/2.pbf?api_key=k1")
+        );
+        assert_eq!(
+            source_headers(key),
+            vec![("Authorization".to_string(), "Bearer secret".to_string())]
+        );
+
+        // Configuring the source again without keys drops the old ones
+        set_vector_tile_source(
+            "https://tiles.example.org/{z}/{x}/{y}.pbf",
+            None,
+            None,
+            Some(" ".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            configured_tile_url(key, 3, 1, 2).as_deref(),
+            Some("https://tiles.example.org/2/3/1.pbf")
+        );
+        assert!(source_headers(key).is_empty());
+
+        clear_tilejson(key).unwrap();
+        assert!(configured_tile_url(key, 3, 1, 2).is_none());
+
+        // Templates need all placeholders and a known scheme
+        let template = |tiles: &str, scheme: Option<&str>| TileJson {
+            tiles: vec![tiles.to_string()],
+            minzoom: 0,
+            maxzoom: default_max_zoom(),
+            bounds: None,
+            attribution: None,
+            scheme: scheme.map(str::to_string),
+            encoding: None,
+        };
+        assert!(template("https://example.org/{z}/{x}.pbf", None)
+            .validate()
+            .is_err());
+        assert_eq!(
+            template("https://example.org/{z}/{x}/{y}.pbf", Some("wmts"))
+                .validate()
+                .unwrap_err(),
+            "Unsupported tile scheme 'wmts'"
+        );
+    }
+}
@@ -7,11 +7,23 @@ use serde_wasm_bindgen::{from_value, to_value};
 use std::collections::HashMap;
 use std::io::Read;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::JsFuture;
 
+use crate::cache_budget::CacheKind;
+use crate::cache_keys;
+use crate::cancellation::{job_error, ProcessCancellation};
+use crate::chunking::YieldTimer;
+use crate::console::{self, LogLevel};
+use crate::fetch_hook::{network_fetch_with_headers, record_validators};
+use crate::filter_expression::{self, FilterContext};
+use crate::mbtiles;
 use crate::module_state::{ModuleState, TileData};
 use crate::polygon_geometry::VtDataSet;
-use crate::{cache_keys, fetch};
+use crate::prefetch::TileSource;
+use crate::provenance;
+use crate::tilejson::{
+    configured_tile_url, source_headers, vector_source_key, vector_source_tiles,
+    DEFAULT_VECTOR_SOURCE,
+};
 
 // Reuse the TileRequest struct from elevation.rs
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -33,6 +45,9 @@ pub struct VectortileProcessingInput {
     pub grid_height: u32,
     // Process reference for consistent resource management
     pub process_id: String,
+    // Vector tile sources to fetch ("default" and/or registered names); default only when None
+    #[serde(default)]
+    pub sources: Option<Vec<String>>,
 }
 
 // Result structure compatible with JS expectations
@@ -40,6 +55,9 @@ pub struct VectortileProcessingInput {
 pub struct VectorTileResult {
     pub tile: TileRequest,
     pub data: Vec<u8>, // Vector tile binary data
+    // Named source of the tile, None for the default source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
 }
 
 // Structure for the GeometryData that we extract from vector tiles
@@ -68,8 +86,21 @@ pub struct ExtractFeaturesInput {
     pub elevation_process_id: Option<String>, // Process ID to find cached elevation data
 }
 
+// Input of extract_features_multi_layer: several layers of one bbox and process
+#[derive(Deserialize)]
+pub struct ExtractMultiLayerInput {
+    pub bbox: Vec<f64>, // [minLng, minLat, maxLng, maxLat]
+    #[serde(rename = "vtDataSets")]
+    pub vt_data_sets: Vec<VtDataSet>,
+    #[serde(rename = "processId")]
+    pub process_id: String,
+    #[serde(rename = "elevationProcessId")]
+    pub elevation_process_id: Option<String>,
+}
+
 // Feature geometry types
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
 pub enum GeometryType {
     Point,
     LineString,
@@ -110,7 +141,7 @@ fn lng_to_tile_x(lng: f64, zoom: u32) -> u32 {
 }
 
 // Calculate the tiles needed to cover a bounding box
-fn get_tiles_for_bbox(
+pub(crate) fn get_tiles_for_bbox(
     min_lng: f64,
     min_lat: f64,
     max_lng: f64,
@@ -152,6 +183,7 @@ pub fn calculate_tile_count(
 }
 
 // Calculate base elevation for a geometry based on its position relative to elevation grid
+#[allow(clippy::too_many_arguments)]
 fn calculate_base_elevation(
     coordinates: &[Vec<f64>],
     elevation_grid: &[Vec<f64>],
@@ -201,13 +233,23 @@ fn calculate_base_elevation(
     }
 }
 
-// Evaluate if a feature matches a filter expression
-fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
+// Evaluate if a feature of a tile at `zoom` matches a filter. Legacy filters may compare
+// "$zoom"; MapLibre expression filters are evaluated by `filter_expression`.
+fn evaluate_filter(filter: &serde_json::Value, feature: &Feature, zoom: u32) -> bool {
     // If no filter, always pass
     if filter.is_null() {
         return true;
     }
 
+    if filter_expression::is_expression_filter(filter) {
+        let context = FilterContext {
+            properties: &feature.properties,
+            geometry_type: &feature.geometry.r#type,
+            zoom: zoom as f64,
+        };
+        return filter_expression::evaluate_filter_expression(filter, &context);
+    }
+
     // Filters should be arrays where the first element is the operator
     let filter_array = match filter.as_array() {
         Some(arr) if !arr.is_empty() => arr,
@@ -218,35 +260,27 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
         Some(op) => op,
         None => return true, // Invalid operator, default to pass
     };
+    let zoom_value = serde_json::Value::from(zoom);
 
     match operator {
         // Logical operators
         "all" => {
             // All conditions must be true
-            for i in 1..filter_array.len() {
-                if !evaluate_filter(&filter_array[i], feature) {
-                    return false;
-                }
-            }
-            true
+            filter_array[1..]
+                .iter()
+                .all(|condition| evaluate_filter(condition, feature, zoom))
         }
         "any" => {
             // At least one condition must be true
-            for i in 1..filter_array.len() {
-                if evaluate_filter(&filter_array[i], feature) {
-                    return true;
-                }
-            }
-            false
+            filter_array[1..]
+                .iter()
+                .any(|condition| evaluate_filter(condition, feature, zoom))
         }
         "none" => {
             // None of the conditions should be true
-            for i in 1..filter_array.len() {
-                if evaluate_filter(&filter_array[i], feature) {
-                    return false;
-                }
-            }
-            true
+            !filter_array[1..]
+                .iter()
+                .any(|condition| evaluate_filter(condition, feature, zoom))
         }
 
         // Equality operators
@@ -272,11 +306,10 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
                     _ => "Unknown",
                 };
                 expected_value
-                    .as_str()
-                    .map_or(false, |v| v == geometry_type)
+                    .as_str() == Some(geometry_type)
             } else {
                 // Compare property value
-                match feature.properties.as_object().and_then(|obj| obj.get(key)) {
+                match legacy_filter_value(key, feature, &zoom_value) {
                     Some(actual_value) => actual_value == expected_value,
                     None => expected_value.is_null(),
                 }
@@ -303,10 +336,10 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
                     "MultiPolygon" => "MultiPolygon",
                     _ => "Unknown",
                 };
-                expected_value.as_str().map_or(true, |v| v != geometry_type)
+                expected_value.as_str() != Some(geometry_type)
             } else {
                 // Compare property value
-                match feature.properties.as_object().and_then(|obj| obj.get(key)) {
+                match legacy_filter_value(key, feature, &zoom_value) {
                     Some(actual_value) => actual_value != expected_value,
                     None => !expected_value.is_null(),
                 }
@@ -334,34 +367,18 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
                     "MultiPolygon" => "MultiPolygon",
                     _ => "Unknown",
                 };
-                for i in 2..filter_array.len() {
-                    if filter_array[i]
-                        .as_str()
-                        .map_or(false, |v| v == geometry_type)
-                    {
-                        return true;
-                    }
-                }
-                false
+                filter_array[2..]
+                    .iter()
+                    .any(|value| value.as_str() == Some(geometry_type))
             } else {
                 // Check if property value is in the list
-                match feature.properties.as_object().and_then(|obj| obj.get(key)) {
+                match legacy_filter_value(key, feature, &zoom_value) {
                     Some(actual_value) => {
-                        for i in 2..filter_array.len() {
-                            if &filter_array[i] == actual_value {
-                                return true;
-                            }
-                        }
-                        false
+                        filter_array[2..].iter().any(|value| value == actual_value)
                     }
                     None => {
                         // Check if null is in the list
-                        for i in 2..filter_array.len() {
-                            if filter_array[i].is_null() {
-                                return true;
-                            }
-                        }
-                        false
+                        filter_array[2..].iter().any(|value| value.is_null())
                     }
                 }
             }
@@ -386,34 +403,18 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
                     "MultiPolygon" => "MultiPolygon",
                     _ => "Unknown",
                 };
-                for i in 2..filter_array.len() {
-                    if filter_array[i]
-                        .as_str()
-                        .map_or(false, |v| v == geometry_type)
-                    {
-                        return false;
-                    }
-                }
-                true
+                !filter_array[2..]
+                    .iter()
+                    .any(|value| value.as_str() == Some(geometry_type))
             } else {
                 // Check if property value is NOT in the list
-                match feature.properties.as_object().and_then(|obj| obj.get(key)) {
+                match legacy_filter_value(key, feature, &zoom_value) {
                     Some(actual_value) => {
-                        for i in 2..filter_array.len() {
-                            if &filter_array[i] == actual_value {
-                                return false;
-                            }
-                        }
-                        true
+                        !filter_array[2..].iter().any(|value| value == actual_value)
                     }
                     None => {
                         // Check if null is NOT in the list
-                        for i in 2..filter_array.len() {
-                            if filter_array[i].is_null() {
-                                return false;
-                            }
-                        }
-                        true
+                        !filter_array[2..].iter().any(|value| value.is_null())
                     }
                 }
             }
@@ -439,7 +440,7 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
                 feature
                     .properties
                     .as_object()
-                    .map_or(false, |obj| obj.contains_key(key))
+                    .is_some_and(|obj| obj.contains_key(key))
             }
         }
         "!has" => {
@@ -451,16 +452,15 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
                 None => return true,
             };
 
-            if key == "$type" {
-                false // Geometry type always exists
-            } else if key == "$id" {
-                false // For now, assume features always have some form of id
+            if key == "$type" || key == "$id" {
+                // Geometry type always exists; for now, assume features always have some form of id
+                false
             } else {
                 // Check if property does NOT exist
                 feature
                     .properties
                     .as_object()
-                    .map_or(true, |obj| !obj.contains_key(key))
+                    .is_none_or(|obj| !obj.contains_key(key))
             }
         }
 
@@ -475,12 +475,12 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
             };
             let expected_value = &filter_array[2];
 
-            // Only compare properties (not $type or $id for ordering)
-            if key.starts_with('$') {
+            // Only compare properties and $zoom (not $type or $id for ordering)
+            if key.starts_with('$') && key != "$zoom" {
                 return true; // Skip comparison for special keys
             }
 
-            match feature.properties.as_object().and_then(|obj| obj.get(key)) {
+            match legacy_filter_value(key, feature, &zoom_value) {
                 Some(actual_value) => {
                     // Try to compare as numbers first, then as strings
                     if let (Some(actual_num), Some(expected_num)) =
@@ -519,6 +519,18 @@ fn evaluate_filter(filter: &serde_json::Value, feature: &Feature) -> bool {
     }
 }
 
+// Value a legacy filter compares for `key`: the tile zoom for "$zoom", else the property
+fn legacy_filter_value<'a>(
+    key: &str,
+    feature: &'a Feature,
+    zoom: &'a serde_json::Value,
+) -> Option<&'a serde_json::Value> {
+    if key == "$zoom" {
+        return Some(zoom);
+    }
+    feature.properties.as_object().and_then(|obj| obj.get(key))
+}
+
 // Convert tile-local coordinates to longitude/latitude
 fn convert_tile_coords_to_lnglat(
     px: f64,
@@ -541,6 +553,46 @@ fn convert_tile_coords_to_lnglat(
 pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsValue, JsValue> {
     // Parse input
     let input: ExtractFeaturesInput = from_value(input_js)?;
+    let input = ExtractMultiLayerInput {
+        bbox: input.bbox,
+        vt_data_sets: vec![input.vt_data_set],
+        process_id: input.process_id,
+        elevation_process_id: input.elevation_process_id,
+    };
+    match extract_layers(&input).await? {
+        // Return undefined since data is cached at process level
+        Some(_) => Ok(JsValue::undefined()),
+        None => Ok(to_value(&Vec::<GeometryData>::new())?),
+    }
+}
+
+/// Extract the features of several layers of the same bbox and process in one pass over
+/// the cached vector tiles: every tile is parsed once and its layers are shared by all
+/// `vtDataSets`. Each layer's features are cached exactly as
+/// `extract_features_from_vector_tiles` caches them. Returns the feature count of every
+/// layer, in the order of `vtDataSets`.
+#[wasm_bindgen]
+pub async fn extract_features_multi_layer(input_js: JsValue) -> Result<JsValue, JsValue> {
+    let input: ExtractMultiLayerInput = from_value(input_js)?;
+    let counts = extract_layers(&input)
+        .await?
+        .unwrap_or_else(|| vec![0; input.vt_data_sets.len()]);
+    Ok(to_value(&counts)?)
+}
+
+// Source of a layer's tiles: None for the default source
+pub(crate) fn dataset_source(vt_dataset: &VtDataSet) -> Option<&str> {
+    vt_dataset
+        .source
+        .as_deref()
+        .filter(|name| *name != DEFAULT_VECTOR_SOURCE)
+}
+
+// Extract and cache the features of every layer of `input`; returns the feature count of
+// each layer, or None when the process has no vector tiles cached
+pub(crate) async fn extract_layers(
+    input: &ExtractMultiLayerInput,
+) -> Result<Option<Vec<usize>>, JsValue> {
     let bbox = &input.bbox;
 
     if bbox.len() != 4 {
@@ -553,7 +605,6 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
     let min_lat = bbox[1];
     let max_lng = bbox[2];
     let max_lat = bbox[3];
-    let vt_dataset = &input.vt_data_set;
 
     // Starting feature extraction
 
@@ -564,8 +615,8 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
         Some(tiles) => tiles,
         None => {
             // No cached vector tiles found
-            // Return empty array instead of error, as fetching might happen separately
-            return Ok(to_value(&Vec::<GeometryData>::new())?);
+            // Not an error, as fetching might happen separately
+            return Ok(None);
         }
     };
 
@@ -586,7 +637,7 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
                 // Fallback: try to find any available elevation grid
                 ModuleState::with(|state| {
                     // Get all available elevation grids and use the first one
-                    for (_key, grid) in &state.elevation_grids {
+                    if let Some((_key, grid)) = state.elevation_grids.iter().next() {
                         // Using fallback elevation grid from different process
                         return Some(grid.clone());
                     }
@@ -605,7 +656,7 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
             } else {
                 // Fallback: try to find any available elevation grid
                 ModuleState::with(|state| {
-                    for (_key, grid) in &state.elevation_grids {
+                    if let Some((_key, grid)) = state.elevation_grids.iter().next() {
                         // Using fallback elevation grid from different process
                         return Some(grid.clone());
                     }
@@ -621,8 +672,12 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
                 // Found cached elevation grid
                 let grid_height = elev_grid.len();
                 let grid_width = if grid_height > 0 { elev_grid[0].len() } else { 0 };
-                web_sys::console::log_1(&format!("WASM: Found elevation grid {}x{} for layer processing",
-                    grid_width, grid_height).into());
+                console::record(
+                    LogLevel::Debug,
+                    "layer",
+                    Some(&input.process_id),
+                    format!("Using elevation grid {}x{} for feature extraction", grid_width, grid_height),
+                );
                 (
                     elev_grid,
                     (grid_width as u32, grid_height as u32),
@@ -640,18 +695,32 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
             }
         };
 
-    // Initialize result vector
-    let mut geometry_data_list: Vec<GeometryData> = Vec::new();
-    let mut feature_count = 0;
+    // Initialize one result vector per layer
+    let mut layer_results: Vec<Vec<GeometryData>> = vec![Vec::new(); input.vt_data_sets.len()];
 
     // Process each vector tile found in the cache for the bbox_key
     // To avoid E0502, collect parsed tiles to cache after iteration
     let mut parsed_tiles_to_cache: Vec<(String, ParsedMvtTile)> = Vec::new();
+    // Yield between tiles now and then so cancel_process can stop the extraction
+    let cancellation = ProcessCancellation::for_process(&input.process_id);
+    let mut yield_timer = YieldTimer::new();
     for vt_tile_data in vector_tiles_data {
+        yield_timer.tick().await;
+        cancellation.check().map_err(|e| job_error(&e))?;
         let tile_x = vt_tile_data.x;
         let tile_y = vt_tile_data.y;
         let tile_z = vt_tile_data.z;
 
+        // Only tiles of the layers' sources; named sources prefix their keys with "name:"
+        let tile_source = vt_tile_data.key.split_once(':').map(|(name, _)| name);
+        if !input
+            .vt_data_sets
+            .iter()
+            .any(|vt_dataset| dataset_source(vt_dataset) == tile_source)
+        {
+            continue;
+        }
+
         // Processing tile data
 
         // The raw MVT data should be stored in rust_parsed_mvt or buffer
@@ -668,14 +737,21 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
         }
 
         // Use cached parsed MVT tile if available, otherwise parse and cache it
-        let cache_key = format!("{}/{}/{}", tile_z, tile_x, tile_y);
+        let cache_key = vector_tile_cache_key(
+            &TileRequest {
+                x: tile_x,
+                y: tile_y,
+                z: tile_z,
+            },
+            tile_source,
+        );
         let parsed_tile = if let Some(cached) =
             ModuleState::with(|state| state.get_parsed_mvt_tile(&cache_key))
         {
             cached
         } else {
             match enhanced_parse_mvt_data(
-                &raw_mvt_data,
+                raw_mvt_data,
                 &TileRequest {
                     x: tile_x,
                     y: tile_y,
@@ -694,305 +770,166 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
             }
         };
 
-        // Find the requested layer in the newly parsed tile
-        let layer = match parsed_tile.layers.get(&vt_dataset.source_layer) {
-            Some(layer_data) => {
-                // Found layer data
-
-                // Count features by class for this tile
-                let mut class_counts: std::collections::HashMap<String, usize> =
-                    std::collections::HashMap::new();
-                for feature in &layer_data.features {
-                    let class_value = feature
-                        .properties
-                        .get("class")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("unknown");
-                    *class_counts.entry(class_value.to_string()).or_insert(0) += 1;
-                }
-
-                // Format the class counts for logging
-                let mut class_stats: Vec<String> = class_counts
-                    .iter()
-                    .map(|(class, count)| format!("{} ({})", class, count))
-                    .collect();
-                class_stats.sort(); // Sort alphabetically for consistent output
-
-                // Class statistics computed
-
-                layer_data
-            }
-            None => {
-                //    vt_dataset.source_layer, tile_z, tile_x, tile_y, parsed_tile.layers.keys());
-                continue; // Skip this tile if the layer isn't present
+        // Every layer of this tile's source reads the same parsed tile
+        for (vt_dataset, geometry_data_list) in
+            input.vt_data_sets.iter().zip(layer_results.iter_mut())
+        {
+            if dataset_source(vt_dataset) != tile_source {
+                continue;
             }
-        };
 
-        // Get extent for coordinate transformation (default to 4096 if not specified somehow in mvt crate result)
-        // Note: The `mvt` crate's `Layer` struct doesn't seem to expose extent directly after parsing.
-        // We have to rely on the default MVT extent.
-        let extent = 4096; // Standard MVT extent
-
-        // Statistics tracking for features per class
-        let mut class_stats: std::collections::HashMap<String, u32> =
-            std::collections::HashMap::new();
-
-        // First pass: collect statistics
-        for feature in &layer.features {
-            let class_value = feature
-                .properties
-                .get("class")
-                .and_then(|v| v.as_str())
-                .unwrap_or("unknown");
-            *class_stats.entry(class_value.to_string()).or_insert(0) += 1;
-        }
+            // Find the requested layer in the newly parsed tile
+            let layer = match parsed_tile.layers.get(&vt_dataset.source_layer) {
+                Some(layer_data) => {
+                    // Found layer data
+
+                    // Count features by class for this tile
+                    let mut class_counts: std::collections::HashMap<String, usize> =
+                        std::collections::HashMap::new();
+                    for feature in &layer_data.features {
+                        let class_value = feature
+                            .properties
+                            .get("class")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("unknown");
+                        *class_counts.entry(class_value.to_string()).or_insert(0) += 1;
+                    }
 
-        // Log statistics for this layer
-
-        for (_class, _count) in &class_stats {}
-
-        // Process each feature in the layer
-        let mut filtered_by_expression = 0;
-        let mut processed_features = 0;
-        let mut geometry_created = 0;
-        let mut geometry_filtered_by_bbox = 0;
-
-        for feature in &layer.features {
-            feature_count += 1;
-
-            // Apply filter expression if provided
-            if let Some(ref filter) = vt_dataset.filter {
-                // Convert MvtFeature to Feature for filter evaluation
-                let filterable_feature = Feature {
-                    geometry: FeatureGeometry {
-                        r#type: feature.geometry_type.clone(),
-                        coordinates: serde_json::to_value(&feature.geometry)
-                            .unwrap_or(serde_json::Value::Null),
-                    },
-                    properties: serde_json::to_value(&feature.properties)
-                        .unwrap_or(serde_json::Value::Object(serde_json::Map::new())),
-                };
+                    // Format the class counts for logging
+                    let mut class_stats: Vec<String> = class_counts
+                        .iter()
+                        .map(|(class, count)| format!("{} ({})", class, count))
+                        .collect();
+                    class_stats.sort(); // Sort alphabetically for consistent output
 
-                // Debug: specifically track primary and secondary features (removed individual logging)
-                // if let Some(class_value) = feature.properties.get("class") {
-                //     if let Some(class_str) = class_value.as_str() {
-                //         if class_str == "primary" || class_str == "secondary" {
-                //             let filter_result = evaluate_filter(filter, &filterable_feature);
-                //             if !filter_result {
-                //
-                //             }
-                //         }
-                //     }
-                // }
-
-                if !evaluate_filter(filter, &filterable_feature) {
-                    filtered_by_expression += 1;
-                    continue; // Skip features that don't pass the filter
-                }
-            }
-
-            processed_features += 1;
+                    // Class statistics computed
 
-            // --- Height Extraction ---
-            // Check hide_3d property first - skip buildings marked as hidden
-            if let Some(hide_3d) = feature.properties.get("hide_3d") {
-                if hide_3d.as_bool().unwrap_or(false) {
-                    continue; // Skip this building entirely
+                    layer_data
                 }
-            }
+                None => {
+                    //    vt_dataset.source_layer, tile_z, tile_x, tile_y, parsed_tile.layers.keys());
+                    continue; // Skip this tile if the layer isn't present
+                }
+            };
 
-            // Extract height, treating render_height=5 as "no height data"
-            let height = feature
-                .properties
-                .get("height")
-                .and_then(|v| v.as_f64())
-                .filter(|&h| h > 0.0) // Only use positive heights
-                .or_else(|| {
-                    // Check render_height - all positive values are valid
-                    feature
-                        .properties
-                        .get("render_height")
-                        .and_then(|v| v.as_f64())
-                        .filter(|&h| h > 0.0) // Accept all positive heights including 5.0
-                })
-                .or_else(|| {
-                    feature
-                        .properties
-                        .get("ele")
-                        .and_then(|v| v.as_f64())
-                        .filter(|&h| h > 0.0)
-                });
-
-            // Convert Option<f64> to the expected format for further processing
-            let height_value = height.unwrap_or(0.0);
-            // Debug: log extracted height for each feature
-            //
-
-            // --- Geometry Processing & Transformation ---
-            let geometry_type_str = feature.geometry_type.as_str();
-            let mut transformed_geometry_parts: Vec<GeometryData> = Vec::new();
-
-            match geometry_type_str {
-                "Polygon" => {
-                    // MVT type 3 covers BOTH single Polygons AND MultiPolygons.
-                    // feature.geometry: Vec<Vec<Vec<f64>>> — each element is a ring.
-                    // We use the shoelace formula (signed area) to determine winding order:
-                    //   - Positive area (CW in tile coords) = exterior ring
-                    //   - Negative area (CCW in tile coords) = hole ring
-                    // Each new exterior ring starts a new polygon; subsequent holes attach to it.
-                    
-                    let mut current_exterior: Option<Vec<Vec<f64>>> = None;
-                    let mut current_holes: Vec<Vec<Vec<f64>>> = Vec::new();
-                    
-                    for ring_tile_coords in &feature.geometry {
-                        let mut transformed_ring: Vec<Vec<f64>> =
-                            Vec::with_capacity(ring_tile_coords.len());
-                        for point_tile_coords in ring_tile_coords {
-                            if point_tile_coords.len() >= 2 {
-                                let (lng, lat) = convert_tile_coords_to_lnglat(
-                                    point_tile_coords[0],
-                                    point_tile_coords[1],
-                                    extent,
-                                    tile_x,
-                                    tile_y,
-                                    tile_z,
-                                );
-                                transformed_ring.push(vec![lng, lat]);
-                            }
-                        }
+            // Get extent for coordinate transformation (default to 4096 if not specified somehow in mvt crate result)
+            // Note: The `mvt` crate's `Layer` struct doesn't seem to expose extent directly after parsing.
+            // We have to rely on the default MVT extent.
+            let extent = 4096; // Standard MVT extent
 
-                        if transformed_ring.len() < 3 {
-                            continue;
-                        }
+            // Statistics tracking for features per class
+            let mut class_stats: std::collections::HashMap<String, u32> =
+                std::collections::HashMap::new();
 
-                        // Compute signed area using shoelace formula
-                        // In lat/lng space after conversion:
-                        //   positive = counter-clockwise = exterior (GeoJSON convention)
-                        //   negative = clockwise = hole
-                        // But MVT tile coords use a Y-down system which flips the sign,
-                        // so we check the sign on the already-transformed coords.
-                        let signed_area = {
-                            let mut area = 0.0_f64;
-                            let n = transformed_ring.len();
-                            for i in 0..n {
-                                let j = (i + 1) % n;
-                                area += transformed_ring[i][0] * transformed_ring[j][1];
-                                area -= transformed_ring[j][0] * transformed_ring[i][1];
-                            }
-                            area / 2.0
-                        };
+            // First pass: collect statistics
+            for feature in &layer.features {
+                let class_value = feature
+                    .properties
+                    .get("class")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown");
+                *class_stats.entry(class_value.to_string()).or_insert(0) += 1;
+            }
 
-                        // MVT exterior rings are CW in tile coords (Y-down).
-                        // After converting to lat/lng (Y-up), CW stays CW → negative shoelace area.
-                        // So: negative area = exterior ring, positive area = hole.
-                        let is_exterior = signed_area.abs() > 1e-20 && signed_area < 0.0;
-                        // If signed_area is ~0, it's degenerate — skip it
-                        if signed_area.abs() < 1e-20 {
-                            continue;
-                        }
+            // Log statistics for this layer
+
+            for _count in class_stats.values() {}
+
+            // Process each feature in the layer
+            for feature in &layer.features {
+                // Apply filter expression if provided
+                if let Some(ref filter) = vt_dataset.filter {
+                    // Convert MvtFeature to Feature for filter evaluation
+                    let filterable_feature = Feature {
+                        geometry: FeatureGeometry {
+                            r#type: feature.geometry_type.clone(),
+                            coordinates: serde_json::to_value(&feature.geometry)
+                                .unwrap_or(serde_json::Value::Null),
+                        },
+                        properties: serde_json::to_value(&feature.properties)
+                            .unwrap_or(serde_json::Value::Object(serde_json::Map::new())),
+                    };
 
-                        if is_exterior {
-                            // Save previous polygon if we have one
-                            if let Some(exterior) = current_exterior.take() {
-                                let holes = if current_holes.is_empty() {
-                                    None
-                                } else {
-                                    Some(std::mem::take(&mut current_holes))
-                                };
-                                
-                                transformed_geometry_parts.push(GeometryData {
-                                    geometry: exterior,
-                                    holes,
-                                    r#type: Some("Polygon".to_string()),
-                                    height: Some(height_value),
-                                    layer: Some(vt_dataset.source_layer.clone()),
-                                    label: vt_dataset.label.clone(),
-                                    tags: None,
-                                    properties: Some(
-                                        serde_json::to_value(&feature.properties)
-                                            .unwrap_or(serde_json::Value::Null),
-                                    ),
-        
-                                });
-                            }
-                            current_exterior = Some(transformed_ring);
-                        } else {
-                            // It's a hole — attach to current exterior
-                            current_holes.push(transformed_ring);
-                        }
+                    // Debug: specifically track primary and secondary features (removed individual logging)
+                    // if let Some(class_value) = feature.properties.get("class") {
+                    //     if let Some(class_str) = class_value.as_str() {
+                    //         if class_str == "primary" || class_str == "secondary" {
+                    //             let filter_result = evaluate_filter(filter, &filterable_feature);
+                    //             if !filter_result {
+                    //
+                    //             }
+                    //         }
+                    //     }
+                    // }
+
+                    if !evaluate_filter(filter, &filterable_feature, tile_z) {
+                        continue; // Skip features that don't pass the filter
                     }
-                    
-                    // Don't forget the last polygon
-                    if let Some(exterior) = current_exterior {
-                        let holes = if current_holes.is_empty() {
-                            None
-                        } else {
-                            Some(current_holes)
-                        };
-                        
-                        transformed_geometry_parts.push(GeometryData {
-                            geometry: exterior,
-                            holes,
-                            r#type: Some("Polygon".to_string()),
-                            height: Some(height_value),
-                            layer: Some(vt_dataset.source_layer.clone()),
-                            label: vt_dataset.label.clone(),
-                            tags: None,
-                            properties: Some(
-                                serde_json::to_value(&feature.properties)
-                                    .unwrap_or(serde_json::Value::Null),
-                            ),
-                        });
+                }
+
+                // --- Height Extraction ---
+                // Check hide_3d property first - skip buildings marked as hidden
+                if let Some(hide_3d) = feature.properties.get("hide_3d") {
+                    if hide_3d.as_bool().unwrap_or(false) {
+                        continue; // Skip this building entirely
                     }
                 }
-                "LineString" | "MultiLineString" => {
-                    // UNIFIED PROCESSING for both LineString and MultiLineString
-                    // feature.geometry structure: Vec<Vec<Vec<f64>>> where each Vec<Vec<f64>> represents a line
-                    // For LineString: contains 1 line
-                    // For MultiLineString: contains multiple lines
-
-                    let _class_value = feature
-                        .properties
-                        .get("class")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("unknown");
-
-                    for (_line_index, line_tile_coords) in feature.geometry.iter().enumerate() {
-                        let mut transformed_line: Vec<Vec<f64>> =
-                            Vec::with_capacity(line_tile_coords.len());
-
-                        // Transform each point in the line from tile coordinates to lat/lng
-                        for point_tile_coords in line_tile_coords {
-                            if point_tile_coords.len() >= 2 {
-                                let (lng, lat) = convert_tile_coords_to_lnglat(
-                                    point_tile_coords[0],
-                                    point_tile_coords[1],
-                                    extent,
-                                    tile_x,
-                                    tile_y,
-                                    tile_z,
-                                );
-                                transformed_line.push(vec![lng, lat]);
-                            }
-                        }
 
-                        // Only create geometry if we have a valid line with at least 2 points
-                        if transformed_line.len() >= 2 {
-                            let _base_elevation = calculate_base_elevation(
-                                &transformed_line,
-                                &elevation_grid,
-                                grid_size.0 as usize,
-                                grid_size.1 as usize,
-                                elev_min_lng,
-                                elev_min_lat,
-                                elev_max_lng,
-                                elev_max_lat,
-                            );
+                // Extract height, treating render_height=5 as "no height data"
+                let height = feature
+                    .properties
+                    .get("height")
+                    .and_then(|v| v.as_f64())
+                    .filter(|&h| h > 0.0) // Only use positive heights
+                    .or_else(|| {
+                        // Check render_height - all positive values are valid
+                        feature
+                            .properties
+                            .get("render_height")
+                            .and_then(|v| v.as_f64())
+                            .filter(|&h| h > 0.0) // Accept all positive heights including 5.0
+                    })
+                    .or_else(|| {
+                        feature
+                            .properties
+                            .get("ele")
+                            .and_then(|v| v.as_f64())
+                            .filter(|&h| h > 0.0)
+                    });
+
+                // Convert Option<f64> to the expected format for further processing
+                let height_value = height.unwrap_or(0.0);
+                // Debug: log extracted height for each feature
+                //
 
+                // --- Geometry Processing & Transformation ---
+                let geometry_type_str = feature.geometry_type.as_str();
+                let mut transformed_geometry_parts: Vec<GeometryData> = Vec::new();
+
+                match geometry_type_str {
+                    "Polygon" | "MultiPolygon" => {
+                        // MVT type 3 covers both Polygons and MultiPolygons. The decoder
+                        // orders the rings as exterior followed by its holes, wound per the
+                        // spec, so every exterior starts a polygon of its own.
+                        let transform_ring = |ring: &Vec<Vec<f64>>| -> Vec<Vec<f64>> {
+                            ring.iter()
+                                .filter(|point| point.len() >= 2)
+                                .map(|point| {
+                                    let (lng, lat) = convert_tile_coords_to_lnglat(
+                                        point[0], point[1], extent, tile_x, tile_y, tile_z,
+                                    );
+                                    vec![lng, lat]
+                                })
+                                .collect()
+                        };
+
+                        for (exterior, holes) in polygon_parts(&feature.geometry) {
+                            let holes: Vec<Vec<Vec<f64>>> =
+                                holes.into_iter().map(transform_ring).collect();
                             transformed_geometry_parts.push(GeometryData {
-                                geometry: transformed_line,
-                                holes: None,
-                                r#type: Some("LineString".to_string()),
+                                geometry: transform_ring(exterior),
+                                holes: if holes.is_empty() { None } else { Some(holes) },
+                                r#type: Some("Polygon".to_string()),
                                 height: Some(height_value),
                                 layer: Some(vt_dataset.source_layer.clone()),
                                 label: vt_dataset.label.clone(),
@@ -1002,29 +939,43 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
                                         .unwrap_or(serde_json::Value::Null),
                                 ),
                             });
-                        } else {
                         }
                     }
-                }
-                "Point" => {
-                    // feature.geometry structure: Vec<Vec<Vec<f64>>> where outer is points, inner should be single point [px, py]
-                    // Assuming single point per feature
-                    if let Some(point_group) = feature.geometry.get(0) {
-                        if let Some(point_tile_coords) = point_group.get(0) {
-                            if point_tile_coords.len() >= 2 {
-                                let (lng, lat) = convert_tile_coords_to_lnglat(
-                                    point_tile_coords[0],
-                                    point_tile_coords[1],
-                                    extent,
-                                    tile_x,
-                                    tile_y,
-                                    tile_z,
-                                );
-                                let transformed_point = vec![lng, lat];
-                                //
+                    "LineString" | "MultiLineString" => {
+                        // UNIFIED PROCESSING for both LineString and MultiLineString
+                        // feature.geometry structure: Vec<Vec<Vec<f64>>> where each Vec<Vec<f64>> represents a line
+                        // For LineString: contains 1 line
+                        // For MultiLineString: contains multiple lines
+
+                        let _class_value = feature
+                            .properties
+                            .get("class")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("unknown");
+
+                        for line_tile_coords in feature.geometry.iter() {
+                            let mut transformed_line: Vec<Vec<f64>> =
+                                Vec::with_capacity(line_tile_coords.len());
+
+                            // Transform each point in the line from tile coordinates to lat/lng
+                            for point_tile_coords in line_tile_coords {
+                                if point_tile_coords.len() >= 2 {
+                                    let (lng, lat) = convert_tile_coords_to_lnglat(
+                                        point_tile_coords[0],
+                                        point_tile_coords[1],
+                                        extent,
+                                        tile_x,
+                                        tile_y,
+                                        tile_z,
+                                    );
+                                    transformed_line.push(vec![lng, lat]);
+                                }
+                            }
 
+                            // Only create geometry if we have a valid line with at least 2 points
+                            if transformed_line.len() >= 2 {
                                 let _base_elevation = calculate_base_elevation(
-                                    &vec![transformed_point.clone()], // Pass as vec of points
+                                    &transformed_line,
                                     &elevation_grid,
                                     grid_size.0 as usize,
                                     grid_size.1 as usize,
@@ -1033,12 +984,11 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
                                     elev_max_lng,
                                     elev_max_lat,
                                 );
-                                //
 
                                 transformed_geometry_parts.push(GeometryData {
-                                    geometry: vec![transformed_point],
+                                    geometry: transformed_line,
                                     holes: None,
-                                    r#type: Some("Point".to_string()),
+                                    r#type: Some("LineString".to_string()),
                                     height: Some(height_value),
                                     layer: Some(vt_dataset.source_layer.clone()),
                                     label: vt_dataset.label.clone(),
@@ -1051,335 +1001,265 @@ pub async fn extract_features_from_vector_tiles(input_js: JsValue) -> Result<JsV
                             }
                         }
                     }
-                }
-                "MultiPolygon" => {
-                    // Handle MultiPolygon geometries with proper hole support
-                    // feature.geometry structure: Vec<Vec<Vec<f64>>> where outer Vec contains multiple polygon rings
-                    // In MVT format, rings are ordered: exterior rings (clockwise) followed by holes (counter-clockwise)
-                    // We group them into proper polygon structures with exterior + holes
-                    
-                    let mut current_exterior: Option<Vec<Vec<f64>>> = None;
-                    let mut current_holes: Vec<Vec<Vec<f64>>> = Vec::new();
-                    
-                    for ring_tile_coords in &feature.geometry {
-                        let mut transformed_ring: Vec<Vec<f64>> =
-                            Vec::with_capacity(ring_tile_coords.len());
-                        for point_tile_coords in ring_tile_coords {
-                            if point_tile_coords.len() >= 2 {
-                                let (lng, lat) = convert_tile_coords_to_lnglat(
-                                    point_tile_coords[0],
-                                    point_tile_coords[1],
-                                    extent,
-                                    tile_x,
-                                    tile_y,
-                                    tile_z,
-                                );
-                                transformed_ring.push(vec![lng, lat]);
-                            }
-                        }
-                        
-                        if transformed_ring.is_empty() {
-                            continue;
-                        }
-
-                        // Determine if this is an exterior ring (counter-clockwise) or hole (clockwise)
-                        // using the shoelace formula for signed area
-                        // GeoJSON/MVT spec: counter-clockwise = exterior, clockwise = hole
-                        let is_exterior = {
-                            let mut area = 0.0;
-                            for i in 0..transformed_ring.len() {
-                                let j = (i + 1) % transformed_ring.len();
-                                area += transformed_ring[i][0] * transformed_ring[j][1];
-                                area -= transformed_ring[j][0] * transformed_ring[i][1];
-                            }
-                            area < 0.0  // Negative area = counter-clockwise = exterior
-                        };
-
-                        if is_exterior {
-                            // Save previous polygon if it exists
-                            if let Some(exterior) = current_exterior.take() {
-                                let holes = if current_holes.is_empty() {
-                                    None
-                                } else {
-                                    Some(current_holes.clone())
-                                };
-                                
-                                transformed_geometry_parts.push(GeometryData {
-                                    geometry: exterior,
-                                    holes,
-                                    r#type: Some("Polygon".to_string()),
-                                    height: Some(height_value),
-                                    layer: Some(vt_dataset.source_layer.clone()),
-                                    label: vt_dataset.label.clone(),
-                                    tags: None,
-                                    properties: Some(
-                                        serde_json::to_value(&feature.properties)
-                                            .unwrap_or(serde_json::Value::Null),
-                                    ),
-                                });
-                                current_holes.clear();
+                    "Point" => {
+                        // feature.geometry structure: Vec<Vec<Vec<f64>>> where outer is points, inner should be single point [px, py]
+                        // Assuming single point per feature
+                        if let Some(point_group) = feature.geometry.first() {
+                            if let Some(point_tile_coords) = point_group.first() {
+                                if point_tile_coords.len() >= 2 {
+                                    let (lng, lat) = convert_tile_coords_to_lnglat(
+                                        point_tile_coords[0],
+                                        point_tile_coords[1],
+                                        extent,
+                                        tile_x,
+                                        tile_y,
+                                        tile_z,
+                                    );
+                                    let transformed_point = vec![lng, lat];
+                                    //
+
+                                    let _base_elevation = calculate_base_elevation(
+                                        std::slice::from_ref(&transformed_point), // Pass as vec of points
+                                        &elevation_grid,
+                                        grid_size.0 as usize,
+                                        grid_size.1 as usize,
+                                        elev_min_lng,
+                                        elev_min_lat,
+                                        elev_max_lng,
+                                        elev_max_lat,
+                                    );
+                                    //
+
+                                    transformed_geometry_parts.push(GeometryData {
+                                        geometry: vec![transformed_point],
+                                        holes: None,
+                                        r#type: Some("Point".to_string()),
+                                        height: Some(height_value),
+                                        layer: Some(vt_dataset.source_layer.clone()),
+                                        label: vt_dataset.label.clone(),
+                                        tags: None,
+                                        properties: Some(
+                                            serde_json::to_value(&feature.properties)
+                                                .unwrap_or(serde_json::Value::Null),
+                                        ),
+                                    });
+                                }
                             }
-                            
-                            // Start new polygon with this exterior ring
-                            current_exterior = Some(transformed_ring);
-                        } else {
-                            // This is a hole - add it to current polygon's holes
-                            current_holes.push(transformed_ring);
                         }
                     }
-                    
-                    // Don't forget the last polygon
-                    if let Some(exterior) = current_exterior {
-                        let holes = if current_holes.is_empty() {
-                            None
-                        } else {
-                            Some(current_holes)
-                        };
-                        
-                        transformed_geometry_parts.push(GeometryData {
-                            geometry: exterior,
-                            holes,
-                            r#type: Some("Polygon".to_string()),
-                            height: Some(height_value),
-                            layer: Some(vt_dataset.source_layer.clone()),
-                            label: vt_dataset.label.clone(),
-                            tags: None,
-                            properties: Some(
-                                serde_json::to_value(&feature.properties)
-                                    .unwrap_or(serde_json::Value::Null),
-                            ),
-                        });
+                    _ => {
+                        // Skip unhandled geometry types
                     }
                 }
-                _ => {
-                    // Skip unhandled geometry types
-                }
-            }
-
-            let pre_bbox_count = transformed_geometry_parts.len();
-            geometry_created += pre_bbox_count;
 
-            // Apply bbox filtering with buffer for LineStrings
-            let bbox_buffer = 0.001; // ~100m buffer for roads that cross boundaries
-            let filtered_parts: Vec<GeometryData> = transformed_geometry_parts
-                .into_iter()
-                .filter(|geom| {
-                    let is_line = geom.r#type.as_ref().map_or(false, |t| t == "LineString");
-                    let bbox_buffer = 0.001;
-
-                    let check_bbox = if is_line {
-                        [
-                            min_lng - bbox_buffer,
-                            min_lat - bbox_buffer,
-                            max_lng + bbox_buffer,
-                            max_lat + bbox_buffer,
-                        ]
-                    } else {
-                        [min_lng, min_lat, max_lng, max_lat]
-                    };
-
-                    crate::bbox_filter::polygon_intersects_bbox(&geom.geometry, &check_bbox)
-                })
-                .collect();
+                // Apply bbox filtering with buffer for LineStrings
+                let filtered_parts: Vec<GeometryData> = transformed_geometry_parts
+                    .into_iter()
+                    .filter(|geom| {
+                        let is_line = geom.r#type.as_ref().is_some_and(|t| t == "LineString");
+                        let bbox_buffer = 0.001; // ~100m buffer for roads that cross boundaries
+
+                        let check_bbox = if is_line {
+                            [
+                                min_lng - bbox_buffer,
+                                min_lat - bbox_buffer,
+                                max_lng + bbox_buffer,
+                                max_lat + bbox_buffer,
+                            ]
+                        } else {
+                            [min_lng, min_lat, max_lng, max_lat]
+                        };
 
-            let post_bbox_count = filtered_parts.len();
-            geometry_filtered_by_bbox += pre_bbox_count - post_bbox_count;
+                        crate::bbox_filter::polygon_intersects_bbox(
+                            &geom.geometry,
+                            geom.holes.as_deref().unwrap_or_default(),
+                            &check_bbox,
+                        )
+                    })
+                    .collect();
 
-            geometry_data_list.extend(filtered_parts);
+                geometry_data_list.extend(filtered_parts);
+            }
         }
 
         // Tile processing completed
     }
 
     // Feature extraction completed
+    ModuleState::with_mut(|state| {
+        for (key, parsed) in parsed_tiles_to_cache {
+            state.set_parsed_mvt_tile(&key, parsed);
+        }
+    });
 
-    // Apply median height fallback for buildings without height data if enabled
-    if vt_dataset.source_layer == "building" && vt_dataset.apply_median_height.unwrap_or(false) {
-        apply_median_height_fallback(&mut geometry_data_list);
-    }
-
-
+    let mut counts = Vec::with_capacity(layer_results.len());
+    for (vt_dataset, mut geometry_data_list) in input.vt_data_sets.iter().zip(layer_results) {
+        // Apply median height fallback for buildings without height data if enabled
+        if vt_dataset.source_layer == "building" && vt_dataset.apply_median_height.unwrap_or(false)
+        {
+            apply_median_height_fallback(&mut geometry_data_list);
+        }
 
-    // Cache the extracted feature data for later use
-    {
+        // Cache the extracted feature data for later use
         // Build process cache key using the VtDataSet configuration
-        let data_key = cache_keys::make_process_vtdataset_key(&input.process_id, &vt_dataset);
+        let data_key = cache_keys::make_process_vtdataset_key(&input.process_id, vt_dataset);
         let cached_value_str =
             serde_json::to_string(&geometry_data_list).map_err(|e| JsValue::from(e.to_string()))?;
         ModuleState::with_mut(|state| {
-            state.add_process_feature_data(&input.process_id, &data_key, cached_value_str.clone());
+            state.add_process_feature_data(&input.process_id, &data_key, cached_value_str);
         });
+        counts.push(geometry_data_list.len());
     }
-    // Return undefined since data is cached at process level
-    Ok(JsValue::undefined())
+    Ok(Some(counts))
 }
 
-// Make this function available to JS
-#[wasm_bindgen]
-pub async fn fetch_vector_tiles(input_js: JsValue) -> Result<JsValue, JsValue> {
-    // Parse input
-    let input: VectortileProcessingInput = from_value(input_js)?;
-
-
-    // Calculate tiles for the requested bounding box
-    let tiles = get_tiles_for_bbox(
-        input.min_lng,
-        input.min_lat,
-        input.max_lng,
-        input.max_lat,
-        input.zoom,
-    );
-
-    // Fetching vector tiles
-
-    // Store the fetch results for later processing
-    let mut tile_results = Vec::new();
-
-    for tile in tiles {
-        let tile_key = format!("{}/{}/{}", tile.z, tile.x, tile.y);
-
-        // Fetch the tile - no caching for now
-        let tile_data = {
-            // Construct the appropriate URL for vector tiles
-            // Using Mapbox Vector Tile format
-            let url = format!(
-                "https://wms.wheregroup.com/tileserver/tile/world-0-14/{}/{}/{}.pbf",
-                tile.z, tile.x, tile.y
-            );
-
-            // Fetch the tile if not cached
-            let fetch_promise = fetch(&url)?;
-            let fetch_result = JsFuture::from(fetch_promise).await?;
-
-            // Process fetch result
-
-            // Extract the raw data array - we need to access the "rawData" property
-            // since our JS helper function returns a TileFetchResponse object
-            let raw_data_value = js_sys::Reflect::get(&fetch_result, &JsValue::from_str("rawData"))
-                .map_err(|_e| JsValue::from_str("Failed to extract rawData from fetch result"))?;
-
-            // Verify we actually got a valid Uint8Array from the rawData property
-            if raw_data_value.is_undefined() || raw_data_value.is_null() {
-                return Err(JsValue::from_str("rawData property is undefined or null"));
-            }
-
-            // Convert to Uint8Array and then to Rust Vec
-            let data_array = Uint8Array::new(&raw_data_value);
-            let mut data_vec = data_array.to_vec();
-
-            // Data conversion completed
+// Source URL of a vector tile
+pub(crate) fn vector_tile_url(tile: &TileRequest) -> String {
+    if let Some(url) = configured_tile_url(TileSource::Vector.name(), tile.x, tile.y, tile.z) {
+        return url;
+    }
+    // Using Mapbox Vector Tile format
+    format!(
+        "https://wms.wheregroup.com/tileserver/tile/world-0-14/{}/{}/{}.pbf",
+        tile.z, tile.x, tile.y
+    )
+}
 
-            // Check if the data is gzipped and decompress if necessary
-            if data_vec.starts_with(&[0x1f, 0x8b]) {
-                // Gzip magic number
+// Cache key of a vector tile: "z/x/y" for the default source, "name:z/x/y" for a
+// named source
+pub(crate) fn vector_tile_cache_key(tile: &TileRequest, source: Option<&str>) -> String {
+    match source.filter(|name| *name != DEFAULT_VECTOR_SOURCE) {
+        Some(name) => format!("{}:{}/{}/{}", name, tile.z, tile.x, tile.y),
+        None => format!("{}/{}/{}", tile.z, tile.x, tile.y),
+    }
+}
 
-                let mut decoder = GzDecoder::new(&data_vec[..]);
-                let mut decompressed_data = Vec::new();
-                decoder
-                    .read_to_end(&mut decompressed_data)
-                    .map_err(|_e| JsValue::from_str("Decompression error"))?;
-                data_vec = decompressed_data;
-            }
+// Decompressed MVT data of a tile of the default (None) or a named source, served from
+// the parsed tile cache when possible. Fetched tiles are parsed once and cached for
+// feature extraction.
+pub(crate) async fn load_vector_tile(
+    tile: &TileRequest,
+    source: Option<&str>,
+) -> Result<Vec<u8>, JsValue> {
+    let source = source.filter(|name| *name != DEFAULT_VECTOR_SOURCE);
+    let tile_key = vector_tile_cache_key(tile, source);
+    let cached = ModuleState::with(|state| {
+        state.cache_usage.touch(CacheKind::ParsedMvt, &tile_key);
+        state
+            .mvt_parsed_tiles
+            .get(&tile_key)
+            .map(|parsed| parsed.raw_data.clone())
+    });
+    if let Some(data) = cached {
+        return Ok(data);
+    }
 
-            // Debug: Print first few bytes to check data format
-            // Data format checked
-            // Debug info processed
+    let source_key = vector_source_key(source);
+    // A loaded MBTiles archive replaces the network; tiles it lacks are empty
+    if let Some(data) = mbtiles::archive_tile(&source_key, tile) {
+        return cache_vector_tile_bytes(tile, source, data.unwrap_or_default());
+    }
+    let url = match source {
+        None => vector_tile_url(tile),
+        Some(name) => configured_tile_url(&source_key, tile.x, tile.y, tile.z).ok_or_else(|| {
+            JsValue::from_str(&format!("Unknown vector tile source '{}'", name))
+        })?,
+    };
+    let headers = source_headers(&source_key);
+    let fetch_result = network_fetch_with_headers(&url, &headers).await?;
+    record_validators(&url, &fetch_result);
+    provenance::record_retrieval(&url);
+    cache_vector_tile_response(tile, source, &fetch_result)
+}
 
-            // Parse the MVT data using our enhanced Rust MVT parser
-            let parsed_mvt = match enhanced_parse_mvt_data(&data_vec, &tile) {
-                Ok(parsed) => {
-                    // Cache the parsed MVTTile for later feature extraction
-                    ModuleState::with_mut(|state| {
-                        state.set_parsed_mvt_tile(&tile_key, parsed.clone());
-                    });
-                    // Successfully parsed MVT data
-                    // Log the number of layers found
-                    let _layer_count = parsed.layers.len();
-                    let _layer_names: Vec<String> = parsed.layers.keys().cloned().collect();
-
-                    // Convert Rust-parsed features to the legacy format for compatibility
-                    let mut legacy_layers = HashMap::new();
-                    for (layer_name, layer) in &parsed.layers {
-                        let mut features = Vec::new();
-                        for mvt_feature in &layer.features {
-                            let geometry_type = mvt_feature.geometry_type.clone();
-                            let coordinates = match geometry_type.as_str() {
-                                "Point" => {
-                                    if !mvt_feature.geometry.is_empty()
-                                        && !mvt_feature.geometry[0].is_empty()
-                                    {
-                                        serde_json::to_value(mvt_feature.geometry[0][0].clone())
-                                            .unwrap_or(serde_json::Value::Null)
-                                    } else {
-                                        serde_json::Value::Null
-                                    }
-                                }
-                                "LineString" => {
-                                    if !mvt_feature.geometry.is_empty() {
-                                        serde_json::to_value(mvt_feature.geometry[0].clone())
-                                            .unwrap_or(serde_json::Value::Null)
-                                    } else {
-                                        serde_json::Value::Null
-                                    }
-                                }
-                                "Polygon" => serde_json::to_value(mvt_feature.geometry.clone())
-                                    .unwrap_or(serde_json::Value::Null),
-                                _ => serde_json::Value::Null,
-                            };
+// Decompress and parse a fetch response and cache the parsed tile
+pub(crate) fn cache_vector_tile_response(
+    tile: &TileRequest,
+    source: Option<&str>,
+    fetch_result: &JsValue,
+) -> Result<Vec<u8>, JsValue> {
+    // Our JS helper returns a TileFetchResponse object with the bytes in "rawData"
+    let raw_data_value = js_sys::Reflect::get(fetch_result, &JsValue::from_str("rawData"))
+        .map_err(|_e| JsValue::from_str("Failed to extract rawData from fetch result"))?;
+    if raw_data_value.is_undefined() || raw_data_value.is_null() {
+        return Err(JsValue::from_str("rawData property is undefined or null"));
+    }
+    cache_vector_tile_bytes(tile, source, Uint8Array::new(&raw_data_value).to_vec())
+}
 
-                            features.push(Feature {
-                                geometry: FeatureGeometry {
-                                    r#type: geometry_type,
-                                    coordinates,
-                                },
-                                properties: serde_json::Value::Object(serde_json::Map::from_iter(
-                                    mvt_feature.properties.clone().into_iter(),
-                                )),
-                            });
-                        }
-                        legacy_layers.insert(layer_name.clone(), features);
-                    }
+// Decompress and parse raw tile bytes and cache the parsed tile
+fn cache_vector_tile_bytes(
+    tile: &TileRequest,
+    source: Option<&str>,
+    mut data_vec: Vec<u8>,
+) -> Result<Vec<u8>, JsValue> {
+    // Check if the data is gzipped and decompress if necessary
+    if data_vec.starts_with(&[0x1f, 0x8b]) {
+        let mut decoder = GzDecoder::new(&data_vec[..]);
+        let mut decompressed_data = Vec::new();
+        decoder
+            .read_to_end(&mut decompressed_data)
+            .map_err(|_e| JsValue::from_str("Decompression error"))?;
+        data_vec = decompressed_data;
+    }
 
-                    Some((parsed, legacy_layers))
-                }
-                Err(_e) => None,
-            };
+    // Cache the parsed MVT tile for later feature extraction
+    if let Ok(parsed) = enhanced_parse_mvt_data(&data_vec, tile) {
+        let tile_key = vector_tile_cache_key(tile, source);
+        ModuleState::with_mut(|state| {
+            state.set_parsed_mvt_tile(&tile_key, parsed);
+        });
+    }
 
-            // Processing raw data
+    Ok(data_vec)
+}
 
-            // Create a hex dump of a small sample of the raw data
-            let _hex_sample = if data_vec.len() > 32 {
-                format!("{:02X?}", &data_vec[0..32])
-            } else {
-                format!("{:02X?}", &data_vec)
-            };
+// Make this function available to JS
+#[wasm_bindgen]
+pub async fn fetch_vector_tiles(input_js: JsValue) -> Result<JsValue, JsValue> {
+    // Parse input
+    let input: VectortileProcessingInput = from_value(input_js)?;
 
-            // Create new tile data entry
-            let tile_data = TileData {
-                width: 256, // Default tile size
-                height: 256,
-                x: tile.x,
-                y: tile.y,
-                z: tile.z,
-                data: data_vec.clone(),
-                timestamp: Date::now(),
-                key: tile_key.clone(),
-                buffer: data_vec.clone(),
-                parsed_layers: parsed_mvt
-                    .as_ref()
-                    .map(|(_, legacy_layers)| legacy_layers.clone()), // Store legacy format for compatibility
-                rust_parsed_mvt: Some(data_vec.clone()), // Store the raw MVT data for Rust parsing
-            };
 
-            tile_data
-        };
+    // The default source unless named sources are requested
+    let sources: Vec<Option<&str>> = match &input.sources {
+        Some(names) if !names.is_empty() => names
+            .iter()
+            .map(|name| Some(name.as_str()).filter(|name| *name != DEFAULT_VECTOR_SOURCE))
+            .collect(),
+        _ => vec![None],
+    };
 
-        // Add to results
-        tile_results.push(VectorTileResult {
-            tile: tile.clone(),
-            data: tile_data.buffer,
-        });
+    // Store the fetch results for later processing
+    let mut tile_results = Vec::new();
+    let cancellation = ProcessCancellation::for_process(&input.process_id);
+
+    for source in sources {
+        // Calculate tiles for the requested bounding box, within the source's zoom range and bounds
+        let tiles = vector_source_tiles(
+            source,
+            &[input.min_lng, input.min_lat, input.max_lng, input.max_lat],
+            input.zoom,
+        )?;
+        for tile in tiles {
+            cancellation.check().map_err(|e| job_error(&e))?;
+            let data = load_vector_tile(&tile, source).await?;
+
+            // Add to results
+            tile_results.push(VectorTileResult {
+                tile,
+                data,
+                source: source.map(str::to_string),
+            });
+        }
     }
+    // Provenance covers the default source, whose attribution is known
+    let fetched_tiles: Vec<TileRequest> = tile_results
+        .iter()
+        .filter(|r| r.source.is_none())
+        .map(|r| r.tile.clone())
+        .collect();
+    provenance::record_process_tiles(&input.process_id, TileSource::Vector, &fetched_tiles);
 
     // Store tiles under the process ID for consistency
     // Storing vector tiles under process ID
@@ -1397,7 +1277,7 @@ pub async fn fetch_vector_tiles(input_js: JsValue) -> Result<JsValue, JsValue> {
                     z: vtr.tile.z,
                     data: vtr.data.clone(),
                     timestamp: Date::now(),
-                    key: format!("{}/{}/{}", vtr.tile.z, vtr.tile.x, vtr.tile.y),
+                    key: vector_tile_cache_key(&vtr.tile, vtr.source.as_deref()),
                     buffer: vtr.data.clone(),
                     parsed_layers: None,
                     rust_parsed_mvt: Some(vtr.data.clone()),
@@ -1440,7 +1320,7 @@ fn is_gzipped(data: &[u8]) -> bool {
 }
 
 // Function to decompress gzipped data
-fn decompress_gzip(data: &[u8]) -> Result<Vec<u8>, String> {
+pub(crate) fn decompress_gzip(data: &[u8]) -> Result<Vec<u8>, String> {
     if !is_gzipped(data) {
         return Ok(data.to_vec());
     }
@@ -1456,7 +1336,7 @@ fn decompress_gzip(data: &[u8]) -> Result<Vec<u8>, String> {
 }
 
 // Enhanced function to parse MVT data with proper geometry decoding
-fn enhanced_parse_mvt_data(
+pub(crate) fn enhanced_parse_mvt_data(
     tile_data: &[u8],
     tile_request: &TileRequest,
 ) -> Result<ParsedMvtTile, String> {
@@ -1477,8 +1357,7 @@ fn enhanced_parse_mvt_data(
     // Try to detect protobuf structure directly
     if tile_data.len() > 10 {
         //
-        for i in 0..10.min(tile_data.len()) {
-            let byte = tile_data[i];
+        for &byte in tile_data.iter().take(10) {
             let field_num = byte >> 3;
             let wire_type = byte & 0x7;
 
@@ -1598,15 +1477,15 @@ fn enhanced_parse_mvt_data(
                                     .map_or(serde_json::Value::Null, serde_json::Value::Number),
                                 Value {
                                     int_value: Some(i), ..
-                                } => serde_json::Value::Number(serde_json::Number::from(*i as i64)),
+                                } => serde_json::Value::Number(serde_json::Number::from(*i )),
                                 Value {
                                     uint_value: Some(u),
                                     ..
-                                } => serde_json::Value::Number(serde_json::Number::from(*u as u64)),
+                                } => serde_json::Value::Number(serde_json::Number::from(*u )),
                                 Value {
                                     sint_value: Some(s),
                                     ..
-                                } => serde_json::Value::Number(serde_json::Number::from(*s as i64)),
+                                } => serde_json::Value::Number(serde_json::Number::from(*s )),
                                 Value {
                                     bool_value: Some(b),
                                     ..
@@ -1770,16 +1649,117 @@ fn decode_mvt_geometry_to_tile_coords(commands: &[u32], geom_type_str: &str) ->
         result.push(current_part);
     }
 
-    // MVT Polygons require winding order checks and area calculation to distinguish outer/inner rings.
-    // This simplified decoder doesn't perform that; it returns all rings.
-    // A more robust implementation would calculate area and potentially reorder rings.
+    if geom_type_str == "Polygon" {
+        return group_polygon_rings(result);
+    }
 
     result // Return the structured tile coordinates
 }
 
+// Surveyor's formula in tile coordinates (Y down): positive for MVT exterior rings
+fn ring_signed_area(ring: &[Vec<f64>]) -> f64 {
+    let n = ring.len();
+    let mut area = 0.0;
+    for i in 0..n {
+        let j = (i + 1) % n;
+        area += ring[i][0] * ring[j][1] - ring[j][0] * ring[i][1];
+    }
+    area / 2.0
+}
+
+// Even-odd point in ring test
+pub(crate) fn point_in_ring(point: &[f64], ring: &[Vec<f64>]) -> bool {
+    let (x, y) = (point[0], point[1]);
+    let mut inside = false;
+    let mut j = ring.len() - 1;
+    for i in 0..ring.len() {
+        let (xi, yi) = (ring[i][0], ring[i][1]);
+        let (xj, yj) = (ring[j][0], ring[j][1]);
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+// Closed ring of decoded [x, y] points
+type Ring = Vec<Vec<f64>>;
+
+// Classify decoded polygon rings by winding and order them as polygons: each exterior
+// ring followed by its holes, exteriors wound with positive and holes with negative area.
+// Exterior rings are those wound like the first ring, which the spec requires to be an
+// exterior (v1 encoders sometimes invert every ring). A hole belongs to the smallest
+// exterior containing it, so islands in lakes and their own ponds nest correctly; holes
+// outside every exterior fall back to the preceding exterior, as the ring order implies.
+// Degenerate rings and holes before any exterior are dropped.
+fn group_polygon_rings(rings: Vec<Vec<Vec<f64>>>) -> Vec<Vec<Vec<f64>>> {
+    let classified: Vec<(Vec<Vec<f64>>, f64)> = rings
+        .into_iter()
+        .filter(|ring| ring.len() >= 4)
+        .map(|ring| {
+            let area = ring_signed_area(&ring);
+            (ring, area)
+        })
+        .filter(|(_, area)| *area != 0.0)
+        .collect();
+    let Some(exterior_sign) = classified.first().map(|(_, area)| area.signum()) else {
+        return Vec::new();
+    };
+
+    // Polygons as (exterior ring, |area|, holes)
+    let mut polygons: Vec<(Ring, f64, Vec<Ring>)> = Vec::new();
+    let mut holes: Vec<(Vec<Vec<f64>>, usize)> = Vec::new();
+    for (mut ring, area) in classified {
+        if area.signum() == exterior_sign {
+            if area < 0.0 {
+                ring.reverse();
+            }
+            polygons.push((ring, area.abs(), Vec::new()));
+        } else {
+            if area > 0.0 {
+                ring.reverse();
+            }
+            // Index of the preceding exterior; the first ring always is one
+            holes.push((ring, polygons.len() - 1));
+        }
+    }
+
+    for (hole, preceding) in holes {
+        let owner = polygons
+            .iter()
+            .enumerate()
+            .filter(|(_, (exterior, _, _))| point_in_ring(&hole[0], exterior))
+            .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+            .map_or(preceding, |(index, _)| index);
+        polygons[owner].2.push(hole);
+    }
+
+    polygons
+        .into_iter()
+        .flat_map(|(exterior, _, holes)| std::iter::once(exterior).chain(holes))
+        .collect()
+}
+
+// Split rings ordered by `group_polygon_rings` into (exterior, holes) polygons
+fn polygon_parts(rings: &[Ring]) -> Vec<(&Ring, Vec<&Ring>)> {
+    let mut parts: Vec<(&Ring, Vec<&Ring>)> = Vec::new();
+    for ring in rings {
+        if ring.len() < 4 {
+            continue;
+        }
+        if ring_signed_area(ring) > 0.0 {
+            parts.push((ring, Vec::new()));
+        } else if let Some((_, holes)) = parts.last_mut() {
+            holes.push(ring);
+        }
+    }
+    parts
+}
+
 /// Apply median height fallback for buildings without height data
 /// Uses the median of buildings with render_height > 5 and applies it to buildings with render_height < 6
-fn apply_median_height_fallback(geometry_list: &mut Vec<GeometryData>) {
+fn apply_median_height_fallback(geometry_list: &mut [GeometryData]) {
     // First pass: collect heights from buildings with render_height > 5
     let mut valid_heights: Vec<f64> = geometry_list
         .iter()
@@ -1798,7 +1778,7 @@ fn apply_median_height_fallback(geometry_list: &mut Vec<GeometryData>) {
     // Calculate median of all buildings with height > 5
     let median_height = if valid_heights.len() == 1 {
         valid_heights[0]
-    } else if valid_heights.len() % 2 == 0 {
+    } else if valid_heights.len().is_multiple_of(2) {
         // Even number of elements: average of middle two
         let mid = valid_heights.len() / 2;
         (valid_heights[mid - 1] + valid_heights[mid]) / 2.0
@@ -1826,3 +1806,378 @@ fn apply_median_height_fallback(geometry_list: &mut Vec<GeometryData>) {
         // Applied median height to buildings without height data
     }
 }
+
+/// Encoded MVT tile holding `layers` of the default (None) or a named source, as
+/// `fetch_vector_tiles` stores it
+#[cfg(test)]
+pub(crate) fn test_tile(
+    source: Option<&str>,
+    [z, x, y]: [u32; 3],
+    layers: Vec<geozero::mvt::tile::Layer>,
+) -> TileData {
+    let data = Tile { layers }.encode_to_vec();
+    TileData {
+        width: 0,
+        height: 0,
+        x,
+        y,
+        z,
+        data: Vec::new(),
+        timestamp: 0.0,
+        key: vector_tile_cache_key(&TileRequest { x, y, z }, source),
+        buffer: data.clone(),
+        parsed_layers: None,
+        rust_parsed_mvt: Some(data),
+    }
+}
+
+/// Cache an encoded MVT tile holding `layer` for `process_id`, as `fetch_vector_tiles` does
+#[cfg(test)]
+pub(crate) fn cache_test_tile(process_id: &str, tile: [u32; 3], layer: geozero::mvt::tile::Layer) {
+    let tile = test_tile(None, tile, vec![layer]);
+    ModuleState::with_mut(|state| state.store_process_vector_tiles(process_id, vec![tile]));
+}
+
+/// MVT geometry commands for line parts, or a point as a part of one vertex
+#[cfg(test)]
+pub(crate) fn line_commands(parts: &[&[[i32; 2]]]) -> Vec<u32> {
+    let zigzag = |v: i32| ((v << 1) ^ (v >> 31)) as u32;
+    let mut cursor = [0, 0];
+    let mut commands = Vec::new();
+    for part in parts {
+        for (i, point) in part.iter().enumerate() {
+            match i {
+                0 => commands.push(1 | (1 << 3)),
+                1 => commands.push(2 | ((part.len() as u32 - 1) << 3)),
+                _ => {}
+            }
+            commands.extend([zigzag(point[0] - cursor[0]), zigzag(point[1] - cursor[1])]);
+            cursor = *point;
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f64, y: f64, size: f64, exterior: bool) -> Vec<Vec<f64>> {
+        // Clockwise on screen (positive area with Y down) for exteriors
+        let mut ring = vec![
+            vec![x, y],
+            vec![x + size, y],
+            vec![x + size, y + size],
+            vec![x, y + size],
+            vec![x, y],
+        ];
+        if !exterior {
+            ring.reverse();
+        }
+        ring
+    }
+
+    #[test]
+    fn test_group_polygon_rings_nests_islands_in_lakes() {
+        // Land with a lake, an island in the lake with a pond, listed with the pond
+        // directly after the outer land ring
+        let land = square(0.0, 0.0, 100.0, true);
+        let lake = square(10.0, 10.0, 80.0, false);
+        let island = square(30.0, 30.0, 40.0, true);
+        let pond = square(45.0, 45.0, 10.0, false);
+        let grouped = group_polygon_rings(vec![
+            land.clone(),
+            pond.clone(),
+            lake.clone(),
+            island.clone(),
+        ]);
+
+        let parts = polygon_parts(&grouped);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0, &land);
+        assert_eq!(parts[0].1, vec![&lake]);
+        assert_eq!(parts[1].0, &island);
+        assert_eq!(parts[1].1, vec![&pond]);
+    }
+
+    #[test]
+    fn test_group_polygon_rings_fixes_inverted_winding() {
+        // A v1-style tile with every ring wound the other way, plus a degenerate ring
+        let outer = square(0.0, 0.0, 50.0, false);
+        let hole = square(10.0, 10.0, 10.0, true);
+        let degenerate = vec![vec![5.0, 5.0], vec![6.0, 6.0], vec![5.0, 5.0]];
+        let grouped = group_polygon_rings(vec![outer, hole, degenerate]);
+
+        assert_eq!(grouped.len(), 2);
+        assert!(ring_signed_area(&grouped[0]) > 0.0);
+        assert!(ring_signed_area(&grouped[1]) < 0.0);
+        assert_eq!(polygon_parts(&grouped)[0].1.len(), 1);
+
+        // Decoding a plain square: MoveTo(0,0) LineTo(10,0),(0,10),(-10,0) ClosePath
+        let commands = [9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15];
+        let decoded = decode_mvt_geometry_to_tile_coords(&commands, "Polygon");
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].len(), 5);
+        assert!(ring_signed_area(&decoded[0]) > 0.0);
+    }
+
+    #[test]
+    fn test_filters_see_the_tile_zoom() {
+        let feature = Feature {
+            geometry: FeatureGeometry {
+                r#type: "LineString".to_string(),
+                coordinates: serde_json::Value::Null,
+            },
+            properties: serde_json::json!({ "class": "path", "rank": 3 }),
+        };
+        let legacy = serde_json::json!(["all", ["==", "class", "path"], [">=", "$zoom", 14]]);
+        assert!(evaluate_filter(&legacy, &feature, 15));
+        assert!(!evaluate_filter(&legacy, &feature, 13));
+
+        let expression = serde_json::json!([
+            "all",
+            ["==", ["get", "class"], "path"],
+            [">=", ["zoom"], 14]
+        ]);
+        assert!(evaluate_filter(&expression, &feature, 14));
+        assert!(!evaluate_filter(&expression, &feature, 12));
+    }
+
+    #[test]
+    fn test_extract_layers_keeps_lines_and_points() {
+        use crate::elevation::{tile_x_to_lng, tile_y_to_lat};
+        use geozero::mvt::tile::{Feature as MvtFeature, GeomType, Layer};
+
+        let process_id = "extract-lines-test";
+        let (z, x, y) = (14, 8529, 5974);
+        let feature = |id: u64, r#type: GeomType, geometry: Vec<u32>| MvtFeature {
+            id: Some(id),
+            tags: vec![0, 0],
+            r#type: Some(r#type as i32),
+            geometry,
+        };
+        cache_test_tile(
+            process_id,
+            [z, x, y],
+            Layer {
+                version: 2,
+                name: "transportation".to_string(),
+                features: vec![
+                    feature(1, GeomType::Linestring, line_commands(&[&[[100, 100], [2000, 2000]]])),
+                    // Two parts of one MultiLineString
+                    feature(
+                        2,
+                        GeomType::Linestring,
+                        line_commands(&[
+                            &[[500, 3000], [3500, 3000]],
+                            &[[500, 3500], [2000, 3500], [3500, 3600]],
+                        ]),
+                    ),
+                    feature(3, GeomType::Point, line_commands(&[&[[2048, 2048]]])),
+                ],
+                keys: vec!["class".to_string()],
+                values: vec![Value {
+                    string_value: Some("primary".to_string()),
+                    ..Default::default()
+                }],
+                extent: Some(4096),
+            },
+        );
+
+        let layer: VtDataSet =
+            serde_json::from_value(serde_json::json!({ "sourceLayer": "transportation" }))
+                .unwrap();
+        let input = ExtractMultiLayerInput {
+            bbox: vec![
+                tile_x_to_lng(x, z),
+                tile_y_to_lat(y + 1, z),
+                tile_x_to_lng(x + 1, z),
+                tile_y_to_lat(y, z),
+            ],
+            vt_data_sets: vec![layer.clone()],
+            process_id: process_id.to_string(),
+            elevation_process_id: None,
+        };
+        let counts = futures::executor::block_on(extract_layers(&input)).ok().flatten();
+        assert_eq!(counts, Some(vec![4]));
+
+        let key = cache_keys::make_process_vtdataset_key(process_id, &layer);
+        let features: Vec<GeometryData> = ModuleState::with_mut(|state| {
+            let json = state.process_feature_data[process_id][&key].clone();
+            state.clear_process_data(process_id);
+            serde_json::from_str(&json).unwrap()
+        });
+        let shapes: Vec<(&str, usize)> = features
+            .iter()
+            .map(|f| (f.r#type.as_deref().unwrap_or_default(), f.geometry.len()))
+            .collect();
+        assert_eq!(
+            shapes,
+            vec![("LineString", 2), ("LineString", 2), ("LineString", 3), ("Point", 1)]
+        );
+        // The point sits in the middle of the tile
+        let [lng, lat] = [features[3].geometry[0][0], features[3].geometry[0][1]];
+        assert!((lng - (input.bbox[0] + input.bbox[2]) / 2.0).abs() < 1e-6);
+        assert!(lat > input.bbox[1] && lat < input.bbox[3]);
+    }
+
+    #[test]
+    fn test_layers_read_tiles_of_their_source() {
+        use crate::elevation::{tile_x_to_lng, tile_y_to_lat};
+        use geozero::mvt::tile::{Feature as MvtFeature, GeomType, Layer};
+
+        let process_id = "extract-sources-test";
+        let (z, x, y) = (14, 8531, 5974);
+        let poi_layer = |points: &[[i32; 2]]| Layer {
+            version: 2,
+            name: "poi".to_string(),
+            features: points
+                .iter()
+                .enumerate()
+                .map(|(i, point)| MvtFeature {
+                    id: Some(i as u64 + 1),
+                    tags: Vec::new(),
+                    r#type: Some(GeomType::Point as i32),
+                    geometry: line_commands(&[&[*point]]),
+                })
+                .collect(),
+            keys: Vec::new(),
+            values: Vec::new(),
+            extent: Some(4096),
+        };
+        // The same tile from the default source and from a named overlay source
+        let tiles = vec![
+            test_tile(None, [z, x, y], vec![poi_layer(&[[1000, 1000]])]),
+            test_tile(
+                Some("overlay"),
+                [z, x, y],
+                vec![poi_layer(&[[2000, 2000], [3000, 3000]])],
+            ),
+        ];
+        assert_eq!(tiles[0].key, format!("{}/{}/{}", z, x, y));
+        assert_eq!(tiles[1].key, format!("overlay:{}/{}/{}", z, x, y));
+        ModuleState::with_mut(|state| state.store_process_vector_tiles(process_id, tiles));
+
+        let layer = |source: Option<&str>| -> VtDataSet {
+            serde_json::from_value(serde_json::json!({ "sourceLayer": "poi", "source": source }))
+                .unwrap()
+        };
+        let layers = vec![layer(None), layer(Some("overlay")), layer(Some("default"))];
+        let input = ExtractMultiLayerInput {
+            bbox: vec![
+                tile_x_to_lng(x, z),
+                tile_y_to_lat(y + 1, z),
+                tile_x_to_lng(x + 1, z),
+                tile_y_to_lat(y, z),
+            ],
+            vt_data_sets: layers.clone(),
+            process_id: process_id.to_string(),
+            elevation_process_id: None,
+        };
+        let counts = futures::executor::block_on(extract_layers(&input)).ok().flatten();
+        // "default" names the default source explicitly
+        assert_eq!(counts, Some(vec![1, 2, 1]));
+
+        // Layers with the same filter from different sources are cached apart
+        let keys: Vec<String> = layers
+            .iter()
+            .map(|layer| cache_keys::make_process_vtdataset_key(process_id, layer))
+            .collect();
+        assert_ne!(keys[0], keys[1]);
+        assert_eq!(keys[0], keys[2]);
+        ModuleState::with_mut(|state| state.clear_process_data(process_id));
+    }
+
+    #[test]
+    fn test_extract_layers_fills_every_layer_in_one_pass() {
+        use crate::elevation::{tile_x_to_lng, tile_y_to_lat};
+        use geozero::mvt::tile::{Feature as MvtFeature, GeomType, Layer};
+
+        let process_id = "extract-multi-layer-test";
+        let (z, x, y) = (14, 8533, 5974);
+        let string = |text: &str| Value {
+            string_value: Some(text.to_string()),
+            ..Default::default()
+        };
+        let mut footprint =
+            line_commands(&[&[[1000, 1000], [2000, 1000], [2000, 2000], [1000, 2000]]]);
+        footprint.push(7 | (1 << 3)); // ClosePath
+        let buildings = Layer {
+            version: 2,
+            name: "building".to_string(),
+            features: vec![MvtFeature {
+                id: Some(1),
+                tags: Vec::new(),
+                r#type: Some(GeomType::Polygon as i32),
+                geometry: footprint,
+            }],
+            keys: Vec::new(),
+            values: Vec::new(),
+            extent: Some(4096),
+        };
+        let roads = Layer {
+            version: 2,
+            name: "transportation".to_string(),
+            features: [(2, 0), (3, 1)]
+                .into_iter()
+                .map(|(id, class)| MvtFeature {
+                    id: Some(id),
+                    tags: vec![0, class],
+                    r#type: Some(GeomType::Linestring as i32),
+                    geometry: line_commands(&[&[[100, 500 * id as i32], [4000, 500 * id as i32]]]),
+                })
+                .collect(),
+            keys: vec!["class".to_string()],
+            values: vec![string("primary"), string("path")],
+            extent: Some(4096),
+        };
+        let tile = test_tile(None, [z, x, y], vec![buildings, roads]);
+        ModuleState::with_mut(|state| state.store_process_vector_tiles(process_id, vec![tile]));
+
+        let layers: Vec<VtDataSet> = [
+            serde_json::json!({ "sourceLayer": "building" }),
+            serde_json::json!({ "sourceLayer": "transportation" }),
+            serde_json::json!({
+                "sourceLayer": "transportation",
+                "label": "Primary roads",
+                "filter": ["==", "class", "primary"]
+            }),
+            serde_json::json!({ "sourceLayer": "water" }),
+        ]
+        .into_iter()
+        .map(|layer| serde_json::from_value(layer).unwrap())
+        .collect();
+        let input = ExtractMultiLayerInput {
+            bbox: vec![
+                tile_x_to_lng(x, z),
+                tile_y_to_lat(y + 1, z),
+                tile_x_to_lng(x + 1, z),
+                tile_y_to_lat(y, z),
+            ],
+            vt_data_sets: layers.clone(),
+            process_id: process_id.to_string(),
+            elevation_process_id: None,
+        };
+        let counts = futures::executor::block_on(extract_layers(&input)).ok().flatten();
+        assert_eq!(counts, Some(vec![1, 2, 1, 0]));
+
+        // Every layer's features are cached under its own key, the tile is parsed once
+        let cached: Vec<Option<usize>> = ModuleState::with_mut(|state| {
+            let cached = layers
+                .iter()
+                .map(|layer| {
+                    let key = cache_keys::make_process_vtdataset_key(process_id, layer);
+                    state.process_feature_data[process_id].get(&key).map(|json| {
+                        serde_json::from_str::<Vec<GeometryData>>(json).unwrap().len()
+                    })
+                })
+                .collect();
+            state.clear_process_data(process_id);
+            cached
+        });
+        assert_eq!(cached, [Some(1), Some(2), Some(1), Some(0)]);
+        let tile_key = format!("{}/{}/{}", z, x, y);
+        let parsed = ModuleState::with(|state| state.get_parsed_mvt_tile(&tile_key));
+        assert_eq!(parsed.map(|tile| tile.layers.len()), Some(2));
+    }
+}
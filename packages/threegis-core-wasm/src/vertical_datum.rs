@@ -0,0 +1,155 @@
+// Vertical datum shared by terrain generation (CPU and GPU) and layer geometry.
+// Owns the mapping from real-world elevation/heights to mesh Z so every consumer
+// places things on exactly the same surface.
+use crate::projection::terrain_size;
+
+// Scale factor to make vertical exaggeration values more visible
+// User value of 1 will result in ~5 units of max elevation variation. CPU and GPU terrain
+// both use it; the GPU shader used to multiply by 15, giving 3x taller GPU relief.
+pub(crate) const EXAGGERATION_SCALE_FACTOR: f64 = 5.0;
+// Terrain top never drops below this Z so the base stays printable
+pub(crate) const MIN_TERRAIN_THICKNESS: f64 = 0.3;
+
+/// Fixed meters→units factor for non-building extrusions (terrain size / 1000 m), so
+/// roads and land use keep the same visual height regardless of the map extent
+pub(crate) fn fixed_meters_to_units() -> f64 {
+    terrain_size() / 1000.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerticalDatum {
+    pub terrain_base_height: f64,
+    pub vertical_exaggeration: f64,
+    pub min_elevation: f64,
+    pub max_elevation: f64,
+    /// Real-world model scale 1:N of the terrain (see `TerrainScale::model_scale`)
+    pub model_scale: Option<f64>,
+}
+
+impl VerticalDatum {
+    pub fn new(
+        terrain_base_height: f64,
+        vertical_exaggeration: f64,
+        min_elevation: f64,
+        max_elevation: f64,
+        model_scale: Option<f64>,
+    ) -> Self {
+        Self {
+            terrain_base_height,
+            vertical_exaggeration,
+            min_elevation,
+            max_elevation,
+            model_scale,
+        }
+    }
+
+    /// Elevation span in meters, at least 1m to keep flat areas well-defined
+    pub fn elevation_range(&self) -> f64 {
+        f64::max(1.0, self.max_elevation - self.min_elevation)
+    }
+
+    /// Mesh units between the lowest and highest point of the terrain surface. At a
+    /// real-world model scale this is the true relief in millimeters times the
+    /// exaggeration, otherwise a fixed height per unit of exaggeration.
+    pub fn relief_height(&self) -> f64 {
+        match self.model_scale {
+            Some(scale) => self.vertical_exaggeration * self.elevation_range() * 1000.0 / scale,
+            None => self.vertical_exaggeration * EXAGGERATION_SCALE_FACTOR,
+        }
+    }
+
+    /// Elevation in meters normalized to [0, 1] over the dataset range
+    pub fn normalize_elevation(&self, elevation: f64) -> f64 {
+        ((elevation - self.min_elevation) / self.elevation_range()).clamp(0.0, 1.0)
+    }
+
+    /// Terrain surface Z for an elevation in meters
+    pub fn elevation_to_z(&self, elevation: f64) -> f64 {
+        let z =
+            self.terrain_base_height + self.normalize_elevation(elevation) * self.relief_height();
+        z.max(MIN_TERRAIN_THICKNESS)
+    }
+
+    /// Inverse of `elevation_to_z` normalized to [0, 1]; used for height-based coloring
+    pub fn z_to_normalized(&self, z: f64) -> f64 {
+        ((z - self.terrain_base_height) / self.relief_height().max(1e-6)).clamp(0.0, 1.0)
+    }
+
+    /// Mesh units per meter of elevation difference, matching the terrain relief
+    pub fn units_per_elevation_meter(&self) -> f64 {
+        self.relief_height() / self.elevation_range()
+    }
+}
+
+/// Horizontal terrain units per real-world meter for a [minLng, minLat, maxLng, maxLat] bbox
+pub(crate) fn meters_to_terrain_units(bbox: &[f64]) -> f64 {
+    terrain_size() / bbox_size_meters(bbox)
+}
+
+/// Real-world side length in meters of a [minLng, minLat, maxLng, maxLat] bbox, the
+/// average of its width and height
+pub(crate) fn bbox_size_meters(bbox: &[f64]) -> f64 {
+    // Calculate the real-world dimensions of the bbox in meters
+    let lat_center = (bbox[1] + bbox[3]) / 2.0;
+    let lat_rad = lat_center.to_radians();
+
+    // Earth's radius in meters
+    const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+    // Calculate width and height in meters
+    let lng_diff = bbox[2] - bbox[0];
+    let lat_diff = bbox[3] - bbox[1];
+
+    let width_m = lng_diff.to_radians() * EARTH_RADIUS_M * lat_rad.cos();
+    let height_m = lat_diff.to_radians() * EARTH_RADIUS_M;
+
+    // Use average dimension for consistent scaling
+    (width_m + height_m) / 2.0
+}
+
+/// Bilinear sample of a row-major elevation grid at normalized (x, y) in [0, 1]
+pub(crate) fn sample_grid_bilinear(grid: &[Vec<f64>], normalized_x: f64, normalized_y: f64) -> f64 {
+    let height = grid.len();
+    let width = grid.first().map_or(0, |row| row.len());
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+
+    let src_x = normalized_x.clamp(0.0, 1.0) * (width - 1) as f64;
+    let src_y = normalized_y.clamp(0.0, 1.0) * (height - 1) as f64;
+
+    let x0 = src_x.floor() as usize;
+    let y0 = src_y.floor() as usize;
+    let x1 = (x0 + 1).min(width - 1);
+    let y1 = (y0 + 1).min(height - 1);
+
+    let dx = src_x - x0 as f64;
+    let dy = src_y - y0 as f64;
+
+    let v00 = grid[y0][x0];
+    let v10 = grid[y0][x1];
+    let v01 = grid[y1][x0];
+    let v11 = grid[y1][x1];
+
+    let v0 = v00 * (1.0 - dx) + v10 * dx;
+    let v1 = v01 * (1.0 - dx) + v11 * dx;
+
+    v0 * (1.0 - dy) + v1 * dy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datum_round_trip_and_thickness() {
+        let datum = VerticalDatum::new(1.0, 2.0, 100.0, 300.0, None);
+        assert_eq!(datum.relief_height(), 10.0);
+        assert_eq!(datum.elevation_to_z(300.0), 11.0);
+        assert_eq!(datum.z_to_normalized(datum.elevation_to_z(200.0)), 0.5);
+        assert_eq!(datum.units_per_elevation_meter(), 0.05);
+
+        let thin = VerticalDatum::new(0.0, 1.0, 0.0, 100.0, None);
+        assert_eq!(thin.elevation_to_z(0.0), MIN_TERRAIN_THICKNESS);
+    }
+}
@@ -0,0 +1,133 @@
+// Water surfaces: polygons of a water layer become flat slabs whose top sits slightly
+// below the shoreline, instead of following the terrain. `engrave_terrain` carves each
+// slab's footprint out of the terrain from the slab bottom upwards, so the slab fills a
+// pocket with clean vertical shorelines and prints as its own part.
+use serde::{Deserialize, Serialize};
+
+use crate::polygon_geometry::BufferGeometry;
+use crate::vertical_datum::{fixed_meters_to_units, VerticalDatum};
+
+/// Water surface below the lowest shoreline point, in meters
+const DEFAULT_RECESS: f64 = 0.5;
+/// Slab thickness below the water surface, in meters
+const DEFAULT_THICKNESS: f64 = 1.0;
+
+/// Flat water surface mode of a layer; lengths in meters, scaled like other non-building
+/// extrusions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WaterSurface {
+    /// Elevation of the water surface; the lowest point of each shoreline when omitted
+    #[serde(default)]
+    pub elevation: Option<f64>,
+    /// Depth of the water surface below the shoreline (default 0.5)
+    #[serde(default)]
+    pub recess: Option<f64>,
+    /// Slab thickness; slabs always reach down to the lowest terrain under them
+    /// (default 1)
+    #[serde(default)]
+    pub thickness: Option<f64>,
+}
+
+fn length_or(value: Option<f64>, default: f64) -> f64 {
+    value
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .unwrap_or(default)
+}
+
+impl WaterSurface {
+    /// Z of the water surface over a shoreline with terrain heights `shoreline`
+    pub(crate) fn surface_z(
+        &self,
+        datum: &VerticalDatum,
+        shoreline: impl IntoIterator<Item = f64>,
+    ) -> Option<f64> {
+        let level = match self.elevation.filter(|e| e.is_finite()) {
+            Some(elevation) => datum.elevation_to_z(elevation),
+            None => shoreline
+                .into_iter()
+                .filter(|z| z.is_finite())
+                .reduce(f64::min)?,
+        };
+        Some(level - length_or(self.recess, DEFAULT_RECESS) * fixed_meters_to_units())
+    }
+
+    /// Bottom Z and height of the slab under a surface at `surface_z`, reaching below the
+    /// lowest terrain point under it by `submerge`
+    pub(crate) fn slab(&self, surface_z: f64, lowest_terrain_z: f64, submerge: f64) -> (f64, f64) {
+        let thickness = length_or(self.thickness, DEFAULT_THICKNESS) * fixed_meters_to_units();
+        let bottom = (surface_z - thickness).min(lowest_terrain_z - submerge);
+        (bottom, surface_z - bottom)
+    }
+}
+
+/// Whether a geometry is a water slab (tagged by `tag_layer_metadata`)
+pub(crate) fn is_water_slab(geometry: &BufferGeometry) -> bool {
+    geometry.has_data
+        && geometry
+            .properties
+            .as_ref()
+            .and_then(|props| props.get("__water"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+}
+
+/// Cutter for the pocket of a water slab: the slab with its top raised to `top`
+pub(crate) fn water_cutter(slab: &BufferGeometry, top: f32) -> BufferGeometry {
+    let surface = slab
+        .vertices
+        .chunks_exact(3)
+        .map(|v| v[2])
+        .fold(f32::MIN, f32::max);
+    let mut cutter = slab.clone();
+    for z in cutter.vertices.iter_mut().skip(2).step_by(3) {
+        if *z >= surface - 1e-5 {
+            *z = top.max(surface);
+        }
+    }
+    cutter.normals = None;
+    cutter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_surface_below_lowest_shoreline() {
+        let datum = VerticalDatum::new(5.0, 1.0, 0.0, 100.0, None);
+        let water = WaterSurface::default();
+        let recess = DEFAULT_RECESS * fixed_meters_to_units();
+        let surface = water.surface_z(&datum, [7.0, 6.5, 8.0]).unwrap();
+        assert!((surface - (6.5 - recess)).abs() < 1e-9);
+        assert_eq!(water.surface_z(&datum, []), None);
+
+        // A fixed elevation ignores the shoreline
+        let fixed = WaterSurface {
+            elevation: Some(50.0),
+            ..WaterSurface::default()
+        };
+        let surface = fixed.surface_z(&datum, [7.0]).unwrap();
+        assert!((surface - (datum.elevation_to_z(50.0) - recess)).abs() < 1e-9);
+
+        // The slab reaches below terrain dipping under its nominal bottom
+        let (bottom, height) = water.slab(6.0, 5.0, 0.1);
+        assert!((bottom - 4.9).abs() < 1e-9 && (height - 1.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cutter_raises_slab_top() {
+        let slab = BufferGeometry {
+            vertices: vec![0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 2.0, 1.0, 0.0, 2.0],
+            normals: Some(vec![0.0; 12]),
+            colors: None,
+            indices: Some(vec![0, 1, 2, 1, 3, 2]),
+            uvs: None,
+            has_data: true,
+            properties: None,
+        };
+        let cutter = water_cutter(&slab, 10.0);
+        let heights: Vec<f32> = cutter.vertices.chunks(3).map(|v| v[2]).collect();
+        assert_eq!(heights, vec![1.0, 1.0, 10.0, 10.0]);
+        assert!(!is_water_slab(&slab));
+    }
+}
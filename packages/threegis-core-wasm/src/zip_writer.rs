@@ -0,0 +1,445 @@
+// Minimal streaming ZIP writer used by the archive-based exporters (3MF).
+// Entries are DEFLATE-compressed on the fly and use data descriptors, so sizes and
+// CRCs never need to be known up front and nothing is buffered beyond the encoder.
+// A small reader is included so exported archives can be checked after writing.
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, CrcWriter};
+use std::io::{self, Write};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIGNATURE: u32 = 0x0605_4b50;
+const VERSION_NEEDED: u16 = 20;
+// Bit 3: sizes/CRC follow in a data descriptor; bit 11: UTF-8 file names
+const FLAGS: u16 = 0x0008 | 0x0800;
+const METHOD_DEFLATE: u16 = 8;
+// DOS date for 1980-01-01, keeps archives byte-identical between runs
+const DOS_DATE: u16 = 0x0021;
+const DOS_TIME: u16 = 0;
+
+struct CentralEntry {
+    name: String,
+    crc: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    offset: u32,
+}
+
+// Pass-through writer that tracks how many bytes reached the underlying sink
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Entry currently being written: the DEFLATE stream owns the output until the entry ends
+struct OpenEntry<W: Write> {
+    name: String,
+    offset: u32,
+    data_start: u64,
+    // Uncompressed bytes written; CrcWriter counts in u32, which wraps past 4 GiB
+    uncompressed: u64,
+    writer: CrcWriter<DeflateEncoder<CountingWriter<W>>>,
+}
+
+pub struct ZipStreamWriter<W: Write> {
+    out: Option<CountingWriter<W>>,
+    open: Option<OpenEntry<W>>,
+    entries: Vec<CentralEntry>,
+}
+
+impl<W: Write> ZipStreamWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Some(CountingWriter {
+                inner: out,
+                count: 0,
+            }),
+            open: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add one file to the archive; `body` streams the uncompressed content.
+    pub fn write_entry<F>(&mut self, name: &str, level: u32, body: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        self.start_entry(name, level)?;
+        body(self)?;
+        self.end_entry()
+    }
+
+    /// Open a new entry; subsequent writes to the ZipStreamWriter go into it
+    /// until `end_entry` is called.
+    pub fn start_entry(&mut self, name: &str, level: u32) -> io::Result<()> {
+        let mut out = self.take_output()?;
+        let offset = to_u32(out.count, "archive offset")?;
+
+        write_u32(&mut out, LOCAL_HEADER_SIGNATURE)?;
+        write_u16(&mut out, VERSION_NEEDED)?;
+        write_u16(&mut out, FLAGS)?;
+        write_u16(&mut out, METHOD_DEFLATE)?;
+        write_u16(&mut out, DOS_TIME)?;
+        write_u16(&mut out, DOS_DATE)?;
+        // CRC and sizes are deferred to the data descriptor
+        write_u32(&mut out, 0)?;
+        write_u32(&mut out, 0)?;
+        write_u32(&mut out, 0)?;
+        write_u16(&mut out, name.len() as u16)?;
+        write_u16(&mut out, 0)?;
+        out.write_all(name.as_bytes())?;
+
+        let data_start = out.count;
+        self.open = Some(OpenEntry {
+            name: name.to_string(),
+            offset,
+            data_start,
+            uncompressed: 0,
+            writer: CrcWriter::new(DeflateEncoder::new(out, Compression::new(level.min(9)))),
+        });
+        Ok(())
+    }
+
+    /// Close the open entry and write its data descriptor.
+    pub fn end_entry(&mut self) -> io::Result<()> {
+        let entry = self
+            .open
+            .take()
+            .ok_or_else(|| io::Error::other("no ZIP entry is open"))?;
+        let crc = entry.writer.crc().sum();
+        let uncompressed_size = to_u32(entry.uncompressed, "entry size")?;
+        let mut out = entry.writer.into_inner().finish()?;
+        let compressed_size = to_u32(out.count - entry.data_start, "compressed entry size")?;
+
+        write_u32(&mut out, DATA_DESCRIPTOR_SIGNATURE)?;
+        write_u32(&mut out, crc)?;
+        write_u32(&mut out, compressed_size)?;
+        write_u32(&mut out, uncompressed_size)?;
+        self.out = Some(out);
+
+        self.entries.push(CentralEntry {
+            name: entry.name,
+            crc,
+            compressed_size,
+            uncompressed_size,
+            offset: entry.offset,
+        });
+        Ok(())
+    }
+
+    fn take_output(&mut self) -> io::Result<CountingWriter<W>> {
+        if self.open.is_some() {
+            return Err(io::Error::other("previous ZIP entry is still open"));
+        }
+        self.out
+            .take()
+            .ok_or_else(|| io::Error::other("ZIP writer is no longer usable"))
+    }
+
+    /// Write the central directory and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut out = self.take_output()?;
+        let directory_offset = to_u32(out.count, "central directory offset")?;
+
+        for entry in &self.entries {
+            write_u32(&mut out, CENTRAL_HEADER_SIGNATURE)?;
+            write_u16(&mut out, VERSION_NEEDED)?; // version made by
+            write_u16(&mut out, VERSION_NEEDED)?;
+            write_u16(&mut out, FLAGS)?;
+            write_u16(&mut out, METHOD_DEFLATE)?;
+            write_u16(&mut out, DOS_TIME)?;
+            write_u16(&mut out, DOS_DATE)?;
+            write_u32(&mut out, entry.crc)?;
+            write_u32(&mut out, entry.compressed_size)?;
+            write_u32(&mut out, entry.uncompressed_size)?;
+            write_u16(&mut out, entry.name.len() as u16)?;
+            write_u16(&mut out, 0)?; // extra field length
+            write_u16(&mut out, 0)?; // comment length
+            write_u16(&mut out, 0)?; // disk number
+            write_u16(&mut out, 0)?; // internal attributes
+            write_u32(&mut out, 0)?; // external attributes
+            write_u32(&mut out, entry.offset)?;
+            out.write_all(entry.name.as_bytes())?;
+        }
+
+        let directory_size = to_u32(out.count, "archive size")? - directory_offset;
+        write_u32(&mut out, END_OF_CENTRAL_DIR_SIGNATURE)?;
+        write_u16(&mut out, 0)?;
+        write_u16(&mut out, 0)?;
+        write_u16(&mut out, self.entries.len() as u16)?;
+        write_u16(&mut out, self.entries.len() as u16)?;
+        write_u32(&mut out, directory_size)?;
+        write_u32(&mut out, directory_offset)?;
+        write_u16(&mut out, 0)?; // comment length
+
+        out.flush()?;
+        Ok(out.inner)
+    }
+}
+
+// Writes go into the currently open entry
+impl<W: Write> Write for ZipStreamWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.open.as_mut() {
+            Some(entry) => {
+                // Refuse data the entry's 32-bit size fields cannot describe
+                to_u32(entry.uncompressed + buf.len() as u64, "entry size")?;
+                let written = entry.writer.write(buf)?;
+                entry.uncompressed += written as u64;
+                Ok(written)
+            }
+            None => Err(io::Error::other("no ZIP entry is open")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.open.as_mut() {
+            Some(entry) => entry.writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writer that collects bytes into fixed-size chunks and hands each full chunk
+/// to a callback (e.g. a JavaScript function receiving Uint8Arrays).
+pub struct ChunkSink<F>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    buffer: Vec<u8>,
+    chunk_size: usize,
+    total: u64,
+    on_chunk: F,
+}
+
+impl<F> ChunkSink<F>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    pub fn new(chunk_size: usize, on_chunk: F) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            buffer: Vec::with_capacity(chunk_size),
+            chunk_size,
+            total: 0,
+            on_chunk,
+        }
+    }
+
+    /// Emit any buffered bytes and return the total number of bytes written.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.emit()?;
+        Ok(self.total)
+    }
+
+    fn emit(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            (self.on_chunk)(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<F> Write for ChunkSink<F>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let space = self.chunk_size - self.buffer.len();
+        let take = space.min(buf.len());
+        self.buffer.extend_from_slice(&buf[..take]);
+        self.total += take as u64;
+        if self.buffer.len() >= self.chunk_size {
+            self.emit()?;
+        }
+        Ok(take)
+    }
+
+    // Flushing only forwards full chunks; the tail is emitted by `finish`
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn to_u32(value: u64, what: &str) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} exceeds the 4 GiB ZIP limit", what),
+        )
+    })
+}
+
+fn write_u16<W: Write>(out: &mut W, value: u16) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u32<W: Write>(out: &mut W, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+/// Read all entries of a ZIP archive (stored or DEFLATE), verifying their CRCs.
+/// Only handles single-disk archives without ZIP64 records, which covers everything
+/// `ZipStreamWriter` and JSZip produce.
+pub(crate) fn read_entries(archive: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    // Sizes and offsets come from the archive, so the range end may overflow on wasm32
+    let bytes_at = |pos: usize, len: usize| {
+        pos.checked_add(len)
+            .and_then(|end| archive.get(pos..end))
+            .ok_or_else(|| invalid("unexpected end of archive"))
+    };
+    let u16_at = |pos: usize| bytes_at(pos, 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at =
+        |pos: usize| bytes_at(pos, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    // The end of central directory record may be followed by a comment
+    let eocd = (0..=archive.len().saturating_sub(22))
+        .rev()
+        .find(|&pos| u32_at(pos).ok() == Some(END_OF_CENTRAL_DIR_SIGNATURE))
+        .ok_or_else(|| invalid("end of central directory not found"))?;
+    let count = u16_at(eocd + 10)? as usize;
+    let mut pos = u32_at(eocd + 16)? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(pos)? != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid("corrupt central directory"));
+        }
+        let method = u16_at(pos + 10)?;
+        let crc = u32_at(pos + 16)?;
+        let compressed_size = u32_at(pos + 20)? as usize;
+        let name_len = u16_at(pos + 28)? as usize;
+        let extra_len = u16_at(pos + 30)? as usize;
+        let comment_len = u16_at(pos + 32)? as usize;
+        let offset = u32_at(pos + 42)? as usize;
+        let name = String::from_utf8_lossy(bytes_at(pos + 46, name_len)?).to_string();
+
+        if u32_at(offset)? != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid("corrupt local file header"));
+        }
+        let data_start =
+            offset + 30 + u16_at(offset + 26)? as usize + u16_at(offset + 28)? as usize;
+        let raw = bytes_at(data_start, compressed_size)?;
+        let data = match method {
+            0 => raw.to_vec(),
+            METHOD_DEFLATE => {
+                let mut data = Vec::new();
+                DeflateDecoder::new(raw).read_to_end(&mut data)?;
+                data
+            }
+            other => {
+                return Err(invalid(&format!(
+                    "unsupported compression method {} for {}",
+                    other, name
+                )))
+            }
+        };
+
+        let mut check = flate2::Crc::new();
+        check.update(&data);
+        if check.sum() != crc {
+            return Err(invalid(&format!("CRC mismatch for {}", name)));
+        }
+
+        entries.push((name, data));
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_sink_splits_output() {
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        let mut sink = ChunkSink::new(4, |chunk: &[u8]| {
+            chunks.push(chunk.to_vec());
+            Ok(())
+        });
+        sink.write_all(b"0123456789").unwrap();
+        assert_eq!(sink.finish().unwrap(), 10);
+        assert_eq!(
+            chunks,
+            vec![b"0123".to_vec(), b"4567".to_vec(), b"89".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_streamed_archive_matches_buffered_archive() {
+        let build = |out: Vec<u8>| {
+            let mut zip = ZipStreamWriter::new(out);
+            zip.write_entry("a.txt", 6, |w| w.write_all(b"hello hello hello"))
+                .unwrap();
+            zip.finish().unwrap()
+        };
+        let buffered = build(Vec::new());
+
+        let mut streamed = Vec::new();
+        let sink = ChunkSink::new(7, |chunk: &[u8]| {
+            streamed.extend_from_slice(chunk);
+            Ok(())
+        });
+        let mut zip = ZipStreamWriter::new(sink);
+        zip.write_entry("a.txt", 6, |w| w.write_all(b"hello hello hello"))
+            .unwrap();
+        zip.finish().unwrap().finish().unwrap();
+
+        assert_eq!(buffered, streamed);
+        let entries = read_entries(&buffered).unwrap();
+        assert_eq!(
+            entries,
+            vec![("a.txt".to_string(), b"hello hello hello".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_entry_size_limit() {
+        let mut zip = ZipStreamWriter::new(io::sink());
+        zip.start_entry("large.bin", 0).unwrap();
+        zip.write_all(b"data").unwrap();
+        // Pretend the entry already holds just under 4 GiB
+        zip.open.as_mut().unwrap().uncompressed = u64::from(u32::MAX) - 4;
+        zip.write_all(b"1234").unwrap();
+        let error = zip.write_all(b"5").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("4 GiB"));
+    }
+
+    #[test]
+    fn test_read_entries_rejects_sizes_past_the_end() {
+        let mut zip = ZipStreamWriter::new(Vec::new());
+        zip.write_entry("a.txt", 0, |w| w.write_all(b"hello"))
+            .unwrap();
+        let mut archive = zip.finish().unwrap();
+
+        // Compressed size of the central directory entry
+        let eocd = archive.len() - 22;
+        let central =
+            u32::from_le_bytes(archive[eocd + 16..eocd + 20].try_into().unwrap()) as usize;
+        archive[central + 20..central + 24].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = read_entries(&archive).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        assert!(read_entries(&archive[..eocd]).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+// Automatic zoom selection from the bbox extent. Elevation zoom is the lowest zoom
+// whose pixels resolve the terrain grid, vector zoom the most detailed one available;
+// both are lowered until the bbox fits the tile budget of their source.
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::prefetch::TileSource;
+use crate::tilejson::{source_metadata, source_tiles};
+use crate::vectortile::TileRequest;
+
+// Pixels along one edge of an elevation tile
+const TILE_PIXELS: f64 = 256.0;
+const MAX_ELEVATION_ZOOM: u32 = 15;
+
+fn default_target_resolution() -> u32 {
+    256
+}
+
+fn default_max_elevation_tiles() -> usize {
+    9
+}
+
+fn default_max_vector_tiles() -> usize {
+    16
+}
+
+fn default_max_vector_zoom() -> u32 {
+    14
+}
+
+#[derive(Deserialize)]
+pub struct ZoomSelectionInput {
+    /// [minLng, minLat, maxLng, maxLat]
+    pub bbox: [f64; 4],
+    /// Terrain grid samples along the longer bbox edge the elevation should resolve
+    #[serde(default = "default_target_resolution", rename = "targetResolution")]
+    pub target_resolution: u32,
+    #[serde(default = "default_max_elevation_tiles", rename = "maxElevationTiles")]
+    pub max_elevation_tiles: usize,
+    #[serde(default = "default_max_vector_tiles", rename = "maxVectorTiles")]
+    pub max_vector_tiles: usize,
+    /// Zoom with full vector detail; higher zooms add no features
+    #[serde(default = "default_max_vector_zoom", rename = "maxVectorZoom")]
+    pub max_vector_zoom: u32,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ZoomSelection {
+    #[serde(rename = "elevationZoom")]
+    pub elevation_zoom: u32,
+    #[serde(rename = "elevationTiles")]
+    pub elevation_tiles: usize,
+    /// The tile budget forced an elevation zoom below the resolution target
+    #[serde(rename = "elevationCapped")]
+    pub elevation_capped: bool,
+    #[serde(rename = "vectorZoom")]
+    pub vector_zoom: u32,
+    #[serde(rename = "vectorTiles")]
+    pub vector_tiles: usize,
+}
+
+impl ZoomSelectionInput {
+    fn validate(&self) -> Result<(), String> {
+        let [min_lng, min_lat, max_lng, max_lat] = self.bbox;
+        if !self.bbox.iter().all(|v| v.is_finite()) || min_lng >= max_lng || min_lat >= max_lat {
+            return Err("Invalid bbox: must be [minLng, minLat, maxLng, maxLat]".to_string());
+        }
+        if self.target_resolution == 0
+            || self.max_elevation_tiles == 0
+            || self.max_vector_tiles == 0
+        {
+            return Err("targetResolution and tile budgets must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Lowest zoom at which the longer bbox edge spans `target_resolution` pixels
+    fn elevation_detail_zoom(&self) -> u32 {
+        let span = bbox_tile_span(&self.bbox);
+        (0..MAX_ELEVATION_ZOOM)
+            .find(|zoom| {
+                span * TILE_PIXELS * 2f64.powi(*zoom as i32) >= self.target_resolution as f64
+            })
+            .unwrap_or(MAX_ELEVATION_ZOOM)
+    }
+
+    fn select(&self) -> Result<ZoomSelection, JsValue> {
+        let detail_zoom = clamp_to_source(TileSource::Raster, self.elevation_detail_zoom());
+        let elevation = highest_zoom_within(
+            TileSource::Raster,
+            &self.bbox,
+            detail_zoom,
+            self.max_elevation_tiles,
+        )?;
+        let vector = highest_zoom_within(
+            TileSource::Vector,
+            &self.bbox,
+            clamp_to_source(TileSource::Vector, self.max_vector_zoom),
+            self.max_vector_tiles,
+        )?;
+        Ok(ZoomSelection {
+            elevation_zoom: elevation.0,
+            elevation_tiles: elevation.1.len(),
+            elevation_capped: elevation.0 < detail_zoom,
+            vector_zoom: vector.0,
+            vector_tiles: vector.1.len(),
+        })
+    }
+}
+
+/// Extent of the longer bbox edge in tiles at zoom 0
+fn bbox_tile_span(bbox: &[f64; 4]) -> f64 {
+    let mercator_y = |lat: f64| {
+        let lat_rad = lat.clamp(-85.0511, 85.0511).to_radians();
+        (1.0 - (lat_rad.tan() + 1.0 / lat_rad.cos()).ln() / std::f64::consts::PI) / 2.0
+    };
+    let width = (bbox[2] - bbox[0]) / 360.0;
+    let height = mercator_y(bbox[1]) - mercator_y(bbox[3]);
+    width.max(height)
+}
+
+fn clamp_to_source(source: TileSource, zoom: u32) -> u32 {
+    source_metadata(source).map_or(zoom, |tilejson| tilejson.clamp_zoom(zoom))
+}
+
+/// Highest zoom up to `max_zoom` covering `bbox` with at most `max_tiles` tiles of
+/// `source`, with those tiles; falls back to zoom 0
+pub(crate) fn highest_zoom_within(
+    source: TileSource,
+    bbox: &[f64],
+    max_zoom: u32,
+    max_tiles: usize,
+) -> Result<(u32, Vec<TileRequest>), JsValue> {
+    let mut zoom = max_zoom;
+    loop {
+        let tiles = source_tiles(source, bbox, zoom)?;
+        if tiles.len() <= max_tiles || zoom == 0 {
+            return Ok((zoom, tiles));
+        }
+        zoom -= 1;
+    }
+}
+
+/// Pick elevation and vector zoom levels for a bbox. Returns `ZoomSelection` as JSON.
+#[wasm_bindgen]
+pub fn select_zoom_levels(input_json: &str) -> Result<String, JsValue> {
+    let input: ZoomSelectionInput = serde_json::from_str(input_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse input: {}", e)))?;
+    input.validate().map_err(|e| JsValue::from_str(&e))?;
+    serde_json::to_string(&input.select()?)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize zoom selection: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zoom_levels_follow_bbox_size_and_budgets() {
+        let input = |bbox: &str| -> ZoomSelectionInput {
+            serde_json::from_str(&format!(r#"{{"bbox": {}}}"#, bbox)).unwrap()
+        };
+
+        // A city block: 256 samples over ~0.01° need z15, vector detail is capped at z14
+        let small = input("[13.40, 52.51, 13.41, 52.52]").select().unwrap();
+        assert_eq!(small.elevation_zoom, 15);
+        assert!(!small.elevation_capped);
+        assert_eq!(small.vector_zoom, 14);
+        assert!(small.vector_tiles <= 16);
+
+        // A whole region: resolution target and tile budgets bring both zooms down
+        let large = input("[5.0, 47.0, 15.0, 55.0]").select().unwrap();
+        assert!(large.elevation_tiles <= 9 && large.vector_tiles <= 16);
+        assert!(large.elevation_zoom < small.elevation_zoom);
+        assert!(large.vector_zoom < 8);
+
+        assert!(input("[1.0, 2.0, 1.0, 3.0]").validate().is_err());
+    }
+}
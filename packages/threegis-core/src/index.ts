@@ -26,6 +26,10 @@ export {
   processElevationDataWasm 
 } from './wasm/elevationProcessor';
 
+// Export the packed binary geometry decoder
+export { unpackGeometries } from './wasm/packedGeometry';
+export type { PackedGeometry } from './wasm/packedGeometry';
+
 // Export vector tile functionality
 export {
   fetchVtData,
@@ -0,0 +1,73 @@
+/**
+ * Decoder for the packed binary geometry results of the WASM module
+ * (process_polygon_geometry_packed, merge_geometries_with_csg_union_packed,
+ * get_layer_geometry_packed). Every array is a view on the packed buffer, so
+ * BufferAttributes can be created without copying.
+ */
+
+const MAGIC = "TGPK";
+const VERSION = 1;
+const HEADER_SIZE = 12;
+const SECTION_COUNT = 6;
+const ENTRY_SIZE = SECTION_COUNT * 8 + 4;
+
+/**
+ * One geometry of a packed result
+ */
+export interface PackedGeometry {
+  vertices: Float32Array;
+  normals: Float32Array | null;
+  colors: Float32Array | null;
+  indices: Uint32Array | null;
+  uvs: Float32Array | null;
+  hasData: boolean;
+  properties: Record<string, unknown> | null;
+}
+
+/**
+ * Decode a packed geometry result into typed array views
+ */
+export function unpackGeometries(packed: Uint8Array | ArrayBuffer): PackedGeometry[] {
+  const bytes = packed instanceof Uint8Array ? packed : new Uint8Array(packed);
+  const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+  const magic = String.fromCharCode(...bytes.subarray(0, 4));
+  if (magic !== MAGIC || view.getUint32(4, true) !== VERSION) {
+    throw new Error("Not a packed geometry buffer");
+  }
+
+  const section = (entry: number, index: number) => ({
+    offset: bytes.byteOffset + view.getUint32(entry + index * 8, true),
+    count: view.getUint32(entry + index * 8 + 4, true),
+  });
+  const floats = (entry: number, index: number) => {
+    const { offset, count } = section(entry, index);
+    return new Float32Array(bytes.buffer, offset, count);
+  };
+
+  const geometries: PackedGeometry[] = [];
+  const count = view.getUint32(8, true);
+  for (let i = 0; i < count; i++) {
+    const entry = HEADER_SIZE + i * ENTRY_SIZE;
+    const flags = view.getUint32(entry + SECTION_COUNT * 8, true);
+    const has = (index: number) => (flags & (1 << index)) !== 0;
+
+    let properties: Record<string, unknown> | null = null;
+    if (has(5)) {
+      const { offset, count: length } = section(entry, 5);
+      const json = new TextDecoder().decode(new Uint8Array(bytes.buffer, offset, length));
+      properties = JSON.parse(json);
+    }
+    const indices = section(entry, 3);
+
+    geometries.push({
+      vertices: floats(entry, 0),
+      normals: has(1) ? floats(entry, 1) : null,
+      colors: has(2) ? floats(entry, 2) : null,
+      indices: has(3) ? new Uint32Array(bytes.buffer, indices.offset, indices.count) : null,
+      uvs: has(4) ? floats(entry, 4) : null,
+      hasData: has(0),
+      properties,
+    });
+  }
+  return geometries;
+}
@@ -20,6 +20,9 @@ export interface TileFetchResponse {
   pixelData?: Uint8Array;   // For raster tiles
   rawData?: Uint8Array;     // For vector tiles (PBF) or any raw data
   mimeType: string;         // Content type of the response
+  etag?: string;            // Validators for conditional revalidation
+  lastModified?: string;
+  notModified?: boolean;    // Conditional request answered with 304
 }
 
 /**
@@ -30,6 +33,7 @@ export interface FetchConfig {
   timeoutMs: number;
   backoffMs: number;
   validateContent: boolean;
+  headers?: Record<string, string>; // e.g. If-None-Match for revalidation
 }
 
 /**
@@ -68,12 +72,16 @@ const extractTileCoordinatesFromUrl = (url: string): { x: number; y: number; z:
 /**
  * Create a fetch function with timeout support
  */
-const fetchWithTimeout = async (url: string, timeoutMs: number): Promise<Response> => {
+const fetchWithTimeout = async (
+  url: string,
+  timeoutMs: number,
+  headers?: Record<string, string>
+): Promise<Response> => {
   const controller = new AbortController();
   const timeoutId = setTimeout(() => controller.abort(), timeoutMs);
 
   try {
-    const response = await window.fetch(url, { signal: controller.signal });
+    const response = await window.fetch(url, { signal: controller.signal, headers });
     clearTimeout(timeoutId);
     return response;
   } catch (error) {
@@ -93,7 +101,23 @@ const robustFetch = async (url: string, config: FetchConfig): Promise<TileFetchR
 
   for (let attempt = 0; attempt <= config.maxRetries; attempt++) {
     try {
-      const response = await fetchWithTimeout(url, config.timeoutMs);
+      const response = await fetchWithTimeout(url, config.timeoutMs, config.headers);
+      const validators = {
+        etag: response.headers.get('etag') || undefined,
+        lastModified: response.headers.get('last-modified') || undefined
+      };
+
+      if (response.status === 304) {
+        const tileCoords = extractTileCoordinatesFromUrl(url);
+        return {
+          width: 256,
+          height: 256,
+          ...tileCoords,
+          mimeType: '',
+          ...validators,
+          notModified: true
+        };
+      }
 
       if (!response.ok) {
         throw new Error(`HTTP ${response.status}: ${response.statusText}`);
@@ -137,7 +161,8 @@ const robustFetch = async (url: string, config: FetchConfig): Promise<TileFetchR
           y: tileCoords.y,
           z: tileCoords.z,
           pixelData,
-          mimeType: contentType
+          mimeType: contentType,
+          ...validators
         };
       } else {
         const arrayBuffer = await response.arrayBuffer();
@@ -154,7 +179,8 @@ const robustFetch = async (url: string, config: FetchConfig): Promise<TileFetchR
           y: tileCoords.y,
           z: tileCoords.z,
           rawData,
-          mimeType: contentType
+          mimeType: contentType,
+          ...validators
         };
       }
     } catch (error) {